@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use tree_sitter::Query;
+
+use crate::error::{coded, ErrorCode};
+use crate::fileops;
+use crate::model::{AstSearchResult, SkippedLanguage};
+use crate::parser::{compile_query, detect_language, query_captures};
+use crate::storage::{GraphStore, QueryDeadline};
+
+/// Indexed files fetched from the store per page while scanning.
+const FILE_PAGE_SIZE: usize = 500;
+
+/// Characters of source kept per capture; longer nodes are cut and flagged.
+const MAX_CAPTURE_TEXT_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Default)]
+pub struct AstSearchOptions {
+    /// Repo-relative glob, matched like `search_files`' `file_glob`.
+    pub file_glob: Option<String>,
+    /// Only files in this language, e.g. `rust`.
+    pub language: Option<String>,
+    pub max_results: usize,
+}
+
+/// Runs a tree-sitter query over indexed files, compiling it once per
+/// language. Languages whose grammar rejects the query (unknown node types
+/// or fields) are skipped and reported; if none accepts it, the first
+/// compile error is returned.
+pub fn ast_search(
+    store: &GraphStore,
+    repo_root: &Path,
+    query_src: &str,
+    options: &AstSearchOptions,
+    deadline: Option<&QueryDeadline>,
+) -> Result<AstSearchResult> {
+    if query_src.trim().is_empty() {
+        return Err(coded(ErrorCode::InvalidParams, "query must not be empty"));
+    }
+    let glob = options
+        .file_glob
+        .as_deref()
+        .map(fileops::glob_to_regex)
+        .transpose()
+        .map_err(|err| coded(ErrorCode::InvalidParams, format!("{err:#}")))?;
+
+    let mut queries: HashMap<&str, Option<Query>> = HashMap::new();
+    let mut result = AstSearchResult {
+        matches: Vec::new(),
+        files_scanned: 0,
+        languages: Vec::new(),
+        skipped_languages: Vec::new(),
+        truncated: false,
+    };
+    let mut after: Option<String> = None;
+    'pages: loop {
+        let files = store.indexed_files(after.as_deref(), FILE_PAGE_SIZE)?;
+        let Some(last) = files.last() else {
+            break;
+        };
+        after = Some(last.path.clone());
+
+        for file in files {
+            if glob.as_ref().is_some_and(|glob| !glob.is_match(&file.path)) {
+                continue;
+            }
+            let Some(language) = detect_language(Path::new(&file.path)) else {
+                continue;
+            };
+            if options
+                .language
+                .as_deref()
+                .is_some_and(|wanted| wanted != language.as_str())
+            {
+                continue;
+            }
+            let query = queries.entry(language.as_str()).or_insert_with(|| {
+                match compile_query(language, query_src) {
+                    Ok(query) => {
+                        result.languages.push(language.as_str().to_string());
+                        Some(query)
+                    }
+                    Err(err) => {
+                        result.skipped_languages.push(SkippedLanguage {
+                            language: language.as_str().to_string(),
+                            error: format!("{err:#}"),
+                        });
+                        None
+                    }
+                }
+            });
+            let Some(query) = query.as_ref() else {
+                continue;
+            };
+            if deadline.is_some_and(QueryDeadline::expired) {
+                result.truncated = true;
+                break 'pages;
+            }
+
+            let Ok(resolved) = fileops::safe_resolve_path(repo_root, &file.path) else {
+                continue;
+            };
+            let Ok(source) = fs::read_to_string(resolved) else {
+                continue;
+            };
+            result.files_scanned += 1;
+            let captures = query_captures(
+                Path::new(&file.path),
+                &source,
+                language,
+                query,
+                MAX_CAPTURE_TEXT_CHARS,
+            )?;
+            for capture in captures {
+                if result.matches.len() >= options.max_results {
+                    result.truncated = true;
+                    break 'pages;
+                }
+                result.matches.push(capture);
+            }
+        }
+    }
+
+    if result.languages.is_empty() {
+        if let Some(skipped) = result.skipped_languages.first() {
+            return Err(coded(
+                ErrorCode::InvalidParams,
+                format!(
+                    "query does not compile for {}: {}",
+                    skipped.language, skipped.error
+                ),
+            ));
+        }
+    }
+    result.languages.sort();
+    result
+        .skipped_languages
+        .sort_by(|left, right| left.language.cmp(&right.language));
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::{index_repository, IndexOptions};
+    use tempfile::TempDir;
+
+    #[test]
+    fn ast_search_matches_structure_across_languages() {
+        let dir = TempDir::new().unwrap();
+        let repo = dir.path();
+        fs::create_dir_all(repo.join("src")).unwrap();
+        fs::write(
+            repo.join("src/lib.rs"),
+            "fn free() { a.unwrap(); }\n\nimpl Widget {\n    fn load(&self) { b.unwrap(); c.expect(\"x\"); }\n}\n",
+        )
+        .unwrap();
+        fs::write(repo.join("tool.py"), "def run():\n    x.unwrap()\n").unwrap();
+        let state = TempDir::new().unwrap();
+        let mut store = GraphStore::open(&state.path().join("graph.db")).unwrap();
+        index_repository(&mut store, repo, IndexOptions::default()).unwrap();
+
+        let query = r#"(impl_item body: (declaration_list (function_item body: (block
+            (expression_statement (call_expression function: (field_expression
+                field: (field_identifier) @method (#eq? @method "unwrap"))) @call)))))"#;
+        let options = AstSearchOptions {
+            max_results: 10,
+            ..Default::default()
+        };
+        let result = ast_search(&store, repo, query, &options, None).unwrap();
+        assert_eq!(result.languages, ["rust"]);
+        assert_eq!(result.skipped_languages.len(), 1, "python rejects it");
+        let calls: Vec<_> = result
+            .matches
+            .iter()
+            .filter(|capture| capture.capture == "call")
+            .collect();
+        assert_eq!(calls.len(), 1, "only the unwrap inside the impl block");
+        assert_eq!(calls[0].text, "b.unwrap()");
+        assert_eq!((calls[0].start_line, calls[0].start_col), (4, 22));
+
+        let options = AstSearchOptions {
+            file_glob: Some("*.py".to_string()),
+            max_results: 10,
+            ..Default::default()
+        };
+        let err = ast_search(&store, repo, query, &options, None).unwrap_err();
+        assert!(format!("{err:#}").contains("python"));
+    }
+}
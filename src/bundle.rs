@@ -0,0 +1,305 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, DatabaseName, OpenFlags};
+use serde::{Deserialize, Serialize};
+
+use crate::daemon::{find_git_dir, read_git_head};
+use crate::indexer::sha256_hex;
+use crate::model::LanguageCoverage;
+use crate::paths::RuntimePaths;
+use crate::storage::{GraphStore, BUSY_TIMEOUT, SCHEMA_VERSION};
+
+/// Bumped whenever the archive layout changes.
+pub const BUNDLE_FORMAT: u32 = 1;
+const MANIFEST_ENTRY: &str = "manifest.json";
+const DATABASE_ENTRY: &str = "graph.db";
+const ZSTD_LEVEL: i32 = 19;
+
+/// Describes the index packed into a bundle; stored as `manifest.json` next
+/// to the database inside the archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format: u32,
+    pub lumora_version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<String>,
+    /// SHA-256 of the exporting checkout's root path. Graph paths are
+    /// repo-relative, so a mismatch on import is reported, not rejected.
+    pub repo_root_hash: String,
+    /// `<ref>@<commit>` (or the commit when detached) at export time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_head: Option<String>,
+    pub created_at: String,
+    pub file_count: i64,
+    pub languages: Vec<LanguageCoverage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleExport {
+    pub path: String,
+    pub size_bytes: u64,
+    pub manifest: BundleManifest,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleImport {
+    pub db_path: String,
+    pub manifest: BundleManifest,
+    pub same_repo_root: bool,
+    /// Whether the bundle was built at the commit currently checked out;
+    /// absent outside git.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub same_git_head: Option<bool>,
+}
+
+/// Writes the graph database and a manifest into a zstd-compressed tarball
+/// at `out`. The database is copied with the online backup API first, so a
+/// running daemon can keep writing.
+pub fn export_bundle(paths: &RuntimePaths, out: &Path) -> Result<BundleExport> {
+    anyhow::ensure!(
+        paths.db_path.exists(),
+        "no database at {}; run `lumora index` first",
+        paths.db_path.display()
+    );
+    let staged = staging_path(paths, "export");
+    let result = write_bundle(paths, &staged, out);
+    let _ = fs::remove_file(&staged);
+    let manifest = result?;
+    let size_bytes = fs::metadata(out)
+        .with_context(|| format!("failed to stat {}", out.display()))?
+        .len();
+    Ok(BundleExport {
+        path: out.display().to_string(),
+        size_bytes,
+        manifest,
+    })
+}
+
+fn write_bundle(paths: &RuntimePaths, staged: &Path, out: &Path) -> Result<BundleManifest> {
+    let source = Connection::open_with_flags(&paths.db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("failed to open {}", paths.db_path.display()))?;
+    source
+        .backup(DatabaseName::Main, staged, None)
+        .with_context(|| format!("failed to copy database to {}", staged.display()))?;
+    let created_at: String =
+        source.query_row("SELECT strftime('%Y-%m-%dT%H:%M:%SZ', 'now')", [], |row| {
+            row.get(0)
+        })?;
+    drop(source);
+
+    let store = GraphStore::open(staged)?;
+    let manifest = BundleManifest {
+        format: BUNDLE_FORMAT,
+        lumora_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version: GraphStore::read_schema_version(staged)?,
+        repo_root_hash: repo_root_hash(&paths.repo_root),
+        git_head: current_git_head(&paths.repo_root),
+        created_at,
+        file_count: store.freshness_info(0)?.file_count,
+        languages: store.language_coverage()?,
+    };
+    drop(store);
+
+    let file = File::create(out).with_context(|| format!("failed to create {}", out.display()))?;
+    let encoder = zstd::Encoder::new(file, ZSTD_LEVEL)?;
+    let mut archive = tar::Builder::new(encoder);
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_json.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    archive.append_data(&mut header, MANIFEST_ENTRY, manifest_json.as_slice())?;
+    archive.append_path_with_name(staged, DATABASE_ENTRY)?;
+    archive.into_inner()?.finish()?;
+    Ok(manifest)
+}
+
+/// Replaces the graph database with the one in a bundle written by
+/// [`export_bundle`]. Bundles from another archive format or schema version
+/// are refused before anything is touched.
+pub fn import_bundle(paths: &RuntimePaths, bundle: &Path) -> Result<BundleImport> {
+    let staged = staging_path(paths, "import");
+    let result = read_bundle(bundle, &staged).and_then(|manifest| {
+        restore_database(paths, &staged)?;
+        Ok(manifest)
+    });
+    let _ = fs::remove_file(&staged);
+    let manifest = result?;
+
+    let same_repo_root = manifest.repo_root_hash == repo_root_hash(&paths.repo_root);
+    let same_git_head = current_git_head(&paths.repo_root)
+        .map(|head| manifest.git_head.as_deref() == Some(head.as_str()));
+    Ok(BundleImport {
+        db_path: paths.db_path.display().to_string(),
+        manifest,
+        same_repo_root,
+        same_git_head,
+    })
+}
+
+fn read_bundle(bundle: &Path, staged: &Path) -> Result<BundleManifest> {
+    let file =
+        File::open(bundle).with_context(|| format!("failed to open {}", bundle.display()))?;
+    let decoder = zstd::Decoder::new(file)?;
+    let mut archive = tar::Archive::new(decoder);
+    let mut manifest = None;
+    let mut has_database = false;
+    for entry in archive
+        .entries()
+        .with_context(|| format!("{} is not a lumora bundle", bundle.display()))?
+    {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().to_string();
+        match name.as_str() {
+            MANIFEST_ENTRY => {
+                let mut raw = String::new();
+                entry.read_to_string(&mut raw)?;
+                manifest = Some(
+                    serde_json::from_str::<BundleManifest>(&raw)
+                        .context("bundle manifest is malformed")?,
+                );
+            }
+            DATABASE_ENTRY => {
+                // Unpacking would follow a symlink or hard link out of the
+                // staging path, so only a regular file's bytes are copied.
+                anyhow::ensure!(
+                    entry.header().entry_type().is_file(),
+                    "{DATABASE_ENTRY} in {} is not a regular file",
+                    bundle.display()
+                );
+                let mut out = File::create(staged)
+                    .with_context(|| format!("failed to create {}", staged.display()))?;
+                std::io::copy(&mut entry, &mut out)?;
+                has_database = true;
+            }
+            _ => {}
+        }
+    }
+
+    let manifest =
+        manifest.with_context(|| format!("{} has no {MANIFEST_ENTRY}", bundle.display()))?;
+    anyhow::ensure!(has_database, "{} has no {DATABASE_ENTRY}", bundle.display());
+    anyhow::ensure!(
+        manifest.format == BUNDLE_FORMAT,
+        "bundle format {} is not supported (expected {BUNDLE_FORMAT})",
+        manifest.format
+    );
+    anyhow::ensure!(
+        manifest.schema_version.as_deref() == Some(SCHEMA_VERSION),
+        "bundle schema version {} does not match this build ({SCHEMA_VERSION}); re-export it with lumora {}",
+        manifest.schema_version.as_deref().unwrap_or("unknown"),
+        env!("CARGO_PKG_VERSION")
+    );
+    Ok(manifest)
+}
+
+/// Copies `staged` over the live database through the backup API, like a
+/// snapshot restore, so open readers pick up the new contents.
+fn restore_database(paths: &RuntimePaths, staged: &Path) -> Result<()> {
+    let mut target = Connection::open(&paths.db_path)
+        .with_context(|| format!("failed to open {}", paths.db_path.display()))?;
+    target.busy_timeout(BUSY_TIMEOUT)?;
+    target
+        .restore(
+            DatabaseName::Main,
+            staged,
+            None::<fn(rusqlite::backup::Progress)>,
+        )
+        .with_context(|| format!("failed to restore {}", paths.db_path.display()))
+}
+
+fn staging_path(paths: &RuntimePaths, purpose: &str) -> PathBuf {
+    paths
+        .state_dir
+        .join(format!(".bundle-{purpose}-{}.db", std::process::id()))
+}
+
+fn repo_root_hash(repo_root: &Path) -> String {
+    sha256_hex(repo_root.to_string_lossy().replace('\\', "/").as_bytes())
+}
+
+fn current_git_head(repo_root: &Path) -> Option<String> {
+    read_git_head(&find_git_dir(repo_root)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn temp_paths(root: &Path) -> RuntimePaths {
+        RuntimePaths {
+            state_dir: root.join(".lumora"),
+            db_path: root.join(".lumora").join("graph.db"),
+            repo_root: root.to_path_buf(),
+        }
+    }
+
+    fn add_file(paths: &RuntimePaths, path: &str) {
+        Connection::open(&paths.db_path)
+            .unwrap()
+            .execute(
+                "INSERT INTO files(path, lang, content_hash, size_bytes, line_count, indexed_at)
+                 VALUES(?1, 'rust', 'h', 1, 3, datetime('now'))",
+                [path],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn bundle_round_trips_into_another_checkout() {
+        let temp = TempDir::new().unwrap();
+        let source = temp_paths(&temp.path().join("ci"));
+        fs::create_dir_all(&source.state_dir).unwrap();
+        drop(GraphStore::open(&source.db_path).unwrap());
+        add_file(&source, "src/a.rs");
+
+        let out = temp.path().join("index.tar.zst");
+        let exported = export_bundle(&source, &out).unwrap();
+        assert_eq!(exported.manifest.file_count, 1);
+        assert_eq!(exported.manifest.languages[0].language, "rust");
+        assert_eq!(
+            exported.manifest.schema_version.as_deref(),
+            Some(SCHEMA_VERSION)
+        );
+        assert!(exported.size_bytes > 0);
+
+        let target = temp_paths(&temp.path().join("dev"));
+        fs::create_dir_all(&target.state_dir).unwrap();
+        let imported = import_bundle(&target, &out).unwrap();
+        assert!(!imported.same_repo_root);
+        assert!(imported.same_git_head.is_none());
+        let store = GraphStore::open(&target.db_path).unwrap();
+        assert!(store.tracked_files().unwrap().contains("src/a.rs"));
+        assert!(
+            fs::read_dir(&target.state_dir).unwrap().all(|entry| !entry
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with(".bundle-")),
+            "staging files should be cleaned up"
+        );
+
+        fs::write(temp.path().join("bogus.tar.zst"), b"not a bundle").unwrap();
+        assert!(import_bundle(&target, &temp.path().join("bogus.tar.zst")).is_err());
+        assert!(store.tracked_files().unwrap().contains("src/a.rs"));
+
+        let linked = temp.path().join("linked.tar.zst");
+        let mut archive = tar::Builder::new(
+            zstd::Encoder::new(File::create(&linked).unwrap(), ZSTD_LEVEL).unwrap(),
+        );
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        archive
+            .append_link(&mut header, DATABASE_ENTRY, &source.db_path)
+            .unwrap();
+        archive.into_inner().unwrap().finish().unwrap();
+        let err = import_bundle(&target, &linked).unwrap_err();
+        assert!(err.to_string().contains("not a regular file"), "{err:#}");
+        assert!(store.tracked_files().unwrap().contains("src/a.rs"));
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::ignore::IgnoreGlobs;
+
+/// Where GitHub looks for the ownership file, in the order it checks them.
+/// Only the first one found is used.
+pub const CODEOWNERS_LOCATIONS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// Parsed `CODEOWNERS` rules. Patterns use the same loose `.gitignore`
+/// matching as [`IgnoreGlobs`], and the last matching rule wins, so a later
+/// rule with no owners clears ownership for its paths.
+#[derive(Debug, Clone, Default)]
+pub struct CodeOwners {
+    rules: Vec<(IgnoreGlobs, Vec<String>)>,
+}
+
+impl CodeOwners {
+    /// Loads the repo's `CODEOWNERS`; a repo without one has no owners.
+    pub fn load(repo_root: &Path) -> Result<Self> {
+        for location in CODEOWNERS_LOCATIONS {
+            let path = repo_root.join(location);
+            match fs::read_to_string(&path) {
+                Ok(raw) => return Ok(Self::parse(&raw)),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => {
+                    return Err(err).with_context(|| format!("failed to read {}", path.display()))
+                }
+            }
+        }
+        Ok(Self::default())
+    }
+
+    /// Parses `CODEOWNERS` text. Lines whose pattern cannot be compiled are
+    /// skipped, as GitHub does.
+    pub fn parse(raw: &str) -> Self {
+        let mut rules = Vec::new();
+        for line in raw.lines() {
+            let line = match line.find(" #") {
+                Some(index) => &line[..index],
+                None => line,
+            };
+            let mut parts = line.split_whitespace();
+            let Some(pattern) = parts.next() else {
+                continue;
+            };
+            if pattern.starts_with('#') {
+                continue;
+            }
+            let Ok(globs) = IgnoreGlobs::new(&[pattern.to_string()]) else {
+                continue;
+            };
+            rules.push((globs, parts.map(str::to_string).collect()));
+        }
+        Self { rules }
+    }
+
+    /// Owners of the repo-relative `rel_path`, empty when no rule assigns any.
+    pub fn owners_for(&self, rel_path: &str) -> &[String] {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(globs, _)| globs.is_ignored(rel_path, false))
+            .map(|(_, owners)| owners.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let owners = CodeOwners::parse(
+            "# Default reviewers\n\
+             *       @org/core\n\
+             *.md    @docs-team   # prose\n\
+             /src/storage/ @alice bob@example.com\n\
+             /src/storage/generated.rs\n",
+        );
+        assert_eq!(owners.owners_for("src/main.rs"), ["@org/core"]);
+        assert_eq!(owners.owners_for("docs/guide.md"), ["@docs-team"]);
+        assert_eq!(
+            owners.owners_for("src/storage/sqlite.rs"),
+            ["@alice", "bob@example.com"]
+        );
+        assert!(owners.owners_for("src/storage/generated.rs").is_empty());
+    }
+
+    #[test]
+    fn load_prefers_the_github_directory() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert!(CodeOwners::load(dir.path())
+            .unwrap()
+            .owners_for("lib.rs")
+            .is_empty());
+
+        fs::write(dir.path().join("CODEOWNERS"), "* @root\n").unwrap();
+        fs::create_dir_all(dir.path().join(".github")).unwrap();
+        fs::write(dir.path().join(".github/CODEOWNERS"), "* @github\n").unwrap();
+        let owners = CodeOwners::load(dir.path()).unwrap();
+        assert_eq!(owners.owners_for("lib.rs"), ["@github"]);
+    }
+}
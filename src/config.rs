@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+/// Optional per-repo settings, read from the repository root.
+pub const CONFIG_FILE_NAME: &str = ".lumora.toml";
+
+/// Written by `lumora init`; every setting is commented out so the defaults
+/// apply until a user opts in.
+pub const STARTER_CONFIG: &str = "\
+# Lumora settings for this repository.
+
+[guardrails]
+# Largest `content` the MCP write_file tool accepts, in bytes.
+# max_write_bytes = 262144
+# Files the MCP write tools may change per rolling minute.
+# max_files_per_minute = 30
+# Require `confirm: true` on delete_file and move_file.
+# confirm_destructive = true
+
+[mcp]
+# Tools `lumora mcp` advertises: groups (index, query, read, write) or tool names.
+# tools = [\"index\", \"query\", \"read\"]
+# Tools to hide even when allowed above.
+# deny_tools = [\"write\"]
+# Reindex files touched by write tools before they return.
+# auto_refresh_index = false
+# Offer tools whose arguments or output may still change (same as --experimental).
+# experimental = false
+# Split tools/list into pages of this many tools.
+# tools_page_size = 20
+
+[fingerprints]
+# Tokens per hashed k-gram in clone fingerprints.
+# k_gram = 5
+# K-grams per winnowing window; one fingerprint is kept per window.
+# window = 4
+# Files with fewer tokens than this get no fingerprints.
+# min_tokens = 5
+
+[low_signal]
+# Names slices rank down and tests do not link to, on top of the built-in
+# per-language lists (`lumora query low-signal` prints them).
+# names = [\"logger\"]
+# Drop the built-in lists and use only the names configured here.
+# replace_defaults = false
+# [low_signal.languages]
+# python = [\"logging\", \"Path\"]
+
+[imports]
+# Directories, relative to the repo root, searched for C/C++ `#include`s
+# after the including file's own directory.
+# include_dirs = [\"include\"]
+
+[serve]
+# Minutes between `PRAGMA optimize` / `ANALYZE` passes while `lumora serve`
+# watches a changing repo; 0 turns them off.
+# optimize_interval_mins = 60
+# Also rewrite the database with `VACUUM INTO` when enough of it is free space.
+# compact = false
+
+[state]
+# Keep the graph in ~/.local/share/lumora/<repo-hash>/ instead of .lumora/,
+# e.g. for read-only checkouts or network mounts.
+# location = \"global\"
+";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LumoraConfig {
+    pub guardrails: Guardrails,
+    pub mcp: McpConfig,
+    pub fingerprints: FingerprintConfig,
+    pub state: StateConfig,
+    pub low_signal: LowSignalConfig,
+    pub imports: ImportsConfig,
+    pub serve: ServeConfig,
+}
+
+/// Limits on the MCP write tools, so a runaway agent cannot rewrite a repo
+/// wholesale. Everything is off unless configured:
+///
+/// ```toml
+/// [guardrails]
+/// max_write_bytes = 262144
+/// max_files_per_minute = 30
+/// confirm_destructive = true
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Guardrails {
+    /// Largest `content` `write_file` accepts.
+    pub max_write_bytes: Option<u64>,
+    /// Files the write tools may modify in any rolling 60-second window of one
+    /// server session. Dry runs do not count.
+    pub max_files_per_minute: Option<usize>,
+    /// Require `confirm: true` on `delete_file`, `move_file` and
+    /// `undo_last_change`.
+    pub confirm_destructive: bool,
+}
+
+/// Which tools `lumora mcp` exposes, e.g. only the graph queries for a
+/// read-only deployment. `--tools` replaces `tools`; `--deny-tools` adds to
+/// `deny_tools`:
+///
+/// ```toml
+/// [mcp]
+/// tools = ["query", "read"]
+/// deny_tools = ["lumora.multi_read"]
+/// auto_refresh_index = true
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct McpConfig {
+    /// Tool groups or names to allow; unset allows every tool.
+    pub tools: Option<Vec<String>>,
+    pub deny_tools: Vec<String>,
+    /// Reindex the files a write tool touched before the call returns. A
+    /// call's own `auto_refresh_index` argument takes precedence.
+    pub auto_refresh_index: bool,
+    /// Offer experimental tools; `--experimental` turns it on too.
+    pub experimental: bool,
+    /// `tools/list` page size; `--tools-page-size` replaces it.
+    pub tools_page_size: Option<usize>,
+}
+
+/// Clone fingerprint tuning; unset keys keep the built-in defaults. Changing
+/// them only affects files indexed afterwards, so run a full index to make
+/// every file comparable again:
+///
+/// ```toml
+/// [fingerprints]
+/// k_gram = 5
+/// window = 4
+/// min_tokens = 5
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct FingerprintConfig {
+    pub k_gram: Option<usize>,
+    pub window: Option<usize>,
+    pub min_tokens: Option<usize>,
+}
+
+/// Extra names to treat as noise in slices and test links. `names` apply to
+/// every language, `languages` to files of one language; both add to the
+/// built-in lists unless `replace_defaults` is set:
+///
+/// ```toml
+/// [low_signal]
+/// names = ["logger"]
+///
+/// [low_signal.languages]
+/// python = ["logging", "Path"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LowSignalConfig {
+    pub names: Vec<String>,
+    /// Keyed by language id (`rust`, `python`, `typescript`, ...).
+    pub languages: BTreeMap<String, Vec<String>>,
+    pub replace_defaults: bool,
+}
+
+/// Where imports that do not name a path of their own are looked up. C and
+/// C++ `#include "..."` tries the including file's directory first, then
+/// these; `#include <...>` tries only these:
+///
+/// ```toml
+/// [imports]
+/// include_dirs = ["include", "third_party/zlib"]
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ImportsConfig {
+    pub include_dirs: Vec<String>,
+}
+
+/// Database upkeep `lumora serve` runs between index runs; its
+/// `--optimize-interval-mins` and `--compact` flags take precedence:
+///
+/// ```toml
+/// [serve]
+/// optimize_interval_mins = 60
+/// compact = true
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ServeConfig {
+    /// Minutes between optimize passes once the graph has changed; 0 turns
+    /// them off. Unset means 60.
+    pub optimize_interval_mins: Option<u64>,
+    /// Compact fragmented databases during optimize passes.
+    pub compact: bool,
+}
+
+/// Where the state dir goes when `--state-dir` is not given. `global` keeps
+/// the repository untouched, for read-only checkouts and network mounts;
+/// `LUMORA_STATE` and `--global-state` override it:
+///
+/// ```toml
+/// [state]
+/// location = "global"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StateConfig {
+    pub location: StateLocation,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StateLocation {
+    /// `.lumora/` in the repository root.
+    #[default]
+    Repo,
+    /// `<data dir>/lumora/<repo-hash>/`, outside the repository.
+    Global,
+}
+
+impl StateLocation {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "repo" => Some(Self::Repo),
+            "global" => Some(Self::Global),
+            _ => None,
+        }
+    }
+}
+
+/// Loads `<repo_root>/.lumora.toml`; a missing file means defaults.
+pub fn load_config(repo_root: &Path) -> Result<LumoraConfig> {
+    let path = repo_root.join(CONFIG_FILE_NAME);
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(LumoraConfig::default())
+        }
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to read {}", path.display()));
+        }
+    };
+    toml::from_str(&raw).with_context(|| format!("invalid config {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn load_config_defaults_when_missing_and_parses_guardrails() {
+        let dir = TempDir::new().unwrap();
+        let config = load_config(dir.path()).unwrap();
+        assert!(config.guardrails.max_write_bytes.is_none());
+        assert!(!config.guardrails.confirm_destructive);
+
+        fs::write(
+            dir.path().join(CONFIG_FILE_NAME),
+            "[guardrails]\nmax_write_bytes = 1024\nmax_files_per_minute = 5\nconfirm_destructive = true\n",
+        )
+        .unwrap();
+        let config = load_config(dir.path()).unwrap();
+        assert_eq!(config.guardrails.max_write_bytes, Some(1024));
+        assert_eq!(config.guardrails.max_files_per_minute, Some(5));
+        assert!(config.guardrails.confirm_destructive);
+
+        fs::write(dir.path().join(CONFIG_FILE_NAME), "[guardrails\n").unwrap();
+        assert!(load_config(dir.path()).is_err());
+    }
+}
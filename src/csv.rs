@@ -0,0 +1,98 @@
+use std::borrow::Cow;
+use std::io::Write;
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Writes `rows` as CSV with a header line of `columns`, taking each cell
+/// from the row's serialized field of that name. Missing and null fields are
+/// left empty, arrays of plain values are joined with `;`, and any other
+/// nested value is written as compact JSON. Fields are quoted per RFC 4180.
+pub fn write_csv<W: Write, T: Serialize>(out: &mut W, columns: &[&str], rows: &[T]) -> Result<()> {
+    write_record(out, columns.iter().map(|column| Cow::Borrowed(*column)))?;
+    for row in rows {
+        let value = serde_json::to_value(row)?;
+        write_record(
+            out,
+            columns
+                .iter()
+                .map(|column| Cow::Owned(cell(value.get(*column)))),
+        )?;
+    }
+    Ok(())
+}
+
+fn write_record<'a, W: Write>(
+    out: &mut W,
+    fields: impl Iterator<Item = Cow<'a, str>>,
+) -> Result<()> {
+    let line = fields
+        .map(|field| escape(&field).into_owned())
+        .collect::<Vec<_>>()
+        .join(",");
+    out.write_all(line.as_bytes())?;
+    out.write_all(b"\r\n")?;
+    Ok(())
+}
+
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(items))
+            if items
+                .iter()
+                .all(|item| !item.is_array() && !item.is_object()) =>
+        {
+            items
+                .iter()
+                .map(|item| cell(Some(item)))
+                .collect::<Vec<_>>()
+                .join(";")
+        }
+        Some(other) => other.to_string(),
+    }
+}
+
+fn escape(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn fields_are_quoted_and_nested_values_flattened() {
+        let rows = vec![
+            json!({"file_path": "src/a,b.rs", "line": 3, "why": "said \"hi\"", "lines": [3, 7]}),
+            json!({"file_path": "lib.rs", "line": 9, "why": null, "regions": [{"start_line": 1}]}),
+        ];
+        let mut out = Vec::new();
+        write_csv(
+            &mut out,
+            &["file_path", "line", "why", "lines", "regions"],
+            &rows,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "file_path,line,why,lines,regions\r\n\
+             \"src/a,b.rs\",3,\"said \"\"hi\"\"\",3;7,\r\n\
+             lib.rs,9,,,\"[{\"\"start_line\"\":1}]\"\r\n"
+        );
+    }
+
+    #[test]
+    fn empty_results_still_get_a_header() {
+        let mut out = Vec::new();
+        write_csv::<_, Value>(&mut out, &["file_path", "line"], &[]).unwrap();
+        assert_eq!(out, b"file_path,line\r\n");
+    }
+}
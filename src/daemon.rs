@@ -1,13 +1,21 @@
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
+use std::thread;
 use std::time::{Duration, Instant};
 
-use anyhow::Result;
-use notify::{recommended_watcher, Event, RecursiveMode, Watcher};
+use anyhow::{anyhow, Result};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{recommended_watcher, Event, EventKind, RecursiveMode, Watcher};
+use serde_json::json;
 
-use crate::indexer::{index_repository, IndexOptions, IndexReport};
+use crate::config::load_config;
+use crate::events::{append_event, IndexChangedEvent};
+use crate::ignore::IgnoreGlobs;
+use crate::indexer::{index_repository, index_repository_with_trees, IndexOptions, IndexReport};
+use crate::parser::TreeCache;
 use crate::paths::{RuntimePaths, STATE_DIR_NAME};
-use crate::storage::GraphStore;
+use crate::storage::{GraphStore, OptimizeReport};
 
 const WATCH_IGNORE_DIRS: &[&str] = &[
     ".git",
@@ -23,47 +31,164 @@ const WATCH_IGNORE_DIRS: &[&str] = &[
     STATE_DIR_NAME,
 ];
 
+/// After a checkout or rebase moves HEAD, git keeps rewriting files for a
+/// while; the refresh waits until events have been quiet this long.
+const BRANCH_SWITCH_SETTLE: Duration = Duration::from_millis(1_000);
+
+/// Upper bound on waiting for a busy checkout to settle.
+const BRANCH_SWITCH_MAX_WAIT: Duration = Duration::from_secs(30);
+
+/// How often a watcher that has written to the graph truncates the WAL.
+/// SQLite's automatic checkpoints never shrink the file, and with readers
+/// such as MCP sessions attached they can fall behind during a long session.
+const WAL_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(300);
+
+const DEFAULT_OPTIMIZE_INTERVAL_MINS: u64 = 60;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UpkeepOverrides {
+    pub optimize_interval_mins: Option<u64>,
+    pub compact: bool,
+}
+
+/// Changes gathered during one debounce window.
+#[derive(Default)]
+struct PendingBatch {
+    saw_relevant_change: bool,
+    force_full_rescan: bool,
+    renames: Vec<(String, String)>,
+    /// `HEAD`, a branch ref, or `packed-refs` was written.
+    git_refs_touched: bool,
+}
+
+/// Watches one or more repositories. A single repo keeps the original output
+/// format; with several, each repo runs on its own thread with its own store
+/// and debounce queue, and every event is tagged with the repo root.
+/// `ignore_globs` apply to every repo, on top of `LUMORA_IGNORE`.
 pub fn run_watcher_daemon(
-    paths: &RuntimePaths,
+    repos: &[RuntimePaths],
     full_first: bool,
     debounce_ms: u64,
+    ignore_globs: &[String],
+    upkeep: UpkeepOverrides,
     json: bool,
 ) -> Result<()> {
+    if let [paths] = repos {
+        let reporter = Reporter { repo: None, json };
+        return watch_repo(
+            paths,
+            full_first,
+            debounce_ms,
+            ignore_globs,
+            upkeep,
+            reporter,
+        );
+    }
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = repos
+            .iter()
+            .map(|paths| {
+                scope.spawn(move || {
+                    let reporter = Reporter {
+                        repo: Some(&paths.repo_root),
+                        json,
+                    };
+                    let result = watch_repo(
+                        paths,
+                        full_first,
+                        debounce_ms,
+                        ignore_globs,
+                        upkeep,
+                        reporter,
+                    );
+                    if let Err(err) = &result {
+                        reporter.error(err);
+                    }
+                    result
+                })
+            })
+            .collect();
+
+        let mut first_error = None;
+        for handle in handles {
+            let result = handle
+                .join()
+                .unwrap_or_else(|_| Err(anyhow!("watcher thread panicked")));
+            if let Err(err) = result {
+                first_error.get_or_insert(err);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    })
+}
+
+fn watch_repo(
+    paths: &RuntimePaths,
+    full_first: bool,
+    debounce_ms: u64,
+    ignore_globs: &[String],
+    overrides: UpkeepOverrides,
+    reporter: Reporter<'_>,
+) -> Result<()> {
+    let ignore = IgnoreGlobs::with_env(ignore_globs)?;
+    let config = load_config(&paths.repo_root)?.serve;
+    let mut upkeep = Upkeep::new(
+        overrides
+            .optimize_interval_mins
+            .or(config.optimize_interval_mins)
+            .unwrap_or(DEFAULT_OPTIMIZE_INTERVAL_MINS),
+        overrides.compact || config.compact,
+    );
     let mut store = GraphStore::open(&paths.db_path)?;
     let initial_report = index_repository(
         &mut store,
         &paths.repo_root,
-        IndexOptions { full: full_first },
-    )?;
-    emit_report(&initial_report, json)?;
+        IndexOptions {
+            full: full_first,
+            ignore_globs: ignore_globs.to_vec(),
+            ..Default::default()
+        },
+    );
+    record_index_event(paths, &initial_report);
+    reporter.report(initial_report)?;
 
     let (tx, rx) = mpsc::channel();
     let mut watcher = recommended_watcher(move |event| {
         let _ = tx.send(event);
     })?;
     watcher.watch(&paths.repo_root, RecursiveMode::Recursive)?;
+    let git_dir = find_git_dir(&paths.repo_root);
+    if let Some(git_dir) = git_dir.as_deref() {
+        // Linked worktrees keep their git dir outside the repo root.
+        if !git_dir.starts_with(&paths.repo_root) {
+            watcher.watch(git_dir, RecursiveMode::NonRecursive)?;
+        }
+    }
+    let mut last_head = git_dir.as_deref().and_then(read_git_head);
 
     eprintln!(
         "watching {} (state: {})",
         paths.repo_root.display(),
         paths.state_dir.display()
     );
+    reporter.status("watching");
 
+    // Trees of files the watcher reparsed, so rapid saves of the same file
+    // reparse only what changed.
+    let mut trees = TreeCache::default();
     loop {
-        let first = match rx.recv() {
+        let first = match rx.recv_timeout(upkeep.next_due()) {
             Ok(event) => event,
-            Err(_) => continue,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                upkeep.run_due(&mut store, paths, reporter);
+                continue;
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => continue,
         };
 
-        let mut saw_relevant_change = false;
-        let mut force_full_rescan = false;
-        consume_event(
-            first,
-            &paths.repo_root,
-            &paths.state_dir,
-            &mut saw_relevant_change,
-            &mut force_full_rescan,
-        );
+        let mut batch = PendingBatch::default();
+        consume_event(first, paths, &ignore, git_dir.as_deref(), &mut batch);
 
         let quiet_for = Duration::from_millis(debounce_ms.max(50));
         let flush_deadline = Instant::now() + quiet_for;
@@ -74,61 +199,273 @@ pub fn run_watcher_daemon(
             }
 
             match rx.recv_timeout(flush_deadline.saturating_duration_since(now)) {
-                Ok(event) => consume_event(
-                    event,
-                    &paths.repo_root,
-                    &paths.state_dir,
-                    &mut saw_relevant_change,
-                    &mut force_full_rescan,
-                ),
+                Ok(event) => consume_event(event, paths, &ignore, git_dir.as_deref(), &mut batch),
                 Err(mpsc::RecvTimeoutError::Timeout) => break,
                 Err(mpsc::RecvTimeoutError::Disconnected) => break,
             }
         }
 
-        if !saw_relevant_change && !force_full_rescan {
+        if batch.git_refs_touched {
+            let head = git_dir.as_deref().and_then(read_git_head);
+            if is_checkout(last_head.as_deref(), head.as_deref(), &batch) {
+                eprintln!(
+                    "git HEAD moved ({} -> {}); refreshing the whole index",
+                    last_head.as_deref().unwrap_or("none"),
+                    head.as_deref().unwrap_or("none")
+                );
+                reporter.status("branch_switched");
+                if let Some(git_dir) = git_dir.as_deref() {
+                    wait_for_checkout_to_settle(&rx, git_dir);
+                }
+                // Per-file events from the checkout are covered by the full
+                // pass; renames would only replay moves it already sees.
+                batch.force_full_rescan = true;
+                batch.renames.clear();
+            }
+            last_head = head;
+        }
+
+        if !batch.saw_relevant_change && !batch.force_full_rescan {
             continue;
         }
 
-        let report = index_repository(
+        let report = index_repository_with_trees(
             &mut store,
             &paths.repo_root,
             IndexOptions {
-                full: force_full_rescan,
+                full: batch.force_full_rescan,
+                renames: batch.renames,
+                ignore_globs: ignore_globs.to_vec(),
+                ..Default::default()
             },
-        )?;
-        emit_report(&report, json)?;
+            &mut trees,
+        );
+        record_index_event(paths, &report);
+        reporter.report(report)?;
+        upkeep.wrote();
+        upkeep.run_due(&mut store, paths, reporter);
+    }
+}
+
+/// Failures only delay the work until the next pass; they never stop the
+/// watcher.
+struct Upkeep {
+    last_checkpoint: Instant,
+    wrote_since_checkpoint: bool,
+    optimize_every: Option<Duration>,
+    last_optimize: Instant,
+    wrote_since_optimize: bool,
+    compact: bool,
+}
+
+impl Upkeep {
+    fn new(optimize_interval_mins: u64, compact: bool) -> Self {
+        Self {
+            last_checkpoint: Instant::now(),
+            wrote_since_checkpoint: true,
+            optimize_every: (optimize_interval_mins > 0)
+                .then(|| Duration::from_secs(optimize_interval_mins.saturating_mul(60))),
+            last_optimize: Instant::now(),
+            wrote_since_optimize: true,
+            compact,
+        }
+    }
+
+    fn wrote(&mut self) {
+        self.wrote_since_checkpoint = true;
+        self.wrote_since_optimize = true;
+    }
+
+    fn next_due(&self) -> Duration {
+        let checkpoint = WAL_CHECKPOINT_INTERVAL.saturating_sub(self.last_checkpoint.elapsed());
+        match self.optimize_every {
+            Some(every) => checkpoint.min(every.saturating_sub(self.last_optimize.elapsed())),
+            None => checkpoint,
+        }
+    }
+
+    fn run_due(&mut self, store: &mut GraphStore, paths: &RuntimePaths, reporter: Reporter<'_>) {
+        if self.last_checkpoint.elapsed() >= WAL_CHECKPOINT_INTERVAL {
+            if self.wrote_since_checkpoint {
+                if let Err(err) = store.checkpoint_wal() {
+                    eprintln!("wal checkpoint failed: {err:#}");
+                }
+                self.wrote_since_checkpoint = false;
+            }
+            self.last_checkpoint = Instant::now();
+        }
+
+        let Some(every) = self.optimize_every else {
+            return;
+        };
+        if self.last_optimize.elapsed() < every {
+            return;
+        }
+        if self.wrote_since_optimize {
+            let staged = paths
+                .state_dir
+                .join(format!(".compact-{}.db", std::process::id()));
+            match store.optimize(self.compact.then_some(staged.as_path())) {
+                Ok(report) => reporter.optimized(&report),
+                Err(err) => eprintln!("optimize failed: {err:#}"),
+            }
+            self.wrote_since_optimize = false;
+        }
+        self.last_optimize = Instant::now();
+    }
+}
+
+/// Appends the paths a run touched to the state dir's event log, where MCP
+/// sessions pick them up. Failing to log never stops the watcher.
+fn record_index_event(paths: &RuntimePaths, report: &Result<IndexReport>) {
+    let Ok(report) = report else {
+        return;
+    };
+    if report.changed_paths.is_empty() {
+        return;
+    }
+    let event = IndexChangedEvent::new(report.changed_paths.clone());
+    if let Err(err) = append_event(&paths.state_dir, &event) {
+        eprintln!("event log write failed: {err:#}");
     }
 }
 
 fn consume_event(
     event: notify::Result<Event>,
-    repo_root: &Path,
-    state_dir: &Path,
-    saw_relevant_change: &mut bool,
-    force_full_rescan: &mut bool,
+    paths: &RuntimePaths,
+    ignore: &IgnoreGlobs,
+    git_dir: Option<&Path>,
+    batch: &mut PendingBatch,
 ) {
+    let (repo_root, state_dir) = (&paths.repo_root, &paths.state_dir);
     match event {
         Ok(event) => {
             if event.paths.is_empty() {
-                *saw_relevant_change = true;
+                batch.saw_relevant_change = true;
                 return;
             }
+            if let Some(git_dir) = git_dir {
+                if event
+                    .paths
+                    .iter()
+                    .any(|path| is_git_ref_path(path, git_dir))
+                {
+                    batch.git_refs_touched = true;
+                }
+            }
+            if let (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) =
+                (&event.kind, event.paths.as_slice())
+            {
+                if let (Some(from), Some(to)) = (
+                    rel_watch_path(from, repo_root, state_dir, ignore),
+                    rel_watch_path(to, repo_root, state_dir, ignore),
+                ) {
+                    batch.renames.push((from, to));
+                }
+            }
             for path in event.paths {
-                if is_relevant_path(&path, repo_root, state_dir) {
-                    *saw_relevant_change = true;
+                if is_relevant_path(&path, repo_root, state_dir, ignore) {
+                    batch.saw_relevant_change = true;
                     return;
                 }
             }
         }
         Err(err) => {
             eprintln!("watch error: {err}");
-            *force_full_rescan = true;
+            batch.force_full_rescan = true;
         }
     }
 }
 
-fn is_relevant_path(path: &Path, repo_root: &Path, state_dir: &Path) -> bool {
+/// Whether a HEAD move rewrote the working tree. Switching refs always
+/// counts; moving the same branch only counts alongside file changes
+/// (reset, pull, rebase), so a plain `git commit` does not force a refresh.
+fn is_checkout(before: Option<&str>, after: Option<&str>, batch: &PendingBatch) -> bool {
+    if before == after {
+        return false;
+    }
+    before.map(head_ref) != after.map(head_ref) || batch.saw_relevant_change
+}
+
+/// The ref part of a [`read_git_head`] value (the whole value when detached).
+fn head_ref(head: &str) -> &str {
+    head.rsplit_once('@')
+        .map_or(head, |(reference, _)| reference)
+}
+
+/// Drains watcher events until the working tree has been quiet for
+/// [`BRANCH_SWITCH_SETTLE`] and git no longer holds its index lock.
+fn wait_for_checkout_to_settle(rx: &mpsc::Receiver<notify::Result<Event>>, git_dir: &Path) {
+    let started = Instant::now();
+    let index_lock = git_dir.join("index.lock");
+    loop {
+        if started.elapsed() >= BRANCH_SWITCH_MAX_WAIT {
+            return;
+        }
+        match rx.recv_timeout(BRANCH_SWITCH_SETTLE) {
+            Ok(_) => continue,
+            Err(mpsc::RecvTimeoutError::Timeout) if !index_lock.exists() => return,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// The repository's git dir: `.git` itself, or the target of a `.git` file
+/// (`gitdir: ...`) as used by linked worktrees and submodules.
+pub fn find_git_dir(repo_root: &Path) -> Option<PathBuf> {
+    let dot_git = repo_root.join(".git");
+    if dot_git.is_dir() {
+        return Some(dot_git);
+    }
+    let pointer = fs::read_to_string(&dot_git).ok()?;
+    let target = pointer.trim().strip_prefix("gitdir:")?.trim();
+    let target = Path::new(target);
+    Some(if target.is_absolute() {
+        target.to_path_buf()
+    } else {
+        repo_root.join(target)
+    })
+}
+
+/// Files whose writes mean HEAD may now point at a different commit.
+fn is_git_ref_path(path: &Path, git_dir: &Path) -> bool {
+    let Ok(rel) = path.strip_prefix(git_dir) else {
+        return false;
+    };
+    rel == Path::new("HEAD") || rel == Path::new("packed-refs") || rel.starts_with("refs")
+}
+
+/// Resolves HEAD to `<ref>@<commit>` (or just the commit when detached), so
+/// both branch switches and resets of the current branch register as moves.
+pub fn read_git_head(git_dir: &Path) -> Option<String> {
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    let Some(reference) = head.strip_prefix("ref:").map(str::trim) else {
+        return Some(head.to_string());
+    };
+    // Linked worktrees keep branch refs in the shared common dir.
+    let common_dir = fs::read_to_string(git_dir.join("commondir"))
+        .map(|dir| git_dir.join(dir.trim()))
+        .unwrap_or_else(|_| git_dir.to_path_buf());
+    let commit = [git_dir, common_dir.as_path()]
+        .iter()
+        .find_map(|dir| fs::read_to_string(dir.join(reference)).ok())
+        .map(|commit| commit.trim().to_string())
+        .or_else(|| packed_ref(&common_dir, reference))
+        .unwrap_or_default();
+    Some(format!("{reference}@{commit}"))
+}
+
+fn packed_ref(common_dir: &Path, reference: &str) -> Option<String> {
+    let packed = fs::read_to_string(common_dir.join("packed-refs")).ok()?;
+    packed.lines().find_map(|line| {
+        let (commit, name) = line.split_once(' ')?;
+        (name.trim() == reference).then(|| commit.to_string())
+    })
+}
+
+fn is_relevant_path(path: &Path, repo_root: &Path, state_dir: &Path, ignore: &IgnoreGlobs) -> bool {
     if path.starts_with(state_dir) {
         return false;
     }
@@ -149,7 +486,124 @@ fn is_relevant_path(path: &Path, repo_root: &Path, state_dir: &Path) -> bool {
         }
     }
 
-    true
+    !ignore.is_ignored(&rel.to_string_lossy(), path.is_dir())
+}
+
+fn rel_watch_path(
+    path: &Path,
+    repo_root: &Path,
+    state_dir: &Path,
+    ignore: &IgnoreGlobs,
+) -> Option<String> {
+    if !is_relevant_path(path, repo_root, state_dir, ignore) {
+        return None;
+    }
+    let rel = path.strip_prefix(repo_root).ok()?;
+    Some(rel.to_string_lossy().replace('\\', "/"))
+}
+
+/// Writes daemon events. Untagged output is the single-repo format; tagged
+/// output prefixes text lines with the repo and emits one JSON object per
+/// line carrying `repo` and `status`.
+#[derive(Clone, Copy)]
+struct Reporter<'a> {
+    repo: Option<&'a Path>,
+    json: bool,
+}
+
+impl Reporter<'_> {
+    /// Emits an index result. Untagged runs stop on errors as before; tagged
+    /// runs report the error and keep watching so other repos are unaffected.
+    fn report(&self, report: Result<IndexReport>) -> Result<()> {
+        let Some(repo) = self.repo else {
+            return emit_report(&report?, self.json);
+        };
+        let report = match report {
+            Ok(report) => report,
+            Err(err) => {
+                self.error(&err);
+                return Ok(());
+            }
+        };
+        if self.json {
+            println!(
+                "{}",
+                serde_json::to_string(&json!({
+                    "repo": repo.display().to_string(),
+                    "status": "indexed",
+                    "report": report,
+                }))?
+            );
+            return Ok(());
+        }
+        println!(
+            "[{}] indexed={} skipped={} removed={} renamed={} parse_failures={} errors={}",
+            repo.display(),
+            report.indexed_files,
+            report.skipped_files,
+            report.removed_files,
+            report.renamed_files,
+            report.parse_failures,
+            report.errors.len()
+        );
+        for error in &report.errors {
+            eprintln!("[{}] index warning: {error}", repo.display());
+        }
+        Ok(())
+    }
+
+    fn optimized(&self, report: &OptimizeReport) {
+        if self.json {
+            let mut event = json!({ "status": "optimized", "optimize": report });
+            if let Some(repo) = self.repo {
+                event["repo"] = json!(repo.display().to_string());
+            }
+            println!("{event}");
+            return;
+        }
+        let prefix = self
+            .repo
+            .map(|repo| format!("[{}] ", repo.display()))
+            .unwrap_or_default();
+        let compacted = match report.compact_ms {
+            Some(ms) => format!(
+                " compacted in {ms:.1} ms: {} -> {} bytes (reclaimed {})",
+                report.bytes_before, report.bytes_after, report.bytes_reclaimed
+            ),
+            None => format!(" free_bytes={}", report.free_bytes_before),
+        };
+        println!(
+            "{prefix}optimized analyze={:.1} ms{compacted}",
+            report.analyze_ms
+        );
+    }
+
+    fn status(&self, status: &str) {
+        if let (Some(repo), true) = (self.repo, self.json) {
+            println!(
+                "{}",
+                json!({ "repo": repo.display().to_string(), "status": status })
+            );
+        }
+    }
+
+    fn error(&self, err: &anyhow::Error) {
+        let Some(repo) = self.repo else {
+            return;
+        };
+        if self.json {
+            println!(
+                "{}",
+                json!({
+                    "repo": repo.display().to_string(),
+                    "status": "error",
+                    "error": format!("{err:#}"),
+                })
+            );
+        } else {
+            eprintln!("[{}] index error: {err:#}", repo.display());
+        }
+    }
 }
 
 fn emit_report(report: &IndexReport, json: bool) -> Result<()> {
@@ -157,10 +611,11 @@ fn emit_report(report: &IndexReport, json: bool) -> Result<()> {
         println!("{}", serde_json::to_string_pretty(report)?);
     } else {
         println!(
-            "indexed={} skipped={} removed={} parse_failures={} errors={}",
+            "indexed={} skipped={} removed={} renamed={} parse_failures={} errors={}",
             report.indexed_files,
             report.skipped_files,
             report.removed_files,
+            report.renamed_files,
             report.parse_failures,
             report.errors.len()
         );
@@ -171,3 +626,148 @@ fn emit_report(report: &IndexReport, json: bool) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, DataChange};
+    use tempfile::TempDir;
+
+    fn test_paths(dir: &TempDir) -> RuntimePaths {
+        let repo_root = dir.path().to_path_buf();
+        let state_dir = repo_root.join(STATE_DIR_NAME);
+        RuntimePaths {
+            db_path: state_dir.join("graph.db"),
+            repo_root,
+            state_dir,
+        }
+    }
+
+    #[test]
+    fn read_git_head_resolves_loose_packed_and_detached_heads() {
+        let dir = TempDir::new().unwrap();
+        let git_dir = dir.path().join(".git");
+        fs::create_dir_all(git_dir.join("refs/heads")).unwrap();
+
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(git_dir.join("refs/heads/main"), "aaa111\n").unwrap();
+        assert_eq!(
+            read_git_head(&git_dir).as_deref(),
+            Some("refs/heads/main@aaa111")
+        );
+
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/feature\n").unwrap();
+        fs::write(
+            git_dir.join("packed-refs"),
+            "# pack-refs with: peeled\nbbb222 refs/heads/feature\n",
+        )
+        .unwrap();
+        assert_eq!(
+            read_git_head(&git_dir).as_deref(),
+            Some("refs/heads/feature@bbb222")
+        );
+
+        fs::write(git_dir.join("HEAD"), "ccc333\n").unwrap();
+        assert_eq!(read_git_head(&git_dir).as_deref(), Some("ccc333"));
+    }
+
+    #[test]
+    fn find_git_dir_follows_gitdir_files() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(find_git_dir(dir.path()), None);
+
+        fs::write(
+            dir.path().join(".git"),
+            "gitdir: ../main/.git/worktrees/wt\n",
+        )
+        .unwrap();
+        assert_eq!(
+            find_git_dir(dir.path()),
+            Some(dir.path().join("../main/.git/worktrees/wt"))
+        );
+    }
+
+    #[test]
+    fn upkeep_waits_for_the_nearest_due_pass() {
+        let upkeep = Upkeep::new(1, false);
+        assert!(upkeep.next_due() <= Duration::from_secs(60));
+        assert!(upkeep.next_due() > Duration::from_secs(50));
+
+        let disabled = Upkeep::new(0, true);
+        assert!(disabled.optimize_every.is_none());
+        assert!(disabled.next_due() > Duration::from_secs(60));
+    }
+
+    #[test]
+    fn is_checkout_ignores_commits_on_the_current_branch() {
+        let quiet = PendingBatch::default();
+        let with_files = PendingBatch {
+            saw_relevant_change: true,
+            ..Default::default()
+        };
+        let main_a = Some("refs/heads/main@a");
+        let main_b = Some("refs/heads/main@b");
+        let feature = Some("refs/heads/feature@a");
+
+        assert!(!is_checkout(main_a, main_a, &with_files));
+        assert!(!is_checkout(main_a, main_b, &quiet), "plain commit");
+        assert!(is_checkout(main_a, main_b, &with_files), "reset or pull");
+        assert!(is_checkout(main_a, feature, &quiet), "branch switch");
+        assert!(is_checkout(main_a, Some("b"), &quiet), "detached checkout");
+    }
+
+    #[test]
+    fn consume_event_flags_git_ref_writes_without_treating_them_as_sources() {
+        let dir = TempDir::new().unwrap();
+        let paths = test_paths(&dir);
+        let git_dir = dir.path().join(".git");
+        let event = |path: PathBuf| {
+            Ok(Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content))).add_path(path))
+        };
+
+        let mut batch = PendingBatch::default();
+        consume_event(
+            event(git_dir.join("HEAD")),
+            &paths,
+            &IgnoreGlobs::default(),
+            Some(&git_dir),
+            &mut batch,
+        );
+        assert!(batch.git_refs_touched);
+        assert!(!batch.saw_relevant_change, ".git is not indexed");
+
+        let mut batch = PendingBatch::default();
+        consume_event(
+            event(git_dir.join("objects/ab/cdef")),
+            &paths,
+            &IgnoreGlobs::default(),
+            Some(&git_dir),
+            &mut batch,
+        );
+        assert!(!batch.git_refs_touched);
+
+        let mut batch = PendingBatch::default();
+        consume_event(
+            Ok(Event::new(EventKind::Create(CreateKind::File))
+                .add_path(dir.path().join("src/lib.rs"))),
+            &paths,
+            &IgnoreGlobs::default(),
+            Some(&git_dir),
+            &mut batch,
+        );
+        assert!(batch.saw_relevant_change);
+        assert!(!batch.git_refs_touched);
+
+        let ignore = IgnoreGlobs::new(&["proto_gen/".to_string()]).unwrap();
+        let mut batch = PendingBatch::default();
+        consume_event(
+            Ok(Event::new(EventKind::Create(CreateKind::File))
+                .add_path(dir.path().join("proto_gen/api.rs"))),
+            &paths,
+            &ignore,
+            Some(&git_dir),
+            &mut batch,
+        );
+        assert!(!batch.saw_relevant_change, "ignore globs are not watched");
+    }
+}
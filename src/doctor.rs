@@ -0,0 +1,304 @@
+use std::fs;
+use std::process::Command;
+
+use notify::{recommended_watcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use tree_sitter::{Parser, Query};
+
+use crate::languages::language_configs;
+use crate::paths::RuntimePaths;
+use crate::storage::{GraphStore, SCHEMA_VERSION};
+
+pub const STATUS_PASS: &str = "pass";
+pub const STATUS_WARN: &str = "warn";
+pub const STATUS_FAIL: &str = "fail";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorCheck {
+    pub name: &'static str,
+    pub status: &'static str,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorReport {
+    pub repo_root: String,
+    pub state_dir: String,
+    pub db_path: String,
+    pub ok: bool,
+    pub checks: Vec<DoctorCheck>,
+}
+
+impl DoctorReport {
+    pub fn failures(&self) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| check.status == STATUS_FAIL)
+            .count()
+    }
+}
+
+/// Runs every environment check. Checks never abort the run; each one reports
+/// its own pass/warn/fail status so users see all problems at once.
+pub fn run_doctor(paths: &RuntimePaths) -> DoctorReport {
+    let checks = vec![
+        check_git(paths),
+        check_state_dir(paths),
+        check_database(paths),
+        check_grammars(),
+        check_watcher(paths),
+    ];
+    let ok = checks.iter().all(|check| check.status != STATUS_FAIL);
+
+    DoctorReport {
+        repo_root: paths.repo_root.display().to_string(),
+        state_dir: paths.state_dir.display().to_string(),
+        db_path: paths.db_path.display().to_string(),
+        ok,
+        checks,
+    }
+}
+
+fn check(
+    name: &'static str,
+    status: &'static str,
+    detail: impl Into<String>,
+    hint: Option<&str>,
+) -> DoctorCheck {
+    DoctorCheck {
+        name,
+        status,
+        detail: detail.into(),
+        hint: hint.map(ToString::to_string),
+    }
+}
+
+fn check_git(paths: &RuntimePaths) -> DoctorCheck {
+    if !paths.repo_root.join(".git").exists() {
+        return check(
+            "git",
+            STATUS_WARN,
+            format!("no .git found at or above {}", paths.repo_root.display()),
+            Some("run from inside a git checkout or pass --repo <path>; without one, the current directory is indexed as the repo root"),
+        );
+    }
+
+    match Command::new("git").arg("--version").output() {
+        Ok(output) if output.status.success() => check(
+            "git",
+            STATUS_PASS,
+            format!(
+                "repository at {} ({})",
+                paths.repo_root.display(),
+                String::from_utf8_lossy(&output.stdout).trim()
+            ),
+            None,
+        ),
+        _ => check(
+            "git",
+            STATUS_WARN,
+            format!(
+                "repository at {} but the git executable was not found",
+                paths.repo_root.display()
+            ),
+            Some("install git and make sure it is on PATH"),
+        ),
+    }
+}
+
+fn check_state_dir(paths: &RuntimePaths) -> DoctorCheck {
+    if !paths.state_dir.is_dir() {
+        return check(
+            "state_dir",
+            STATUS_WARN,
+            format!("{} does not exist yet", paths.state_dir.display()),
+            Some("run `lumora init` or `lumora index` to create it"),
+        );
+    }
+
+    let probe = paths.state_dir.join(".doctor-probe");
+    let result = fs::write(&probe, b"ok").and_then(|_| fs::remove_file(&probe));
+
+    match result {
+        Ok(()) => check(
+            "state_dir",
+            STATUS_PASS,
+            format!("{} is writable", paths.state_dir.display()),
+            None,
+        ),
+        Err(err) => check(
+            "state_dir",
+            STATUS_FAIL,
+            format!("{} is not writable: {err}", paths.state_dir.display()),
+            Some("fix the directory permissions or pass --state-dir <path> to a writable location"),
+        ),
+    }
+}
+
+fn check_database(paths: &RuntimePaths) -> DoctorCheck {
+    if !paths.db_path.exists() {
+        return check(
+            "database",
+            STATUS_WARN,
+            format!("{} does not exist yet", paths.db_path.display()),
+            Some("run `lumora index` to build the graph"),
+        );
+    }
+
+    match GraphStore::read_schema_version(&paths.db_path) {
+        Ok(Some(version)) if version == SCHEMA_VERSION => check(
+            "database",
+            STATUS_PASS,
+            format!("{} (schema v{version})", paths.db_path.display()),
+            None,
+        ),
+        Ok(Some(version)) => check(
+            "database",
+            STATUS_FAIL,
+            format!(
+                "{} has schema v{version}, expected v{SCHEMA_VERSION}",
+                paths.db_path.display()
+            ),
+            Some("delete the database and run `lumora index --full`; the index is regenerable from source"),
+        ),
+        Ok(None) => check(
+            "database",
+            STATUS_FAIL,
+            format!("{} has no schema version", paths.db_path.display()),
+            Some("the file may not be a Lumora database; pass --db <path> or delete it and run `lumora index --full`"),
+        ),
+        Err(err) => check(
+            "database",
+            STATUS_FAIL,
+            format!("{err:#}"),
+            Some("check file permissions, or delete the database and run `lumora index --full`"),
+        ),
+    }
+}
+
+fn check_grammars() -> DoctorCheck {
+    let configs = language_configs();
+    let mut broken = Vec::new();
+    for config in &configs {
+        let mut parser = Parser::new();
+        if let Err(err) = parser.set_language(&config.grammar) {
+            broken.push(format!("{}: {err}", config.kind.as_str()));
+            continue;
+        }
+        if config.tags_query.trim().is_empty() {
+            continue;
+        }
+        if let Err(err) = Query::new(&config.grammar, config.tags_query) {
+            broken.push(format!("{} query: {err}", config.kind.as_str()));
+        }
+    }
+
+    if broken.is_empty() {
+        check(
+            "grammars",
+            STATUS_PASS,
+            format!("{} grammars and queries loaded", configs.len()),
+            None,
+        )
+    } else {
+        check(
+            "grammars",
+            STATUS_FAIL,
+            broken.join("; "),
+            Some("reinstall lumora (`cargo install lumora --force`); grammars are compiled into the binary"),
+        )
+    }
+}
+
+fn check_watcher(paths: &RuntimePaths) -> DoctorCheck {
+    let result = recommended_watcher(|_| {})
+        .and_then(|mut watcher| watcher.watch(&paths.repo_root, RecursiveMode::NonRecursive));
+
+    match result {
+        Ok(()) => check(
+            "watcher",
+            STATUS_PASS,
+            "file watcher backend is available",
+            None,
+        ),
+        Err(err) => check(
+            "watcher",
+            STATUS_FAIL,
+            format!("file watcher backend unavailable: {err}"),
+            Some("`lumora serve` needs OS file notifications; raise inotify limits (fs.inotify.max_user_watches) or run `lumora index` on demand instead"),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+    use tempfile::TempDir;
+
+    fn temp_paths(temp: &TempDir) -> RuntimePaths {
+        let repo_root = temp.path().join("repo");
+        fs::create_dir_all(repo_root.join(".git")).unwrap();
+        RuntimePaths {
+            state_dir: repo_root.join(".lumora"),
+            db_path: repo_root.join(".lumora").join("graph.db"),
+            repo_root,
+        }
+    }
+
+    fn status_of(report: &DoctorReport, name: &str) -> &'static str {
+        report
+            .checks
+            .iter()
+            .find(|check| check.name == name)
+            .map(|check| check.status)
+            .unwrap()
+    }
+
+    #[test]
+    fn doctor_warns_on_missing_db_and_passes_once_opened() {
+        let temp = TempDir::new().unwrap();
+        let paths = temp_paths(&temp);
+
+        let report = run_doctor(&paths);
+        assert!(report.ok);
+        assert_eq!(status_of(&report, "database"), STATUS_WARN);
+        assert_eq!(status_of(&report, "state_dir"), STATUS_WARN);
+        assert_eq!(status_of(&report, "grammars"), STATUS_PASS);
+        assert!(!paths.state_dir.exists(), "doctor only inspects");
+
+        fs::create_dir_all(&paths.state_dir).unwrap();
+        GraphStore::open(&paths.db_path).unwrap();
+        let report = run_doctor(&paths);
+        assert_eq!(status_of(&report, "database"), STATUS_PASS);
+        assert_eq!(status_of(&report, "state_dir"), STATUS_PASS);
+    }
+
+    #[test]
+    fn doctor_fails_on_schema_mismatch_with_hint() {
+        let temp = TempDir::new().unwrap();
+        let paths = temp_paths(&temp);
+        fs::create_dir_all(&paths.state_dir).unwrap();
+        let conn = Connection::open(&paths.db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+             INSERT INTO meta(key, value) VALUES('schema_version', '0');",
+        )
+        .unwrap();
+        drop(conn);
+
+        let report = run_doctor(&paths);
+        assert!(!report.ok);
+        assert_eq!(report.failures(), 1);
+        let database = report
+            .checks
+            .iter()
+            .find(|check| check.name == "database")
+            .unwrap();
+        assert_eq!(database.status, STATUS_FAIL);
+        assert!(database.detail.contains("expected v1"));
+        assert!(database.hint.is_some());
+    }
+}
@@ -0,0 +1,241 @@
+use anyhow::Result;
+
+use crate::model::FileExtraction;
+
+/// Sentence-embedding model behind `lumora.semantic_search`; its files are
+/// downloaded once into the global state directory.
+pub const EMBEDDING_MODEL: &str = "all-MiniLM-L6-v2";
+
+const CHUNK_LINES: usize = 40;
+const MAX_SYMBOL_LINES: usize = 30;
+#[cfg(feature = "semantic")]
+const EMBED_BATCH_SIZE: usize = 32;
+
+/// A unit of text embedded during indexing: either a symbol definition or a
+/// fixed-size line window of a file.
+#[derive(Debug, Clone)]
+pub struct EmbeddingRecord {
+    pub kind: &'static str,
+    pub label: String,
+    pub detail: Option<String>,
+    pub start_line: i64,
+    pub end_line: i64,
+    pub text: String,
+    /// Filled in by [`Embedder::embed_records`].
+    pub vector: Vec<f32>,
+}
+
+#[cfg(feature = "semantic")]
+fn model_cache_dir() -> Result<std::path::PathBuf> {
+    Ok(crate::paths::global_state_root()?.join("models"))
+}
+
+#[cfg(feature = "semantic")]
+pub struct Embedder {
+    model: fastembed::TextEmbedding,
+}
+
+/// Stand-in for builds without the `semantic` feature; it cannot be loaded.
+#[cfg(not(feature = "semantic"))]
+pub struct Embedder {
+    never: std::convert::Infallible,
+}
+
+impl Embedder {
+    /// The process-wide model, loaded on first use so the watcher does not
+    /// reload it for every batch.
+    pub fn shared() -> Result<&'static Embedder> {
+        static SHARED: std::sync::OnceLock<Embedder> = std::sync::OnceLock::new();
+        if let Some(embedder) = SHARED.get() {
+            return Ok(embedder);
+        }
+        let embedder = Self::load()?;
+        Ok(SHARED.get_or_init(|| embedder))
+    }
+
+    #[cfg(feature = "semantic")]
+    fn load() -> Result<Self> {
+        use anyhow::Context;
+        use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+
+        let options = InitOptions::new(EmbeddingModel::AllMiniLML6V2)
+            .with_cache_dir(model_cache_dir()?)
+            .with_show_download_progress(false);
+        let model = TextEmbedding::try_new(options).with_context(|| {
+            format!("failed to load the {EMBEDDING_MODEL} embedding model; is ORT_DYLIB_PATH set to an ONNX Runtime library?")
+        })?;
+        Ok(Self { model })
+    }
+
+    #[cfg(not(feature = "semantic"))]
+    fn load() -> Result<Self> {
+        anyhow::bail!(
+            "this lumora was built without the `semantic` feature; reinstall with `cargo install lumora --features semantic` to compute {EMBEDDING_MODEL} embeddings"
+        )
+    }
+
+    #[cfg(feature = "semantic")]
+    pub fn embed(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        self.model.embed(texts, Some(EMBED_BATCH_SIZE))
+    }
+
+    #[cfg(not(feature = "semantic"))]
+    pub fn embed(&self, _texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        match self.never {}
+    }
+
+    pub fn embed_records(&self, records: &mut [EmbeddingRecord]) -> Result<()> {
+        let texts = records.iter().map(|record| record.text.clone()).collect();
+        for (record, vector) in records.iter_mut().zip(self.embed(texts)?) {
+            record.vector = vector;
+        }
+        Ok(())
+    }
+}
+
+/// The texts to embed for one indexed file: each definition, then the file in
+/// fixed-size chunks.
+pub fn build_file_embeddings(
+    file_path: &str,
+    content: &str,
+    extraction: &FileExtraction,
+) -> Vec<EmbeddingRecord> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut records = Vec::new();
+
+    for definition in &extraction.definitions {
+        let start = (definition.line.max(1) - 1) as usize;
+        let end = (definition.end_line.max(definition.line) as usize)
+            .min(lines.len())
+            .min(start + MAX_SYMBOL_LINES);
+        let body = lines.get(start..end).unwrap_or_default().join("\n");
+        let text = format!(
+            "{} {} {} {file_path}\n{body}",
+            definition.qualname, definition.name, definition.kind
+        );
+        records.push(EmbeddingRecord {
+            kind: "symbol",
+            label: definition.qualname.clone(),
+            detail: Some(definition.kind.clone()),
+            start_line: definition.line,
+            end_line: definition.end_line,
+            text,
+            vector: Vec::new(),
+        });
+    }
+
+    for (chunk_index, chunk) in lines.chunks(CHUNK_LINES).enumerate() {
+        let text = chunk.join("\n");
+        if text.trim().is_empty() {
+            continue;
+        }
+        let start_line = (chunk_index * CHUNK_LINES + 1) as i64;
+        let end_line = start_line + chunk.len() as i64 - 1;
+        records.push(EmbeddingRecord {
+            kind: "chunk",
+            label: format!("{file_path}:{start_line}-{end_line}"),
+            detail: None,
+            start_line,
+            end_line,
+            text: format!("{file_path}\n{text}"),
+            vector: Vec::new(),
+        });
+    }
+
+    records
+}
+
+pub fn cosine_similarity(left: &[f32], right: &[f32]) -> f32 {
+    let dot: f32 = left.iter().zip(right).map(|(a, b)| a * b).sum();
+    let norms = left.iter().map(|a| a * a).sum::<f32>().sqrt()
+        * right.iter().map(|b| b * b).sum::<f32>().sqrt();
+    if norms > 0.0 {
+        dot / norms
+    } else {
+        0.0
+    }
+}
+
+pub fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+pub fn decode_vector(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Definition, LanguageKind};
+
+    #[test]
+    fn cosine_similarity_ignores_vector_length() {
+        assert!((cosine_similarity(&[1.0, 1.0], &[2.0, 2.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 3.0]).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn build_file_embeddings_emits_symbols_and_chunks() {
+        let content = "fn greet() {\n    println!(\"hi\");\n}\n";
+        let extraction = FileExtraction {
+            language: LanguageKind::Rust,
+            definitions: vec![Definition {
+                name: "greet".into(),
+                qualname: "greet".into(),
+                kind: "function_item".into(),
+                line: 1,
+                col: 1,
+                end_line: 3,
+                end_col: 1,
+                signature: None,
+                arity: None,
+                offsets: None,
+                visibility: None,
+                modifiers: Vec::new(),
+            }],
+            references: vec![],
+            imports: vec![],
+            relations: vec![],
+            noise_spans: vec![],
+            annotations: vec![],
+        };
+        let records = build_file_embeddings("src/lib.rs", content, &extraction);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].kind, "symbol");
+        assert_eq!(records[1].kind, "chunk");
+        assert_eq!(records[1].label, "src/lib.rs:1-3");
+        assert!(records[0]
+            .text
+            .starts_with("greet greet function_item src/lib.rs\n"));
+        assert!(records[1].text.starts_with("src/lib.rs\nfn greet()"));
+        let vector = vec![0.25, -1.5, 3.0];
+        assert_eq!(decode_vector(&encode_vector(&vector)), vector);
+    }
+
+    /// Downloads the model and needs ONNX Runtime, so it only runs in
+    /// `--features semantic` builds.
+    #[cfg(feature = "semantic")]
+    #[test]
+    fn embedder_ranks_related_text_higher() {
+        let embedder = Embedder::shared().unwrap();
+        let vectors = embedder
+            .embed(vec![
+                "parse a selector string".to_string(),
+                "fn parse_selector(input: &str) -> Selector".to_string(),
+                "fn write_file_contents(path: &Path)".to_string(),
+            ])
+            .unwrap();
+        assert!(
+            cosine_similarity(&vectors[0], &vectors[1])
+                > cosine_similarity(&vectors[0], &vectors[2])
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::model::{EntryPoint, SymbolLocation};
+use crate::packages::{join, parent_dir, CARGO_MANIFEST_FILE_NAME, NPM_MANIFEST_FILE_NAME};
+use crate::storage::{GraphStore, SymbolSearchOptions};
+
+const SCRIPT_EXTENSIONS: &[&str] = &["js", "mjs", "cjs", "ts", "mts", "cts", "tsx", "py"];
+
+const ROOT_ENTRY_FILES: &[&str] = &["src/main.rs", "src/lib.rs", "__main__.py"];
+
+/// Each entry lists up to `max_calls` repo-defined names called from its
+/// `main` function, or from the whole file when it has none.
+pub fn find_entrypoints(
+    store: &GraphStore,
+    repo_root: &Path,
+    max_calls: usize,
+) -> Result<Vec<EntryPoint>> {
+    let tracked = store.tracked_files()?;
+    let mut manifests: Vec<&String> = tracked
+        .iter()
+        .filter(|path| {
+            let name = path.rsplit('/').next().unwrap_or_default();
+            name == CARGO_MANIFEST_FILE_NAME || name == NPM_MANIFEST_FILE_NAME
+        })
+        .collect();
+    manifests.sort();
+
+    let mut out = Vec::new();
+    for manifest in manifests {
+        let Ok(text) = fs::read_to_string(repo_root.join(manifest)) else {
+            continue;
+        };
+        if manifest.ends_with(CARGO_MANIFEST_FILE_NAME) {
+            cargo_entrypoints(manifest, &text, &tracked, &mut out);
+        } else {
+            npm_entrypoints(manifest, &text, &tracked, &mut out);
+        }
+    }
+
+    let mains: Vec<SymbolLocation> = store
+        .symbols_matching(
+            "^main$",
+            &SymbolSearchOptions {
+                limit: usize::MAX,
+                ..Default::default()
+            },
+        )?
+        .into_iter()
+        .filter(|symbol| {
+            symbol.symbol_name == "main"
+                && (symbol.kind.contains("function") || symbol.kind.contains("method"))
+        })
+        .collect();
+    let covered: HashSet<String> = out
+        .iter()
+        .filter_map(|entry| entry.file_path.clone())
+        .collect();
+    for symbol in &mains {
+        if !covered.contains(&symbol.file_path) {
+            out.push(entry(
+                "main_function",
+                &symbol.qualname,
+                Some(&symbol.file_path),
+            ));
+        }
+    }
+    let covered: HashSet<String> = out
+        .iter()
+        .filter_map(|entry| entry.file_path.clone())
+        .collect();
+    // The indexer only classifies nested roots (`pkg/src/main.rs`), so the
+    // repo-root ones are picked up from the tracked files.
+    let mut roots: BTreeSet<String> = store.entrypoint_files()?.into_iter().collect();
+    roots.extend(
+        ROOT_ENTRY_FILES
+            .iter()
+            .filter(|path| tracked.contains(**path))
+            .map(|path| path.to_string()),
+    );
+    for file in roots {
+        if !covered.contains(&file) {
+            let name = file.rsplit('/').next().unwrap_or(&file).to_string();
+            out.push(entry("entry_file", &name, Some(&file)));
+        }
+    }
+
+    for item in &mut out {
+        let Some(file) = item.file_path.as_deref() else {
+            continue;
+        };
+        let main = mains.iter().find(|symbol| symbol.file_path == file);
+        let (start, end) = match main {
+            Some(main) => (main.line, main.end_line.unwrap_or(main.line)),
+            None => (1, i64::MAX),
+        };
+        item.line = main.map(|main| main.line);
+        item.calls = store.calls_in_span(file, start, end, max_calls)?;
+    }
+    Ok(out)
+}
+
+fn entry(kind: &str, name: &str, file_path: Option<&str>) -> EntryPoint {
+    EntryPoint {
+        kind: kind.to_string(),
+        name: name.to_string(),
+        file_path: file_path.map(str::to_string),
+        line: None,
+        declared_in: None,
+        command: None,
+        calls: Vec::new(),
+    }
+}
+
+fn cargo_entrypoints(
+    manifest: &str,
+    text: &str,
+    tracked: &HashSet<String>,
+    out: &mut Vec<EntryPoint>,
+) {
+    let Ok(value) = toml::from_str::<toml::Value>(text) else {
+        return;
+    };
+    let dir = parent_dir(manifest);
+    let package = value
+        .get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(toml::Value::as_str);
+    let mut bins: Vec<(String, String)> = Vec::new();
+    for bin in value
+        .get("bin")
+        .and_then(toml::Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let Some(name) = bin.get("name").and_then(toml::Value::as_str) else {
+            continue;
+        };
+        let path = match bin.get("path").and_then(toml::Value::as_str) {
+            Some(path) => path.to_string(),
+            None if Some(name) == package => "src/main.rs".to_string(),
+            None => format!("src/bin/{name}.rs"),
+        };
+        bins.push((name.to_string(), normalize(&join(dir, &path))));
+    }
+    if let Some(package) = package {
+        let main = join(dir, "src/main.rs");
+        if tracked.contains(&main) {
+            bins.push((package.to_string(), main));
+        }
+    }
+    let bin_dir = format!("{}/", join(dir, "src/bin"));
+    let mut implicit: Vec<&String> = tracked
+        .iter()
+        .filter(|path| path.starts_with(&bin_dir))
+        .collect();
+    implicit.sort();
+    for path in implicit {
+        let rest = &path[bin_dir.len()..];
+        let name = match rest.split_once('/') {
+            Some((name, "main.rs")) => name,
+            Some(_) => continue,
+            None => match rest.strip_suffix(".rs") {
+                Some(name) => name,
+                None => continue,
+            },
+        };
+        bins.push((name.to_string(), path.clone()));
+    }
+
+    let mut seen = BTreeSet::new();
+    for (name, path) in bins {
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+        let mut item = entry(
+            "cargo_bin",
+            &name,
+            tracked.contains(&path).then_some(&*path),
+        );
+        item.declared_in = Some(manifest.to_string());
+        out.push(item);
+    }
+}
+
+/// A script's file is the first argument that names a tracked source file.
+fn npm_entrypoints(
+    manifest: &str,
+    text: &str,
+    tracked: &HashSet<String>,
+    out: &mut Vec<EntryPoint>,
+) {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(text) else {
+        return;
+    };
+    let dir = parent_dir(manifest);
+    let package = value
+        .get("name")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or(dir);
+    let resolve = |path: &str| {
+        let path = normalize(&join(dir, path));
+        tracked.contains(&path).then_some(path)
+    };
+    let mut push = |kind: &str, name: &str, file: Option<String>, command: Option<&str>| {
+        let mut item = entry(kind, name, file.as_deref());
+        item.declared_in = Some(manifest.to_string());
+        item.command = command.map(str::to_string);
+        out.push(item);
+    };
+
+    match value.get("bin") {
+        Some(serde_json::Value::String(path)) => push("npm_bin", package, resolve(path), None),
+        Some(serde_json::Value::Object(bins)) => {
+            for (name, path) in bins {
+                if let Some(path) = path.as_str() {
+                    push("npm_bin", name, resolve(path), None);
+                }
+            }
+        }
+        _ => {}
+    }
+    if let Some(main) = value.get("main").and_then(serde_json::Value::as_str) {
+        push("npm_main", package, resolve(main), None);
+    }
+    if let Some(scripts) = value.get("scripts").and_then(serde_json::Value::as_object) {
+        for (name, command) in scripts {
+            let Some(command) = command.as_str() else {
+                continue;
+            };
+            let file = command
+                .split_whitespace()
+                .map(|word| word.trim_matches(|ch| ch == '"' || ch == '\''))
+                .filter(|word| {
+                    word.rsplit_once('.')
+                        .is_some_and(|(_, ext)| SCRIPT_EXTENSIONS.contains(&ext))
+                })
+                .find_map(resolve);
+            push("npm_script", name, file, Some(command));
+        }
+    }
+}
+
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::{index_repository, IndexOptions};
+    use tempfile::TempDir;
+
+    #[test]
+    fn finds_manifest_targets_and_main_functions_with_their_calls() {
+        let dir = TempDir::new().unwrap();
+        let repo = dir.path();
+        let write = |path: &str, text: &str| {
+            let path = repo.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        };
+        write(
+            "Cargo.toml",
+            "[package]\nname = \"app\"\n\n[[bin]]\nname = \"tool\"\npath = \"tools/tool.rs\"\n",
+        );
+        write(
+            "src/main.rs",
+            "fn main() {\n    setup();\n    println!(\"hi\");\n    run(1);\n}\n",
+        );
+        write(
+            "src/lib.rs",
+            "pub fn setup() {}\npub fn run(n: u32) -> u32 { n }\n",
+        );
+        write("tools/tool.rs", "fn main() {\n    app::run(2);\n}\n");
+        write(
+            "web/package.json",
+            r#"{"name": "web", "main": "./server.js", "scripts": {"start": "node ./server.js --port 80", "lint": "eslint ."}}"#,
+        );
+        write("web/server.js", "function listen() {}\nlisten();\n");
+        write(
+            "cmd/svc/main.go",
+            "package main\n\nfunc main() {\n\tserve()\n}\n\nfunc serve() {}\n",
+        );
+
+        let mut store = GraphStore::open(&repo.join("graph.db")).unwrap();
+        index_repository(&mut store, repo, IndexOptions::default()).unwrap();
+        let entries = find_entrypoints(&store, repo, 10).unwrap();
+        let find = |kind: &str, name: &str| {
+            entries
+                .iter()
+                .find(|entry| entry.kind == kind && entry.name == name)
+                .unwrap_or_else(|| panic!("missing {kind} {name} in {entries:#?}"))
+        };
+
+        let app = find("cargo_bin", "app");
+        assert_eq!(app.file_path.as_deref(), Some("src/main.rs"));
+        assert_eq!(app.line, Some(1));
+        assert_eq!(app.declared_in.as_deref(), Some("Cargo.toml"));
+        assert_eq!(app.calls, ["setup", "run"]);
+        assert_eq!(
+            find("cargo_bin", "tool").file_path.as_deref(),
+            Some("tools/tool.rs")
+        );
+
+        let start = find("npm_script", "start");
+        assert_eq!(start.file_path.as_deref(), Some("web/server.js"));
+        assert_eq!(start.command.as_deref(), Some("node ./server.js --port 80"));
+        assert_eq!(start.calls, ["listen"]);
+        assert_eq!(find("npm_script", "lint").file_path, None);
+        assert_eq!(
+            find("npm_main", "web").file_path.as_deref(),
+            Some("web/server.js")
+        );
+
+        let go = find("main_function", "main");
+        assert_eq!(go.file_path.as_deref(), Some("cmd/svc/main.go"));
+        assert_eq!(go.calls, ["serve"]);
+        assert_eq!(
+            find("entry_file", "lib.rs").file_path.as_deref(),
+            Some("src/lib.rs")
+        );
+        assert!(
+            !entries.iter().any(|entry| entry.kind == "main_function"
+                && entry.file_path.as_deref() == Some("src/main.rs")),
+            "a main already listed as a cargo bin is not repeated"
+        );
+    }
+
+    #[test]
+    fn normalize_collapses_relative_components() {
+        assert_eq!(normalize("web/./server.js"), "web/server.js");
+        assert_eq!(normalize("web/../bin/cli.js"), "bin/cli.js");
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt;
+use std::io;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Machine-readable failure categories shared by the CLI `--json` output and
+/// MCP `structuredContent.error.code`, so agents can branch without parsing
+/// messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    InvalidParams,
+    IndexEmpty,
+    FileNotFound,
+    PathOutsideRepo,
+    EditConflict,
+    DbLocked,
+    Timeout,
+    /// `pick` missed every candidate of an ambiguous selector. An ambiguous
+    /// selector without `pick` is not an error: the first candidate is used
+    /// and the rest are listed in `resolution.candidates`.
+    SelectorAmbiguous,
+    /// A `.lumora.toml` guardrail refused a write tool call.
+    GuardrailBlocked,
+    Internal,
+}
+
+/// An error tagged with its [`ErrorCode`]. It travels inside `anyhow`
+/// chains, so context added further up does not lose the code.
+#[derive(Debug)]
+pub struct CodedError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CodedError {}
+
+pub fn coded(code: ErrorCode, message: impl Into<String>) -> anyhow::Error {
+    CodedError {
+        code,
+        message: message.into(),
+    }
+    .into()
+}
+
+/// Finds the code for an error: an explicit [`CodedError`] anywhere in the
+/// chain wins, then well-known SQLite and I/O failures. Anything else is
+/// `Internal`.
+pub fn error_code(err: &anyhow::Error) -> ErrorCode {
+    if let Some(coded) = err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<CodedError>())
+    {
+        return coded.code;
+    }
+    for cause in err.chain() {
+        if let Some(sqlite) = cause.downcast_ref::<rusqlite::Error>() {
+            match sqlite.sqlite_error_code() {
+                Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked) => {
+                    return ErrorCode::DbLocked
+                }
+                Some(rusqlite::ErrorCode::OperationInterrupted) => return ErrorCode::Timeout,
+                _ => {}
+            }
+        }
+        if let Some(io_err) = cause.downcast_ref::<io::Error>() {
+            if io_err.kind() == io::ErrorKind::NotFound {
+                return ErrorCode::FileNotFound;
+            }
+        }
+    }
+    ErrorCode::Internal
+}
+
+/// The `{ "code", "message" }` object used wherever errors are emitted as JSON.
+pub fn error_json(code: ErrorCode, message: &str) -> Value {
+    json!({ "code": code, "message": message })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn error_code_prefers_explicit_codes_then_classifies_known_failures() {
+        let explicit = Err::<(), _>(coded(ErrorCode::EditConflict, "old_text not found"))
+            .context("edit failed")
+            .unwrap_err();
+        assert_eq!(error_code(&explicit), ErrorCode::EditConflict);
+        assert_eq!(explicit.to_string(), "edit failed");
+
+        let missing = Err::<(), _>(io::Error::from(io::ErrorKind::NotFound))
+            .context("failed to read src/missing.rs")
+            .unwrap_err();
+        assert_eq!(error_code(&missing), ErrorCode::FileNotFound);
+
+        let locked = anyhow::Error::from(rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            None,
+        ));
+        assert_eq!(error_code(&locked), ErrorCode::DbLocked);
+
+        assert_eq!(
+            error_code(&anyhow::anyhow!("something else")),
+            ErrorCode::Internal
+        );
+        assert_eq!(
+            error_json(ErrorCode::IndexEmpty, "empty")["code"],
+            "INDEX_EMPTY"
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Append-only log of watcher re-indexes, one JSON event per line, under the
+/// state dir. `lumora serve` writes it; MCP sessions tail it and forward each
+/// event to their client as a `lumora/indexChanged` notification.
+pub const EVENT_LOG_FILE_NAME: &str = "events.jsonl";
+
+/// Once the log grows past this, the next append starts it over. Tailers
+/// notice the file shrank and read from the top.
+const MAX_EVENT_LOG_BYTES: u64 = 256 * 1024;
+
+/// Paths listed per event; a larger change set is cut off and flagged.
+pub const MAX_EVENT_PATHS: usize = 500;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexChangedEvent {
+    /// Milliseconds since the unix epoch.
+    pub at_ms: u64,
+    /// Repo-relative paths that were re-indexed, removed, or renamed.
+    pub paths: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+impl IndexChangedEvent {
+    pub fn new(mut paths: Vec<String>) -> Self {
+        let truncated = paths.len() > MAX_EVENT_PATHS;
+        paths.truncate(MAX_EVENT_PATHS);
+        let at_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        Self {
+            at_ms,
+            paths,
+            truncated,
+        }
+    }
+}
+
+pub fn event_log_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(EVENT_LOG_FILE_NAME)
+}
+
+/// Appends one event, starting the log over once it passes
+/// [`MAX_EVENT_LOG_BYTES`].
+pub fn append_event(state_dir: &Path, event: &IndexChangedEvent) -> Result<()> {
+    let path = event_log_path(state_dir);
+    let oversized = fs::metadata(&path)
+        .map(|meta| meta.len() > MAX_EVENT_LOG_BYTES)
+        .unwrap_or(false);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(!oversized)
+        .write(true)
+        .truncate(oversized)
+        .open(&path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    let mut line = serde_json::to_vec(event)?;
+    line.push(b'\n');
+    file.write_all(&line)
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Follows the event log from the point it was opened, like `tail -f`.
+#[derive(Debug)]
+pub struct EventTail {
+    path: PathBuf,
+    offset: u64,
+    partial: Vec<u8>,
+}
+
+impl EventTail {
+    /// Starts at the current end of the log, so only later events are read.
+    pub fn from_end(state_dir: &Path) -> Self {
+        let path = event_log_path(state_dir);
+        let offset = fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+        Self {
+            path,
+            offset,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Events appended since the last call. A line still being written is
+    /// held back until it is complete; unreadable lines are skipped.
+    pub fn poll(&mut self) -> Result<Vec<IndexChangedEvent>> {
+        let len = match fs::metadata(&self.path) {
+            Ok(meta) => meta.len(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        if len < self.offset {
+            self.offset = 0;
+            self.partial.clear();
+        }
+        if len == self.offset {
+            return Ok(Vec::new());
+        }
+
+        let mut file = fs::File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let read = file
+            .take(len - self.offset)
+            .read_to_end(&mut self.partial)?;
+        self.offset += read as u64;
+
+        let Some(complete) = self.partial.iter().rposition(|byte| *byte == b'\n') else {
+            return Ok(Vec::new());
+        };
+        let lines = self.partial.drain(..=complete).collect::<Vec<_>>();
+        Ok(lines
+            .split(|byte| *byte == b'\n')
+            .filter_map(|line| serde_json::from_slice(line).ok())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn tail_reads_only_new_events_and_survives_rotation() {
+        let dir = TempDir::new().unwrap();
+        append_event(dir.path(), &IndexChangedEvent::new(vec!["old.rs".into()])).unwrap();
+
+        let mut tail = EventTail::from_end(dir.path());
+        assert!(tail.poll().unwrap().is_empty());
+
+        append_event(dir.path(), &IndexChangedEvent::new(vec!["a.rs".into()])).unwrap();
+        append_event(dir.path(), &IndexChangedEvent::new(vec!["b.rs".into()])).unwrap();
+        let events = tail.poll().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].paths, ["b.rs"]);
+
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(event_log_path(dir.path()))
+            .unwrap();
+        log.write_all(b"{\"at_ms\":1,\"paths\":[\"c.").unwrap();
+        assert!(tail.poll().unwrap().is_empty(), "partial lines wait");
+        log.write_all(b"rs\"]}\n").unwrap();
+        assert_eq!(tail.poll().unwrap()[0].paths, ["c.rs"]);
+
+        fs::write(event_log_path(dir.path()), "").unwrap();
+        append_event(dir.path(), &IndexChangedEvent::new(vec!["d.rs".into()])).unwrap();
+        assert_eq!(tail.poll().unwrap()[0].paths, ["d.rs"]);
+    }
+
+    #[test]
+    fn large_change_sets_are_truncated() {
+        let paths = (0..MAX_EVENT_PATHS + 1)
+            .map(|index| format!("f{index}.rs"))
+            .collect();
+        let event = IndexChangedEvent::new(paths);
+        assert!(event.truncated);
+        assert_eq!(event.paths.len(), MAX_EVENT_PATHS);
+    }
+}
@@ -1,13 +1,18 @@
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context, Result};
 use regex::Regex;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use walkdir::{DirEntry, WalkDir};
 
+use crate::error::{coded, ErrorCode};
+use crate::ignore::{gitignore_globs, IgnoreGlobs};
+use crate::indexer::sha256_hex;
 use crate::parser::{detect_language, parse_file};
+use crate::paths::canonicalize;
 
 const IGNORE_DIRS: &[&str] = &[
     ".git",
@@ -23,6 +28,19 @@ const IGNORE_DIRS: &[&str] = &[
     ".lumora",
 ];
 
+/// Largest file `search_in_files` reads unless told otherwise.
+pub const DEFAULT_SEARCH_MAX_FILE_SIZE: u64 = 1024 * 1024;
+
+/// How much of a file is checked for NUL bytes before it is called binary.
+const BINARY_SNIFF_BYTES: usize = 8000;
+
+/// Most bytes `read_range` returns unless told otherwise.
+pub const DEFAULT_READ_RANGE_MAX_LEN: u64 = 64 * 1024;
+
+/// Largest changed region `compare_files` aligns line by line (rows times
+/// columns of the LCS table). Past it the region is reported as replaced.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct MultiReadRequest {
     pub path: String,
@@ -44,6 +62,80 @@ pub struct BatchEditRequest {
     pub replace_all: bool,
 }
 
+/// Match options for `edit_file_contents`. The default is a single, unique
+/// literal match.
+#[derive(Debug, Clone, Default)]
+pub struct EditOptions {
+    /// Replace every match instead of requiring exactly one.
+    pub replace_all: bool,
+    /// Fail unless the pattern matches exactly this many times; every match
+    /// is then replaced.
+    pub expected_occurrences: Option<u64>,
+    /// Treat `old_text` as a regex; `new_text` may use `$1` / `${name}`.
+    pub is_regex: bool,
+}
+
+/// How `write_file_contents` treats an existing file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Replace the file, creating it if needed.
+    #[default]
+    Overwrite,
+    /// Add to the end of the file, creating it if needed.
+    Append,
+    /// Create the file; fail if it already exists.
+    CreateNew,
+}
+
+impl WriteMode {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "overwrite" => Some(Self::Overwrite),
+            "append" => Some(Self::Append),
+            "create_new" => Some(Self::CreateNew),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Overwrite => "overwrite",
+            Self::Append => "append",
+            Self::CreateNew => "create_new",
+        }
+    }
+}
+
+/// Options for `write_file_contents`.
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// Create missing parent directories.
+    pub create_dirs: bool,
+    pub mode: WriteMode,
+    /// SHA-256 (hex) the file must still have, as reported by `read_file`
+    /// or a previous write; the write fails with `EDIT_CONFLICT` otherwise.
+    pub expected_hash: Option<String>,
+}
+
+/// File filters for `search_in_files`. The default skips files over 1 MiB
+/// and paths the repo's `.gitignore` excludes.
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// Skip files larger than this many bytes.
+    pub max_file_size: Option<u64>,
+    /// Also leave out paths matched by the root `.gitignore`.
+    pub respect_gitignore: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            max_file_size: Some(DEFAULT_SEARCH_MAX_FILE_SIZE),
+            respect_gitignore: true,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct PatchHunkRequest {
     pub start_line: u64,
@@ -58,15 +150,18 @@ pub struct FilePatchRequest {
 }
 
 pub fn safe_resolve_path(base: &Path, user_path: &str) -> Result<PathBuf> {
-    let base_canonical = fs::canonicalize(base)
+    let base_canonical = canonicalize(base)
         .with_context(|| format!("failed to canonicalize base path {}", base.display()))?;
     let joined = base.join(user_path);
 
     if joined.exists() {
-        let resolved = fs::canonicalize(&joined)
+        let resolved = canonicalize(&joined)
             .with_context(|| format!("failed to canonicalize path {}", joined.display()))?;
         if !resolved.starts_with(&base_canonical) {
-            return Err(anyhow!("path escapes repository root"));
+            return Err(coded(
+                ErrorCode::PathOutsideRepo,
+                "path escapes repository root",
+            ));
         }
         return Ok(resolved);
     }
@@ -75,13 +170,19 @@ pub fn safe_resolve_path(base: &Path, user_path: &str) -> Result<PathBuf> {
         .parent()
         .ok_or_else(|| anyhow!("invalid path: missing parent"))?;
     if !parent.exists() {
-        return Err(anyhow!("parent directory does not exist"));
+        return Err(coded(
+            ErrorCode::FileNotFound,
+            "parent directory does not exist",
+        ));
     }
 
-    let parent_canonical = fs::canonicalize(parent)
+    let parent_canonical = canonicalize(parent)
         .with_context(|| format!("failed to canonicalize parent {}", parent.display()))?;
     if !parent_canonical.starts_with(&base_canonical) {
-        return Err(anyhow!("path escapes repository root"));
+        return Err(coded(
+            ErrorCode::PathOutsideRepo,
+            "path escapes repository root",
+        ));
     }
 
     let file_name = joined
@@ -109,9 +210,97 @@ pub fn read_file_contents(
                 .map(str::to_string)
         });
 
-    build_read_response(
+    let mut response = build_read_response(
         repo_root, &resolved, &source, start_line, end_line, max_lines, language,
-    )
+    )?;
+    // Of the whole file, for `write_file`'s `expected_hash`.
+    response["sha256"] = json!(sha256_hex(source.as_bytes()));
+    Ok(response)
+}
+
+/// Reads `start_byte..end_byte` of a file, for spans taken from index
+/// metadata. Offsets that fall inside a UTF-8 character widen to cover it,
+/// and the range is capped at `max_len` bytes (then snapped back to a
+/// boundary), so the content is always valid text.
+pub fn read_file_range(
+    repo_root: &Path,
+    path: &str,
+    start_byte: u64,
+    end_byte: Option<u64>,
+    max_len: u64,
+) -> Result<Value> {
+    let resolved = safe_resolve_path(repo_root, path)?;
+    let source = fs::read_to_string(&resolved)
+        .with_context(|| format!("failed to read {}", resolved.display()))?;
+    let total = source.len();
+    let requested_end = end_byte.map_or(total, |end| end.min(total as u64) as usize);
+    if start_byte as usize > requested_end {
+        return Err(coded(
+            ErrorCode::InvalidParams,
+            format!(
+                "start_byte {start_byte} is past end_byte {requested_end} (file is {total} bytes)"
+            ),
+        ));
+    }
+
+    let mut start = start_byte as usize;
+    while !source.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = requested_end;
+    while !source.is_char_boundary(end) {
+        end += 1;
+    }
+    let capped = start.saturating_add(max_len as usize).min(end);
+    let truncated = capped < end;
+    let mut final_end = capped;
+    while !source.is_char_boundary(final_end) {
+        final_end -= 1;
+    }
+
+    let start_line = source[..start].matches('\n').count() + 1;
+    let line_start = source[..start].rfind('\n').map_or(0, |index| index + 1);
+    Ok(json!({
+        "path": to_rel_path(repo_root, &resolved)?,
+        "content": &source[start..final_end],
+        "start_byte": start,
+        "end_byte": final_end,
+        "total_bytes": total,
+        "start_line": start_line,
+        "start_col": start - line_start + 1,
+        "snapped": start != start_byte as usize || end != requested_end,
+        "truncated": truncated,
+        "sha256": sha256_hex(source.as_bytes())
+    }))
+}
+
+/// Also matches the identifier just before `col` when the cursor sits at its
+/// end.
+pub fn identifier_at(source: &str, line: usize, col: usize) -> Option<&str> {
+    let text = source.lines().nth(line.checked_sub(1)?)?;
+    let is_ident = |ch: char| ch.is_alphanumeric() || ch == '_' || ch == '$';
+    let mut at = col.checked_sub(1)?.min(text.len());
+    while !text.is_char_boundary(at) {
+        at -= 1;
+    }
+    if !text[at..].starts_with(is_ident) {
+        at = text[..at].char_indices().next_back()?.0;
+        if !text[at..].starts_with(is_ident) {
+            return None;
+        }
+    }
+    let start = text[..at]
+        .char_indices()
+        .rev()
+        .take_while(|(_, ch)| is_ident(*ch))
+        .last()
+        .map_or(at, |(index, _)| index);
+    let end = text[at..]
+        .char_indices()
+        .find(|(_, ch)| !is_ident(*ch))
+        .map_or(text.len(), |(index, _)| at + index);
+    let identifier = &text[start..end];
+    (!identifier.starts_with(|ch: char| ch.is_ascii_digit())).then_some(identifier)
 }
 
 pub fn file_outline(repo_root: &Path, path: &str, max_depth: Option<usize>) -> Result<Value> {
@@ -140,13 +329,17 @@ pub fn file_outline(repo_root: &Path, path: &str, max_depth: Option<usize>) -> R
             }
         })
         .map(|definition| {
-            json!({
+            let mut entry = json!({
                 "name": definition.name,
                 "kind": definition.kind,
                 "qualname": definition.qualname,
                 "line": definition.line,
                 "end_line": definition.end_line
-            })
+            });
+            if let Some(signature) = definition.signature {
+                entry["signature"] = json!(signature);
+            }
+            entry
         })
         .collect();
 
@@ -191,6 +384,7 @@ pub fn search_in_files(
     context_lines: u64,
     max_results: u64,
     is_regex: bool,
+    options: &SearchOptions,
 ) -> Result<Value> {
     let regex = if is_regex {
         Regex::new(pattern).with_context(|| format!("invalid regex pattern `{pattern}`"))?
@@ -198,15 +392,23 @@ pub fn search_in_files(
         Regex::new(&regex::escape(pattern)).expect("escaped literal regex should compile")
     };
     let file_glob_regex = file_glob.map(glob_to_regex).transpose()?;
+    let extra_globs = if options.respect_gitignore {
+        gitignore_globs(repo_root)?
+    } else {
+        Vec::new()
+    };
+    let ignore = IgnoreGlobs::with_env(&extra_globs)?;
 
     let mut matches = Vec::new();
     let mut truncated = false;
+    let mut files_scanned = 0_u64;
+    let mut skipped = SkippedFiles::default();
 
     let walker = WalkDir::new(repo_root).into_iter().filter_entry(|entry| {
         if entry.depth() == 0 {
             return true;
         }
-        should_descend(entry)
+        should_descend(entry) && !is_user_ignored(repo_root, entry, &ignore)
     });
 
     for entry in walker {
@@ -222,17 +424,48 @@ pub fn search_in_files(
             }
         }
 
-        let content = match fs::read_to_string(entry.path()) {
-            Ok(value) => value,
-            Err(_) => continue,
+        if let Some(max_file_size) = options.max_file_size {
+            if entry
+                .metadata()
+                .is_ok_and(|meta| meta.len() > max_file_size)
+            {
+                skipped.too_large += 1;
+                continue;
+            }
+        }
+        let Ok(bytes) = fs::read(entry.path()) else {
+            skipped.unreadable += 1;
+            continue;
+        };
+        if looks_binary(&bytes) {
+            skipped.binary += 1;
+            continue;
+        }
+        let Ok(content) = String::from_utf8(bytes) else {
+            skipped.not_utf8 += 1;
+            continue;
         };
-        let lines: Vec<&str> = content.lines().collect();
+        files_scanned += 1;
+
+        let mut line_offsets = Vec::new();
+        let mut lines = Vec::new();
+        let mut offset = 0;
+        for raw in content.split_inclusive('\n') {
+            line_offsets.push(offset);
+            offset += raw.len();
+            let line = raw.strip_suffix('\n').unwrap_or(raw);
+            lines.push(line.strip_suffix('\r').unwrap_or(line));
+        }
         let context = context_lines as usize;
 
         for (idx, line) in lines.iter().enumerate() {
-            if !regex.is_match(line) {
+            let spans = regex
+                .find_iter(line)
+                .map(|found| [found.start(), found.end()])
+                .collect::<Vec<_>>();
+            let Some(first) = spans.first() else {
                 continue;
-            }
+            };
 
             let before_start = idx.saturating_sub(context);
             let after_end = (idx + context + 1).min(lines.len());
@@ -248,6 +481,8 @@ pub fn search_in_files(
             matches.push(json!({
                 "file": rel_path,
                 "line": idx + 1,
+                "byte_offset": line_offsets[idx] + first[0],
+                "match_spans": spans,
                 "content": line,
                 "context_before": context_before,
                 "context_after": context_after
@@ -268,10 +503,34 @@ pub fn search_in_files(
     Ok(json!({
         "matches": matches,
         "total_matches": total_matches,
-        "truncated": truncated
+        "truncated": truncated,
+        "files_scanned": files_scanned,
+        "files_skipped": skipped.total(),
+        "skipped": skipped
     }))
 }
 
+/// Files `search_in_files` passed over, by reason.
+#[derive(Debug, Default, Serialize)]
+struct SkippedFiles {
+    binary: u64,
+    too_large: u64,
+    not_utf8: u64,
+    unreadable: u64,
+}
+
+impl SkippedFiles {
+    fn total(&self) -> u64 {
+        self.binary + self.too_large + self.not_utf8 + self.unreadable
+    }
+}
+
+/// Treats a NUL byte in the first [`BINARY_SNIFF_BYTES`] as binary, the same
+/// heuristic git uses.
+fn looks_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_SNIFF_BYTES)].contains(&0)
+}
+
 pub fn list_dir(
     repo_root: &Path,
     path: &str,
@@ -285,6 +544,7 @@ pub fn list_dir(
     }
 
     let file_glob_regex = file_glob.map(glob_to_regex).transpose()?;
+    let ignore = IgnoreGlobs::with_env(&[])?;
     let mut entries = Vec::new();
 
     if recursive {
@@ -297,7 +557,7 @@ pub fn list_dir(
                 if entry.path() == resolved {
                     return true;
                 }
-                should_descend(entry)
+                should_descend(entry) && !is_user_ignored(repo_root, entry, &ignore)
             });
 
         for entry in walker {
@@ -319,6 +579,9 @@ pub fn list_dir(
 
             let path = entry.path();
             let rel_path = to_rel_path(repo_root, &path)?;
+            if ignore.is_ignored(&rel_path, file_type.is_dir()) {
+                continue;
+            }
             if let Some(glob_regex) = file_glob_regex.as_ref() {
                 if file_type.is_file() && !glob_regex.is_match(&rel_path) {
                     continue;
@@ -356,12 +619,15 @@ pub fn list_dir(
     }))
 }
 
+/// Writes `content` to `path` as `options.mode` says. The response carries
+/// the file's new `sha256`, to pass as `expected_hash` on the next write.
 pub fn write_file_contents(
     repo_root: &Path,
     path: &str,
     content: &str,
-    create_dirs: bool,
+    options: &WriteOptions,
 ) -> Result<Value> {
+    let create_dirs = options.create_dirs;
     let resolved = match safe_resolve_path(repo_root, path) {
         Ok(path) => path,
         Err(err) if create_dirs => {
@@ -381,12 +647,59 @@ pub fn write_file_contents(
         }
     }
 
-    fs::write(&resolved, content)
+    if let Some(expected) = options.expected_hash.as_deref() {
+        let current = match fs::read(&resolved) {
+            Ok(bytes) => sha256_hex(&bytes),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(coded(
+                    ErrorCode::FileNotFound,
+                    "file does not exist, so it cannot match `expected_hash`",
+                ));
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", resolved.display()))
+            }
+        };
+        if !current.eq_ignore_ascii_case(expected.trim()) {
+            return Err(coded(
+                ErrorCode::EditConflict,
+                format!(
+                    "file changed since it was read: expected hash {expected}, found {current}"
+                ),
+            ));
+        }
+    }
+
+    let mut open = fs::OpenOptions::new();
+    match options.mode {
+        WriteMode::Overwrite => open.write(true).create(true).truncate(true),
+        WriteMode::Append => open.append(true).create(true),
+        WriteMode::CreateNew => open.write(true).create_new(true),
+    };
+    let mut file = match open.open(&resolved) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err(coded(
+                ErrorCode::EditConflict,
+                "file already exists; `create_new` only creates files",
+            ));
+        }
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to write {}", resolved.display()))
+        }
+    };
+    file.write_all(content.as_bytes())
         .with_context(|| format!("failed to write {}", resolved.display()))?;
+    drop(file);
 
+    let written = fs::read(&resolved)
+        .with_context(|| format!("failed to read back {}", resolved.display()))?;
     Ok(json!({
         "path": to_rel_path(repo_root, &resolved)?,
-        "bytes_written": content.len()
+        "mode": options.mode.as_str(),
+        "bytes_written": content.len(),
+        "size_bytes": written.len(),
+        "sha256": sha256_hex(&written)
     }))
 }
 
@@ -410,7 +723,7 @@ pub fn move_file_op(repo_root: &Path, source: &str, destination: &str) -> Result
 pub fn delete_file_op(repo_root: &Path, path: &str) -> Result<Value> {
     let resolved = safe_resolve_path(repo_root, path)?;
     if !resolved.exists() {
-        return Err(anyhow!("file does not exist"));
+        return Err(coded(ErrorCode::FileNotFound, "file does not exist"));
     }
     if !resolved.is_file() {
         return Err(anyhow!("path is not a file"));
@@ -430,12 +743,13 @@ pub fn edit_file_contents(
     path: &str,
     old_text: &str,
     new_text: &str,
+    options: &EditOptions,
     dry_run: bool,
 ) -> Result<Value> {
     let resolved = safe_resolve_path(repo_root, path)?;
     let original = fs::read_to_string(&resolved)
         .with_context(|| format!("failed to read {}", resolved.display()))?;
-    let applied_edit = apply_text_edit(&original, old_text, new_text, false)?;
+    let applied_edit = apply_text_edit(&original, old_text, new_text, options)?;
     let updated = applied_edit.updated;
     if !dry_run {
         fs::write(&resolved, updated.as_bytes())
@@ -485,7 +799,10 @@ pub fn batch_edit_file_contents(
             &pending.current,
             &edit.old_text,
             &edit.new_text,
-            edit.replace_all,
+            &EditOptions {
+                replace_all: edit.replace_all,
+                ..Default::default()
+            },
         )?;
         pending.current = applied_edit.updated;
         total_replacements_applied += applied_edit.replacements_applied;
@@ -685,6 +1002,205 @@ pub fn multi_read(
     }))
 }
 
+/// Line diff of two repo files as unified-diff hunks with `context_lines`
+/// of context. Hunks stop once they hold `max_lines` lines, with `truncated`
+/// set; the added/removed counts always cover the whole diff.
+pub fn compare_files(
+    repo_root: &Path,
+    left: &str,
+    right: &str,
+    context_lines: usize,
+    max_lines: usize,
+) -> Result<Value> {
+    let mut sides = Vec::with_capacity(2);
+    for path in [left, right] {
+        let resolved = safe_resolve_path(repo_root, path)?;
+        let source = fs::read_to_string(&resolved)
+            .with_context(|| format!("failed to read {}", resolved.display()))?;
+        sides.push((to_rel_path(repo_root, &resolved)?, source));
+    }
+    let left_lines: Vec<&str> = sides[0].1.lines().collect();
+    let right_lines: Vec<&str> = sides[1].1.lines().collect();
+    let ops = diff_lines(&left_lines, &right_lines);
+
+    let count = |wanted: DiffOp| ops.iter().filter(|op| **op == wanted).count();
+    let (unchanged, removed, added) = (
+        count(DiffOp::Equal),
+        count(DiffOp::Delete),
+        count(DiffOp::Insert),
+    );
+    let total = left_lines.len() + right_lines.len();
+    let line_similarity = if total == 0 {
+        1.0
+    } else {
+        (2 * unchanged) as f64 / total as f64
+    };
+
+    let hunks = diff_hunks(&ops, &left_lines, &right_lines, context_lines);
+    let hunk_count = hunks.len();
+    let mut budget = max_lines;
+    let mut truncated = false;
+    let mut out = Vec::new();
+    for (header, mut lines) in hunks {
+        if lines.len() > budget {
+            truncated = true;
+            if budget == 0 {
+                break;
+            }
+            lines.truncate(budget);
+        }
+        budget -= lines.len();
+        out.push(json!({ "header": header, "lines": lines }));
+        if truncated {
+            break;
+        }
+    }
+
+    Ok(json!({
+        "left": { "path": sides[0].0, "total_lines": left_lines.len() },
+        "right": { "path": sides[1].0, "total_lines": right_lines.len() },
+        "identical": removed == 0 && added == 0,
+        "unchanged": unchanged,
+        "removed": removed,
+        "added": added,
+        "line_similarity": line_similarity,
+        "hunk_count": hunk_count,
+        "hunks": out,
+        "truncated": truncated
+    }))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffOp {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Edit script turning `left` into `right`, from a longest common
+/// subsequence of lines. The common prefix and suffix are peeled off first;
+/// a middle too large for [`MAX_DIFF_CELLS`] is treated as replaced.
+fn diff_lines(left: &[&str], right: &[&str]) -> Vec<DiffOp> {
+    let prefix = left
+        .iter()
+        .zip(right)
+        .take_while(|(left, right)| left == right)
+        .count();
+    let suffix = left[prefix..]
+        .iter()
+        .rev()
+        .zip(right[prefix..].iter().rev())
+        .take_while(|(left, right)| left == right)
+        .count();
+    let old = &left[prefix..left.len() - suffix];
+    let new = &right[prefix..right.len() - suffix];
+
+    let mut ops = vec![DiffOp::Equal; prefix];
+    if old.len().saturating_mul(new.len()) > MAX_DIFF_CELLS {
+        ops.extend(std::iter::repeat(DiffOp::Delete).take(old.len()));
+        ops.extend(std::iter::repeat(DiffOp::Insert).take(new.len()));
+    } else {
+        // lcs[i * width + j]: LCS length of old[i..] and new[j..].
+        let width = new.len() + 1;
+        let mut lcs = vec![0_u32; (old.len() + 1) * width];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lcs[i * width + j] = if old[i] == new[j] {
+                    lcs[(i + 1) * width + j + 1] + 1
+                } else {
+                    lcs[(i + 1) * width + j].max(lcs[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                ops.push(DiffOp::Equal);
+                i += 1;
+                j += 1;
+            } else if j == new.len()
+                || (i < old.len() && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+            {
+                ops.push(DiffOp::Delete);
+                i += 1;
+            } else {
+                ops.push(DiffOp::Insert);
+                j += 1;
+            }
+        }
+    }
+    ops.extend(std::iter::repeat(DiffOp::Equal).take(suffix));
+    ops
+}
+
+/// Groups an edit script into unified-diff hunks: an `@@ -a,b +c,d @@`
+/// header and lines prefixed with ` `, `-`, or `+`. Changes separated by
+/// at most twice `context` unchanged lines share a hunk.
+fn diff_hunks(
+    ops: &[DiffOp],
+    left: &[&str],
+    right: &[&str],
+    context: usize,
+) -> Vec<(String, Vec<String>)> {
+    // Lines of each side consumed before op `k`.
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut l, mut r) = (0, 0);
+    for op in ops {
+        positions.push((l, r));
+        match op {
+            DiffOp::Equal => {
+                l += 1;
+                r += 1;
+            }
+            DiffOp::Delete => l += 1,
+            DiffOp::Insert => r += 1,
+        }
+    }
+    positions.push((l, r));
+
+    let changes: Vec<usize> = (0..ops.len())
+        .filter(|&k| ops[k] != DiffOp::Equal)
+        .collect();
+    let mut groups: Vec<(usize, usize)> = Vec::new();
+    for &k in &changes {
+        match groups.last_mut() {
+            Some((_, last)) if k - *last <= 2 * context + 1 => *last = k,
+            _ => groups.push((k, k)),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(first, last)| {
+            let from = first.saturating_sub(context);
+            let to = (last + 1 + context).min(ops.len());
+            let (left_from, right_from) = positions[from];
+            let (left_to, right_to) = positions[to];
+            let range = |from: usize, to: usize| {
+                let len = to - from;
+                let start = if len == 0 { from } else { from + 1 };
+                format!("{start},{len}")
+            };
+            let header = format!(
+                "@@ -{} +{} @@",
+                range(left_from, left_to),
+                range(right_from, right_to)
+            );
+            let lines = (from..to)
+                .map(|k| {
+                    let (l, r) = positions[k];
+                    match ops[k] {
+                        DiffOp::Equal => format!(" {}", left[l]),
+                        DiffOp::Delete => format!("-{}", left[l]),
+                        DiffOp::Insert => format!("+{}", right[r]),
+                    }
+                })
+                .collect();
+            (header, lines)
+        })
+        .collect()
+}
+
 fn compute_range(
     total_lines: u64,
     start_line: Option<u64>,
@@ -822,7 +1338,12 @@ fn should_descend(entry: &DirEntry) -> bool {
     !IGNORE_DIRS.contains(&name.as_str())
 }
 
-fn glob_to_regex(glob: &str) -> Result<Regex> {
+fn is_user_ignored(repo_root: &Path, entry: &DirEntry, ignore: &IgnoreGlobs) -> bool {
+    to_rel_path(repo_root, entry.path())
+        .is_ok_and(|rel_path| ignore.is_ignored(&rel_path, entry.file_type().is_dir()))
+}
+
+pub fn glob_to_regex(glob: &str) -> Result<Regex> {
     let mut pattern = String::from("^");
     for ch in glob.replace('\\', "/").chars() {
         match ch {
@@ -879,29 +1400,31 @@ fn prepare_parent_dirs(repo_root: &Path, user_path: &str) -> Result<()> {
         .parent()
         .ok_or_else(|| anyhow!("invalid path: missing parent"))?;
 
-    let base_canonical = fs::canonicalize(repo_root)?;
+    let base_canonical = canonicalize(repo_root)?;
     let mut cursor = parent.to_path_buf();
     while !cursor.exists() {
         let Some(next) = cursor.parent() else {
-            return Err(anyhow!("parent directory does not exist"));
+            return Err(coded(
+                ErrorCode::FileNotFound,
+                "parent directory does not exist",
+            ));
         };
         cursor = next.to_path_buf();
     }
 
-    let existing_canonical = fs::canonicalize(&cursor)?;
+    let existing_canonical = canonicalize(&cursor)?;
     if !existing_canonical.starts_with(&base_canonical) {
-        return Err(anyhow!("path escapes repository root"));
+        return Err(coded(
+            ErrorCode::PathOutsideRepo,
+            "path escapes repository root",
+        ));
     }
 
     fs::create_dir_all(parent)
         .with_context(|| format!("failed to create directory {}", parent.display()))
 }
 
-fn build_diff_preview(original: &str, updated: &str, old_text: &str) -> String {
-    let Some(idx) = original.find(old_text) else {
-        return String::new();
-    };
-
+fn build_diff_preview(original: &str, updated: &str, idx: usize) -> String {
     let line_num = original[..idx].bytes().filter(|b| *b == b'\n').count() + 1;
     let before_start = line_num.saturating_sub(2).max(1);
     let after_end = line_num + 2;
@@ -936,35 +1459,74 @@ fn apply_text_edit(
     original: &str,
     old_text: &str,
     new_text: &str,
-    replace_all: bool,
+    options: &EditOptions,
 ) -> Result<AppliedTextEdit> {
     if old_text.is_empty() {
-        return Err(anyhow!("old_text must not be empty"));
+        return Err(coded(
+            ErrorCode::InvalidParams,
+            "old_text must not be empty",
+        ));
     }
 
-    let occurrences_found = original.matches(old_text).count() as u64;
-    if occurrences_found == 0 {
-        return Err(anyhow!("old_text not found in file"));
+    let pattern = if options.is_regex {
+        Regex::new(old_text).map_err(|err| {
+            coded(
+                ErrorCode::InvalidParams,
+                format!("invalid old_text regex: {err}"),
+            )
+        })?
+    } else {
+        Regex::new(&regex::escape(old_text))?
+    };
+    let matches: Vec<_> = pattern.find_iter(original).collect();
+    if matches.iter().any(|found| found.is_empty()) {
+        return Err(coded(
+            ErrorCode::InvalidParams,
+            "old_text regex must not match empty text",
+        ));
     }
-    if !replace_all && occurrences_found > 1 {
-        return Err(anyhow!(
-            "old_text matches {} times; must match exactly once",
-            occurrences_found
+
+    let occurrences_found = matches.len() as u64;
+    if occurrences_found == 0 {
+        return Err(coded(ErrorCode::EditConflict, "old_text not found in file"));
+    }
+    if let Some(expected) = options.expected_occurrences {
+        if occurrences_found != expected {
+            return Err(coded(
+                ErrorCode::EditConflict,
+                format!(
+                    "old_text matches {} times; expected {}",
+                    occurrences_found, expected
+                ),
+            ));
+        }
+    } else if !options.replace_all && occurrences_found > 1 {
+        return Err(coded(
+            ErrorCode::EditConflict,
+            format!(
+                "old_text matches {} times; must match exactly once",
+                occurrences_found
+            ),
         ));
     }
 
-    let replacements_applied = if replace_all { occurrences_found } else { 1 };
-    let updated = if replace_all {
-        original.replace(old_text, new_text)
+    let replace_every = options.replace_all || options.expected_occurrences.is_some();
+    let replacements_applied = if replace_every { occurrences_found } else { 1 };
+    let limit = if replace_every { 0 } else { 1 };
+    // Literal mode must not expand `$` in the replacement.
+    let updated = if options.is_regex {
+        pattern.replacen(original, limit, new_text).into_owned()
     } else {
-        original.replacen(old_text, new_text, 1)
+        pattern
+            .replacen(original, limit, regex::NoExpand(new_text))
+            .into_owned()
     };
 
     Ok(AppliedTextEdit {
-        updated: updated.clone(),
+        diff_preview: build_diff_preview(original, &updated, matches[0].start()),
+        updated,
         occurrences_found,
         replacements_applied,
-        diff_preview: build_diff_preview(original, &updated, old_text),
     })
 }
 
@@ -1000,11 +1562,14 @@ fn apply_patch_hunk(
 
         let actual = &buffer.lines[adjusted_start..adjusted_end];
         if actual != hunk.old_lines.as_slice() {
-            return Err(anyhow!(
-                "patch hunk at line {} did not match file contents\nexpected:\n{}\nfound:\n{}",
-                hunk.start_line,
-                format_patch_lines(&hunk.old_lines),
-                format_patch_lines(actual)
+            return Err(coded(
+                ErrorCode::EditConflict,
+                format!(
+                    "patch hunk at line {} did not match file contents\nexpected:\n{}\nfound:\n{}",
+                    hunk.start_line,
+                    format_patch_lines(&hunk.old_lines),
+                    format_patch_lines(actual)
+                ),
             ));
         }
     }
@@ -1045,8 +1610,16 @@ fn format_patch_lines(lines: &[impl AsRef<str>]) -> String {
     }
 }
 
-fn to_rel_path(repo_root: &Path, path: &Path) -> Result<String> {
-    let rel = path.strip_prefix(repo_root).with_context(|| {
+pub fn to_rel_path(repo_root: &Path, path: &Path) -> Result<String> {
+    // Resolved paths are canonical; a caller-supplied root may not be (a
+    // symlinked checkout, a mapped drive pointing at a UNC share).
+    let canonical_root = if path.starts_with(repo_root) {
+        None
+    } else {
+        canonicalize(repo_root).ok()
+    };
+    let root = canonical_root.as_deref().unwrap_or(repo_root);
+    let rel = path.strip_prefix(root).with_context(|| {
         format!(
             "failed to make path relative to repo root: {}",
             path.display()
@@ -1135,6 +1708,7 @@ struct AppliedPatchHunk {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::error_code;
     use tempfile::TempDir;
 
     fn setup_repo() -> TempDir {
@@ -1167,6 +1741,20 @@ mod tests {
         assert!(resolved.ends_with("src/new.rs"));
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_read_file_contents_through_symlinked_root() {
+        let dir = setup_repo();
+        fs::write(dir.path().join("src/lib.rs"), "a\n").expect("file should be written");
+        let link = TempDir::new().expect("temp dir should be created");
+        let root = link.path().join("checkout");
+        std::os::unix::fs::symlink(dir.path(), &root).expect("symlink should be created");
+
+        let value =
+            read_file_contents(&root, "src/lib.rs", None, None, 500).expect("read should succeed");
+        assert_eq!(value["path"], "src/lib.rs");
+    }
+
     #[test]
     fn test_read_file_contents_basic() {
         let dir = setup_repo();
@@ -1198,6 +1786,30 @@ mod tests {
         assert_eq!(value["end_line"], 2);
     }
 
+    #[test]
+    fn test_read_file_range_snaps_to_char_boundaries() {
+        let dir = setup_repo();
+        fs::write(dir.path().join("src/app.min.js"), "let a=\"é\";let b=1;")
+            .expect("file should be written");
+        let value = read_file_range(dir.path(), "src/app.min.js", 8, Some(9), 1024)
+            .expect("read should succeed");
+        assert_eq!(value["content"], "é");
+        assert_eq!(value["start_byte"], 7);
+        assert_eq!(value["end_byte"], 9);
+        assert_eq!(value["snapped"], true);
+
+        let value = read_file_range(dir.path(), "src/app.min.js", 11, None, 4)
+            .expect("read should succeed");
+        assert_eq!(value["content"], "let ");
+        assert_eq!(value["start_col"], 12);
+        assert_eq!(value["truncated"], true);
+        assert_eq!(value["total_bytes"], 19);
+
+        let err = read_file_range(dir.path(), "src/app.min.js", 30, None, 1024)
+            .expect_err("start past the end should fail");
+        assert_eq!(crate::error::error_code(&err), ErrorCode::InvalidParams);
+    }
+
     #[test]
     fn test_file_outline_rust_file() {
         let dir = setup_repo();
@@ -1231,8 +1843,16 @@ mod tests {
     fn test_search_in_files_literal() {
         let dir = setup_repo();
         fs::write(dir.path().join("src/a.rs"), "hello world\n").expect("file should be written");
-        let value = search_in_files(dir.path(), "world", Some("*.rs"), 1, 10, false)
-            .expect("search should succeed");
+        let value = search_in_files(
+            dir.path(),
+            "world",
+            Some("*.rs"),
+            1,
+            10,
+            false,
+            &SearchOptions::default(),
+        )
+        .expect("search should succeed");
         assert_eq!(value["total_matches"], 1);
     }
 
@@ -1240,8 +1860,16 @@ mod tests {
     fn test_search_in_files_regex() {
         let dir = setup_repo();
         fs::write(dir.path().join("src/a.rs"), "foo123\n").expect("file should be written");
-        let value = search_in_files(dir.path(), "foo\\d+", Some("*.rs"), 1, 10, true)
-            .expect("search should succeed");
+        let value = search_in_files(
+            dir.path(),
+            "foo\\d+",
+            Some("*.rs"),
+            1,
+            10,
+            true,
+            &SearchOptions::default(),
+        )
+        .expect("search should succeed");
         assert_eq!(value["total_matches"], 1);
     }
 
@@ -1249,11 +1877,53 @@ mod tests {
     fn test_search_in_files_no_matches() {
         let dir = setup_repo();
         fs::write(dir.path().join("src/a.rs"), "abc\n").expect("file should be written");
-        let value =
-            search_in_files(dir.path(), "zzz", None, 1, 10, false).expect("search should succeed");
+        let value = search_in_files(
+            dir.path(),
+            "zzz",
+            None,
+            1,
+            10,
+            false,
+            &SearchOptions::default(),
+        )
+        .expect("search should succeed");
         assert_eq!(value["total_matches"], 0);
     }
 
+    #[test]
+    fn test_search_in_files_skips_binary_large_and_gitignored_files() {
+        let dir = setup_repo();
+        fs::write(dir.path().join(".gitignore"), "# build output\nout/\n").unwrap();
+        fs::write(dir.path().join("src/a.rs"), "let x = 1;\nlet needle = 2;\n").unwrap();
+        fs::write(dir.path().join("src/blob.bin"), b"needle\0\x01").unwrap();
+        fs::write(dir.path().join("src/big.txt"), "needle ".repeat(200)).unwrap();
+        fs::create_dir_all(dir.path().join("out")).unwrap();
+        fs::write(dir.path().join("out/gen.rs"), "needle\n").unwrap();
+
+        let options = SearchOptions {
+            max_file_size: Some(1000),
+            ..Default::default()
+        };
+        let value = search_in_files(dir.path(), "needle", None, 0, 10, false, &options)
+            .expect("search should succeed");
+        assert_eq!(value["total_matches"], 1);
+        assert_eq!(value["matches"][0]["line"], 2);
+        assert_eq!(value["matches"][0]["byte_offset"], 15);
+        assert_eq!(value["matches"][0]["match_spans"], json!([[4, 10]]));
+        assert_eq!(value["skipped"]["binary"], 1);
+        assert_eq!(value["skipped"]["too_large"], 1);
+        assert_eq!(value["files_skipped"], 2);
+
+        let options = SearchOptions {
+            max_file_size: None,
+            respect_gitignore: false,
+        };
+        let value = search_in_files(dir.path(), "needle", None, 0, 10, false, &options)
+            .expect("search should succeed");
+        assert_eq!(value["total_matches"], 3);
+        assert_eq!(value["files_skipped"], 1);
+    }
+
     #[test]
     fn test_list_dir_non_recursive() {
         let dir = setup_repo();
@@ -1285,8 +1955,13 @@ mod tests {
     #[test]
     fn test_write_file_contents_create_new() {
         let dir = setup_repo();
-        let value = write_file_contents(dir.path(), "src/new.rs", "fn a() {}", false)
-            .expect("write should succeed");
+        let value = write_file_contents(
+            dir.path(),
+            "src/new.rs",
+            "fn a() {}",
+            &WriteOptions::default(),
+        )
+        .expect("write should succeed");
         assert_eq!(value["bytes_written"], 9);
         assert!(dir.path().join("src/new.rs").exists());
     }
@@ -1294,18 +1969,87 @@ mod tests {
     #[test]
     fn test_write_file_contents_create_with_dirs() {
         let dir = setup_repo();
-        let value = write_file_contents(dir.path(), "nested/deep/file.txt", "ok", true)
-            .expect("write with dirs should succeed");
+        let value = write_file_contents(
+            dir.path(),
+            "nested/deep/file.txt",
+            "ok",
+            &WriteOptions {
+                create_dirs: true,
+                ..Default::default()
+            },
+        )
+        .expect("write with dirs should succeed");
         assert_eq!(value["bytes_written"], 2);
         assert!(dir.path().join("nested/deep/file.txt").exists());
     }
 
+    #[test]
+    fn test_write_file_contents_modes_and_expected_hash() {
+        let dir = setup_repo();
+        let write = |content: &str, mode: WriteMode, expected_hash: Option<&str>| {
+            write_file_contents(
+                dir.path(),
+                "log.txt",
+                content,
+                &WriteOptions {
+                    mode,
+                    expected_hash: expected_hash.map(str::to_string),
+                    ..Default::default()
+                },
+            )
+        };
+
+        let created = write("one\n", WriteMode::CreateNew, None).unwrap();
+        assert_eq!(created["sha256"], sha256_hex(b"one\n"));
+        let err = write("again\n", WriteMode::CreateNew, None).unwrap_err();
+        assert_eq!(error_code(&err), ErrorCode::EditConflict);
+
+        let hash = created["sha256"].as_str().unwrap().to_string();
+        let appended = write("two\n", WriteMode::Append, Some(&hash)).unwrap();
+        assert_eq!(appended["bytes_written"], 4);
+        assert_eq!(appended["size_bytes"], 8);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("log.txt")).unwrap(),
+            "one\ntwo\n"
+        );
+
+        // The first hash is stale now.
+        let err = write("three\n", WriteMode::Overwrite, Some(&hash)).unwrap_err();
+        assert_eq!(error_code(&err), ErrorCode::EditConflict);
+        let read = read_file_contents(dir.path(), "log.txt", None, None, 10).unwrap();
+        assert_eq!(read["sha256"], appended["sha256"]);
+        write("three\n", WriteMode::Overwrite, read["sha256"].as_str()).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("log.txt")).unwrap(),
+            "three\n"
+        );
+
+        let err = write_file_contents(
+            dir.path(),
+            "missing.txt",
+            "x",
+            &WriteOptions {
+                expected_hash: Some(hash),
+                ..Default::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(error_code(&err), ErrorCode::FileNotFound);
+    }
+
     #[test]
     fn test_edit_file_contents_successful_edit() {
         let dir = setup_repo();
         fs::write(dir.path().join("src/edit.rs"), "let a = 1;\n").expect("file should be written");
-        let value = edit_file_contents(dir.path(), "src/edit.rs", "1", "2", false)
-            .expect("edit should succeed");
+        let value = edit_file_contents(
+            dir.path(),
+            "src/edit.rs",
+            "1",
+            "2",
+            &EditOptions::default(),
+            false,
+        )
+        .expect("edit should succeed");
         assert_eq!(value["applied"], true);
         assert_eq!(value["replacements_applied"], 1);
         assert_eq!(
@@ -1318,7 +2062,14 @@ mod tests {
     fn test_edit_file_contents_zero_matches_error() {
         let dir = setup_repo();
         fs::write(dir.path().join("src/edit.rs"), "abc\n").expect("file should be written");
-        let result = edit_file_contents(dir.path(), "src/edit.rs", "missing", "x", false);
+        let result = edit_file_contents(
+            dir.path(),
+            "src/edit.rs",
+            "missing",
+            "x",
+            &EditOptions::default(),
+            false,
+        );
         assert!(result.is_err(), "zero matches should fail");
     }
 
@@ -1326,7 +2077,14 @@ mod tests {
     fn test_edit_file_contents_multiple_matches_error() {
         let dir = setup_repo();
         fs::write(dir.path().join("src/edit.rs"), "x x\n").expect("file should be written");
-        let result = edit_file_contents(dir.path(), "src/edit.rs", "x", "y", false);
+        let result = edit_file_contents(
+            dir.path(),
+            "src/edit.rs",
+            "x",
+            "y",
+            &EditOptions::default(),
+            false,
+        );
         assert!(result.is_err(), "multiple matches should fail");
     }
 
@@ -1334,8 +2092,15 @@ mod tests {
     fn test_edit_file_contents_dry_run() {
         let dir = setup_repo();
         fs::write(dir.path().join("src/edit.rs"), "hello\n").expect("file should be written");
-        let value = edit_file_contents(dir.path(), "src/edit.rs", "hello", "bye", true)
-            .expect("dry run should succeed");
+        let value = edit_file_contents(
+            dir.path(),
+            "src/edit.rs",
+            "hello",
+            "bye",
+            &EditOptions::default(),
+            true,
+        )
+        .expect("dry run should succeed");
         assert_eq!(value["applied"], false);
         assert_eq!(value["replacements_applied"], 1);
         assert_eq!(
@@ -1344,6 +2109,87 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_edit_file_contents_replace_all_and_expected_occurrences() {
+        let dir = setup_repo();
+        let path = dir.path().join("src/edit.rs");
+        fs::write(&path, "x + x + x\n").expect("file should be written");
+
+        let mismatch = EditOptions {
+            expected_occurrences: Some(2),
+            ..Default::default()
+        };
+        let err = edit_file_contents(dir.path(), "src/edit.rs", "x", "y", &mismatch, false)
+            .expect_err("wrong occurrence count should fail");
+        assert!(err.to_string().contains("matches 3 times; expected 2"));
+
+        let expected = EditOptions {
+            expected_occurrences: Some(3),
+            ..Default::default()
+        };
+        let value = edit_file_contents(dir.path(), "src/edit.rs", "x", "$y", &expected, false)
+            .expect("matching count should succeed");
+        assert_eq!(value["replacements_applied"], 3);
+        assert_eq!(
+            fs::read_to_string(&path).expect("file should be readable"),
+            "$y + $y + $y\n",
+            "literal mode must not expand `$`"
+        );
+
+        let replace_all = EditOptions {
+            replace_all: true,
+            ..Default::default()
+        };
+        edit_file_contents(dir.path(), "src/edit.rs", "$y", "z", &replace_all, false)
+            .expect("replace_all should succeed");
+        assert_eq!(
+            fs::read_to_string(&path).expect("file should be readable"),
+            "z + z + z\n"
+        );
+    }
+
+    #[test]
+    fn test_edit_file_contents_regex_with_capture_groups() {
+        let dir = setup_repo();
+        let path = dir.path().join("src/edit.rs");
+        fs::write(&path, "foo(1);\nfoo(22);\nbar(3);\n").expect("file should be written");
+
+        let options = EditOptions {
+            is_regex: true,
+            replace_all: true,
+            ..Default::default()
+        };
+        let value = edit_file_contents(
+            dir.path(),
+            "src/edit.rs",
+            r"foo\((?<arg>\d+)\)",
+            "call(${arg}, 0)",
+            &options,
+            false,
+        )
+        .expect("regex edit should succeed");
+        assert_eq!(value["occurrences_found"], 2);
+        assert_eq!(
+            fs::read_to_string(&path).expect("file should be readable"),
+            "call(1, 0);\ncall(22, 0);\nbar(3);\n"
+        );
+
+        let single = EditOptions {
+            is_regex: true,
+            ..Default::default()
+        };
+        assert!(
+            edit_file_contents(dir.path(), "src/edit.rs", r"call\(\d+", "x", &single, false)
+                .is_err(),
+            "regex mode still requires a unique match by default"
+        );
+        for (old_text, options) in [(r"\d*", &options), ("(", &single), ("", &single)] {
+            let err = edit_file_contents(dir.path(), "src/edit.rs", old_text, "x", options, false)
+                .expect_err("empty matches, invalid regexes and empty old_text are rejected");
+            assert_eq!(error_code(&err), ErrorCode::InvalidParams, "{err}");
+        }
+    }
+
     #[test]
     fn test_batch_edit_file_contents_across_files() {
         let dir = setup_repo();
@@ -1681,4 +2527,40 @@ mod tests {
         assert_eq!(deleted["deleted"], true);
         assert!(!dir.path().join("src/to.rs").exists());
     }
+
+    #[test]
+    fn test_compare_files_returns_unified_hunks() {
+        let dir = setup_repo();
+        let body = (1..=12).map(|n| format!("line {n}\n")).collect::<String>();
+        fs::write(dir.path().join("src/a.rs"), &body).expect("file should be written");
+        let changed = body
+            .replace("line 2\n", "line two\n")
+            .replace("line 11\n", "line 11\nline 11.5\n");
+        fs::write(dir.path().join("src/b.rs"), changed).expect("file should be written");
+
+        let value = compare_files(dir.path(), "src/a.rs", "./src/b.rs", 1, 100)
+            .expect("compare should succeed");
+        assert_eq!(value["right"]["path"], "src/b.rs");
+        assert_eq!(value["identical"], false);
+        assert_eq!(
+            (value["removed"].as_u64(), value["added"].as_u64()),
+            (Some(1), Some(2))
+        );
+        assert_eq!(value["hunk_count"], 2);
+        assert_eq!(value["hunks"][0]["header"], "@@ -1,3 +1,3 @@");
+        assert_eq!(
+            value["hunks"][0]["lines"],
+            json!([" line 1", "-line 2", "+line two", " line 3"])
+        );
+        assert_eq!(value["hunks"][1]["header"], "@@ -11,2 +11,3 @@");
+
+        let clipped = compare_files(dir.path(), "src/a.rs", "src/b.rs", 1, 3).unwrap();
+        assert_eq!(clipped["truncated"], true);
+        assert_eq!(clipped["hunks"].as_array().unwrap().len(), 1);
+        assert_eq!(clipped["added"], 2, "counts cover the whole diff");
+
+        let same = compare_files(dir.path(), "src/a.rs", "src/a.rs", 3, 100).unwrap();
+        assert_eq!(same["identical"], true);
+        assert_eq!(same["line_similarity"], 1.0);
+    }
 }
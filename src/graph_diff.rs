@@ -0,0 +1,445 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+
+use crate::error::{coded, ErrorCode};
+use crate::indexer::{index_repository, IndexOptions};
+use crate::storage::GraphStore;
+
+const DIFF_SCRATCH_DIR_NAME: &str = "diff";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphDiff {
+    pub before: String,
+    pub after: String,
+    pub added_symbols: Vec<DiffSymbol>,
+    pub removed_symbols: Vec<DiffSymbol>,
+    pub changed_symbols: Vec<ChangedSymbol>,
+    pub added_dependencies: Vec<DiffDependency>,
+    pub broken_dependencies: Vec<DiffDependency>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffSymbol {
+    pub file_path: String,
+    pub qualname: String,
+    pub kind: String,
+    pub lang: Option<String>,
+    pub line: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangedSymbol {
+    pub file_path: String,
+    pub qualname: String,
+    pub line: i64,
+    pub before_kind: String,
+    pub after_kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before_signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after_signature: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffDependency {
+    pub from_file: String,
+    pub to_file: String,
+    /// Why a dependency disappeared: `source_removed`, `target_removed`, or
+    /// `dropped` when both files remain but the import no longer resolves.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<&'static str>,
+}
+
+/// One side of a diff, loaded from a graph database.
+struct GraphState {
+    files: BTreeSet<String>,
+    /// Definitions keyed by `(file_path, qualname)`, so a symbol that only
+    /// moved within its file is not reported as removed and re-added.
+    symbols: BTreeMap<(String, String), DiffSymbol>,
+    dependencies: BTreeSet<(String, String)>,
+}
+
+/// Compares two graph databases: definitions added, removed, or changed in
+/// kind or signature, and file `depends_on` edges that appeared or broke.
+/// Signatures are only compared when both sides recorded one, so an index
+/// built before signatures existed does not flag every function.
+pub fn diff_databases(before: &Path, after: &Path) -> Result<GraphDiff> {
+    let before_state = load_graph_state(before)?;
+    let after_state = load_graph_state(after)?;
+
+    let mut added_symbols = Vec::new();
+    let mut changed_symbols = Vec::new();
+    for (identity, symbol) in &after_state.symbols {
+        match before_state.symbols.get(identity) {
+            None => added_symbols.push(symbol.clone()),
+            Some(previous) if symbol_changed(previous, symbol) => {
+                changed_symbols.push(ChangedSymbol {
+                    file_path: symbol.file_path.clone(),
+                    qualname: symbol.qualname.clone(),
+                    line: symbol.line,
+                    before_kind: previous.kind.clone(),
+                    after_kind: symbol.kind.clone(),
+                    before_signature: previous.signature.clone(),
+                    after_signature: symbol.signature.clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    let removed_symbols = before_state
+        .symbols
+        .iter()
+        .filter(|(identity, _)| !after_state.symbols.contains_key(*identity))
+        .map(|(_, symbol)| symbol.clone())
+        .collect();
+
+    let added_dependencies = after_state
+        .dependencies
+        .difference(&before_state.dependencies)
+        .map(|(from_file, to_file)| DiffDependency {
+            from_file: from_file.clone(),
+            to_file: to_file.clone(),
+            reason: None,
+        })
+        .collect();
+    let broken_dependencies = before_state
+        .dependencies
+        .difference(&after_state.dependencies)
+        .map(|(from_file, to_file)| {
+            let reason = if !after_state.files.contains(from_file) {
+                "source_removed"
+            } else if !after_state.files.contains(to_file) {
+                "target_removed"
+            } else {
+                "dropped"
+            };
+            DiffDependency {
+                from_file: from_file.clone(),
+                to_file: to_file.clone(),
+                reason: Some(reason),
+            }
+        })
+        .collect();
+
+    Ok(GraphDiff {
+        before: before.display().to_string(),
+        after: after.display().to_string(),
+        added_symbols,
+        removed_symbols,
+        changed_symbols,
+        added_dependencies,
+        broken_dependencies,
+    })
+}
+
+fn symbol_changed(before: &DiffSymbol, after: &DiffSymbol) -> bool {
+    if before.kind != after.kind {
+        return true;
+    }
+    matches!(
+        (&before.signature, &after.signature),
+        (Some(before), Some(after)) if before != after
+    )
+}
+
+fn load_graph_state(db_path: &Path) -> Result<GraphState> {
+    if !db_path.exists() {
+        return Err(coded(
+            ErrorCode::FileNotFound,
+            format!("no database at {}", db_path.display()),
+        ));
+    }
+    let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("failed to open {}", db_path.display()))?;
+
+    let mut files = BTreeSet::new();
+    let mut stmt = conn
+        .prepare("SELECT path FROM files")
+        .with_context(|| format!("{} is not a lumora graph database", db_path.display()))?;
+    for path in stmt.query_map([], |row| row.get::<_, String>(0))? {
+        files.insert(path?);
+    }
+
+    let mut symbols = BTreeMap::new();
+    let mut stmt = conn.prepare(
+        "
+        SELECT file_path,
+               json_extract(meta_json, '$.qualname'),
+               json_extract(meta_json, '$.kind'),
+               lang,
+               line,
+               json_extract(meta_json, '$.signature')
+        FROM entities
+        WHERE entity_type = 'symbol' AND file_path IS NOT NULL
+        ORDER BY file_path, line, col
+        ",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(DiffSymbol {
+            file_path: row.get(0)?,
+            qualname: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            kind: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+            lang: row.get(3)?,
+            line: row.get::<_, Option<i64>>(4)?.unwrap_or_default(),
+            signature: row.get(5)?,
+        })
+    })?;
+    for symbol in rows {
+        let symbol = symbol?;
+        symbols
+            .entry((symbol.file_path.clone(), symbol.qualname.clone()))
+            .or_insert(symbol);
+    }
+
+    let mut dependencies = BTreeSet::new();
+    let mut stmt = conn.prepare(
+        "
+        SELECT DISTINCT src.file_path, dst.file_path
+        FROM edges e
+        JOIN entities src ON src.id = e.src_entity_id AND src.entity_type = 'file'
+        JOIN entities dst ON dst.id = e.dst_entity_id AND dst.entity_type = 'file'
+        WHERE e.edge_type = 'depends_on'
+          AND src.file_path IS NOT NULL
+          AND dst.file_path IS NOT NULL
+        ",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+    for dependency in rows {
+        dependencies.insert(dependency?);
+    }
+
+    Ok(GraphState {
+        files,
+        symbols,
+        dependencies,
+    })
+}
+
+/// Builds a throwaway index of `rev` by checking it out into a detached git
+/// worktree under `<state-dir>/diff/`. The worktree is removed afterwards;
+/// the returned database is left for the caller to diff and delete.
+pub fn index_revision(
+    repo_root: &Path,
+    state_dir: &Path,
+    rev: &str,
+    label: &str,
+) -> Result<PathBuf> {
+    let commit = resolve_commit(repo_root, rev)?;
+    let scratch = diff_scratch_dir(state_dir).join(format!("{label}-{}", std::process::id()));
+    if scratch.exists() {
+        fs::remove_dir_all(&scratch)
+            .with_context(|| format!("failed to clear {}", scratch.display()))?;
+    }
+    fs::create_dir_all(&scratch)
+        .with_context(|| format!("failed to create {}", scratch.display()))?;
+    let worktree = scratch.join("tree");
+    let db_path = scratch.join("graph.db");
+
+    run_git(
+        repo_root,
+        &[
+            "worktree",
+            "add",
+            "--detach",
+            "--force",
+            &worktree.to_string_lossy(),
+            &commit,
+        ],
+    )
+    .with_context(|| format!("failed to check out `{rev}`"))?;
+
+    let indexed = GraphStore::open(&db_path).and_then(|mut store| {
+        index_repository(
+            &mut store,
+            &worktree,
+            IndexOptions {
+                full: true,
+                ..IndexOptions::default()
+            },
+        )
+    });
+    let removed = run_git(
+        repo_root,
+        &["worktree", "remove", "--force", &worktree.to_string_lossy()],
+    );
+    indexed.with_context(|| format!("failed to index `{rev}`"))?;
+    removed.with_context(|| format!("failed to remove worktree {}", worktree.display()))?;
+    Ok(db_path)
+}
+
+/// Deletes the scratch directory that holds a database from [`index_revision`].
+pub fn discard_revision_index(db_path: &Path) -> Result<()> {
+    match db_path.parent() {
+        Some(scratch) if scratch.exists() => fs::remove_dir_all(scratch)
+            .with_context(|| format!("failed to remove {}", scratch.display())),
+        _ => Ok(()),
+    }
+}
+
+/// The commit id `rev` names, so that only a verified SHA ever reaches the
+/// `git worktree` command line.
+fn resolve_commit(repo_root: &Path, rev: &str) -> Result<String> {
+    if rev.starts_with('-') {
+        return Err(coded(
+            ErrorCode::InvalidParams,
+            format!("`{rev}` is not a revision"),
+        ));
+    }
+    let spec = format!("{rev}^{{commit}}");
+    let commit = run_git(
+        repo_root,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            "--end-of-options",
+            &spec,
+        ],
+    )
+    .map_err(|_| {
+        coded(
+            ErrorCode::InvalidParams,
+            format!("unknown revision `{rev}`"),
+        )
+    })?;
+    Ok(commit.trim().to_string())
+}
+
+fn diff_scratch_dir(state_dir: &Path) -> PathBuf {
+    state_dir.join(DIFF_SCRATCH_DIR_NAME)
+}
+
+fn run_git(repo_root: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo_root)
+        .args(args)
+        .output()
+        .context("failed to run git")?;
+    anyhow::ensure!(
+        output.status.success(),
+        "git {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn index_into(repo: &Path, db_path: &Path) {
+        let mut store = GraphStore::open(db_path).expect("failed to open store");
+        index_repository(
+            &mut store,
+            repo,
+            IndexOptions {
+                full: true,
+                ..IndexOptions::default()
+            },
+        )
+        .expect("indexing should succeed");
+    }
+
+    #[test]
+    fn diff_databases_reports_symbol_and_dependency_changes() {
+        let before_repo = TempDir::new().unwrap();
+        let after_repo = TempDir::new().unwrap();
+        let dbs = TempDir::new().unwrap();
+
+        fs::write(
+            before_repo.path().join("app.py"),
+            "import util\n\ndef run():\n    return util.helper()\n\ndef old():\n    pass\n",
+        )
+        .unwrap();
+        fs::write(
+            before_repo.path().join("util.py"),
+            "def helper():\n    return 1\n",
+        )
+        .unwrap();
+        fs::write(
+            after_repo.path().join("app.py"),
+            "\n\ndef run():\n    return 2\n\ndef fresh():\n    pass\n",
+        )
+        .unwrap();
+        fs::write(
+            after_repo.path().join("util.py"),
+            "def helper(scale):\n    return scale\n",
+        )
+        .unwrap();
+
+        let before_db = dbs.path().join("before.db");
+        let after_db = dbs.path().join("after.db");
+        index_into(before_repo.path(), &before_db);
+        index_into(after_repo.path(), &after_db);
+
+        let diff = diff_databases(&before_db, &after_db).expect("diff should succeed");
+        let names = |symbols: &[DiffSymbol]| {
+            symbols
+                .iter()
+                .map(|symbol| symbol.qualname.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&diff.added_symbols), vec!["fresh"]);
+        assert_eq!(names(&diff.removed_symbols), vec!["old"]);
+        assert_eq!(
+            diff.changed_symbols.len(),
+            1,
+            "moved `run` should not be reported as changed"
+        );
+        let changed = &diff.changed_symbols[0];
+        assert_eq!(changed.qualname, "helper");
+        assert_eq!(changed.before_signature.as_deref(), Some("def helper()"));
+        assert_eq!(
+            changed.after_signature.as_deref(),
+            Some("def helper(scale)")
+        );
+        assert!(diff.added_dependencies.is_empty());
+        assert_eq!(diff.broken_dependencies.len(), 1);
+        assert_eq!(diff.broken_dependencies[0].from_file, "app.py");
+        assert_eq!(diff.broken_dependencies[0].to_file, "util.py");
+        assert_eq!(diff.broken_dependencies[0].reason, Some("dropped"));
+
+        let missing = diff_databases(&dbs.path().join("nope.db"), &after_db).unwrap_err();
+        assert_eq!(crate::error::error_code(&missing), ErrorCode::FileNotFound);
+    }
+
+    #[test]
+    fn resolve_commit_only_accepts_revisions_naming_a_commit() {
+        let repo = TempDir::new().unwrap();
+        let git = |args: &[&str]| {
+            run_git(
+                repo.path(),
+                &[
+                    &["-c", "user.name=Ada", "-c", "user.email=ada@example.com"],
+                    args,
+                ]
+                .concat(),
+            )
+            .unwrap()
+        };
+        git(&["init", "-q"]);
+        fs::write(repo.path().join("lib.rs"), "fn main() {}\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-qm", "init"]);
+
+        let head = git(&["rev-parse", "HEAD"]);
+        assert_eq!(resolve_commit(repo.path(), "HEAD").unwrap(), head.trim());
+        for rev in ["--orphan=x", "-b", "missing-branch"] {
+            let err = resolve_commit(repo.path(), rev).unwrap_err();
+            assert_eq!(crate::error::error_code(&err), ErrorCode::InvalidParams);
+        }
+    }
+}
@@ -0,0 +1,1026 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use rusqlite::types::Value as SqlValue;
+use serde::Serialize;
+
+use crate::error::{coded, ErrorCode};
+
+/// Upper bound on rows a single graph query returns, whatever its `LIMIT`.
+pub const GRAPH_QUERY_MAX_ROWS: usize = 10_000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphQueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    pub truncated: bool,
+}
+
+/// A graph query lowered to SQL over `entities` and `edges`. `json_columns`
+/// marks columns holding a whole node or edge as a JSON object.
+#[derive(Debug, Clone)]
+pub struct CompiledQuery {
+    pub sql: String,
+    pub params: Vec<SqlValue>,
+    pub columns: Vec<String>,
+    pub json_columns: Vec<bool>,
+    pub limit: usize,
+}
+
+/// Compiles `MATCH <pattern> [WHERE <expr>] RETURN [DISTINCT] <items>
+/// [ORDER BY <keys>] [LIMIT <n>]`. `default_limit` applies when the query
+/// has no `LIMIT`; the SQL fetches one extra row so callers can tell the
+/// result was cut off.
+pub fn compile(query: &str, default_limit: usize) -> Result<CompiledQuery> {
+    let tokens = tokenize(query)?;
+    let parsed = Parser { tokens, pos: 0 }.parse_query()?;
+    Compiler::default().compile(parsed, default_limit)
+}
+
+fn invalid(message: impl std::fmt::Display) -> anyhow::Error {
+    coded(ErrorCode::InvalidParams, format!("graph query: {message}"))
+}
+
+// ── Tokens ─────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Sym(&'static str),
+}
+
+const TWO_CHAR_SYMBOLS: &[&str] = &["<=", ">=", "!=", "<>", "=~"];
+const ONE_CHAR_SYMBOLS: &[&str] = &[
+    "(", ")", "[", "]", "{", "}", ":", ",", ".", "-", "<", ">", "=", "|", "*",
+];
+
+fn tokenize(input: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let is_float = i + 1 < chars.len() && chars[i] == '.' && chars[i + 1].is_ascii_digit();
+            if is_float {
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(if is_float {
+                Token::Float(text.parse().map_err(|_| invalid("bad number"))?)
+            } else {
+                Token::Int(
+                    text.parse()
+                        .map_err(|_| invalid(format!("number out of range: {text}")))?,
+                )
+            });
+        } else if c == '\'' || c == '"' {
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(invalid("unterminated string literal")),
+                    Some('\\') => {
+                        let escaped = chars
+                            .get(i + 1)
+                            .ok_or_else(|| invalid("unterminated string literal"))?;
+                        value.push(*escaped);
+                        i += 2;
+                    }
+                    Some(&ch) if ch == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&ch) => {
+                        value.push(ch);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(Token::Str(value));
+        } else {
+            let pair: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            if let Some(sym) = TWO_CHAR_SYMBOLS.iter().find(|sym| **sym == pair) {
+                tokens.push(Token::Sym(sym));
+                i += 2;
+            } else if let Some(sym) = ONE_CHAR_SYMBOLS
+                .iter()
+                .find(|sym| sym.starts_with(c) && sym.len() == c.len_utf8())
+            {
+                tokens.push(Token::Sym(sym));
+                i += 1;
+            } else {
+                return Err(invalid(format!("unexpected character '{c}'")));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// ── Syntax tree ────────────────────────────────────────────────────
+
+#[derive(Debug)]
+struct Query {
+    patterns: Vec<Pattern>,
+    filter: Option<Expr>,
+    distinct: bool,
+    items: Vec<ReturnItem>,
+    order: Vec<(OrderKey, bool)>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Pattern {
+    start: NodePattern,
+    steps: Vec<(EdgePattern, NodePattern)>,
+}
+
+#[derive(Debug)]
+struct NodePattern {
+    var: Option<String>,
+    labels: Vec<String>,
+    props: Vec<(String, Literal)>,
+}
+
+#[derive(Debug)]
+struct EdgePattern {
+    var: Option<String>,
+    types: Vec<String>,
+    props: Vec<(String, Literal)>,
+    direction: Direction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    Out,
+    In,
+    Either,
+}
+
+#[derive(Debug, Clone)]
+enum Literal {
+    Str(String),
+    Int(i64),
+    Float(f64),
+    Null,
+}
+
+impl Literal {
+    fn to_sql(&self) -> SqlValue {
+        match self {
+            Literal::Str(value) => SqlValue::Text(value.clone()),
+            Literal::Int(value) => SqlValue::Integer(*value),
+            Literal::Float(value) => SqlValue::Real(*value),
+            Literal::Null => SqlValue::Null,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Operand {
+    Property(String, String),
+    Variable(String),
+    Literal(Literal),
+}
+
+#[derive(Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, &'static str, Operand),
+    IsNull(Operand, bool),
+    In(Operand, Vec<Literal>),
+}
+
+#[derive(Debug)]
+enum ReturnValue {
+    Property(String, String),
+    Variable(String),
+    Count {
+        distinct: bool,
+        arg: Option<Operand>,
+    },
+}
+
+#[derive(Debug)]
+struct ReturnItem {
+    value: ReturnValue,
+    alias: Option<String>,
+}
+
+#[derive(Debug)]
+enum OrderKey {
+    Value(ReturnValue),
+    Name(String),
+}
+
+// ── Parser ─────────────────────────────────────────────────────────
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_sym(&self, sym: &str) -> bool {
+        matches!(self.peek(), Some(Token::Sym(found)) if *found == sym)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_sym(&mut self, sym: &str) -> bool {
+        let found = self.peek_sym(sym);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_sym(&mut self, sym: &str) -> Result<()> {
+        if self.eat_sym(sym) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{sym}'")))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(keyword))
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+
+    fn unexpected(&self, wanted: &str) -> anyhow::Error {
+        match self.peek() {
+            None => invalid(format!("expected {wanted}, found end of query")),
+            Some(token) => invalid(format!(
+                "expected {wanted}, found {}",
+                describe_token(token)
+            )),
+        }
+    }
+
+    fn parse_query(mut self) -> Result<Query> {
+        self.expect_keyword("MATCH")?;
+        let mut patterns = vec![self.parse_pattern()?];
+        while self.eat_sym(",") {
+            patterns.push(self.parse_pattern()?);
+        }
+        let filter = if self.eat_keyword("WHERE") {
+            Some(self.parse_or()?)
+        } else {
+            None
+        };
+        self.expect_keyword("RETURN")?;
+        let distinct = self.eat_keyword("DISTINCT");
+        let mut items = vec![self.parse_return_item()?];
+        while self.eat_sym(",") {
+            items.push(self.parse_return_item()?);
+        }
+        let mut order = Vec::new();
+        if self.eat_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let key = self.parse_order_key()?;
+                let descending = if self.eat_keyword("DESC") {
+                    true
+                } else {
+                    self.eat_keyword("ASC");
+                    false
+                };
+                order.push((key, descending));
+                if !self.eat_sym(",") {
+                    break;
+                }
+            }
+        }
+        let limit = if self.eat_keyword("LIMIT") {
+            match self.peek() {
+                Some(Token::Int(value)) if *value >= 0 => {
+                    let value = *value as usize;
+                    self.pos += 1;
+                    Some(value)
+                }
+                _ => return Err(self.unexpected("a row count")),
+            }
+        } else {
+            None
+        };
+        if self.peek().is_some() {
+            return Err(self.unexpected("end of query"));
+        }
+        Ok(Query {
+            patterns,
+            filter,
+            distinct,
+            items,
+            order,
+            limit,
+        })
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern> {
+        let start = self.parse_node()?;
+        let mut steps = Vec::new();
+        while self.peek_sym("-") || self.peek_sym("<") {
+            let edge = self.parse_edge()?;
+            let node = self.parse_node()?;
+            steps.push((edge, node));
+        }
+        Ok(Pattern { start, steps })
+    }
+
+    fn parse_node(&mut self) -> Result<NodePattern> {
+        self.expect_sym("(")?;
+        let var = match self.peek() {
+            Some(Token::Ident(_)) => Some(self.expect_ident()?),
+            _ => None,
+        };
+        let labels = self.parse_labels()?;
+        let props = self.parse_props()?;
+        self.expect_sym(")")?;
+        Ok(NodePattern { var, labels, props })
+    }
+
+    /// `-[...]->`, `<-[...]-`, or `-[...]-`; the bracketed part is optional.
+    fn parse_edge(&mut self) -> Result<EdgePattern> {
+        let incoming = self.eat_sym("<");
+        self.expect_sym("-")?;
+        let (var, types, props) = if self.eat_sym("[") {
+            let var = match self.peek() {
+                Some(Token::Ident(_)) => Some(self.expect_ident()?),
+                _ => None,
+            };
+            let types = self.parse_labels()?;
+            let props = self.parse_props()?;
+            self.expect_sym("]")?;
+            (var, types, props)
+        } else {
+            (None, Vec::new(), Vec::new())
+        };
+        self.expect_sym("-")?;
+        let outgoing = self.eat_sym(">");
+        let direction = match (incoming, outgoing) {
+            (true, true) => return Err(invalid("an edge cannot point both ways")),
+            (true, false) => Direction::In,
+            (false, true) => Direction::Out,
+            (false, false) => Direction::Either,
+        };
+        Ok(EdgePattern {
+            var,
+            types,
+            props,
+            direction,
+        })
+    }
+
+    fn parse_labels(&mut self) -> Result<Vec<String>> {
+        let mut labels = Vec::new();
+        if self.eat_sym(":") {
+            labels.push(self.expect_ident()?);
+            while self.eat_sym("|") {
+                self.eat_sym(":");
+                labels.push(self.expect_ident()?);
+            }
+        }
+        Ok(labels)
+    }
+
+    fn parse_props(&mut self) -> Result<Vec<(String, Literal)>> {
+        let mut props = Vec::new();
+        if self.eat_sym("{") {
+            loop {
+                let name = self.expect_ident()?;
+                self.expect_sym(":")?;
+                props.push((name, self.parse_literal()?));
+                if !self.eat_sym(",") {
+                    break;
+                }
+            }
+            self.expect_sym("}")?;
+        }
+        Ok(props)
+    }
+
+    fn parse_literal(&mut self) -> Result<Literal> {
+        let negative = self.eat_sym("-");
+        let literal = match self.peek() {
+            Some(Token::Str(value)) if !negative => Literal::Str(value.clone()),
+            Some(Token::Int(value)) => Literal::Int(if negative { -value } else { *value }),
+            Some(Token::Float(value)) => Literal::Float(if negative { -value } else { *value }),
+            Some(Token::Ident(word)) if !negative && word.eq_ignore_ascii_case("NULL") => {
+                Literal::Null
+            }
+            _ => return Err(self.unexpected("a literal")),
+        };
+        self.pos += 1;
+        Ok(literal)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_and()?;
+        while self.eat_keyword("OR") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_not()?;
+        while self.eat_keyword("AND") {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr> {
+        if self.eat_keyword("NOT") {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        if self.eat_sym("(") {
+            let expr = self.parse_or()?;
+            self.expect_sym(")")?;
+            return Ok(expr);
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr> {
+        let left = self.parse_operand()?;
+        if self.eat_keyword("IS") {
+            let negated = self.eat_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            return Ok(Expr::IsNull(left, negated));
+        }
+        if self.eat_keyword("IN") {
+            self.expect_sym("[")?;
+            let mut values = Vec::new();
+            if !self.peek_sym("]") {
+                values.push(self.parse_literal()?);
+                while self.eat_sym(",") {
+                    values.push(self.parse_literal()?);
+                }
+            }
+            self.expect_sym("]")?;
+            return Ok(Expr::In(left, values));
+        }
+        let op = match self.peek() {
+            Some(Token::Sym(sym))
+                if ["=", "!=", "<>", "<", "<=", ">", ">=", "=~"].contains(sym) =>
+            {
+                *sym
+            }
+            Some(Token::Ident(word)) => match word.to_ascii_uppercase().as_str() {
+                "GLOB" => "GLOB",
+                "LIKE" => "LIKE",
+                "CONTAINS" => "CONTAINS",
+                _ => return Err(self.unexpected("a comparison")),
+            },
+            _ => return Err(self.unexpected("a comparison")),
+        };
+        self.pos += 1;
+        let right = self.parse_operand()?;
+        Ok(Expr::Compare(left, op, right))
+    }
+
+    fn parse_operand(&mut self) -> Result<Operand> {
+        match self.peek() {
+            Some(Token::Ident(word)) if !word.eq_ignore_ascii_case("NULL") => {
+                let var = self.expect_ident()?;
+                if self.eat_sym(".") {
+                    Ok(Operand::Property(var, self.expect_ident()?))
+                } else {
+                    Ok(Operand::Variable(var))
+                }
+            }
+            _ => Ok(Operand::Literal(self.parse_literal()?)),
+        }
+    }
+
+    fn parse_return_value(&mut self) -> Result<ReturnValue> {
+        if self.peek_keyword("count")
+            && matches!(self.tokens.get(self.pos + 1), Some(Token::Sym("(")))
+        {
+            self.pos += 2;
+            let (distinct, arg) = if self.eat_sym("*") {
+                (false, None)
+            } else {
+                let distinct = self.eat_keyword("DISTINCT");
+                match self.parse_operand()? {
+                    Operand::Literal(_) => {
+                        return Err(invalid("count() takes *, a variable, or a property"))
+                    }
+                    operand => (distinct, Some(operand)),
+                }
+            };
+            self.expect_sym(")")?;
+            return Ok(ReturnValue::Count { distinct, arg });
+        }
+        let var = self.expect_ident()?;
+        if self.eat_sym(".") {
+            Ok(ReturnValue::Property(var, self.expect_ident()?))
+        } else {
+            Ok(ReturnValue::Variable(var))
+        }
+    }
+
+    fn parse_return_item(&mut self) -> Result<ReturnItem> {
+        let value = self.parse_return_value()?;
+        let alias = if self.eat_keyword("AS") {
+            Some(self.expect_ident()?)
+        } else {
+            None
+        };
+        Ok(ReturnItem { value, alias })
+    }
+
+    fn parse_order_key(&mut self) -> Result<OrderKey> {
+        let is_name = matches!(self.peek(), Some(Token::Ident(_)))
+            && !matches!(self.tokens.get(self.pos + 1), Some(Token::Sym("." | "(")));
+        if is_name {
+            Ok(OrderKey::Name(self.expect_ident()?))
+        } else {
+            Ok(OrderKey::Value(self.parse_return_value()?))
+        }
+    }
+}
+
+fn describe_token(token: &Token) -> String {
+    match token {
+        Token::Ident(name) => format!("'{name}'"),
+        Token::Str(value) => format!("string '{value}'"),
+        Token::Int(value) => value.to_string(),
+        Token::Float(value) => value.to_string(),
+        Token::Sym(sym) => format!("'{sym}'"),
+    }
+}
+
+// ── SQL ────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BindingKind {
+    Node,
+    Edge,
+}
+
+#[derive(Default)]
+struct Compiler {
+    bindings: HashMap<String, (BindingKind, String)>,
+    tables: Vec<String>,
+    conditions: Vec<String>,
+    params: Vec<SqlValue>,
+    edge_aliases: Vec<String>,
+}
+
+const NODE_PROPERTIES: &[&str] = &[
+    "id",
+    "type",
+    "key",
+    "name",
+    "lang",
+    "path",
+    "line",
+    "col",
+    "end_line",
+    "end_col",
+    "kind",
+    "qualname",
+    "signature",
+    "visibility",
+];
+const EDGE_PROPERTIES: &[&str] = &[
+    "id",
+    "type",
+    "path",
+    "line",
+    "col",
+    "end_line",
+    "end_col",
+    "origin",
+    "confidence",
+];
+
+fn property_sql(kind: BindingKind, alias: &str, property: &str) -> Result<String> {
+    let column = match (kind, property) {
+        (_, "type") => format!(
+            "{alias}.{}",
+            if kind == BindingKind::Node {
+                "entity_type"
+            } else {
+                "edge_type"
+            }
+        ),
+        (_, "path") => format!("{alias}.file_path"),
+        (_, "id" | "line" | "col" | "end_line" | "end_col") => format!("{alias}.{property}"),
+        (BindingKind::Node, "key" | "name" | "lang") => format!("{alias}.{property}"),
+        (BindingKind::Node, "kind" | "qualname" | "signature" | "visibility") => {
+            format!("json_extract({alias}.meta_json, '$.{property}')")
+        }
+        (BindingKind::Edge, "origin" | "confidence") => format!("{alias}.{property}"),
+        _ => {
+            let known = match kind {
+                BindingKind::Node => NODE_PROPERTIES,
+                BindingKind::Edge => EDGE_PROPERTIES,
+            };
+            return Err(invalid(format!(
+                "unknown property '{property}'; expected one of {}",
+                known.join(", ")
+            )));
+        }
+    };
+    Ok(column)
+}
+
+fn object_sql(kind: BindingKind, alias: &str) -> String {
+    let properties = match kind {
+        BindingKind::Node => &["id", "type", "name", "qualname", "kind", "path", "line"][..],
+        BindingKind::Edge => &["id", "type", "path", "line", "origin", "confidence"][..],
+    };
+    let fields: Vec<String> = properties
+        .iter()
+        .map(|property| {
+            let column = property_sql(kind, alias, property).expect("known property");
+            format!("'{property}', {column}")
+        })
+        .collect();
+    format!("json_object({})", fields.join(", "))
+}
+
+impl Compiler {
+    fn bind_node(&mut self, node: &NodePattern) -> Result<String> {
+        let alias = match node.var.as_ref().and_then(|var| self.bindings.get(var)) {
+            Some((BindingKind::Node, alias)) => alias.clone(),
+            Some((BindingKind::Edge, _)) => {
+                return Err(invalid(format!(
+                    "'{}' is an edge, not a node",
+                    node.var.as_deref().unwrap_or_default()
+                )))
+            }
+            None => {
+                let alias = format!("n{}", self.tables.len());
+                self.tables.push(format!("entities {alias}"));
+                if let Some(var) = &node.var {
+                    self.bindings
+                        .insert(var.clone(), (BindingKind::Node, alias.clone()));
+                }
+                alias
+            }
+        };
+        self.constrain(BindingKind::Node, &alias, &node.labels, &node.props)?;
+        Ok(alias)
+    }
+
+    fn bind_edge(&mut self, edge: &EdgePattern, from: &str, to: &str) -> Result<()> {
+        if let Some(var) = &edge.var {
+            if self.bindings.contains_key(var) {
+                return Err(invalid(format!("'{var}' is already bound")));
+            }
+        }
+        let alias = format!("e{}", self.tables.len());
+        self.tables.push(format!("edges {alias}"));
+        if let Some(var) = &edge.var {
+            self.bindings
+                .insert(var.clone(), (BindingKind::Edge, alias.clone()));
+        }
+        let out = format!("{alias}.src_entity_id = {from}.id AND {alias}.dst_entity_id = {to}.id");
+        let back = format!("{alias}.src_entity_id = {to}.id AND {alias}.dst_entity_id = {from}.id");
+        self.conditions.push(match edge.direction {
+            Direction::Out => out,
+            Direction::In => back,
+            Direction::Either => format!("(({out}) OR ({back}))"),
+        });
+        // Like Cypher, one MATCH never walks the same edge twice.
+        for other in &self.edge_aliases {
+            self.conditions.push(format!("{alias}.id != {other}.id"));
+        }
+        self.edge_aliases.push(alias.clone());
+        self.constrain(BindingKind::Edge, &alias, &edge.types, &edge.props)
+    }
+
+    fn constrain(
+        &mut self,
+        kind: BindingKind,
+        alias: &str,
+        labels: &[String],
+        props: &[(String, Literal)],
+    ) -> Result<()> {
+        if !labels.is_empty() {
+            let column = property_sql(kind, alias, "type")?;
+            let placeholders = vec!["?"; labels.len()].join(", ");
+            self.conditions
+                .push(format!("{column} IN ({placeholders})"));
+            self.params
+                .extend(labels.iter().map(|label| SqlValue::Text(label.clone())));
+        }
+        for (property, value) in props {
+            let column = property_sql(kind, alias, property)?;
+            if matches!(value, Literal::Null) {
+                self.conditions.push(format!("{column} IS NULL"));
+            } else {
+                self.conditions.push(format!("{column} = ?"));
+                self.params.push(value.to_sql());
+            }
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, var: &str) -> Result<(BindingKind, String)> {
+        self.bindings
+            .get(var)
+            .cloned()
+            .ok_or_else(|| invalid(format!("unknown variable '{var}'")))
+    }
+
+    fn operand_sql(&mut self, operand: &Operand) -> Result<String> {
+        match operand {
+            Operand::Property(var, property) => {
+                let (kind, alias) = self.lookup(var)?;
+                property_sql(kind, &alias, property)
+            }
+            Operand::Variable(var) => Ok(format!("{}.id", self.lookup(var)?.1)),
+            Operand::Literal(value) => {
+                self.params.push(value.to_sql());
+                Ok("?".to_string())
+            }
+        }
+    }
+
+    fn expr_sql(&mut self, expr: &Expr) -> Result<String> {
+        Ok(match expr {
+            Expr::And(left, right) => {
+                format!("({} AND {})", self.expr_sql(left)?, self.expr_sql(right)?)
+            }
+            Expr::Or(left, right) => {
+                format!("({} OR {})", self.expr_sql(left)?, self.expr_sql(right)?)
+            }
+            Expr::Not(inner) => format!("(NOT {})", self.expr_sql(inner)?),
+            Expr::Compare(left, op, right) => {
+                let left = self.operand_sql(left)?;
+                let right = self.operand_sql(right)?;
+                match *op {
+                    "CONTAINS" => format!("(instr({left}, {right}) > 0)"),
+                    "=~" => format!("({left} REGEXP {right})"),
+                    "<>" => format!("({left} != {right})"),
+                    op => format!("({left} {op} {right})"),
+                }
+            }
+            Expr::IsNull(operand, negated) => {
+                let operand = self.operand_sql(operand)?;
+                let not = if *negated { " NOT" } else { "" };
+                format!("({operand} IS{not} NULL)")
+            }
+            Expr::In(operand, values) => {
+                let operand = self.operand_sql(operand)?;
+                self.params.extend(values.iter().map(Literal::to_sql));
+                let placeholders = vec!["?"; values.len()].join(", ");
+                format!("({operand} IN ({placeholders}))")
+            }
+        })
+    }
+
+    /// SQL for a returned value, whether it is a whole node or edge, and
+    /// whether it aggregates.
+    fn value_sql(&mut self, value: &ReturnValue) -> Result<(String, bool, bool)> {
+        Ok(match value {
+            ReturnValue::Property(var, property) => {
+                let (kind, alias) = self.lookup(var)?;
+                (property_sql(kind, &alias, property)?, false, false)
+            }
+            ReturnValue::Variable(var) => {
+                let (kind, alias) = self.lookup(var)?;
+                (object_sql(kind, &alias), true, false)
+            }
+            ReturnValue::Count { distinct, arg } => {
+                let inner = match arg {
+                    None => "*".to_string(),
+                    Some(operand) => self.operand_sql(operand)?,
+                };
+                let distinct = if *distinct { "DISTINCT " } else { "" };
+                (format!("count({distinct}{inner})"), false, true)
+            }
+        })
+    }
+
+    fn compile(mut self, query: Query, default_limit: usize) -> Result<CompiledQuery> {
+        for pattern in &query.patterns {
+            let mut previous = self.bind_node(&pattern.start)?;
+            for (edge, node) in &pattern.steps {
+                let next = self.bind_node(node)?;
+                self.bind_edge(edge, &previous, &next)?;
+                previous = next;
+            }
+        }
+        let filter = match &query.filter {
+            Some(expr) => Some(self.expr_sql(expr)?),
+            None => None,
+        };
+
+        // Placeholders bind in the order they appear in the SQL, and the
+        // SELECT list comes before the WHERE clause built above.
+        let where_params = std::mem::take(&mut self.params);
+        let mut select = Vec::new();
+        let mut columns = Vec::new();
+        let mut json_columns = Vec::new();
+        let mut group_by = Vec::new();
+        let mut aggregates = false;
+        for (index, item) in query.items.iter().enumerate() {
+            let (sql, is_json, is_aggregate) = self.value_sql(&item.value)?;
+            let column = format!("c{index}");
+            select.push(format!("{sql} AS {column}"));
+            if is_aggregate {
+                aggregates = true;
+            } else {
+                group_by.push(column);
+            }
+            columns.push(
+                item.alias
+                    .clone()
+                    .unwrap_or_else(|| describe_value(&item.value)),
+            );
+            json_columns.push(is_json);
+        }
+        let select_params = std::mem::take(&mut self.params);
+
+        let mut order_by = Vec::new();
+        for (key, descending) in &query.order {
+            let sql = match key {
+                OrderKey::Name(name) => match columns.iter().position(|column| column == name) {
+                    Some(index) => format!("c{index}"),
+                    None => self.value_sql(&ReturnValue::Variable(name.clone()))?.0,
+                },
+                OrderKey::Value(value) => {
+                    let described = describe_value(value);
+                    match columns.iter().position(|column| *column == described) {
+                        Some(index) => format!("c{index}"),
+                        None => self.value_sql(value)?.0,
+                    }
+                }
+            };
+            order_by.push(format!("{sql}{}", if *descending { " DESC" } else { "" }));
+        }
+        let order_params = std::mem::take(&mut self.params);
+
+        let limit = query
+            .limit
+            .unwrap_or(default_limit)
+            .min(GRAPH_QUERY_MAX_ROWS);
+        let mut sql = format!(
+            "SELECT {}{} FROM {}",
+            if query.distinct { "DISTINCT " } else { "" },
+            select.join(", "),
+            self.tables.join(", ")
+        );
+        let mut conditions = self.conditions;
+        conditions.extend(filter);
+        if !conditions.is_empty() {
+            sql.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
+        }
+        if aggregates && !group_by.is_empty() {
+            sql.push_str(&format!(" GROUP BY {}", group_by.join(", ")));
+        }
+        if !order_by.is_empty() {
+            sql.push_str(&format!(" ORDER BY {}", order_by.join(", ")));
+        }
+        sql.push_str(&format!(" LIMIT {}", limit + 1));
+
+        let mut params = select_params;
+        params.extend(where_params);
+        params.extend(order_params);
+        Ok(CompiledQuery {
+            sql,
+            params,
+            columns,
+            json_columns,
+            limit,
+        })
+    }
+}
+
+fn describe_value(value: &ReturnValue) -> String {
+    match value {
+        ReturnValue::Property(var, property) => format!("{var}.{property}"),
+        ReturnValue::Variable(var) => var.clone(),
+        ReturnValue::Count { distinct, arg } => {
+            let inner = match arg {
+                None => "*".to_string(),
+                Some(Operand::Property(var, property)) => format!("{var}.{property}"),
+                Some(Operand::Variable(var)) => var.clone(),
+                Some(Operand::Literal(_)) => "?".to_string(),
+            };
+            let distinct = if *distinct { "DISTINCT " } else { "" };
+            format!("count({distinct}{inner})")
+        }
+    }
+}
+
+pub fn sql_to_json(value: SqlValue, is_json: bool) -> serde_json::Value {
+    match value {
+        SqlValue::Null => serde_json::Value::Null,
+        SqlValue::Integer(value) => value.into(),
+        SqlValue::Real(value) => value.into(),
+        SqlValue::Text(text) if is_json => {
+            serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text))
+        }
+        SqlValue::Text(text) => text.into(),
+        SqlValue::Blob(bytes) => format!("<{} bytes>", bytes.len()).into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::error_code;
+
+    #[test]
+    fn compiles_a_chain_with_labels_filter_and_order() {
+        let compiled = compile(
+            "MATCH (f:file)-[:imports]->(m:module) WHERE f.path GLOB 'src/*' \
+             RETURN f.path, m.name AS module ORDER BY module DESC LIMIT 5",
+            100,
+        )
+        .unwrap();
+        assert_eq!(compiled.columns, vec!["f.path", "module"]);
+        assert_eq!(compiled.limit, 5);
+        assert!(compiled.sql.contains("e2.src_entity_id = n0.id"));
+        assert!(compiled.sql.contains("(n0.file_path GLOB ?)"));
+        assert!(compiled.sql.ends_with("ORDER BY c1 DESC LIMIT 6"));
+        let params: Vec<_> = compiled
+            .params
+            .iter()
+            .map(|value| match value {
+                SqlValue::Text(text) => text.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(params, vec!["file", "module", "imports", "src/*"]);
+    }
+
+    #[test]
+    fn groups_by_non_aggregate_items_and_rejects_bad_queries() {
+        let compiled = compile(
+            "MATCH (s:symbol)<-[:defines]-(f) RETURN f.path, count(*) AS defs",
+            10,
+        )
+        .unwrap();
+        assert!(compiled.sql.contains("GROUP BY c0"));
+        assert!(compiled.sql.contains("e2.src_entity_id = n1.id"));
+
+        for bad in [
+            "RETURN x",
+            "MATCH (a) RETURN b.name",
+            "MATCH (a) RETURN a.colour",
+            "MATCH (a)<-[r]->(b) RETURN a",
+            "MATCH (a) WHERE a.name = 'x RETURN a",
+            "MATCH (a)-[a]->(b) RETURN a",
+        ] {
+            let err = compile(bad, 10).unwrap_err();
+            assert_eq!(error_code(&err), ErrorCode::InvalidParams, "{bad}");
+        }
+    }
+}
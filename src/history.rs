@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+use crate::daemon::find_git_dir;
+use crate::error::{coded, ErrorCode};
+use crate::model::{HistoryAuthor, HistoryCommit, LineHistory};
+
+/// Blame reports uncommitted lines against this all-zero commit id.
+const UNCOMMITTED: &str = "0000000000000000000000000000000000000000";
+
+/// Recent commits touching `start_line..=end_line` of `file_path` (newest
+/// first, at most `limit`) plus who last changed each of those lines today.
+pub fn line_history(
+    repo_root: &Path,
+    file_path: &str,
+    start_line: i64,
+    end_line: i64,
+    limit: usize,
+) -> Result<LineHistory> {
+    if find_git_dir(repo_root).is_none() {
+        return Err(coded(
+            ErrorCode::InvalidParams,
+            format!("{} is not a git repository", repo_root.display()),
+        ));
+    }
+    let start_line = start_line.max(1);
+    let end_line = end_line.max(start_line);
+    let range = format!("{start_line},{end_line}");
+
+    let log = run_git(
+        repo_root,
+        &[
+            "log",
+            &format!("-L{range}:{file_path}"),
+            "--no-patch",
+            "--format=%H%x1f%an%x1f%aI%x1f%s",
+            &format!("-n{}", limit.max(1)),
+        ],
+    )?;
+    let commits = parse_log(&log);
+
+    let blame = run_git(
+        repo_root,
+        &["blame", "--line-porcelain", "-L", &range, "--", file_path],
+    )?;
+    let (authors, uncommitted_lines) = summarize_blame(&blame);
+
+    Ok(LineHistory {
+        file_path: file_path.to_string(),
+        start_line,
+        end_line,
+        commits,
+        authors,
+        uncommitted_lines,
+    })
+}
+
+fn run_git(repo_root: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo_root)
+        .args(args)
+        .output()
+        .context("failed to run git")?;
+    anyhow::ensure!(
+        output.status.success(),
+        "git {} failed: {}",
+        args.first().copied().unwrap_or_default(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn parse_log(raw: &str) -> Vec<HistoryCommit> {
+    raw.lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\u{1f}');
+            let commit = fields.next()?;
+            let author = fields.next()?;
+            let date = fields.next()?;
+            let summary = fields.next()?;
+            Some(HistoryCommit {
+                commit: commit.to_string(),
+                author: author.to_string(),
+                date: date.to_string(),
+                summary: summary.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Folds `git blame --line-porcelain` output into per-author line counts,
+/// most lines first. Uncommitted lines are counted separately.
+fn summarize_blame(raw: &str) -> (Vec<HistoryAuthor>, usize) {
+    let mut by_author: HashMap<String, (usize, i64)> = HashMap::new();
+    let mut uncommitted = 0;
+    let mut commit = "";
+    let mut author = "";
+    let mut time = 0_i64;
+    for line in raw.lines() {
+        if line.starts_with('\t') {
+            if commit == UNCOMMITTED {
+                uncommitted += 1;
+            } else {
+                let entry = by_author.entry(author.to_string()).or_default();
+                entry.0 += 1;
+                entry.1 = entry.1.max(time);
+            }
+        } else if let Some(value) = line.strip_prefix("author ") {
+            author = value;
+        } else if let Some(value) = line.strip_prefix("author-time ") {
+            time = value.parse().unwrap_or_default();
+        } else if line.len() > 40 && line.as_bytes()[40] == b' ' {
+            commit = &line[..40];
+        }
+    }
+
+    let mut authors = by_author
+        .into_iter()
+        .map(|(author, (lines, time))| HistoryAuthor {
+            author,
+            lines,
+            last_changed: unix_to_utc(time),
+        })
+        .collect::<Vec<_>>();
+    authors.sort_by(|left, right| {
+        right
+            .lines
+            .cmp(&left.lines)
+            .then_with(|| right.last_changed.cmp(&left.last_changed))
+            .then_with(|| left.author.cmp(&right.author))
+    });
+    (authors, uncommitted)
+}
+
+/// Formats unix seconds as `YYYY-MM-DDTHH:MM:SSZ`.
+fn unix_to_utc(secs: i64) -> String {
+    let days = secs.div_euclid(86_400);
+    let rem = secs.rem_euclid(86_400);
+    // Civil-from-days, after Howard Hinnant's date algorithms.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unix_to_utc_handles_epoch_and_leap_days() {
+        assert_eq!(unix_to_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(unix_to_utc(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(unix_to_utc(1_700_000_000), "2023-11-14T22:13:20Z");
+    }
+
+    #[test]
+    fn summarize_blame_counts_lines_per_author() {
+        let raw = "\
+aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa 1 1 2
+author Ada
+author-time 100
+\tfn a() {
+aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa 2 2
+author Ada
+author-time 100
+\t}
+bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb 3 3 1
+author Linus
+author-time 200
+\tfn b() {}
+0000000000000000000000000000000000000000 4 4 1
+author Not Committed Yet
+author-time 300
+\t// wip
+";
+        let (authors, uncommitted) = summarize_blame(raw);
+        assert_eq!(uncommitted, 1);
+        assert_eq!(authors.len(), 2);
+        assert_eq!((authors[0].author.as_str(), authors[0].lines), ("Ada", 2));
+        assert_eq!(authors[1].last_changed, "1970-01-01T00:03:20Z");
+    }
+}
@@ -0,0 +1,136 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use crate::fileops::glob_to_regex;
+
+/// Comma-separated ignore globs applied on top of the built-in ignored
+/// directories, e.g. `LUMORA_IGNORE=proto_gen/,*.pb.go`.
+pub const IGNORE_ENV: &str = "LUMORA_IGNORE";
+
+/// User-supplied paths to leave out of indexing, watching, and file tools.
+///
+/// Patterns follow `.gitignore` loosely: one without a `/` matches any single
+/// path component (`*.pb.go`, `proto_gen`), one with a `/` matches from the
+/// repo root (`src/gen/*`), and a trailing `/` restricts it to directories.
+/// A matching directory excludes everything below it.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreGlobs {
+    patterns: Vec<IgnorePattern>,
+}
+
+#[derive(Debug, Clone)]
+struct IgnorePattern {
+    regex: Regex,
+    anchored: bool,
+    dir_only: bool,
+}
+
+impl IgnoreGlobs {
+    pub fn new(globs: &[String]) -> Result<Self> {
+        let mut patterns = Vec::new();
+        for glob in globs {
+            let glob = glob.trim().replace('\\', "/");
+            let dir_only = glob.ends_with('/');
+            let glob = glob.trim_matches('/');
+            if glob.is_empty() {
+                continue;
+            }
+            patterns.push(IgnorePattern {
+                regex: glob_to_regex(glob)?,
+                anchored: glob.contains('/'),
+                dir_only,
+            });
+        }
+        Ok(Self { patterns })
+    }
+
+    /// `globs` plus whatever [`IGNORE_ENV`] lists.
+    pub fn with_env(globs: &[String]) -> Result<Self> {
+        let mut all: Vec<String> = std::env::var(IGNORE_ENV)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::to_string)
+            .collect();
+        all.extend(globs.iter().cloned());
+        Self::new(&all)
+    }
+
+    /// Whether the repo-relative `rel_path`, or any directory above it, is
+    /// ignored.
+    pub fn is_ignored(&self, rel_path: &str, is_dir: bool) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let rel_path = rel_path.replace('\\', "/");
+        let components: Vec<&str> = rel_path
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .collect();
+        for (index, component) in components.iter().enumerate() {
+            let prefix = components[..=index].join("/");
+            let entry_is_dir = is_dir || index + 1 < components.len();
+            let matched = self.patterns.iter().any(|pattern| {
+                (entry_is_dir || !pattern.dir_only)
+                    && pattern
+                        .regex
+                        .is_match(if pattern.anchored { &prefix } else { component })
+            });
+            if matched {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// Patterns from the repo root's `.gitignore`, for tools that should skip
+/// what git skips. Negated (`!`) patterns are not supported and are dropped;
+/// nested `.gitignore` files are not read.
+pub fn gitignore_globs(repo_root: &Path) -> Result<Vec<String>> {
+    let path = repo_root.join(".gitignore");
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", path.display())),
+    };
+    Ok(raw
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+        .map(str::to_string)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn globs(patterns: &[&str]) -> IgnoreGlobs {
+        IgnoreGlobs::new(&patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn component_patterns_match_at_any_depth() {
+        let ignore = globs(&["proto_gen/", "*.pb.go"]);
+        assert!(ignore.is_ignored("proto_gen", true));
+        assert!(ignore.is_ignored("api/proto_gen/types.rs", false));
+        assert!(ignore.is_ignored("svc/api.pb.go", false));
+        assert!(!ignore.is_ignored("svc/api.go", false));
+        assert!(
+            !ignore.is_ignored("docs/proto_gen", false),
+            "a trailing slash only matches directories"
+        );
+    }
+
+    #[test]
+    fn patterns_with_a_slash_are_anchored_at_the_root() {
+        let ignore = globs(&["src/gen/*"]);
+        assert!(ignore.is_ignored("src/gen/a.rs", false));
+        assert!(ignore.is_ignored("src/gen/deep/b.rs", false));
+        assert!(!ignore.is_ignored("lib/src/gen/a.rs", false));
+        assert!(!globs(&[]).is_ignored("anything.rs", false));
+    }
+}
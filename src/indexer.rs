@@ -1,14 +1,24 @@
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
 use serde::Serialize;
 use sha2::{Digest, Sha256};
 use walkdir::WalkDir;
 
-use crate::model::{FileExtraction, LanguageKind};
-use crate::parser::{detect_language, parse_file};
+use crate::codeowners::CodeOwners;
+use crate::config::{load_config, FingerprintConfig, ImportsConfig};
+use crate::embeddings::{build_file_embeddings, Embedder};
+use crate::error::{coded, ErrorCode};
+use crate::ignore::IgnoreGlobs;
+use crate::languages::language_configs;
+use crate::low_signal::LowSignalNames;
+use crate::model::{FileExtraction, Fingerprint, LanguageKind, PackageInfo, UnsupportedExtension};
+use crate::packages::{discover_packages, CARGO_MANIFEST_FILE_NAME, NPM_MANIFEST_FILE_NAME};
+use crate::parser::{detect_language, parse_file, parse_file_cached, TreeCache};
 use crate::paths::STATE_DIR_NAME;
 use crate::storage::{GraphStore, UpsertOutcome};
 
@@ -42,9 +52,108 @@ const IGNORE_DIRS: &[&str] = &[
     STATE_DIR_NAME,
 ];
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct IndexOptions {
     pub full: bool,
+    /// `Some(true)` turns on embedding generation and `Some(false)` turns it
+    /// off and drops stored vectors; `None` keeps the remembered setting.
+    pub embeddings: Option<bool>,
+    /// Known `(old, new)` relative path renames to move in place before indexing.
+    pub renames: Vec<(String, String)>,
+    /// Overrides how clone fingerprints are computed; `None` keeps the mode the
+    /// index was built with.
+    pub fingerprint_mode: Option<FingerprintMode>,
+    /// Extra paths to leave out, on top of `LUMORA_IGNORE`; see [`IgnoreGlobs`].
+    /// Files already indexed under them are dropped as removed.
+    pub ignore_globs: Vec<String>,
+    /// Turns `external` entities for names defined outside the repo on or
+    /// off; `None` keeps the stored setting.
+    pub external_entities: Option<bool>,
+    /// `None` uses `[fingerprints]` from `.lumora.toml` over the defaults.
+    pub fingerprint_params: Option<FingerprintParams>,
+    /// Restrict the run to these repo-relative directories or files. Indexed
+    /// files outside them are left untouched, not removed. Empty means all.
+    pub scope_paths: Vec<String>,
+    /// Restrict the run to these languages (`rust`, `python`, ...), by the
+    /// same names `query languages` reports. Empty means all.
+    pub scope_languages: Vec<String>,
+    /// Reindex exactly these repo-relative files without walking the repo;
+    /// the stored file list stands in for everything else, and a listed file
+    /// that no longer exists is removed. Takes the place of the scope above.
+    /// A run that has to rebuild every file still walks the whole repo.
+    pub files: Vec<String>,
+    /// Turns lazy mode on or off; `None` keeps the stored setting. A lazy
+    /// index only parses the paths it has been asked about (see
+    /// [`index_on_demand`]), and a run without `scope_paths` refreshes just
+    /// those. Turning it on for an index that already has files keeps the
+    /// whole repo covered.
+    pub lazy: Option<bool>,
+    /// Attach an [`IndexProfile`] of where the run spent its time.
+    pub profile: bool,
+}
+
+/// Text fed to clone fingerprinting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FingerprintMode {
+    /// Source code with comments and string literals blanked out.
+    #[default]
+    Code,
+    /// The raw file text, comments and strings included.
+    Raw,
+}
+
+impl FingerprintMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Code => "code",
+            Self::Raw => "raw",
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "code" => Some(Self::Code),
+            "raw" => Some(Self::Raw),
+            _ => None,
+        }
+    }
+}
+
+/// Hashes from different parameters are not comparable, so each file records
+/// the ones it was indexed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FingerprintParams {
+    pub k_gram: usize,
+    pub window: usize,
+    pub min_tokens: usize,
+}
+
+impl Default for FingerprintParams {
+    fn default() -> Self {
+        Self {
+            k_gram: 5,
+            window: 4,
+            min_tokens: 5,
+        }
+    }
+}
+
+impl FingerprintParams {
+    pub fn from_config(config: &FingerprintConfig) -> Self {
+        let defaults = Self::default();
+        Self {
+            k_gram: config.k_gram.unwrap_or(defaults.k_gram),
+            window: config.window.unwrap_or(defaults.window),
+            min_tokens: config.min_tokens.unwrap_or(defaults.min_tokens),
+        }
+    }
+
+    pub fn as_meta(self) -> String {
+        format!(
+            "k_gram={},window={},min_tokens={}",
+            self.k_gram, self.window, self.min_tokens
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -53,8 +162,134 @@ pub struct IndexReport {
     pub indexed_files: usize,
     pub skipped_files: usize,
     pub removed_files: usize,
+    pub renamed_files: usize,
+    /// Files whose extraction was reused from the content-addressed parse cache.
+    pub cached_parses: usize,
+    /// Files reparsed incrementally from a tree kept by the watcher.
+    pub incremental_parses: usize,
     pub parse_failures: usize,
     pub errors: Vec<String>,
+    /// Repo-relative paths whose graph rows were written, removed, or moved
+    /// by this run, sorted. Left out of JSON reports; the watcher forwards it
+    /// to the event log.
+    #[serde(skip)]
+    pub changed_paths: Vec<String>,
+    /// Timings, when [`IndexOptions::profile`] asked for them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<IndexProfile>,
+}
+
+/// Slowest files listed in an [`IndexProfile`].
+const PROFILE_SLOWEST_FILES: usize = 20;
+
+/// Where an index run spent its time, in milliseconds. Phase totals cover
+/// every file the run read; per-file and per-language rows only count files
+/// that were actually re-indexed.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IndexProfile {
+    pub total_ms: f64,
+    pub read_ms: f64,
+    /// Parse-cache lookups, tree-sitter parses, and import resolution.
+    pub parse_ms: f64,
+    pub fingerprint_ms: f64,
+    /// SQLite writes of the file's entities, edges, and fingerprints.
+    pub store_ms: f64,
+    pub embeddings_ms: f64,
+    /// Slowest language first.
+    pub languages: Vec<LanguageTiming>,
+    /// Up to 20 files, slowest first.
+    pub slowest_files: Vec<FileTiming>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageTiming {
+    pub language: String,
+    pub files: usize,
+    pub parse_ms: f64,
+    pub fingerprint_ms: f64,
+    pub store_ms: f64,
+    pub total_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileTiming {
+    pub path: String,
+    pub language: String,
+    pub parse_ms: f64,
+    pub fingerprint_ms: f64,
+    pub store_ms: f64,
+    pub total_ms: f64,
+}
+
+/// Collects phase durations while files are indexed.
+#[derive(Default)]
+struct Profiler {
+    read: Duration,
+    parse: Duration,
+    fingerprint: Duration,
+    store: Duration,
+    embeddings: Duration,
+    files: Vec<FileTiming>,
+}
+
+impl Profiler {
+    fn record_file(
+        &mut self,
+        path: &str,
+        language: LanguageKind,
+        parse: Duration,
+        fingerprint: Duration,
+        store: Duration,
+    ) {
+        self.files.push(FileTiming {
+            path: path.to_string(),
+            language: language.as_str().to_string(),
+            parse_ms: millis(parse),
+            fingerprint_ms: millis(fingerprint),
+            store_ms: millis(store),
+            total_ms: millis(parse + fingerprint + store),
+        });
+    }
+
+    fn finish(mut self, total: Duration) -> IndexProfile {
+        let mut languages: BTreeMap<String, LanguageTiming> = BTreeMap::new();
+        for file in &self.files {
+            let entry = languages
+                .entry(file.language.clone())
+                .or_insert_with(|| LanguageTiming {
+                    language: file.language.clone(),
+                    files: 0,
+                    parse_ms: 0.0,
+                    fingerprint_ms: 0.0,
+                    store_ms: 0.0,
+                    total_ms: 0.0,
+                });
+            entry.files += 1;
+            entry.parse_ms += file.parse_ms;
+            entry.fingerprint_ms += file.fingerprint_ms;
+            entry.store_ms += file.store_ms;
+            entry.total_ms += file.total_ms;
+        }
+        let mut languages: Vec<_> = languages.into_values().collect();
+        languages.sort_by(|left, right| right.total_ms.total_cmp(&left.total_ms));
+        self.files
+            .sort_by(|left, right| right.total_ms.total_cmp(&left.total_ms));
+        self.files.truncate(PROFILE_SLOWEST_FILES);
+        IndexProfile {
+            total_ms: millis(total),
+            read_ms: millis(self.read),
+            parse_ms: millis(self.parse),
+            fingerprint_ms: millis(self.fingerprint),
+            store_ms: millis(self.store),
+            embeddings_ms: millis(self.embeddings),
+            languages,
+            slowest_files: self.files,
+        }
+    }
+}
+
+pub(crate) fn millis(duration: Duration) -> f64 {
+    (duration.as_secs_f64() * 1_000_000.0).round() / 1_000.0
 }
 
 pub fn index_repository(
@@ -62,102 +297,539 @@ pub fn index_repository(
     repo_root: &Path,
     options: IndexOptions,
 ) -> Result<IndexReport> {
+    index_with_trees(store, repo_root, options, None)
+}
+
+/// Reindexes the files a write just touched, as `IndexOptions::files` does.
+/// Paths lumora would not index, and missing paths that were never indexed,
+/// are passed over rather than failing; `None` means nothing needed it,
+/// including when the repo has no index yet.
+pub fn refresh_files(
+    store: &mut GraphStore,
+    repo_root: &Path,
+    paths: &[String],
+) -> Result<Option<IndexReport>> {
+    let tracked = store.tracked_files()?;
+    if tracked.is_empty() {
+        return Ok(None);
+    }
+    let ignore = IgnoreGlobs::with_env(&[])?;
+    let scope = IndexScope::new(repo_root, paths, &[])?;
+    let files: Vec<String> = scope
+        .paths
+        .into_iter()
+        .filter(|rel_path| {
+            let abs_path = repo_root.join(rel_path);
+            if abs_path.is_file() {
+                FileKind::classify(&abs_path).is_some() && !walk_skips(rel_path, &ignore)
+            } else {
+                tracked.contains(rel_path)
+            }
+        })
+        .collect();
+    if files.is_empty() {
+        return Ok(None);
+    }
+    let options = IndexOptions {
+        files,
+        ..Default::default()
+    };
+    index_repository(store, repo_root, options).map(Some)
+}
+
+/// Parses `path` (a repo-relative directory or file) on demand when the index
+/// is lazy and has not covered it yet. Returns `None` when there was nothing
+/// to do: the index is not lazy, the path is already covered, or it does not
+/// exist.
+pub fn index_on_demand(
+    store: &mut GraphStore,
+    repo_root: &Path,
+    path: &str,
+) -> Result<Option<IndexReport>> {
+    let Some(coverage) = store.lazy_coverage()? else {
+        return Ok(None);
+    };
+    let scope = IndexScope::new(repo_root, &[path.to_string()], &[])?;
+    let path = scope.paths.first().map_or(".", String::as_str);
+    if lazy_covers(&coverage, path) || !repo_root.join(path).exists() {
+        return Ok(None);
+    }
+    let options = IndexOptions {
+        scope_paths: vec![path.to_string()],
+        ..Default::default()
+    };
+    index_repository(store, repo_root, options).map(Some)
+}
+
+/// Whether `path` falls under one of the lazily covered paths.
+fn lazy_covers(coverage: &[String], path: &str) -> bool {
+    coverage.iter().any(|covered| {
+        covered == "."
+            || path
+                .strip_prefix(covered.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    })
+}
+
+/// Adds `path` to the coverage list, dropping entries it now subsumes.
+fn add_lazy_coverage(coverage: &mut Vec<String>, path: &str) {
+    if lazy_covers(coverage, path) {
+        return;
+    }
+    coverage.retain(|covered| !lazy_covers(&[path.to_string()], covered));
+    coverage.push(path.to_string());
+    coverage.sort();
+}
+
+/// [`index_repository`] for long-running callers: changed files are reparsed
+/// incrementally against the trees kept in `trees` from earlier runs.
+pub fn index_repository_with_trees(
+    store: &mut GraphStore,
+    repo_root: &Path,
+    options: IndexOptions,
+    trees: &mut TreeCache,
+) -> Result<IndexReport> {
+    index_with_trees(store, repo_root, options, Some(trees))
+}
+
+fn index_with_trees(
+    store: &mut GraphStore,
+    repo_root: &Path,
+    options: IndexOptions,
+    mut trees: Option<&mut TreeCache>,
+) -> Result<IndexReport> {
+    let started = Instant::now();
+    let mut profiler = Profiler::default();
     let mut outcome = UpsertOutcome::new();
     let mut errors = Vec::new();
 
-    let files = discover_files(repo_root)?;
-    let current_paths: HashSet<String> = files.iter().map(|item| item.rel_path.clone()).collect();
+    // One transaction for the whole run; orphan cleanup happens once at the end.
+    let mut store = store.begin_index_session()?;
 
-    let tracked = store.tracked_files()?;
-    let mut removed: Vec<String> = if options.full {
-        tracked.iter().cloned().collect()
+    // Enabling embeddings on an existing index forces one full pass so every
+    // unchanged file gets vectors too. Like a fingerprint mode change below,
+    // that pass covers the whole repo whatever scope was asked for.
+    let mut options = options;
+    let mut scope = if options.files.is_empty() {
+        IndexScope::new(repo_root, &options.scope_paths, &options.scope_languages)?
     } else {
-        tracked
-            .iter()
-            .filter(|old_path| !current_paths.contains(*old_path))
-            .cloned()
-            .collect()
+        IndexScope::new(repo_root, &options.files, &[])?
+    };
+    let has_index = !store.tracked_files()?.is_empty();
+    let mut embeddings_enabled = store.embeddings_enabled()?;
+    match options.embeddings {
+        Some(true) if !embeddings_enabled => {
+            // Fail before recording the setting if the model cannot run here.
+            Embedder::shared()?;
+            store.set_embeddings_enabled(true)?;
+            embeddings_enabled = true;
+            options.full = true;
+            if has_index {
+                scope = IndexScope::default();
+            }
+        }
+        Some(false) if embeddings_enabled => {
+            store.set_embeddings_enabled(false)?;
+            store.clear_embeddings()?;
+            embeddings_enabled = false;
+        }
+        _ => {}
+    }
+    let embedder = if embeddings_enabled {
+        match Embedder::shared() {
+            Ok(embedder) => Some(embedder),
+            Err(err) => {
+                errors.push(format!(
+                    "embeddings: {err:#}; changed files lose their vectors until the model loads or `--no-embeddings` is set"
+                ));
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Fingerprints from different modes are not comparable, so a mode change
+    // (including the first run on an index that predates the setting) rebuilds
+    // every file.
+    let stored_mode = store
+        .fingerprint_mode()?
+        .and_then(|raw| FingerprintMode::parse(&raw));
+    let fingerprint_mode = options.fingerprint_mode.or(stored_mode).unwrap_or_default();
+    if stored_mode != Some(fingerprint_mode) {
+        store.set_fingerprint_mode(fingerprint_mode.as_str())?;
+        options.full = true;
+        if has_index {
+            scope = IndexScope::default();
+        }
+    }
+
+    let config = load_config(repo_root)?;
+    store.set_low_signal_names(LowSignalNames::from_config(&config.low_signal));
+    let fingerprint_params = match options.fingerprint_params {
+        Some(params) => params,
+        None => FingerprintParams::from_config(&config.fingerprints),
     };
+    if fingerprint_params.k_gram == 0 || fingerprint_params.window == 0 {
+        return Err(coded(
+            ErrorCode::InvalidParams,
+            "fingerprint `k_gram` and `window` must be at least 1",
+        ));
+    }
+    let params_meta = fingerprint_params.as_meta();
+    if store.fingerprint_params()?.as_deref() != Some(params_meta.as_str()) {
+        store.set_fingerprint_params(&params_meta)?;
+    }
+
+    if let Some(enabled) = options.external_entities {
+        if enabled != store.external_entities_enabled()? {
+            store.set_external_entities_enabled(enabled)?;
+        }
+    }
+
+    let lazy_coverage = match options.lazy {
+        Some(false) => None,
+        Some(true) => Some(store.lazy_coverage()?.unwrap_or_else(|| {
+            if has_index {
+                vec![".".to_string()]
+            } else {
+                Vec::new()
+            }
+        })),
+        None => store.lazy_coverage()?,
+    };
+    let walk = options.files.is_empty() || scope.is_everything();
+    if let Some(mut coverage) = lazy_coverage {
+        if options.files.is_empty()
+            && options.scope_languages.is_empty()
+            && !options.scope_paths.is_empty()
+        {
+            if scope.paths.is_empty() {
+                add_lazy_coverage(&mut coverage, ".");
+            } else {
+                for path in &scope.paths {
+                    add_lazy_coverage(&mut coverage, path);
+                }
+            }
+        }
+        store.set_lazy_coverage(Some(&coverage))?;
+        // An unscoped run, or one widened above, refreshes what is covered
+        // rather than parsing the whole repo.
+        if scope.is_everything() && !lazy_covers(&coverage, ".") {
+            if coverage.is_empty() {
+                store.finish()?;
+                return Ok(IndexReport {
+                    repo_root: normalize_rel_path(repo_root),
+                    indexed_files: 0,
+                    skipped_files: 0,
+                    removed_files: 0,
+                    renamed_files: 0,
+                    cached_parses: 0,
+                    incremental_parses: 0,
+                    parse_failures: 0,
+                    errors: Vec::new(),
+                    changed_paths: Vec::new(),
+                    profile: options.profile.then(|| profiler.finish(started.elapsed())),
+                });
+            }
+            scope.paths = coverage;
+        }
+    } else if options.lazy == Some(false) {
+        store.set_lazy_coverage(None)?;
+    }
+
+    let ignore = IgnoreGlobs::with_env(&options.ignore_globs)?;
+    let (mut files, current_paths) = if walk {
+        let files = discover_files(repo_root, &ignore)?;
+        let current_paths: HashSet<String> =
+            files.iter().map(|item| item.rel_path.clone()).collect();
+        (files, current_paths)
+    } else {
+        listed_files(repo_root, &scope.paths, store.tracked_files()?, &ignore)?
+    };
+    if !scope.is_everything() {
+        files.retain(|file| scope.contains(&file.rel_path));
+    }
+
+    let packages = discover_packages(repo_root, &current_paths);
+    let workspace =
+        WorkspacePackages::new(&packages, &current_paths).with_imports_config(&config.imports);
+
+    let mut renamed_files = 0;
+    let mut cached_parses = 0;
+    let mut incremental_parses = 0;
+    let mut changed_paths = BTreeSet::new();
+    if !options.full {
+        for (old_path, new_path) in &options.renames {
+            let Some(file) = files.iter().find(|file| &file.rel_path == new_path) else {
+                continue;
+            };
+            if current_paths.contains(old_path) {
+                continue;
+            }
+            match apply_rename(&mut store, repo_root, &workspace, file, old_path) {
+                Ok(true) => {
+                    renamed_files += 1;
+                    changed_paths.insert(old_path.clone());
+                    changed_paths.insert(new_path.clone());
+                }
+                Ok(false) => {}
+                Err(err) => errors.push(format!("{old_path} -> {new_path}: rename failed: {err}")),
+            }
+        }
+    }
+
+    let tracked = store.tracked_files()?;
+    let mut removed: Vec<String> = tracked
+        .iter()
+        .filter(|old_path| options.full || !current_paths.contains(*old_path))
+        .filter(|old_path| scope.contains(old_path))
+        .cloned()
+        .collect();
     removed.sort();
 
     if !removed.is_empty() {
         store.remove_files(&removed, &mut outcome)?;
+        changed_paths.extend(removed.iter().cloned());
     }
+    store.retain_index_errors(&current_paths)?;
+    // Fingerprints written before line spans were stored can't locate clone
+    // regions; reindex those files even if their content is unchanged.
+    let missing_fingerprint_lines = store.files_missing_fingerprint_lines()?;
 
     for file in files {
+        let read_started = Instant::now();
+        // Unchanged size and mtime mean unchanged content; skip reading the
+        // file at all. Anything else falls through to hashing.
+        let metadata = fs::metadata(&file.abs_path).ok();
+        let mtime_ns = metadata.as_ref().and_then(trusted_mtime_ns);
+        if !options.full && !missing_fingerprint_lines.contains(&file.rel_path) {
+            if let (Some(metadata), Some(mtime_ns)) = (&metadata, mtime_ns) {
+                if store.tracked_file_stat(&file.rel_path)? == Some((metadata.len(), mtime_ns)) {
+                    profiler.read += read_started.elapsed();
+                    store.clear_index_error(&file.rel_path)?;
+                    outcome.skipped += 1;
+                    continue;
+                }
+            }
+        }
         let content = match fs::read_to_string(&file.abs_path) {
             Ok(content) => content,
             Err(err) => {
+                store.record_index_error(&file.rel_path, "read", &err.to_string())?;
                 errors.push(format!("{}: failed to read file: {err}", file.rel_path));
                 continue;
             }
         };
 
         let hash = sha256_hex(content.as_bytes());
+        profiler.read += read_started.elapsed();
         if !options.full {
             if let Some(existing_hash) = store.tracked_file_hash(&file.rel_path)? {
-                if existing_hash == hash {
+                if existing_hash == hash && !missing_fingerprint_lines.contains(&file.rel_path) {
+                    // Touched but not edited: remember the new mtime.
+                    store.set_file_mtime(&file.rel_path, mtime_ns)?;
+                    store.clear_index_error(&file.rel_path)?;
                     outcome.skipped += 1;
                     continue;
                 }
             }
         }
 
+        let parse_started = Instant::now();
         let extraction = match file.kind {
-            FileKind::Source(_language) => match parse_file(&file.abs_path, &content) {
-                Ok(Some(extraction)) => extraction,
-                Ok(None) => {
-                    outcome.skipped += 1;
-                    continue;
-                }
-                Err(err) => {
-                    errors.push(format!("{}: parse failed: {err}", file.rel_path));
-                    continue;
+            FileKind::Source(language) => {
+                match store.cached_extraction(&hash, language.as_str())? {
+                    Some(extraction) => {
+                        cached_parses += 1;
+                        extraction
+                    }
+                    None => match parse_source(&file.abs_path, &content, trees.as_deref_mut()) {
+                        Ok(Some((extraction, incremental))) => {
+                            incremental_parses += usize::from(incremental);
+                            store.cache_extraction(&hash, language.as_str(), &extraction)?;
+                            extraction
+                        }
+                        Ok(None) => {
+                            store.clear_index_error(&file.rel_path)?;
+                            outcome.skipped += 1;
+                            continue;
+                        }
+                        Err(err) => {
+                            store.record_index_error(&file.rel_path, "parse", &err.to_string())?;
+                            errors.push(format!("{}: parse failed: {err}", file.rel_path));
+                            continue;
+                        }
+                    },
                 }
-            },
+            }
             FileKind::Config(language) => FileExtraction {
                 language,
                 definitions: Vec::new(),
                 references: Vec::new(),
                 imports: Vec::new(),
+                relations: Vec::new(),
+                noise_spans: Vec::new(),
+                annotations: Vec::new(),
             },
         };
 
         let resolved_imports = resolve_imports(
             repo_root,
+            &workspace,
             &file.rel_path,
             extraction.language,
             &extraction.imports,
         );
-        let fingerprints = build_winnowed_fingerprints(&content, 5, 4);
+        let parse_time = parse_started.elapsed();
+        let fingerprint_started = Instant::now();
+        let fingerprint_text = match (fingerprint_mode, file.kind) {
+            (FingerprintMode::Code, FileKind::Source(_)) => {
+                Cow::Owned(blank_spans(&content, &extraction.noise_spans))
+            }
+            _ => Cow::Borrowed(content.as_str()),
+        };
+        let fingerprints = build_winnowed_fingerprints(
+            &fingerprint_text,
+            fingerprint_params.k_gram,
+            fingerprint_params.window,
+            fingerprint_params.min_tokens,
+        );
+        let fingerprint_time = fingerprint_started.elapsed();
 
+        let store_started = Instant::now();
         if let Err(err) = store.index_file(
             &file.rel_path,
             extraction.language.as_str(),
             &hash,
             content.len() as u64,
+            content.lines().count() as u64,
             &extraction,
             &fingerprints,
             &resolved_imports,
             &mut outcome,
         ) {
+            store.record_index_error(&file.rel_path, "write", &err.to_string())?;
             errors.push(format!("{}: index write failed: {err}", file.rel_path));
+            continue;
+        }
+        store.set_file_mtime(&file.rel_path, mtime_ns)?;
+        store.set_file_generated(&file.rel_path, has_generated_marker(&content))?;
+        store.clear_index_error(&file.rel_path)?;
+        changed_paths.insert(file.rel_path.clone());
+        let store_time = store_started.elapsed();
+        profiler.parse += parse_time;
+        profiler.fingerprint += fingerprint_time;
+        profiler.store += store_time;
+        profiler.record_file(
+            &file.rel_path,
+            extraction.language,
+            parse_time,
+            fingerprint_time,
+            store_time,
+        );
+
+        if embeddings_enabled {
+            let embeddings_started = Instant::now();
+            let mut records = match embedder {
+                Some(_) => build_file_embeddings(&file.rel_path, &content, &extraction),
+                None => Vec::new(),
+            };
+            let written = match embedder.map(|embedder| embedder.embed_records(&mut records)) {
+                Some(Err(err)) => Err(err),
+                _ => store.replace_file_embeddings(&file.rel_path, &records),
+            };
+            if let Err(err) = written {
+                errors.push(format!("{}: embedding failed: {err:#}", file.rel_path));
+            }
+            profiler.embeddings += embeddings_started.elapsed();
+        }
+    }
+
+    match CodeOwners::load(repo_root) {
+        Ok(codeowners) => {
+            let owners = current_paths
+                .iter()
+                .map(|path| (path.clone(), codeowners.owners_for(path).to_vec()))
+                .collect();
+            store.sync_file_owners(&owners)?;
         }
+        Err(err) => errors.push(format!("CODEOWNERS: {err:#}")),
     }
+    store.sync_packages(&packages)?;
+
+    store.finish()?;
 
     Ok(IndexReport {
         repo_root: normalize_rel_path(repo_root),
         indexed_files: outcome.updated,
         skipped_files: outcome.skipped,
         removed_files: outcome.removed,
+        renamed_files,
+        cached_parses,
+        incremental_parses,
         parse_failures: errors
             .iter()
             .filter(|msg| msg.contains("parse failed"))
             .count(),
         errors,
+        changed_paths: changed_paths.into_iter().collect(),
+        profile: options.profile.then(|| profiler.finish(started.elapsed())),
     })
 }
 
+fn parse_source(
+    path: &Path,
+    source: &str,
+    trees: Option<&mut TreeCache>,
+) -> Result<Option<(FileExtraction, bool)>> {
+    match trees {
+        Some(trees) => parse_file_cached(path, source, trees),
+        None => Ok(parse_file(path, source)?.map(|extraction| (extraction, false))),
+    }
+}
+
+/// Moves an already-indexed file to its new path and re-resolves its imports
+/// from the new location, so unchanged content is not reparsed.
+fn apply_rename(
+    store: &mut GraphStore,
+    repo_root: &Path,
+    workspace: &WorkspacePackages,
+    file: &CandidateFile,
+    old_path: &str,
+) -> Result<bool> {
+    if !store.rename_file(old_path, &file.rel_path)? {
+        return Ok(false);
+    }
+
+    let language = match file.kind {
+        FileKind::Source(language) | FileKind::Config(language) => language,
+    };
+    if let Some((_, imports)) = store.file_imports(&file.rel_path)? {
+        let resolved = resolve_imports(repo_root, workspace, &file.rel_path, language, &imports);
+        store.replace_resolved_imports(&file.rel_path, &resolved)?;
+    }
+    Ok(true)
+}
+
+/// A file modified this recently may change again within the same mtime
+/// tick, after it was hashed; its mtime is not recorded, so the next run
+/// hashes it again.
+const RACY_MTIME_WINDOW: Duration = Duration::from_secs(2);
+
+/// Modification time in nanoseconds since the epoch, or `None` when it is
+/// unavailable or too recent to vouch for the content (see
+/// [`RACY_MTIME_WINDOW`]).
+fn trusted_mtime_ns(metadata: &fs::Metadata) -> Option<i64> {
+    let modified = metadata.modified().ok()?;
+    let age = SystemTime::now().duration_since(modified).ok()?;
+    if age < RACY_MTIME_WINDOW {
+        return None;
+    }
+    let since_epoch = modified.duration_since(UNIX_EPOCH).ok()?;
+    i64::try_from(since_epoch.as_nanos()).ok()
+}
+
 #[derive(Debug, Clone)]
 struct CandidateFile {
     abs_path: PathBuf,
@@ -171,9 +843,220 @@ enum FileKind {
     Config(LanguageKind),
 }
 
-fn discover_files(repo_root: &Path) -> Result<Vec<CandidateFile>> {
+impl FileKind {
+    /// How a file at `path` would be indexed, or `None` if it would not be.
+    fn classify(path: &Path) -> Option<Self> {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        if INDEXABLE_CONFIG_FILES.contains(&file_name) {
+            return Some(Self::Config(config_language_hint(file_name)));
+        }
+        detect_language(path).map(Self::Source)
+    }
+
+    fn language(self) -> LanguageKind {
+        match self {
+            Self::Source(language) | Self::Config(language) => language,
+        }
+    }
+}
+
+fn discover_files(repo_root: &Path, ignore: &IgnoreGlobs) -> Result<Vec<CandidateFile>> {
     let mut files = Vec::new();
+    for (abs_path, rel_path) in walk_repo_files(repo_root, ignore)? {
+        if let Some(kind) = FileKind::classify(&abs_path) {
+            files.push(CandidateFile {
+                abs_path,
+                rel_path,
+                kind,
+            });
+        }
+    }
+
+    files.sort_by(|left, right| left.rel_path.cmp(&right.rel_path));
+    Ok(files)
+}
+
+/// Candidates for an explicit file list, checked the way the walker would
+/// check them, plus the paths the run treats as present: the stored file list
+/// with each listed path added, or dropped if it no longer exists.
+fn listed_files(
+    repo_root: &Path,
+    listed: &[String],
+    mut current_paths: HashSet<String>,
+    ignore: &IgnoreGlobs,
+) -> Result<(Vec<CandidateFile>, HashSet<String>)> {
+    let mut files: Vec<CandidateFile> = Vec::new();
+    for rel_path in listed {
+        let abs_path = repo_root.join(rel_path);
+        if abs_path.is_dir() {
+            return Err(coded(
+                ErrorCode::InvalidParams,
+                format!("`{rel_path}` is a directory; index it as a scope path instead"),
+            ));
+        }
+        if !abs_path.is_file() {
+            if !current_paths.remove(rel_path) {
+                return Err(coded(
+                    ErrorCode::FileNotFound,
+                    format!("`{rel_path}` does not exist and is not indexed"),
+                ));
+            }
+            continue;
+        }
+        let kind = FileKind::classify(&abs_path)
+            .filter(|_| !walk_skips(rel_path, ignore))
+            .ok_or_else(|| {
+                coded(
+                    ErrorCode::InvalidParams,
+                    format!("`{rel_path}` is not a file lumora indexes"),
+                )
+            })?;
+        current_paths.insert(rel_path.clone());
+        if files.iter().all(|file| &file.rel_path != rel_path) {
+            files.push(CandidateFile {
+                abs_path,
+                rel_path: rel_path.clone(),
+                kind,
+            });
+        }
+    }
+    files.sort_by(|left, right| left.rel_path.cmp(&right.rel_path));
+    Ok((files, current_paths))
+}
 
+/// Whether [`walk_repo_files`] would pass over `rel_path`: it sits under an
+/// ignored directory or it or a parent matches an `ignore` glob.
+fn walk_skips(rel_path: &str, ignore: &IgnoreGlobs) -> bool {
+    let parts: Vec<&str> = rel_path.split('/').collect();
+    (0..parts.len()).any(|index| {
+        let is_dir = index + 1 < parts.len();
+        (is_dir && IGNORE_DIRS.contains(&parts[index]))
+            || ignore.is_ignored(&parts[..=index].join("/"), is_dir)
+    })
+}
+
+/// The part of the repo one index run covers. Files outside it are neither
+/// indexed nor removed, so a scoped run leaves the rest of the graph as is.
+#[derive(Debug, Default)]
+struct IndexScope {
+    /// Repo-relative directories or files, without trailing slashes.
+    paths: Vec<String>,
+    languages: Vec<LanguageKind>,
+}
+
+impl IndexScope {
+    fn new(repo_root: &Path, paths: &[String], languages: &[String]) -> Result<Self> {
+        let mut scope = Self::default();
+        for raw in paths {
+            let path = Path::new(raw);
+            let rel = path.strip_prefix(repo_root).unwrap_or(path);
+            if rel.is_absolute()
+                || rel
+                    .components()
+                    .any(|part| matches!(part, Component::ParentDir))
+            {
+                return Err(coded(
+                    ErrorCode::InvalidParams,
+                    format!("index path `{raw}` must be inside the repository"),
+                ));
+            }
+            let rel = normalize_rel_path(rel);
+            let rel = rel.trim_start_matches("./").trim_matches('/');
+            if rel.is_empty() || rel == "." {
+                // The repo root covers everything; drop any narrower paths.
+                scope.paths.clear();
+                break;
+            }
+            scope.paths.push(rel.to_string());
+        }
+
+        let known = language_configs();
+        for raw in languages {
+            let name = raw.trim().to_ascii_lowercase();
+            let Some(config) = known.iter().find(|config| config.kind.as_str() == name) else {
+                let names = known
+                    .iter()
+                    .map(|config| config.kind.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                return Err(coded(
+                    ErrorCode::InvalidParams,
+                    format!("unknown language `{raw}`; expected one of: {names}"),
+                ));
+            };
+            scope.languages.push(config.kind);
+        }
+        Ok(scope)
+    }
+
+    fn is_everything(&self) -> bool {
+        self.paths.is_empty() && self.languages.is_empty()
+    }
+
+    fn contains(&self, rel_path: &str) -> bool {
+        let in_paths = self.paths.is_empty()
+            || self.paths.iter().any(|scope| {
+                rel_path
+                    .strip_prefix(scope.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            });
+        let in_languages = self.languages.is_empty()
+            || FileKind::classify(Path::new(rel_path))
+                .is_some_and(|kind| self.languages.contains(&kind.language()));
+        in_paths && in_languages
+    }
+}
+
+/// Files the walker visits but neither a grammar nor a config rule claims,
+/// grouped by extension (most common first) with up to `max_examples` paths.
+pub fn unsupported_files(
+    repo_root: &Path,
+    max_examples: usize,
+) -> Result<Vec<UnsupportedExtension>> {
+    let mut groups: BTreeMap<String, UnsupportedExtension> = BTreeMap::new();
+    let mut paths = walk_repo_files(repo_root, &IgnoreGlobs::with_env(&[])?)?;
+    paths.sort_by(|left, right| left.1.cmp(&right.1));
+    for (abs_path, rel_path) in paths {
+        let file_name = abs_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        if INDEXABLE_CONFIG_FILES.contains(&file_name) || detect_language(&abs_path).is_some() {
+            continue;
+        }
+        let extension = abs_path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let group = groups
+            .entry(extension.clone())
+            .or_insert_with(|| UnsupportedExtension {
+                extension,
+                files: 0,
+                examples: Vec::new(),
+            });
+        group.files += 1;
+        if group.examples.len() < max_examples {
+            group.examples.push(rel_path);
+        }
+    }
+
+    let mut out: Vec<_> = groups.into_values().collect();
+    out.sort_by(|left, right| {
+        right
+            .files
+            .cmp(&left.files)
+            .then_with(|| left.extension.cmp(&right.extension))
+    });
+    Ok(out)
+}
+
+/// Every regular file under `repo_root` outside the ignored directories and
+/// `ignore` globs, as `(absolute, repo-relative)` paths.
+fn walk_repo_files(repo_root: &Path, ignore: &IgnoreGlobs) -> Result<Vec<(PathBuf, String)>> {
     let walker = WalkDir::new(repo_root).into_iter().filter_entry(|entry| {
         let path = entry.path();
         let name = path
@@ -183,9 +1066,15 @@ fn discover_files(repo_root: &Path) -> Result<Vec<CandidateFile>> {
         if path.is_dir() && IGNORE_DIRS.contains(&name) {
             return false;
         }
-        true
+        match path.strip_prefix(repo_root) {
+            Ok(rel) if entry.depth() > 0 => {
+                !ignore.is_ignored(&normalize_rel_path(rel), entry.file_type().is_dir())
+            }
+            _ => true,
+        }
     });
 
+    let mut out = Vec::new();
     for entry in walker {
         let entry = entry?;
         if !entry.file_type().is_file() {
@@ -196,32 +1085,9 @@ fn discover_files(repo_root: &Path) -> Result<Vec<CandidateFile>> {
             .strip_prefix(repo_root)
             .with_context(|| format!("failed to strip repo prefix for {}", abs_path.display()))?;
         let rel_path = normalize_rel_path(rel);
-
-        let file_name = abs_path
-            .file_name()
-            .and_then(|name| name.to_str())
-            .unwrap_or_default()
-            .to_string();
-        if INDEXABLE_CONFIG_FILES.contains(&file_name.as_str()) {
-            files.push(CandidateFile {
-                abs_path,
-                rel_path,
-                kind: FileKind::Config(config_language_hint(&file_name)),
-            });
-            continue;
-        }
-
-        if let Some(lang) = detect_language(&abs_path) {
-            files.push(CandidateFile {
-                abs_path,
-                rel_path,
-                kind: FileKind::Source(lang),
-            });
-        }
+        out.push((abs_path, rel_path));
     }
-
-    files.sort_by(|left, right| left.rel_path.cmp(&right.rel_path));
-    Ok(files)
+    Ok(out)
 }
 
 fn config_language_hint(file_name: &str) -> LanguageKind {
@@ -237,29 +1103,489 @@ fn config_language_hint(file_name: &str) -> LanguageKind {
         "composer.json" => LanguageKind::Json,
         _ => LanguageKind::Json,
     }
-}
+}
+
+#[derive(Debug, Clone, Default)]
+struct WorkspacePackages {
+    /// Crate name as written in `use` paths (`-` becomes `_`) -> crate dir.
+    crates: BTreeMap<String, PathBuf>,
+    npm: BTreeMap<String, PathBuf>,
+    /// Maven/Gradle source set directories such as `app/src/main/java`,
+    /// where Java and Kotlin package paths start.
+    jvm_roots: BTreeSet<PathBuf>,
+    /// `[imports] include_dirs` from `.lumora.toml`, searched for C/C++
+    /// includes.
+    include_dirs: Vec<PathBuf>,
+}
+
+impl WorkspacePackages {
+    fn new(packages: &[PackageInfo], paths: &HashSet<String>) -> Self {
+        let mut workspace = Self {
+            jvm_roots: paths
+                .iter()
+                .filter_map(|path| jvm_source_root(path))
+                .collect(),
+            ..Self::default()
+        };
+        for package in packages {
+            let Some(name) = &package.name else {
+                continue;
+            };
+            let dir = match package.dir.as_str() {
+                "." => PathBuf::new(),
+                dir => PathBuf::from(dir),
+            };
+            if package.anchor.ends_with(CARGO_MANIFEST_FILE_NAME) {
+                workspace.crates.insert(name.replace('-', "_"), dir);
+            } else if package.anchor.ends_with(NPM_MANIFEST_FILE_NAME) {
+                workspace.npm.insert(name.clone(), dir);
+            }
+        }
+        workspace
+    }
+
+    fn with_imports_config(mut self, config: &ImportsConfig) -> Self {
+        self.include_dirs = config
+            .include_dirs
+            .iter()
+            .map(|dir| normalize_lexically(Path::new(dir.trim_matches('/'))))
+            .collect();
+        self
+    }
+
+    fn crate_root(&self, rel_path: &str) -> PathBuf {
+        self.crates
+            .values()
+            .filter(|dir| Path::new(rel_path).starts_with(dir))
+            .max_by_key(|dir| dir.components().count())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// JVM source roots, those sharing the most leading directories with
+    /// `rel_path` first so a module's own classes win over a same-named
+    /// class in another module.
+    fn jvm_roots_for(&self, rel_path: &str) -> Vec<&PathBuf> {
+        let mut roots: Vec<&PathBuf> = self.jvm_roots.iter().collect();
+        roots.sort_by_key(|root| {
+            std::cmp::Reverse(
+                root.components()
+                    .zip(Path::new(rel_path).components())
+                    .take_while(|(left, right)| left == right)
+                    .count(),
+            )
+        });
+        roots
+    }
+}
+
+/// The source set directory holding a `.java` or `.kt` file laid out as
+/// `<module>/src/<set>/java|kotlin/<package path>`.
+fn jvm_source_root(rel_path: &str) -> Option<PathBuf> {
+    if !(rel_path.ends_with(".java") || rel_path.ends_with(".kt")) {
+        return None;
+    }
+    let parts: Vec<&str> = rel_path.split('/').collect();
+    let dirs = &parts[..parts.len() - 1];
+    dirs.windows(3)
+        .position(|window| window[0] == "src" && matches!(window[2], "java" | "kotlin"))
+        .map(|start| dirs[..start + 3].iter().collect())
+}
+
+fn resolve_imports(
+    repo_root: &Path,
+    workspace: &WorkspacePackages,
+    rel_path: &str,
+    language: LanguageKind,
+    imports: &[crate::model::Import],
+) -> Vec<(String, String)> {
+    let mut out = Vec::new();
+    let go_module = match language {
+        LanguageKind::Go => find_go_module(repo_root, rel_path),
+        _ => None,
+    };
+    let is_js = matches!(
+        language,
+        LanguageKind::JavaScript | LanguageKind::TypeScript | LanguageKind::Tsx
+    );
+    let is_jvm = matches!(language, LanguageKind::Java | LanguageKind::Kotlin);
+    let ts_paths = if is_js && !imports.is_empty() {
+        find_ts_path_config(repo_root, rel_path)
+    } else {
+        None
+    };
+
+    for import_item in imports {
+        if let Some(go_module) = &go_module {
+            for resolved in go_package_files(repo_root, go_module, &import_item.module) {
+                out.push((import_item.module.clone(), resolved));
+            }
+            continue;
+        }
+        if is_js {
+            if let Some(resolved) = resolve_js_import(
+                repo_root,
+                workspace,
+                rel_path,
+                ts_paths.as_ref(),
+                &import_item.module,
+            ) {
+                out.push((import_item.module.clone(), resolved));
+            }
+            continue;
+        }
+        if is_jvm {
+            let name = jvm_import_name(&import_item.module);
+            if let Some(package) = name.strip_suffix(".*") {
+                let files = jvm_package_files(repo_root, workspace, rel_path, package);
+                if !files.is_empty() {
+                    for resolved in files {
+                        out.push((import_item.module.clone(), resolved));
+                    }
+                    continue;
+                }
+            }
+        }
+        if let Some(resolved) = resolve_single_import(
+            repo_root,
+            workspace,
+            rel_path,
+            language,
+            &import_item.module,
+        ) {
+            out.push((import_item.module.clone(), resolved));
+        }
+    }
+
+    out
+}
+
+/// The `module` path declared by the nearest `go.mod` and the repo-relative
+/// directory holding it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GoModule {
+    path: String,
+    root: PathBuf,
+}
+
+fn find_go_module(repo_root: &Path, rel_path: &str) -> Option<GoModule> {
+    let mut dir = Path::new(rel_path).parent();
+    while let Some(current) = dir {
+        if let Ok(content) = fs::read_to_string(repo_root.join(current).join("go.mod")) {
+            let path = parse_go_module_path(&content)?;
+            return Some(GoModule {
+                path,
+                root: current.to_path_buf(),
+            });
+        }
+        dir = current.parent();
+    }
+    None
+}
+
+fn parse_go_module_path(go_mod: &str) -> Option<String> {
+    go_mod.lines().find_map(|line| {
+        let line = line.split("//").next().unwrap_or_default().trim();
+        let path = line.strip_prefix("module")?;
+        if !path.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let path = path.trim().trim_matches(['"', '`']);
+        (!path.is_empty()).then(|| path.to_string())
+    })
+}
+
+/// Maps an import path inside the module to the package directory and
+/// returns its non-test `.go` files, since a Go package spans every file in
+/// the directory. Standard library and third-party imports resolve to nothing.
+fn go_package_files(repo_root: &Path, module: &GoModule, import_path: &str) -> Vec<String> {
+    let Some(rest) = import_path.strip_prefix(module.path.as_str()) else {
+        return Vec::new();
+    };
+    if !(rest.is_empty() || rest.starts_with('/')) {
+        return Vec::new();
+    }
+    let package_dir = module.root.join(rest.trim_start_matches('/'));
+    let Ok(entries) = fs::read_dir(repo_root.join(&package_dir)) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_type()
+                .map(|kind| kind.is_file())
+                .unwrap_or(false)
+        })
+        .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+        .filter(|name| name.ends_with(".go") && !name.ends_with("_test.go"))
+        .map(|name| normalize_rel_path(package_dir.join(name)))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Extensions tried, in order, for an extensionless JS/TS specifier and for
+/// `index` files inside a directory specifier.
+const JS_RESOLVE_EXTENSIONS: &[&str] =
+    &["ts", "tsx", "d.ts", "mts", "cts", "js", "jsx", "mjs", "cjs"];
+
+/// `compilerOptions.baseUrl` and `compilerOptions.paths` from the nearest
+/// `tsconfig.json` or `jsconfig.json`, with directories relative to the repo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TsPathConfig {
+    base_dir: PathBuf,
+    has_base_url: bool,
+    paths: Vec<(String, Vec<String>)>,
+}
+
+fn find_ts_path_config(repo_root: &Path, rel_path: &str) -> Option<TsPathConfig> {
+    let mut dir = Path::new(rel_path).parent();
+    while let Some(current) = dir {
+        for name in ["tsconfig.json", "jsconfig.json"] {
+            if let Ok(content) = fs::read_to_string(repo_root.join(current).join(name)) {
+                return parse_ts_path_config(&content, current);
+            }
+        }
+        dir = current.parent();
+    }
+    None
+}
+
+fn parse_ts_path_config(content: &str, config_dir: &Path) -> Option<TsPathConfig> {
+    let value: serde_json::Value = serde_json::from_str(&strip_jsonc(content)).ok()?;
+    let options = value.get("compilerOptions")?;
+    let base_url = options.get("baseUrl").and_then(|item| item.as_str());
+    let paths: Vec<(String, Vec<String>)> = options
+        .get("paths")
+        .and_then(|item| item.as_object())
+        .map(|paths| {
+            paths
+                .iter()
+                .map(|(pattern, targets)| {
+                    let targets = targets
+                        .as_array()
+                        .map(|items| {
+                            items
+                                .iter()
+                                .filter_map(|item| item.as_str().map(str::to_string))
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    (pattern.clone(), targets)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if base_url.is_none() && paths.is_empty() {
+        return None;
+    }
+    Some(TsPathConfig {
+        // Without `baseUrl`, `paths` targets are relative to the config file.
+        base_dir: normalize_lexically(&config_dir.join(base_url.unwrap_or("."))),
+        has_base_url: base_url.is_some(),
+        paths,
+    })
+}
+
+/// Drops `//` and `/* */` comments and trailing commas so tsconfig files,
+/// which are JSON with comments, parse as plain JSON.
+fn strip_jsonc(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    let mut in_string = false;
+    // Byte offset of the last comma in `out`, until a non-space token follows.
+    let mut pending_comma = None;
+    while let Some(ch) = chars.next() {
+        if in_string {
+            out.push(ch);
+            match ch {
+                '\\' => out.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (ch, chars.peek()) {
+            ('/', Some('/')) => while chars.next_if(|next| *next != '\n').is_some() {},
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = None;
+                for next in chars.by_ref() {
+                    if previous == Some('*') && next == '/' {
+                        break;
+                    }
+                    previous = Some(next);
+                }
+            }
+            _ if ch.is_whitespace() => out.push(ch),
+            _ => {
+                if let (Some(offset), '}' | ']') = (pending_comma.take(), ch) {
+                    out.remove(offset);
+                }
+                if ch == ',' {
+                    pending_comma = Some(out.len());
+                }
+                in_string = ch == '"';
+                out.push(ch);
+            }
+        }
+    }
+    out
+}
+
+/// Tries relative paths, tsconfig `paths`, `baseUrl`, then workspace packages.
+fn resolve_js_import(
+    repo_root: &Path,
+    workspace: &WorkspacePackages,
+    rel_path: &str,
+    ts_paths: Option<&TsPathConfig>,
+    specifier: &str,
+) -> Option<String> {
+    let specifier = specifier.trim();
+    if specifier.is_empty() {
+        return None;
+    }
+
+    if specifier.starts_with("./") || specifier.starts_with("../") || specifier == "." {
+        let base_dir = Path::new(rel_path)
+            .parent()
+            .unwrap_or_else(|| Path::new(""));
+        return probe_js_path(repo_root, &base_dir.join(specifier));
+    }
+
+    if let Some(config) = ts_paths {
+        if let Some(resolved) = resolve_ts_alias(repo_root, config, specifier) {
+            return Some(resolved);
+        }
+    }
+    resolve_workspace_package(repo_root, workspace, specifier)
+}
+
+fn resolve_workspace_package(
+    repo_root: &Path,
+    workspace: &WorkspacePackages,
+    specifier: &str,
+) -> Option<String> {
+    let (dir, subpath) = workspace.npm.iter().find_map(|(name, dir)| {
+        if specifier == name {
+            Some((dir, ""))
+        } else {
+            specifier
+                .strip_prefix(name.as_str())
+                .and_then(|rest| rest.strip_prefix('/'))
+                .map(|rest| (dir, rest))
+        }
+    })?;
+    if !subpath.is_empty() {
+        return probe_js_path(repo_root, &dir.join(subpath));
+    }
+    probe_js_path(repo_root, &dir.join("src/index"))
+        .or_else(|| probe_js_path(repo_root, &dir.join("index")))
+}
+
+fn resolve_ts_alias(repo_root: &Path, config: &TsPathConfig, specifier: &str) -> Option<String> {
+    let mut best: Option<(usize, String, &[String])> = None;
+    for (pattern, targets) in &config.paths {
+        let captured = match pattern.split_once('*') {
+            Some((prefix, suffix)) => specifier
+                .strip_prefix(prefix)
+                .and_then(|rest| rest.strip_suffix(suffix))
+                .map(|wildcard| (prefix.len(), wildcard.to_string())),
+            None => (pattern == specifier).then(|| (usize::MAX, String::new())),
+        };
+        // TypeScript prefers the pattern with the longest matching prefix.
+        if let Some((rank, wildcard)) = captured {
+            if best
+                .as_ref()
+                .map_or(true, |(best_rank, _, _)| rank > *best_rank)
+            {
+                best = Some((rank, wildcard, targets));
+            }
+        }
+    }
+    if let Some((_, wildcard, targets)) = best {
+        for target in targets {
+            let target = target.replacen('*', &wildcard, 1);
+            if let Some(resolved) = probe_js_path(repo_root, &config.base_dir.join(target)) {
+                return Some(resolved);
+            }
+        }
+    }
+
+    if config.has_base_url {
+        return probe_js_path(repo_root, &config.base_dir.join(specifier));
+    }
+    None
+}
+
+/// Tries a specifier path as written, with each known extension, with a
+/// compiled `.js`-style extension swapped for its TypeScript source, and as a
+/// directory `index` file. Paths escaping the repo never resolve.
+fn probe_js_path(repo_root: &Path, candidate: &Path) -> Option<String> {
+    let candidate = normalize_lexically(candidate);
+    if candidate.starts_with("..") {
+        return None;
+    }
+    let raw = normalize_rel_path(&candidate);
+    let raw = raw.trim_end_matches('/');
+
+    let mut candidates = vec![raw.to_string()];
+    for (compiled, sources) in [
+        (".js", &["ts", "tsx"][..]),
+        (".jsx", &["tsx"][..]),
+        (".mjs", &["mts"][..]),
+        (".cjs", &["cts"][..]),
+    ] {
+        if let Some(stem) = raw.strip_suffix(compiled) {
+            candidates.extend(sources.iter().map(|ext| format!("{stem}.{ext}")));
+        }
+    }
+    candidates.extend(
+        JS_RESOLVE_EXTENSIONS
+            .iter()
+            .map(|ext| format!("{raw}.{ext}")),
+    );
+    let index_prefix = if raw.is_empty() {
+        String::new()
+    } else {
+        format!("{raw}/")
+    };
+    candidates.extend(
+        JS_RESOLVE_EXTENSIONS
+            .iter()
+            .map(|ext| format!("{index_prefix}index.{ext}")),
+    );
 
-fn resolve_imports(
-    repo_root: &Path,
-    rel_path: &str,
-    language: LanguageKind,
-    imports: &[crate::model::Import],
-) -> Vec<(String, String)> {
-    let mut out = Vec::new();
+    candidates
+        .into_iter()
+        .find(|item| !item.is_empty() && repo_root.join(item).is_file())
+}
 
-    for import_item in imports {
-        if let Some(resolved) =
-            resolve_single_import(repo_root, rel_path, language, &import_item.module)
-        {
-            out.push((import_item.module.clone(), resolved));
+/// Collapses `.` and `..` components without touching the filesystem.
+/// Leading `..` components that climb above the start are kept.
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(out.components().next_back(), Some(Component::Normal(_))) {
+                    out.pop();
+                } else {
+                    out.push("..");
+                }
+            }
+            other => out.push(other),
         }
     }
-
     out
 }
 
 fn resolve_single_import(
     repo_root: &Path,
+    workspace: &WorkspacePackages,
     rel_path: &str,
     language: LanguageKind,
     module: &str,
@@ -270,7 +1596,27 @@ fn resolve_single_import(
     }
 
     let candidates: Vec<PathBuf> = match language {
-        LanguageKind::Rust => rust_import_candidates(normalized_module),
+        LanguageKind::Rust => {
+            // `crate::` paths start at the importing file's own crate; a path
+            // that starts with another workspace crate's name starts there.
+            let (head, rest) = normalized_module
+                .split_once("::")
+                .unwrap_or((normalized_module, ""));
+            match workspace.crates.get(head.trim()) {
+                Some(dir) => rust_import_candidates(rest)
+                    .into_iter()
+                    .map(|candidate| dir.join(candidate))
+                    .chain([dir.join("src/lib.rs")])
+                    .collect(),
+                None => {
+                    let root = workspace.crate_root(rel_path);
+                    rust_import_candidates(normalized_module)
+                        .into_iter()
+                        .map(|candidate| root.join(candidate))
+                        .collect()
+                }
+            }
+        }
         LanguageKind::Python => {
             let module_path = normalized_module.replace('.', "/");
             if module_path.is_empty() {
@@ -281,6 +1627,37 @@ fn resolve_single_import(
                 PathBuf::from(module_path).join("__init__.py"),
             ]
         }
+        LanguageKind::Java | LanguageKind::Kotlin => {
+            let name = jvm_import_name(normalized_module);
+            // A static wildcard (`Outer.*`) names members of a class.
+            let segments: Vec<&str> = name.trim_end_matches(".*").split('.').collect();
+            let mut candidates = Vec::new();
+            for root in workspace.jvm_roots_for(rel_path) {
+                // Shorter prefixes cover nested classes and static members,
+                // which live in the file of their outermost class.
+                for len in (2..=segments.len()).rev() {
+                    let class_path = segments[..len].join("/");
+                    for extension in ["java", "kt"] {
+                        candidates.push(root.join(format!("{class_path}.{extension}")));
+                    }
+                }
+            }
+            candidates
+        }
+        LanguageKind::C | LanguageKind::Cpp => {
+            let (header, quoted) = c_include_target(normalized_module)?;
+            let base_dir = Path::new(rel_path)
+                .parent()
+                .unwrap_or_else(|| Path::new(""));
+            quoted
+                .then(|| base_dir.join(header))
+                .into_iter()
+                .chain(workspace.include_dirs.iter().map(|dir| dir.join(header)))
+                .map(|candidate| normalize_lexically(&candidate))
+                // Includes that climb out of the repo never resolve.
+                .filter(|candidate| !candidate.starts_with(".."))
+                .collect()
+        }
         _ => return None,
     };
 
@@ -307,6 +1684,73 @@ fn resolve_single_import(
     None
 }
 
+/// The header an `#include` names and whether it was quoted (`"x.h"`) rather
+/// than bracketed (`<x.h>`). Macro includes (`#include FOO_H`) give `None`.
+fn c_include_target(module: &str) -> Option<(&str, bool)> {
+    let target = module
+        .trim()
+        .strip_prefix('#')?
+        .trim_start()
+        .strip_prefix("include")?
+        .trim();
+    if let Some(rest) = target.strip_prefix('"') {
+        let (header, _) = rest.split_once('"')?;
+        return (!header.is_empty()).then_some((header, true));
+    }
+    let (header, _) = target.strip_prefix('<')?.split_once('>')?;
+    (!header.is_empty()).then_some((header, false))
+}
+
+/// The dotted name a Java or Kotlin import names, without the `import` and
+/// `static` keywords, the trailing `;`, or a Kotlin `as` alias.
+fn jvm_import_name(module: &str) -> &str {
+    let mut name = module.trim();
+    for keyword in ["import", "static"] {
+        if let Some(rest) = name
+            .strip_prefix(keyword)
+            .filter(|rest| rest.starts_with(char::is_whitespace))
+        {
+            name = rest.trim_start();
+        }
+    }
+    let name = name.split_whitespace().next().unwrap_or_default();
+    name.trim_end_matches(';')
+}
+
+/// The `.java` and `.kt` files of a package named by a wildcard import, from
+/// the first source root that has the package directory.
+fn jvm_package_files(
+    repo_root: &Path,
+    workspace: &WorkspacePackages,
+    rel_path: &str,
+    package: &str,
+) -> Vec<String> {
+    let package_path = package.replace('.', "/");
+    for root in workspace.jvm_roots_for(rel_path) {
+        let package_dir = root.join(&package_path);
+        let Ok(entries) = fs::read_dir(repo_root.join(&package_dir)) else {
+            continue;
+        };
+        let mut files: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_type()
+                    .map(|kind| kind.is_file())
+                    .unwrap_or(false)
+            })
+            .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+            .filter(|name| name.ends_with(".java") || name.ends_with(".kt"))
+            .map(|name| normalize_rel_path(package_dir.join(name)))
+            .collect();
+        if !files.is_empty() {
+            files.sort();
+            return files;
+        }
+    }
+    Vec::new()
+}
+
 fn rust_import_candidates(raw_module: &str) -> Vec<PathBuf> {
     // Keep only the stable module prefix from `use` shapes like:
     // - crate::mcp::run_mcp_stdio
@@ -355,17 +1799,31 @@ fn rust_import_candidates(raw_module: &str) -> Vec<PathBuf> {
     out
 }
 
-fn build_winnowed_fingerprints(content: &str, k: usize, window: usize) -> Vec<(i64, i64, i64)> {
+fn build_winnowed_fingerprints(
+    content: &str,
+    k: usize,
+    window: usize,
+    min_tokens: usize,
+) -> Vec<Fingerprint> {
     let tokens = tokenize(content);
-    if tokens.len() < k || k == 0 || window == 0 {
+    if tokens.len() < k.max(min_tokens) || k == 0 || window == 0 {
         return Vec::new();
     }
 
     let mut kgrams = Vec::new();
     for i in 0..=(tokens.len() - k) {
-        let gram = tokens[i..i + k].join(" ");
-        let hash = stable_i64_hash(gram.as_bytes());
-        kgrams.push((hash, i as i64, (i + k) as i64));
+        let gram = tokens[i..i + k]
+            .iter()
+            .map(|(token, _)| token.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        kgrams.push(Fingerprint {
+            hash: stable_i64_hash(gram.as_bytes()),
+            span_start: i as i64,
+            span_end: (i + k) as i64,
+            start_line: tokens[i].1,
+            end_line: tokens[i + k - 1].1,
+        });
     }
 
     if kgrams.len() <= window {
@@ -377,11 +1835,11 @@ fn build_winnowed_fingerprints(content: &str, k: usize, window: usize) -> Vec<(i
     for i in 0..=(kgrams.len() - window) {
         let mut min_item = kgrams[i];
         for item in &kgrams[i..i + window] {
-            if item.0 < min_item.0 {
+            if item.hash < min_item.hash {
                 min_item = *item;
             }
         }
-        if selected_set.insert((min_item.0, min_item.1)) {
+        if selected_set.insert((min_item.hash, min_item.span_start)) {
             selected.push(min_item);
         }
     }
@@ -389,11 +1847,49 @@ fn build_winnowed_fingerprints(content: &str, k: usize, window: usize) -> Vec<(i
     selected
 }
 
-fn tokenize(content: &str) -> Vec<String> {
+/// Header lines searched for [`GENERATED_MARKERS`]; a marker further down is
+/// more likely a string or comment about generated code than a header.
+const GENERATED_HEADER_LINES: usize = 10;
+
+/// Header text code generators leave: `@generated` (Buck, Relay, Hack),
+/// `DO NOT EDIT` (Go's `// Code generated ... DO NOT EDIT.`, protoc), and
+/// `<auto-generated` (.NET).
+const GENERATED_MARKERS: &[&str] = &["@generated", "DO NOT EDIT", "<auto-generated"];
+
+/// Whether the first lines of `content` mark the file as generated code.
+pub fn has_generated_marker(content: &str) -> bool {
     content
-        .split(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
-        .filter(|part| !part.is_empty())
-        .map(|part| part.to_ascii_lowercase())
+        .lines()
+        .take(GENERATED_HEADER_LINES)
+        .any(|line| GENERATED_MARKERS.iter().any(|marker| line.contains(marker)))
+}
+
+/// Replaces every byte inside `spans` with a space, keeping newlines so the
+/// remaining tokens stay on their original lines.
+fn blank_spans(content: &str, spans: &[(usize, usize)]) -> String {
+    let mut bytes = content.as_bytes().to_vec();
+    for &(start, end) in spans {
+        let end = end.min(bytes.len());
+        for byte in bytes.iter_mut().take(end).skip(start) {
+            if *byte != b'\n' {
+                *byte = b' ';
+            }
+        }
+    }
+    String::from_utf8(bytes).unwrap_or_else(|_| content.to_string())
+}
+
+/// Splits `content` into lowercased identifier-like tokens, each paired with
+/// the 1-based line it appears on.
+fn tokenize(content: &str) -> Vec<(String, i64)> {
+    content
+        .lines()
+        .zip(1_i64..)
+        .flat_map(|(line, line_no)| {
+            line.split(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+                .filter(|part| !part.is_empty())
+                .map(move |part| (part.to_ascii_lowercase(), line_no))
+        })
         .collect()
 }
 
@@ -406,7 +1902,7 @@ fn stable_i64_hash(bytes: &[u8]) -> i64 {
     ])
 }
 
-fn sha256_hex(content: &[u8]) -> String {
+pub fn sha256_hex(content: &[u8]) -> String {
     let mut hasher = Sha256::new();
     hasher.update(content);
     let digest = hasher.finalize();
@@ -424,7 +1920,10 @@ mod tests {
     use std::collections::BTreeSet;
 
     use crate::model::Import;
-    use crate::storage::GraphStore;
+    use crate::storage::{
+        CloneQueryOptions, GraphStore, ReferenceQueryOptions, SelectorSuggestOptions,
+        SemanticSearchOptions, SymbolSearchOptions,
+    };
 
     fn setup_test_repo() -> (tempfile::TempDir, std::path::PathBuf) {
         let dir = tempfile::TempDir::new().unwrap();
@@ -446,13 +1945,94 @@ mod tests {
         GraphStore::open(&repo.join("graph.db")).unwrap()
     }
 
+    #[test]
+    fn language_coverage_counts_lines_and_reports_unsupported_files() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(
+            &repo.join("src/lib.rs"),
+            "pub fn greet() {}\n\npub fn wave() { greet(); }\n",
+        );
+        write_file(&repo.join("notes.md"), "# notes\n");
+        write_file(&repo.join("docs/guide.md"), "# guide\n");
+        write_file(&repo.join("docs/setup.md"), "# setup\n");
+
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+
+        let coverage = store.language_coverage().unwrap();
+        assert_eq!(coverage.len(), 1);
+        assert_eq!(coverage[0].language, "rust");
+        assert_eq!(coverage[0].files, 1);
+        assert_eq!(coverage[0].lines, 3);
+        assert_eq!(coverage[0].definitions, 2);
+        assert!(coverage[0].references >= 1);
+
+        let unsupported = unsupported_files(&repo, 2).unwrap();
+        let markdown = unsupported
+            .iter()
+            .find(|item| item.extension == "md")
+            .expect("markdown files should be reported");
+        assert_eq!(markdown.files, 3);
+        assert_eq!(markdown.examples, vec!["docs/guide.md", "docs/setup.md"]);
+        assert!(unsupported
+            .iter()
+            .all(|item| item.examples.iter().all(|path| !path.ends_with(".rs"))));
+    }
+
+    #[test]
+    fn index_repository_reuses_cached_parses_for_full_and_moved_files() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(&repo.join("src/lib.rs"), "pub fn greet() {}\n");
+        write_file(&repo.join("src/util.rs"), "pub fn helper() {}\n");
+
+        let mut store = open_test_store(&repo);
+        let first = index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert_eq!(first.cached_parses, 0);
+
+        let full = index_repository(
+            &mut store,
+            &repo,
+            IndexOptions {
+                full: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(full.indexed_files, 2);
+        assert_eq!(full.cached_parses, 2);
+
+        std::fs::rename(repo.join("src/util.rs"), repo.join("src/helpers.rs")).unwrap();
+        let moved = index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert_eq!(moved.indexed_files, 1);
+        assert_eq!(moved.cached_parses, 1);
+        let defs = store.symbol_definitions("helper").unwrap();
+        assert_eq!(defs.len(), 1);
+        assert_eq!(defs[0].file_path, "src/helpers.rs");
+
+        write_file(
+            &repo.join("src/lib.rs"),
+            "pub fn greet() {}\npub fn wave() {}\n",
+        );
+        let edited = index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert_eq!(edited.cached_parses, 0, "changed content must be reparsed");
+        assert_eq!(store.symbol_definitions("wave").unwrap().len(), 1);
+    }
+
     #[test]
     fn index_repository_basic_indexes_one_file() {
         let (_dir, repo) = setup_test_repo();
         write_file(&repo.join("src/lib.rs"), "pub fn greet() {}\n");
 
         let mut store = open_test_store(&repo);
-        let report = index_repository(&mut store, &repo, IndexOptions { full: false }).unwrap();
+        let report = index_repository(
+            &mut store,
+            &repo,
+            IndexOptions {
+                full: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         assert_eq!(report.indexed_files, 1);
         assert_eq!(report.skipped_files, 0);
@@ -465,8 +2045,24 @@ mod tests {
         write_file(&repo.join("src/lib.rs"), "pub fn greet() {}\n");
 
         let mut store = open_test_store(&repo);
-        let first = index_repository(&mut store, &repo, IndexOptions { full: false }).unwrap();
-        let second = index_repository(&mut store, &repo, IndexOptions { full: false }).unwrap();
+        let first = index_repository(
+            &mut store,
+            &repo,
+            IndexOptions {
+                full: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let second = index_repository(
+            &mut store,
+            &repo,
+            IndexOptions {
+                full: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         assert_eq!(first.indexed_files, 1);
         assert_eq!(second.indexed_files, 0);
@@ -474,32 +2070,755 @@ mod tests {
         assert_eq!(second.removed_files, 0);
     }
 
+    #[test]
+    fn index_repository_skips_reading_files_with_unchanged_size_and_mtime() {
+        let (_dir, repo) = setup_test_repo();
+        let path = repo.join("src/lib.rs");
+        let set_mtime = |mtime: SystemTime| {
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+        };
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        write_file(&path, "pub fn greet() {}\n");
+        set_mtime(an_hour_ago);
+
+        let mut store = open_test_store(&repo);
+        let index = |store: &mut GraphStore| {
+            index_repository(store, &repo, IndexOptions::default()).unwrap()
+        };
+        assert_eq!(index(&mut store).indexed_files, 1);
+        assert!(store.tracked_file_stat("src/lib.rs").unwrap().is_some());
+
+        // Same size and mtime: trusted without reading, even though the
+        // content differs.
+        write_file(&path, "pub fn grin() {}\n\n");
+        set_mtime(an_hour_ago);
+        let skipped = index(&mut store);
+        assert_eq!((skipped.indexed_files, skipped.skipped_files), (0, 1));
+        assert_eq!(store.symbol_definitions("grin").unwrap().len(), 0);
+
+        // A new mtime makes it hash the file and pick up the edit.
+        set_mtime(an_hour_ago + Duration::from_secs(60));
+        assert_eq!(index(&mut store).indexed_files, 1);
+        assert_eq!(store.symbol_definitions("grin").unwrap().len(), 1);
+
+        // A file written just now is hashed every run until its mtime settles.
+        write_file(&path, "pub fn wave() {}\n");
+        assert_eq!(index(&mut store).indexed_files, 1);
+        assert!(store.tracked_file_stat("src/lib.rs").unwrap().is_none());
+    }
+
     #[test]
     fn index_repository_full_rebuild_reindexes_without_skips() {
         let (_dir, repo) = setup_test_repo();
         write_file(&repo.join("src/lib.rs"), "pub fn greet() {}\n");
 
         let mut store = open_test_store(&repo);
-        let _ = index_repository(&mut store, &repo, IndexOptions { full: false }).unwrap();
-        let rebuild = index_repository(&mut store, &repo, IndexOptions { full: true }).unwrap();
+        let _ = index_repository(
+            &mut store,
+            &repo,
+            IndexOptions {
+                full: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let rebuild = index_repository(
+            &mut store,
+            &repo,
+            IndexOptions {
+                full: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(rebuild.indexed_files, 1);
+        assert_eq!(rebuild.skipped_files, 0);
+    }
+
+    #[test]
+    fn index_repository_removes_stale_files() {
+        let (_dir, repo) = setup_test_repo();
+        let file = repo.join("src/lib.rs");
+        write_file(&file, "pub fn greet() {}\n");
+
+        let mut store = open_test_store(&repo);
+        let _ = index_repository(
+            &mut store,
+            &repo,
+            IndexOptions {
+                full: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        std::fs::remove_file(&file).unwrap();
+        let report = index_repository(
+            &mut store,
+            &repo,
+            IndexOptions {
+                full: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(report.removed_files, 1);
+    }
+
+    #[test]
+    fn index_repository_applies_renames_without_reparsing() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(&repo.join("src/old.rs"), "pub fn greet() {}\n");
+
+        let mut store = open_test_store(&repo);
+        let _ = index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+
+        std::fs::rename(repo.join("src/old.rs"), repo.join("src/new.rs")).unwrap();
+        let report = index_repository(
+            &mut store,
+            &repo,
+            IndexOptions {
+                renames: vec![("src/old.rs".to_string(), "src/new.rs".to_string())],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(report.renamed_files, 1);
+        assert_eq!(
+            report.indexed_files, 0,
+            "renamed file should not be reparsed"
+        );
+        assert_eq!(report.removed_files, 0);
+        let defs = store.symbol_definitions("greet").unwrap();
+        assert_eq!(defs.len(), 1);
+        assert_eq!(defs[0].file_path, "src/new.rs");
+    }
+
+    #[test]
+    fn index_repository_fingerprints_ignore_comments_unless_raw() {
+        let (_dir, repo) = setup_test_repo();
+        let header = "// Licensed under the Apache License, Version 2.0 (the License); you may\n\
+                      // not use this file except in compliance with the License. You may obtain\n\
+                      // a copy of the License at http://www.apache.org/licenses/LICENSE-2.0\n";
+        write_file(
+            &repo.join("src/alpha.rs"),
+            &format!("{header}pub fn alpha(left: u32) -> u32 {{ left + 1 }}\n"),
+        );
+        write_file(
+            &repo.join("src/beta.rs"),
+            &format!("{header}pub struct Beta {{ name: String, count: usize }}\n"),
+        );
+
+        let options = CloneQueryOptions {
+            min_similarity: 0.01,
+            limit: 10,
+            offset: 0,
+            ..Default::default()
+        };
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        let (rows, _, _) = store.clone_matches_page("src/alpha.rs", &options).unwrap();
+        assert!(rows.is_empty(), "shared comments alone should not match");
+
+        let report = index_repository(
+            &mut store,
+            &repo,
+            IndexOptions {
+                fingerprint_mode: Some(FingerprintMode::Raw),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(report.indexed_files, 2, "mode change should rebuild");
+        let (rows, _, _) = store.clone_matches_page("src/alpha.rs", &options).unwrap();
+        assert_eq!(rows.len(), 1, "raw mode should keep the old behavior");
+        assert_eq!(rows[0].other_file, "src/beta.rs");
+
+        let report = index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert_eq!(report.skipped_files, 2, "stored mode should be reused");
+    }
+
+    #[test]
+    fn index_report_lists_changed_paths() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(&repo.join("src/a.rs"), "pub fn a() {}\n");
+        write_file(&repo.join("src/b.rs"), "pub fn b() {}\n");
+        let mut store = open_test_store(&repo);
+
+        let report = index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert_eq!(report.changed_paths, ["src/a.rs", "src/b.rs"]);
+        let report = index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert!(report.changed_paths.is_empty());
+
+        write_file(&repo.join("src/a.rs"), "pub fn a2() {}\n");
+        fs::remove_file(repo.join("src/b.rs")).unwrap();
+        let report = index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert_eq!(report.changed_paths, ["src/a.rs", "src/b.rs"]);
+    }
+
+    #[test]
+    fn codeowners_are_attached_to_file_entities_and_follow_edits() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(&repo.join("src/lib.rs"), "pub fn lib() {}\n");
+        write_file(&repo.join("docs/guide.py"), "def guide():\n    pass\n");
+        write_file(&repo.join(".github/CODEOWNERS"), "* @core\ndocs/ @docs\n");
+
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert_eq!(store.file_owners("src/lib.rs").unwrap(), ["@core"]);
+        assert_eq!(store.file_owners("docs/guide.py").unwrap(), ["@docs"]);
+
+        // Ownership changes apply even though the owned files did not change.
+        write_file(&repo.join(".github/CODEOWNERS"), "src/ @storage\n");
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert_eq!(store.file_owners("src/lib.rs").unwrap(), ["@storage"]);
+        assert!(store.file_owners("docs/guide.py").unwrap().is_empty());
+    }
+
+    #[test]
+    fn clone_matches_report_line_regions_and_backfill_old_fingerprints() {
+        let (_dir, repo) = setup_test_repo();
+        let block = "pub fn checksum(bytes: &[u8]) -> u32 {\n\
+                     \x20   let mut total = 0u32;\n\
+                     \x20   for byte in bytes {\n\
+                     \x20       total = total.wrapping_mul(31).wrapping_add(*byte as u32);\n\
+                     \x20   }\n\
+                     \x20   total\n\
+                     }\n";
+        write_file(&repo.join("src/alpha.rs"), block);
+        write_file(
+            &repo.join("src/beta.rs"),
+            &format!("pub struct Marker;\n\nimpl Marker {{}}\n\n{block}"),
+        );
+
+        let options = CloneQueryOptions {
+            min_similarity: 0.1,
+            limit: 10,
+            offset: 0,
+            ..Default::default()
+        };
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        let (rows, _, _) = store.clone_matches_page("src/alpha.rs", &options).unwrap();
+        assert_eq!(rows.len(), 1);
+        let region = &rows[0].regions[0];
+        assert_eq!(rows[0].regions.len(), 1);
+        assert!(region.start_line >= 1 && region.end_line <= 7);
+        assert_eq!(region.other_start_line, region.start_line + 4);
+        assert_eq!(region.other_end_line, region.end_line + 4);
+        let expected = rows[0].regions.clone();
+
+        // Simulate an index written before line spans were stored.
+        rusqlite::Connection::open(repo.join("graph.db"))
+            .unwrap()
+            .execute(
+                "UPDATE fingerprints SET start_line = NULL, end_line = NULL WHERE file_path = 'src/beta.rs'",
+                [],
+            )
+            .unwrap();
+        let (rows, _, _) = store.clone_matches_page("src/alpha.rs", &options).unwrap();
+        assert!(rows[0].regions.is_empty());
+
+        let report = index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert_eq!(report.indexed_files, 1, "only the stale file is reindexed");
+        let (rows, _, _) = store.clone_matches_page("src/alpha.rs", &options).unwrap();
+        assert_eq!(rows[0].regions, expected);
+    }
+
+    #[test]
+    fn include_tests_false_keeps_queries_on_production_code() {
+        let (_dir, repo) = setup_test_repo();
+        let block = "pub fn checksum(bytes: &[u8]) -> u32 {\n\
+                     \x20   let mut total = 0u32;\n\
+                     \x20   for byte in bytes {\n\
+                     \x20       total = total.wrapping_mul(31).wrapping_add(*byte as u32);\n\
+                     \x20   }\n\
+                     \x20   total\n\
+                     }\n";
+        write_file(
+            &repo.join("src/lib.rs"),
+            &format!("pub fn used() {{}}\n\npub fn only_tested() {{}}\n\npub fn run() {{\n    used();\n}}\n\n{block}"),
+        );
+        write_file(
+            &repo.join("tests/api.rs"),
+            &format!("fn check() {{\n    used();\n    only_tested();\n}}\n\n{block}"),
+        );
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+
+        let callers = |name: &str, include_tests| {
+            let options = ReferenceQueryOptions {
+                edge_type_filter: Some("calls".to_string()),
+                include_tests,
+                ..Default::default()
+            };
+            let (rows, _) = store.symbol_references_page(name, &options).unwrap();
+            rows.into_iter()
+                .map(|row| row.file_path)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(callers("used", true), ["src/lib.rs", "tests/api.rs"]);
+        assert_eq!(callers("used", false), ["src/lib.rs"]);
+
+        let dead = |include_tests| {
+            let options = SymbolSearchOptions {
+                unreferenced: true,
+                include_tests,
+                ..Default::default()
+            };
+            let mut names: Vec<String> = store
+                .symbols_matching(".", &options)
+                .unwrap()
+                .into_iter()
+                .map(|row| row.symbol_name)
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(dead(true), ["check", "checksum", "checksum", "run"]);
+        assert_eq!(dead(false), ["checksum", "only_tested", "run"]);
+
+        let clones = |include_tests| {
+            let options = CloneQueryOptions {
+                min_similarity: 0.1,
+                include_tests,
+                ..Default::default()
+            };
+            store.clone_matches_page("src/lib.rs", &options).unwrap()
+        };
+        assert_eq!(clones(true).0.len(), 1);
+        let (rows, _, analysis) = clones(false);
+        assert!(rows.is_empty());
+        assert_eq!(analysis.filtered_tests, 1);
+        assert!(analysis.empty_reason.unwrap().contains("tests"));
+    }
+
+    #[test]
+    fn generated_files_are_marked_and_left_out_by_default() {
+        let (_dir, repo) = setup_test_repo();
+        let block = "pub fn checksum(bytes: &[u8]) -> u32 {\n\
+                     \x20   let mut total = 0u32;\n\
+                     \x20   for byte in bytes {\n\
+                     \x20       total = total.wrapping_mul(31).wrapping_add(*byte as u32);\n\
+                     \x20   }\n\
+                     \x20   total\n\
+                     }\n";
+        write_file(
+            &repo.join("src/lib.rs"),
+            &format!("pub fn used() {{}}\n\npub fn run() {{\n    used();\n}}\n\n{block}"),
+        );
+        write_file(
+            &repo.join("src/api_pb.rs"),
+            &format!("// Code generated by protoc-gen-rust. DO NOT EDIT.\n\npub fn unused_rpc() {{\n    used();\n}}\n\n{block}"),
+        );
+
+        assert!(has_generated_marker("// @generated by relay\nfn x() {}\n"));
+        assert!(!has_generated_marker(&format!(
+            "{}// DO NOT EDIT\n",
+            "\n".repeat(GENERATED_HEADER_LINES)
+        )));
+
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert!(
+            store
+                .file_profile("src/api_pb.rs")
+                .unwrap()
+                .unwrap()
+                .is_generated
+        );
+        assert!(
+            !store
+                .file_profile("src/lib.rs")
+                .unwrap()
+                .unwrap()
+                .is_generated
+        );
+
+        let callers = |include_generated| {
+            let options = ReferenceQueryOptions {
+                edge_type_filter: Some("calls".to_string()),
+                include_generated,
+                ..Default::default()
+            };
+            let (rows, _) = store.symbol_references_page("used", &options).unwrap();
+            rows.into_iter()
+                .map(|row| row.file_path)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(callers(false), ["src/lib.rs"]);
+        assert_eq!(callers(true), ["src/lib.rs", "src/api_pb.rs"]);
+
+        let dead = |include_generated| {
+            let options = SymbolSearchOptions {
+                unreferenced: true,
+                include_generated,
+                ..Default::default()
+            };
+            let mut names: Vec<String> = store
+                .symbols_matching(".", &options)
+                .unwrap()
+                .into_iter()
+                .map(|row| row.symbol_name)
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(dead(false), ["checksum", "run"]);
+        assert_eq!(dead(true), ["checksum", "checksum", "run", "unused_rpc"]);
+
+        let clones = |include_generated| {
+            let options = CloneQueryOptions {
+                min_similarity: 0.1,
+                include_generated,
+                ..Default::default()
+            };
+            store.clone_matches_page("src/lib.rs", &options).unwrap()
+        };
+        assert_eq!(clones(true).0.len(), 1);
+        let (rows, _, analysis) = clones(false);
+        assert!(rows.is_empty());
+        assert_eq!(analysis.filtered_generated, 1);
+        assert!(analysis.empty_reason.unwrap().contains("generated"));
+    }
+
+    #[test]
+    fn fingerprint_params_come_from_config_and_mismatches_are_flagged() {
+        let (_dir, repo) = setup_test_repo();
+        let block = "pub fn checksum(bytes: &[u8]) -> u32 {\n\
+                     \x20   let mut total = 0u32;\n\
+                     \x20   for byte in bytes {\n\
+                     \x20       total = total.wrapping_mul(31).wrapping_add(*byte as u32);\n\
+                     \x20   }\n\
+                     \x20   total\n\
+                     }\n";
+        write_file(&repo.join("src/alpha.rs"), block);
+        write_file(&repo.join("src/beta.rs"), block);
+
+        let options = CloneQueryOptions {
+            min_similarity: 0.1,
+            limit: 10,
+            offset: 0,
+            ..Default::default()
+        };
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert_eq!(
+            store.fingerprint_params().unwrap().as_deref(),
+            Some("k_gram=5,window=4,min_tokens=5")
+        );
+        let (rows, _, analysis) = store.clone_matches_page("src/alpha.rs", &options).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert!(analysis.params_warning.is_none());
+
+        write_file(&repo.join(".lumora.toml"), "[fingerprints]\nwindow = 2\n");
+        write_file(
+            &repo.join("src/beta.rs"),
+            &format!("{block}pub struct Beta;\n"),
+        );
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        let (rows, _, analysis) = store.clone_matches_page("src/alpha.rs", &options).unwrap();
+        assert_eq!(
+            rows[0].fingerprint_params.as_deref(),
+            Some("k_gram=5,window=2,min_tokens=5")
+        );
+        assert!(analysis
+            .params_warning
+            .unwrap()
+            .contains("k_gram=5,window=4,min_tokens=5"));
+
+        index_repository(
+            &mut store,
+            &repo,
+            IndexOptions {
+                full: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let (rows, _, analysis) = store.clone_matches_page("src/alpha.rs", &options).unwrap();
+        assert!(rows[0].fingerprint_params.is_none());
+        assert!(analysis.params_warning.is_none());
+
+        let strict = IndexOptions {
+            full: true,
+            fingerprint_params: Some(FingerprintParams {
+                min_tokens: 1000,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        index_repository(&mut store, &repo, strict).unwrap();
+        let (_, _, analysis) = store.clone_matches_page("src/alpha.rs", &options).unwrap();
+        assert_eq!(analysis.self_fingerprint_count, 0);
+    }
+
+    #[test]
+    fn scoped_runs_only_touch_files_in_scope() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(&repo.join("services/api/lib.rs"), "pub fn api() {}\n");
+        write_file(
+            &repo.join("services/api/tool.py"),
+            "def tool():\n    pass\n",
+        );
+        write_file(&repo.join("services/apix/lib.rs"), "pub fn apix() {}\n");
+        write_file(&repo.join("web/app.py"), "def app():\n    pass\n");
+
+        let mut store = open_test_store(&repo);
+        let scoped = |paths: &[&str], languages: &[&str]| IndexOptions {
+            scope_paths: paths.iter().map(|path| path.to_string()).collect(),
+            scope_languages: languages.iter().map(|lang| lang.to_string()).collect(),
+            ..Default::default()
+        };
+        let report =
+            index_repository(&mut store, &repo, scoped(&["./services/api/"], &[])).unwrap();
+        assert_eq!(
+            report.changed_paths,
+            ["services/api/lib.rs", "services/api/tool.py"]
+        );
+
+        let report = index_repository(&mut store, &repo, scoped(&[], &["Python"])).unwrap();
+        assert_eq!(report.changed_paths, ["web/app.py"]);
+        assert_eq!(report.skipped_files, 1);
+
+        // Out-of-scope files are neither rebuilt by `full` nor dropped when gone.
+        fs::remove_file(repo.join("web/app.py")).unwrap();
+        let report = index_repository(
+            &mut store,
+            &repo,
+            IndexOptions {
+                full: true,
+                ..scoped(&["services/api"], &["rust"])
+            },
+        )
+        .unwrap();
+        assert_eq!(report.changed_paths, ["services/api/lib.rs"]);
+        assert!(store.tracked_file_hash("web/app.py").unwrap().is_some());
+        assert!(store
+            .tracked_file_hash("services/apix/lib.rs")
+            .unwrap()
+            .is_none());
+
+        let report = index_repository(&mut store, &repo, scoped(&["web"], &[])).unwrap();
+        assert_eq!(report.removed_files, 1);
+
+        let err = index_repository(&mut store, &repo, scoped(&[], &["cobol"])).unwrap_err();
+        assert!(err.to_string().contains("unknown language `cobol`"));
+        assert!(index_repository(&mut store, &repo, scoped(&["../elsewhere"], &[])).is_err());
+    }
+
+    #[test]
+    fn listed_files_are_reindexed_without_a_walk() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(&repo.join("src/lib.rs"), "pub fn greet() {}\n");
+        write_file(&repo.join("src/util.rs"), "pub fn helper() {}\n");
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+
+        let only = |paths: &[&str]| IndexOptions {
+            files: paths.iter().map(|path| path.to_string()).collect(),
+            ..Default::default()
+        };
+        write_file(&repo.join("src/lib.rs"), "pub fn wave() {}\n");
+        write_file(&repo.join("src/new.rs"), "pub fn fresh() {}\n");
+        let report = index_repository(&mut store, &repo, only(&["src/lib.rs"])).unwrap();
+        assert_eq!(report.changed_paths, ["src/lib.rs"]);
+        assert_eq!(report.indexed_files, 1);
+        assert_eq!(store.symbol_definitions("wave").unwrap().len(), 1);
+        assert!(store.symbol_definitions("greet").unwrap().is_empty());
+        assert!(store.tracked_file_hash("src/new.rs").unwrap().is_none());
+        assert!(store.tracked_file_hash("src/util.rs").unwrap().is_some());
+
+        fs::remove_file(repo.join("src/util.rs")).unwrap();
+        let report = index_repository(&mut store, &repo, only(&["src/util.rs"])).unwrap();
+        assert_eq!(report.removed_files, 1);
+        assert!(store.symbol_definitions("helper").unwrap().is_empty());
+
+        let err = index_repository(&mut store, &repo, only(&["src/util.rs"])).unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+        write_file(&repo.join("notes.txt"), "hello\n");
+        assert!(index_repository(&mut store, &repo, only(&["notes.txt"])).is_err());
+        write_file(&repo.join("target/gen.rs"), "pub fn gen() {}\n");
+        assert!(index_repository(&mut store, &repo, only(&["target/gen.rs"])).is_err());
+        assert!(index_repository(&mut store, &repo, only(&["src"])).is_err());
+    }
+
+    #[test]
+    fn profile_reports_timings_only_when_asked() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(&repo.join("src/lib.rs"), "pub fn greet() {}\n");
+        write_file(&repo.join("app.py"), "def run():\n    pass\n");
+        let mut store = open_test_store(&repo);
+        let report = index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert!(report.profile.is_none());
+
+        let profiled = IndexOptions {
+            full: true,
+            profile: true,
+            ..Default::default()
+        };
+        let report = index_repository(&mut store, &repo, profiled).unwrap();
+        let profile = report.profile.expect("profile requested");
+        let mut languages: Vec<_> = profile
+            .languages
+            .iter()
+            .map(|timing| (timing.language.as_str(), timing.files))
+            .collect();
+        languages.sort();
+        assert_eq!(languages, [("python", 1), ("rust", 1)]);
+        let mut files: Vec<_> = profile
+            .slowest_files
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["app.py", "src/lib.rs"]);
+        assert!(profile
+            .slowest_files
+            .windows(2)
+            .all(|pair| pair[0].total_ms >= pair[1].total_ms));
+        assert!(profile.total_ms >= profile.parse_ms + profile.store_ms);
+    }
+
+    #[test]
+    fn lazy_index_parses_only_covered_paths() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(&repo.join("src/lib.rs"), "pub fn greet() {}\n");
+        write_file(&repo.join("tools/gen.rs"), "pub fn generate() {}\n");
+        let mut store = open_test_store(&repo);
+        let lazy = IndexOptions {
+            lazy: Some(true),
+            ..Default::default()
+        };
+        let report = index_repository(&mut store, &repo, lazy).unwrap();
+        assert_eq!(report.indexed_files, 0);
+        assert!(store.is_empty().unwrap());
+        assert_eq!(store.lazy_coverage().unwrap(), Some(Vec::new()));
+
+        let report = index_on_demand(&mut store, &repo, "src").unwrap().unwrap();
+        assert_eq!(report.indexed_files, 1);
+        assert!(index_on_demand(&mut store, &repo, "src/lib.rs")
+            .unwrap()
+            .is_none());
+        assert!(index_on_demand(&mut store, &repo, "missing")
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            store.lazy_coverage().unwrap(),
+            Some(vec!["src".to_string()])
+        );
+        assert!(store.symbol_definitions("generate").unwrap().is_empty());
+
+        // Unscoped runs refresh the covered paths only.
+        write_file(&repo.join("src/lib.rs"), "pub fn wave() {}\n");
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert_eq!(store.symbol_definitions("wave").unwrap().len(), 1);
+        assert!(store.symbol_definitions("generate").unwrap().is_empty());
+        let freshness = store.freshness_info(24).unwrap();
+        assert_eq!(freshness.lazy_coverage, Some(vec!["src".to_string()]));
+
+        let off = IndexOptions {
+            lazy: Some(false),
+            ..Default::default()
+        };
+        index_repository(&mut store, &repo, off).unwrap();
+        assert_eq!(store.lazy_coverage().unwrap(), None);
+        assert_eq!(store.symbol_definitions("generate").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn packages_contain_their_files_and_anchor_dependency_paths() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(
+            &repo.join("src/main.rs"),
+            "use crate::storage::open;\nfn main() { open(); }\n",
+        );
+        write_file(
+            &repo.join("src/storage/mod.rs"),
+            "//! Graph storage.\npub fn open() {}\n",
+        );
+        write_file(&repo.join("src/storage/sql.rs"), "pub fn query() {}\n");
+
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
 
-        assert_eq!(rebuild.indexed_files, 1);
-        assert_eq!(rebuild.skipped_files, 0);
+        let path = store
+            .dependency_path("file:src/main.rs", "package:src/storage", 4)
+            .unwrap();
+        assert!(path.found);
+        let hops: Vec<&str> = path
+            .hops
+            .iter()
+            .map(|hop| hop.entity_key.as_str())
+            .collect();
+        assert_eq!(
+            hops,
+            [
+                "file:src/main.rs",
+                "file:src/storage/mod.rs",
+                "package:src/storage"
+            ]
+        );
+
+        let slice = store
+            .minimal_slice_with_options("src/storage/sql.rs", None, 2, &Default::default())
+            .unwrap()
+            .unwrap();
+        let package = slice
+            .neighbors
+            .iter()
+            .find(|edge| edge.entity.entity_type == "package")
+            .expect("slice lists the enclosing package");
+        assert_eq!(package.edge_type, "contains");
+        assert!(package
+            .entity
+            .meta_json
+            .as_deref()
+            .unwrap()
+            .contains("Graph storage."));
+
+        fs::remove_file(repo.join("src/storage/mod.rs")).unwrap();
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert!(
+            !store
+                .dependency_path("file:src/storage/sql.rs", "package:src/storage", 2)
+                .unwrap()
+                .found
+        );
     }
 
     #[test]
-    fn index_repository_removes_stale_files() {
+    fn index_repository_drops_files_under_ignore_globs() {
         let (_dir, repo) = setup_test_repo();
-        let file = repo.join("src/lib.rs");
-        write_file(&file, "pub fn greet() {}\n");
+        write_file(&repo.join("src/lib.rs"), "pub fn kept() {}\n");
+        write_file(&repo.join("proto_gen/api.rs"), "pub fn generated() {}\n");
+        write_file(&repo.join("src/api.pb.go"), "package api\nfunc Gen() {}\n");
 
         let mut store = open_test_store(&repo);
-        let _ = index_repository(&mut store, &repo, IndexOptions { full: false }).unwrap();
-
-        std::fs::remove_file(&file).unwrap();
-        let report = index_repository(&mut store, &repo, IndexOptions { full: false }).unwrap();
-
-        assert_eq!(report.removed_files, 1);
+        let report = index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert_eq!(report.indexed_files, 3);
+
+        let report = index_repository(
+            &mut store,
+            &repo,
+            IndexOptions {
+                ignore_globs: vec!["proto_gen/".into(), "*.pb.go".into()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(report.removed_files, 2);
+        assert!(store.symbol_definitions("generated").unwrap().is_empty());
+        assert!(store.symbol_definitions("Gen").unwrap().is_empty());
+        assert_eq!(store.symbol_definitions("kept").unwrap().len(), 1);
     }
 
     #[test]
@@ -509,7 +2828,7 @@ mod tests {
         write_file(&repo.join("node_modules/bar.py"), "print('ignored')\n");
         write_file(&repo.join(".git/thing.rs"), "pub fn ignored() {}\n");
 
-        let files = discover_files(&repo).unwrap();
+        let files = discover_files(&repo, &IgnoreGlobs::default()).unwrap();
         assert!(files.is_empty());
     }
 
@@ -523,7 +2842,7 @@ mod tests {
         write_file(&repo.join("pyproject.toml"), "[project]\nname = \"x\"\n");
         write_file(&repo.join("package.json"), "{\"name\":\"x\"}\n");
 
-        let files = discover_files(&repo).unwrap();
+        let files = discover_files(&repo, &IgnoreGlobs::default()).unwrap();
         let rel_paths: BTreeSet<String> = files.iter().map(|item| item.rel_path.clone()).collect();
         assert_eq!(
             rel_paths,
@@ -564,7 +2883,7 @@ mod tests {
         write_file(&repo.join("src/lib.rs"), "pub fn r() {}\n");
         write_file(&repo.join("src/mod.py"), "def p():\n    return 1\n");
 
-        let files = discover_files(&repo).unwrap();
+        let files = discover_files(&repo, &IgnoreGlobs::default()).unwrap();
         let rel_paths: BTreeSet<String> = files.iter().map(|item| item.rel_path.clone()).collect();
 
         assert_eq!(
@@ -576,27 +2895,40 @@ mod tests {
     #[test]
     fn build_winnowed_fingerprints_produces_non_empty_tuples() {
         let content = "fn main() { let alpha = 1; let beta = alpha + 2; println!(\"{}\", beta); }";
-        let fps = build_winnowed_fingerprints(content, 5, 4);
+        let fps = build_winnowed_fingerprints(content, 5, 4, 0);
         let token_count = tokenize(content).len() as i64;
 
         assert!(!fps.is_empty());
-        for (hash, start, end) in fps {
-            assert_ne!(hash, 0);
-            assert!(start >= 0);
-            assert!(end > start);
-            assert!(end <= token_count);
+        for fp in fps {
+            assert_ne!(fp.hash, 0);
+            assert!(fp.span_start >= 0);
+            assert!(fp.span_end > fp.span_start);
+            assert!(fp.span_end <= token_count);
+            assert_eq!((fp.start_line, fp.end_line), (1, 1));
         }
     }
 
+    #[test]
+    fn build_winnowed_fingerprints_map_token_spans_to_lines() {
+        let content = "alpha beta\n\ngamma delta\nepsilon zeta eta\n";
+        let fps = build_winnowed_fingerprints(content, 3, 1, 0);
+
+        let lines = fps
+            .iter()
+            .map(|fp| (fp.start_line, fp.end_line))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![(1, 3), (1, 3), (3, 4), (3, 4), (4, 4)]);
+    }
+
     #[test]
     fn build_winnowed_fingerprints_empty_content_returns_empty_vec() {
-        let fps = build_winnowed_fingerprints("", 5, 4);
+        let fps = build_winnowed_fingerprints("", 5, 4, 0);
         assert!(fps.is_empty());
     }
 
     #[test]
     fn build_winnowed_fingerprints_short_content_returns_empty_vec() {
-        let fps = build_winnowed_fingerprints("short tokens", 5, 4);
+        let fps = build_winnowed_fingerprints("short tokens", 5, 4, 0);
         assert!(fps.is_empty());
     }
 
@@ -614,11 +2946,18 @@ mod tests {
             line: 1,
             col: 1,
         }];
-        let resolved = resolve_imports(&repo, "src/main.rs", LanguageKind::Rust, &imports);
+        let resolved = resolve_imports(
+            &repo,
+            &WorkspacePackages::default(),
+            "src/main.rs",
+            LanguageKind::Rust,
+            &imports,
+        );
 
         assert_eq!(
             resolve_single_import(
                 &repo,
+                &WorkspacePackages::default(),
                 "src/main.rs",
                 LanguageKind::Rust,
                 "crate::storage::GraphStore"
@@ -642,15 +2981,423 @@ mod tests {
             line: 1,
             col: 1,
         }];
-        let resolved = resolve_imports(&repo, "main.py", LanguageKind::Python, &imports);
+        let resolved = resolve_imports(
+            &repo,
+            &WorkspacePackages::default(),
+            "main.py",
+            LanguageKind::Python,
+            &imports,
+        );
 
         assert_eq!(
-            resolve_single_import(&repo, "main.py", LanguageKind::Python, "foo"),
+            resolve_single_import(
+                &repo,
+                &WorkspacePackages::default(),
+                "main.py",
+                LanguageKind::Python,
+                "foo"
+            ),
             Some("foo.py".to_string())
         );
         assert!(resolved.contains(&("foo".to_string(), "foo.py".to_string())));
     }
 
+    #[test]
+    fn import_resolution_for_go_maps_module_paths_to_package_files() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(
+            &repo.join("go.mod"),
+            "module example.com/app // root module\n\ngo 1.22\n",
+        );
+        write_file(
+            &repo.join("cmd/app/main.go"),
+            "package main\n\nimport (\n\t\"fmt\"\n\tstore \"example.com/app/internal/store\"\n)\n\nfunc main() { fmt.Println(store.Open()) }\n",
+        );
+        write_file(
+            &repo.join("internal/store/store.go"),
+            "package store\n\nimport \"example.com/app/internal/codec\"\n\nfunc Open() string { return codec.Name() }\n",
+        );
+        write_file(
+            &repo.join("internal/store/store_test.go"),
+            "package store\n",
+        );
+        write_file(
+            &repo.join("internal/codec/codec.go"),
+            "package codec\n\nfunc Name() string { return \"codec\" }\n",
+        );
+
+        assert_eq!(
+            find_go_module(&repo, "internal/store/store.go"),
+            Some(GoModule {
+                path: "example.com/app".to_string(),
+                root: PathBuf::new(),
+            })
+        );
+
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+
+        let (_, imports) = store.file_imports("cmd/app/main.go").unwrap().unwrap();
+        let modules: Vec<&str> = imports.iter().map(|item| item.module.as_str()).collect();
+        assert_eq!(modules, vec!["fmt", "example.com/app/internal/store"]);
+
+        let resolved = resolve_imports(
+            &repo,
+            &WorkspacePackages::default(),
+            "cmd/app/main.go",
+            LanguageKind::Go,
+            &imports,
+        );
+        assert_eq!(
+            resolved,
+            vec![(
+                "example.com/app/internal/store".to_string(),
+                "internal/store/store.go".to_string()
+            )]
+        );
+
+        let path = store
+            .dependency_path("cmd/app/main.go", "internal/codec/codec.go", 4)
+            .unwrap();
+        assert!(path.found);
+        assert_eq!(path.hops.len(), 3);
+    }
+
+    #[test]
+    fn import_resolution_for_java_and_kotlin_uses_source_roots() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(
+            &repo.join("app/src/main/java/com/acme/app/Main.java"),
+            "package com.acme.app;\n\nimport java.util.List;\nimport com.acme.core.Outer.Inner;\nimport static com.acme.core.Strings.trim;\nimport com.acme.model.*;\n\nclass Main {}\n",
+        );
+        write_file(
+            &repo.join("core/src/main/java/com/acme/core/Outer.java"),
+            "package com.acme.core;\n\npublic class Outer { public static class Inner {} }\n",
+        );
+        write_file(
+            &repo.join("core/src/main/kotlin/com/acme/core/Strings.kt"),
+            "package com.acme.core\n\nimport com.acme.model.User as Account\n\nobject Strings { fun trim(s: String) = s.trim() }\n",
+        );
+        write_file(
+            &repo.join("core/src/main/kotlin/com/acme/model/User.kt"),
+            "package com.acme.model\n\nclass User\n",
+        );
+        write_file(
+            &repo.join("core/src/main/kotlin/com/acme/model/Role.kt"),
+            "package com.acme.model\n\nclass Role\n",
+        );
+
+        assert_eq!(
+            jvm_source_root("app/src/main/java/com/acme/app/Main.java"),
+            Some(PathBuf::from("app/src/main/java"))
+        );
+        assert_eq!(jvm_import_name("import static a.B.c;"), "a.B.c");
+        assert_eq!(jvm_import_name("import a.B as C"), "a.B");
+
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+
+        let (_, imports) = store
+            .file_imports("app/src/main/java/com/acme/app/Main.java")
+            .unwrap()
+            .unwrap();
+        let paths: HashSet<String> = [
+            "app/src/main/java/com/acme/app/Main.java",
+            "core/src/main/java/com/acme/core/Outer.java",
+            "core/src/main/kotlin/com/acme/core/Strings.kt",
+            "core/src/main/kotlin/com/acme/model/User.kt",
+            "core/src/main/kotlin/com/acme/model/Role.kt",
+        ]
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+        let workspace = WorkspacePackages::new(&[], &paths);
+        let resolved: Vec<String> = resolve_imports(
+            &repo,
+            &workspace,
+            "app/src/main/java/com/acme/app/Main.java",
+            LanguageKind::Java,
+            &imports,
+        )
+        .into_iter()
+        .map(|(_, target)| target)
+        .collect();
+        assert_eq!(
+            resolved,
+            vec![
+                "core/src/main/java/com/acme/core/Outer.java",
+                "core/src/main/kotlin/com/acme/core/Strings.kt",
+                "core/src/main/kotlin/com/acme/model/Role.kt",
+                "core/src/main/kotlin/com/acme/model/User.kt",
+            ]
+        );
+
+        let path = store
+            .dependency_path(
+                "app/src/main/java/com/acme/app/Main.java",
+                "core/src/main/kotlin/com/acme/model/User.kt",
+                4,
+            )
+            .unwrap();
+        assert!(path.found);
+        assert_eq!(path.hops.len(), 2);
+    }
+
+    #[test]
+    fn import_resolution_for_c_and_cpp_uses_include_dirs() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(
+            &repo.join(".lumora.toml"),
+            "[imports]\ninclude_dirs = [\"include/\"]\n",
+        );
+        write_file(
+            &repo.join("src/main.c"),
+            "#include <stdio.h>\n#include \"util.h\"\n#include <acme/config.h>\n#include \"../../outside.h\"\n\nint main(void) { return util(); }\n",
+        );
+        write_file(&repo.join("src/util.h"), "int util(void);\n");
+        write_file(
+            &repo.join("include/acme/config.h"),
+            "#include \"acme/version.h\"\n",
+        );
+        write_file(&repo.join("include/acme/version.h"), "#define ACME 1\n");
+
+        assert_eq!(
+            c_include_target("#include \"a/b.h\""),
+            Some(("a/b.h", true))
+        );
+        assert_eq!(
+            c_include_target("#  include <vector>"),
+            Some(("vector", false))
+        );
+        assert_eq!(c_include_target("#include CONFIG_H"), None);
+
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+
+        let (_, imports) = store.file_imports("src/main.c").unwrap().unwrap();
+        let workspace = WorkspacePackages::default().with_imports_config(&ImportsConfig {
+            include_dirs: vec!["include".to_string()],
+        });
+        let resolved: Vec<String> =
+            resolve_imports(&repo, &workspace, "src/main.c", LanguageKind::C, &imports)
+                .into_iter()
+                .map(|(_, target)| target)
+                .collect();
+        assert_eq!(resolved, vec!["src/util.h", "include/acme/config.h"]);
+
+        // Quoted includes fall back to the include dirs.
+        let path = store
+            .dependency_path("src/main.c", "include/acme/version.h", 4)
+            .unwrap();
+        assert!(path.found);
+        assert_eq!(path.hops.len(), 3);
+    }
+
+    #[test]
+    fn dependency_paths_lists_each_route_shortest_first() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(&repo.join("app.py"), "import billing\nimport audit\n");
+        write_file(&repo.join("billing.py"), "import ledger\n");
+        write_file(&repo.join("audit.py"), "import ledger\n");
+        write_file(&repo.join("ledger.py"), "def post():\n    return 1\n");
+
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+
+        let (found, _, _) = store
+            .dependency_paths_with_diagnostics("app.py", "ledger.py", 4, 5, None, None)
+            .unwrap();
+        assert!(found.found);
+        assert!(!found.truncated);
+        let mut routes: Vec<Vec<&str>> = found
+            .paths
+            .iter()
+            .map(|hops| hops.iter().map(|hop| hop.entity_name.as_str()).collect())
+            .collect();
+        assert!(routes.windows(2).all(|pair| pair[0].len() <= pair[1].len()));
+        routes.truncate(2);
+        routes.sort();
+        assert_eq!(
+            routes,
+            vec![
+                vec!["app.py", "audit.py", "ledger.py"],
+                vec!["app.py", "billing.py", "ledger.py"],
+            ]
+        );
+        let first = &found.paths[0];
+        assert!(first[0].edge_type.is_none());
+        assert_eq!(first[1].edge_type.as_deref(), Some("depends_on"));
+
+        let (single, _, _) = store
+            .dependency_paths_with_diagnostics("app.py", "ledger.py", 4, 1, None, None)
+            .unwrap();
+        assert_eq!(single.paths.len(), 1);
+    }
+
+    #[test]
+    fn workspace_imports_roll_up_to_package_dependencies() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(
+            &repo.join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\"]\n",
+        );
+        write_file(
+            &repo.join("crates/core-utils/Cargo.toml"),
+            "[package]\nname = \"core-utils\"\ndescription = \"Shared helpers\"\n",
+        );
+        write_file(
+            &repo.join("crates/core-utils/src/lib.rs"),
+            "pub mod text;\n",
+        );
+        write_file(
+            &repo.join("crates/core-utils/src/text.rs"),
+            "pub fn slug() {}\n",
+        );
+        write_file(
+            &repo.join("crates/app/Cargo.toml"),
+            "[package]\nname = \"app\"\n",
+        );
+        write_file(
+            &repo.join("crates/app/src/main.rs"),
+            "use core_utils::text::slug;\nuse crate::cli::run;\nfn main() { slug(); run(); }\n",
+        );
+        write_file(&repo.join("crates/app/src/cli.rs"), "pub fn run() {}\n");
+        write_file(&repo.join("web/ui/package.json"), r#"{"name": "@acme/ui"}"#);
+        write_file(
+            &repo.join("web/ui/src/index.ts"),
+            "export const button = 1;\n",
+        );
+        write_file(&repo.join("web/site/package.json"), r#"{"name": "site"}"#);
+        write_file(
+            &repo.join("web/site/main.ts"),
+            "import { button } from '@acme/ui';\n",
+        );
+
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+
+        let deps = store.package_dependencies(false).unwrap();
+        let names: Vec<Option<&str>> = deps
+            .packages
+            .iter()
+            .map(|package| package.name.as_deref())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                Some("app"),
+                Some("core-utils"),
+                Some("site"),
+                Some("@acme/ui")
+            ]
+        );
+        let edges: Vec<(&str, &str)> = deps
+            .edges
+            .iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str()))
+            .collect();
+        assert_eq!(
+            edges,
+            [("crates/app", "crates/core-utils"), ("web/site", "web/ui"),]
+        );
+        assert_eq!(
+            deps.internal_edges, 1,
+            "`crate::cli` resolves inside the app crate"
+        );
+    }
+
+    #[test]
+    fn import_resolution_for_js_and_ts_handles_relative_and_tsconfig_paths() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(
+            &repo.join("web/tsconfig.json"),
+            r#"{
+  // comments and trailing commas are allowed in tsconfig
+  "compilerOptions": {
+    "baseUrl": "./src",
+    "paths": {
+      "@lib/*": ["lib/*"],
+      "@config": ["config/index.ts"], /* exact alias */
+    },
+  },
+}"#,
+        );
+        write_file(
+            &repo.join("web/src/app/main.ts"),
+            "import { util } from './util';\nimport { run } from '../lib/runner.js';\nimport { fmt } from '@lib/format';\nimport config from '@config';\nimport { h } from './helpers';\nimport React from 'react';\nimport { store } from 'state/store';\n",
+        );
+        write_file(
+            &repo.join("web/src/app/util.ts"),
+            "export const util = 1;\n",
+        );
+        write_file(
+            &repo.join("web/src/app/helpers/index.ts"),
+            "export const h = 1;\n",
+        );
+        write_file(
+            &repo.join("web/src/lib/runner.ts"),
+            "export const run = 1;\n",
+        );
+        write_file(
+            &repo.join("web/src/lib/format.tsx"),
+            "export const fmt = 1;\n",
+        );
+        write_file(
+            &repo.join("web/src/config/index.ts"),
+            "export default {};\n",
+        );
+        write_file(
+            &repo.join("web/src/state/store.js"),
+            "module.exports = {};\n",
+        );
+        write_file(
+            &repo.join("scripts/build.js"),
+            "const main = require(\"../web/src/app/main\");\nconst missing = require(\"../../outside\");\n",
+        );
+
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+
+        let (_, imports) = store.file_imports("web/src/app/main.ts").unwrap().unwrap();
+        let resolved = resolve_imports(
+            &repo,
+            &WorkspacePackages::default(),
+            "web/src/app/main.ts",
+            LanguageKind::TypeScript,
+            &imports,
+        );
+        let pairs: Vec<(&str, &str)> = resolved
+            .iter()
+            .map(|(module, file)| (module.as_str(), file.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("./util", "web/src/app/util.ts"),
+                ("../lib/runner.js", "web/src/lib/runner.ts"),
+                ("@lib/format", "web/src/lib/format.tsx"),
+                ("@config", "web/src/config/index.ts"),
+                ("./helpers", "web/src/app/helpers/index.ts"),
+                ("state/store", "web/src/state/store.js"),
+            ]
+        );
+
+        assert_eq!(
+            resolve_js_import(
+                &repo,
+                &WorkspacePackages::default(),
+                "scripts/build.js",
+                None,
+                "../../outside"
+            ),
+            None
+        );
+        let path = store
+            .dependency_path("scripts/build.js", "web/src/lib/format.tsx", 6)
+            .unwrap();
+        assert!(path.found);
+    }
+
     #[test]
     fn parse_failures_stay_zero_for_valid_rust_content() {
         let (_dir, repo) = setup_test_repo();
@@ -660,12 +3407,254 @@ mod tests {
         );
 
         let mut store = open_test_store(&repo);
-        let report = index_repository(&mut store, &repo, IndexOptions { full: false }).unwrap();
+        let report = index_repository(
+            &mut store,
+            &repo,
+            IndexOptions {
+                full: false,
+                ..Default::default()
+            },
+        )
+        .unwrap();
 
         assert_eq!(report.parse_failures, 0);
         assert!(report.errors.is_empty());
     }
 
+    #[test]
+    fn reference_confidence_weighs_imports_and_call_arity() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(
+            &repo.join("loader.py"),
+            "def load(path, mode=\"r\"):\n    return path\n",
+        );
+        write_file(
+            &repo.join("app.py"),
+            "from loader import load\n\nload(\"a\")\nload(1, 2, 3)\n",
+        );
+        write_file(&repo.join("other.py"), "def run(load):\n    load()\n");
+
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+
+        let options = ReferenceQueryOptions {
+            edge_type_filter: Some("calls".to_string()),
+            ..Default::default()
+        };
+        let (rows, _) = store.symbol_references_page("load", &options).unwrap();
+        let confidence = |file: &str, line: i64| {
+            let row = rows
+                .iter()
+                .find(|row| row.file_path == file && row.line == line)
+                .unwrap_or_else(|| panic!("no call at {file}:{line}: {rows:?}"));
+            (row.confidence.unwrap(), row.why.clone().unwrap())
+        };
+        let (matching, why) = confidence("app.py", 3);
+        assert!(
+            why.contains("imports_definition") && why.contains("arity_match"),
+            "{why}"
+        );
+        let (mismatched, why) = confidence("app.py", 4);
+        assert!(why.contains("arity_mismatch"), "{why}");
+        let (unrelated, _) = confidence("other.py", 2);
+        assert!(matching > mismatched && matching > unrelated);
+        assert!(matching <= 1.0 && unrelated >= 0.0);
+
+        let options = ReferenceQueryOptions {
+            min_confidence: Some(matching),
+            ..options
+        };
+        let (rows, _) = store.symbol_references_page("load", &options).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!((rows[0].file_path.as_str(), rows[0].line), ("app.py", 3));
+    }
+
+    #[test]
+    fn references_follow_alias_edges_in_both_directions() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(&repo.join("models.py"), "class User:\n    pass\n");
+        write_file(
+            &repo.join("app.py"),
+            "from models import User as Account\n\nAccount()\n",
+        );
+        write_file(
+            &repo.join("admin.py"),
+            "from models import User\n\nUser()\n",
+        );
+
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+
+        let calls = |name: &str| {
+            let options = ReferenceQueryOptions {
+                edge_type_filter: Some("calls".to_string()),
+                ..Default::default()
+            };
+            let (rows, _) = store.symbol_references_page(name, &options).unwrap();
+            rows.into_iter()
+                .map(|row| (row.file_path, row.symbol_name))
+                .collect::<Vec<_>>()
+        };
+        let canonical = calls("User");
+        assert!(canonical.contains(&("admin.py".to_string(), "User".to_string())));
+        assert!(
+            canonical.contains(&("app.py".to_string(), "Account".to_string())),
+            "calls through the alias count for the canonical name: {canonical:?}"
+        );
+        let alias = calls("Account");
+        assert!(alias.contains(&("admin.py".to_string(), "User".to_string())));
+    }
+
+    #[test]
+    fn external_entities_track_names_without_definitions() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(
+            &repo.join("src/lib.rs"),
+            "pub fn run() {\n    spawn_worker();\n}\n",
+        );
+
+        let mut store = open_test_store(&repo);
+        let enabled = IndexOptions {
+            external_entities: Some(true),
+            ..Default::default()
+        };
+        index_repository(&mut store, &repo, enabled).unwrap();
+        assert!(store.external_entities_enabled().unwrap());
+
+        let externals = |store: &GraphStore| {
+            store
+                .selector_suggestions_advanced(&SelectorSuggestOptions {
+                    entity_type: Some("external".to_string()),
+                    limit: 50,
+                    ..Default::default()
+                })
+                .unwrap()
+                .into_iter()
+                .map(|suggestion| suggestion.name)
+                .collect::<Vec<_>>()
+        };
+        let refs = |store: &GraphStore, exclude_externals: bool| {
+            let options = ReferenceQueryOptions {
+                exclude_externals,
+                ..Default::default()
+            };
+            store
+                .symbol_references_page("spawn_worker", &options)
+                .unwrap()
+                .0
+                .len()
+        };
+        assert!(externals(&store).contains(&"spawn_worker".to_string()));
+        assert!(!externals(&store).contains(&"run".to_string()));
+        assert_eq!(refs(&store, false), 1);
+        assert_eq!(refs(&store, true), 0);
+
+        write_file(&repo.join("src/worker.rs"), "pub fn spawn_worker() {}\n");
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert!(!externals(&store).contains(&"spawn_worker".to_string()));
+        assert_eq!(refs(&store, true), 1);
+
+        fs::remove_file(repo.join("src/worker.rs")).unwrap();
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert!(externals(&store).contains(&"spawn_worker".to_string()));
+
+        let disabled = IndexOptions {
+            external_entities: Some(false),
+            ..Default::default()
+        };
+        index_repository(&mut store, &repo, disabled).unwrap();
+        assert!(externals(&store).is_empty());
+        assert_eq!(refs(&store, true), 1);
+    }
+
+    #[test]
+    fn embeddings_can_be_turned_off_and_drop_stored_vectors() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(&repo.join("src/lib.rs"), "pub fn parse_selector() {}\n");
+
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        let search = |store: &GraphStore| {
+            store
+                .semantic_search(&[1.0, 0.0], &SemanticSearchOptions::default())
+                .unwrap()
+                .len()
+        };
+        store.set_embeddings_enabled(true).unwrap();
+        let record = crate::embeddings::EmbeddingRecord {
+            kind: "symbol",
+            label: "parse_selector".to_string(),
+            detail: None,
+            start_line: 1,
+            end_line: 1,
+            text: "parse_selector".to_string(),
+            vector: vec![1.0, 0.0],
+        };
+        store
+            .replace_file_embeddings("src/lib.rs", &[record])
+            .unwrap();
+        assert_eq!(search(&store), 1);
+
+        let disabled = IndexOptions {
+            embeddings: Some(false),
+            ..Default::default()
+        };
+        index_repository(&mut store, &repo, disabled).unwrap();
+        assert!(!store.embeddings_enabled().unwrap());
+        assert_eq!(search(&store), 0);
+
+        write_file(
+            &repo.join("src/lib.rs"),
+            "pub fn parse_selector_list() {}\n",
+        );
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert_eq!(search(&store), 0);
+    }
+
+    #[cfg(not(feature = "semantic"))]
+    #[test]
+    fn enabling_embeddings_without_the_semantic_feature_fails() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(&repo.join("src/lib.rs"), "pub fn parse_selector() {}\n");
+
+        let mut store = open_test_store(&repo);
+        let enabled = IndexOptions {
+            embeddings: Some(true),
+            ..Default::default()
+        };
+        let err = index_repository(&mut store, &repo, enabled).unwrap_err();
+        assert!(format!("{err:#}").contains("`semantic` feature"));
+        assert!(!store.embeddings_enabled().unwrap());
+    }
+
+    #[test]
+    fn index_failures_persist_until_the_file_indexes_or_disappears() {
+        let (_dir, repo) = setup_test_repo();
+        write_file(&repo.join("src/main.rs"), "fn main() {}\n");
+        fs::write(repo.join("src/bad.rs"), [0xff, 0xfe, b'f', b'n']).unwrap();
+
+        let mut store = open_test_store(&repo);
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+
+        let errors = store.index_errors().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "src/bad.rs");
+        assert_eq!(errors[0].stage, "read");
+        assert_eq!(store.freshness_info(24).unwrap().index_errors, 1);
+
+        write_file(&repo.join("src/bad.rs"), "fn fixed() {}\n");
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert!(store.index_errors().unwrap().is_empty());
+
+        fs::write(repo.join("src/bad.rs"), [0xff]).unwrap();
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert_eq!(store.index_errors().unwrap().len(), 1);
+        fs::remove_file(repo.join("src/bad.rs")).unwrap();
+        index_repository(&mut store, &repo, IndexOptions::default()).unwrap();
+        assert!(store.index_errors().unwrap().is_empty());
+        assert_eq!(store.freshness_info(24).unwrap().index_errors, 0);
+    }
+
     #[test]
     fn private_helpers_cover_hashes_paths_and_candidates() {
         assert_eq!(config_language_hint("Cargo.toml"), LanguageKind::Rust);
@@ -679,7 +3668,10 @@ mod tests {
             "src/lib.rs"
         );
 
-        assert_eq!(tokenize("Hello_World! 123"), vec!["hello_world", "123"]);
+        assert_eq!(
+            tokenize("Hello_World!\n123"),
+            vec![("hello_world".to_string(), 1), ("123".to_string(), 2)]
+        );
 
         let first_hash = stable_i64_hash(b"alpha");
         let same_hash = stable_i64_hash(b"alpha");
@@ -0,0 +1,151 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::config::{CONFIG_FILE_NAME, STARTER_CONFIG};
+use crate::paths::{ensure_state_layout, RuntimePaths};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InitReport {
+    pub repo_root: String,
+    pub state_dir: String,
+    pub config_path: String,
+    /// False when a `.lumora.toml` already existed and was left alone.
+    pub config_created: bool,
+    /// The `.gitignore` line that was appended, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gitignore_entry: Option<String>,
+}
+
+/// Creates the state dir and a starter `.lumora.toml`, and with `gitignore`
+/// appends the state dir to `.gitignore`. Safe to re-run: existing config is
+/// kept and an existing ignore entry is not duplicated.
+pub fn init_repository(paths: &RuntimePaths, gitignore: bool) -> Result<InitReport> {
+    ensure_state_layout(paths)?;
+
+    let config_path = paths.repo_root.join(CONFIG_FILE_NAME);
+    let config_created = match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&config_path)
+    {
+        Ok(mut file) => {
+            file.write_all(STARTER_CONFIG.as_bytes())
+                .with_context(|| format!("failed to write {}", config_path.display()))?;
+            true
+        }
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => false,
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to create {}", config_path.display()))
+        }
+    };
+
+    let gitignore_entry = if gitignore {
+        ignore_state_dir(paths)?
+    } else {
+        None
+    };
+
+    Ok(InitReport {
+        repo_root: paths.repo_root.display().to_string(),
+        state_dir: paths.state_dir.display().to_string(),
+        config_path: config_path.display().to_string(),
+        config_created,
+        gitignore_entry,
+    })
+}
+
+/// Appends `<state dir>/` to the repo's `.gitignore`. Returns `None` when the
+/// state dir lives outside the repo or is already ignored.
+fn ignore_state_dir(paths: &RuntimePaths) -> Result<Option<String>> {
+    let Ok(rel) = paths.state_dir.strip_prefix(&paths.repo_root) else {
+        return Ok(None);
+    };
+    let rel = rel.to_string_lossy().replace('\\', "/");
+    if rel.is_empty() {
+        return Ok(None);
+    }
+
+    let gitignore_path = paths.repo_root.join(".gitignore");
+    let existing = match fs::read_to_string(&gitignore_path) {
+        Ok(raw) => raw,
+        Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to read {}", gitignore_path.display()))
+        }
+    };
+    let already_ignored = existing
+        .lines()
+        .any(|line| line.trim().trim_start_matches('/').trim_end_matches('/') == rel);
+    if already_ignored {
+        return Ok(None);
+    }
+
+    let entry = format!("{rel}/");
+    let mut updated = existing;
+    if !updated.is_empty() && !updated.ends_with('\n') {
+        updated.push('\n');
+    }
+    updated.push_str(&entry);
+    updated.push('\n');
+    fs::write(&gitignore_path, updated)
+        .with_context(|| format!("failed to write {}", gitignore_path.display()))?;
+    Ok(Some(entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::load_config;
+    use crate::paths::STATE_DIR_NAME;
+    use tempfile::TempDir;
+
+    fn test_paths(repo: &std::path::Path) -> RuntimePaths {
+        let state_dir = repo.join(STATE_DIR_NAME);
+        RuntimePaths {
+            repo_root: repo.to_path_buf(),
+            db_path: state_dir.join("graph.db"),
+            state_dir,
+        }
+    }
+
+    #[test]
+    fn init_writes_starter_config_and_ignores_state_dir_once() {
+        let dir = TempDir::new().unwrap();
+        let paths = test_paths(dir.path());
+        fs::write(dir.path().join(".gitignore"), "target").unwrap();
+
+        let report = init_repository(&paths, true).unwrap();
+        assert!(report.config_created);
+        assert_eq!(report.gitignore_entry.as_deref(), Some(".lumora/"));
+        assert!(paths.state_dir.is_dir());
+        assert!(load_config(dir.path()).is_ok());
+        assert_eq!(
+            fs::read_to_string(dir.path().join(".gitignore")).unwrap(),
+            "target\n.lumora/\n"
+        );
+
+        fs::write(dir.path().join(CONFIG_FILE_NAME), "# mine\n").unwrap();
+        let report = init_repository(&paths, true).unwrap();
+        assert!(!report.config_created);
+        assert!(report.gitignore_entry.is_none());
+        assert_eq!(
+            fs::read_to_string(dir.path().join(CONFIG_FILE_NAME)).unwrap(),
+            "# mine\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join(".gitignore")).unwrap(),
+            "target\n.lumora/\n"
+        );
+    }
+
+    #[test]
+    fn init_leaves_gitignore_alone_unless_asked() {
+        let dir = TempDir::new().unwrap();
+        let report = init_repository(&test_paths(dir.path()), false).unwrap();
+        assert!(report.gitignore_entry.is_none());
+        assert!(!dir.path().join(".gitignore").exists());
+    }
+}
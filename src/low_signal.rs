@@ -0,0 +1,172 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::config::LowSignalConfig;
+
+/// Key in [`LowSignalNames::effective`] for names that apply to every language.
+pub const ALL_LANGUAGES: &str = "*";
+
+/// Shared by JavaScript, TypeScript, and TSX.
+const JS_NAMES: &[&str] = &[
+    "this",
+    "console",
+    "log",
+    "require",
+    "undefined",
+    "Promise",
+    "Array",
+    "Object",
+    "String",
+    "JSON",
+];
+
+/// Built-in per-language names: wrappers, primitives, and receivers that
+/// appear in nearly every function and say little about what it does.
+const BUILTIN: &[(&str, &[&str])] = &[
+    (
+        "rust",
+        &[
+            "Ok", "Err", "Some", "None", "Result", "Option", "String", "Vec", "Box", "Self", "self",
+        ],
+    ),
+    (
+        "python",
+        &[
+            "self",
+            "cls",
+            "super",
+            "print",
+            "len",
+            "str",
+            "int",
+            "dict",
+            "list",
+            "isinstance",
+            "None",
+            "True",
+            "False",
+        ],
+    ),
+    ("javascript", JS_NAMES),
+    ("typescript", JS_NAMES),
+    ("tsx", JS_NAMES),
+    (
+        "go",
+        &[
+            "err", "nil", "fmt", "make", "len", "append", "error", "string",
+        ],
+    ),
+];
+
+/// Symbol names slices rank down and test links skip, per language. Built
+/// from the defaults above plus `[low_signal]` in `.lumora.toml`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LowSignalNames {
+    common: BTreeSet<String>,
+    by_language: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Default for LowSignalNames {
+    fn default() -> Self {
+        Self::from_config(&LowSignalConfig::default())
+    }
+}
+
+impl LowSignalNames {
+    pub fn from_config(config: &LowSignalConfig) -> Self {
+        let mut by_language: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        if !config.replace_defaults {
+            for (language, names) in BUILTIN {
+                by_language
+                    .entry(language.to_string())
+                    .or_default()
+                    .extend(names.iter().map(|name| name.to_string()));
+            }
+        }
+        for (language, names) in &config.languages {
+            by_language
+                .entry(language.to_ascii_lowercase())
+                .or_default()
+                .extend(names.iter().cloned());
+        }
+        Self {
+            common: config.names.iter().cloned().collect(),
+            by_language,
+        }
+    }
+
+    /// Whether `name` is low signal in `language`. Without a language every
+    /// list applies, which matches the single list used before it was split.
+    pub fn contains(&self, language: Option<&str>, name: &str) -> bool {
+        if self.common.contains(name) {
+            return true;
+        }
+        match language {
+            Some(language) => self
+                .by_language
+                .get(language)
+                .is_some_and(|names| names.contains(name)),
+            None => self.by_language.values().any(|names| names.contains(name)),
+        }
+    }
+
+    /// The lists in effect, keyed by language, with names for every language
+    /// under [`ALL_LANGUAGES`]. `language` narrows it to that language and
+    /// the shared names.
+    pub fn effective(&self, language: Option<&str>) -> BTreeMap<String, Vec<String>> {
+        let mut out = BTreeMap::new();
+        if !self.common.is_empty() {
+            out.insert(
+                ALL_LANGUAGES.to_string(),
+                self.common.iter().cloned().collect(),
+            );
+        }
+        for (lang, names) in &self.by_language {
+            if language.map_or(true, |wanted| wanted == lang) {
+                out.insert(lang.clone(), names.iter().cloned().collect());
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_lists_are_per_language() {
+        let names = LowSignalNames::default();
+        assert!(names.contains(Some("rust"), "Ok"));
+        assert!(!names.contains(Some("python"), "Ok"));
+        assert!(names.contains(Some("python"), "self"));
+        assert!(names.contains(Some("go"), "err"));
+        assert!(names.contains(None, "Ok"));
+        assert!(!names.contains(Some("rust"), "GraphStore"));
+    }
+
+    #[test]
+    fn config_extends_or_replaces_the_builtin_lists() {
+        let config: LowSignalConfig = toml::from_str(
+            "names = [\"logger\"]\n[languages]\nPython = [\"Path\"]\nruby = [\"puts\"]\n",
+        )
+        .unwrap();
+        let names = LowSignalNames::from_config(&config);
+        assert!(names.contains(Some("java"), "logger"));
+        assert!(names.contains(Some("python"), "Path"));
+        assert!(names.contains(Some("python"), "self"));
+        assert!(names.contains(Some("ruby"), "puts"));
+
+        let effective = names.effective(Some("ruby"));
+        assert_eq!(
+            effective.keys().collect::<Vec<_>>(),
+            vec![ALL_LANGUAGES, "ruby"]
+        );
+
+        let replaced = LowSignalNames::from_config(&LowSignalConfig {
+            replace_defaults: true,
+            ..config
+        });
+        assert!(!replaced.contains(Some("python"), "self"));
+        assert!(replaced.contains(Some("python"), "Path"));
+    }
+}
@@ -0,0 +1,471 @@
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use crate::error::{coded, error_code, ErrorCode};
+use crate::fileops;
+use crate::mcp::{
+    error_response, percent_decode, read_frame, success_response, write_frame, FrameStyle,
+};
+use crate::model::{SpanOffsets, SymbolLocation};
+use crate::paths::RuntimePaths;
+use crate::storage::{GraphStore, ReferenceQueryOptions, SortOrder, SymbolSearchOptions};
+
+const MAX_REFERENCES: usize = 1_000;
+const MAX_WORKSPACE_SYMBOLS: usize = 200;
+
+const INVALID_PARAMS: i64 = -32602;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Document sync notifications are ignored: every answer comes from the index
+/// on disk, so it is as fresh as the last `lumora index` or watcher pass.
+pub fn run_lsp_stdio(paths: RuntimePaths) -> Result<()> {
+    let stdin = io::stdin();
+    let mut reader = BufReader::new(stdin.lock());
+    while let Some(frame) = read_frame(&mut reader)? {
+        let message = frame.value;
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            continue;
+        };
+        if method == "exit" {
+            break;
+        }
+        let Some(id) = message.get("id").cloned() else {
+            continue;
+        };
+        let response = handle_lsp_request(method, message.get("params"), id, &paths);
+        write_frame(
+            &mut io::stdout().lock(),
+            &response,
+            FrameStyle::ContentLength,
+        )?;
+    }
+    Ok(())
+}
+
+/// Answers one LSP request with a complete JSON-RPC response.
+pub fn handle_lsp_request(
+    method: &str,
+    params: Option<&Value>,
+    id: Value,
+    paths: &RuntimePaths,
+) -> Value {
+    let params = params.unwrap_or(&Value::Null);
+    let result = match method {
+        "initialize" => Ok(initialize_result()),
+        "shutdown" => Ok(Value::Null),
+        "textDocument/definition" => definition(params, paths),
+        "textDocument/references" => references(params, paths),
+        "textDocument/documentSymbol" => document_symbols(params, paths),
+        "workspace/symbol" => workspace_symbols(params, paths),
+        _ => {
+            return error_response(
+                Some(id),
+                METHOD_NOT_FOUND,
+                &format!("method not supported: {method}"),
+            )
+        }
+    };
+    match result {
+        Ok(result) => success_response(id, result),
+        Err(err) => {
+            let code = match error_code(&err) {
+                ErrorCode::InvalidParams => INVALID_PARAMS,
+                _ => INTERNAL_ERROR,
+            };
+            error_response(Some(id), code, &format!("{err:#}"))
+        }
+    }
+}
+
+fn initialize_result() -> Value {
+    json!({
+        "capabilities": {
+            "positionEncoding": "utf-16",
+            "textDocumentSync": 0,
+            "definitionProvider": true,
+            "referencesProvider": true,
+            "documentSymbolProvider": true,
+            "workspaceSymbolProvider": true
+        },
+        "serverInfo": {
+            "name": "lumora",
+            "version": env!("CARGO_PKG_VERSION")
+        }
+    })
+}
+
+fn definition(params: &Value, paths: &RuntimePaths) -> Result<Value> {
+    let Some((path, identifier, line, col)) = identifier_under_cursor(params, paths)? else {
+        return Ok(Value::Null);
+    };
+    let store = open_store(paths)?;
+    let (definitions, chosen) = store.definition_at(&path, &identifier, line, col)?;
+    Ok(match chosen {
+        Some(index) => symbol_location(paths, &definitions[index]),
+        None => Value::Null,
+    })
+}
+
+fn references(params: &Value, paths: &RuntimePaths) -> Result<Value> {
+    let Some((_, identifier, _, _)) = identifier_under_cursor(params, paths)? else {
+        return Ok(json!([]));
+    };
+    let include_declaration = params
+        .pointer("/context/includeDeclaration")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let store = open_store(paths)?;
+
+    let mut locations = Vec::new();
+    if include_declaration {
+        let pattern = format!("^{}$", regex::escape(&identifier));
+        let definitions = store.symbols_matching(
+            &pattern,
+            &SymbolSearchOptions {
+                limit: MAX_REFERENCES,
+                ..Default::default()
+            },
+        )?;
+        locations.extend(
+            definitions
+                .iter()
+                .filter(|definition| definition.symbol_name == identifier)
+                .map(|definition| symbol_location(paths, definition)),
+        );
+    }
+    let (rows, _) = store.symbol_references_page(
+        &identifier,
+        &ReferenceQueryOptions {
+            limit: MAX_REFERENCES,
+            order: SortOrder::LineAsc,
+            dedup: true,
+            ..Default::default()
+        },
+    )?;
+    locations.extend(rows.iter().map(|row| {
+        location(
+            paths,
+            &row.file_path,
+            lsp_range(
+                row.line,
+                row.col,
+                row.end_line,
+                row.end_col,
+                row.offsets.as_ref(),
+            ),
+        )
+    }));
+    Ok(Value::Array(locations))
+}
+
+fn document_symbols(params: &Value, paths: &RuntimePaths) -> Result<Value> {
+    let path = document_path(params, paths)?;
+    let store = open_store(paths)?;
+    let symbols = store.file_definitions(&path)?;
+    Ok(Value::Array(
+        symbols
+            .iter()
+            .map(|symbol| symbol_information(paths, symbol))
+            .collect(),
+    ))
+}
+
+fn workspace_symbols(params: &Value, paths: &RuntimePaths) -> Result<Value> {
+    let query = params.get("query").and_then(Value::as_str).unwrap_or("");
+    let pattern = if query.is_empty() {
+        ".".to_string()
+    } else {
+        format!("(?i){}", regex::escape(query))
+    };
+    let store = open_store(paths)?;
+    let symbols = store.symbols_matching(
+        &pattern,
+        &SymbolSearchOptions {
+            limit: MAX_WORKSPACE_SYMBOLS,
+            ..Default::default()
+        },
+    )?;
+    Ok(Value::Array(
+        symbols
+            .iter()
+            .map(|symbol| symbol_information(paths, symbol))
+            .collect(),
+    ))
+}
+
+fn open_store(paths: &RuntimePaths) -> Result<GraphStore> {
+    if !paths.db_path.exists() {
+        return Err(coded(
+            ErrorCode::IndexEmpty,
+            format!(
+                "no index at {}; run `lumora index` first",
+                paths.db_path.display()
+            ),
+        ));
+    }
+    GraphStore::open(&paths.db_path)
+}
+
+fn document_path(params: &Value, paths: &RuntimePaths) -> Result<String> {
+    let uri = params
+        .pointer("/textDocument/uri")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid_params("missing `textDocument.uri`"))?;
+    let raw = uri
+        .strip_prefix("file://")
+        .ok_or_else(|| invalid_params(format!("not a file uri: {uri}")))?;
+    let decoded =
+        percent_decode(raw).ok_or_else(|| invalid_params(format!("malformed uri: {uri}")))?;
+    // `file:///C:/src` decodes to `/C:/src`; drop the slash before a drive letter.
+    let decoded = match decoded.as_bytes() {
+        [b'/', _, b':', ..] => decoded[1..].to_string(),
+        _ => decoded,
+    };
+    let abs_path = Path::new(&decoded);
+    let relative = abs_path
+        .strip_prefix(&paths.repo_root)
+        .map(Path::to_path_buf)
+        .ok()
+        .or_else(|| {
+            // Editors may report a symlink-resolved path (e.g. `/private/var` on macOS).
+            let canonical_root = paths.repo_root.canonicalize().ok()?;
+            let canonical_path = abs_path.canonicalize().ok()?;
+            Some(
+                canonical_path
+                    .strip_prefix(canonical_root)
+                    .ok()?
+                    .to_path_buf(),
+            )
+        })
+        .ok_or_else(|| {
+            invalid_params(format!(
+                "{uri} is outside the repository at {}",
+                paths.repo_root.display()
+            ))
+        })?;
+    Ok(relative.to_string_lossy().replace('\\', "/"))
+}
+
+fn identifier_under_cursor(
+    params: &Value,
+    paths: &RuntimePaths,
+) -> Result<Option<(String, String, i64, i64)>> {
+    let path = document_path(params, paths)?;
+    let line = params
+        .pointer("/position/line")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| invalid_params("missing `position.line`"))? as usize;
+    let character = params
+        .pointer("/position/character")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| invalid_params("missing `position.character`"))?
+        as usize;
+    let abs_path = fileops::safe_resolve_path(&paths.repo_root, &path)?;
+    let source = fs::read_to_string(&abs_path)?;
+    let Some(text) = source.lines().nth(line) else {
+        return Ok(None);
+    };
+    let col = utf16_to_byte(text, character) + 1;
+    Ok(fileops::identifier_at(&source, line + 1, col)
+        .map(|identifier| (path, identifier.to_string(), line as i64 + 1, col as i64)))
+}
+
+fn utf16_to_byte(text: &str, character: usize) -> usize {
+    let mut units = 0;
+    for (byte, ch) in text.char_indices() {
+        if units >= character {
+            return byte;
+        }
+        units += ch.len_utf16();
+    }
+    text.len()
+}
+
+/// Rows without recorded UTF-16 offsets fall back to byte columns, which
+/// agree for ASCII lines.
+fn lsp_range(
+    line: i64,
+    col: i64,
+    end_line: Option<i64>,
+    end_col: Option<i64>,
+    offsets: Option<&SpanOffsets>,
+) -> Value {
+    let start_character = offsets.map_or(col, |offsets| offsets.col_utf16) - 1;
+    let end_line = end_line.unwrap_or(line);
+    let end_character = match (offsets, end_col) {
+        (Some(offsets), _) => offsets.end_col_utf16 - 1,
+        (None, Some(end_col)) => end_col - 1,
+        (None, None) => start_character,
+    };
+    json!({
+        "start": { "line": line - 1, "character": start_character.max(0) },
+        "end": { "line": end_line - 1, "character": end_character.max(0) }
+    })
+}
+
+fn location(paths: &RuntimePaths, rel_path: &str, range: Value) -> Value {
+    json!({ "uri": file_uri(&paths.repo_root.join(rel_path)), "range": range })
+}
+
+fn symbol_location(paths: &RuntimePaths, symbol: &SymbolLocation) -> Value {
+    location(
+        paths,
+        &symbol.file_path,
+        lsp_range(
+            symbol.line,
+            symbol.col,
+            symbol.end_line,
+            symbol.end_col,
+            symbol.offsets.as_ref(),
+        ),
+    )
+}
+
+fn symbol_information(paths: &RuntimePaths, symbol: &SymbolLocation) -> Value {
+    let mut info = json!({
+        "name": symbol.symbol_name,
+        "kind": symbol_kind(&symbol.kind),
+        "location": symbol_location(paths, symbol),
+    });
+    if let Some((container, _)) = symbol.qualname.rsplit_once("::") {
+        info["containerName"] = container.into();
+    }
+    info
+}
+
+fn symbol_kind(kind: &str) -> u8 {
+    const KINDS: &[(&str, u8)] = &[
+        ("method", 6),
+        ("constructor", 9),
+        ("function", 12),
+        ("class", 5),
+        ("struct", 23),
+        ("enum", 10),
+        ("interface", 11),
+        ("trait", 11),
+        ("mod", 2),
+        ("namespace", 3),
+        ("const", 14),
+        ("static", 14),
+        ("field", 8),
+        ("type", 26),
+    ];
+    KINDS
+        .iter()
+        .find(|(needle, _)| kind.contains(needle))
+        .map_or(13, |(_, code)| *code)
+}
+
+fn file_uri(path: &Path) -> String {
+    let text = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !text.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~:".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri
+}
+
+fn invalid_params(message: impl Into<String>) -> anyhow::Error {
+    coded(ErrorCode::InvalidParams, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::{index_repository, IndexOptions};
+    use tempfile::TempDir;
+
+    #[test]
+    fn answers_definition_references_and_symbol_requests() {
+        let dir = TempDir::new().unwrap();
+        let repo_root = dir.path().canonicalize().unwrap();
+        let state_dir = repo_root.join(".lumora");
+        fs::create_dir_all(&state_dir).unwrap();
+        let paths = RuntimePaths {
+            db_path: state_dir.join("graph.db"),
+            repo_root: repo_root.clone(),
+            state_dir,
+        };
+        fs::create_dir_all(repo_root.join("src")).unwrap();
+        fs::write(
+            repo_root.join("src/lib.rs"),
+            "pub fn add(a: u32, b: u32) -> u32 {\n    a + b\n}\n\n\
+             fn main() {\n    let é = add(1, 2);\n    add(é, 3);\n}\n",
+        )
+        .unwrap();
+        let mut store = GraphStore::open(&paths.db_path).unwrap();
+        index_repository(&mut store, &repo_root, IndexOptions::default()).unwrap();
+
+        let uri = file_uri(&repo_root.join("src/lib.rs"));
+        let request = |method: &str, params: Value| {
+            let response = handle_lsp_request(method, Some(&params), json!(1), &paths);
+            assert!(response.get("error").is_none(), "{response}");
+            response["result"].clone()
+        };
+
+        let init = request("initialize", json!({}));
+        assert_eq!(init["capabilities"]["definitionProvider"], true);
+
+        // `add(é, 3)`: the identifier starts after 4 spaces; `é` is one UTF-16 unit.
+        let at_call = json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": 6, "character": 5 },
+            "context": { "includeDeclaration": true }
+        });
+        let definition = request("textDocument/definition", at_call.clone());
+        assert_eq!(definition["uri"], uri);
+        assert_eq!(
+            definition["range"]["start"],
+            json!({"line": 0, "character": 0})
+        );
+        assert_eq!(definition["range"]["end"]["line"], 2);
+
+        let references = request("textDocument/references", at_call);
+        let lines: Vec<i64> = references
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|location| location["range"]["start"]["line"].as_i64().unwrap())
+            .collect();
+        assert_eq!(lines.first(), Some(&0));
+        assert!(lines.contains(&5) && lines.contains(&6), "{lines:?}");
+
+        let symbols = request(
+            "textDocument/documentSymbol",
+            json!({ "textDocument": { "uri": uri } }),
+        );
+        let names: Vec<&str> = symbols
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|symbol| symbol["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["add", "main"]);
+        assert_eq!(symbols[0]["kind"], 12);
+
+        let found = request("workspace/symbol", json!({ "query": "AD" }));
+        assert_eq!(found[0]["name"], "add");
+
+        let unknown = handle_lsp_request("textDocument/hover", None, json!(2), &paths);
+        assert_eq!(unknown["error"]["code"], METHOD_NOT_FOUND);
+        let outside = handle_lsp_request(
+            "textDocument/documentSymbol",
+            Some(&json!({ "textDocument": { "uri": "file:///elsewhere/x.rs" } })),
+            json!(3),
+            &paths,
+        );
+        assert_eq!(outside["error"]["code"], INVALID_PARAMS);
+    }
+}
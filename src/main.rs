@@ -1,13 +1,36 @@
+mod ast_search;
+mod bundle;
+mod codeowners;
+mod config;
+mod csv;
 mod daemon;
+mod doctor;
+mod embeddings;
+mod entrypoints;
+mod error;
+mod events;
 mod fileops;
+mod graph_diff;
+mod graph_query;
+mod history;
+mod ignore;
 mod indexer;
+mod init;
 mod languages;
+mod low_signal;
+mod lsp;
 mod mcp;
 mod model;
+mod oplog;
+mod packages;
 mod parser;
 mod paths;
+mod service;
+mod snapshot;
 mod storage;
+mod tui;
 
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
@@ -16,23 +39,39 @@ use clap::{ArgAction, Args, Parser, Subcommand};
 use serde::Serialize;
 use serde_json::json;
 
-use crate::indexer::{index_repository, IndexOptions};
-use crate::mcp::run_mcp_stdio;
-use crate::paths::{ensure_state_layout, resolve_runtime_paths, RuntimePaths};
+use crate::daemon::UpkeepOverrides;
+use crate::error::{coded, error_code, error_json, ErrorCode};
+use crate::indexer::{
+    index_repository, unsupported_files, FingerprintMode, IndexOptions, IndexProfile,
+};
+use crate::low_signal::LowSignalNames;
+use crate::mcp::{run_mcp_stdio, ToolFilter};
+use crate::model::{ImportDirection, LanguageReport, PathHop, TopFileSummary};
+use crate::paths::{
+    ensure_distinct_repos, ensure_state_layout, load_workspace_config, resolve_runtime_paths,
+    RuntimePaths,
+};
 use crate::storage::{
-    CloneQueryOptions, GraphStore, ReferenceQueryOptions, SliceQueryOptions, SortOrder,
+    CloneQueryOptions, DirOutlineOptions, GraphStore, PaginationInfo, ReferenceQueryOptions,
+    SliceQueryOptions, SortOrder, SymbolSearchOptions,
 };
 
 #[derive(Debug, Parser)]
 #[command(name = "lumora")]
 #[command(about = "Local semantic code graph + query engine", version)]
 struct Cli {
+    /// Keep state in ~/.local/share/lumora/<repo-hash>/ instead of `.lumora/`
+    /// in the repo. Same as `LUMORA_STATE=global`.
+    #[arg(long, global = true)]
+    global_state: bool,
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Debug, Subcommand)]
 enum Commands {
+    /// Set up Lumora in a repository: state dir, starter config, first index.
+    Init(InitArgs),
     /// Index a repository incrementally into a local sqlite graph.
     Index(IndexArgs),
     /// Run event-driven watcher daemon for continuous refresh.
@@ -41,10 +80,33 @@ enum Commands {
     Query(QueryArgs),
     /// Run as an MCP stdio server for agent/tool integration.
     Mcp(McpArgs),
+    /// Run as a minimal Language Server (definition, references, symbols) over stdio.
+    Lsp(LspArgs),
     /// Register Lumora as a Codex MCP server from this machine.
     SetupCodex(SetupCodexArgs),
     /// Print generic MCP client config JSON snippet.
     PrintMcpConfig(PrintMcpConfigArgs),
+    /// Install a login service (systemd, launchd, or a scheduled task) that
+    /// runs `lumora serve` for a repository.
+    SetupService(SetupServiceArgs),
+    /// Browse the graph interactively in the terminal.
+    Tui(TuiArgs),
+    /// Check the database, state dir, grammars, watcher, and git setup.
+    Doctor(DoctorArgs),
+    /// Checkpoint and restore the graph database.
+    Snapshot(SnapshotArgs),
+    /// Audit and undo file changes made through MCP write tools.
+    Oplog(OplogArgs),
+    /// Compare two index states: symbols and dependency edges added or removed.
+    Diff(DiffArgs),
+    /// Remove graph rows left behind for untracked files and orphaned nodes.
+    Prune(PruneArgs),
+    /// Check the graph for dangling edges, stale rows, and duplicates.
+    Verify(VerifyArgs),
+    /// Pack the graph database and a manifest into a portable `.tar.zst` bundle.
+    ExportBundle(ExportBundleArgs),
+    /// Replace the graph database with one from a bundle made by `export-bundle`.
+    ImportBundle(ImportBundleArgs),
 }
 
 #[derive(Debug, Args)]
@@ -57,22 +119,100 @@ struct IndexArgs {
     db: Option<PathBuf>,
     #[arg(long)]
     full: bool,
+    /// Compute local model embeddings for `lumora.semantic_search` (stays
+    /// enabled for later runs). Needs a build with the `semantic` feature.
+    #[arg(long, conflicts_with = "no_embeddings")]
+    embeddings: bool,
+    /// Stop computing embeddings and delete the stored vectors.
+    #[arg(long)]
+    no_embeddings: bool,
+    /// Clone fingerprint input: `code` strips comments and string literals,
+    /// `raw` hashes the file text as-is. Changing it triggers a full rebuild.
+    #[arg(long, value_parser = ["code", "raw"])]
+    fingerprints: Option<String>,
+    /// Store names with no definition in the repo (stdlib, third-party) as
+    /// `external` entities. The setting is remembered across runs.
+    #[arg(long, value_parser = ["on", "off"])]
+    external_entities: Option<String>,
+    /// Leave out matching paths, e.g. `proto_gen/` or `*.pb.go`; repeatable.
+    /// Adds to `LUMORA_IGNORE`.
+    #[arg(long = "ignore-glob", value_name = "GLOB")]
+    ignore_globs: Vec<String>,
+    /// Only index this directory or file (repo-relative); repeatable. Files
+    /// already indexed elsewhere are kept as they are.
+    #[arg(long = "path", value_name = "PATH")]
+    paths: Vec<String>,
+    /// Only index these languages, e.g. `rust,python`.
+    #[arg(long = "language", value_name = "LANG", value_delimiter = ',')]
+    languages: Vec<String>,
+    /// Re-parse just this file (repo-relative) without walking the repo;
+    /// repeatable. A file that was deleted is dropped from the index.
+    #[arg(long = "file", value_name = "FILE", conflicts_with_all = ["paths", "languages"])]
+    files: Vec<String>,
+    /// Lazy mode for very large repos: only the `--path`s indexed so far and
+    /// whatever MCP queries name are parsed. Remembered across runs.
+    #[arg(long, value_parser = ["on", "off"])]
+    lazy: Option<String>,
+    /// Report the slowest files, time per language, and time spent parsing,
+    /// fingerprinting, and writing to SQLite.
+    #[arg(long)]
+    profile: bool,
     #[arg(long)]
     json: bool,
 }
 
 #[derive(Debug, Args)]
-struct ServeArgs {
+struct InitArgs {
     #[arg(long)]
     repo: Option<PathBuf>,
     #[arg(long)]
     state_dir: Option<PathBuf>,
     #[arg(long)]
     db: Option<PathBuf>,
+    /// Append the state dir to the repo's `.gitignore`.
+    #[arg(long)]
+    gitignore: bool,
+    /// Run a first index once the repo is set up.
+    #[arg(long)]
+    index: bool,
+    /// Server name used in the printed MCP client config.
+    #[arg(long, default_value = "lumora")]
+    name: String,
+    /// Command used in the printed MCP client config.
+    #[arg(long, default_value = "lumora")]
+    command: String,
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Args)]
+struct ServeArgs {
+    /// Repository to watch; repeat to watch several, each with its own DB.
+    #[arg(long)]
+    repo: Vec<PathBuf>,
+    /// JSON workspace config listing repos to watch alongside any `--repo`.
+    #[arg(long)]
+    workspace: Option<PathBuf>,
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+    #[arg(long)]
+    db: Option<PathBuf>,
     #[arg(long)]
     full_first: bool,
     #[arg(long, default_value_t = 300)]
     debounce_ms: u64,
+    /// Leave out matching paths, e.g. `proto_gen/` or `*.pb.go`; repeatable.
+    /// Adds to `LUMORA_IGNORE`.
+    #[arg(long = "ignore-glob", value_name = "GLOB")]
+    ignore_globs: Vec<String>,
+    /// Minutes between `PRAGMA optimize` / `ANALYZE` passes after the graph
+    /// changes; 0 turns them off. Defaults to `[serve]` in `.lumora.toml`, or 60.
+    #[arg(long, value_name = "MINUTES")]
+    optimize_interval_mins: Option<u64>,
+    /// During optimize passes, compact the database with `VACUUM INTO` when
+    /// enough of it is free space.
+    #[arg(long)]
+    compact: bool,
     #[arg(long)]
     json: bool,
 }
@@ -105,6 +245,203 @@ struct McpArgs {
     auto_index: bool,
     #[arg(long)]
     full_first: bool,
+    /// Only advertise and accept these tools: groups (`index`, `query`,
+    /// `read`, `write`) or tool names, comma-separated. Overrides
+    /// `[mcp] tools` in `.lumora.toml`.
+    #[arg(long, value_delimiter = ',')]
+    tools: Option<Vec<String>>,
+    /// Hide and refuse these tools or groups, e.g. `write`; added to
+    /// `[mcp] deny_tools`.
+    #[arg(long, value_delimiter = ',')]
+    deny_tools: Vec<String>,
+    /// Also offer tools whose arguments or output may still change, such as
+    /// `lumora.semantic_search`.
+    #[arg(long)]
+    experimental: bool,
+    /// Split `tools/list` into pages of this many tools, for clients with a
+    /// small tool budget. Overrides `[mcp] tools_page_size`.
+    #[arg(long)]
+    tools_page_size: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+struct TuiArgs {
+    #[arg(long)]
+    repo: Option<PathBuf>,
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+    #[arg(long)]
+    db: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct LspArgs {
+    #[arg(long)]
+    repo: Option<PathBuf>,
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+    #[arg(long)]
+    db: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct DoctorArgs {
+    #[arg(long)]
+    repo: Option<PathBuf>,
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+    #[arg(long)]
+    db: Option<PathBuf>,
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Args)]
+struct SnapshotArgs {
+    #[arg(long)]
+    repo: Option<PathBuf>,
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+    #[arg(long)]
+    db: Option<PathBuf>,
+    #[arg(long)]
+    json: bool,
+    #[command(subcommand)]
+    command: SnapshotCommands,
+}
+
+#[derive(Debug, Args)]
+struct OplogArgs {
+    #[arg(long)]
+    repo: Option<PathBuf>,
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+    #[arg(long)]
+    db: Option<PathBuf>,
+    #[arg(long)]
+    json: bool,
+    #[command(subcommand)]
+    command: OplogCommands,
+}
+
+#[derive(Debug, Args)]
+struct PruneArgs {
+    #[arg(long)]
+    repo: Option<PathBuf>,
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+    #[arg(long)]
+    db: Option<PathBuf>,
+    /// Report what would be deleted without changing the database.
+    #[arg(long)]
+    dry_run: bool,
+    /// Run VACUUM afterwards to reclaim disk space.
+    #[arg(long)]
+    vacuum: bool,
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Args)]
+struct VerifyArgs {
+    #[arg(long)]
+    repo: Option<PathBuf>,
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+    #[arg(long)]
+    db: Option<PathBuf>,
+    /// Repair what was found instead of only reporting it.
+    #[arg(long)]
+    fix: bool,
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Args)]
+struct ExportBundleArgs {
+    /// Archive to write, e.g. `index.tar.zst`.
+    out: PathBuf,
+    #[arg(long)]
+    repo: Option<PathBuf>,
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+    #[arg(long)]
+    db: Option<PathBuf>,
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Args)]
+struct ImportBundleArgs {
+    /// Bundle written by `lumora export-bundle`.
+    bundle: PathBuf,
+    #[arg(long)]
+    repo: Option<PathBuf>,
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+    #[arg(long)]
+    db: Option<PathBuf>,
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Args)]
+struct DiffArgs {
+    #[arg(long)]
+    repo: Option<PathBuf>,
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+    #[arg(long)]
+    db: Option<PathBuf>,
+    /// Graph database for the earlier state, such as a snapshot.
+    #[arg(
+        long,
+        conflicts_with = "before_rev",
+        required_unless_present = "before_rev"
+    )]
+    before: Option<PathBuf>,
+    /// Graph database for the later state; defaults to the current index.
+    #[arg(long, conflicts_with = "after_rev")]
+    after: Option<PathBuf>,
+    /// Git revision to index into a temporary worktree as the earlier state.
+    #[arg(long)]
+    before_rev: Option<String>,
+    /// Git revision to index into a temporary worktree as the later state.
+    #[arg(long)]
+    after_rev: Option<String>,
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum SnapshotCommands {
+    /// Copy the current graph into `<state-dir>/snapshots/<name>.db`.
+    Create {
+        /// Snapshot name; defaults to the current UTC time (YYYYMMDD-HHMMSS).
+        name: Option<String>,
+    },
+    /// List saved snapshots.
+    List,
+    /// Replace the current graph with a saved snapshot.
+    Restore { name: String },
+}
+
+#[derive(Debug, Subcommand)]
+enum OplogCommands {
+    /// List recent entries, or show one entry's files and arguments.
+    Show {
+        id: Option<u64>,
+        /// Most recent entries to list.
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Put back every file an entry changed, logged as a new entry.
+    Revert {
+        id: u64,
+        /// Overwrite files that changed again after the entry.
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[derive(Debug, Args)]
@@ -123,6 +460,27 @@ struct SetupCodexArgs {
     dry_run: bool,
 }
 
+#[derive(Debug, Args)]
+struct SetupServiceArgs {
+    #[arg(long)]
+    repo: Option<PathBuf>,
+    #[arg(long)]
+    state_dir: Option<PathBuf>,
+    #[arg(long)]
+    db: Option<PathBuf>,
+    /// Lumora binary the service runs; defaults to this one.
+    #[arg(long)]
+    command: Option<PathBuf>,
+    /// Service manager to target; defaults to the one for this OS.
+    #[arg(long, value_parser = ["systemd", "launchd", "windows"])]
+    platform: Option<String>,
+    /// Print the service definition and commands without installing.
+    #[arg(long)]
+    dry_run: bool,
+    #[arg(long)]
+    json: bool,
+}
+
 #[derive(Debug, Args)]
 struct PrintMcpConfigArgs {
     #[arg(long)]
@@ -136,16 +494,50 @@ struct PrintMcpConfigArgs {
 #[derive(Debug, Subcommand)]
 enum QueryCommands {
     /// Find where a symbol is defined.
-    Symbol { name: String },
+    Symbol {
+        name: String,
+        /// Print CSV with a header row instead of text.
+        #[arg(long)]
+        csv: bool,
+    },
+    /// Find definitions whose name or qualified name matches a regex.
+    Symbols {
+        #[arg(long)]
+        regex: String,
+        /// Only definitions whose kind contains this text (e.g. `function`, `struct`).
+        #[arg(long)]
+        kind: Option<String>,
+        #[arg(long)]
+        language: Option<String>,
+        /// Only definitions with this visibility, as recorded at index time.
+        #[arg(long, value_parser = ["public", "private", "protected", "internal"])]
+        visibility: Option<String>,
+        /// Only definitions whose name is never referenced or called, e.g.
+        /// `--visibility private --unreferenced` for likely dead code.
+        #[arg(long)]
+        unreferenced: bool,
+        /// `false` drops definitions in test files; with `--unreferenced`,
+        /// names only tests use then count as unreferenced too.
+        #[arg(long, default_value_t = true, action = ArgAction::Set)]
+        include_tests: bool,
+        /// With `--unreferenced`, keep definitions in generated files.
+        #[arg(long)]
+        include_generated: bool,
+        #[arg(long, default_value_t = 200)]
+        limit: usize,
+    },
     /// Find where a symbol is referenced.
     Refs {
         name: String,
         #[arg(long)]
         calls_only: bool,
-        #[arg(long, default_value_t = 200)]
+        #[arg(long, default_value_t = 200, visible_alias = "per-page")]
         limit: usize,
         #[arg(long, default_value_t = 0)]
         offset: usize,
+        /// Show page N (from 1) of `--per-page` rows instead of an offset.
+        #[arg(long, conflicts_with = "offset", value_parser = clap::value_parser!(u64).range(1..))]
+        page: Option<u64>,
         #[arg(long, default_value_t = true, action = ArgAction::Set)]
         dedup: bool,
         #[arg(long, default_value = "score_desc")]
@@ -156,16 +548,47 @@ enum QueryCommands {
         language: Option<String>,
         #[arg(long)]
         max_age_hours: Option<u64>,
+        /// Only show references bound to a definition in the same file or an import.
+        #[arg(long)]
+        resolved_only: bool,
+        /// Skip names marked `external` (no definition in the repo).
+        #[arg(long)]
+        exclude_externals: bool,
+        /// Hide references whose confidence (0-1) is below this.
+        #[arg(long)]
+        min_confidence: Option<f64>,
+        /// Skip edges the indexer inferred heuristically rather than read from the AST.
+        #[arg(long)]
+        exclude_heuristic: bool,
+        /// `false` hides references made from test files.
+        #[arg(long, default_value_t = true, action = ArgAction::Set)]
+        include_tests: bool,
+        /// Keep references made from generated files (`@generated`, `DO NOT EDIT`).
+        #[arg(long)]
+        include_generated: bool,
         #[arg(long)]
         top_files: bool,
+        /// Count references per innermost enclosing function/class instead of listing lines.
+        #[arg(long, value_parser = ["enclosing_symbol"])]
+        group_by: Option<String>,
+        /// Print every row from `--offset` on, one JSON object per line,
+        /// instead of a pretty-printed page; `--limit` does not cut it short.
+        #[arg(long)]
+        jsonl: bool,
+        /// Print CSV with a header row instead of text.
+        #[arg(long, conflicts_with = "jsonl")]
+        csv: bool,
     },
     /// Find call sites for a symbol.
     Callers {
         name: String,
-        #[arg(long, default_value_t = 200)]
+        #[arg(long, default_value_t = 200, visible_alias = "per-page")]
         limit: usize,
         #[arg(long, default_value_t = 0)]
         offset: usize,
+        /// Show page N (from 1) of `--per-page` rows instead of an offset.
+        #[arg(long, conflicts_with = "offset", value_parser = clap::value_parser!(u64).range(1..))]
+        page: Option<u64>,
         #[arg(long, default_value_t = true, action = ArgAction::Set)]
         dedup: bool,
         #[arg(long, default_value = "score_desc")]
@@ -176,8 +599,122 @@ enum QueryCommands {
         language: Option<String>,
         #[arg(long)]
         max_age_hours: Option<u64>,
+        /// Only show references bound to a definition in the same file or an import.
+        #[arg(long)]
+        resolved_only: bool,
+        /// Skip names marked `external` (no definition in the repo).
+        #[arg(long)]
+        exclude_externals: bool,
+        /// Hide references whose confidence (0-1) is below this.
+        #[arg(long)]
+        min_confidence: Option<f64>,
+        /// Skip edges the indexer inferred heuristically rather than read from the AST.
+        #[arg(long)]
+        exclude_heuristic: bool,
+        /// `false` hides references made from test files.
+        #[arg(long, default_value_t = true, action = ArgAction::Set)]
+        include_tests: bool,
+        /// Keep references made from generated files (`@generated`, `DO NOT EDIT`).
+        #[arg(long)]
+        include_generated: bool,
         #[arg(long)]
         top_files: bool,
+        /// Print every row from `--offset` on, one JSON object per line,
+        /// instead of a pretty-printed page; `--limit` does not cut it short.
+        #[arg(long)]
+        jsonl: bool,
+        /// Print CSV with a header row instead of text.
+        #[arg(long, conflicts_with = "jsonl")]
+        csv: bool,
+    },
+    /// Outline stored definitions for every indexed file under a directory.
+    Outline {
+        #[arg(default_value = ".")]
+        dir: String,
+        #[arg(long)]
+        max_depth: Option<usize>,
+        #[arg(long, default_value_t = 200)]
+        max_files: usize,
+    },
+    /// List TODO / FIXME / HACK comments with their file and line.
+    Todos {
+        #[arg(long)]
+        file_glob: Option<String>,
+        /// Only these markers, e.g. `fixme,hack`.
+        #[arg(long = "tag", value_delimiter = ',')]
+        tags: Vec<String>,
+    },
+    /// Find test functions that exercise a symbol.
+    TestsFor { name: String },
+    /// List program entry points (Cargo bins, npm scripts, `main` functions)
+    /// and the repo symbols each one calls.
+    Entrypoints {
+        /// Called names to list per entry point.
+        #[arg(long, default_value_t = 10)]
+        max_calls: usize,
+    },
+    /// Run a Cypher-like query over the graph, e.g.
+    /// `MATCH (f:file)-[:imports]->(m:module) WHERE f.path GLOB 'src/*' RETURN f.path, m.name`.
+    Graph {
+        query: String,
+        /// Rows to return when the query has no `LIMIT`.
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+    },
+    /// Print the low-signal names slices rank down, per language, after
+    /// `[low_signal]` in `.lumora.toml` is applied.
+    LowSignal {
+        /// Only this language (plus names shared by every language).
+        #[arg(long)]
+        language: Option<String>,
+    },
+    /// Per-language coverage plus discovered files that were not indexed.
+    Languages {
+        /// Example paths to list per unsupported extension.
+        #[arg(long, default_value_t = 3)]
+        examples: usize,
+    },
+    /// List the files a file imports, or (`--direction in`) the files importing it.
+    Imports {
+        file: String,
+        /// Follow imports of imports, annotating each file with its depth.
+        #[arg(long)]
+        transitive: bool,
+        #[arg(long, default_value = "out", value_parser = ["out", "in"])]
+        direction: String,
+        /// Depth limit for `--transitive`.
+        #[arg(long, default_value_t = 10)]
+        max_depth: usize,
+    },
+    /// List the files whose imports name a module, with lines and a
+    /// per-directory count.
+    WhoImports {
+        module: String,
+        /// Also match submodules and items under it, e.g. `crate::storage`
+        /// covers `crate::storage::GraphStore`.
+        #[arg(long)]
+        submodules: bool,
+        /// Only imports written in this language, e.g. `python`.
+        #[arg(long)]
+        language: Option<String>,
+    },
+    /// List files the last index run failed to read, parse, or store.
+    Errors,
+    /// Aggregate file dependencies into a directory-level edge list / matrix.
+    ModuleDeps {
+        /// Number of leading directory components that define a module.
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..=2))]
+        level: u64,
+        /// Print the adjacency matrix as TSV instead of an edge list.
+        #[arg(long)]
+        matrix: bool,
+    },
+    /// Roll file dependencies up to crate / npm package edges.
+    PackageDeps {
+        /// Keep every detected package (README / `mod.rs` directories too)
+        /// instead of rolling files up to the nearest manifest.
+        #[arg(long)]
+        all_packages: bool,
     },
     /// Find dependency path A -> B using graph edges.
     Deps {
@@ -186,6 +723,16 @@ enum QueryCommands {
         #[arg(long, default_value_t = 8)]
         max_depth: usize,
     },
+    /// List up to K distinct dependency paths A -> B, shortest first, with
+    /// the edge type of each hop.
+    PathAll {
+        from: String,
+        to: String,
+        #[arg(long, default_value_t = 5)]
+        k: usize,
+        #[arg(long, default_value_t = 8)]
+        max_depth: usize,
+    },
     /// Return a minimal context slice around file/line.
     Slice {
         file: String,
@@ -203,32 +750,199 @@ enum QueryCommands {
         low_signal_name_cap: usize,
         #[arg(long, default_value_t = true, action = ArgAction::Set)]
         prefer_project_symbols: bool,
+        /// Leave `external` names (no definition in the repo) out of the slice.
+        #[arg(long)]
+        exclude_externals: bool,
+        /// Skip edges the indexer inferred heuristically, e.g. test links.
+        #[arg(long)]
+        exclude_heuristic: bool,
     },
     /// Find similar files by token-winnowing fingerprints.
     Clones {
         file: String,
         #[arg(long, default_value_t = 0.02)]
         min_similarity: f64,
-        #[arg(long, default_value_t = 50)]
+        #[arg(long, default_value_t = 50, visible_alias = "per-page")]
         limit: usize,
         #[arg(long, default_value_t = 0)]
         offset: usize,
+        /// Show page N (from 1) of `--per-page` rows instead of an offset.
+        #[arg(long, conflicts_with = "offset", value_parser = clap::value_parser!(u64).range(1..))]
+        page: Option<u64>,
         #[arg(long)]
         hotspots: bool,
+        /// Skip files in a different language than FILE.
+        #[arg(long)]
+        same_language_only: bool,
+        /// Only match files in this language, e.g. `python`.
+        #[arg(long)]
+        language: Option<String>,
+        /// `false` skips test files.
+        #[arg(long, default_value_t = true, action = ArgAction::Set)]
+        include_tests: bool,
+        /// Keep files marked as generated code.
+        #[arg(long)]
+        include_generated: bool,
+        /// Print every row from `--offset` on, one JSON object per line,
+        /// instead of a pretty-printed page; `--limit` does not cut it short.
+        #[arg(long)]
+        jsonl: bool,
+        /// Print CSV with a header row instead of text.
+        #[arg(long, conflicts_with = "jsonl")]
+        csv: bool,
+    },
+    /// Compare two directory trees for copied files, e.g. modules duplicated
+    /// between services.
+    ClonesDir {
+        dir: String,
+        other_dir: String,
+        #[arg(long, default_value_t = 0.02)]
+        min_similarity: f64,
+        #[arg(long, default_value_t = 50, visible_alias = "per-page")]
+        limit: usize,
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+        /// Show page N (from 1) of `--per-page` rows instead of an offset.
+        #[arg(long, conflicts_with = "offset", value_parser = clap::value_parser!(u64).range(1..))]
+        page: Option<u64>,
+        /// Only pair files that are in the same language.
+        #[arg(long)]
+        same_language_only: bool,
+        /// Only pair files in this language, e.g. `python`.
+        #[arg(long)]
+        language: Option<String>,
+        /// `false` skips test files.
+        #[arg(long, default_value_t = true, action = ArgAction::Set)]
+        include_tests: bool,
+        /// Keep files marked as generated code.
+        #[arg(long)]
+        include_generated: bool,
+        /// Print every row from `--offset` on, one JSON object per line,
+        /// instead of a pretty-printed page; `--limit` does not cut it short.
+        #[arg(long)]
+        jsonl: bool,
+        /// Print CSV with a header row instead of text.
+        #[arg(long, conflicts_with = "jsonl")]
+        csv: bool,
     },
 }
 
+impl Commands {
+    /// Whether the command was asked for machine-readable output, in which
+    /// case failures are reported as a JSON error object as well.
+    fn wants_json(&self) -> bool {
+        match self {
+            Commands::Init(args) => args.json,
+            Commands::Index(args) => args.json,
+            Commands::Serve(args) => args.json,
+            Commands::Query(args) => args.json,
+            Commands::Doctor(args) => args.json,
+            Commands::Snapshot(args) => args.json,
+            Commands::Oplog(args) => args.json,
+            Commands::Diff(args) => args.json,
+            Commands::Prune(args) => args.json,
+            Commands::Verify(args) => args.json,
+            Commands::ExportBundle(args) => args.json,
+            Commands::ImportBundle(args) => args.json,
+            Commands::SetupService(args) => args.json,
+            Commands::Mcp(_)
+            | Commands::Lsp(_)
+            | Commands::SetupCodex(_)
+            | Commands::PrintMcpConfig(_)
+            | Commands::Tui(_) => false,
+        }
+    }
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
+    if cli.global_state {
+        // Set before any path resolution or threads, and inherited by children.
+        std::env::set_var(paths::STATE_LOCATION_ENV, "global");
+    }
+    let json_errors = cli.command.wants_json();
 
-    match cli.command {
+    let result = match cli.command {
+        Commands::Init(args) => run_init(args),
         Commands::Index(args) => run_index(args),
         Commands::Serve(args) => run_serve(args),
         Commands::Query(args) => run_query(args),
         Commands::Mcp(args) => run_mcp(args),
+        Commands::Lsp(args) => run_lsp(args),
         Commands::SetupCodex(args) => run_setup_codex(args),
         Commands::PrintMcpConfig(args) => run_print_mcp_config(args),
+        Commands::SetupService(args) => run_setup_service(args),
+        Commands::Tui(args) => run_tui(args),
+        Commands::Doctor(args) => run_doctor(args),
+        Commands::Snapshot(args) => run_snapshot(args),
+        Commands::Oplog(args) => run_oplog(args),
+        Commands::Diff(args) => run_diff(args),
+        Commands::Prune(args) => run_prune(args),
+        Commands::Verify(args) => run_verify(args),
+        Commands::ExportBundle(args) => run_export_bundle(args),
+        Commands::ImportBundle(args) => run_import_bundle(args),
+    };
+    if let (Err(err), true) = (&result, json_errors) {
+        let message = format!("{err:#}");
+        println!(
+            "{}",
+            json!({ "error": error_json(error_code(err), &message) })
+        );
+        std::process::exit(1);
     }
+    result
+}
+
+fn run_init(args: InitArgs) -> Result<()> {
+    let paths = resolve_paths(
+        args.repo.as_deref(),
+        args.state_dir.as_deref(),
+        args.db.as_deref(),
+    )?;
+    let report = init::init_repository(&paths, args.gitignore)?;
+    let index_report = if args.index {
+        let mut store = GraphStore::open(&paths.db_path)?;
+        Some(index_repository(
+            &mut store,
+            &paths.repo_root,
+            IndexOptions::default(),
+        )?)
+    } else {
+        None
+    };
+    let mcp_config = mcp_config_snippet(&args.name, &args.command, Some(&paths.repo_root));
+
+    if args.json {
+        print_json(&json!({
+            "init": report,
+            "index": index_report,
+            "mcp_config": mcp_config,
+        }))?;
+        return Ok(());
+    }
+
+    println!("repo: {}", report.repo_root);
+    println!("state: {}", report.state_dir);
+    if report.config_created {
+        println!("created: {}", report.config_path);
+    } else {
+        println!("kept existing: {}", report.config_path);
+    }
+    if let Some(entry) = &report.gitignore_entry {
+        println!("gitignore: added `{entry}`");
+    }
+    match &index_report {
+        Some(index) => println!(
+            "indexed: {} files ({} errors)",
+            index.indexed_files,
+            index.errors.len()
+        ),
+        None => println!("Run `lumora index` to build the graph."),
+    }
+    println!();
+    println!("Add this to your MCP client config:");
+    println!("{}", serde_json::to_string_pretty(&mcp_config)?);
+    Ok(())
 }
 
 fn run_index(args: IndexArgs) -> Result<()> {
@@ -243,7 +957,22 @@ fn run_index(args: IndexArgs) -> Result<()> {
     let report = index_repository(
         &mut store,
         &paths.repo_root,
-        IndexOptions { full: args.full },
+        IndexOptions {
+            full: args.full,
+            embeddings: (args.embeddings || args.no_embeddings).then_some(args.embeddings),
+            fingerprint_mode: args
+                .fingerprints
+                .as_deref()
+                .and_then(FingerprintMode::parse),
+            ignore_globs: args.ignore_globs,
+            external_entities: args.external_entities.as_deref().map(|value| value == "on"),
+            scope_paths: args.paths,
+            scope_languages: args.languages,
+            files: args.files,
+            lazy: args.lazy.as_deref().map(|value| value == "on"),
+            profile: args.profile,
+            ..Default::default()
+        },
     )?;
 
     if args.json {
@@ -255,19 +984,289 @@ fn run_index(args: IndexArgs) -> Result<()> {
         println!("indexed: {}", report.indexed_files);
         println!("skipped: {}", report.skipped_files);
         println!("removed: {}", report.removed_files);
+        println!("cached_parses: {}", report.cached_parses);
         println!("parse_failures: {}", report.parse_failures);
         if !report.errors.is_empty() {
             println!("errors:");
-            for error in report.errors {
+            for error in &report.errors {
                 println!("  - {error}");
             }
         }
+        if let Some(profile) = &report.profile {
+            print_index_profile(profile);
+        }
     }
 
     Ok(())
 }
 
+fn print_index_profile(profile: &IndexProfile) {
+    println!("profile: {:.1} ms total", profile.total_ms);
+    println!(
+        "  read {:.1} ms, parse {:.1} ms, fingerprint {:.1} ms, sqlite {:.1} ms, embeddings {:.1} ms",
+        profile.read_ms,
+        profile.parse_ms,
+        profile.fingerprint_ms,
+        profile.store_ms,
+        profile.embeddings_ms
+    );
+    if !profile.languages.is_empty() {
+        println!("by language:");
+        for language in &profile.languages {
+            println!(
+                "  {:<12} {:>5} files {:>9.1} ms (parse {:.1}, fingerprint {:.1}, sqlite {:.1})",
+                language.language,
+                language.files,
+                language.total_ms,
+                language.parse_ms,
+                language.fingerprint_ms,
+                language.store_ms
+            );
+        }
+    }
+    if !profile.slowest_files.is_empty() {
+        println!("slowest files:");
+        for file in &profile.slowest_files {
+            println!(
+                "  {:>9.1} ms {} (parse {:.1}, fingerprint {:.1}, sqlite {:.1})",
+                file.total_ms, file.path, file.parse_ms, file.fingerprint_ms, file.store_ms
+            );
+        }
+    }
+}
+
 fn run_serve(args: ServeArgs) -> Result<()> {
+    let mut repos = match &args.workspace {
+        Some(config) => load_workspace_config(config)?,
+        None => Vec::new(),
+    };
+    if repos.len() + args.repo.len() > 1 && (args.state_dir.is_some() || args.db.is_some()) {
+        anyhow::bail!(
+            "--state-dir and --db apply to a single repo; set per-repo paths in a --workspace config"
+        );
+    }
+    if args.repo.is_empty() && repos.is_empty() {
+        repos.push(resolve_paths(
+            None,
+            args.state_dir.as_deref(),
+            args.db.as_deref(),
+        )?);
+    }
+    for repo in &args.repo {
+        repos.push(resolve_paths(
+            Some(repo),
+            args.state_dir.as_deref(),
+            args.db.as_deref(),
+        )?);
+    }
+    ensure_distinct_repos(&repos)?;
+    for paths in &repos {
+        ensure_state_layout(paths)?;
+    }
+
+    daemon::run_watcher_daemon(
+        &repos,
+        args.full_first,
+        args.debounce_ms,
+        &args.ignore_globs,
+        UpkeepOverrides {
+            optimize_interval_mins: args.optimize_interval_mins,
+            compact: args.compact,
+        },
+        args.json,
+    )
+}
+
+fn run_tui(args: TuiArgs) -> Result<()> {
+    let paths = resolve_paths(
+        args.repo.as_deref(),
+        args.state_dir.as_deref(),
+        args.db.as_deref(),
+    )?;
+    ensure_state_layout(&paths)?;
+
+    tui::run_tui(&paths)
+}
+
+fn run_doctor(args: DoctorArgs) -> Result<()> {
+    let paths = resolve_paths(
+        args.repo.as_deref(),
+        args.state_dir.as_deref(),
+        args.db.as_deref(),
+    )?;
+
+    let report = doctor::run_doctor(&paths);
+    if args.json {
+        print_json(&report)?;
+    } else {
+        println!("repo: {}", report.repo_root);
+        println!("state: {}", report.state_dir);
+        println!("db: {}", report.db_path);
+        for check in &report.checks {
+            println!("[{}] {}: {}", check.status, check.name, check.detail);
+            if let Some(hint) = &check.hint {
+                println!("       hint: {hint}");
+            }
+        }
+    }
+
+    if !report.ok {
+        return Err(anyhow::anyhow!(
+            "{} doctor check(s) failed",
+            report.failures()
+        ));
+    }
+    Ok(())
+}
+
+fn run_prune(args: PruneArgs) -> Result<()> {
+    let paths = resolve_paths(
+        args.repo.as_deref(),
+        args.state_dir.as_deref(),
+        args.db.as_deref(),
+    )?;
+    ensure_state_layout(&paths)?;
+
+    let mut store = GraphStore::open(&paths.db_path)?;
+    let report = store.prune(args.dry_run, args.vacuum)?;
+
+    if args.json {
+        let mut value = serde_json::to_value(&report)?;
+        value["total_rows"] = json!(report.total_rows());
+        print_json(&value)?;
+    } else {
+        let verb = if report.dry_run {
+            "would remove"
+        } else {
+            "removed"
+        };
+        println!("db: {}", paths.db_path.display());
+        println!("fingerprints: {}", report.fingerprints);
+        println!("embeddings: {}", report.embeddings);
+        println!("edges: {}", report.edges);
+        println!("entities: {}", report.entities);
+        println!("orphan_nodes: {}", report.orphan_nodes);
+        println!("parse_cache: {}", report.parse_cache);
+        println!("{verb} {} rows", report.total_rows());
+        if report.vacuumed {
+            println!(
+                "vacuumed: {} -> {} bytes",
+                report.bytes_before,
+                report.bytes_after.unwrap_or(report.bytes_before)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn run_verify(args: VerifyArgs) -> Result<()> {
+    let paths = resolve_paths(
+        args.repo.as_deref(),
+        args.state_dir.as_deref(),
+        args.db.as_deref(),
+    )?;
+    ensure_state_layout(&paths)?;
+
+    let mut store = GraphStore::open(&paths.db_path)?;
+    let report = store.verify(&paths.repo_root, args.fix)?;
+    let repairable = report.issues() - report.integrity_errors.len();
+
+    if args.json {
+        let mut value = serde_json::to_value(&report)?;
+        value["issues"] = json!(report.issues());
+        print_json(&value)?;
+    } else {
+        println!("db: {}", paths.db_path.display());
+        println!("dangling_edges: {}", report.dangling_edges);
+        println!("missing_files: {}", report.missing_files.len());
+        for path in &report.missing_files {
+            println!("  {path}");
+        }
+        println!("stale_entities: {}", report.stale_entities);
+        println!("untracked_fingerprints: {}", report.untracked_fingerprints);
+        println!("untracked_embeddings: {}", report.untracked_embeddings);
+        println!("duplicate_keys: {}", report.duplicate_keys);
+        println!("duplicate_edges: {}", report.duplicate_edges);
+        for error in &report.integrity_errors {
+            println!("integrity: {error}");
+        }
+        if report.fixed {
+            println!("repaired {repairable} issues");
+        }
+    }
+
+    let unrepaired = if report.fixed {
+        report.integrity_errors.len()
+    } else {
+        report.issues()
+    };
+    if unrepaired > 0 {
+        let hint = if report.fixed || repairable == 0 {
+            "rebuild with `lumora index --full`"
+        } else {
+            "run `lumora verify --fix`"
+        };
+        return Err(anyhow::anyhow!(
+            "{unrepaired} index inconsistencies found; {hint}"
+        ));
+    }
+    Ok(())
+}
+
+fn run_export_bundle(args: ExportBundleArgs) -> Result<()> {
+    let paths = resolve_paths(
+        args.repo.as_deref(),
+        args.state_dir.as_deref(),
+        args.db.as_deref(),
+    )?;
+    ensure_state_layout(&paths)?;
+
+    let export = bundle::export_bundle(&paths, &args.out)?;
+    if args.json {
+        print_json(&export)?;
+    } else {
+        println!(
+            "wrote {} ({} bytes, {} files)",
+            export.path, export.size_bytes, export.manifest.file_count
+        );
+        if let Some(head) = &export.manifest.git_head {
+            println!("git head: {head}");
+        }
+    }
+    Ok(())
+}
+
+fn run_import_bundle(args: ImportBundleArgs) -> Result<()> {
+    let paths = resolve_paths(
+        args.repo.as_deref(),
+        args.state_dir.as_deref(),
+        args.db.as_deref(),
+    )?;
+    ensure_state_layout(&paths)?;
+
+    let import = bundle::import_bundle(&paths, &args.bundle)?;
+    if args.json {
+        print_json(&import)?;
+    } else {
+        println!(
+            "imported {} files into {} (exported {})",
+            import.manifest.file_count, import.db_path, import.manifest.created_at
+        );
+        if import.same_git_head == Some(false) {
+            println!(
+                "bundle was built at {}; run `lumora index` to catch up with this checkout",
+                import
+                    .manifest
+                    .git_head
+                    .as_deref()
+                    .unwrap_or("an unknown commit")
+            );
+        }
+    }
+    Ok(())
+}
+
+fn run_snapshot(args: SnapshotArgs) -> Result<()> {
     let paths = resolve_paths(
         args.repo.as_deref(),
         args.state_dir.as_deref(),
@@ -275,7 +1274,244 @@ fn run_serve(args: ServeArgs) -> Result<()> {
     )?;
     ensure_state_layout(&paths)?;
 
-    daemon::run_watcher_daemon(&paths, args.full_first, args.debounce_ms, args.json)
+    match args.command {
+        SnapshotCommands::Create { name } => {
+            let info = snapshot::create_snapshot(&paths, name.as_deref())?;
+            if args.json {
+                print_json(&info)?;
+            } else {
+                println!("created snapshot {} ({} bytes)", info.name, info.size_bytes);
+                println!("path: {}", info.path);
+            }
+        }
+        SnapshotCommands::List => {
+            let snapshots = snapshot::list_snapshots(&paths)?;
+            if args.json {
+                print_json(&json!({ "snapshots": snapshots }))?;
+            } else if snapshots.is_empty() {
+                println!(
+                    "No snapshots in {}",
+                    snapshot::snapshot_dir(&paths).display()
+                );
+            } else {
+                for info in &snapshots {
+                    println!(
+                        "{}\t{} bytes\tschema v{}",
+                        info.name,
+                        info.size_bytes,
+                        info.schema_version.as_deref().unwrap_or("?")
+                    );
+                }
+            }
+        }
+        SnapshotCommands::Restore { name } => {
+            let info = snapshot::restore_snapshot(&paths, &name)?;
+            if args.json {
+                print_json(&json!({ "restored": info }))?;
+            } else {
+                println!(
+                    "restored snapshot {} into {}",
+                    info.name,
+                    paths.db_path.display()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_oplog(args: OplogArgs) -> Result<()> {
+    let paths = resolve_paths(
+        args.repo.as_deref(),
+        args.state_dir.as_deref(),
+        args.db.as_deref(),
+    )?;
+
+    match args.command {
+        OplogCommands::Show { id: Some(id), .. } => {
+            let entries = oplog::read_entries(&paths)?;
+            let Some(entry) = entries.iter().find(|entry| entry.id == id) else {
+                return Err(coded(
+                    ErrorCode::InvalidParams,
+                    format!("no oplog entry #{id}"),
+                ));
+            };
+            let reverted_by = entries
+                .iter()
+                .find(|other| other.reverts == Some(id))
+                .map(|other| other.id);
+            if args.json {
+                print_json(&json!({ "entry": entry, "reverted_by": reverted_by }))?;
+            } else {
+                println!(
+                    "#{} {} {}",
+                    entry.id,
+                    oplog::format_utc(entry.at_ms),
+                    entry.tool
+                );
+                println!("diff: {}", entry.diff_hash);
+                if let Some(undo) = reverted_by {
+                    println!("reverted by #{undo}");
+                }
+                for file in &entry.files {
+                    let short = |hash: &Option<String>| {
+                        hash.as_deref().map_or("(none)".to_string(), |hash| {
+                            hash.get(..12).unwrap_or(hash).to_string()
+                        })
+                    };
+                    println!(
+                        "  {}  {} -> {}",
+                        file.path,
+                        short(&file.before),
+                        short(&file.after)
+                    );
+                }
+                println!("args: {}", serde_json::to_string(&entry.args)?);
+            }
+        }
+        OplogCommands::Show { id: None, limit } => {
+            let entries = oplog::read_entries(&paths)?;
+            let recent = &entries[entries.len().saturating_sub(limit)..];
+            if args.json {
+                print_json(&json!({ "entries": recent, "total": entries.len() }))?;
+            } else if recent.is_empty() {
+                println!(
+                    "No oplog entries in {}",
+                    oplog::oplog_path(&paths).display()
+                );
+            } else {
+                for entry in recent.iter().rev() {
+                    let files: Vec<&str> =
+                        entry.files.iter().map(|file| file.path.as_str()).collect();
+                    let reverted = entries.iter().any(|other| other.reverts == Some(entry.id));
+                    println!(
+                        "#{}\t{}\t{}\t{}{}",
+                        entry.id,
+                        oplog::format_utc(entry.at_ms),
+                        entry.tool,
+                        files.join(", "),
+                        if reverted { "\t(reverted)" } else { "" }
+                    );
+                }
+            }
+        }
+        OplogCommands::Revert { id, force } => {
+            let entry = oplog::revert(&paths, id, force)?;
+            if args.json {
+                print_json(&entry)?;
+            } else {
+                println!("reverted #{id} as #{}", entry.id);
+                for file in &entry.files {
+                    let action = match (&file.before, &file.after) {
+                        (_, None) => "removed",
+                        (None, Some(_)) => "recreated",
+                        (Some(_), Some(_)) => "restored",
+                    };
+                    println!("  {action} {}", file.path);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_diff(args: DiffArgs) -> Result<()> {
+    let paths = resolve_paths(
+        args.repo.as_deref(),
+        args.state_dir.as_deref(),
+        args.db.as_deref(),
+    )?;
+    ensure_state_layout(&paths)?;
+
+    let mut scratch_dbs = Vec::new();
+    let result = (|| {
+        let before = match (&args.before, &args.before_rev) {
+            (Some(path), _) => path.clone(),
+            (None, Some(rev)) => {
+                let db =
+                    graph_diff::index_revision(&paths.repo_root, &paths.state_dir, rev, "before")?;
+                scratch_dbs.push(db.clone());
+                db
+            }
+            (None, None) => unreachable!("clap requires --before or --before-rev"),
+        };
+        let after = match (&args.after, &args.after_rev) {
+            (Some(path), _) => path.clone(),
+            (None, Some(rev)) => {
+                let db =
+                    graph_diff::index_revision(&paths.repo_root, &paths.state_dir, rev, "after")?;
+                scratch_dbs.push(db.clone());
+                db
+            }
+            (None, None) => paths.db_path.clone(),
+        };
+        let mut diff = graph_diff::diff_databases(&before, &after)?;
+        if let Some(rev) = &args.before_rev {
+            diff.before = rev.clone();
+        }
+        if let Some(rev) = &args.after_rev {
+            diff.after = rev.clone();
+        }
+        Ok::<_, anyhow::Error>(diff)
+    })();
+    for db in &scratch_dbs {
+        graph_diff::discard_revision_index(db)?;
+    }
+    let diff = result?;
+
+    if args.json {
+        return print_json(&diff);
+    }
+    println!("before: {}", diff.before);
+    println!("after: {}", diff.after);
+    for symbol in &diff.added_symbols {
+        println!(
+            "+ {}:{} [{}] {}",
+            symbol.file_path, symbol.line, symbol.kind, symbol.qualname
+        );
+    }
+    for symbol in &diff.removed_symbols {
+        println!(
+            "- {}:{} [{}] {}",
+            symbol.file_path, symbol.line, symbol.kind, symbol.qualname
+        );
+    }
+    for symbol in &diff.changed_symbols {
+        match (&symbol.before_signature, &symbol.after_signature) {
+            (Some(before), Some(after)) if before != after => println!(
+                "~ {}:{} {}: {} -> {}",
+                symbol.file_path, symbol.line, symbol.qualname, before, after
+            ),
+            _ => println!(
+                "~ {}:{} {} [{} -> {}]",
+                symbol.file_path,
+                symbol.line,
+                symbol.qualname,
+                symbol.before_kind,
+                symbol.after_kind
+            ),
+        }
+    }
+    for dependency in &diff.added_dependencies {
+        println!("+ dep {} -> {}", dependency.from_file, dependency.to_file);
+    }
+    for dependency in &diff.broken_dependencies {
+        println!(
+            "! dep {} -> {} ({})",
+            dependency.from_file,
+            dependency.to_file,
+            dependency.reason.unwrap_or("dropped")
+        );
+    }
+    println!(
+        "symbols: +{} -{} ~{}; dependencies: +{} broken {}",
+        diff.added_symbols.len(),
+        diff.removed_symbols.len(),
+        diff.changed_symbols.len(),
+        diff.added_dependencies.len(),
+        diff.broken_dependencies.len()
+    );
+    Ok(())
 }
 
 fn run_query(args: QueryArgs) -> Result<()> {
@@ -286,15 +1522,116 @@ fn run_query(args: QueryArgs) -> Result<()> {
     )?;
     ensure_state_layout(&paths)?;
 
-    let store = GraphStore::open(&paths.db_path)?;
+    let mut store = GraphStore::open(&paths.db_path)?;
+    store.set_low_signal_names(LowSignalNames::from_config(
+        &config::load_config(&paths.repo_root)?.low_signal,
+    ));
 
     match args.command {
-        QueryCommands::Symbol { name } => {
+        QueryCommands::Symbol { name, csv } => {
             let rows = store.symbol_definitions(&name)?;
+            if csv {
+                print_csv(SYMBOL_CSV_COLUMNS, &rows)?;
+            } else if args.json {
+                print_json(&rows)?;
+            } else if rows.is_empty() {
+                println!("No definitions found for `{name}`");
+                print_did_you_mean(&store, &name)?;
+            } else {
+                for row in rows {
+                    println!(
+                        "{}:{}:{} [{}] {}",
+                        row.file_path, row.line, row.col, row.kind, row.qualname
+                    );
+                }
+            }
+        }
+        QueryCommands::Symbols {
+            regex,
+            kind,
+            language,
+            visibility,
+            unreferenced,
+            include_tests,
+            include_generated,
+            limit,
+        } => {
+            let options = SymbolSearchOptions {
+                kind,
+                language,
+                visibility,
+                unreferenced,
+                include_tests,
+                include_generated,
+                limit,
+            };
+            let rows = store.symbols_matching(&regex, &options)?;
+            if args.json {
+                print_json(&rows)?;
+            } else if rows.is_empty() {
+                println!("No definitions match `{regex}`");
+            } else {
+                for row in rows {
+                    let modifiers = row
+                        .visibility
+                        .iter()
+                        .chain(&row.modifiers)
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    println!(
+                        "{}:{}:{} [{}] {}{}",
+                        row.file_path,
+                        row.line,
+                        row.col,
+                        row.kind,
+                        row.qualname,
+                        if modifiers.is_empty() {
+                            String::new()
+                        } else {
+                            format!(" ({modifiers})")
+                        }
+                    );
+                }
+            }
+        }
+        QueryCommands::Outline {
+            dir,
+            max_depth,
+            max_files,
+        } => {
+            let options = DirOutlineOptions {
+                max_depth,
+                max_files: max_files.max(1),
+            };
+            let outline = store.dir_outline(&dir, &options)?;
+            if args.json {
+                print_json(&outline)?;
+            } else if outline.files.is_empty() {
+                println!("No indexed files under `{dir}`");
+            } else {
+                for file in &outline.files {
+                    println!("{} ({})", file.path, file.language);
+                    for entry in &file.entries {
+                        let indent = "  ".repeat(entry.depth + 1);
+                        println!("{indent}{} [{}] {}", entry.line, entry.kind, entry.name);
+                    }
+                }
+                if outline.truncated {
+                    println!(
+                        "... showing {} of {} files (raise --max-files)",
+                        outline.files.len(),
+                        outline.total_files
+                    );
+                }
+            }
+        }
+        QueryCommands::TestsFor { name } => {
+            let rows = store.tests_for_symbol(&name)?;
             if args.json {
                 print_json(&rows)?;
             } else if rows.is_empty() {
-                println!("No definitions found for `{name}`");
+                println!("No tests found for `{name}`");
             } else {
                 for row in rows {
                     println!(
@@ -304,17 +1641,287 @@ fn run_query(args: QueryArgs) -> Result<()> {
                 }
             }
         }
+        QueryCommands::Entrypoints { max_calls } => {
+            let entries = entrypoints::find_entrypoints(&store, &paths.repo_root, max_calls)?;
+            if args.json {
+                print_json(&entries)?;
+            } else if entries.is_empty() {
+                println!("No entry points found");
+            } else {
+                for entry in entries {
+                    let location = match (&entry.file_path, entry.line) {
+                        (Some(file), Some(line)) => format!("{file}:{line}"),
+                        (Some(file), None) => file.clone(),
+                        (None, _) => "-".to_string(),
+                    };
+                    println!("[{}] {} {}", entry.kind, entry.name, location);
+                    if let Some(command) = &entry.command {
+                        println!("  $ {command}");
+                    }
+                    if !entry.calls.is_empty() {
+                        println!("  calls: {}", entry.calls.join(", "));
+                    }
+                }
+            }
+        }
+        QueryCommands::Graph { query, limit } => {
+            let result = store.graph_query(&query, limit)?;
+            if args.json {
+                print_json(&result)?;
+            } else {
+                println!("{}", result.columns.join("\t"));
+                for row in &result.rows {
+                    let cells: Vec<String> = row
+                        .iter()
+                        .map(|value| match value {
+                            serde_json::Value::String(text) => text.clone(),
+                            serde_json::Value::Null => "-".to_string(),
+                            other => other.to_string(),
+                        })
+                        .collect();
+                    println!("{}", cells.join("\t"));
+                }
+                if result.truncated {
+                    eprintln!(
+                        "(showing the first {} rows; add LIMIT or --limit for more)",
+                        result.rows.len()
+                    );
+                }
+            }
+        }
+        QueryCommands::LowSignal { language } => {
+            let language = language.map(|language| language.to_ascii_lowercase());
+            let lists = store.low_signal_names().effective(language.as_deref());
+            if args.json {
+                print_json(&lists)?;
+            } else if lists.is_empty() {
+                println!("No low-signal names configured");
+            } else {
+                for (language, names) in lists {
+                    println!("{language}: {}", names.join(", "));
+                }
+            }
+        }
+        QueryCommands::Languages { examples } => {
+            let languages = store.language_coverage()?;
+            let unsupported = unsupported_files(&paths.repo_root, examples)?;
+            let report = LanguageReport {
+                indexed_files: languages.iter().map(|item| item.files).sum(),
+                unsupported_files: unsupported.iter().map(|item| item.files).sum(),
+                languages,
+                unsupported,
+            };
+            if args.json {
+                print_json(&report)?;
+            } else {
+                if report.languages.is_empty() {
+                    println!("No indexed files; run `lumora index` first");
+                }
+                for item in &report.languages {
+                    println!(
+                        "{:<12} files={} lines={} definitions={} references={}",
+                        item.language, item.files, item.lines, item.definitions, item.references
+                    );
+                }
+                if report.unsupported_files > 0 {
+                    println!("unsupported: {} files", report.unsupported_files);
+                    for item in &report.unsupported {
+                        let extension = if item.extension.is_empty() {
+                            "(none)".to_string()
+                        } else {
+                            format!(".{}", item.extension)
+                        };
+                        println!(
+                            "  {extension:<10} {:>5}  {}",
+                            item.files,
+                            item.examples.join(", ")
+                        );
+                    }
+                }
+            }
+        }
+        QueryCommands::Imports {
+            file,
+            transitive,
+            direction,
+            max_depth,
+        } => {
+            let direction = ImportDirection::parse(&direction).unwrap_or(ImportDirection::Out);
+            let max_depth = if transitive { max_depth } else { 1 };
+            let file = file.replace('\\', "/");
+            let Some(graph) = store.import_graph(&file, direction, max_depth)? else {
+                return Err(coded(
+                    ErrorCode::FileNotFound,
+                    format!("`{file}` is not indexed"),
+                ));
+            };
+            if args.json {
+                print_json(&graph)?;
+            } else if graph.rows.is_empty() {
+                match direction {
+                    ImportDirection::Out => println!("`{file}` imports nothing"),
+                    ImportDirection::In => println!("No indexed file imports `{file}`"),
+                }
+            } else {
+                for row in &graph.rows {
+                    let mut line = format!("{}\t{}", row.depth, row.target);
+                    if let Some(via) = &row.via {
+                        line.push_str(&format!(" (via {via})"));
+                    }
+                    if row.kind == "module" {
+                        line.push_str(" [unresolved]");
+                    }
+                    if row.depth > 1 {
+                        line.push_str(&format!(" from {}", row.from));
+                    }
+                    println!("{line}");
+                }
+            }
+        }
+        QueryCommands::Errors => {
+            let errors = store.index_errors()?;
+            if args.json {
+                print_json(&errors)?;
+            } else if errors.is_empty() {
+                println!("No index failures recorded");
+            } else {
+                for error in &errors {
+                    println!(
+                        "{} [{}] {} ({})",
+                        error.path, error.stage, error.message, error.failed_at
+                    );
+                }
+            }
+        }
+        QueryCommands::ModuleDeps { level, matrix } => {
+            let deps = store.module_dependencies(level as usize)?;
+            if args.json {
+                print_json(&deps)?;
+            } else if matrix {
+                println!("from\\to\t{}", deps.modules.join("\t"));
+                for (module, row) in deps.modules.iter().zip(&deps.matrix) {
+                    let cells: Vec<String> = row.iter().map(ToString::to_string).collect();
+                    println!("{module}\t{}", cells.join("\t"));
+                }
+            } else if deps.edges.is_empty() {
+                println!(
+                    "No cross-module dependencies at level {} ({} modules)",
+                    deps.level,
+                    deps.modules.len()
+                );
+            } else {
+                for edge in &deps.edges {
+                    println!("{} -> {} ({})", edge.from, edge.to, edge.edge_count);
+                }
+                println!(
+                    "{} modules, {} internal edges",
+                    deps.modules.len(),
+                    deps.internal_edges
+                );
+            }
+        }
+        QueryCommands::Todos { file_glob, tags } => {
+            let rows = store.annotations(file_glob.as_deref(), &tags)?;
+            if args.json {
+                print_json(&rows)?;
+            } else if rows.is_empty() {
+                println!("No TODO, FIXME, or HACK comments found");
+            } else {
+                for row in &rows {
+                    println!("{}:{}\t{}\t{}", row.file_path, row.line, row.tag, row.text);
+                }
+            }
+        }
+        QueryCommands::WhoImports {
+            module,
+            submodules,
+            language,
+        } => {
+            let result = store.who_imports(&module, submodules, language.as_deref())?;
+            if args.json {
+                print_json(&result)?;
+            } else if result.importers.is_empty() {
+                println!("No indexed file imports `{module}`");
+            } else {
+                for importer in &result.importers {
+                    let mut line = format!("{}:{}", importer.file_path, importer.line);
+                    if importer.module != module {
+                        line.push_str(&format!(" ({})", importer.module));
+                    }
+                    println!("{line}");
+                }
+                println!();
+                for directory in &result.directories {
+                    println!(
+                        "{}\t{} imports in {} files",
+                        directory.directory, directory.imports, directory.files
+                    );
+                }
+                println!(
+                    "{} imports in {} files",
+                    result.importers.len(),
+                    result.total_files
+                );
+            }
+        }
+        QueryCommands::PackageDeps { all_packages } => {
+            let deps = store.package_dependencies(all_packages)?;
+            if args.json {
+                print_json(&deps)?;
+            } else if deps.packages.is_empty() {
+                println!(
+                    "No packages detected; index a repo with Cargo.toml or package.json manifests"
+                );
+            } else if deps.edges.is_empty() {
+                println!(
+                    "No cross-package dependencies ({} packages)",
+                    deps.packages.len()
+                );
+            } else {
+                let label = |dir: &str| {
+                    deps.packages
+                        .iter()
+                        .find(|package| package.dir == dir)
+                        .and_then(|package| package.name.clone())
+                        .unwrap_or_else(|| dir.to_string())
+                };
+                for edge in &deps.edges {
+                    println!(
+                        "{} -> {} ({})",
+                        label(&edge.from),
+                        label(&edge.to),
+                        edge.edge_count
+                    );
+                }
+                println!(
+                    "{} packages, {} internal edges, {} unpackaged edges",
+                    deps.packages.len(),
+                    deps.internal_edges,
+                    deps.unpackaged_edges
+                );
+            }
+        }
         QueryCommands::Refs {
             name,
             calls_only,
             limit,
             offset,
+            page,
             dedup,
             order,
             file_glob,
             language,
             max_age_hours,
+            resolved_only,
+            exclude_externals,
+            min_confidence,
+            exclude_heuristic,
+            include_tests,
+            include_generated,
             top_files,
+            group_by,
+            jsonl,
+            csv,
         } => {
             let edge_type_filter = if calls_only {
                 Some("calls".to_string())
@@ -327,25 +1934,92 @@ fn run_query(args: QueryArgs) -> Result<()> {
                 language,
                 max_age_hours,
                 limit: limit.max(1),
-                offset,
+                offset: page_offset(page, limit, offset),
                 dedup,
                 order: parse_sort_order(&order)?,
+                cursor: None,
+                resolved_only,
+                exclude_externals,
+                min_confidence,
+                exclude_heuristic,
+                include_tests,
+                include_generated,
             };
-            let (rows, pagination) = store.symbol_references_page(&name, &options)?;
+            if group_by.is_some() {
+                if jsonl {
+                    let all = ReferenceQueryOptions {
+                        limit: usize::MAX,
+                        ..options
+                    };
+                    return print_jsonl(&store.symbol_references_grouped(&name, &all)?.0);
+                }
+                let (groups, pagination) = store.symbol_references_grouped(&name, &options)?;
+                if csv {
+                    print_csv(REFERENCE_GROUP_CSV_COLUMNS, &groups)?;
+                } else if args.json {
+                    print_json(&json!({
+                        "groups": groups,
+                        "group_by": "enclosing_symbol",
+                        "pagination": pagination
+                    }))?;
+                } else if groups.is_empty() {
+                    println!("No references found for `{name}`");
+                    print_did_you_mean(&store, &name)?;
+                } else {
+                    for group in &groups {
+                        let location = match group.line {
+                            Some(line) => format!("{}:{line}", group.file_path),
+                            None => group.file_path.clone(),
+                        };
+                        println!(
+                            "{:>5} {location} {} [{}] calls={}",
+                            group.count,
+                            group.enclosing_symbol.as_deref().unwrap_or("<file scope>"),
+                            group.kind.as_deref().unwrap_or("-"),
+                            group.calls
+                        );
+                    }
+                    print_page_hint(&pagination);
+                }
+                return Ok(());
+            }
+            if jsonl {
+                return print_jsonl_pages(options.offset, |offset, cursor| {
+                    store.symbol_references_page(
+                        &name,
+                        &ReferenceQueryOptions {
+                            offset,
+                            cursor: cursor.map(str::to_string),
+                            ..options.clone()
+                        },
+                    )
+                });
+            }
+            let (rows, mut pagination) = store.symbol_references_page(&name, &options)?;
+            // Cursors only live in this process, so a later command can't use one.
+            pagination.next_cursor = None;
 
-            if args.json {
+            if csv {
+                print_csv(REFERENCE_CSV_COLUMNS, &rows)?;
+            } else if args.json {
                 print_json(&json!({
                     "rows": rows,
                     "pagination": pagination
                 }))?;
             } else if rows.is_empty() {
                 println!("No references found for `{name}`");
+                print_did_you_mean(&store, &name)?;
             } else {
                 for row in &rows {
                     if let Some(score) = row.score {
                         println!(
-                            "{}:{}:{} [{}] score={:.2}",
-                            row.file_path, row.line, row.col, row.edge_type, score
+                            "{}:{}:{} [{}] score={:.2} confidence={:.2}",
+                            row.file_path,
+                            row.line,
+                            row.col,
+                            row.edge_type,
+                            score,
+                            row.confidence.unwrap_or_default()
                         );
                     } else {
                         println!(
@@ -355,24 +2029,34 @@ fn run_query(args: QueryArgs) -> Result<()> {
                     }
                 }
                 if top_files {
-                    let summary = store.top_reference_files(&rows, 10);
+                    let summary = store.top_reference_files(&rows, 10)?;
                     println!("top files:");
                     for item in summary {
-                        println!("  {} ({})", item.file_path, item.count);
+                        println!("  {}", format_top_file(&item));
                     }
                 }
+                print_page_hint(&pagination);
             }
         }
         QueryCommands::Callers {
             name,
             limit,
             offset,
+            page,
             dedup,
             order,
             file_glob,
             language,
             max_age_hours,
+            resolved_only,
+            exclude_externals,
+            min_confidence,
+            exclude_heuristic,
+            include_tests,
+            include_generated,
             top_files,
+            jsonl,
+            csv,
         } => {
             let options = ReferenceQueryOptions {
                 edge_type_filter: Some("calls".to_string()),
@@ -380,36 +2064,65 @@ fn run_query(args: QueryArgs) -> Result<()> {
                 language,
                 max_age_hours,
                 limit: limit.max(1),
-                offset,
+                offset: page_offset(page, limit, offset),
                 dedup,
                 order: parse_sort_order(&order)?,
+                cursor: None,
+                resolved_only,
+                exclude_externals,
+                min_confidence,
+                exclude_heuristic,
+                include_tests,
+                include_generated,
             };
-            let (rows, pagination) = store.symbol_references_page(&name, &options)?;
-            if args.json {
+            if jsonl {
+                return print_jsonl_pages(options.offset, |offset, cursor| {
+                    store.symbol_references_page(
+                        &name,
+                        &ReferenceQueryOptions {
+                            offset,
+                            cursor: cursor.map(str::to_string),
+                            ..options.clone()
+                        },
+                    )
+                });
+            }
+            let (rows, mut pagination) = store.symbol_references_page(&name, &options)?;
+            // Cursors only live in this process, so a later command can't use one.
+            pagination.next_cursor = None;
+            if csv {
+                print_csv(REFERENCE_CSV_COLUMNS, &rows)?;
+            } else if args.json {
                 print_json(&json!({
                     "rows": rows,
                     "pagination": pagination
                 }))?;
             } else if rows.is_empty() {
                 println!("No callers found for `{name}`");
+                print_did_you_mean(&store, &name)?;
             } else {
                 for row in &rows {
                     if let Some(score) = row.score {
                         println!(
-                            "{}:{}:{} score={:.2}",
-                            row.file_path, row.line, row.col, score
+                            "{}:{}:{} score={:.2} confidence={:.2}",
+                            row.file_path,
+                            row.line,
+                            row.col,
+                            score,
+                            row.confidence.unwrap_or_default()
                         );
                     } else {
                         println!("{}:{}:{}", row.file_path, row.line, row.col);
                     }
                 }
                 if top_files {
-                    let summary = store.top_reference_files(&rows, 10);
+                    let summary = store.top_reference_files(&rows, 10)?;
                     println!("top caller files:");
                     for item in summary {
-                        println!("  {} ({})", item.file_path, item.count);
+                        println!("  {}", format_top_file(&item));
                     }
                 }
+                print_page_hint(&pagination);
             }
         }
         QueryCommands::Deps {
@@ -424,7 +2137,37 @@ fn run_query(args: QueryArgs) -> Result<()> {
                 println!("No path found from `{from}` to `{to}`");
             } else {
                 for (idx, hop) in path.hops.iter().enumerate() {
-                    println!("{}. {} [{}]", idx + 1, hop.entity_key, hop.entity_type);
+                    println!("{}. {}", idx + 1, format_path_hop(hop));
+                }
+            }
+        }
+        QueryCommands::PathAll {
+            from,
+            to,
+            k,
+            max_depth,
+        } => {
+            let (found, _, _) = store.dependency_paths_with_diagnostics(
+                &from,
+                &to,
+                max_depth.max(1),
+                k.max(1),
+                None,
+                None,
+            )?;
+            if args.json {
+                print_json(&found)?;
+            } else if !found.found {
+                println!("No path found from `{from}` to `{to}`");
+            } else {
+                for (idx, hops) in found.paths.iter().enumerate() {
+                    println!("path {} ({} hops):", idx + 1, hops.len());
+                    for hop in hops {
+                        println!("  {}", format_path_hop(hop));
+                    }
+                }
+                if found.truncated {
+                    println!("(search stopped early; longer paths may be missing)");
                 }
             }
         }
@@ -437,6 +2180,8 @@ fn run_query(args: QueryArgs) -> Result<()> {
             suppress_low_signal_repeats,
             low_signal_name_cap,
             prefer_project_symbols,
+            exclude_externals,
+            exclude_heuristic,
         } => {
             let result = store.minimal_slice_with_options(
                 &file,
@@ -448,6 +2193,8 @@ fn run_query(args: QueryArgs) -> Result<()> {
                     suppress_low_signal_repeats,
                     low_signal_name_cap,
                     prefer_project_symbols,
+                    exclude_externals,
+                    exclude_heuristic,
                 },
             )?;
             if args.json {
@@ -476,14 +2223,43 @@ fn run_query(args: QueryArgs) -> Result<()> {
             min_similarity,
             limit,
             offset,
+            page,
             hotspots,
+            same_language_only,
+            language,
+            include_tests,
+            include_generated,
+            jsonl,
+            csv,
         } => {
             let options = CloneQueryOptions {
                 min_similarity,
                 limit,
-                offset,
+                offset: page_offset(page, limit, offset),
+                same_language_only,
+                language,
+                include_tests,
+                include_generated,
             };
-            if args.json {
+            if csv {
+                if hotspots {
+                    let rows = store.clone_hotspots_page(&file, &options)?.0;
+                    print_csv(CLONE_HOTSPOT_CSV_COLUMNS, &rows)?;
+                } else {
+                    let rows = store.clone_matches_page(&file, &options)?.0;
+                    print_csv(CLONE_MATCH_CSV_COLUMNS, &rows)?;
+                }
+            } else if jsonl {
+                let all = CloneQueryOptions {
+                    limit: usize::MAX,
+                    ..options
+                };
+                if hotspots {
+                    print_jsonl(&store.clone_hotspots_page(&file, &all)?.0)?;
+                } else {
+                    print_jsonl(&store.clone_matches_page(&file, &all)?.0)?;
+                }
+            } else if args.json {
                 if hotspots {
                     let (rows, pagination, analysis) =
                         store.clone_hotspots_page(&file, &options)?;
@@ -515,17 +2291,92 @@ fn run_query(args: QueryArgs) -> Result<()> {
                     }
                 }
             } else {
-                let rows = store.clone_matches_with_options(&file, &options)?;
+                let (rows, pagination, analysis) = store.clone_matches_page(&file, &options)?;
                 if rows.is_empty() {
                     println!("No clone candidates found for `{file}`");
                 } else {
+                    if let Some(warning) = &analysis.params_warning {
+                        println!("warning: {warning}");
+                    }
                     for row in rows {
                         println!(
                             "{} similarity={:.3} shared={}",
                             row.other_file, row.similarity, row.shared_fingerprints
                         );
+                        for region in &row.regions {
+                            println!(
+                                "  {file}:{}-{} ~ {}:{}-{}",
+                                region.start_line,
+                                region.end_line,
+                                row.other_file,
+                                region.other_start_line,
+                                region.other_end_line
+                            );
+                        }
                     }
+                    print_page_hint(&pagination);
+                }
+            }
+        }
+        QueryCommands::ClonesDir {
+            dir,
+            other_dir,
+            min_similarity,
+            limit,
+            offset,
+            page,
+            same_language_only,
+            language,
+            include_tests,
+            include_generated,
+            jsonl,
+            csv,
+        } => {
+            let options = CloneQueryOptions {
+                min_similarity,
+                limit,
+                offset: page_offset(page, limit, offset),
+                same_language_only,
+                language,
+                include_tests,
+                include_generated,
+            };
+            if jsonl {
+                let all = CloneQueryOptions {
+                    limit: usize::MAX,
+                    ..options
+                };
+                return print_jsonl(&store.clone_dirs_page(&dir, &other_dir, &all)?.0);
+            }
+            let (rows, pagination, summary) = store.clone_dirs_page(&dir, &other_dir, &options)?;
+            if csv {
+                print_csv(DIR_CLONE_CSV_COLUMNS, &rows)?;
+            } else if args.json {
+                print_json(&json!({
+                    "rows": rows,
+                    "pagination": pagination,
+                    "summary": summary
+                }))?;
+            } else {
+                println!(
+                    "{} ({} files) ~ {} ({} files): similarity={:.3} shared={}",
+                    summary.dir,
+                    summary.files,
+                    summary.other_dir,
+                    summary.other_files,
+                    summary.similarity,
+                    summary.shared_fingerprints
+                );
+                if let Some(reason) = &summary.empty_reason {
+                    println!("No file pairs found: {reason}");
+                }
+                for row in rows {
+                    println!(
+                        "{} ~ {} similarity={:.3} shared={}",
+                        row.file, row.other_file, row.similarity, row.shared_fingerprints
+                    );
                 }
+                print_page_hint(&pagination);
             }
         }
     }
@@ -533,6 +2384,15 @@ fn run_query(args: QueryArgs) -> Result<()> {
     Ok(())
 }
 
+fn run_lsp(args: LspArgs) -> Result<()> {
+    let paths = resolve_paths(
+        args.repo.as_deref(),
+        args.state_dir.as_deref(),
+        args.db.as_deref(),
+    )?;
+    lsp::run_lsp_stdio(paths)
+}
+
 fn run_mcp(args: McpArgs) -> Result<()> {
     let repo_str = match (&args.repo, args.repo_tail.is_empty()) {
         (Some(r), true) => Some(r.clone()),
@@ -550,7 +2410,13 @@ fn run_mcp(args: McpArgs) -> Result<()> {
     if args.auto_index {
         ensure_state_layout(&paths)?;
     }
-    run_mcp_stdio(paths, args.auto_index, args.full_first)
+    let mcp_config = config::load_config(&paths.repo_root)?.mcp;
+    let mut deny = mcp_config.deny_tools;
+    deny.extend(args.deny_tools);
+    let tools = ToolFilter::new(args.tools.or(mcp_config.tools).as_deref(), &deny)?
+        .with_experimental(args.experimental || mcp_config.experimental)
+        .with_page_size(args.tools_page_size.or(mcp_config.tools_page_size));
+    run_mcp_stdio(paths, tools, args.auto_index, args.full_first)
 }
 
 fn run_setup_codex(args: SetupCodexArgs) -> Result<()> {
@@ -596,17 +2462,91 @@ fn run_setup_codex(args: SetupCodexArgs) -> Result<()> {
 }
 
 fn run_print_mcp_config(args: PrintMcpConfigArgs) -> Result<()> {
-    let snippet = json!({
+    let snippet = mcp_config_snippet(&args.name, &args.command, None);
+    println!("{}", serde_json::to_string_pretty(&snippet)?);
+    Ok(())
+}
+
+fn run_setup_service(args: SetupServiceArgs) -> Result<()> {
+    let paths = resolve_paths(
+        args.repo.as_deref(),
+        args.state_dir.as_deref(),
+        args.db.as_deref(),
+    )?;
+    let exe = match args.command {
+        Some(command) => command,
+        None => std::env::current_exe().context("failed to locate the lumora binary")?,
+    };
+    let platform = args
+        .platform
+        .as_deref()
+        .and_then(service::ServicePlatform::parse)
+        .unwrap_or_else(service::ServicePlatform::current);
+    let home = std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .context("cannot find the home directory; set HOME")?;
+    let config_home = std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from);
+
+    let mut serve_args = vec!["--repo".to_string(), paths.repo_root.display().to_string()];
+    if args.state_dir.is_some() || !paths.state_dir.starts_with(&paths.repo_root) {
+        serve_args.push("--state-dir".to_string());
+        serve_args.push(paths.state_dir.display().to_string());
+    }
+    if args.db.is_some() {
+        serve_args.push("--db".to_string());
+        serve_args.push(paths.db_path.display().to_string());
+    }
+    let plan = service::plan_service(
+        platform,
+        &exe,
+        &paths.repo_root,
+        &serve_args,
+        &home,
+        config_home.as_deref(),
+    );
+    if !args.dry_run {
+        service::install_service(&plan)?;
+    }
+
+    if args.json {
+        print_json(&json!({ "installed": !args.dry_run, "service": plan }))?;
+        return Ok(());
+    }
+    if args.dry_run {
+        if let (Some(path), Some(contents)) = (&plan.file_path, &plan.contents) {
+            println!("# {path}");
+            println!("{contents}");
+        }
+        for command in &plan.commands {
+            println!("{}", command.args.join(" "));
+        }
+        return Ok(());
+    }
+    println!("Installed {} service `{}`", plan.platform, plan.name);
+    if let Some(path) = &plan.file_path {
+        println!("definition: {path}");
+    }
+    println!("Run `{}` to verify.", plan.status_command);
+    Ok(())
+}
+
+/// `mcpServers` entry for a generic MCP client. With `repo`, the server is
+/// pinned to that checkout instead of the client's working directory.
+fn mcp_config_snippet(name: &str, command: &str, repo: Option<&Path>) -> serde_json::Value {
+    let mut server_args = vec!["mcp".to_string()];
+    if let Some(repo) = repo {
+        server_args.push("--repo".to_string());
+        server_args.push(repo.display().to_string());
+    }
+    json!({
         "mcpServers": {
-            args.name: {
-                "command": args.command,
-                "args": ["mcp"],
+            name: {
+                "command": command,
+                "args": server_args,
             }
         }
-    });
-
-    println!("{}", serde_json::to_string_pretty(&snippet)?);
-    Ok(())
+    })
 }
 
 fn resolve_paths(
@@ -618,18 +2558,209 @@ fn resolve_paths(
     resolve_runtime_paths(repo_hint, state_dir, db)
 }
 
+/// Prints close symbol names after a lookup that found nothing.
+fn print_did_you_mean(store: &GraphStore, name: &str) -> Result<()> {
+    let names = store.similar_symbol_names(name, 5)?;
+    if !names.is_empty() {
+        println!("Did you mean: {}?", names.join(", "));
+    }
+    Ok(())
+}
+
+/// `--calls--> symbol:helper [symbol] (src/lib.rs:3)`; the first hop has no
+/// edge.
+fn format_path_hop(hop: &PathHop) -> String {
+    let mut text = format!("{} [{}]", hop.entity_key, hop.entity_type);
+    if let Some(edge_type) = &hop.edge_type {
+        text = format!("--{edge_type}--> {text}");
+    }
+    match (&hop.edge_file_path, hop.edge_line) {
+        (Some(path), Some(line)) => text.push_str(&format!(" ({path}:{line})")),
+        (Some(path), None) => text.push_str(&format!(" ({path})")),
+        _ => {}
+    }
+    text
+}
+
+/// The offset `--page` (counted from 1) selects in pages of `per_page` rows;
+/// `offset` when no page was given.
+fn page_offset(page: Option<u64>, per_page: usize, offset: usize) -> usize {
+    page.map_or(offset, |page| {
+        (page as usize - 1).saturating_mul(per_page.max(1))
+    })
+}
+
+fn print_page_hint(pagination: &PaginationInfo) {
+    if let Some(hint) = pagination.text_hint() {
+        println!("{hint}");
+    }
+}
+
+fn format_top_file(item: &TopFileSummary) -> String {
+    if item.owners.is_empty() {
+        format!("{} ({})", item.file_path, item.count)
+    } else {
+        format!(
+            "{} ({}) owners: {}",
+            item.file_path,
+            item.count,
+            item.owners.join(" ")
+        )
+    }
+}
+
 fn print_json<T: Serialize>(value: &T) -> Result<()> {
     println!("{}", serde_json::to_string_pretty(value)?);
     Ok(())
 }
 
+/// Writes one compact JSON document per row and stops quietly when the reader
+/// closes the pipe (e.g. `| head`).
+/// Columns of `query symbol --csv`, in output order.
+const SYMBOL_CSV_COLUMNS: &[&str] = &[
+    "symbol_name",
+    "file_path",
+    "line",
+    "col",
+    "end_line",
+    "end_col",
+    "kind",
+    "qualname",
+    "signature",
+];
+
+/// Columns of `query refs --csv` and `query callers --csv`.
+const REFERENCE_CSV_COLUMNS: &[&str] = &[
+    "row_id",
+    "symbol_name",
+    "file_path",
+    "line",
+    "col",
+    "end_line",
+    "end_col",
+    "edge_type",
+    "resolved_symbol_key",
+    "score",
+    "confidence",
+    "why",
+];
+
+/// Columns of `query refs --group-by enclosing_symbol --csv`.
+const REFERENCE_GROUP_CSV_COLUMNS: &[&str] = &[
+    "file_path",
+    "enclosing_symbol",
+    "kind",
+    "line",
+    "end_line",
+    "count",
+    "calls",
+    "lines",
+];
+
+const CLONE_MATCH_CSV_COLUMNS: &[&str] = &[
+    "other_file",
+    "language",
+    "similarity",
+    "shared_fingerprints",
+    "regions",
+];
+
+const DIR_CLONE_CSV_COLUMNS: &[&str] = &[
+    "file",
+    "other_file",
+    "language",
+    "similarity",
+    "shared_fingerprints",
+];
+
+const CLONE_HOTSPOT_CSV_COLUMNS: &[&str] =
+    &["directory", "files", "avg_similarity", "max_similarity"];
+
+fn print_csv<T: Serialize>(columns: &[&str], rows: &[T]) -> Result<()> {
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let written = csv::write_csv(&mut out, columns, rows).and_then(|()| Ok(out.flush()?));
+    match written {
+        Err(err)
+            if err
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|err| err.kind() == std::io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Prints each page `fetch` returns as JSON lines, following `next_offset`
+/// (and `next_cursor`, which skips re-running the query) to the last page.
+fn print_jsonl_pages<T: Serialize>(
+    offset: usize,
+    fetch: impl FnMut(usize, Option<&str>) -> Result<(Vec<T>, PaginationInfo)>,
+) -> Result<()> {
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let written = write_jsonl_pages(&mut out, offset, fetch).and_then(|_| Ok(out.flush()?));
+    match written {
+        Err(err)
+            if err
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|err| err.kind() == std::io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+fn write_jsonl_pages<T: Serialize>(
+    out: &mut impl Write,
+    mut offset: usize,
+    mut fetch: impl FnMut(usize, Option<&str>) -> Result<(Vec<T>, PaginationInfo)>,
+) -> Result<usize> {
+    let mut cursor = None;
+    let mut written = 0;
+    loop {
+        let (rows, pagination) = fetch(offset, cursor.as_deref())?;
+        for row in &rows {
+            serde_json::to_writer(&mut *out, row)?;
+            out.write_all(b"\n")?;
+        }
+        written += rows.len();
+        match pagination.next_offset {
+            Some(next) if pagination.has_more && next > offset => {
+                offset = next;
+                cursor = pagination.next_cursor;
+            }
+            _ => return Ok(written),
+        }
+    }
+}
+
+fn print_jsonl<T: Serialize>(rows: &[T]) -> Result<()> {
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    for row in rows {
+        let mut line = serde_json::to_string(row)?;
+        line.push('\n');
+        match out.write_all(line.as_bytes()) {
+            Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => return Ok(()),
+            result => result?,
+        }
+    }
+    match out.flush() {
+        Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
 fn parse_sort_order(raw: &str) -> Result<SortOrder> {
     match raw {
         "asc" | "line_asc" => Ok(SortOrder::LineAsc),
         "desc" | "line_desc" => Ok(SortOrder::LineDesc),
         "score_desc" => Ok(SortOrder::ScoreDesc),
-        other => Err(anyhow::anyhow!(
-            "invalid --order `{other}`; expected one of: asc, desc, score_desc, line_asc, line_desc"
+        other => Err(coded(
+            ErrorCode::InvalidParams,
+            format!(
+                "invalid --order `{other}`; expected one of: asc, desc, score_desc, line_asc, line_desc"
+            ),
         )),
     }
 }
@@ -668,3 +2799,63 @@ fn run_codex_cli(codex_command: &str, args: &[String]) -> Result<std::process::E
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn page_offset_counts_pages_from_one() {
+        assert_eq!(page_offset(None, 50, 30), 30, "no --page keeps --offset");
+        assert_eq!(page_offset(Some(1), 50, 30), 0);
+        // 120 rows in pages of 50: the last page starts at row 100.
+        assert_eq!(page_offset(Some(3), 50, 0), 100);
+        assert_eq!(page_offset(Some(4), 7, 0), 21, "custom --per-page");
+        assert_eq!(page_offset(Some(2), 0, 0), 1, "--per-page 0 acts as 1");
+    }
+
+    #[test]
+    fn jsonl_pages_cover_every_reference_past_the_limit() {
+        let dir = TempDir::new().unwrap();
+        let calls = "    foo();\n".repeat(450);
+        std::fs::write(
+            dir.path().join("lib.rs"),
+            format!("fn foo() {{}}\nfn bar() {{\n{calls}}}\n"),
+        )
+        .unwrap();
+        let mut store = GraphStore::open(&dir.path().join("graph.db")).unwrap();
+        index_repository(&mut store, dir.path(), IndexOptions::default()).unwrap();
+
+        let options = ReferenceQueryOptions::default();
+        let all = ReferenceQueryOptions {
+            limit: usize::MAX,
+            ..options.clone()
+        };
+        let expected = store.symbol_references_page("foo", &all).unwrap().0;
+        assert!(expected.len() > options.limit, "{}", expected.len());
+
+        let mut out = Vec::new();
+        let mut cursors = 0;
+        let written = write_jsonl_pages(&mut out, 0, |offset, cursor| {
+            cursors += usize::from(cursor.is_some());
+            store.symbol_references_page(
+                "foo",
+                &ReferenceQueryOptions {
+                    offset,
+                    cursor: cursor.map(str::to_string),
+                    ..options.clone()
+                },
+            )
+        })
+        .unwrap();
+        assert_eq!(written, expected.len());
+        assert!(cursors > 0, "later pages should reuse the cached result");
+        let lines = String::from_utf8(out).unwrap();
+        let expected_lines = expected
+            .iter()
+            .map(|row| serde_json::to_string(row).unwrap() + "\n")
+            .collect::<String>();
+        assert_eq!(lines, expected_lines);
+    }
+}
@@ -1,47 +1,272 @@
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
 use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context, Result};
 use serde_json::{json, Value};
 
-use crate::fileops;
-use crate::indexer::{index_repository, IndexOptions};
+use crate::ast_search::{ast_search, AstSearchOptions};
+use crate::config::load_config;
+use crate::embeddings::Embedder;
+use crate::error::{coded, error_code, error_json, ErrorCode};
+use crate::events::{EventTail, IndexChangedEvent};
+use crate::fileops::{self, WriteMode, WriteOptions};
+use crate::history;
+use crate::indexer::{
+    index_on_demand, index_repository, refresh_files, FingerprintMode, IndexOptions,
+};
+use crate::low_signal::LowSignalNames;
+use crate::model::SliceResult;
+use crate::oplog;
 use crate::paths::RuntimePaths;
 use crate::storage::{
-    CloneQueryOptions, GraphStore, ReferenceQueryOptions, SelectorSuggestOptions,
-    SliceQueryOptions, SortOrder,
+    CloneQueryOptions, DirOutlineOptions, GraphStore, QueryDeadline, ReferenceQueryOptions,
+    SelectorSuggestOptions, SemanticSearchOptions, SliceQueryOptions, SortOrder,
 };
 
 const DEFAULT_PROTOCOL_VERSION: &str = "2025-06-18";
+const BYTES_PER_TOKEN: u64 = 4;
+/// Room left for the `truncation` report itself when trimming a response.
+const TRUNCATION_RESERVE: usize = 512;
+/// Bulky per-row fields dropped first when a response exceeds its budget.
+const SNIPPET_FIELDS: &[&str] = &[
+    "context_before",
+    "context_after",
+    "diff_preview",
+    "why",
+    "meta_json",
+    "diagnostics",
+];
+/// Window `guardrails.max_files_per_minute` counts writes over.
+const WRITE_WINDOW: Duration = Duration::from_secs(60);
+/// Notification sent when the watcher daemon re-indexes files.
+const INDEX_CHANGED_METHOD: &str = "lumora/indexChanged";
+/// How often MCP sessions check the watcher's event log.
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Indexed files are exposed as resources under this URI prefix.
+const FILE_RESOURCE_PREFIX: &str = "lumora://file/";
+/// Resources per `resources/list` page.
+const RESOURCE_PAGE_SIZE: usize = 200;
+/// JSON-RPC error MCP uses for an unknown resource URI.
+const RESOURCE_NOT_FOUND: i64 = -32002;
+/// Lines of each neighbor's definition inlined by `minimal_slice` with
+/// `include_source`.
+const SLICE_SNIPPET_LINES: u64 = 8;
+/// References returned by `lumora.hover`, best-ranked first.
+const HOVER_REFERENCES: usize = 5;
+/// Lines of doc comment `lumora.hover` keeps.
+const HOVER_DOC_LINES: usize = 40;
+
+/// Most names one `symbols_batch` call may look up.
+const SYMBOLS_BATCH_MAX_NAMES: usize = 50;
+
+/// Most top references `symbols_batch` returns per name.
+const SYMBOLS_BATCH_MAX_REFERENCES: u64 = 20;
+
+/// Tool groups accepted by `--tools`/`--deny-tools` and the `[mcp]` config
+/// section. Every tool belongs to exactly one group.
+const TOOL_GROUPS: &[(&str, &[&str])] = &[
+    (
+        "index",
+        &[
+            "lumora.index_repository",
+            "lumora.index_file",
+            "lumora.prune",
+        ],
+    ),
+    (
+        "query",
+        &[
+            "lumora.symbol_definitions",
+            "lumora.symbols_batch",
+            "lumora.symbol_source",
+            "lumora.get_symbol_source",
+            "lumora.hover",
+            "lumora.symbol_references",
+            "lumora.symbol_callers",
+            "lumora.dependency_path",
+            "lumora.minimal_slice",
+            "lumora.symbol_neighbors",
+            "lumora.symbol_history",
+            "lumora.clone_matches",
+            "lumora.compare_files",
+            "lumora.dir_outline",
+            "lumora.file_profile",
+            "lumora.todos",
+            "lumora.semantic_search",
+            "lumora.selector_discover",
+        ],
+    ),
+    (
+        "read",
+        &[
+            "lumora.read_file",
+            "lumora.read_range",
+            "lumora.file_outline",
+            "lumora.multi_outline",
+            "lumora.search_files",
+            "lumora.list_directory",
+            "lumora.multi_read",
+            "lumora.ast_search",
+        ],
+    ),
+    (
+        "write",
+        &[
+            "lumora.write_file",
+            "lumora.edit_file",
+            "lumora.batch_edit",
+            "lumora.apply_patch",
+            "lumora.move_file",
+            "lumora.delete_file",
+            "lumora.undo_last_change",
+        ],
+    ),
+];
+
+/// Tools whose arguments or output may still change. `lumora mcp` hides
+/// them unless started with `--experimental` or `[mcp] experimental = true`.
+const EXPERIMENTAL_TOOLS: &[&str] = &["lumora.semantic_search", "lumora.ast_search"];
+
+/// Which tools a server session advertises in `tools/list` and accepts in
+/// `tools/call`, and how many `tools/list` returns per page. The default
+/// allows every tool on a single page.
+#[derive(Debug, Clone, Default)]
+pub struct ToolFilter {
+    /// `None` allows every tool not denied.
+    allow: Option<HashSet<&'static str>>,
+    deny: HashSet<&'static str>,
+    hide_experimental: bool,
+    page_size: Option<usize>,
+}
+
+impl ToolFilter {
+    /// Entries are group names (`index`, `query`, `read`, `write`) or tool
+    /// names, with or without the `lumora.` prefix. Deny wins over allow.
+    pub fn new(allow: Option<&[String]>, deny: &[String]) -> Result<Self> {
+        let allow = match allow {
+            Some(entries) => Some(expand_tool_entries(entries)?),
+            None => None,
+        };
+        Ok(Self {
+            allow,
+            deny: expand_tool_entries(deny)?,
+            ..Self::default()
+        })
+    }
+
+    /// Offers [`EXPERIMENTAL_TOOLS`] too; without it they are hidden and
+    /// refused even when allowed by name.
+    pub fn with_experimental(mut self, enabled: bool) -> Self {
+        self.hide_experimental = !enabled;
+        self
+    }
+
+    /// Splits `tools/list` into pages of `page_size` tools, linked by
+    /// `nextCursor`. `None` lists every tool at once.
+    pub fn with_page_size(mut self, page_size: Option<usize>) -> Self {
+        self.page_size = page_size.filter(|size| *size > 0);
+        self
+    }
+
+    fn permits(&self, tool_name: &str) -> bool {
+        !self.deny.contains(tool_name)
+            && !self.hides_experimental(tool_name)
+            && self
+                .allow
+                .as_ref()
+                .map_or(true, |allow| allow.contains(tool_name))
+    }
 
+    fn hides_experimental(&self, tool_name: &str) -> bool {
+        self.hide_experimental && EXPERIMENTAL_TOOLS.contains(&tool_name)
+    }
+}
+
+fn expand_tool_entries(entries: &[String]) -> Result<HashSet<&'static str>> {
+    let mut tools = HashSet::new();
+    for entry in entries {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+        if let Some((_, members)) = TOOL_GROUPS.iter().find(|(group, _)| *group == entry) {
+            tools.extend(members.iter().copied());
+            continue;
+        }
+        let name = entry.strip_prefix("lumora.").unwrap_or(entry);
+        let tool = TOOL_GROUPS
+            .iter()
+            .flat_map(|(_, members)| members.iter().copied())
+            .find(|tool| tool.strip_prefix("lumora.") == Some(name));
+        match tool {
+            Some(tool) => {
+                tools.insert(tool);
+            }
+            None => {
+                return Err(coded(
+                    ErrorCode::InvalidParams,
+                    format!(
+                        "unknown tool or group `{entry}`; groups are index, query, read, write"
+                    ),
+                ))
+            }
+        }
+    }
+    Ok(tools)
+}
+
+fn is_known_tool(tool_name: &str) -> bool {
+    TOOL_GROUPS
+        .iter()
+        .any(|(_, members)| members.contains(&tool_name))
+}
+
+/// How a JSON-RPC message is framed on stdio; also used by `lumora lsp`.
 #[derive(Clone, Copy)]
-enum FrameStyle {
+pub enum FrameStyle {
     ContentLength,
     LineDelimited,
 }
 
-pub fn run_mcp_stdio(paths: RuntimePaths, auto_index: bool, full_first: bool) -> Result<()> {
+pub fn run_mcp_stdio(
+    paths: RuntimePaths,
+    tools: ToolFilter,
+    auto_index: bool,
+    full_first: bool,
+) -> Result<()> {
     if auto_index {
         let mut store = GraphStore::open(&paths.db_path)?;
         let _ = index_repository(
             &mut store,
             &paths.repo_root,
-            IndexOptions { full: full_first },
+            IndexOptions {
+                full: full_first,
+                ..Default::default()
+            },
         )?;
     }
 
     let stdin = io::stdin();
-    let stdout = io::stdout();
     let mut reader = BufReader::new(stdin.lock());
-    let mut writer = stdout.lock();
+    let notify_style = Arc::new(Mutex::new(None));
+    spawn_index_change_notifier(&paths, Arc::clone(&notify_style));
 
     while let Some(frame) = read_frame(&mut reader)? {
         let message = frame.value;
         if let Some(method) = message.get("method").and_then(Value::as_str) {
             let id = message.get("id").cloned();
             if let Some(id) = id {
-                let response = handle_request(method, message.get("params"), id, &paths)?;
-                write_frame(&mut writer, &response, frame.style)?;
+                let response = handle_request(method, message.get("params"), id, &paths, &tools)?;
+                write_frame(&mut io::stdout().lock(), &response, frame.style)?;
+            } else if method == "notifications/initialized" {
+                *notify_style
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(frame.style);
             }
         }
     }
@@ -49,16 +274,75 @@ pub fn run_mcp_stdio(paths: RuntimePaths, auto_index: bool, full_first: bool) ->
     Ok(())
 }
 
+/// Tails the state dir's event log, written by `lumora serve`, and forwards
+/// each re-index as a `lumora/indexChanged` notification. Nothing is sent
+/// until the client has finished initializing, which also fixes the framing.
+fn spawn_index_change_notifier(paths: &RuntimePaths, style: Arc<Mutex<Option<FrameStyle>>>) {
+    let mut tail = EventTail::from_end(&paths.state_dir);
+    thread::spawn(move || loop {
+        thread::sleep(EVENT_POLL_INTERVAL);
+        let events = match tail.poll() {
+            Ok(events) => events,
+            Err(err) => {
+                eprintln!("event log read failed: {err:#}");
+                continue;
+            }
+        };
+        let Some(style) = *style
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+        else {
+            continue;
+        };
+        for event in events {
+            let notification = index_changed_notification(&event);
+            if write_frame(&mut io::stdout().lock(), &notification, style).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+fn index_changed_notification(event: &IndexChangedEvent) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": INDEX_CHANGED_METHOD,
+        "params": event
+    })
+}
+
 fn handle_request(
     method: &str,
     params: Option<&Value>,
     id: Value,
     paths: &RuntimePaths,
+    tools: &ToolFilter,
 ) -> Result<Value> {
     let response = match method {
         "initialize" => success_response(id, initialize_result(params)),
         "ping" => success_response(id, json!({})),
-        "tools/list" => success_response(id, json!({ "tools": tool_descriptors() })),
+        "tools/list" => list_tools(params, id, tools),
+        "resources/list" => list_resources(params, id, paths),
+        "resources/templates/list" => success_response(
+            id,
+            json!({
+                "resourceTemplates": [{
+                    "uriTemplate": format!("{FILE_RESOURCE_PREFIX}{{path}}"),
+                    "name": "file",
+                    "description": "An indexed file by repo-relative path."
+                }]
+            }),
+        ),
+        "resources/read" => read_resource(params, id, paths),
+        "prompts/list" => {
+            let prompts: Vec<Value> = PROMPTS
+                .iter()
+                .filter(|prompt| prompt.tools.iter().all(|tool| tools.permits(tool)))
+                .map(prompt_descriptor)
+                .collect();
+            success_response(id, json!({ "prompts": prompts }))
+        }
+        "prompts/get" => get_prompt(params, id, tools),
         "tools/call" => {
             let Some(params) = params else {
                 return Ok(error_response(
@@ -78,17 +362,32 @@ fn handle_request(
                     ))
                 }
             };
+            if !tools.permits(tool_name) && is_known_tool(tool_name) {
+                let message = if tools.hides_experimental(tool_name) {
+                    format!("Tool `{tool_name}` is experimental; start the server with `--experimental` to enable it")
+                } else {
+                    format!("Tool `{tool_name}` is disabled for this server")
+                };
+                let mut response = error_response(Some(id), -32602, &message);
+                response["error"]["data"] = json!({ "code": ErrorCode::InvalidParams });
+                return Ok(response);
+            }
             let arguments = params
                 .get("arguments")
                 .cloned()
                 .unwrap_or_else(|| json!({}));
 
-            match call_tool(tool_name, &arguments, paths) {
+            match call_tool_with_budget(tool_name, &arguments, paths) {
                 Ok(structured_content) => success_response(id, tool_ok(structured_content)),
                 Err(ToolCallError::InvalidParams(msg)) => {
-                    error_response(Some(id), -32602, &format!("Invalid tool params: {msg}"))
+                    let mut response =
+                        error_response(Some(id), -32602, &format!("Invalid tool params: {msg}"));
+                    response["error"]["data"] = json!({ "code": ErrorCode::InvalidParams });
+                    response
+                }
+                Err(ToolCallError::Runtime { code, message }) => {
+                    success_response(id, tool_error(code, message))
                 }
-                Err(ToolCallError::Runtime(msg)) => success_response(id, tool_error(msg)),
             }
         }
         _ => error_response(Some(id), -32601, &format!("Unknown method `{method}`")),
@@ -97,37 +396,873 @@ fn handle_request(
     Ok(response)
 }
 
+/// Lists the tools the filter permits, a page at a time when it sets a page
+/// size. The cursor is the name of the last tool on the previous page.
+fn list_tools(params: Option<&Value>, id: Value, tools: &ToolFilter) -> Value {
+    let mut descriptors: Vec<Value> = tool_descriptors()
+        .into_iter()
+        .filter(|tool| {
+            tool["name"]
+                .as_str()
+                .is_some_and(|name| tools.permits(name))
+        })
+        .collect();
+    let cursor = params
+        .and_then(|params| params.get("cursor"))
+        .and_then(Value::as_str);
+    if let Some(cursor) = cursor {
+        let Some(position) = descriptors
+            .iter()
+            .position(|tool| tool["name"].as_str() == Some(cursor))
+        else {
+            return error_response(
+                Some(id),
+                -32602,
+                &format!("Invalid cursor `{cursor}` for tools/list"),
+            );
+        };
+        descriptors.drain(..=position);
+    }
+
+    let mut next_cursor = None;
+    if let Some(page_size) = tools.page_size {
+        if descriptors.len() > page_size {
+            descriptors.truncate(page_size);
+            next_cursor = descriptors
+                .last()
+                .and_then(|tool| tool["name"].as_str())
+                .map(str::to_string);
+        }
+    }
+    let mut result = json!({ "tools": descriptors });
+    if let Some(next_cursor) = next_cursor {
+        result["nextCursor"] = json!(next_cursor);
+    }
+    success_response(id, result)
+}
+
+/// Lists indexed files as `lumora://file/<path>` resources, a page at a
+/// time. The cursor is the last path of the previous page.
+fn list_resources(params: Option<&Value>, id: Value, paths: &RuntimePaths) -> Value {
+    let cursor = params
+        .and_then(|params| params.get("cursor"))
+        .and_then(Value::as_str);
+    let files = match GraphStore::open(&paths.db_path)
+        .and_then(|store| store.indexed_files(cursor, RESOURCE_PAGE_SIZE + 1))
+    {
+        Ok(files) => files,
+        Err(err) => return error_response(Some(id), -32603, &format!("{err:#}")),
+    };
+
+    let has_more = files.len() > RESOURCE_PAGE_SIZE;
+    let page = &files[..files.len().min(RESOURCE_PAGE_SIZE)];
+    let resources: Vec<Value> = page
+        .iter()
+        .map(|file| {
+            json!({
+                "uri": file_resource_uri(&file.path),
+                "name": file.path,
+                "description": format!("{} source file", file.language),
+                "mimeType": resource_mime_type(&file.language),
+                "size": file.size_bytes
+            })
+        })
+        .collect();
+    let mut result = json!({ "resources": resources });
+    if has_more {
+        if let Some(last) = page.last() {
+            result["nextCursor"] = json!(last.path);
+        }
+    }
+    success_response(id, result)
+}
+
+/// Returns the text of one indexed file. URIs outside the index, including
+/// files that exist on disk but are not tracked, are not found.
+fn read_resource(params: Option<&Value>, id: Value, paths: &RuntimePaths) -> Value {
+    let Some(uri) = params
+        .and_then(|params| params.get("uri"))
+        .and_then(Value::as_str)
+    else {
+        return error_response(
+            Some(id),
+            -32602,
+            "resources/read requires string field `uri`",
+        );
+    };
+    let Some(rel_path) = uri
+        .strip_prefix(FILE_RESOURCE_PREFIX)
+        .and_then(percent_decode)
+    else {
+        return error_response(
+            Some(id),
+            -32602,
+            &format!("unsupported resource URI `{uri}`; expected {FILE_RESOURCE_PREFIX}<path>"),
+        );
+    };
+
+    let language = match GraphStore::open(&paths.db_path)
+        .and_then(|store| store.tracked_file_language(&rel_path))
+    {
+        Ok(Some(language)) => language,
+        Ok(None) => {
+            return error_response(
+                Some(id),
+                RESOURCE_NOT_FOUND,
+                &format!("Resource not found: {uri}"),
+            )
+        }
+        Err(err) => return error_response(Some(id), -32603, &format!("{err:#}")),
+    };
+    let text = match fileops::safe_resolve_path(&paths.repo_root, &rel_path).and_then(|resolved| {
+        fs::read_to_string(&resolved)
+            .with_context(|| format!("failed to read {}", resolved.display()))
+    }) {
+        Ok(text) => text,
+        Err(err) if error_code(&err) == ErrorCode::FileNotFound => {
+            return error_response(
+                Some(id),
+                RESOURCE_NOT_FOUND,
+                &format!("Resource not found: {uri}"),
+            )
+        }
+        Err(err) => return error_response(Some(id), -32603, &format!("{err:#}")),
+    };
+
+    success_response(
+        id,
+        json!({
+            "contents": [{
+                "uri": file_resource_uri(&rel_path),
+                "mimeType": resource_mime_type(&language),
+                "text": text
+            }]
+        }),
+    )
+}
+
+/// A canned workflow advertised through `prompts/list`. `render` turns the
+/// client's arguments into step-by-step guidance naming Lumora's tools.
+struct PromptSpec {
+    name: &'static str,
+    description: &'static str,
+    /// `(name, description, required)`.
+    arguments: &'static [(&'static str, &'static str, bool)],
+    /// Tools the guidance relies on; the prompt is hidden when the tool
+    /// filter disables any of them.
+    tools: &'static [&'static str],
+    render: fn(&HashMap<String, String>) -> String,
+}
+
+const PROMPTS: &[PromptSpec] = &[
+    PromptSpec {
+        name: "orient_in_repo",
+        description: "Get oriented in an unfamiliar repository or subdirectory before changing it.",
+        arguments: &[(
+            "focus",
+            "Directory to concentrate on, relative to the repo root; defaults to the whole repo.",
+            false,
+        )],
+        tools: &[
+            "lumora.index_repository",
+            "lumora.dir_outline",
+            "lumora.multi_outline",
+            "lumora.selector_discover",
+            "lumora.symbol_neighbors",
+        ],
+        render: render_orient_prompt,
+    },
+    PromptSpec {
+        name: "investigate_symbol",
+        description: "Understand one symbol: where it is defined, what it does, who uses it, and how it changed.",
+        arguments: &[("name", "Symbol name or selector, e.g. `parse_config` or `symbol:Config`.", true)],
+        tools: &[
+            "lumora.symbol_definitions",
+            "lumora.get_symbol_source",
+            "lumora.symbol_references",
+            "lumora.symbol_callers",
+            "lumora.minimal_slice",
+            "lumora.symbol_history",
+        ],
+        render: render_investigate_prompt,
+    },
+    PromptSpec {
+        name: "find_duplication",
+        description: "Find duplicated code under a directory and judge which copies are worth merging.",
+        arguments: &[
+            ("directory", "Directory to scan, relative to the repo root.", true),
+            ("min_similarity", "Lowest clone similarity to report, 0-1; defaults to 0.3.", false),
+        ],
+        tools: &[
+            "lumora.dir_outline",
+            "lumora.clone_matches",
+            "lumora.compare_files",
+        ],
+        render: render_duplication_prompt,
+    },
+];
+
+fn render_orient_prompt(args: &HashMap<String, String>) -> String {
+    let focus = args.get("focus").map_or(".", String::as_str);
+    format!(
+        "Get oriented in `{focus}` of this repository using the Lumora tools, spending as few tokens as possible.\n\n\
+         1. Call `lumora.index_repository` (incremental) so the graph is current.\n\
+         2. Call `lumora.dir_outline` with `path: \"{focus}\"` and `max_depth: 2` to see the files and their top-level definitions.\n\
+         3. Pick the files that look like entry points or core types (`main`, `lib`, `mod`, `index`, `app`, `server`) and read their shape with one `lumora.multi_outline` call instead of reading them.\n\
+         4. For the two or three most central types or functions, call `lumora.symbol_neighbors` to see what they depend on and what depends on them. Use `lumora.selector_discover` when you only know part of a name.\n\
+         5. Summarize the layout: main modules, how data flows between them, and where to look first for changes. Read file contents with `lumora.read_file` only where the outline is not enough."
+    )
+}
+
+fn render_investigate_prompt(args: &HashMap<String, String>) -> String {
+    let name = args.get("name").map_or("", String::as_str);
+    format!(
+        "Investigate the symbol `{name}` using the Lumora tools.\n\n\
+         1. Call `lumora.symbol_definitions` with `name: \"{name}\"`. If nothing matches, retry with a `did_you_mean` suggestion; if several definitions match, note them and pick the relevant one.\n\
+         2. Call `lumora.get_symbol_source` with the chosen selector to read the exact definition.\n\
+         3. Call `lumora.symbol_references` with `group_by: \"enclosing_symbol\"` to see which functions use it, then `lumora.symbol_callers` (`include_tests: false` for production call sites only).\n\
+         4. Call `lumora.minimal_slice` on the definition's file and line with `depth: 2` for its surrounding dependency graph.\n\
+         5. Call `lumora.symbol_history` to see recent commits and authors that touched it.\n\
+         6. Report what `{name}` does, its inputs and outputs, its main callers, and anything surprising, such as unresolved references or recent churn."
+    )
+}
+
+fn render_duplication_prompt(args: &HashMap<String, String>) -> String {
+    let directory = args.get("directory").map_or(".", String::as_str);
+    let min_similarity = args.get("min_similarity").map_or("0.3", String::as_str);
+    format!(
+        "Find duplicated code under `{directory}` using the Lumora tools.\n\n\
+         1. Call `lumora.dir_outline` with `path: \"{directory}\"` to list the indexed files there.\n\
+         2. For each file, call `lumora.clone_matches` with `min_similarity: {min_similarity}`, `same_language_only: true`, and `include_tests: false`. Use `mode: \"hotspots\"` on large directories to see which areas attract the most copies first.\n\
+         3. For the strongest pairs, call `lumora.compare_files` to see the shared regions side by side.\n\
+         4. List the duplicated blocks, grouped by pair, with the line ranges from each match's `regions`. For each, say whether it is worth extracting into a shared helper and where that helper should live."
+    )
+}
+
+fn prompt_descriptor(prompt: &PromptSpec) -> Value {
+    let arguments: Vec<Value> = prompt
+        .arguments
+        .iter()
+        .map(|(name, description, required)| {
+            json!({ "name": name, "description": description, "required": required })
+        })
+        .collect();
+    json!({
+        "name": prompt.name,
+        "description": prompt.description,
+        "arguments": arguments
+    })
+}
+
+/// Expands one prompt into a single user message. Unknown prompts and
+/// missing required arguments are invalid params, as the spec asks.
+fn get_prompt(params: Option<&Value>, id: Value, tools: &ToolFilter) -> Value {
+    let Some(name) = params
+        .and_then(|params| params.get("name"))
+        .and_then(Value::as_str)
+    else {
+        return error_response(Some(id), -32602, "prompts/get requires string field `name`");
+    };
+    let Some(prompt) = PROMPTS
+        .iter()
+        .find(|prompt| prompt.name == name)
+        .filter(|prompt| prompt.tools.iter().all(|tool| tools.permits(tool)))
+    else {
+        return error_response(Some(id), -32602, &format!("Unknown prompt `{name}`"));
+    };
+
+    let mut arguments = HashMap::new();
+    if let Some(given) = params
+        .and_then(|params| params.get("arguments"))
+        .and_then(Value::as_object)
+    {
+        for (key, value) in given {
+            let text = match value {
+                Value::String(text) => text.clone(),
+                other => other.to_string(),
+            };
+            if !text.trim().is_empty() {
+                arguments.insert(key.clone(), text);
+            }
+        }
+    }
+    if let Some((missing, _, _)) = prompt
+        .arguments
+        .iter()
+        .find(|(arg, _, required)| *required && !arguments.contains_key(*arg))
+    {
+        return error_response(
+            Some(id),
+            -32602,
+            &format!("prompt `{name}` requires argument `{missing}`"),
+        );
+    }
+
+    success_response(
+        id,
+        json!({
+            "description": prompt.description,
+            "messages": [{
+                "role": "user",
+                "content": { "type": "text", "text": (prompt.render)(&arguments) }
+            }]
+        }),
+    )
+}
+
+/// `lumora://file/<path>`, with characters outside the URI path set
+/// percent-encoded.
+fn file_resource_uri(rel_path: &str) -> String {
+    let mut uri = FILE_RESOURCE_PREFIX.to_string();
+    for byte in rel_path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    uri
+}
+
+pub fn percent_decode(raw: &str) -> Option<String> {
+    let bytes = raw.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = raw.get(index + 1..index + 3)?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            out.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8(out).ok().filter(|path| !path.is_empty())
+}
+
+fn resource_mime_type(language: &str) -> &'static str {
+    match language {
+        "json" => "application/json",
+        "toml" => "application/toml",
+        "yaml" => "application/yaml",
+        "html" => "text/html",
+        "css" => "text/css",
+        _ => "text/plain",
+    }
+}
+
+/// Runs a tool and enforces the caller's `max_bytes` / `max_tokens_estimate`
+/// budget on its structured result. A `timeout_ms` bounds graph queries; a
+/// query that runs out of time returns what it gathered, flagged `timed_out`.
+fn call_tool_with_budget(
+    tool_name: &str,
+    args: &Value,
+    paths: &RuntimePaths,
+) -> std::result::Result<Value, ToolCallError> {
+    let max_bytes = opt_u64(args, "max_bytes")?;
+    let max_tokens = opt_u64(args, "max_tokens_estimate")?;
+    let budget = match (
+        max_bytes,
+        max_tokens.map(|t| t.saturating_mul(BYTES_PER_TOKEN)),
+    ) {
+        (Some(bytes), Some(tokens)) => Some(bytes.min(tokens)),
+        (bytes, tokens) => bytes.or(tokens),
+    };
+
+    let timeout_ms = opt_u64(args, "timeout_ms")?;
+    let deadline = timeout_ms.map(|ms| QueryDeadline::after(Duration::from_millis(ms)));
+
+    let WritePlan {
+        files: files_written,
+        targets,
+        auto_refresh_index,
+    } = check_write_guardrails(tool_name, args, paths)?;
+    // A revert journals itself, so it is not logged a second time here.
+    let pending_op = if files_written > 0 && tool_name != "lumora.undo_last_change" {
+        Some(oplog::begin(paths, tool_name, args, &targets).map_err(ToolCallError::from)?)
+    } else {
+        None
+    };
+    let lazy_index = index_queried_paths(tool_name, args, paths);
+    let response = match call_tool(tool_name, args, paths, deadline.as_ref()) {
+        Ok(mut response) => {
+            if let (Some(lazy_index), Some(object)) = (lazy_index, response.as_object_mut()) {
+                object.insert("lazy_index".to_string(), lazy_index);
+            }
+            record_writes(paths, files_written);
+            if let Some(pending_op) = pending_op {
+                journal_write(pending_op, paths, &mut response);
+            }
+            if files_written > 0 && auto_refresh_index {
+                refresh_written_paths(&targets, paths, &mut response);
+            }
+            response
+        }
+        Err(ToolCallError::Runtime { message, .. })
+            if deadline.as_ref().is_some_and(|d| d.timed_out()) =>
+        {
+            return Err(ToolCallError::Runtime {
+                code: ErrorCode::Timeout,
+                message: format!(
+                    "query timed out after {} ms before producing results: {message}",
+                    timeout_ms.unwrap_or_default()
+                ),
+            });
+        }
+        Err(err) => return Err(err),
+    };
+    let response = match (deadline, response) {
+        (Some(deadline), Value::Object(mut object)) if deadline.timed_out() => {
+            object.insert("timed_out".to_string(), json!(true));
+            object.insert("timeout_ms".to_string(), json!(timeout_ms));
+            Value::Object(object)
+        }
+        (_, response) => response,
+    };
+    match budget {
+        Some(budget) => Ok(apply_response_budget(response, budget.max(1) as usize)),
+        None => Ok(response),
+    }
+}
+
+/// Files each write tool call is about to modify, or `None` for tools that
+/// never write. Dry runs modify nothing.
+fn files_written_by(tool_name: &str, args: &Value, paths: &RuntimePaths) -> Option<usize> {
+    let dry_run = args.get("dry_run").and_then(Value::as_bool) == Some(true);
+    let distinct_paths = |field: &str| {
+        args.get(field)
+            .and_then(Value::as_array)
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item.get("path")?.as_str())
+                    .collect::<HashSet<_>>()
+                    .len()
+            })
+            .unwrap_or(0)
+    };
+    let count = match tool_name {
+        "lumora.write_file" | "lumora.edit_file" | "lumora.delete_file" => 1,
+        "lumora.move_file" => 2,
+        "lumora.batch_edit" => distinct_paths("edits"),
+        "lumora.apply_patch" => distinct_paths("patches"),
+        "lumora.undo_last_change" => paths_written_by(tool_name, args, paths).len(),
+        _ => return None,
+    };
+    Some(if dry_run { 0 } else { count })
+}
+
+/// Repo-relative paths a write tool call names: both ends of a move, every
+/// file of a batch edit or patch, the files of the change an undo reverts.
+fn paths_written_by(tool_name: &str, args: &Value, paths: &RuntimePaths) -> Vec<String> {
+    let field = |key: &str| args.get(key).and_then(Value::as_str).map(str::to_string);
+    let item_paths = |key: &str| {
+        args.get(key)
+            .and_then(Value::as_array)
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| Some(item.get("path")?.as_str()?.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    match tool_name {
+        "lumora.move_file" => field("source")
+            .into_iter()
+            .chain(field("destination"))
+            .collect(),
+        "lumora.batch_edit" => item_paths("edits"),
+        "lumora.apply_patch" => item_paths("patches"),
+        "lumora.undo_last_change" => undo_target_paths(args, paths),
+        _ => field("path").into_iter().collect(),
+    }
+}
+
+/// Files of the oplog entry an undo will revert: `undo_id`, else the latest
+/// undoable one. Empty if there is none; the undo itself reports why.
+fn undo_target_paths(args: &Value, paths: &RuntimePaths) -> Vec<String> {
+    let id = match args.get("undo_id").and_then(Value::as_u64) {
+        Some(id) => Some(id),
+        None => oplog::last_undoable(paths).ok().flatten(),
+    };
+    let entry = id.and_then(|id| {
+        oplog::read_entries(paths)
+            .ok()?
+            .into_iter()
+            .find(|entry| entry.id == id)
+    });
+    entry
+        .map(|entry| entry.files.into_iter().map(|file| file.path).collect())
+        .unwrap_or_default()
+}
+
+/// Logs a finished write tool call to the oplog and reports its entry as
+/// `undo_id`. The write already happened, so a failure to log it is
+/// reported as `oplog_error` instead of failing the call.
+fn journal_write(pending_op: oplog::PendingOp, paths: &RuntimePaths, response: &mut Value) {
+    let Some(object) = response.as_object_mut() else {
+        return;
+    };
+    match pending_op.commit(paths) {
+        Ok(Some(entry)) => {
+            object.insert("undo_id".to_string(), json!(entry.id));
+        }
+        Ok(None) => {}
+        Err(err) => {
+            object.insert("oplog_error".to_string(), json!(format!("{err:#}")));
+        }
+    }
+}
+
+/// Reindexes what a write tool just changed and reports it as
+/// `index_refresh`. The write already happened, so a failed refresh is
+/// reported as `index_refresh_error` instead of failing the call.
+fn refresh_written_paths(written: &[String], paths: &RuntimePaths, response: &mut Value) {
+    // Without a database there is no index to keep in step yet.
+    let Some(object) = response.as_object_mut().filter(|_| paths.db_path.exists()) else {
+        return;
+    };
+    let refreshed = GraphStore::open(&paths.db_path)
+        .and_then(|mut store| refresh_files(&mut store, &paths.repo_root, written));
+    match refreshed {
+        Ok(Some(report)) => {
+            object.insert("index_refresh".to_string(), json!(report));
+        }
+        Ok(None) => {}
+        Err(err) => {
+            object.insert("index_refresh_error".to_string(), json!(format!("{err:#}")));
+        }
+    }
+}
+
+/// On a lazy index, parses the directory or file a read-only query names in
+/// `path`, `file`, or the literal prefix of `file_glob` before it runs. A
+/// query over the whole repo never triggers a full parse. Returns the index
+/// report (or the error) to attach to the response.
+fn index_queried_paths(tool_name: &str, args: &Value, paths: &RuntimePaths) -> Option<Value> {
+    let index_tool = TOOL_GROUPS
+        .iter()
+        .any(|(group, tools)| *group == "index" && tools.contains(&tool_name));
+    if index_tool || files_written_by(tool_name, args, paths).is_some() || !paths.db_path.exists() {
+        return None;
+    }
+    let mut targets: Vec<String> = ["path", "file"]
+        .iter()
+        .filter_map(|key| args.get(*key).and_then(Value::as_str))
+        .map(str::to_string)
+        .collect();
+    if let Some(glob) = args.get("file_glob").and_then(Value::as_str) {
+        targets.extend(glob_literal_prefix(glob));
+    }
+    targets.retain(|target| {
+        let target = target.trim_start_matches("./").trim_matches('/');
+        !target.is_empty() && target != "."
+    });
+    if targets.is_empty() {
+        return None;
+    }
+
+    let mut store = GraphStore::open(&paths.db_path).ok()?;
+    let mut indexed = Vec::new();
+    for target in targets {
+        match index_on_demand(&mut store, &paths.repo_root, &target) {
+            Ok(Some(report)) => indexed.push(json!({
+                "path": target,
+                "indexed_files": report.indexed_files,
+                "errors": report.errors,
+            })),
+            Ok(None) => {}
+            Err(err) => indexed.push(json!({ "path": target, "error": format!("{err:#}") })),
+        }
+    }
+    (!indexed.is_empty()).then(|| json!(indexed))
+}
+
+/// The directory (or file, when there are no wildcards) a glob is rooted at,
+/// e.g. `src/store` for `src/store/**/*.rs`; `None` if the first segment
+/// already has a wildcard.
+fn glob_literal_prefix(glob: &str) -> Option<String> {
+    let Some(wildcard) = glob.find(['*', '?', '[', '{']) else {
+        return Some(glob.to_string());
+    };
+    let dir = glob[..wildcard].rsplit_once('/')?.0;
+    (!dir.is_empty()).then(|| dir.to_string())
+}
+
+/// What a write tool call will do, settled before it runs so nothing after
+/// the write can fail the call.
+#[derive(Default)]
+struct WritePlan {
+    files: usize,
+    /// Resolved up front: once an undo runs, the change it reverted is no
+    /// longer the latest one.
+    targets: Vec<String>,
+    /// The call's `auto_refresh_index`, else `[mcp] auto_refresh_index`.
+    auto_refresh_index: bool,
+}
+
+/// Applies the `[guardrails]` section of `.lumora.toml` to a write tool call
+/// before it runs, and resolves the rest of its [`WritePlan`].
+fn check_write_guardrails(
+    tool_name: &str,
+    args: &Value,
+    paths: &RuntimePaths,
+) -> std::result::Result<WritePlan, ToolCallError> {
+    let Some(files) = files_written_by(tool_name, args, paths) else {
+        return Ok(WritePlan::default());
+    };
+    let config = load_config(&paths.repo_root).map_err(ToolCallError::from)?;
+    let auto_refresh_index =
+        opt_bool(args, "auto_refresh_index")?.unwrap_or(config.mcp.auto_refresh_index);
+    let guardrails = config.guardrails;
+    let blocked = |message: String| ToolCallError::Runtime {
+        code: ErrorCode::GuardrailBlocked,
+        message,
+    };
+
+    if let (Some(max), "lumora.write_file") = (guardrails.max_write_bytes, tool_name) {
+        let size = args
+            .get("content")
+            .and_then(Value::as_str)
+            .map_or(0, str::len) as u64;
+        if size > max {
+            return Err(blocked(format!(
+                "content is {size} bytes, over the {max}-byte `max_write_bytes` limit"
+            )));
+        }
+    }
+    if guardrails.confirm_destructive
+        && matches!(
+            tool_name,
+            "lumora.delete_file" | "lumora.move_file" | "lumora.undo_last_change"
+        )
+        && opt_bool(args, "confirm")? != Some(true)
+    {
+        return Err(blocked(format!(
+            "`{tool_name}` needs `confirm: true` (`confirm_destructive` is set)"
+        )));
+    }
+    if let Some(max) = guardrails.max_files_per_minute {
+        let mut history = write_history()
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let recent = history.entry(paths.repo_root.clone()).or_default();
+        while recent
+            .front()
+            .is_some_and(|written| written.elapsed() >= WRITE_WINDOW)
+        {
+            recent.pop_front();
+        }
+        if recent.len() + files > max {
+            let retry_after = recent
+                .front()
+                .map_or(WRITE_WINDOW, |oldest| WRITE_WINDOW - oldest.elapsed());
+            return Err(blocked(format!(
+                "{} files modified in the last minute; `max_files_per_minute` is {max}, retry in {}s",
+                recent.len(),
+                retry_after.as_secs() + 1
+            )));
+        }
+    }
+    Ok(WritePlan {
+        files,
+        targets: paths_written_by(tool_name, args, paths),
+        auto_refresh_index,
+    })
+}
+
+fn record_writes(paths: &RuntimePaths, files: usize) {
+    if files == 0 {
+        return;
+    }
+    let mut history = write_history()
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let recent = history.entry(paths.repo_root.clone()).or_default();
+    let now = Instant::now();
+    recent.extend(std::iter::repeat(now).take(files));
+}
+
+/// Recent write times per repository. A stdio server is one session, so the
+/// history lives as long as the process.
+fn write_history() -> &'static Mutex<HashMap<PathBuf, VecDeque<Instant>>> {
+    static HISTORY: OnceLock<Mutex<HashMap<PathBuf, VecDeque<Instant>>>> = OnceLock::new();
+    HISTORY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
 fn call_tool(
     tool_name: &str,
     args: &Value,
     paths: &RuntimePaths,
+    deadline: Option<&QueryDeadline>,
 ) -> std::result::Result<Value, ToolCallError> {
     match tool_name {
         "lumora.index_repository" => {
             let full = opt_bool(args, "full")?.unwrap_or(false);
-            let mut store = open_store(paths)?;
-            let report = index_repository(&mut store, &paths.repo_root, IndexOptions { full })
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))?;
+            let embeddings = opt_bool(args, "embeddings")?;
+            let external_entities = opt_bool(args, "external_entities")?;
+            let profile = opt_bool(args, "profile")?.unwrap_or(false);
+            let scope_paths = match args.get("paths") {
+                Some(value) => json_string_array(Some(value), "paths")?,
+                None => Vec::new(),
+            };
+            let scope_languages = match args.get("languages") {
+                Some(value) => json_string_array(Some(value), "languages")?,
+                None => Vec::new(),
+            };
+            let fingerprint_mode = match opt_string(args, "fingerprint_mode")? {
+                Some(raw) => Some(FingerprintMode::parse(&raw).ok_or_else(|| {
+                    ToolCallError::InvalidParams(format!(
+                        "invalid `fingerprint_mode` `{raw}`; expected `code` or `raw`"
+                    ))
+                })?),
+                None => None,
+            };
+            let mut store = open_store(paths, None)?;
+            let report = index_repository(
+                &mut store,
+                &paths.repo_root,
+                IndexOptions {
+                    full,
+                    embeddings,
+                    fingerprint_mode,
+                    external_entities,
+                    scope_paths,
+                    scope_languages,
+                    profile,
+                    ..Default::default()
+                },
+            )
+            .map_err(ToolCallError::from)?;
+            serde_json::to_value(report)
+                .map_err(|err| ToolCallError::internal(format!("serialization error: {err}")))
+        }
+        "lumora.index_file" => {
+            let path = required_str(args, "path")?;
+            let mut store = open_store(paths, None)?;
+            let report = index_repository(
+                &mut store,
+                &paths.repo_root,
+                IndexOptions {
+                    files: vec![path.to_string()],
+                    ..Default::default()
+                },
+            )
+            .map_err(ToolCallError::from)?;
             serde_json::to_value(report)
-                .map_err(|err| ToolCallError::Runtime(format!("serialization error: {err}")))
+                .map_err(|err| ToolCallError::internal(format!("serialization error: {err}")))
+        }
+        "lumora.prune" => {
+            let dry_run = opt_bool(args, "dry_run")?.unwrap_or(false);
+            let vacuum = opt_bool(args, "vacuum")?.unwrap_or(false);
+            let mut store = open_store(paths, None)?;
+            let report = store.prune(dry_run, vacuum).map_err(ToolCallError::from)?;
+            let total_rows = report.total_rows();
+            let mut value = serde_json::to_value(report)
+                .map_err(|err| ToolCallError::internal(format!("serialization error: {err}")))?;
+            value["total_rows"] = json!(total_rows);
+            Ok(value)
         }
         "lumora.symbol_definitions" => {
             let symbol = required_str(args, "name")?;
-            let store = open_store(paths)?;
+            let did_you_mean = opt_bool(args, "did_you_mean")?.unwrap_or(true);
+            let store = open_store(paths, deadline)?;
             let rows = store
                 .symbol_definitions(symbol)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))?;
-            Ok(json!({ "rows": rows }))
+                .map_err(ToolCallError::from)?;
+            let mut response = json!({ "rows": rows });
+            if rows.is_empty() && did_you_mean {
+                attach_did_you_mean(&store, &mut response, symbol)?;
+            }
+            Ok(response)
+        }
+        "lumora.symbols_batch" => {
+            let names = args
+                .get("names")
+                .and_then(Value::as_array)
+                .ok_or_else(|| {
+                    ToolCallError::InvalidParams("`names` must be an array of strings".into())
+                })?
+                .iter()
+                .enumerate()
+                .map(|(idx, name)| {
+                    name.as_str().ok_or_else(|| {
+                        ToolCallError::InvalidParams(format!("`names[{idx}]` must be a string"))
+                    })
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            if names.is_empty() || names.len() > SYMBOLS_BATCH_MAX_NAMES {
+                return Err(ToolCallError::InvalidParams(format!(
+                    "`names` must hold 1 to {SYMBOLS_BATCH_MAX_NAMES} names"
+                )));
+            }
+            let references = opt_u64(args, "references")?.unwrap_or(0);
+            if references > SYMBOLS_BATCH_MAX_REFERENCES {
+                return Err(ToolCallError::InvalidParams(format!(
+                    "`references` must be at most {SYMBOLS_BATCH_MAX_REFERENCES}"
+                )));
+            }
+            let did_you_mean = opt_bool(args, "did_you_mean")?.unwrap_or(true);
+            let include_freshness = opt_bool(args, "include_freshness")?.unwrap_or(false);
+            let verbosity = opt_verbosity(args, "verbosity")?.unwrap_or(Verbosity::Normal);
+
+            let store = open_store(paths, deadline)?;
+            let top = ReferenceQueryOptions {
+                limit: references as usize,
+                ..Default::default()
+            };
+            let mut results = Vec::with_capacity(names.len());
+            let mut seen = HashSet::new();
+            for name in names {
+                // Repeated names would only repeat the same rows.
+                if !seen.insert(name) {
+                    continue;
+                }
+                let definitions = store
+                    .symbol_definitions(name)
+                    .map_err(ToolCallError::from)?;
+                let mut result = json!({ "name": name, "definitions": definitions });
+                if references > 0 {
+                    let (rows, pagination) = store
+                        .symbol_references_page(name, &top)
+                        .map_err(ToolCallError::from)?;
+                    result["references"] = json!(rows);
+                    result["total_references"] = json!(pagination.total);
+                }
+                if definitions.is_empty() && did_you_mean {
+                    attach_did_you_mean(&store, &mut result, name)?;
+                }
+                results.push(result);
+            }
+            let found = results
+                .iter()
+                .filter(|result| {
+                    result["definitions"]
+                        .as_array()
+                        .is_some_and(|rows| !rows.is_empty())
+                })
+                .count();
+            let mut response = json!({ "results": results, "found": found });
+            attach_diagnostics(
+                &store,
+                &mut response,
+                verbosity,
+                include_freshness,
+                json!({ "query": { "references": references } }),
+            )?;
+            Ok(compact_if_needed(response, verbosity))
         }
         "lumora.symbol_source" => {
             let symbol = required_str(args, "name")?;
             let context_lines = opt_u64(args, "context_lines")?.unwrap_or(2);
             let max_definitions = opt_u64(args, "max_definitions")?.unwrap_or(10).max(1) as usize;
             let max_total_lines = opt_u64(args, "max_total_lines")?.unwrap_or(400).max(1);
-            let store = open_store(paths)?;
+            let store = open_store(paths, deadline)?;
             let defs = store
                 .symbol_definitions(symbol)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))?;
+                .map_err(ToolCallError::from)?;
 
             let selected = defs.into_iter().take(max_definitions).collect::<Vec<_>>();
             let reads = selected
@@ -148,7 +1283,7 @@ fn call_tool(
                 .collect::<Vec<_>>();
 
             let read_results = fileops::multi_read(&paths.repo_root, &reads, max_total_lines)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))?;
+                .map_err(ToolCallError::from)?;
             let result_rows = read_results
                 .get("results")
                 .and_then(Value::as_array)
@@ -157,7 +1292,7 @@ fn call_tool(
 
             let rows = selected
                 .into_iter()
-                .zip(result_rows.into_iter())
+                .zip(result_rows)
                 .map(|(definition, read)| {
                     json!({
                         "symbol_name": definition.symbol_name,
@@ -182,18 +1317,176 @@ fn call_tool(
                 }
             }))
         }
-        "lumora.symbol_references" => {
-            let symbol = required_str(args, "name")?;
-            let verbosity = opt_verbosity(args, "verbosity")?.unwrap_or(Verbosity::Normal);
+        "lumora.get_symbol_source" => {
+            let selector = required_str(args, "selector")?;
+            let context_lines = opt_u64(args, "context_lines")?.unwrap_or(0);
+            let max_lines = opt_u64(args, "max_lines")?.unwrap_or(400).max(1);
+            let pick = opt_u64(args, "pick")?.map(|index| index as usize);
+            let store = open_store(paths, deadline)?;
+            let (symbol, resolution) = store
+                .symbol_for_selector(selector, pick)
+                .map_err(ToolCallError::from)?;
+            let query = json!({
+                "selector": selector,
+                "context_lines": context_lines,
+                "max_lines": max_lines,
+                "pick": pick
+            });
+            let Some((symbol, file_path, line)) = symbol.and_then(|symbol| {
+                let file_path = symbol.file_path.clone()?;
+                let line = symbol.line?;
+                Some((symbol, file_path, line))
+            }) else {
+                ensure_indexed(&store)?;
+                return Ok(json!({
+                    "found": false,
+                    "resolution": resolution,
+                    "hint": UNRESOLVED_SELECTOR_HINT,
+                    "query": query
+                }));
+            };
+
+            let end_line = symbol.end_line.unwrap_or(line).max(line);
+            let read =
+                fileops::multi_read(
+                    &paths.repo_root,
+                    &[fileops::MultiReadRequest {
+                        path: file_path.clone(),
+                        start_line: Some(
+                            line.max(1).saturating_sub(context_lines as i64).max(1) as u64
+                        ),
+                        end_line: Some(end_line.saturating_add(context_lines as i64) as u64),
+                    }],
+                    max_lines,
+                )
+                .map_err(ToolCallError::from)?;
+            let read = read["results"][0].clone();
+            let meta = symbol
+                .meta_json
+                .as_deref()
+                .and_then(|raw| serde_json::from_str::<Value>(raw).ok())
+                .unwrap_or(Value::Null);
+
+            let mut symbol_json = json!({
+                "key": symbol.key,
+                "name": symbol.name,
+                "qualname": meta["qualname"],
+                "kind": meta["kind"],
+                "lang": symbol.lang,
+                "file_path": file_path,
+                "line": line,
+                "col": symbol.col,
+                "end_line": symbol.end_line,
+                "end_col": symbol.end_col
+            });
+            if let Some(offsets) = &symbol.offsets {
+                offsets.write_meta(&mut symbol_json);
+            }
+
+            let ambiguous = resolution.ambiguous;
+            let mut response = json!({
+                "found": true,
+                "resolution": resolution,
+                "symbol": symbol_json,
+                "source": read["content"],
+                "start_line": read["start_line"],
+                "end_line": read["end_line"],
+                "truncated": read["truncated"],
+                "query": query
+            });
+            if ambiguous {
+                response["ambiguous"] = json!(true);
+                response["hint"] = json!(AMBIGUOUS_SELECTOR_HINT);
+            }
+            Ok(response)
+        }
+        "lumora.hover" => {
+            let path = required_str(args, "path")?;
+            let line = opt_u64(args, "line")?.ok_or_else(|| {
+                ToolCallError::InvalidParams("missing integer field `line`".into())
+            })?;
+            let col = opt_u64(args, "col")?.ok_or_else(|| {
+                ToolCallError::InvalidParams("missing integer field `col`".into())
+            })?;
+            let abs_path =
+                fileops::safe_resolve_path(&paths.repo_root, path).map_err(ToolCallError::from)?;
+            let source = fs::read_to_string(&abs_path).map_err(|err| ToolCallError::Runtime {
+                code: ErrorCode::FileNotFound,
+                message: format!("cannot read `{path}`: {err}"),
+            })?;
+            let position = json!({ "path": path, "line": line, "col": col });
+            let Some(identifier) = fileops::identifier_at(&source, line as usize, col as usize)
+            else {
+                return Ok(json!({
+                    "found": false,
+                    "position": position,
+                    "hint": "no identifier at this position; `line` and `col` are 1-based"
+                }));
+            };
+
+            let store = open_store(paths, deadline)?;
+            let (definitions, chosen) = store
+                .definition_at(path, identifier, line as i64, col as i64)
+                .map_err(ToolCallError::from)?;
+            let definition = chosen.map(|index| &definitions[index]);
+            let doc = match definition {
+                Some(definition) if definition.file_path == path => {
+                    leading_doc_comment(&source, definition.line)
+                }
+                Some(definition) => {
+                    fileops::safe_resolve_path(&paths.repo_root, &definition.file_path)
+                        .ok()
+                        .and_then(|abs_path| fs::read_to_string(abs_path).ok())
+                        .and_then(|text| leading_doc_comment(&text, definition.line))
+                }
+                None => None,
+            };
+
+            let top = ReferenceQueryOptions {
+                limit: HOVER_REFERENCES,
+                ..Default::default()
+            };
+            let (references, pagination) = store
+                .symbol_references_page(identifier, &top)
+                .map_err(ToolCallError::from)?;
+            let mut response = json!({
+                "found": definition.is_some(),
+                "identifier": identifier,
+                "position": position,
+                "definition": definition,
+                "signature": definition.and_then(|definition| definition.signature.clone()),
+                "doc": doc,
+                "definition_count": definitions.len(),
+                "references": references,
+                "total_references": pagination.total,
+            });
+            if definition.is_none() {
+                ensure_indexed(&store)?;
+                attach_did_you_mean(&store, &mut response, identifier)?;
+            }
+            Ok(response)
+        }
+        "lumora.symbol_references" => {
+            let symbol = required_str(args, "name")?;
+            let verbosity = opt_verbosity(args, "verbosity")?.unwrap_or(Verbosity::Normal);
             let limit = opt_u64(args, "limit")?.unwrap_or(200) as usize;
             let offset = opt_u64(args, "offset")?.unwrap_or(0) as usize;
+            let cursor = opt_string(args, "cursor")?;
             let dedup = opt_bool(args, "dedup")?.unwrap_or(true);
             let order = opt_order(args, "order")?.unwrap_or(SortOrder::ScoreDesc);
             let file_glob = opt_string(args, "file_glob")?;
             let language = opt_string(args, "language")?;
             let max_age_hours = opt_u64(args, "max_age_hours")?;
             let summary_mode = opt_string(args, "summary_mode")?;
+            let group_by = opt_group_by(args, "group_by")?;
             let include_freshness = opt_bool(args, "include_freshness")?.unwrap_or(false);
+            let resolved_only = opt_bool(args, "resolved_only")?.unwrap_or(false);
+            let exclude_externals = opt_bool(args, "exclude_externals")?.unwrap_or(false);
+            let exclude_heuristic = opt_bool(args, "exclude_heuristic")?.unwrap_or(false);
+            let include_tests = opt_bool(args, "include_tests")?.unwrap_or(true);
+            let include_generated = opt_bool(args, "include_generated")?.unwrap_or(false);
+            let did_you_mean = opt_bool(args, "did_you_mean")?.unwrap_or(true);
+            let min_confidence = opt_f64(args, "min_confidence")?;
             let calls_only = opt_bool(args, "calls_only")?.unwrap_or(false);
             let edge_type = opt_string(args, "edge_type")?;
 
@@ -214,21 +1507,26 @@ fn call_tool(
                 offset,
                 dedup,
                 order,
+                cursor,
+                resolved_only,
+                exclude_externals,
+                min_confidence,
+                exclude_heuristic,
+                include_tests,
+                include_generated,
             };
-            let store = open_store(paths)?;
-            let (rows, pagination) = store
-                .symbol_references_page(symbol, &options)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))?;
-            let summary = if summary_mode.as_deref() == Some("top_files") {
-                Some(store.top_reference_files(&rows, 10))
-            } else {
-                None
-            };
-
-            let mut response = json!({ "rows": rows, "pagination": pagination });
-            if let Some(summary) = summary {
-                response["top_files"] = serde_json::to_value(summary)
-                    .map_err(|err| ToolCallError::Runtime(format!("serialization error: {err}")))?;
+            let store = open_store(paths, deadline)?;
+            let mut response =
+                reference_rows_response(&store, symbol, &options, group_by, summary_mode)?;
+            if did_you_mean && options.offset == 0 && options.cursor.is_none() {
+                let empty = ["rows", "groups"].iter().any(|field| {
+                    response[*field]
+                        .as_array()
+                        .is_some_and(|items| items.is_empty())
+                });
+                if empty {
+                    attach_did_you_mean(&store, &mut response, symbol)?;
+                }
             }
             attach_diagnostics(
                 &store,
@@ -245,7 +1543,11 @@ fn call_tool(
                         "file_glob": options.file_glob,
                         "language": options.language,
                         "max_age_hours": options.max_age_hours,
-                        "edge_type": options.edge_type_filter
+                        "resolved_only": options.resolved_only,
+                        "exclude_externals": options.exclude_externals,
+                        "min_confidence": options.min_confidence,
+                        "edge_type": options.edge_type_filter,
+                        "group_by": group_by.then_some(GROUP_BY_ENCLOSING_SYMBOL)
                     }
                 }),
             )?;
@@ -256,13 +1558,22 @@ fn call_tool(
             let verbosity = opt_verbosity(args, "verbosity")?.unwrap_or(Verbosity::Normal);
             let limit = opt_u64(args, "limit")?.unwrap_or(200) as usize;
             let offset = opt_u64(args, "offset")?.unwrap_or(0) as usize;
+            let cursor = opt_string(args, "cursor")?;
             let dedup = opt_bool(args, "dedup")?.unwrap_or(true);
             let order = opt_order(args, "order")?.unwrap_or(SortOrder::ScoreDesc);
             let file_glob = opt_string(args, "file_glob")?;
             let language = opt_string(args, "language")?;
             let max_age_hours = opt_u64(args, "max_age_hours")?;
             let summary_mode = opt_string(args, "summary_mode")?;
+            let group_by = opt_group_by(args, "group_by")?;
             let include_freshness = opt_bool(args, "include_freshness")?.unwrap_or(false);
+            let resolved_only = opt_bool(args, "resolved_only")?.unwrap_or(false);
+            let exclude_externals = opt_bool(args, "exclude_externals")?.unwrap_or(false);
+            let exclude_heuristic = opt_bool(args, "exclude_heuristic")?.unwrap_or(false);
+            let include_tests = opt_bool(args, "include_tests")?.unwrap_or(true);
+            let include_generated = opt_bool(args, "include_generated")?.unwrap_or(false);
+            let did_you_mean = opt_bool(args, "did_you_mean")?.unwrap_or(true);
+            let min_confidence = opt_f64(args, "min_confidence")?;
 
             let options = ReferenceQueryOptions {
                 edge_type_filter: Some("calls".to_string()),
@@ -273,20 +1584,26 @@ fn call_tool(
                 offset,
                 dedup,
                 order,
+                cursor,
+                resolved_only,
+                exclude_externals,
+                min_confidence,
+                exclude_heuristic,
+                include_tests,
+                include_generated,
             };
-            let store = open_store(paths)?;
-            let (rows, pagination) = store
-                .symbol_references_page(symbol, &options)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))?;
-            let summary = if summary_mode.as_deref() == Some("top_files") {
-                Some(store.top_reference_files(&rows, 10))
-            } else {
-                None
-            };
-            let mut response = json!({ "rows": rows, "pagination": pagination });
-            if let Some(summary) = summary {
-                response["top_files"] = serde_json::to_value(summary)
-                    .map_err(|err| ToolCallError::Runtime(format!("serialization error: {err}")))?;
+            let store = open_store(paths, deadline)?;
+            let mut response =
+                reference_rows_response(&store, symbol, &options, group_by, summary_mode)?;
+            if did_you_mean && options.offset == 0 && options.cursor.is_none() {
+                let empty = ["rows", "groups"].iter().any(|field| {
+                    response[*field]
+                        .as_array()
+                        .is_some_and(|items| items.is_empty())
+                });
+                if empty {
+                    attach_did_you_mean(&store, &mut response, symbol)?;
+                }
             }
 
             attach_diagnostics(
@@ -303,7 +1620,11 @@ fn call_tool(
                         "order": order_name(order),
                         "file_glob": options.file_glob,
                         "language": options.language,
-                        "max_age_hours": options.max_age_hours
+                        "max_age_hours": options.max_age_hours,
+                        "resolved_only": options.resolved_only,
+                        "exclude_externals": options.exclude_externals,
+                        "min_confidence": options.min_confidence,
+                        "group_by": group_by.then_some(GROUP_BY_ENCLOSING_SYMBOL)
                     }
                 }),
             )?;
@@ -315,21 +1636,45 @@ fn call_tool(
             let verbosity = opt_verbosity(args, "verbosity")?.unwrap_or(Verbosity::Normal);
             let include_freshness = opt_bool(args, "include_freshness")?.unwrap_or(false);
             let max_depth = opt_u64(args, "max_depth")?.unwrap_or(8).max(1) as usize;
-            let store = open_store(paths)?;
-            let (path, from_diag, to_diag) = store
-                .dependency_path_with_diagnostics(from, to, max_depth)
-                .map_err(|err| {
-                    let msg = err.to_string();
-                    if msg.contains("selector") || msg.contains("invalid `") {
-                        ToolCallError::InvalidParams(format!(
-                            "{msg}. Selector examples: file:src/main.rs, symbol_name:rust:main, symbol:main"
-                        ))
-                    } else {
-                        ToolCallError::Runtime(msg)
-                    }
-                })?;
-            let mut response = serde_json::to_value(path)
-                .map_err(|err| ToolCallError::Runtime(format!("serialization error: {err}")))?;
+            let max_paths = opt_u64(args, "max_paths")?.unwrap_or(1).max(1) as usize;
+            let (from_pick, to_pick) = opt_path_pick(args)?;
+            let store = open_store(paths, deadline)?;
+            let with_selector_examples = |err| match ToolCallError::from(err) {
+                ToolCallError::InvalidParams(msg) => ToolCallError::InvalidParams(format!(
+                    "{msg}. Selector examples: file:src/main.rs, symbol_name:rust:main, symbol:main"
+                )),
+                other => other,
+            };
+            let (found, response, from_diag, to_diag) = if max_paths > 1 {
+                let (found_paths, from_diag, to_diag) = store
+                    .dependency_paths_with_diagnostics(
+                        from, to, max_depth, max_paths, from_pick, to_pick,
+                    )
+                    .map_err(with_selector_examples)?;
+                (
+                    found_paths.found,
+                    serde_json::to_value(found_paths),
+                    from_diag,
+                    to_diag,
+                )
+            } else {
+                let (path, from_diag, to_diag) = store
+                    .dependency_path_with_diagnostics(from, to, max_depth, from_pick, to_pick)
+                    .map_err(with_selector_examples)?;
+                (path.found, serde_json::to_value(path), from_diag, to_diag)
+            };
+            if !found {
+                ensure_indexed(&store)?;
+            }
+            let mut response = response
+                .map_err(|err| ToolCallError::internal(format!("serialization error: {err}")))?;
+            if from_diag.ambiguous || to_diag.ambiguous {
+                response["ambiguous"] = json!(true);
+                response["selector"] = json!({ "from": from_diag, "to": to_diag });
+                response["hint"] = json!(
+                    "a selector matched several entities; pass `pick: {\"from\": N, \"to\": N}` with a candidate index, or use a candidate `key` as the selector"
+                );
+            }
             attach_diagnostics(
                 &store,
                 &mut response,
@@ -343,15 +1688,19 @@ fn call_tool(
                     "query": {
                         "from": from,
                         "to": to,
-                        "max_depth": max_depth
+                        "max_depth": max_depth,
+                        "max_paths": max_paths,
+                        "pick": { "from": from_pick, "to": to_pick }
                     }
                 }),
             )?;
             Ok(compact_if_needed(response, verbosity))
         }
         "lumora.minimal_slice" => {
-            let file = required_str(args, "file")?;
-            let line = opt_i64(args, "line")?;
+            let file = opt_string(args, "file")?;
+            let selector = opt_string(args, "selector")?;
+            let pick = opt_u64(args, "pick")?.map(|index| index as usize);
+            let mut line = opt_i64(args, "line")?;
             let depth = opt_u64(args, "depth")?.unwrap_or(2).max(1) as usize;
             let max_neighbors = opt_u64(args, "max_neighbors")?.unwrap_or(40) as usize;
             let dedup = opt_bool(args, "dedup")?.unwrap_or(true);
@@ -359,20 +1708,76 @@ fn call_tool(
                 opt_bool(args, "suppress_low_signal_repeats")?.unwrap_or(true);
             let low_signal_name_cap = opt_u64(args, "low_signal_name_cap")?.unwrap_or(1) as usize;
             let prefer_project_symbols = opt_bool(args, "prefer_project_symbols")?.unwrap_or(true);
+            let exclude_externals = opt_bool(args, "exclude_externals")?.unwrap_or(false);
+            let exclude_heuristic = opt_bool(args, "exclude_heuristic")?.unwrap_or(false);
             let include_freshness = opt_bool(args, "include_freshness")?.unwrap_or(false);
+            let include_source = opt_bool(args, "include_source")?.unwrap_or(false);
+            let max_source_lines = opt_u64(args, "max_source_lines")?.unwrap_or(200);
             let verbosity = opt_verbosity(args, "verbosity")?.unwrap_or(Verbosity::Normal);
-            let store = open_store(paths)?;
+            let mut store = open_store(paths, deadline)?;
+            let config = load_config(&paths.repo_root).map_err(ToolCallError::from)?;
+            store.set_low_signal_names(LowSignalNames::from_config(&config.low_signal));
             let options = SliceQueryOptions {
                 max_neighbors,
                 dedup,
                 suppress_low_signal_repeats,
                 low_signal_name_cap,
                 prefer_project_symbols,
+                exclude_externals,
+                exclude_heuristic,
+            };
+            let mut resolution = None;
+            let file = match (file, selector.as_deref()) {
+                (Some(file), None) => file,
+                (None, Some(selector)) => {
+                    let (symbol, found) = store
+                        .symbol_for_selector(selector, pick)
+                        .map_err(ToolCallError::from)?;
+                    let anchor = symbol.and_then(|symbol| Some((symbol.file_path?, symbol.line)));
+                    let Some((file, symbol_line)) = anchor else {
+                        ensure_indexed(&store)?;
+                        return Ok(json!({
+                            "slice": null,
+                            "resolution": found,
+                            "hint": UNRESOLVED_SELECTOR_HINT
+                        }));
+                    };
+                    line = symbol_line;
+                    resolution = Some(found);
+                    file
+                }
+                (Some(_), Some(_)) => {
+                    return Err(ToolCallError::InvalidParams(
+                        "pass either `file` or `selector`, not both".to_string(),
+                    ))
+                }
+                (None, None) => {
+                    return Err(ToolCallError::InvalidParams(
+                        "missing required argument `file` (or `selector`)".to_string(),
+                    ))
+                }
             };
             let value = store
-                .minimal_slice_with_options(file, line, depth, &options)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))?;
+                .minimal_slice_with_options(&file, line, depth, &options)
+                .map_err(ToolCallError::from)?;
+            if value.is_none() {
+                ensure_indexed(&store)?;
+            }
+            let source = match (&value, include_source) {
+                (Some(slice), true) => Some(slice_source(paths, &store, slice, max_source_lines)?),
+                _ => None,
+            };
             let mut response = json!({ "slice": value });
+            if let Some(source) = source {
+                response["source"] = source;
+            }
+            if let Some(resolution) = resolution {
+                if resolution.ambiguous {
+                    response["ambiguous"] = json!(true);
+                    response["hint"] = json!(AMBIGUOUS_SELECTOR_HINT);
+                }
+                response["resolution"] = json!(resolution);
+            }
             attach_diagnostics(
                 &store,
                 &mut response,
@@ -381,41 +1786,138 @@ fn call_tool(
                 json!({
                     "query": {
                         "file": file,
+                        "selector": selector,
+                        "pick": pick,
                         "line": line,
                         "depth": depth,
                         "max_neighbors": max_neighbors,
                         "dedup": dedup,
                         "suppress_low_signal_repeats": suppress_low_signal_repeats,
                         "low_signal_name_cap": low_signal_name_cap,
-                        "prefer_project_symbols": prefer_project_symbols
+                        "prefer_project_symbols": prefer_project_symbols,
+                        "include_source": include_source,
+                        "max_source_lines": include_source.then_some(max_source_lines)
+                    }
+                }),
+            )?;
+            Ok(compact_if_needed(response, verbosity))
+        }
+        "lumora.symbol_neighbors" => {
+            let selector = required_str(args, "selector")?;
+            let pick = opt_u64(args, "pick")?.map(|index| index as usize);
+            let limit = opt_u64(args, "limit")?.unwrap_or(10) as usize;
+            let include_freshness = opt_bool(args, "include_freshness")?.unwrap_or(false);
+            let verbosity = opt_verbosity(args, "verbosity")?.unwrap_or(Verbosity::Normal);
+            let store = open_store(paths, deadline)?;
+            let (neighbors, resolution) = store
+                .symbol_neighbors(selector, pick, limit)
+                .map_err(ToolCallError::from)?;
+            if neighbors.is_none() {
+                ensure_indexed(&store)?;
+            }
+            let ambiguous = resolution.ambiguous;
+            let mut response = json!({
+                "found": neighbors.is_some(),
+                "neighbors": neighbors,
+                "resolution": resolution
+            });
+            if ambiguous {
+                response["ambiguous"] = json!(true);
+                response["hint"] = json!(AMBIGUOUS_SELECTOR_HINT);
+            }
+            attach_diagnostics(
+                &store,
+                &mut response,
+                verbosity,
+                include_freshness,
+                json!({
+                    "query": {
+                        "selector": selector,
+                        "pick": pick,
+                        "limit": limit
                     }
                 }),
             )?;
             Ok(compact_if_needed(response, verbosity))
         }
+        "lumora.symbol_history" => {
+            let selector = required_str(args, "selector")?;
+            let pick = opt_u64(args, "pick")?.map(|index| index as usize);
+            let limit = opt_u64(args, "limit")?.unwrap_or(10).max(1) as usize;
+            let store = open_store(paths, deadline)?;
+            let (symbol, resolution) = store
+                .symbol_for_selector(selector, pick)
+                .map_err(ToolCallError::from)?;
+            let query = json!({ "selector": selector, "pick": pick, "limit": limit });
+            let Some((symbol, file_path, line)) = symbol.and_then(|symbol| {
+                let file_path = symbol.file_path.clone()?;
+                let line = symbol.line?;
+                Some((symbol, file_path, line))
+            }) else {
+                ensure_indexed(&store)?;
+                return Ok(json!({
+                    "found": false,
+                    "resolution": resolution,
+                    "hint": UNRESOLVED_SELECTOR_HINT,
+                    "query": query
+                }));
+            };
+
+            let end_line = symbol.end_line.unwrap_or(line).max(line);
+            let history =
+                history::line_history(&paths.repo_root, &file_path, line, end_line, limit)
+                    .map_err(ToolCallError::from)?;
+            let ambiguous = resolution.ambiguous;
+            let mut response = json!({
+                "found": true,
+                "resolution": resolution,
+                "symbol": {
+                    "key": symbol.key,
+                    "name": symbol.name,
+                    "file_path": file_path,
+                    "line": line,
+                    "end_line": end_line
+                },
+                "history": history,
+                "query": query
+            });
+            if ambiguous {
+                response["ambiguous"] = json!(true);
+                response["hint"] = json!(AMBIGUOUS_SELECTOR_HINT);
+            }
+            Ok(response)
+        }
         "lumora.clone_matches" => {
             let file = required_str(args, "file")?;
             let min_similarity = opt_f64(args, "min_similarity")?.unwrap_or(0.02);
             let limit = opt_u64(args, "limit")?.unwrap_or(50) as usize;
             let offset = opt_u64(args, "offset")?.unwrap_or(0) as usize;
             let mode = opt_string(args, "mode")?.unwrap_or_else(|| "matches".to_string());
+            let same_language_only = opt_bool(args, "same_language_only")?.unwrap_or(false);
+            let language = opt_string(args, "language")?;
+            let include_tests = opt_bool(args, "include_tests")?.unwrap_or(true);
+            let include_generated = opt_bool(args, "include_generated")?.unwrap_or(false);
             let include_freshness = opt_bool(args, "include_freshness")?.unwrap_or(false);
             let verbosity = opt_verbosity(args, "verbosity")?.unwrap_or(Verbosity::Normal);
-            let store = open_store(paths)?;
+            let store = open_store(paths, deadline)?;
             let options = CloneQueryOptions {
                 min_similarity,
                 limit,
                 offset,
+                same_language_only,
+                language: language.clone(),
+                include_tests,
+                include_generated,
             };
             let mut response = if mode == "hotspots" {
                 let (rows, pagination, analysis) = store
                     .clone_hotspots_page(file, &options)
-                    .map_err(|err| ToolCallError::Runtime(err.to_string()))?;
+                    .map_err(ToolCallError::from)?;
                 json!({ "rows": rows, "mode": "hotspots", "pagination": pagination, "analysis": analysis })
             } else {
                 let (rows, pagination, analysis) = store
                     .clone_matches_page(file, &options)
-                    .map_err(|err| ToolCallError::Runtime(err.to_string()))?;
+                    .map_err(ToolCallError::from)?;
                 json!({ "rows": rows, "mode": "matches", "pagination": pagination, "analysis": analysis })
             };
             if response["rows"]
@@ -426,6 +1928,8 @@ fn call_tool(
                 if let Some(reason) = response["analysis"]["empty_reason"].as_str() {
                     response["warning"] = json!(reason);
                 }
+            } else if let Some(warning) = response["analysis"]["params_warning"].as_str() {
+                response["warning"] = json!(warning);
             }
             attach_diagnostics(
                 &store,
@@ -438,25 +1942,145 @@ fn call_tool(
                         "min_similarity": min_similarity,
                         "limit": limit,
                         "offset": offset,
-                        "mode": mode
+                        "mode": mode,
+                        "same_language_only": same_language_only,
+                        "language": language,
+                        "include_tests": include_tests,
+                        "include_generated": include_generated
                     }
                 }),
             )?;
             Ok(compact_if_needed(response, verbosity))
         }
+        "lumora.compare_files" => {
+            let left = required_str(args, "left")?;
+            let right = required_str(args, "right")?;
+            let context_lines = opt_u64(args, "context_lines")?.unwrap_or(3) as usize;
+            let max_lines = opt_u64(args, "max_lines")?.unwrap_or(500) as usize;
+            let mut response =
+                fileops::compare_files(&paths.repo_root, left, right, context_lines, max_lines)
+                    .map_err(ToolCallError::from)?;
+            let left_path = response["left"]["path"]
+                .as_str()
+                .unwrap_or(left)
+                .to_string();
+            let right_path = response["right"]["path"]
+                .as_str()
+                .unwrap_or(right)
+                .to_string();
+            let store = open_store(paths, deadline)?;
+            match store
+                .clone_match_between(&left_path, &right_path)
+                .map_err(ToolCallError::from)?
+            {
+                Some(fingerprints) => {
+                    if let Some(params) = &fingerprints.fingerprint_params {
+                        response["warning"] = json!(format!(
+                            "`{right_path}` was fingerprinted with different parameters ({params}) than `{left_path}`, so the similarity is not comparable; run `lumora index --full` to rebuild with the current settings"
+                        ));
+                    }
+                    response["fingerprints"] = json!(fingerprints);
+                }
+                None => {
+                    response["fingerprints"] = Value::Null;
+                    response["fingerprint_note"] = json!(
+                        "one or both files have no fingerprints; they may be too small, unsupported, or not yet indexed"
+                    );
+                }
+            }
+            Ok(response)
+        }
+        "lumora.dir_outline" => {
+            let path = opt_string(args, "path")?.unwrap_or_default();
+            let options = DirOutlineOptions {
+                max_depth: opt_u64(args, "max_depth")?.map(|v| v as usize),
+                max_files: opt_u64(args, "max_files")?.unwrap_or(200).max(1) as usize,
+            };
+            let store = open_store(paths, deadline)?;
+            let outline = store
+                .dir_outline(&path, &options)
+                .map_err(ToolCallError::from)?;
+            serde_json::to_value(outline)
+                .map_err(|err| ToolCallError::internal(format!("serialization error: {err}")))
+        }
+        "lumora.file_profile" => {
+            let path = required_str(args, "path")?;
+            let store = open_store(paths, deadline)?;
+            let profile = store
+                .file_profile(path)
+                .map_err(ToolCallError::from)?
+                .ok_or_else(|| {
+                    ToolCallError::from(coded(
+                        ErrorCode::FileNotFound,
+                        format!("`{path}` is not indexed"),
+                    ))
+                })?;
+            serde_json::to_value(profile)
+                .map_err(|err| ToolCallError::internal(format!("serialization error: {err}")))
+        }
+        "lumora.todos" => {
+            let file_glob = opt_string(args, "file_glob")?;
+            let tags = match args.get("tags") {
+                Some(value) => json_string_array(Some(value), "tags")?,
+                None => Vec::new(),
+            };
+            let limit = opt_u64(args, "limit")?.unwrap_or(200).max(1) as usize;
+            let store = open_store(paths, deadline)?;
+            let mut rows = store
+                .annotations(file_glob.as_deref(), &tags)
+                .map_err(ToolCallError::from)?;
+            let total = rows.len();
+            rows.truncate(limit);
+            Ok(json!({ "rows": rows, "total": total, "has_more": total > limit }))
+        }
+        "lumora.semantic_search" => {
+            let query = required_str(args, "query")?;
+            let options = SemanticSearchOptions {
+                kind: opt_string(args, "kind")?,
+                file_glob: opt_string(args, "file_glob")?,
+                min_score: opt_f64(args, "min_score")?.unwrap_or(0.1),
+                limit: opt_u64(args, "limit")?.unwrap_or(20) as usize,
+            };
+            let store = open_store(paths, deadline)?;
+            let enabled = store.embeddings_enabled().map_err(ToolCallError::from)?;
+            if !enabled {
+                return Ok(json!({
+                    "rows": [],
+                    "warning": "embeddings are not enabled for this index; run `lumora index --embeddings` or call lumora.index_repository with embeddings=true",
+                }));
+            }
+            let query_vector = Embedder::shared()
+                .and_then(|embedder| embedder.embed(vec![query.to_string()]))
+                .map_err(ToolCallError::from)?
+                .pop()
+                .unwrap_or_default();
+            let rows = store
+                .semantic_search(&query_vector, &options)
+                .map_err(ToolCallError::from)?;
+            Ok(json!({ "rows": rows }))
+        }
         "lumora.read_file" => {
             let path = required_str(args, "path")?;
             let start_line = opt_u64(args, "start_line")?;
             let end_line = opt_u64(args, "end_line")?;
             let max_lines = opt_u64(args, "max_lines")?.unwrap_or(500);
             fileops::read_file_contents(&paths.repo_root, path, start_line, end_line, max_lines)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))
+                .map_err(ToolCallError::from)
+        }
+        "lumora.read_range" => {
+            let path = required_str(args, "path")?;
+            let start_byte = opt_u64(args, "start_byte")?.ok_or_else(|| {
+                ToolCallError::InvalidParams("missing field `start_byte`".to_string())
+            })?;
+            let end_byte = opt_u64(args, "end_byte")?;
+            let max_len = opt_u64(args, "max_len")?.unwrap_or(fileops::DEFAULT_READ_RANGE_MAX_LEN);
+            fileops::read_file_range(&paths.repo_root, path, start_byte, end_byte, max_len)
+                .map_err(ToolCallError::from)
         }
         "lumora.file_outline" => {
             let path = required_str(args, "path")?;
             let max_depth = opt_u64(args, "max_depth")?.map(|v| v as usize);
-            fileops::file_outline(&paths.repo_root, path, max_depth)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))
+            fileops::file_outline(&paths.repo_root, path, max_depth).map_err(ToolCallError::from)
         }
         "lumora.multi_outline" => {
             let outlines_arg = args.get("outlines").ok_or_else(|| {
@@ -492,8 +2116,7 @@ fn call_tool(
                 outlines.push(fileops::MultiOutlineRequest { path, max_depth });
             }
 
-            fileops::multi_outline(&paths.repo_root, &outlines)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))
+            fileops::multi_outline(&paths.repo_root, &outlines).map_err(ToolCallError::from)
         }
         "lumora.search_files" => {
             let pattern = required_str(args, "pattern")?;
@@ -501,6 +2124,13 @@ fn call_tool(
             let context_lines = opt_u64(args, "context_lines")?.unwrap_or(2);
             let max_results = opt_u64(args, "max_results")?.unwrap_or(50);
             let is_regex = opt_bool(args, "is_regex")?.unwrap_or(false);
+            let options = fileops::SearchOptions {
+                max_file_size: Some(
+                    opt_u64(args, "max_file_size")?
+                        .unwrap_or(fileops::DEFAULT_SEARCH_MAX_FILE_SIZE),
+                ),
+                respect_gitignore: opt_bool(args, "respect_gitignore")?.unwrap_or(true),
+            };
             fileops::search_in_files(
                 &paths.repo_root,
                 pattern,
@@ -508,8 +2138,24 @@ fn call_tool(
                 context_lines,
                 max_results,
                 is_regex,
+                &options,
             )
-            .map_err(|err| ToolCallError::Runtime(err.to_string()))
+            .map_err(ToolCallError::from)
+        }
+        "lumora.ast_search" => {
+            let query = required_str(args, "query")?;
+            let options = AstSearchOptions {
+                file_glob: opt_string(args, "file_glob")?,
+                language: opt_string(args, "language")?,
+                max_results: opt_u64(args, "max_results")?.unwrap_or(100).max(1) as usize,
+            };
+            let store = open_store(paths, deadline)?;
+            let result = ast_search(&store, &paths.repo_root, query, &options, deadline)?;
+            if result.files_scanned == 0 {
+                ensure_indexed(&store)?;
+            }
+            serde_json::to_value(result)
+                .map_err(|err| ToolCallError::internal(format!("serialization error: {err}")))
         }
         "lumora.list_directory" => {
             let path = opt_string(args, "path")?.unwrap_or_else(|| ".".to_string());
@@ -523,22 +2169,47 @@ fn call_tool(
                 max_depth,
                 file_glob.as_deref(),
             )
-            .map_err(|err| ToolCallError::Runtime(err.to_string()))
+            .map_err(ToolCallError::from)
         }
         "lumora.write_file" => {
             let path = required_str(args, "path")?;
             let content = required_str(args, "content")?;
             let create_dirs = opt_bool(args, "create_dirs")?.unwrap_or(true);
-            fileops::write_file_contents(&paths.repo_root, path, content, create_dirs)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))
+            let mode = match opt_string(args, "mode")? {
+                Some(raw) => WriteMode::parse(&raw).ok_or_else(|| {
+                    ToolCallError::InvalidParams(format!(
+                        "`mode` must be `overwrite`, `append`, or `create_new`, got `{raw}`"
+                    ))
+                })?,
+                None => WriteMode::default(),
+            };
+            let options = WriteOptions {
+                create_dirs,
+                mode,
+                expected_hash: opt_string(args, "expected_hash")?,
+            };
+            fileops::write_file_contents(&paths.repo_root, path, content, &options)
+                .map_err(ToolCallError::from)
         }
         "lumora.edit_file" => {
             let path = required_str(args, "path")?;
             let old_text = required_str(args, "old_text")?;
             let new_text = required_str(args, "new_text")?;
             let dry_run = opt_bool(args, "dry_run")?.unwrap_or(false);
-            fileops::edit_file_contents(&paths.repo_root, path, old_text, new_text, dry_run)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))
+            let options = fileops::EditOptions {
+                replace_all: opt_bool(args, "replace_all")?.unwrap_or(false),
+                expected_occurrences: opt_u64(args, "expected_occurrences")?,
+                is_regex: opt_bool(args, "is_regex")?.unwrap_or(false),
+            };
+            fileops::edit_file_contents(
+                &paths.repo_root,
+                path,
+                old_text,
+                new_text,
+                &options,
+                dry_run,
+            )
+            .map_err(ToolCallError::from)
         }
         "lumora.batch_edit" => {
             let edits_arg = args
@@ -599,7 +2270,7 @@ fn call_tool(
 
             let dry_run = opt_bool(args, "dry_run")?.unwrap_or(false);
             fileops::batch_edit_file_contents(&paths.repo_root, &edits, dry_run)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))
+                .map_err(ToolCallError::from)
         }
         "lumora.apply_patch" => {
             let patches_arg = args.get("patches").ok_or_else(|| {
@@ -672,7 +2343,7 @@ fn call_tool(
 
             let dry_run = opt_bool(args, "dry_run")?.unwrap_or(false);
             fileops::apply_patch_file_contents(&paths.repo_root, &patches, dry_run)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))
+                .map_err(ToolCallError::from)
         }
         "lumora.multi_read" => {
             let reads_arg = args
@@ -723,18 +2394,28 @@ fn call_tool(
 
             let max_total_lines = opt_u64(args, "max_total_lines")?.unwrap_or(2000);
             fileops::multi_read(&paths.repo_root, &reads, max_total_lines)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))
+                .map_err(ToolCallError::from)
         }
         "lumora.move_file" => {
             let source = required_str(args, "source")?;
             let destination = required_str(args, "destination")?;
             fileops::move_file_op(&paths.repo_root, source, destination)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))
+                .map_err(ToolCallError::from)
         }
         "lumora.delete_file" => {
             let path = required_str(args, "path")?;
-            fileops::delete_file_op(&paths.repo_root, path)
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))
+            fileops::delete_file_op(&paths.repo_root, path).map_err(ToolCallError::from)
+        }
+        "lumora.undo_last_change" => {
+            let id = match opt_u64(args, "undo_id")? {
+                Some(id) => id,
+                None => oplog::last_undoable(paths)
+                    .map_err(ToolCallError::from)?
+                    .ok_or_else(|| ToolCallError::InvalidParams("no change to undo".to_string()))?,
+            };
+            let force = opt_bool(args, "force")?.unwrap_or(false);
+            let entry = oplog::revert(paths, id, force).map_err(ToolCallError::from)?;
+            Ok(json!({ "undone": id, "undo_id": entry.id, "files": entry.files }))
         }
         "lumora.selector_discover" => {
             let query = opt_string(args, "query")?;
@@ -742,7 +2423,7 @@ fn call_tool(
             let file_glob = opt_string(args, "file_glob")?;
             let entity_type = opt_string(args, "entity_type")?;
             let fuzzy = opt_bool(args, "fuzzy")?.unwrap_or(true);
-            let store = open_store(paths)?;
+            let store = open_store(paths, deadline)?;
             let rows = store
                 .selector_suggestions_advanced(&SelectorSuggestOptions {
                     query,
@@ -751,7 +2432,7 @@ fn call_tool(
                     limit,
                     fuzzy,
                 })
-                .map_err(|err| ToolCallError::Runtime(err.to_string()))?;
+                .map_err(ToolCallError::from)?;
             Ok(json!({
                 "rows": rows,
                 "query_info": {
@@ -772,11 +2453,16 @@ fn call_tool(
     }
 }
 
-fn open_store(paths: &RuntimePaths) -> std::result::Result<GraphStore, ToolCallError> {
+fn open_store(
+    paths: &RuntimePaths,
+    deadline: Option<&QueryDeadline>,
+) -> std::result::Result<GraphStore, ToolCallError> {
     if let Some(parent) = paths.db_path.parent() {
         let _ = fs::create_dir_all(parent);
     }
-    GraphStore::open(&paths.db_path).map_err(|err| ToolCallError::Runtime(err.to_string()))
+    let mut store = GraphStore::open(&paths.db_path).map_err(ToolCallError::from)?;
+    store.set_query_deadline(deadline.cloned());
+    Ok(store)
 }
 
 fn initialize_result(params: Option<&Value>) -> Value {
@@ -790,6 +2476,16 @@ fn initialize_result(params: Option<&Value>) -> Value {
         "capabilities": {
             "tools": {
                 "listChanged": false
+            },
+            "resources": {
+                "subscribe": false,
+                "listChanged": false
+            },
+            "prompts": {
+                "listChanged": false
+            },
+            "experimental": {
+                INDEX_CHANGED_METHOD: {}
             }
         },
         "serverInfo": {
@@ -800,25 +2496,69 @@ fn initialize_result(params: Option<&Value>) -> Value {
 }
 
 fn tool_descriptors() -> Vec<Value> {
-    vec![
+    let mut tools = vec![
         json!({
             "name": "lumora.index_repository",
             "description": "Run incremental or full indexing for the configured repository.",
             "inputSchema": {
                 "type": "object",
                 "properties": {
-                    "full": { "type": "boolean", "description": "Set true for full rebuild." }
+                    "full": { "type": "boolean", "description": "Set true for full rebuild." },
+                    "embeddings": { "type": "boolean", "description": "Set true to start computing semantic_search embeddings (needs the `semantic` feature), false to stop and delete them; omitted keeps the current setting." },
+                    "fingerprint_mode": { "type": "string", "enum": ["code", "raw"], "description": "Clone fingerprint input: `code` ignores comments and string literals, `raw` uses the file text. Changing it rebuilds the index." },
+                    "external_entities": { "type": "boolean", "description": "Mark names with no definition in the repo (stdlib, third-party) as `external` entities. Stays on until turned off." },
+                    "paths": { "type": "array", "items": { "type": "string" }, "description": "Only index these repo-relative directories or files; other indexed files are left as they are." },
+                    "languages": { "type": "array", "items": { "type": "string" }, "description": "Only index these languages, e.g. [\"rust\", \"python\"]." },
+                    "profile": { "type": "boolean", "description": "Add a `profile` with time spent reading, parsing, fingerprinting, and writing, per language, and for the slowest files." }
+                }
+            }
+        }),
+        json!({
+            "name": "lumora.index_file",
+            "description": "Re-parse one file and update its graph rows right away, without walking the repository. Call it after editing a file to query the new code immediately; a deleted file is removed from the index.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["path"],
+                "properties": {
+                    "path": { "type": "string", "description": "Repo-relative path of the file to reindex." }
+                }
+            }
+        }),
+        json!({
+            "name": "lumora.prune",
+            "description": "Delete fingerprints, embeddings, edges and nodes left behind for files that are no longer indexed, plus orphaned nodes and stale parse cache entries. Reports per-category row counts.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "dry_run": { "type": "boolean", "description": "Report what would be deleted without changing the index." },
+                    "vacuum": { "type": "boolean", "description": "Run VACUUM afterwards to reclaim disk space (ignored for dry runs)." }
                 }
             }
         }),
         json!({
             "name": "lumora.symbol_definitions",
-            "description": "Find symbol definition locations by name.",
+            "description": "Find symbol definition locations by name, with function signatures where available.",
             "inputSchema": {
                 "type": "object",
                 "required": ["name"],
                 "properties": {
-                    "name": { "type": "string" }
+                    "name": { "type": "string" },
+                    "did_you_mean": { "type": "boolean", "default": true, "description": "When nothing matches, list close symbol names as `did_you_mean`." }
+                }
+            }
+        }),
+        json!({
+            "name": "lumora.symbols_batch",
+            "description": "Look up definitions for many symbol names in one call, optionally with each name's top references. Results keep the order of `names`.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["names"],
+                "properties": {
+                    "names": { "type": "array", "items": { "type": "string" }, "minItems": 1, "maxItems": SYMBOLS_BATCH_MAX_NAMES },
+                    "references": { "type": "integer", "minimum": 0, "maximum": SYMBOLS_BATCH_MAX_REFERENCES, "default": 0, "description": "Top-ranked references to include per name, with `total_references`." },
+                    "did_you_mean": { "type": "boolean", "default": true, "description": "For names with no definition, list close symbol names as `did_you_mean`." },
+                    "include_freshness": { "type": "boolean" },
+                    "verbosity": { "type": "string", "enum": ["compact", "normal", "debug"] }
                 }
             }
         }),
@@ -836,6 +2576,33 @@ fn tool_descriptors() -> Vec<Value> {
                 }
             }
         }),
+        json!({
+            "name": "lumora.get_symbol_source",
+            "description": "Resolve one symbol selector to its definition and return the exact source text of its span in a single call.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["selector"],
+                "properties": {
+                    "selector": { "type": "string", "description": "Symbol key (symbol:...), symbol_name:<lang>:<name>, or a bare name." },
+                    "pick": { "type": "integer", "minimum": 0, "description": "Index into `resolution.candidates` when the selector is ambiguous." },
+                    "context_lines": { "type": "integer", "minimum": 0 },
+                    "max_lines": { "type": "integer", "minimum": 1 }
+                }
+            }
+        }),
+        json!({
+            "name": "lumora.hover",
+            "description": "What an editor hover shows, in one call: the identifier at a file position, its definition and signature, its doc comment, and its top 5 references with the total count. When a name has several definitions, the one the reference under the cursor was bound to wins, then one in the same file.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["path", "line", "col"],
+                "properties": {
+                    "path": { "type": "string", "description": "Repo-relative file path." },
+                    "line": { "type": "integer", "minimum": 1, "description": "1-based line." },
+                    "col": { "type": "integer", "minimum": 1, "description": "1-based column of any character of the identifier." }
+                }
+            }
+        }),
         json!({
             "name": "lumora.symbol_references",
             "description": "Find references for a symbol name with ranking, paging, filtering, and summary controls.",
@@ -849,11 +2616,20 @@ fn tool_descriptors() -> Vec<Value> {
                     "file_glob": { "type": "string" },
                     "language": { "type": "string" },
                     "max_age_hours": { "type": "integer", "minimum": 1 },
+                    "resolved_only": { "type": "boolean", "description": "Only return references bound to a definition via the same file or its imports." },
+                    "exclude_externals": { "type": "boolean", "description": "Drop references to names marked `external` (defined outside the repo)." },
+                    "exclude_heuristic": { "type": "boolean", "description": "Drop edges whose `origin` is `heuristic` rather than `ast` or `import-resolution`." },
+                    "include_tests": { "type": "boolean", "default": true, "description": "Set to false to drop references made from test files and keep production call sites only." },
+                    "include_generated": { "type": "boolean", "default": false, "description": "Keep references made from files whose header marks them as generated (`@generated`, `DO NOT EDIT`)." },
+                    "min_confidence": { "type": "number", "description": "Drop rows whose `confidence` (0-1: exact name, same file or import of the definition, resolution, call arity) is below this." },
+                    "did_you_mean": { "type": "boolean", "default": true, "description": "When nothing matches, list close symbol names as `did_you_mean`." },
                     "limit": { "type": "integer", "minimum": 1 },
                     "offset": { "type": "integer", "minimum": 0 },
+                    "cursor": { "type": "string", "description": "Token from a previous page's pagination.next_cursor; overrides offset." },
                     "dedup": { "type": "boolean" },
                     "order": { "type": "string", "enum": ["asc", "desc", "score_desc", "line_asc", "line_desc"] },
                     "summary_mode": { "type": "string", "enum": ["top_files"] },
+                    "group_by": { "type": "string", "enum": ["enclosing_symbol"], "description": "Count references per innermost enclosing function/class instead of listing lines; returns `groups` paged by `offset`." },
                     "include_freshness": { "type": "boolean" },
                     "verbosity": { "type": "string", "enum": ["compact", "normal", "debug"] }
                 }
@@ -870,11 +2646,20 @@ fn tool_descriptors() -> Vec<Value> {
                     "file_glob": { "type": "string" },
                     "language": { "type": "string" },
                     "max_age_hours": { "type": "integer", "minimum": 1 },
+                    "resolved_only": { "type": "boolean", "description": "Only return references bound to a definition via the same file or its imports." },
+                    "exclude_externals": { "type": "boolean", "description": "Drop references to names marked `external` (defined outside the repo)." },
+                    "exclude_heuristic": { "type": "boolean", "description": "Drop edges whose `origin` is `heuristic` rather than `ast` or `import-resolution`." },
+                    "include_tests": { "type": "boolean", "default": true, "description": "Set to false to drop references made from test files and keep production call sites only." },
+                    "include_generated": { "type": "boolean", "default": false, "description": "Keep references made from files whose header marks them as generated (`@generated`, `DO NOT EDIT`)." },
+                    "min_confidence": { "type": "number", "description": "Drop rows whose `confidence` (0-1: exact name, same file or import of the definition, resolution, call arity) is below this." },
+                    "did_you_mean": { "type": "boolean", "default": true, "description": "When nothing matches, list close symbol names as `did_you_mean`." },
                     "limit": { "type": "integer", "minimum": 1 },
                     "offset": { "type": "integer", "minimum": 0 },
+                    "cursor": { "type": "string", "description": "Token from a previous page's pagination.next_cursor; overrides offset." },
                     "dedup": { "type": "boolean" },
                     "order": { "type": "string", "enum": ["asc", "desc", "score_desc", "line_asc", "line_desc"] },
                     "summary_mode": { "type": "string", "enum": ["top_files"] },
+                    "group_by": { "type": "string", "enum": ["enclosing_symbol"], "description": "Count references per innermost enclosing function/class instead of listing lines; returns `groups` paged by `offset`." },
                     "include_freshness": { "type": "boolean" },
                     "verbosity": { "type": "string", "enum": ["compact", "normal", "debug"] }
                 }
@@ -882,7 +2667,7 @@ fn tool_descriptors() -> Vec<Value> {
         }),
         json!({
             "name": "lumora.dependency_path",
-            "description": "Find a dependency path from selector A to selector B.",
+            "description": "Find a dependency path from selector A to selector B. With `max_paths` > 1, returns up to that many distinct paths, shortest first, with the edge type of each hop.",
             "inputSchema": {
                 "type": "object",
                 "required": ["from", "to"],
@@ -890,6 +2675,19 @@ fn tool_descriptors() -> Vec<Value> {
                     "from": { "type": "string" },
                     "to": { "type": "string" },
                     "max_depth": { "type": "integer", "minimum": 1 },
+                    "max_paths": {
+                        "type": "integer",
+                        "minimum": 1,
+                        "description": "Return up to this many distinct paths as `paths` instead of a single `hops` list. Defaults to 1."
+                    },
+                    "pick": {
+                        "type": "object",
+                        "description": "Candidate indexes into `selector.from.candidates` / `selector.to.candidates` when a selector is ambiguous.",
+                        "properties": {
+                            "from": { "type": "integer", "minimum": 0 },
+                            "to": { "type": "integer", "minimum": 0 }
+                        }
+                    },
                     "include_freshness": { "type": "boolean" },
                     "verbosity": { "type": "string", "enum": ["compact", "normal", "debug"] }
                 }
@@ -897,12 +2695,13 @@ fn tool_descriptors() -> Vec<Value> {
         }),
         json!({
             "name": "lumora.minimal_slice",
-            "description": "Return a bounded graph slice around a file and optional line.",
+            "description": "Return a bounded graph slice around a file and optional line, or around the definition a symbol selector resolves to.",
             "inputSchema": {
                 "type": "object",
-                "required": ["file"],
                 "properties": {
                     "file": { "type": "string" },
+                    "selector": { "type": "string", "description": "Anchor on a symbol definition instead of `file`/`line`: symbol key, symbol_name:<lang>:<name>, or a bare name." },
+                    "pick": { "type": "integer", "minimum": 0, "description": "Index into `resolution.candidates` when `selector` is ambiguous." },
                     "line": { "type": ["integer", "null"] },
                     "depth": { "type": "integer", "minimum": 1 },
                     "max_neighbors": { "type": "integer", "minimum": 1 },
@@ -910,11 +2709,43 @@ fn tool_descriptors() -> Vec<Value> {
                     "suppress_low_signal_repeats": { "type": "boolean" },
                     "low_signal_name_cap": { "type": "integer", "minimum": 1 },
                     "prefer_project_symbols": { "type": "boolean" },
+                    "exclude_externals": { "type": "boolean", "description": "Leave `external` names (defined outside the repo) out of the slice." },
+                    "exclude_heuristic": { "type": "boolean", "description": "Skip edges whose `origin` is `heuristic`, e.g. test links inferred from names." },
+                    "include_source": { "type": "boolean", "description": "Inline the anchor's definition body and the first lines of each neighbor's definition, highest-scored first, as `source`." },
+                    "max_source_lines": { "type": "integer", "minimum": 1, "default": 200, "description": "Total lines `include_source` may inline; the anchor is served first." },
+                    "include_freshness": { "type": "boolean" },
+                    "verbosity": { "type": "string", "enum": ["compact", "normal", "debug"] }
+                }
+            }
+        }),
+        json!({
+            "name": "lumora.symbol_neighbors",
+            "description": "Return the direct incoming and outgoing edges of any selector (file, symbol, symbol name, module), grouped by edge type with counts. Lighter than minimal_slice for quick orientation.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["selector"],
+                "properties": {
+                    "selector": { "type": "string", "description": "file:<path>, symbol:<key>, symbol_name:<lang>:<name>, module:<lang>:<name>, or a bare name." },
+                    "pick": { "type": "integer", "minimum": 0, "description": "Index into `resolution.candidates` when the selector is ambiguous." },
+                    "limit": { "type": "integer", "minimum": 0, "description": "Example neighbors listed per edge type (default 10); counts always cover every edge." },
                     "include_freshness": { "type": "boolean" },
                     "verbosity": { "type": "string", "enum": ["compact", "normal", "debug"] }
                 }
             }
         }),
+        json!({
+            "name": "lumora.symbol_history",
+            "description": "Git history for a symbol's current span: recent commits that touched it (via `git log -L`) and who last changed its lines (via `git blame`). Use it to judge churn and recency.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["selector"],
+                "properties": {
+                    "selector": { "type": "string", "description": "symbol:<key>, symbol_name:<lang>:<name>, or a bare name." },
+                    "pick": { "type": "integer", "minimum": 0, "description": "Index into `resolution.candidates` when the selector is ambiguous." },
+                    "limit": { "type": "integer", "minimum": 1, "description": "Most commits to return (default 10)." }
+                }
+            }
+        }),
         json!({
             "name": "lumora.clone_matches",
             "description": "Find likely clone files or near-duplicate hotspots.",
@@ -927,21 +2758,89 @@ fn tool_descriptors() -> Vec<Value> {
                     "limit": { "type": "integer", "minimum": 1 },
                     "offset": { "type": "integer", "minimum": 0 },
                     "mode": { "type": "string", "enum": ["matches", "hotspots"] },
+                    "same_language_only": { "type": "boolean", "description": "Skip files in a different language than `file`, e.g. JSON fixtures overlapping YAML." },
+                    "language": { "type": "string", "description": "Only match files in this language, e.g. to find a port of `file`." },
+                    "include_tests": { "type": "boolean", "default": true, "description": "Set to false to skip test files, e.g. fixtures copied between tests." },
+                    "include_generated": { "type": "boolean", "default": false, "description": "Keep files whose header marks them as generated code, which are skipped by default." },
                     "include_freshness": { "type": "boolean" },
                     "verbosity": { "type": "string", "enum": ["compact", "normal", "debug"] }
                 }
             }
         }),
+        json!({
+            "name": "lumora.compare_files",
+            "description": "Line diff of two repo files as unified-diff hunks, plus their shared-fingerprint similarity and duplicated regions from the index; confirms a suspected clone or reviews a refactor in one call.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["left", "right"],
+                "properties": {
+                    "left": { "type": "string" },
+                    "right": { "type": "string" },
+                    "context_lines": { "type": "integer", "minimum": 0 },
+                    "max_lines": { "type": "integer", "minimum": 1, "description": "Hunk lines to return; the added/removed counts always cover the whole diff." }
+                }
+            }
+        }),
+        json!({
+            "name": "lumora.dir_outline",
+            "description": "Outline every indexed file under a directory from the stored graph (no re-parsing); returns compact per-file definition trees.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Directory relative to the repo root; empty for the whole repo." },
+                    "max_depth": { "type": "integer", "minimum": 0 },
+                    "max_files": { "type": "integer", "minimum": 1 }
+                }
+            }
+        }),
+        json!({
+            "name": "lumora.file_profile",
+            "description": "One dashboard row for a file from the stored graph: definition counts by kind, imports in and out, call sites in and out, its most similar file by clone fingerprints, and when it was last indexed. Use it to triage which files matter before reading them.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["path"],
+                "properties": {
+                    "path": { "type": "string", "description": "Indexed file relative to the repo root." }
+                }
+            }
+        }),
+        json!({
+            "name": "lumora.todos",
+            "description": "List TODO, FIXME, and HACK comments recorded at index time, with file, line, and the note text; filter by path glob to survey one subsystem's known debt.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "file_glob": { "type": "string", "description": "Only comments in matching files, e.g. `src/storage/*`." },
+                    "tags": { "type": "array", "items": { "type": "string", "enum": ["TODO", "FIXME", "HACK"] }, "description": "Only these markers; all three by default." },
+                    "limit": { "type": "integer", "minimum": 1, "default": 200 }
+                }
+            }
+        }),
+        json!({
+            "name": "lumora.semantic_search",
+            "description": "Natural-language search over symbol and file-chunk embeddings from a local sentence-embedding model (all-MiniLM-L6-v2). Needs a lumora built with the `semantic` feature and an index with embeddings enabled.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["query"],
+                "properties": {
+                    "query": { "type": "string" },
+                    "kind": { "type": "string", "enum": ["symbol", "chunk"] },
+                    "file_glob": { "type": "string" },
+                    "min_score": { "type": "number", "minimum": -1.0, "maximum": 1.0 },
+                    "limit": { "type": "integer", "minimum": 1 }
+                }
+            }
+        }),
         json!({
             "name": "lumora.selector_discover",
-            "description": "List known selectors (files, symbol names, keys) to help construct queries.",
+            "description": "List known selectors (files, symbol names, keys) to help construct queries. Ranked by name match, then by graph degree, so with no query the best-connected files and symbols come first.",
             "inputSchema": {
                 "type": "object",
                 "properties": {
                     "query": { "type": "string" },
                     "limit": { "type": "integer", "minimum": 1 },
                     "file_glob": { "type": "string" },
-                    "entity_type": { "type": "string", "enum": ["file", "symbol", "symbol_name", "module", "config", "entrypoint"] },
+                    "entity_type": { "type": "string", "enum": ["file", "symbol", "symbol_name", "external", "module", "package", "config", "entrypoint"] },
                     "fuzzy": { "type": "boolean" }
                 }
             }
@@ -960,9 +2859,23 @@ fn tool_descriptors() -> Vec<Value> {
                 }
             }
         }),
+        json!({
+            "name": "lumora.read_range",
+            "description": "Read a file by byte offsets instead of lines, e.g. a `start_byte`/`end_byte` span from symbol or reference results. Suited to minified or very long single-line files. Offsets inside a UTF-8 character widen to cover it.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["path", "start_byte"],
+                "properties": {
+                    "path": { "type": "string" },
+                    "start_byte": { "type": "integer", "minimum": 0 },
+                    "end_byte": { "type": "integer", "minimum": 0, "description": "Exclusive; defaults to the end of the file." },
+                    "max_len": { "type": "integer", "minimum": 1, "default": 65536, "description": "Most bytes to return; longer ranges set `truncated`." }
+                }
+            }
+        }),
         json!({
             "name": "lumora.file_outline",
-            "description": "Get AST-derived structure outline of a file (definitions only, no content). Fast symbol lookup.",
+            "description": "Get AST-derived structure outline of a file (definitions and function signatures, no bodies). Fast symbol lookup.",
             "inputSchema": {
                 "type": "object",
                 "required": ["path"],
@@ -995,7 +2908,7 @@ fn tool_descriptors() -> Vec<Value> {
         }),
         json!({
             "name": "lumora.search_files",
-            "description": "Search file contents with regex or literal patterns. Returns matches with context.",
+            "description": "Search file contents with regex or literal patterns. Returns matches with context and byte offsets. Binary, non-UTF-8, oversized, and gitignored files are skipped and counted in `files_skipped`.",
             "inputSchema": {
                 "type": "object",
                 "required": ["pattern"],
@@ -1004,7 +2917,23 @@ fn tool_descriptors() -> Vec<Value> {
                     "file_glob": { "type": "string" },
                     "context_lines": { "type": "integer", "default": 2 },
                     "max_results": { "type": "integer", "default": 50 },
-                    "is_regex": { "type": "boolean", "default": false }
+                    "is_regex": { "type": "boolean", "default": false },
+                    "max_file_size": { "type": "integer", "minimum": 1, "default": 1048576, "description": "Skip files larger than this many bytes." },
+                    "respect_gitignore": { "type": "boolean", "default": true, "description": "Also skip paths the repo root `.gitignore` excludes." }
+                }
+            }
+        }),
+        json!({
+            "name": "lumora.ast_search",
+            "description": "Structural search: run a tree-sitter query (S-expression pattern with captures and predicates such as `#eq?`) over indexed files and return each captured node's span and text. Languages whose grammar rejects the query are skipped and listed in `skipped_languages`.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["query"],
+                "properties": {
+                    "query": { "type": "string", "description": "Tree-sitter query, e.g. `(call_expression function: (field_expression field: (field_identifier) @m (#eq? @m \"unwrap\"))) @call`." },
+                    "file_glob": { "type": "string" },
+                    "language": { "type": "string", "description": "Only search files in this language, e.g. `rust`." },
+                    "max_results": { "type": "integer", "minimum": 1, "default": 100, "description": "Captures returned before the response is marked `truncated`." }
                 }
             }
         }),
@@ -1023,20 +2952,22 @@ fn tool_descriptors() -> Vec<Value> {
         }),
         json!({
             "name": "lumora.write_file",
-            "description": "Create or overwrite a file.",
+            "description": "Create, overwrite, or append to a file. Returns the file's new `sha256`; pass it back as `expected_hash` to make sure nothing else changed the file in between.",
             "inputSchema": {
                 "type": "object",
                 "required": ["path", "content"],
                 "properties": {
                     "path": { "type": "string" },
                     "content": { "type": "string" },
-                    "create_dirs": { "type": "boolean", "default": true }
+                    "create_dirs": { "type": "boolean", "default": true },
+                    "mode": { "type": "string", "enum": ["overwrite", "append", "create_new"], "default": "overwrite", "description": "`append` adds `content` to the end; `create_new` fails with EDIT_CONFLICT if the file exists." },
+                    "expected_hash": { "type": "string", "description": "SHA-256 hex the file must currently have (from `read_file` or a previous write); fails with EDIT_CONFLICT if it changed." }
                 }
             }
         }),
         json!({
             "name": "lumora.edit_file",
-            "description": "Search-and-replace edit. old_text must match exactly once in the file.",
+            "description": "Search-and-replace edit. old_text must match exactly once unless replace_all or expected_occurrences is set.",
             "inputSchema": {
                 "type": "object",
                 "required": ["path", "old_text", "new_text"],
@@ -1044,6 +2975,9 @@ fn tool_descriptors() -> Vec<Value> {
                     "path": { "type": "string" },
                     "old_text": { "type": "string" },
                     "new_text": { "type": "string" },
+                    "replace_all": { "type": "boolean", "default": false, "description": "Replace every match instead of requiring a unique one." },
+                    "expected_occurrences": { "type": "integer", "minimum": 1, "description": "Fail unless old_text matches exactly this many times; all matches are replaced." },
+                    "is_regex": { "type": "boolean", "default": false, "description": "Treat old_text as a regex; new_text may reference groups as $1 or ${name}." },
                     "dry_run": { "type": "boolean", "default": false }
                 }
             }
@@ -1142,7 +3076,8 @@ fn tool_descriptors() -> Vec<Value> {
                 "required": ["source", "destination"],
                 "properties": {
                     "source": { "type": "string" },
-                    "destination": { "type": "string" }
+                    "destination": { "type": "string" },
+                    "confirm": { "type": "boolean", "description": "Required when `.lumora.toml` sets `guardrails.confirm_destructive`." }
                 }
             }
         }),
@@ -1153,11 +3088,57 @@ fn tool_descriptors() -> Vec<Value> {
                 "type": "object",
                 "required": ["path"],
                 "properties": {
-                    "path": { "type": "string" }
+                    "path": { "type": "string" },
+                    "confirm": { "type": "boolean", "description": "Required when `.lumora.toml` sets `guardrails.confirm_destructive`." }
+                }
+            }
+        }),
+        json!({
+            "name": "lumora.undo_last_change",
+            "description": "Revert the most recent change made by a write tool (or the one named by `undo_id`, as returned by write_file, edit_file, and the other write tools), restoring every file it touched. Repeated calls walk further back. The undo is itself undoable by its own `undo_id`.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "undo_id": { "type": "integer", "minimum": 1, "description": "Change to revert; defaults to the latest one not yet undone." },
+                    "force": { "type": "boolean", "default": false, "description": "Revert even if a file changed again after that change." },
+                    "confirm": { "type": "boolean", "description": "Required when `.lumora.toml` sets `guardrails.confirm_destructive`." }
                 }
             }
         }),
-    ]
+    ];
+
+    for tool in &mut tools {
+        if let Some(properties) = tool["inputSchema"]["properties"].as_object_mut() {
+            properties.insert(
+                "max_bytes".to_string(),
+                json!({ "type": "integer", "minimum": 1, "description": "Upper bound on the serialized response size; oversized results are trimmed and flagged `truncated`." }),
+            );
+            properties.insert(
+                "max_tokens_estimate".to_string(),
+                json!({ "type": "integer", "minimum": 1, "description": "Like max_bytes, estimated at ~4 bytes per token." }),
+            );
+            properties.insert(
+                "timeout_ms".to_string(),
+                json!({ "type": "integer", "minimum": 1, "description": "Wall-clock limit for graph queries; on expiry the rows gathered so far are returned with `timed_out: true`." }),
+            );
+        }
+        let writes = TOOL_GROUPS.iter().any(|(group, tools)| {
+            *group == "write"
+                && tool["name"]
+                    .as_str()
+                    .is_some_and(|name| tools.contains(&name))
+        });
+        if let Some(properties) = tool["inputSchema"]["properties"]
+            .as_object_mut()
+            .filter(|_| writes)
+        {
+            properties.insert(
+                "auto_refresh_index".to_string(),
+                json!({ "type": "boolean", "description": "Reindex the touched files before returning, reported as `index_refresh`. Defaults to `mcp.auto_refresh_index` in `.lumora.toml` (off)." }),
+            );
+        }
+    }
+    tools
 }
 
 fn tool_ok(structured_content: Value) -> Value {
@@ -1173,7 +3154,7 @@ fn tool_ok(structured_content: Value) -> Value {
     })
 }
 
-fn tool_error(message: String) -> Value {
+fn tool_error(code: ErrorCode, message: String) -> Value {
     json!({
         "content": [
             {
@@ -1181,11 +3162,12 @@ fn tool_error(message: String) -> Value {
                 "text": message
             }
         ],
+        "structuredContent": { "error": error_json(code, &message) },
         "isError": true
     })
 }
 
-fn success_response(id: Value, result: Value) -> Value {
+pub fn success_response(id: Value, result: Value) -> Value {
     json!({
         "jsonrpc": "2.0",
         "id": id,
@@ -1193,7 +3175,7 @@ fn success_response(id: Value, result: Value) -> Value {
     })
 }
 
-fn error_response(id: Option<Value>, code: i64, message: &str) -> Value {
+pub fn error_response(id: Option<Value>, code: i64, message: &str) -> Value {
     json!({
         "jsonrpc": "2.0",
         "id": id,
@@ -1204,12 +3186,12 @@ fn error_response(id: Option<Value>, code: i64, message: &str) -> Value {
     })
 }
 
-struct InboundFrame {
-    style: FrameStyle,
-    value: Value,
+pub struct InboundFrame {
+    pub style: FrameStyle,
+    pub value: Value,
 }
 
-fn read_frame(reader: &mut impl BufRead) -> Result<Option<InboundFrame>> {
+pub fn read_frame(reader: &mut impl BufRead) -> Result<Option<InboundFrame>> {
     let mut first_line = String::new();
     let first_n = reader.read_line(&mut first_line)?;
     if first_n == 0 {
@@ -1275,7 +3257,7 @@ fn parse_content_length_header(line: &str) -> Result<Option<usize>> {
     Ok(Some(parsed))
 }
 
-fn write_frame(writer: &mut impl Write, payload: &Value, style: FrameStyle) -> Result<()> {
+pub fn write_frame(writer: &mut impl Write, payload: &Value, style: FrameStyle) -> Result<()> {
     let serialized = serde_json::to_vec(payload)?;
     match style {
         FrameStyle::ContentLength => {
@@ -1306,15 +3288,225 @@ fn compact_if_needed(mut value: Value, verbosity: Verbosity) -> Value {
     value
 }
 
-fn strip_compact_fields(value: &mut Value) {
-    match value {
-        Value::Object(map) => {
-            map.remove("why");
-            map.remove("meta_json");
-            map.remove("diagnostics");
-            for nested in map.values_mut() {
-                strip_compact_fields(nested);
-            }
+fn response_size(value: &Value) -> usize {
+    serde_json::to_string_pretty(value)
+        .map(|text| text.len())
+        .unwrap_or(0)
+}
+
+/// Size of the `tools/call` result `tool_ok` builds around `value`, which
+/// carries it twice: as pretty-printed text and as `structuredContent`.
+fn envelope_size(value: &Value) -> usize {
+    serde_json::to_string(&tool_ok(value.clone()))
+        .map(|text| text.len())
+        .unwrap_or(0)
+}
+
+/// Shrinks a tool result until the response carrying it fits `max_bytes`:
+/// first snippet fields are removed, then rows are dropped from the end of the
+/// largest array, and finally the longest string is cut. Any change sets
+/// `truncated` and records what was removed under `truncation`.
+fn apply_response_budget(mut value: Value, max_bytes: usize) -> Value {
+    let original_bytes = envelope_size(&value);
+    if original_bytes <= max_bytes {
+        return value;
+    }
+
+    // `truncation` is added afterwards, once in each copy.
+    let target = max_bytes.saturating_sub(2 * TRUNCATION_RESERVE);
+    let mut dropped_fields = Vec::new();
+    for field in SNIPPET_FIELDS {
+        if remove_field(&mut value, field) {
+            dropped_fields.push(*field);
+        }
+    }
+
+    // Each byte of `value` appears about twice in the envelope.
+    let mut dropped_rows = 0usize;
+    loop {
+        let size = envelope_size(&value);
+        if size <= target {
+            break;
+        }
+        let dropped = drop_trailing_rows(&mut value, (size - target).div_ceil(2));
+        if dropped == 0 {
+            break;
+        }
+        dropped_rows += dropped;
+    }
+    if dropped_rows > 0 {
+        repaginate(&mut value);
+    }
+
+    let mut truncated_strings = false;
+    loop {
+        let size = envelope_size(&value);
+        if size <= target || !truncate_longest_string(&mut value, (size - target).div_ceil(2)) {
+            break;
+        }
+        truncated_strings = true;
+    }
+
+    let hint = if dropped_rows > 0 && value.get("pagination").is_some() {
+        format!(
+            "{dropped_rows} row(s) were dropped from the end of this page; `pagination` now points at the first of them, so follow `next_offset` or `next_cursor`, or raise `max_bytes`."
+        )
+    } else if dropped_rows > 0 {
+        format!(
+            "{dropped_rows} row(s) were dropped; narrow the query (limit, file_glob, path, line range) or raise `max_bytes`."
+        )
+    } else {
+        "Fields were trimmed to fit; narrow the query or raise `max_bytes` for full output."
+            .to_string()
+    };
+
+    if let Some(map) = value.as_object_mut() {
+        map.insert("truncated".to_string(), json!(true));
+        map.insert(
+            "truncation".to_string(),
+            json!({
+                "max_bytes": max_bytes,
+                "original_bytes": original_bytes,
+                "dropped_rows": dropped_rows,
+                "dropped_fields": dropped_fields,
+                "truncated_strings": truncated_strings,
+                "hint": hint
+            }),
+        );
+    }
+    value
+}
+
+/// Points `pagination` just past the rows that survived the budget, so the
+/// next page starts at the first dropped row.
+fn repaginate(value: &mut Value) {
+    let Some(kept) = ["rows", "groups"]
+        .iter()
+        .find_map(|key| value.get(*key).and_then(Value::as_array))
+        .map(Vec::len)
+    else {
+        return;
+    };
+    let Some(pagination) = value.get_mut("pagination").and_then(Value::as_object_mut) else {
+        return;
+    };
+    let offset = pagination
+        .get("offset")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    let next_offset = offset + kept as u64;
+    pagination.insert("returned".to_string(), json!(kept));
+    pagination.insert("has_more".to_string(), json!(true));
+    pagination.insert("next_offset".to_string(), json!(next_offset));
+    // Cursors are `<result id>:<offset>`; the cached result stays valid.
+    if let Some(Value::String(cursor)) = pagination.get_mut("next_cursor") {
+        if let Some((id, _)) = cursor.split_once(':') {
+            *cursor = format!("{id}:{next_offset}");
+        }
+    }
+}
+
+fn remove_field(value: &mut Value, field: &str) -> bool {
+    match value {
+        Value::Object(map) => {
+            let mut removed = map.remove(field).is_some();
+            for nested in map.values_mut() {
+                removed |= remove_field(nested, field);
+            }
+            removed
+        }
+        Value::Array(items) => {
+            let mut removed = false;
+            for item in items {
+                removed |= remove_field(item, field);
+            }
+            removed
+        }
+        _ => false,
+    }
+}
+
+/// Pops rows off the largest array that still has more than one row until
+/// roughly `excess` bytes are gone. Returns the number of rows removed.
+fn drop_trailing_rows(value: &mut Value, excess: usize) -> usize {
+    fn collect_arrays(value: &Value, pointer: String, out: &mut Vec<(String, usize)>) {
+        match value {
+            Value::Object(map) => {
+                for (key, nested) in map {
+                    let escaped = key.replace('~', "~0").replace('/', "~1");
+                    collect_arrays(nested, format!("{pointer}/{escaped}"), out);
+                }
+            }
+            Value::Array(items) => {
+                if items.len() > 1 {
+                    out.push((pointer.clone(), response_size(value)));
+                }
+                for (index, nested) in items.iter().enumerate() {
+                    collect_arrays(nested, format!("{pointer}/{index}"), out);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut arrays = Vec::new();
+    collect_arrays(value, String::new(), &mut arrays);
+    let Some((pointer, _)) = arrays.into_iter().max_by_key(|(_, size)| *size) else {
+        return 0;
+    };
+    let Some(Value::Array(rows)) = value.pointer_mut(&pointer) else {
+        return 0;
+    };
+
+    let mut removed_bytes = 0;
+    let mut removed = 0;
+    while removed_bytes < excess && rows.len() > 1 {
+        let Some(row) = rows.pop() else {
+            break;
+        };
+        removed_bytes += response_size(&row);
+        removed += 1;
+    }
+    removed
+}
+
+fn truncate_longest_string(value: &mut Value, excess: usize) -> bool {
+    fn longest(value: &mut Value) -> Option<&mut String> {
+        match value {
+            Value::String(text) => Some(text),
+            Value::Object(map) => map
+                .values_mut()
+                .filter_map(longest)
+                .max_by_key(|text| text.len()),
+            Value::Array(items) => items.iter_mut().filter_map(longest).max_by_key(|t| t.len()),
+            _ => None,
+        }
+    }
+
+    let Some(text) = longest(value) else {
+        return false;
+    };
+    let mut keep = text.len().saturating_sub(excess + 16);
+    while keep > 0 && !text.is_char_boundary(keep) {
+        keep -= 1;
+    }
+    if keep >= text.len() {
+        return false;
+    }
+    text.truncate(keep);
+    text.push_str("…[truncated]");
+    true
+}
+
+fn strip_compact_fields(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.remove("why");
+            map.remove("meta_json");
+            map.remove("diagnostics");
+            for nested in map.values_mut() {
+                strip_compact_fields(nested);
+            }
         }
         Value::Array(items) => {
             for item in items {
@@ -1332,19 +3524,15 @@ fn attach_diagnostics(
     include_freshness: bool,
     mut details: Value,
 ) -> std::result::Result<(), ToolCallError> {
-    let warning = store
-        .index_warning(24)
-        .map_err(|err| ToolCallError::Runtime(err.to_string()))?;
+    let warning = store.index_warning(24).map_err(ToolCallError::from)?;
     if let Some(warning) = warning {
         response["warning"] = json!(warning);
     }
 
     if include_freshness || verbosity == Verbosity::Debug {
-        let freshness = store
-            .freshness_info(24)
-            .map_err(|err| ToolCallError::Runtime(err.to_string()))?;
+        let freshness = store.freshness_info(24).map_err(ToolCallError::from)?;
         response["freshness"] = serde_json::to_value(&freshness)
-            .map_err(|err| ToolCallError::Runtime(format!("serialization error: {err}")))?;
+            .map_err(|err| ToolCallError::internal(format!("serialization error: {err}")))?;
     }
 
     if verbosity == Verbosity::Debug {
@@ -1393,6 +3581,121 @@ fn opt_u64(args: &Value, key: &str) -> std::result::Result<Option<u64>, ToolCall
     }
 }
 
+/// Doc comment of the definition starting on 1-based `line`: the comment
+/// block right above it (past attributes and decorators), or else a Python
+/// docstring opening its body. Comment markers are stripped.
+fn leading_doc_comment(source: &str, line: i64) -> Option<String> {
+    let lines: Vec<&str> = source.lines().collect();
+    let start = usize::try_from(line).ok()?.checked_sub(1)?;
+    if start >= lines.len() {
+        return None;
+    }
+
+    let mut doc = Vec::new();
+    let mut in_block = false;
+    for raw in lines[..start].iter().rev() {
+        let text = raw.trim();
+        if in_block {
+            let opening = text.starts_with("/*");
+            doc.push(
+                text.trim_start_matches("/**")
+                    .trim_start_matches("/*")
+                    .trim_start_matches('*')
+                    .trim(),
+            );
+            if opening {
+                break;
+            }
+            continue;
+        }
+        if doc.is_empty() && (text.starts_with("#[") || text.starts_with('@')) {
+            continue;
+        }
+        if text.ends_with("*/") {
+            let body = text.trim_end_matches("*/").trim();
+            if body.starts_with("/*") {
+                doc.push(
+                    body.trim_start_matches("/**")
+                        .trim_start_matches("/*")
+                        .trim(),
+                );
+                break;
+            }
+            in_block = true;
+            doc.push(body.trim_start_matches('*').trim());
+            continue;
+        }
+        let marker = ["///", "//!", "//", "--", "#"].into_iter().find(|marker| {
+            text.starts_with(marker) && !text.starts_with("#!") && !text.starts_with("#[")
+        });
+        match marker {
+            Some(marker) => doc.push(text[marker.len()..].trim()),
+            None => break,
+        }
+    }
+    doc.reverse();
+
+    if doc.is_empty() {
+        // Python docstring: the first statement of the body.
+        let body = lines[start + 1..]
+            .iter()
+            .map(|raw| raw.trim())
+            .find(|text| !text.is_empty())?;
+        let quote = ["\"\"\"", "'''"]
+            .into_iter()
+            .find(|quote| body.starts_with(quote))?;
+        let rest = &body[quote.len()..];
+        if let Some(end) = rest.find(quote) {
+            doc.push(rest[..end].trim());
+        } else {
+            doc.push(rest.trim());
+            let after = lines[start + 1..]
+                .iter()
+                .map(|raw| raw.trim())
+                .skip_while(|text| !text.starts_with(quote))
+                .skip(1);
+            for text in after {
+                if let Some(end) = text.find(quote) {
+                    doc.push(text[..end].trim());
+                    break;
+                }
+                doc.push(text);
+            }
+        }
+    }
+
+    while doc.first().is_some_and(|text| text.is_empty()) {
+        doc.remove(0);
+    }
+    while doc.last().is_some_and(|text| text.is_empty()) {
+        doc.pop();
+    }
+    doc.truncate(HOVER_DOC_LINES);
+    (!doc.is_empty()).then(|| doc.join("\n"))
+}
+
+/// Close names offered as `did_you_mean` when a symbol lookup finds nothing.
+const DID_YOU_MEAN_LIMIT: usize = 5;
+
+const UNRESOLVED_SELECTOR_HINT: &str = "selector did not resolve to a symbol definition; try `symbol:<key>`, `symbol_name:<lang>:<name>`, or a bare name";
+const AMBIGUOUS_SELECTOR_HINT: &str = "the selector matched several definitions; pass `pick` with an index into `resolution.candidates`, or use a candidate `key` as the selector";
+
+/// `pick: {"from": N, "to": N}` for tools that take two selectors.
+fn opt_path_pick(
+    args: &Value,
+) -> std::result::Result<(Option<usize>, Option<usize>), ToolCallError> {
+    match args.get("pick") {
+        None => Ok((None, None)),
+        Some(pick) if pick.is_object() => Ok((
+            opt_u64(pick, "from")?.map(|index| index as usize),
+            opt_u64(pick, "to")?.map(|index| index as usize),
+        )),
+        Some(_) => Err(ToolCallError::InvalidParams(
+            "`pick` must be an object like {\"from\": 0, \"to\": 1}".to_string(),
+        )),
+    }
+}
+
 fn opt_i64(args: &Value, key: &str) -> std::result::Result<Option<i64>, ToolCallError> {
     match args.get(key) {
         Some(v) if v.is_null() => Ok(None),
@@ -1461,6 +3764,180 @@ fn opt_order(args: &Value, key: &str) -> std::result::Result<Option<SortOrder>,
     }
 }
 
+const GROUP_BY_ENCLOSING_SYMBOL: &str = "enclosing_symbol";
+
+/// `group_by` on reference tools; `enclosing_symbol` is the only grouping.
+fn opt_group_by(args: &Value, key: &str) -> std::result::Result<bool, ToolCallError> {
+    match opt_string(args, key)?.as_deref() {
+        None => Ok(false),
+        Some(GROUP_BY_ENCLOSING_SYMBOL) => Ok(true),
+        Some(_) => Err(ToolCallError::InvalidParams(format!(
+            "`{key}` must be `{GROUP_BY_ENCLOSING_SYMBOL}`"
+        ))),
+    }
+}
+
+/// Source for a slice: the anchor's whole span (a file anchor reads half the
+/// budget from the top), then up to [`SLICE_SNIPPET_LINES`] from the start of
+/// each neighbor definition, highest-scored first, until `max_lines` is spent.
+/// Name neighbors (`symbol_name`) use their first definition in the repo.
+/// Unreadable spans are skipped.
+fn slice_source(
+    paths: &RuntimePaths,
+    store: &GraphStore,
+    slice: &SliceResult,
+    max_lines: u64,
+) -> std::result::Result<Value, ToolCallError> {
+    let max_lines = max_lines.max(1);
+    let mut remaining = max_lines;
+    let read = |key: &str,
+                path: &str,
+                line: Option<i64>,
+                end_line: Option<i64>,
+                cap: u64,
+                remaining: &mut u64| {
+        let start = line.unwrap_or(1).max(1) as u64;
+        let span_end = end_line.map_or(start + cap - 1, |end| end.max(start as i64) as u64);
+        let read = fileops::read_file_contents(
+            &paths.repo_root,
+            path,
+            Some(start),
+            Some(span_end.min(start + cap - 1)),
+            (*remaining).min(cap),
+        )
+        .ok()?;
+        *remaining = remaining.saturating_sub(read["returned_lines"].as_u64().unwrap_or(0));
+        Some(json!({
+            "key": key,
+            "path": path,
+            "start_line": read["start_line"],
+            "end_line": read["end_line"],
+            "text": read["content"],
+            "truncated": read["end_line"].as_u64().unwrap_or(0) < span_end,
+        }))
+    };
+
+    let anchor = &slice.anchor;
+    let anchor_source = match anchor.file_path.as_deref() {
+        Some(path) if anchor.entity_type == "file" => read(
+            &anchor.key,
+            path,
+            None,
+            None,
+            (max_lines / 2).max(1),
+            &mut remaining,
+        ),
+        Some(path) => read(
+            &anchor.key,
+            path,
+            anchor.line,
+            anchor.end_line,
+            max_lines,
+            &mut remaining,
+        ),
+        None => None,
+    };
+    let mut seen = HashSet::from([(anchor.file_path.clone(), anchor.line)]);
+    let mut neighbors = Vec::new();
+    for neighbor in &slice.neighbors {
+        if remaining == 0 {
+            break;
+        }
+        let entity = &neighbor.entity;
+        let span = match entity.entity_type.as_str() {
+            "symbol" => entity
+                .file_path
+                .clone()
+                .map(|path| (path, entity.line, entity.end_line)),
+            "symbol_name" => store
+                .symbol_definitions(&entity.name)
+                .map_err(ToolCallError::from)?
+                .into_iter()
+                .next()
+                .map(|definition| {
+                    (
+                        definition.file_path,
+                        Some(definition.line),
+                        definition.end_line,
+                    )
+                }),
+            _ => None,
+        };
+        let Some((path, line, end_line)) = span else {
+            continue;
+        };
+        if !seen.insert((Some(path.clone()), line)) {
+            continue;
+        }
+        if let Some(snippet) = read(
+            &entity.key,
+            &path,
+            line,
+            end_line,
+            SLICE_SNIPPET_LINES,
+            &mut remaining,
+        ) {
+            neighbors.push(snippet);
+        }
+    }
+    Ok(json!({
+        "anchor": anchor_source,
+        "neighbors": neighbors,
+        "lines_used": max_lines - remaining,
+        "max_source_lines": max_lines,
+        "budget_exhausted": remaining == 0
+    }))
+}
+
+/// Adds close symbol names as `did_you_mean` when there are any.
+fn attach_did_you_mean(
+    store: &GraphStore,
+    response: &mut Value,
+    symbol: &str,
+) -> std::result::Result<(), ToolCallError> {
+    let names = store
+        .similar_symbol_names(symbol, DID_YOU_MEAN_LIMIT)
+        .map_err(ToolCallError::from)?;
+    if !names.is_empty() {
+        response["did_you_mean"] = json!(names);
+    }
+    Ok(())
+}
+
+/// Rows for `symbol_references` / `symbol_callers`: either a page of
+/// reference locations (plus optional `top_files`), or a page of counts per
+/// enclosing definition when grouping.
+fn reference_rows_response(
+    store: &GraphStore,
+    symbol: &str,
+    options: &ReferenceQueryOptions,
+    group_by: bool,
+    summary_mode: Option<String>,
+) -> std::result::Result<Value, ToolCallError> {
+    if group_by {
+        let (groups, pagination) = store
+            .symbol_references_grouped(symbol, options)
+            .map_err(ToolCallError::from)?;
+        return Ok(json!({
+            "groups": groups,
+            "group_by": GROUP_BY_ENCLOSING_SYMBOL,
+            "pagination": pagination
+        }));
+    }
+    let (rows, pagination) = store
+        .symbol_references_page(symbol, options)
+        .map_err(ToolCallError::from)?;
+    let mut response = json!({ "rows": rows, "pagination": pagination });
+    if summary_mode.as_deref() == Some("top_files") {
+        let top_files = store
+            .top_reference_files(&rows, 10)
+            .map_err(ToolCallError::from)?;
+        response["top_files"] = serde_json::to_value(top_files)
+            .map_err(|err| ToolCallError::internal(format!("serialization error: {err}")))?;
+    }
+    Ok(response)
+}
+
 fn opt_verbosity(args: &Value, key: &str) -> std::result::Result<Option<Verbosity>, ToolCallError> {
     let Some(value) = opt_string(args, key)? else {
         return Ok(None);
@@ -1475,10 +3952,41 @@ fn opt_verbosity(args: &Value, key: &str) -> std::result::Result<Option<Verbosit
     }
 }
 
+/// Fails with `INDEX_EMPTY` when nothing has been indexed yet, so an empty
+/// lookup is not mistaken for a genuine "not found".
+fn ensure_indexed(store: &GraphStore) -> Result<(), ToolCallError> {
+    if store.is_empty().map_err(ToolCallError::from)? {
+        return Err(ToolCallError::Runtime {
+            code: ErrorCode::IndexEmpty,
+            message: "index is empty; run lumora.index_repository before querying".to_string(),
+        });
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 enum ToolCallError {
     InvalidParams(String),
-    Runtime(String),
+    Runtime { code: ErrorCode, message: String },
+}
+
+impl ToolCallError {
+    fn internal(message: String) -> Self {
+        Self::Runtime {
+            code: ErrorCode::Internal,
+            message,
+        }
+    }
+}
+
+impl From<anyhow::Error> for ToolCallError {
+    fn from(err: anyhow::Error) -> Self {
+        let message = err.to_string();
+        match error_code(&err) {
+            ErrorCode::InvalidParams => Self::InvalidParams(message),
+            code => Self::Runtime { code, message },
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1801,168 +4309,1531 @@ mod tests {
     }
 
     #[test]
-    fn test_tool_ok() {
-        let result = tool_ok(json!({"data": 1}));
-        let content = &result["content"];
-        assert!(content.is_array(), "content should be array");
+    fn test_tool_ok() {
+        let result = tool_ok(json!({"data": 1}));
+        let content = &result["content"];
+        assert!(content.is_array(), "content should be array");
+        assert_eq!(
+            content[0]["type"], "text",
+            "first content item type should be text"
+        );
+        assert!(
+            result["structuredContent"].is_object(),
+            "structuredContent should be present"
+        );
+    }
+
+    #[test]
+    fn test_tool_error() {
+        let result = tool_error(ErrorCode::Internal, "boom".to_string());
+        assert_eq!(
+            result["content"][0]["text"], "boom",
+            "error text should be boom"
+        );
+        assert_eq!(result["isError"], true, "isError should be true");
+        assert_eq!(result["structuredContent"]["error"]["code"], "INTERNAL");
+        assert_eq!(result["structuredContent"]["error"]["message"], "boom");
+    }
+
+    #[test]
+    fn test_handle_tools_call_prune_reports_counts() {
+        let (paths, _dir) = test_paths();
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+
+        let resp = handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.prune", "arguments": {"dry_run": true}})),
+            json!(2),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        let content = &resp["result"]["structuredContent"];
+        assert_eq!(content["dry_run"], true);
+        assert_eq!(content["vacuumed"], false);
+        assert_eq!(content["total_rows"], 0);
+        assert!(content["bytes_before"].as_u64().unwrap() > 0);
+    }
+
+    #[test]
+    fn test_handle_tools_call_reports_structured_error_codes() {
+        let (paths, _dir) = test_paths();
+
+        let resp = handle_request(
+            "tools/call",
+            Some(&json!({
+                "name": "lumora.read_file",
+                "arguments": { "path": "missing.rs" }
+            })),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        let result = &resp["result"];
+        assert_eq!(result["isError"], true);
+        assert_eq!(
+            result["structuredContent"]["error"]["code"],
+            "FILE_NOT_FOUND"
+        );
+
+        let resp = handle_request(
+            "tools/call",
+            Some(&json!({
+                "name": "lumora.read_file",
+                "arguments": { "path": "../outside.rs" }
+            })),
+            json!(2),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            resp["result"]["structuredContent"]["error"]["code"],
+            "PATH_OUTSIDE_REPO"
+        );
+
+        let resp = handle_request(
+            "tools/call",
+            Some(&json!({
+                "name": "lumora.get_symbol_source",
+                "arguments": { "selector": "main" }
+            })),
+            json!(3),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            resp["result"]["structuredContent"]["error"]["code"],
+            "INDEX_EMPTY"
+        );
+
+        let resp = handle_request(
+            "tools/call",
+            Some(&json!({
+                "name": "lumora.dependency_path",
+                "arguments": { "from": "symbol_name:rust", "to": "file:src/main.rs" }
+            })),
+            json!(4),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        assert_eq!(resp["error"]["code"], -32602);
+        assert_eq!(resp["error"]["data"]["code"], "INVALID_PARAMS");
+    }
+
+    // ── handle_request integration tests ───────────────────────────
+
+    #[test]
+    fn test_handle_initialize() {
+        let (paths, _dir) = test_paths();
+        let params = json!({"protocolVersion": "2025-06-18"});
+        let resp = handle_request(
+            "initialize",
+            Some(&params),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .expect("handle_request initialize should succeed");
+        assert!(
+            resp["result"]["protocolVersion"].is_string(),
+            "should have protocolVersion"
+        );
+        assert!(
+            resp["result"]["capabilities"]["tools"].is_object(),
+            "should have tools capability"
+        );
+    }
+
+    #[test]
+    fn test_handle_resources_list_and_read_indexed_files() {
+        let (paths, _dir) = test_paths();
+        std::fs::create_dir_all(paths.repo_root.join("src")).unwrap();
+        std::fs::write(paths.repo_root.join("src/a b.rs"), "fn a() {}\n").unwrap();
+        std::fs::write(paths.repo_root.join("src/c.rs"), "fn c() {}\n").unwrap();
+        std::fs::write(paths.repo_root.join("notes.txt"), "not indexed\n").unwrap();
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+
+        let init =
+            handle_request("initialize", None, json!(2), &paths, &ToolFilter::default()).unwrap();
+        assert!(init["result"]["capabilities"]["resources"].is_object());
+
+        let list = handle_request(
+            "resources/list",
+            None,
+            json!(3),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        let resources = list["result"]["resources"].as_array().unwrap();
+        assert_eq!(resources.len(), 2);
+        assert_eq!(resources[0]["uri"], "lumora://file/src/a%20b.rs");
+        assert_eq!(resources[0]["name"], "src/a b.rs");
+        assert_eq!(resources[0]["mimeType"], "text/plain");
+        assert!(list["result"].get("nextCursor").is_none());
+
+        let after = handle_request(
+            "resources/list",
+            Some(&json!({"cursor": "src/a b.rs"})),
+            json!(4),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        assert_eq!(after["result"]["resources"][0]["name"], "src/c.rs");
+
+        let read = handle_request(
+            "resources/read",
+            Some(&json!({"uri": "lumora://file/src/a%20b.rs"})),
+            json!(5),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        assert_eq!(read["result"]["contents"][0]["text"], "fn a() {}\n");
+
+        let read_error = |uri: &str| {
+            handle_request(
+                "resources/read",
+                Some(&json!({ "uri": uri })),
+                json!(6),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()["error"]["code"]
+                .clone()
+        };
+        assert_eq!(
+            read_error("lumora://file/notes.txt"),
+            json!(RESOURCE_NOT_FOUND)
+        );
+        assert_eq!(read_error("file:///etc/passwd"), json!(-32602));
+    }
+
+    #[test]
+    fn test_handle_minimal_slice_inlines_source_within_budget() {
+        let (paths, _dir) = test_paths();
+        std::fs::create_dir_all(paths.repo_root.join("src")).unwrap();
+        std::fs::write(
+            paths.repo_root.join("src/lib.rs"),
+            "fn helper() -> u32 {\n    1\n}\n\nfn run() -> u32 {\n    let a = helper();\n    a + 1\n}\n",
+        )
+        .unwrap();
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+
+        let slice = |arguments: Value| {
+            handle_request(
+                "tools/call",
+                Some(&json!({ "name": "lumora.minimal_slice", "arguments": arguments })),
+                json!(2),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()["result"]["structuredContent"]
+                .clone()
+        };
+        assert!(slice(json!({ "selector": "run" })).get("source").is_none());
+
+        let response = slice(json!({
+            "selector": "run",
+            "include_source": true,
+            "max_source_lines": 6
+        }));
+        let source = &response["source"];
+        assert_eq!(
+            source["anchor"]["text"],
+            "fn run() -> u32 {\n    let a = helper();\n    a + 1\n}"
+        );
+        let helper = &source["neighbors"][0];
+        assert_eq!(helper["start_line"], 1);
+        assert_eq!(helper["text"], "fn helper() -> u32 {\n    1");
+        assert_eq!(helper["truncated"], true);
+        assert_eq!(source["lines_used"], 6);
+        assert_eq!(source["budget_exhausted"], true);
+    }
+
+    #[test]
+    fn test_handle_ping() {
+        let (paths, _dir) = test_paths();
+        let resp = handle_request("ping", None, json!(2), &paths, &ToolFilter::default())
+            .expect("handle_request ping should succeed");
+        assert!(resp["result"].is_object(), "ping result should be object");
+    }
+
+    #[test]
+    fn test_index_changed_notification_carries_event_paths() {
+        let event = IndexChangedEvent::new(vec!["src/a.rs".into(), "src/b.rs".into()]);
+        let notification = index_changed_notification(&event);
+        assert_eq!(notification["method"], "lumora/indexChanged");
+        assert!(notification.get("id").is_none());
+        assert_eq!(
+            notification["params"]["paths"],
+            json!(["src/a.rs", "src/b.rs"])
+        );
+
+        let (paths, _dir) = test_paths();
+        let init =
+            handle_request("initialize", None, json!(1), &paths, &ToolFilter::default()).unwrap();
+        assert!(init["result"]["capabilities"]["experimental"]
+            .get(INDEX_CHANGED_METHOD)
+            .is_some());
+    }
+
+    #[test]
+    fn test_handle_tools_list() {
+        let (paths, _dir) = test_paths();
+        let resp = handle_request("tools/list", None, json!(3), &paths, &ToolFilter::default())
+            .expect("handle_request tools/list should succeed");
+        let tools = &resp["result"]["tools"];
+        assert!(tools.is_array(), "tools should be an array");
+        assert_eq!(tools.as_array().unwrap().len(), 36, "should list 36 tools");
+    }
+
+    #[test]
+    fn test_prompts_expand_workflows_and_respect_the_tool_filter() {
+        let (paths, _dir) = test_paths();
+        for prompt in PROMPTS {
+            assert!(
+                prompt.tools.iter().all(|tool| is_known_tool(tool)),
+                "{} names an unknown tool",
+                prompt.name
+            );
+        }
+        let init =
+            handle_request("initialize", None, json!(1), &paths, &ToolFilter::default()).unwrap();
+        assert!(init["result"]["capabilities"]["prompts"].is_object());
+
+        let list = handle_request(
+            "prompts/list",
+            None,
+            json!(2),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        let prompts = list["result"]["prompts"].as_array().unwrap();
+        assert_eq!(prompts.len(), PROMPTS.len());
+        let investigate = prompts
+            .iter()
+            .find(|prompt| prompt["name"] == "investigate_symbol")
+            .unwrap();
+        assert_eq!(investigate["arguments"][0]["name"], "name");
+        assert_eq!(investigate["arguments"][0]["required"], true);
+
+        let get = |arguments: Value, tools: &ToolFilter| {
+            handle_request(
+                "prompts/get",
+                Some(&json!({ "name": "investigate_symbol", "arguments": arguments })),
+                json!(3),
+                &paths,
+                tools,
+            )
+            .unwrap()
+        };
+        let expanded = get(json!({ "name": "parse_config" }), &ToolFilter::default());
+        let text = expanded["result"]["messages"][0]["content"]["text"]
+            .as_str()
+            .unwrap();
+        assert!(text.contains("`parse_config`"));
+        assert!(text.contains("lumora.symbol_callers"));
+        assert_eq!(expanded["result"]["messages"][0]["role"], "user");
+
+        assert_eq!(
+            get(json!({}), &ToolFilter::default())["error"]["code"],
+            -32602
+        );
+        let no_history = ToolFilter::new(None, &["lumora.symbol_history".to_string()]).unwrap();
+        assert_eq!(
+            get(json!({ "name": "parse_config" }), &no_history)["error"]["code"],
+            -32602
+        );
+        let list = handle_request("prompts/list", None, json!(4), &paths, &no_history).unwrap();
+        assert!(list["result"]["prompts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|prompt| prompt["name"] != "investigate_symbol"));
+    }
+
+    #[test]
+    fn test_tool_filter_limits_listed_and_callable_tools() {
+        let grouped: HashSet<&str> = TOOL_GROUPS
+            .iter()
+            .flat_map(|(_, members)| members.iter().copied())
+            .collect();
+        let listed = tool_descriptors();
+        assert_eq!(grouped.len(), listed.len(), "each tool is in one group");
+        assert!(listed
+            .iter()
+            .all(|tool| grouped.contains(tool["name"].as_str().unwrap())));
+
+        let (paths, _dir) = test_paths();
+        let tools = ToolFilter::new(
+            Some(&["query".to_string(), "read_file".to_string()]),
+            &["lumora.dir_outline".to_string()],
+        )
+        .unwrap();
+        let resp = handle_request("tools/list", None, json!(1), &paths, &tools).unwrap();
+        let names: Vec<&str> = resp["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names.len(), 18);
+        assert!(names.contains(&"lumora.read_file"));
+        assert!(!names.contains(&"lumora.dir_outline"));
+        assert!(!names.contains(&"lumora.write_file"));
+
+        let resp = handle_request(
+            "tools/call",
+            Some(&json!({
+                "name": "lumora.write_file",
+                "arguments": { "path": "a.txt", "content": "x" }
+            })),
+            json!(2),
+            &paths,
+            &tools,
+        )
+        .unwrap();
+        assert_eq!(resp["error"]["code"], -32602);
+        assert!(resp["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("disabled"));
+        assert!(!paths.repo_root.join("a.txt").exists());
+
+        assert!(ToolFilter::new(None, &["writes".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_tools_list_pages_and_hides_experimental_tools() {
+        let (paths, _dir) = test_paths();
+        let tools = ToolFilter::default()
+            .with_experimental(false)
+            .with_page_size(Some(10));
+        let mut names = Vec::new();
+        let mut cursor: Option<String> = None;
+        let mut pages = 0;
+        loop {
+            let params = cursor.as_ref().map(|cursor| json!({ "cursor": cursor }));
+            let resp =
+                handle_request("tools/list", params.as_ref(), json!(1), &paths, &tools).unwrap();
+            let page = resp["result"]["tools"].as_array().unwrap();
+            assert!(page.len() <= 10);
+            names.extend(
+                page.iter()
+                    .map(|tool| tool["name"].as_str().unwrap().to_string()),
+            );
+            pages += 1;
+            match resp["result"]["nextCursor"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => break,
+            }
+        }
+        assert_eq!(pages, 4);
+        assert_eq!(names.len(), 36 - EXPERIMENTAL_TOOLS.len());
+        assert!(!names.iter().any(|name| name == "lumora.semantic_search"));
+
+        let bad_cursor = handle_request(
+            "tools/list",
+            Some(&json!({ "cursor": "lumora.nope" })),
+            json!(2),
+            &paths,
+            &tools,
+        )
+        .unwrap();
+        assert_eq!(bad_cursor["error"]["code"], -32602);
+
+        let resp = handle_request(
+            "tools/call",
+            Some(&json!({
+                "name": "lumora.semantic_search",
+                "arguments": { "query": "selector" }
+            })),
+            json!(3),
+            &paths,
+            &tools,
+        )
+        .unwrap();
+        assert_eq!(resp["error"]["code"], -32602);
+        assert!(resp["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("--experimental"));
+    }
+
+    #[test]
+    fn test_handle_unknown_method() {
+        let (paths, _dir) = test_paths();
+        let resp = handle_request("foo/bar", None, json!(4), &paths, &ToolFilter::default())
+            .expect("handle_request unknown method should succeed");
+        assert_eq!(
+            resp["error"]["code"], -32601,
+            "unknown method should return -32601"
+        );
+    }
+
+    #[test]
+    fn test_handle_tools_call_missing_params() {
+        let (paths, _dir) = test_paths();
+        let resp = handle_request("tools/call", None, json!(5), &paths, &ToolFilter::default())
+            .expect("handle_request should succeed");
+        assert!(
+            resp["error"].is_object(),
+            "missing params should produce error"
+        );
+    }
+
+    #[test]
+    fn test_handle_tools_call_missing_name() {
+        let (paths, _dir) = test_paths();
+        let params = json!({"arguments": {}});
+        let resp = handle_request(
+            "tools/call",
+            Some(&params),
+            json!(6),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .expect("handle_request should succeed");
+        assert!(
+            resp["error"].is_object(),
+            "missing name should produce error"
+        );
+    }
+
+    #[test]
+    fn test_reference_and_slice_rows_carry_stable_row_ids() {
+        let (paths, _dir) = test_paths();
+        std::fs::write(
+            paths.repo_root.join("lib.rs"),
+            "pub fn helper() {}\n\npub fn run() {\n    helper();\n    helper();\n    helper();\n}\n",
+        )
+        .unwrap();
+        let call = |name: &str, arguments: Value| {
+            handle_request(
+                "tools/call",
+                Some(&json!({ "name": name, "arguments": arguments })),
+                json!(1),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()["result"]["structuredContent"]
+                .clone()
+        };
+        call("lumora.index_repository", json!({}));
+
+        let row_ids = |arguments: Value| -> Vec<String> {
+            call("lumora.symbol_references", arguments)["rows"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|row| row["row_id"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let all = row_ids(json!({ "name": "helper", "order": "line_asc" }));
+        assert_eq!(all.len(), 3);
+        assert_eq!(all.iter().collect::<HashSet<_>>().len(), 3);
+        let page =
+            row_ids(json!({ "name": "helper", "order": "line_asc", "limit": 2, "offset": 1 }));
+        assert_eq!(page, all[1..]);
+
+        let slice = call(
+            "lumora.minimal_slice",
+            json!({ "file": "lib.rs", "line": 4, "depth": 1 }),
+        );
+        let neighbors = slice["slice"]["neighbors"].as_array().unwrap();
+        assert!(!neighbors.is_empty());
+        assert!(neighbors
+            .iter()
+            .all(|row| row["row_id"].as_str().is_some_and(|id| id.len() == 16)));
+    }
+
+    #[test]
+    fn test_handle_todos_lists_markers_by_glob_and_tag() {
+        let (paths, _dir) = test_paths();
+        std::fs::create_dir_all(paths.repo_root.join("src/store")).unwrap();
+        std::fs::write(
+            paths.repo_root.join("src/store/db.rs"),
+            "// TODO: batch writes\n// FIXME: locks\nfn db() {}\n",
+        )
+        .unwrap();
+        std::fs::write(paths.repo_root.join("cli.py"), "# HACK: env var\n").unwrap();
+        let call = |arguments: Value| {
+            handle_request(
+                "tools/call",
+                Some(&json!({"name": "lumora.todos", "arguments": arguments})),
+                json!(1),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()["result"]["structuredContent"]
+                .clone()
+        };
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+
+        assert_eq!(call(json!({}))["total"], 3);
+        let scoped = call(json!({"file_glob": "src/store/*"}));
+        assert_eq!(scoped["total"], 2);
+        assert_eq!(scoped["rows"][0]["text"], "batch writes");
+        let fixmes = call(json!({"tags": ["fixme"]}));
+        assert_eq!(fixmes["total"], 1);
+        assert_eq!(fixmes["rows"][0]["line"], 2);
+    }
+
+    #[test]
+    fn test_handle_hover_returns_definition_doc_and_references() {
+        let (paths, _dir) = test_paths();
+        std::fs::create_dir_all(paths.repo_root.join("src")).unwrap();
+        std::fs::write(
+            paths.repo_root.join("src/lib.rs"),
+            "/// Adds two numbers.\n///\n/// Wraps on overflow.\n#[inline]\n\
+             pub fn add(a: u32, b: u32) -> u32 {\n    a.wrapping_add(b)\n}\n\n\
+             fn main() {\n    let x = add(1, 2);\n    add(x, 3);\n}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            paths.repo_root.join("app.py"),
+            "def greet(name):\n    \"\"\"Say hello.\n\n    Politely.\n    \"\"\"\n    return name\n",
+        )
+        .unwrap();
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        let hover = |path: &str, line: u64, col: u64| {
+            handle_request(
+                "tools/call",
+                Some(&json!({
+                    "name": "lumora.hover",
+                    "arguments": { "path": path, "line": line, "col": col }
+                })),
+                json!(2),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()["result"]["structuredContent"]
+                .clone()
+        };
+
+        // Cursor on the middle of `add` in `add(1, 2)`.
+        let call_site = hover("src/lib.rs", 10, 14);
+        assert_eq!(call_site["found"], true);
+        assert_eq!(call_site["identifier"], "add");
+        assert_eq!(call_site["definition"]["line"], 5);
+        assert_eq!(call_site["signature"], "pub fn add(a: u32, b: u32) -> u32");
+        assert_eq!(call_site["doc"], "Adds two numbers.\n\nWraps on overflow.");
+        assert_eq!(call_site["total_references"], 2);
+
+        let docstring = hover("app.py", 1, 9);
+        assert_eq!(docstring["identifier"], "greet");
+        assert_eq!(docstring["doc"], "Say hello.\n\nPolitely.");
+
+        let blank = hover("src/lib.rs", 8, 1);
+        assert_eq!(blank["found"], false);
+        assert!(blank.get("identifier").is_none());
+    }
+
+    #[test]
+    fn test_lazy_index_parses_paths_named_by_queries() {
+        let (paths, _dir) = test_paths();
+        std::fs::create_dir_all(paths.repo_root.join("src/store")).unwrap();
+        std::fs::write(paths.repo_root.join("src/store/db.rs"), "// TODO: batch\n").unwrap();
+        std::fs::write(paths.repo_root.join("cli.py"), "# TODO: flags\n").unwrap();
+        let mut store = GraphStore::open(&paths.db_path).unwrap();
+        let lazy = IndexOptions {
+            lazy: Some(true),
+            ..Default::default()
+        };
+        index_repository(&mut store, &paths.repo_root, lazy).unwrap();
+        let call = |arguments: Value| {
+            handle_request(
+                "tools/call",
+                Some(&json!({"name": "lumora.todos", "arguments": arguments})),
+                json!(1),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()["result"]["structuredContent"]
+                .clone()
+        };
+
+        let whole_repo = call(json!({"file_glob": "**/*.py"}));
+        assert_eq!(whole_repo["total"], 0);
+        assert!(whole_repo.get("lazy_index").is_none());
+
+        let scoped = call(json!({"file_glob": "src/store/*.rs"}));
+        assert_eq!(scoped["total"], 1);
+        assert_eq!(scoped["lazy_index"][0]["path"], "src/store");
+        assert_eq!(scoped["lazy_index"][0]["indexed_files"], 1);
+        assert!(call(json!({"file_glob": "src/store/*.rs"}))
+            .get("lazy_index")
+            .is_none());
+        assert_eq!(glob_literal_prefix("cli.py").as_deref(), Some("cli.py"));
+        assert_eq!(glob_literal_prefix("src/*/db.rs").as_deref(), Some("src"));
+    }
+
+    #[test]
+    fn test_handle_index_file_refreshes_one_path() {
+        let (paths, _dir) = test_paths();
+        std::fs::write(paths.repo_root.join("lib.rs"), "pub fn old_name() {}\n").unwrap();
+        let call = |name: &str, arguments: Value| {
+            handle_request(
+                "tools/call",
+                Some(&json!({"name": name, "arguments": arguments})),
+                json!(1),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .expect("handle_request should succeed")
+        };
+        call("lumora.index_repository", json!({}));
+
+        std::fs::write(paths.repo_root.join("lib.rs"), "pub fn new_name() {}\n").unwrap();
+        let resp = call("lumora.index_file", json!({"path": "lib.rs"}));
+        assert_eq!(resp["result"]["structuredContent"]["indexed_files"], 1);
+        let resp = call("lumora.symbol_definitions", json!({"name": "new_name"}));
+        assert_eq!(
+            resp["result"]["structuredContent"]["rows"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+
+        let resp = call("lumora.index_file", json!({"path": "missing.rs"}));
+        assert_eq!(resp["result"]["isError"], true);
+    }
+
+    #[test]
+    fn test_handle_symbol_definitions_tool() {
+        let (paths, _dir) = test_paths();
+        // First index the repository to create the DB
+        let _index_resp = handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(10),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .expect("index should succeed");
+        // Then query for a nonexistent symbol
+        let resp = handle_request(
+            "tools/call",
+            Some(
+                &json!({"name": "lumora.symbol_definitions", "arguments": {"name": "nonexistent"}}),
+            ),
+            json!(11),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .expect("symbol_definitions should succeed");
+        assert!(
+            resp["result"]["structuredContent"].is_object(),
+            "should have structuredContent"
+        );
+    }
+
+    #[test]
+    fn test_handle_symbol_lookups_suggest_close_names_when_empty() {
+        let (paths, _dir) = test_paths();
+        std::fs::create_dir_all(paths.repo_root.join("src")).unwrap();
+        std::fs::write(
+            paths.repo_root.join("src/lib.rs"),
+            "pub fn parse_config() {}\n\nfn run() { parse_config(); }\n",
+        )
+        .unwrap();
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+
+        let call = |tool: &str, arguments: Value| {
+            handle_request(
+                "tools/call",
+                Some(&json!({"name": tool, "arguments": arguments})),
+                json!(2),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()["result"]["structuredContent"]
+                .clone()
+        };
+        let definitions = call("lumora.symbol_definitions", json!({"name": "parse_confg"}));
+        assert_eq!(definitions["did_you_mean"], json!(["parse_config"]));
+        let references = call("lumora.symbol_references", json!({"name": "Parse_Config"}));
+        assert_eq!(references["did_you_mean"], json!(["parse_config"]));
+
+        let found = call("lumora.symbol_definitions", json!({"name": "parse_config"}));
+        assert!(found.get("did_you_mean").is_none());
+        let disabled = call(
+            "lumora.symbol_definitions",
+            json!({"name": "parse_confg", "did_you_mean": false}),
+        );
+        assert!(disabled.get("did_you_mean").is_none());
+    }
+
+    #[test]
+    fn test_handle_symbols_batch_looks_up_each_name_once() {
+        let (paths, _dir) = test_paths();
+        std::fs::create_dir_all(paths.repo_root.join("src")).unwrap();
+        std::fs::write(
+            paths.repo_root.join("src/lib.rs"),
+            "pub fn parse_config() {}\n\npub fn load() {}\n\nfn run() { parse_config(); load(); }\n",
+        )
+        .unwrap();
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+
+        let call = |arguments: Value| {
+            handle_request(
+                "tools/call",
+                Some(&json!({"name": "lumora.symbols_batch", "arguments": arguments})),
+                json!(2),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()
+        };
+        let result = call(json!({
+            "names": ["parse_config", "load", "parse_confg", "load"],
+            "references": 3
+        }));
+        let content = &result["result"]["structuredContent"];
+        let results = content["results"].as_array().unwrap();
+        assert_eq!(results.len(), 3, "repeated names are looked up once");
+        assert_eq!(content["found"], 2);
+        assert_eq!(results[0]["name"], "parse_config");
+        assert_eq!(results[0]["definitions"][0]["file_path"], "src/lib.rs");
+        assert_eq!(results[0]["total_references"], 1);
+        assert_eq!(results[1]["name"], "load");
+        assert_eq!(results[2]["definitions"], json!([]));
+        assert_eq!(results[2]["did_you_mean"], json!(["parse_config"]));
+
+        let plain = call(json!({ "names": ["load"] }));
+        assert!(plain["result"]["structuredContent"]["results"][0]
+            .get("references")
+            .is_none());
+        assert_eq!(call(json!({ "names": [] }))["error"]["code"], -32602);
+        assert_eq!(
+            call(json!({ "names": ["load", 3] }))["error"]["code"],
+            -32602
+        );
+    }
+
+    #[test]
+    fn test_handle_file_profile_summarizes_a_files_role() {
+        let (paths, _dir) = test_paths();
+        std::fs::create_dir_all(paths.repo_root.join("src")).unwrap();
+        std::fs::write(
+            paths.repo_root.join("src/util.rs"),
+            "pub struct Config;\n\npub fn parse_config() -> Config { Config }\n\npub fn load() {}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            paths.repo_root.join("src/main.rs"),
+            "mod util;\nuse crate::util::parse_config;\n\nfn main() { parse_config(); util::load(); }\n",
+        )
+        .unwrap();
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+
+        let call = |path: &str| {
+            handle_request(
+                "tools/call",
+                Some(&json!({"name": "lumora.file_profile", "arguments": { "path": path }})),
+                json!(2),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()
+        };
+        let resp = call("./src/util.rs");
+        let profile = &resp["result"]["structuredContent"];
+        assert_eq!(profile["file_path"], "src/util.rs");
+        assert_eq!(profile["language"], "rust");
+        assert_eq!(profile["definitions"]["function_item"], 2);
+        assert_eq!(profile["definitions"]["struct_item"], 1);
+        assert_eq!(profile["imports_in"], 1);
+        assert_eq!(profile["calls_in"], 2);
+        assert_eq!(profile["caller_files"], 1);
+        assert!(profile["indexed_at"].is_string());
+
+        let profile = &call("src/main.rs")["result"]["structuredContent"];
+        assert_eq!(profile["imports_out"], 1);
+        assert_eq!(profile["calls_out"], 2);
+        assert_eq!(profile["calls_in"], 0);
+
+        let missing = &call("src/nope.rs")["result"];
+        assert_eq!(missing["isError"], true);
+        assert_eq!(
+            missing["structuredContent"]["error"]["code"],
+            "FILE_NOT_FOUND"
+        );
+    }
+
+    #[test]
+    fn test_handle_compare_files_combines_diff_and_fingerprints() {
+        let (paths, _dir) = test_paths();
+        std::fs::create_dir_all(paths.repo_root.join("src")).unwrap();
+        let body = "pub fn total(items: &[u32]) -> u32 {\n    let mut sum = 0;\n    for item in items {\n        sum += item * 2 + 1;\n    }\n    sum\n}\n";
+        std::fs::write(paths.repo_root.join("src/a.rs"), body).unwrap();
+        std::fs::write(
+            paths.repo_root.join("src/b.rs"),
+            body.replace("pub fn total", "pub fn total_copy"),
+        )
+        .unwrap();
+        std::fs::write(paths.repo_root.join("src/notes.txt"), "draft\n").unwrap();
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+
+        let compare = |left: &str, right: &str| {
+            handle_request(
+                "tools/call",
+                Some(&json!({
+                    "name": "lumora.compare_files",
+                    "arguments": { "left": left, "right": right }
+                })),
+                json!(2),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()["result"]["structuredContent"]
+                .clone()
+        };
+        let clone = compare("src/a.rs", "src/b.rs");
+        assert_eq!(
+            clone["hunks"][0]["lines"][0],
+            "-pub fn total(items: &[u32]) -> u32 {"
+        );
+        assert!(
+            clone["fingerprints"]["shared_fingerprints"]
+                .as_i64()
+                .unwrap()
+                > 0
+        );
+        assert!(clone["fingerprints"]["similarity"].as_f64().unwrap() > 0.5);
+
+        let unindexed = compare("src/a.rs", "src/notes.txt");
+        assert!(unindexed["fingerprints"].is_null());
+        assert!(unindexed["fingerprint_note"].is_string());
+    }
+
+    #[test]
+    fn test_handle_symbol_source_tool() {
+        let (paths, _dir) = test_paths();
+        std::fs::create_dir_all(paths.repo_root.join("src")).expect("src dir should exist");
+        std::fs::write(
+            paths.repo_root.join("src/lib.rs"),
+            "fn demo() {\n    println!(\"hi\");\n}\n",
+        )
+        .expect("rust file should be written");
+
+        let _index_resp = handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(12),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .expect("index should succeed");
+
+        let resp = handle_request(
+            "tools/call",
+            Some(&json!({
+                "name": "lumora.symbol_source",
+                "arguments": {"name": "demo", "context_lines": 0, "max_total_lines": 20}
+            })),
+            json!(13),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .expect("symbol_source should succeed");
+
+        let content = &resp["result"]["structuredContent"];
+        let rows = content["rows"].as_array().expect("rows should be array");
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["symbol_name"], "demo");
+        assert_eq!(rows[0]["read"]["path"], "src/lib.rs");
+        assert!(
+            rows[0]["read"]["content"]
+                .as_str()
+                .unwrap_or_default()
+                .contains("println!"),
+            "source read should include function body"
+        );
+    }
+
+    #[test]
+    fn test_handle_ambiguous_selectors_report_candidates_and_accept_pick() {
+        let (paths, _dir) = test_paths();
+        std::fs::create_dir_all(paths.repo_root.join("src")).unwrap();
+        std::fs::write(paths.repo_root.join("src/a.rs"), "fn run() {}\n").unwrap();
+        std::fs::write(paths.repo_root.join("src/b.rs"), "fn run() {}\n").unwrap();
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        let call = |name: &str, arguments: Value| {
+            handle_request(
+                "tools/call",
+                Some(&json!({ "name": name, "arguments": arguments })),
+                json!(2),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()["result"]["structuredContent"]
+                .clone()
+        };
+
+        let source = call("lumora.get_symbol_source", json!({ "selector": "run" }));
+        assert_eq!(source["ambiguous"], true);
+        let candidates = source["resolution"]["candidates"].as_array().unwrap();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[1]["file_path"], "src/b.rs");
+        let picked = call(
+            "lumora.get_symbol_source",
+            json!({ "selector": "run", "pick": 1 }),
+        );
+        assert_eq!(picked["symbol"]["file_path"], "src/b.rs");
+
+        let path = call(
+            "lumora.dependency_path",
+            json!({ "from": "file:src/b.rs", "to": "run", "pick": { "to": 1 } }),
+        );
+        assert_eq!(path["ambiguous"], true);
+        assert_eq!(path["found"], true);
+        assert!(path["selector"]["from"]["candidates"].is_null());
+        assert_eq!(
+            path["selector"]["to"]["candidates"][1]["file_path"],
+            "src/b.rs"
+        );
+
+        let slice = call(
+            "lumora.minimal_slice",
+            json!({ "selector": "run", "pick": 1 }),
+        );
+        assert_eq!(slice["ambiguous"], true);
+        assert_eq!(slice["slice"]["anchor"]["file_path"], "src/b.rs");
+
+        let resp = handle_request(
+            "tools/call",
+            Some(&json!({
+                "name": "lumora.get_symbol_source",
+                "arguments": { "selector": "run", "pick": 9 }
+            })),
+            json!(3),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        let result = &resp["result"];
+        assert_eq!(result["isError"], true);
+        assert_eq!(
+            result["structuredContent"]["error"]["code"],
+            "SELECTOR_AMBIGUOUS"
+        );
+    }
+
+    #[test]
+    fn test_write_tools_refresh_the_index_when_asked() {
+        let (paths, _dir) = test_paths();
+        std::fs::write(paths.repo_root.join("lib.rs"), "pub fn before() {}\n").unwrap();
+        let call = |name: &str, arguments: Value| {
+            handle_request(
+                "tools/call",
+                Some(&json!({ "name": name, "arguments": arguments })),
+                json!(1),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()["result"]["structuredContent"]
+                .clone()
+        };
+        let defined = |name: &str| {
+            let rows = call("lumora.symbol_definitions", json!({ "name": name }));
+            !rows["rows"].as_array().unwrap().is_empty()
+        };
+        call("lumora.index_repository", json!({}));
+
+        let edit = json!({ "path": "lib.rs", "old_text": "before", "new_text": "after" });
+        let result = call("lumora.edit_file", edit);
+        assert!(result.get("index_refresh").is_none(), "off by default");
+        assert!(!defined("after"));
+
+        let edit = json!({
+            "path": "lib.rs",
+            "old_text": "after",
+            "new_text": "later",
+            "auto_refresh_index": true
+        });
+        let result = call("lumora.edit_file", edit);
+        assert_eq!(result["index_refresh"]["indexed_files"], 1);
+        assert!(defined("later"));
+
+        std::fs::write(
+            paths.repo_root.join(".lumora.toml"),
+            "[mcp]\nauto_refresh_index = true\n",
+        )
+        .unwrap();
+        let result = call(
+            "lumora.move_file",
+            json!({ "source": "lib.rs", "destination": "core.rs" }),
+        );
+        assert_eq!(result["index_refresh"]["removed_files"], 1);
+        let rows = call("lumora.symbol_definitions", json!({ "name": "later" }));
+        assert_eq!(rows["rows"][0]["file_path"], "core.rs");
+
+        let result = call("lumora.undo_last_change", json!({}));
+        assert_eq!(result["index_refresh"]["indexed_files"], 1);
+        let rows = call("lumora.symbol_definitions", json!({ "name": "later" }));
+        assert_eq!(rows["rows"].as_array().unwrap().len(), 1);
+        assert_eq!(rows["rows"][0]["file_path"], "lib.rs");
+
+        let result = call(
+            "lumora.write_file",
+            json!({ "path": "notes.txt", "content": "hi" }),
+        );
+        assert!(result.get("index_refresh").is_none(), "not an indexed file");
+    }
+
+    #[test]
+    fn test_write_tools_are_journaled_to_the_oplog() {
+        let (paths, _dir) = test_paths();
+        std::fs::write(paths.repo_root.join("lib.rs"), "pub fn before() {}\n").unwrap();
+        let call = |name: &str, arguments: Value| {
+            handle_request(
+                "tools/call",
+                Some(&json!({ "name": name, "arguments": arguments })),
+                json!(1),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()["result"]["structuredContent"]
+                .clone()
+        };
+
+        let edit = json!({ "path": "lib.rs", "old_text": "before", "new_text": "after" });
+        assert_eq!(call("lumora.edit_file", edit)["undo_id"], 1);
+        let dry_run =
+            json!({ "path": "lib.rs", "old_text": "after", "new_text": "x", "dry_run": true });
+        assert!(call("lumora.edit_file", dry_run).get("undo_id").is_none());
+        let result = call("lumora.delete_file", json!({ "path": "lib.rs" }));
+        assert_eq!(result["undo_id"], 2);
+
+        let entries = oplog::read_entries(&paths).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].tool, "lumora.edit_file");
+        assert_eq!(entries[0].args["new_text"], "after");
+        assert_eq!(entries[1].files[0].path, "lib.rs");
+        assert_eq!(entries[1].files[0].after, None);
+
+        let undone = call("lumora.undo_last_change", json!({}));
+        assert_eq!(undone["undone"], 2);
+        assert_eq!(undone["undo_id"], 3);
+        let undone = call("lumora.undo_last_change", json!({}));
+        assert_eq!(undone["undone"], 1);
         assert_eq!(
-            content[0]["type"], "text",
-            "first content item type should be text"
-        );
-        assert!(
-            result["structuredContent"].is_object(),
-            "structuredContent should be present"
+            std::fs::read_to_string(paths.repo_root.join("lib.rs")).unwrap(),
+            "pub fn before() {}\n"
         );
-    }
+        let nothing_left = handle_request(
+            "tools/call",
+            Some(&json!({ "name": "lumora.undo_last_change", "arguments": {} })),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        assert_eq!(nothing_left["error"]["code"], -32602);
 
-    #[test]
-    fn test_tool_error() {
-        let result = tool_error("boom".to_string());
+        // Undoing the undo redoes the edit.
+        call("lumora.undo_last_change", json!({ "undo_id": 4 }));
         assert_eq!(
-            result["content"][0]["text"], "boom",
-            "error text should be boom"
+            std::fs::read_to_string(paths.repo_root.join("lib.rs")).unwrap(),
+            "pub fn after() {}\n"
         );
-        assert_eq!(result["isError"], true, "isError should be true");
     }
 
-    // ── handle_request integration tests ───────────────────────────
-
     #[test]
-    fn test_handle_initialize() {
+    fn test_handle_write_guardrails_from_config() {
         let (paths, _dir) = test_paths();
-        let params = json!({"protocolVersion": "2025-06-18"});
-        let resp = handle_request("initialize", Some(&params), json!(1), &paths)
-            .expect("handle_request initialize should succeed");
-        assert!(
-            resp["result"]["protocolVersion"].is_string(),
-            "should have protocolVersion"
+        std::fs::write(
+            paths.repo_root.join(".lumora.toml"),
+            "[guardrails]\nmax_write_bytes = 16\nmax_files_per_minute = 3\nconfirm_destructive = true\n",
+        )
+        .unwrap();
+        let call = |name: &str, arguments: Value| {
+            handle_request(
+                "tools/call",
+                Some(&json!({ "name": name, "arguments": arguments })),
+                json!(1),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()["result"]
+                .clone()
+        };
+        let blocked = |result: &Value| {
+            result["isError"] == true
+                && result["structuredContent"]["error"]["code"] == "GUARDRAIL_BLOCKED"
+        };
+
+        let big = call(
+            "lumora.write_file",
+            json!({ "path": "big.txt", "content": "x".repeat(17) }),
+        );
+        assert!(blocked(&big), "oversized writes are refused: {big}");
+        assert!(!paths.repo_root.join("big.txt").exists());
+
+        for name in ["a.txt", "b.txt"] {
+            let ok = call(
+                "lumora.write_file",
+                json!({ "path": name, "content": "hi" }),
+            );
+            assert!(ok.get("isError").is_none(), "{ok}");
+        }
+        let unconfirmed = call("lumora.delete_file", json!({ "path": "a.txt" }));
+        assert!(blocked(&unconfirmed));
+        assert!(paths.repo_root.join("a.txt").exists());
+
+        let dry_run = call(
+            "lumora.edit_file",
+            json!({ "path": "a.txt", "old_text": "hi", "new_text": "yo", "dry_run": true }),
+        );
+        assert!(dry_run.get("isError").is_none(), "dry runs do not count");
+        let moved = call(
+            "lumora.move_file",
+            json!({ "source": "a.txt", "destination": "c.txt", "confirm": true }),
         );
         assert!(
-            resp["result"]["capabilities"]["tools"].is_object(),
-            "should have tools capability"
+            blocked(&moved),
+            "a move touches two files, exceeding the limit"
         );
-    }
+        let deleted = call(
+            "lumora.delete_file",
+            json!({ "path": "a.txt", "confirm": true }),
+        );
+        assert!(deleted.get("isError").is_none(), "{deleted}");
+        let over = call(
+            "lumora.write_file",
+            json!({ "path": "d.txt", "content": "hi" }),
+        );
+        assert!(blocked(&over));
+        assert!(over["structuredContent"]["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("max_files_per_minute"));
 
-    #[test]
-    fn test_handle_ping() {
-        let (paths, _dir) = test_paths();
-        let resp = handle_request("ping", None, json!(2), &paths)
-            .expect("handle_request ping should succeed");
-        assert!(resp["result"].is_object(), "ping result should be object");
+        let undo = call("lumora.undo_last_change", json!({}));
+        assert!(
+            blocked(&undo),
+            "undo restores files like delete_file: {undo}"
+        );
+        assert!(!paths.repo_root.join("a.txt").exists());
+        let undo = call("lumora.undo_last_change", json!({ "confirm": true }));
+        assert!(undo["structuredContent"]["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("max_files_per_minute"));
     }
 
     #[test]
-    fn test_handle_tools_list() {
+    fn test_handle_symbol_history_reports_commits_and_authors() {
         let (paths, _dir) = test_paths();
-        let resp = handle_request("tools/list", None, json!(3), &paths)
-            .expect("handle_request tools/list should succeed");
-        let tools = &resp["result"]["tools"];
-        assert!(tools.is_array(), "tools should be an array");
-        assert_eq!(tools.as_array().unwrap().len(), 21, "should list 21 tools");
-    }
+        let repo = &paths.repo_root;
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(repo)
+                .args(["-c", "user.name=Ada", "-c", "user.email=ada@example.com"])
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(status.status.success(), "git {args:?} failed");
+        };
+        std::fs::create_dir_all(repo.join("src")).unwrap();
+        std::fs::write(
+            repo.join("src/lib.rs"),
+            "fn other() {}\n\nfn target() {\n    let x = 1;\n}\n",
+        )
+        .unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "."]);
+        git(&["commit", "-qm", "add target"]);
+        std::fs::write(
+            repo.join("src/lib.rs"),
+            "fn other() { let y = 2; }\n\nfn target() {\n    let x = 1;\n}\n",
+        )
+        .unwrap();
+        git(&["commit", "-qam", "touch other"]);
+        std::fs::write(
+            repo.join("src/lib.rs"),
+            "fn other() { let y = 2; }\n\nfn target() {\n    let x = 3;\n}\n",
+        )
+        .unwrap();
+        git(&["commit", "-qam", "tweak target"]);
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
 
-    #[test]
-    fn test_handle_unknown_method() {
-        let (paths, _dir) = test_paths();
-        let resp = handle_request("foo/bar", None, json!(4), &paths)
-            .expect("handle_request unknown method should succeed");
-        assert_eq!(
-            resp["error"]["code"], -32601,
-            "unknown method should return -32601"
-        );
+        let response = handle_request(
+            "tools/call",
+            Some(&json!({
+                "name": "lumora.symbol_history",
+                "arguments": { "selector": "target" }
+            })),
+            json!(2),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        let result = &response["result"]["structuredContent"];
+        assert_eq!(result["found"], true);
+        let summaries = result["history"]["commits"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|commit| commit["summary"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(summaries, ["tweak target", "add target"]);
+        assert_eq!(result["history"]["authors"][0]["author"], "Ada");
+        assert_eq!(result["history"]["authors"][0]["lines"], 3);
     }
 
     #[test]
-    fn test_handle_tools_call_missing_params() {
+    fn test_handle_symbol_neighbors_groups_edges_by_type() {
         let (paths, _dir) = test_paths();
-        let resp = handle_request("tools/call", None, json!(5), &paths)
-            .expect("handle_request should succeed");
-        assert!(
-            resp["error"].is_object(),
-            "missing params should produce error"
-        );
-    }
+        std::fs::create_dir_all(paths.repo_root.join("src")).unwrap();
+        std::fs::write(
+            paths.repo_root.join("src/lib.rs"),
+            "fn helper() {}\n\nfn run() {\n    helper();\n    helper();\n}\n",
+        )
+        .unwrap();
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        let call = |arguments: Value| {
+            handle_request(
+                "tools/call",
+                Some(&json!({ "name": "lumora.symbol_neighbors", "arguments": arguments })),
+                json!(2),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()["result"]["structuredContent"]
+                .clone()
+        };
 
-    #[test]
-    fn test_handle_tools_call_missing_name() {
-        let (paths, _dir) = test_paths();
-        let params = json!({"arguments": {}});
-        let resp = handle_request("tools/call", Some(&params), json!(6), &paths)
-            .expect("handle_request should succeed");
-        assert!(
-            resp["error"].is_object(),
-            "missing name should produce error"
-        );
+        let file = call(json!({ "selector": "file:src/lib.rs", "limit": 1 }));
+        assert_eq!(file["found"], true);
+        let outgoing = file["neighbors"]["outgoing"].as_array().unwrap();
+        let defines = outgoing
+            .iter()
+            .find(|group| group["edge_type"] == "defines")
+            .expect("file should define symbols");
+        assert_eq!(defines["count"], 2);
+        assert_eq!(defines["neighbors"].as_array().unwrap().len(), 1);
+        assert!(file["neighbors"]["incoming"].as_array().unwrap().is_empty());
+
+        let name = call(json!({ "selector": "symbol_name:rust:helper" }));
+        let incoming = name["neighbors"]["incoming"].as_array().unwrap();
+        assert_eq!(incoming[0]["edge_type"], "calls");
+        assert_eq!(incoming[0]["count"], 2);
+        assert_eq!(incoming[0]["distinct"], 1);
+        assert_eq!(incoming[0]["neighbors"][1]["line"], 5);
+        assert_eq!(incoming[1]["edge_type"], "names");
+
+        let missing = call(json!({ "selector": "file:src/none.rs" }));
+        assert_eq!(missing["found"], false);
+        assert!(missing["neighbors"].is_null());
     }
 
     #[test]
-    fn test_handle_symbol_definitions_tool() {
+    fn test_handle_get_symbol_source_tool() {
         let (paths, _dir) = test_paths();
-        // First index the repository to create the DB
-        let _index_resp = handle_request(
+        std::fs::create_dir_all(paths.repo_root.join("src")).expect("src dir should exist");
+        std::fs::write(
+            paths.repo_root.join("src/lib.rs"),
+            "// header\nfn demo() {\n    println!(\"hi\");\n}\n\nfn other() {}\n",
+        )
+        .expect("rust file should be written");
+        handle_request(
             "tools/call",
             Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
-            json!(10),
+            json!(1),
             &paths,
+            &ToolFilter::default(),
         )
         .expect("index should succeed");
-        // Then query for a nonexistent symbol
+
+        for selector in ["demo", "symbol_name:rust:demo"] {
+            let resp = handle_request(
+                "tools/call",
+                Some(&json!({
+                    "name": "lumora.get_symbol_source",
+                    "arguments": {"selector": selector}
+                })),
+                json!(2),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .expect("get_symbol_source should succeed");
+            let content = &resp["result"]["structuredContent"];
+            assert_eq!(content["found"], true, "{selector} should resolve");
+            assert_eq!(content["symbol"]["file_path"], "src/lib.rs");
+            assert_eq!(content["start_line"], 2);
+            assert_eq!(content["end_line"], 4);
+            assert_eq!(content["source"], "fn demo() {\n    println!(\"hi\");\n}");
+        }
+
         let resp = handle_request(
             "tools/call",
-            Some(
-                &json!({"name": "lumora.symbol_definitions", "arguments": {"name": "nonexistent"}}),
-            ),
-            json!(11),
+            Some(&json!({
+                "name": "lumora.get_symbol_source",
+                "arguments": {"selector": "file:src/lib.rs"}
+            })),
+            json!(3),
             &paths,
+            &ToolFilter::default(),
         )
-        .expect("symbol_definitions should succeed");
-        assert!(
-            resp["result"]["structuredContent"].is_object(),
-            "should have structuredContent"
-        );
+        .expect("get_symbol_source should succeed for non-symbols");
+        assert_eq!(resp["result"]["structuredContent"]["found"], false);
     }
 
     #[test]
-    fn test_handle_symbol_source_tool() {
+    fn test_handle_semantic_search_tool() {
         let (paths, _dir) = test_paths();
         std::fs::create_dir_all(paths.repo_root.join("src")).expect("src dir should exist");
         std::fs::write(
             paths.repo_root.join("src/lib.rs"),
-            "fn demo() {\n    println!(\"hi\");\n}\n",
+            "fn parse_selector(input: &str) {}\n\nfn write_report() {}\n",
         )
         .expect("rust file should be written");
 
-        let _index_resp = handle_request(
+        let resp = handle_request(
             "tools/call",
-            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
-            json!(12),
+            Some(&json!({"name": "lumora.semantic_search", "arguments": {"query": "selector"}})),
+            json!(14),
             &paths,
+            &ToolFilter::default(),
         )
-        .expect("index should succeed");
+        .expect("semantic_search should succeed");
+        assert!(
+            resp["result"]["structuredContent"]["warning"].is_string(),
+            "should warn when embeddings are disabled"
+        );
+
+        let index_resp = handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {"embeddings": true}})),
+            json!(15),
+            &paths,
+            &ToolFilter::default(),
+        );
+        if cfg!(not(feature = "semantic")) {
+            let message = match index_resp {
+                Ok(resp) => resp.to_string(),
+                Err(err) => err.to_string(),
+            };
+            assert!(
+                message.contains("`semantic` feature"),
+                "enabling embeddings should name the missing feature: {message}"
+            );
+            return;
+        }
+        index_resp.expect("index should succeed");
 
         let resp = handle_request(
             "tools/call",
             Some(&json!({
-                "name": "lumora.symbol_source",
-                "arguments": {"name": "demo", "context_lines": 0, "max_total_lines": 20}
+                "name": "lumora.semantic_search",
+                "arguments": {"query": "parse selector", "kind": "symbol", "limit": 1}
             })),
-            json!(13),
+            json!(16),
             &paths,
+            &ToolFilter::default(),
         )
-        .expect("symbol_source should succeed");
-
+        .expect("semantic_search should succeed");
         let content = &resp["result"]["structuredContent"];
+        assert!(content["warning"].is_null());
         let rows = content["rows"].as_array().expect("rows should be array");
         assert_eq!(rows.len(), 1);
-        assert_eq!(rows[0]["symbol_name"], "demo");
-        assert_eq!(rows[0]["read"]["path"], "src/lib.rs");
-        assert!(
-            rows[0]["read"]["content"]
-                .as_str()
-                .unwrap_or_default()
-                .contains("println!"),
-            "source read should include function body"
-        );
+        assert_eq!(rows[0]["label"], "parse_selector");
+        assert_eq!(rows[0]["file_path"], "src/lib.rs");
     }
 
     #[test]
@@ -1990,6 +5861,7 @@ mod tests {
             })),
             json!(12),
             &paths,
+            &ToolFilter::default(),
         )
         .expect("multi_outline should succeed");
 
@@ -2020,6 +5892,7 @@ mod tests {
             })),
             json!(14),
             &paths,
+            &ToolFilter::default(),
         )
         .expect("batch_edit should succeed");
 
@@ -2053,6 +5926,7 @@ mod tests {
             })),
             json!(15),
             &paths,
+            &ToolFilter::default(),
         )
         .expect("apply_patch should succeed");
 
@@ -2107,4 +5981,241 @@ mod tests {
             "why should be preserved in normal mode"
         );
     }
+
+    // ── Response budget ─────────────────────────────────────────────
+
+    #[test]
+    fn test_apply_response_budget_under_limit_is_untouched() {
+        let value = json!({ "rows": [{"name": "x"}] });
+        let result = apply_response_budget(value.clone(), 10_000);
+        assert_eq!(result, value, "small responses should pass through");
+    }
+
+    #[test]
+    fn test_apply_response_budget_drops_snippets_and_rows() {
+        let rows: Vec<Value> = (0..200)
+            .map(|i| {
+                json!({
+                    "file_path": format!("src/file_{i}.rs"),
+                    "line": i,
+                    "why": "a fairly long ranking explanation string",
+                    "context_before": ["let a = 1;", "let b = 2;"],
+                })
+            })
+            .collect();
+        let value = json!({
+            "rows": rows,
+            "pagination": {
+                "offset": 0,
+                "limit": 200,
+                "returned": 200,
+                "has_more": true,
+                "next_offset": 200,
+                "next_cursor": "7:200"
+            }
+        });
+
+        let result = apply_response_budget(value, 4_000);
+        assert!(envelope_size(&result) <= 4_000, "result should fit budget");
+        assert_eq!(result["truncated"], true);
+        let kept = result["rows"].as_array().unwrap();
+        assert!(
+            !kept.is_empty() && kept.len() < 200,
+            "rows should be trimmed"
+        );
+        assert!(kept[0].get("why").is_none());
+        assert!(kept[0].get("context_before").is_none());
+        assert_eq!(kept[0]["line"], 0, "rows should be dropped from the end");
+        let truncation = &result["truncation"];
+        assert_eq!(
+            truncation["dropped_rows"].as_u64().unwrap() as usize,
+            200 - kept.len()
+        );
+        assert!(truncation["hint"].as_str().unwrap().contains("offset"));
+        let pagination = &result["pagination"];
+        assert_eq!(pagination["returned"], kept.len());
+        assert_eq!(pagination["next_offset"], kept.len());
+        assert_eq!(pagination["has_more"], true);
+        assert_eq!(pagination["next_cursor"], format!("7:{}", kept.len()));
+    }
+
+    #[test]
+    fn test_apply_response_budget_cuts_long_strings() {
+        let value = json!({ "path": "big.txt", "content": "x".repeat(20_000) });
+        let result = apply_response_budget(value, 2_000);
+        assert!(envelope_size(&result) <= 2_000);
+        assert_eq!(result["truncation"]["truncated_strings"], true);
+        assert!(result["content"].as_str().unwrap().ends_with("[truncated]"));
+    }
+
+    #[test]
+    fn test_handle_tools_call_applies_max_tokens_estimate() {
+        let (paths, _dir) = test_paths();
+        let content = (0..2_000)
+            .map(|i| format!("line {i}\n"))
+            .collect::<String>();
+        std::fs::write(paths.repo_root.join("big.txt"), content).unwrap();
+
+        let resp = handle_request(
+            "tools/call",
+            Some(&json!({
+                "name": "lumora.read_file",
+                "arguments": { "path": "big.txt", "max_tokens_estimate": 500 }
+            })),
+            json!(40),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        let structured = &resp["result"]["structuredContent"];
+        assert_eq!(structured["truncated"], true);
+        assert_eq!(structured["truncation"]["max_bytes"], 2_000);
+        assert!(serde_json::to_string(&resp["result"]).unwrap().len() <= 2_000);
+
+        let tools = tool_descriptors();
+        assert!(tools
+            .iter()
+            .all(|tool| tool["inputSchema"]["properties"]["max_bytes"].is_object()));
+    }
+
+    #[test]
+    fn test_handle_tools_call_symbol_references_group_by_enclosing_symbol() {
+        let (paths, _dir) = test_paths();
+        std::fs::create_dir_all(paths.repo_root.join("src")).unwrap();
+        std::fs::write(
+            paths.repo_root.join("src/lib.rs"),
+            "fn demo() {\n    helper();\n    helper();\n}\n\nfn other() { helper(); }\n\nfn helper() {}\n",
+        )
+        .unwrap();
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+
+        let resp = handle_request(
+            "tools/call",
+            Some(&json!({
+                "name": "lumora.symbol_callers",
+                "arguments": { "name": "helper", "group_by": "enclosing_symbol" }
+            })),
+            json!(2),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        let structured = &resp["result"]["structuredContent"];
+        assert_eq!(structured["group_by"], "enclosing_symbol");
+        let groups = structured["groups"].as_array().expect("groups array");
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0]["enclosing_symbol"], "demo");
+        assert_eq!(groups[0]["count"], 2);
+        assert_eq!(groups[1]["enclosing_symbol"], "other");
+
+        let resp = handle_request(
+            "tools/call",
+            Some(&json!({
+                "name": "lumora.symbol_references",
+                "arguments": { "name": "helper", "group_by": "file" }
+            })),
+            json!(3),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        assert_eq!(resp["error"]["code"], -32602);
+    }
+
+    #[test]
+    fn test_handle_tools_call_timeout_ms_flags_partial_results() {
+        let (paths, _dir) = test_paths();
+        std::fs::create_dir_all(paths.repo_root.join("src")).unwrap();
+        std::fs::write(
+            paths.repo_root.join("src/lib.rs"),
+            "fn demo() { helper(); }\n\nfn helper() {}\n",
+        )
+        .unwrap();
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {"timeout_ms": 0}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .expect("indexing ignores the query deadline");
+
+        let call = |timeout_ms: u64| {
+            handle_request(
+                "tools/call",
+                Some(&json!({
+                    "name": "lumora.dependency_path",
+                    "arguments": {
+                        "from": "file:src/lib.rs",
+                        "to": "symbol:helper",
+                        "timeout_ms": timeout_ms
+                    }
+                })),
+                json!(2),
+                &paths,
+                &ToolFilter::default(),
+            )
+            .unwrap()
+        };
+
+        let resp = call(0);
+        let structured = &resp["result"]["structuredContent"];
+        assert_eq!(structured["timed_out"], true);
+        assert_eq!(structured["timeout_ms"], 0);
+        assert_eq!(structured["found"], false);
+
+        let resp = call(60_000);
+        let structured = &resp["result"]["structuredContent"];
+        assert!(structured.get("timed_out").is_none());
+        assert_eq!(structured["found"], true);
+    }
+
+    #[test]
+    fn test_handle_dependency_path_max_paths_lists_alternatives() {
+        let (paths, _dir) = test_paths();
+        std::fs::create_dir_all(paths.repo_root.join("src")).unwrap();
+        std::fs::write(
+            paths.repo_root.join("src/lib.rs"),
+            "fn demo() { helper(); }\n\nfn helper() {}\n",
+        )
+        .unwrap();
+        handle_request(
+            "tools/call",
+            Some(&json!({"name": "lumora.index_repository", "arguments": {}})),
+            json!(1),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+
+        let resp = handle_request(
+            "tools/call",
+            Some(&json!({
+                "name": "lumora.dependency_path",
+                "arguments": {
+                    "from": "file:src/lib.rs",
+                    "to": "symbol:helper",
+                    "max_paths": 3
+                }
+            })),
+            json!(2),
+            &paths,
+            &ToolFilter::default(),
+        )
+        .unwrap();
+        let structured = &resp["result"]["structuredContent"];
+        assert_eq!(structured["found"], true);
+        let found = structured["paths"].as_array().unwrap();
+        assert!(!found.is_empty() && found.len() <= 3);
+        assert_eq!(found[0][0]["entity_key"], "file:src/lib.rs");
+        assert!(found[0][0].get("edge_type").is_none());
+        assert!(found[0][1]["edge_type"].is_string());
+    }
 }
@@ -1,6 +1,9 @@
-use serde::Serialize;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LanguageKind {
     Rust,
     Python,
@@ -57,7 +60,7 @@ impl LanguageKind {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Definition {
     pub name: String,
     pub qualname: String,
@@ -66,9 +69,40 @@ pub struct Definition {
     pub col: i64,
     pub end_line: i64,
     pub end_col: i64,
+    /// Parameter list and return type of callable definitions, as written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// How many arguments a call to this callable can pass.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arity: Option<Arity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offsets: Option<SpanOffsets>,
+    /// `public`, `private`, `protected`, or `internal`, from an explicit
+    /// keyword or the language's naming/export rules; `None` when neither
+    /// says.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<String>,
+    /// `async`, `static`, and `abstract`, in source order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// Argument counts a callable accepts. `max` is `None` for variadic
+/// callables; a receiver (`self`, `cls`) raises `max` by one, since it may
+/// be passed explicitly (`Type::method(value, ...)`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Arity {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    pub fn accepts(&self, args: usize) -> bool {
+        args >= self.min && self.max.map_or(true, |max| args <= max)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Reference {
     pub name: String,
     pub kind: ReferenceKind,
@@ -76,9 +110,48 @@ pub struct Reference {
     pub col: i64,
     pub end_line: i64,
     pub end_col: i64,
+    /// Arguments passed at a call site; `None` for plain references and
+    /// calls whose arguments cannot be counted (spreads, no argument list).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arity: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offsets: Option<SpanOffsets>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+/// Precise positions for editor integrations. `line`/`col` count bytes
+/// within the line; these add absolute byte offsets into the file and
+/// 1-based UTF-16 code-unit columns (subtract one for LSP positions), which
+/// differ from `col` on lines with non-ASCII text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpanOffsets {
+    pub start_byte: usize,
+    pub end_byte: usize,
+    pub col_utf16: i64,
+    pub end_col_utf16: i64,
+}
+
+impl SpanOffsets {
+    /// Reads the flat `start_byte`/`end_byte`/`col_utf16`/`end_col_utf16`
+    /// keys written into entity and edge `meta_json`.
+    pub fn from_meta(meta: &serde_json::Value) -> Option<Self> {
+        let int = |key: &str| meta.get(key)?.as_i64();
+        Some(Self {
+            start_byte: int("start_byte")?.try_into().ok()?,
+            end_byte: int("end_byte")?.try_into().ok()?,
+            col_utf16: int("col_utf16")?,
+            end_col_utf16: int("end_col_utf16")?,
+        })
+    }
+
+    pub fn write_meta(&self, meta: &mut serde_json::Value) {
+        meta["start_byte"] = self.start_byte.into();
+        meta["end_byte"] = self.end_byte.into();
+        meta["col_utf16"] = self.col_utf16.into();
+        meta["end_col_utf16"] = self.end_col_utf16.into();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ReferenceKind {
     Call,
     Ref,
@@ -93,19 +166,66 @@ impl ReferenceKind {
     }
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RelationKind {
+    Implements,
+    Extends,
+    /// The subject is another name for the target (`use a::B as C`,
+    /// `export { B as C }`, `from a import B as C`).
+    Aliases,
+}
+
+impl RelationKind {
+    pub fn as_edge_type(self) -> &'static str {
+        match self {
+            Self::Implements => "implements",
+            Self::Extends => "extends",
+            Self::Aliases => "aliases",
+        }
+    }
+}
+
+/// A name-level relation such as `impl Trait for Type`, `class A extends B`,
+/// or an `as` rename.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Relation {
+    pub subject: String,
+    pub target: String,
+    pub kind: RelationKind,
+    pub line: i64,
+    pub col: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Import {
     pub module: String,
     pub line: i64,
     pub col: i64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileExtraction {
     pub language: LanguageKind,
     pub definitions: Vec<Definition>,
     pub references: Vec<Reference>,
     pub imports: Vec<Import>,
+    pub relations: Vec<Relation>,
+    /// Byte ranges of comments and string literals, blanked out before clone
+    /// fingerprinting.
+    pub noise_spans: Vec<(usize, usize)>,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+/// A `TODO`, `FIXME`, or `HACK` marker found in a comment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Annotation {
+    /// The marker, upper-cased: `TODO`, `FIXME`, or `HACK`.
+    pub tag: String,
+    pub line: i64,
+    pub col: i64,
+    /// The rest of the comment line after the marker.
+    pub text: String,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -121,6 +241,29 @@ pub struct Entity {
     pub end_line: Option<i64>,
     pub end_col: Option<i64>,
     pub meta_json: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(flatten)]
+    pub offsets: Option<SpanOffsets>,
+}
+
+impl Entity {
+    /// Lifts `meta_json.signature` and the span offsets into their own
+    /// fields, so they survive compact responses that drop `meta_json`.
+    pub fn with_meta_fields(mut self) -> Self {
+        let meta = self
+            .meta_json
+            .as_deref()
+            .and_then(|meta| serde_json::from_str::<serde_json::Value>(meta).ok());
+        if let Some(meta) = meta {
+            self.signature = meta
+                .get("signature")
+                .and_then(|value| value.as_str())
+                .map(str::to_string);
+            self.offsets = SpanOffsets::from_meta(&meta);
+        }
+        self
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -135,19 +278,96 @@ pub struct SymbolLocation {
     pub end_col: Option<i64>,
     pub kind: String,
     pub qualname: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<String>,
+    #[serde(flatten)]
+    pub offsets: Option<SpanOffsets>,
+}
+
+/// Hex digest of a row's identifying fields, the same for the same row in
+/// every response, so rows from overlapping queries can be deduplicated.
+fn stable_row_id(parts: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part.as_bytes());
+        hasher.update([0x1f]);
+    }
+    hasher.finalize()[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct ReferenceLocation {
+    /// Stable id of this reference: a hash of its file, position, edge type,
+    /// and name. See [`ReferenceLocation::with_row_id`].
+    pub row_id: String,
     pub symbol_name: String,
     pub file_path: String,
     pub line: i64,
     pub col: i64,
+    /// Where the referenced identifier ends, in the same units as
+    /// `line`/`col`. Missing for edges stored before ends were recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_col: Option<i64>,
     pub edge_type: String,
+    /// Key of the definition this reference was bound to, when resolvable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved_symbol_key: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub score: Option<f64>,
+    /// How likely, from 0 to 1, this reference means the queried symbol
+    /// rather than an unrelated one with the same name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub why: Option<String>,
+    /// How the edge was derived; see [`EdgeOrigin`]. Missing for edges
+    /// stored before origins were recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    #[serde(flatten)]
+    pub offsets: Option<SpanOffsets>,
+}
+
+impl ReferenceLocation {
+    pub fn with_row_id(mut self) -> Self {
+        self.row_id = stable_row_id(&[
+            &self.file_path,
+            &self.line.to_string(),
+            &self.col.to_string(),
+            &self.edge_type,
+            &self.symbol_name,
+        ]);
+        self
+    }
+}
+
+/// References to a symbol from inside one definition, as returned by
+/// `group_by: enclosing_symbol`.
+#[derive(Debug, Clone, Serialize)]
+pub struct EnclosingSymbolGroup {
+    pub file_path: String,
+    /// Qualified name of the innermost definition containing the references;
+    /// `None` for file-level code outside any definition.
+    pub enclosing_symbol: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<i64>,
+    pub count: usize,
+    pub calls: usize,
+    /// Reference lines inside this definition, ascending.
+    pub lines: Vec<i64>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -156,11 +376,31 @@ pub struct DependencyPath {
     pub hops: Vec<PathHop>,
 }
 
+/// Up to K distinct dependency paths between two entities, shortest first.
+#[derive(Debug, Clone, Serialize)]
+pub struct DependencyPaths {
+    pub found: bool,
+    pub paths: Vec<Vec<PathHop>>,
+    /// The search hit its step limit or the deadline before it had explored
+    /// every path up to `max_depth`; longer alternatives may be missing.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct PathHop {
     pub entity_key: String,
     pub entity_name: String,
     pub entity_type: String,
+    /// Edge followed from the previous hop into this one; unset on the first
+    /// hop.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edge_type: Option<String>,
+    /// Where that edge was recorded, e.g. the import or call site.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edge_file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edge_line: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -169,8 +409,44 @@ pub struct SliceResult {
     pub neighbors: Vec<RelatedEdge>,
 }
 
+/// How an edge got into the graph, stored with it as `origin` next to a
+/// `confidence` from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeOrigin {
+    /// Read directly off the syntax tree: definitions, references, calls,
+    /// imports, and type relations.
+    Ast,
+    /// An import statement matched to the repo file it loads.
+    ImportResolution,
+    /// Inferred from naming or layout, e.g. a test linked to the names it
+    /// uses or a file assigned to the package whose directory holds it.
+    Heuristic,
+}
+
+impl EdgeOrigin {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ast => "ast",
+            Self::ImportResolution => "import-resolution",
+            Self::Heuristic => "heuristic",
+        }
+    }
+
+    /// Confidence recorded for edges of this origin.
+    pub fn confidence(self) -> f64 {
+        match self {
+            Self::Ast => 1.0,
+            Self::ImportResolution => 0.9,
+            Self::Heuristic => 0.6,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct RelatedEdge {
+    /// Stable id of this neighbor: a hash of the entity's key and location
+    /// plus the edge type and direction. See [`RelatedEdge::with_row_id`].
+    pub row_id: String,
     pub edge_type: String,
     pub direction: String,
     pub entity: Entity,
@@ -180,6 +456,100 @@ pub struct RelatedEdge {
     pub score: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub why: Option<String>,
+    /// How the edge was derived; see [`EdgeOrigin`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    /// The edge's stored confidence, from 0 to 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+}
+
+impl RelatedEdge {
+    pub fn with_row_id(mut self) -> Self {
+        let entity = &self.entity;
+        self.row_id = stable_row_id(&[
+            entity.file_path.as_deref().unwrap_or_default(),
+            &entity.line.unwrap_or_default().to_string(),
+            &entity.col.unwrap_or_default().to_string(),
+            &self.edge_type,
+            &self.direction,
+            &entity.key,
+        ]);
+        self
+    }
+}
+
+/// Direct graph neighborhood of one entity, grouped by edge type.
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolNeighbors {
+    pub entity: Entity,
+    pub outgoing: Vec<NeighborGroup>,
+    pub incoming: Vec<NeighborGroup>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NeighborGroup {
+    pub edge_type: String,
+    /// Edges of this type, which can exceed `neighbors.len()`.
+    pub count: i64,
+    /// Distinct entities on the other end.
+    pub distinct: i64,
+    pub neighbors: Vec<Neighbor>,
+}
+
+/// The entity on the other end of an edge. `file_path`/`line` are where the
+/// edge was recorded (a call site, an import line), falling back to the
+/// entity's own location.
+#[derive(Debug, Clone, Serialize)]
+pub struct Neighbor {
+    pub key: String,
+    pub name: String,
+    pub entity_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<i64>,
+}
+
+/// One winnowed k-gram: its hash, the token span it covers, and the 1-based
+/// lines that span starts and ends on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub hash: i64,
+    pub span_start: i64,
+    pub span_end: i64,
+    pub start_line: i64,
+    pub end_line: i64,
+}
+
+/// Git history for a line range, as returned by `symbol_history`.
+#[derive(Debug, Clone, Serialize)]
+pub struct LineHistory {
+    pub file_path: String,
+    pub start_line: i64,
+    pub end_line: i64,
+    /// Commits that touched the range, newest first.
+    pub commits: Vec<HistoryCommit>,
+    /// Who last changed the range's current lines, most lines first.
+    pub authors: Vec<HistoryAuthor>,
+    pub uncommitted_lines: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryCommit {
+    pub commit: String,
+    pub author: String,
+    /// Author date in ISO 8601 with the author's offset.
+    pub date: String,
+    pub summary: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryAuthor {
+    pub author: String,
+    pub lines: usize,
+    /// Most recent author date among this author's lines, in UTC.
+    pub last_changed: String,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -187,12 +557,35 @@ pub struct CloneMatch {
     pub other_file: String,
     pub shared_fingerprints: i64,
     pub similarity: f64,
+    /// Language the other file was indexed as.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Line ranges covered by shared fingerprints, merged where they touch,
+    /// so callers can jump to the duplicated block in either file.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub regions: Vec<CloneRegion>,
+    /// Set only when they differ from the queried file's.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint_params: Option<String>,
+}
+
+/// A duplicated block: `start_line..=end_line` in the queried file matches
+/// `other_start_line..=other_end_line` in the other file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CloneRegion {
+    pub start_line: i64,
+    pub end_line: i64,
+    pub other_start_line: i64,
+    pub other_end_line: i64,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct TopFileSummary {
     pub file_path: String,
     pub count: i64,
+    /// Owners from `CODEOWNERS`, to know who to consult about a hotspot.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub owners: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -204,6 +597,9 @@ pub struct SelectorSuggestion {
     pub file_path: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub line: Option<i64>,
+    /// Edges touching the entity; a symbol also counts the uses of its name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub degree: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub score: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -217,3 +613,312 @@ pub struct CloneHotspot {
     pub avg_similarity: f64,
     pub max_similarity: f64,
 }
+
+/// Two files, one under each compared directory, that share fingerprints.
+#[derive(Debug, Clone, Serialize)]
+pub struct DirClonePair {
+    pub file: String,
+    pub other_file: String,
+    pub shared_fingerprints: i64,
+    pub similarity: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SemanticMatch {
+    pub kind: String,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub file_path: String,
+    pub start_line: i64,
+    pub end_line: i64,
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutlineEntry {
+    pub name: String,
+    pub kind: String,
+    pub qualname: String,
+    pub line: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// Definitions whose span encloses this one; 0 at the top level.
+    pub depth: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileOutline {
+    pub path: String,
+    pub language: String,
+    pub entries: Vec<OutlineEntry>,
+}
+
+/// One file's role in the graph at a glance: what it defines, how it is
+/// wired to the rest of the repo, and whether it duplicates another file.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileProfile {
+    pub file_path: String,
+    pub language: String,
+    pub is_test: bool,
+    /// The file's header marks it as generated code.
+    pub is_generated: bool,
+    pub line_count: Option<i64>,
+    pub size_bytes: i64,
+    pub indexed_at: String,
+    /// Definition counts keyed by kind, e.g. `function_item`.
+    pub definitions: BTreeMap<String, i64>,
+    /// Import statements in this file.
+    pub imports_out: i64,
+    /// Repo files that import this one.
+    pub imports_in: i64,
+    /// Call sites in this file.
+    pub calls_out: i64,
+    /// Call sites in other files to names this file defines.
+    pub calls_in: i64,
+    /// Distinct files those incoming call sites are in.
+    pub caller_files: i64,
+    pub max_clone_similarity: Option<f64>,
+    /// The file `max_clone_similarity` was measured against.
+    pub most_similar_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DirOutline {
+    pub directory: String,
+    pub files: Vec<FileOutline>,
+    pub total_files: usize,
+    pub total_entries: usize,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleDependency {
+    pub from: String,
+    pub to: String,
+    pub edge_count: i64,
+}
+
+/// File `depends_on` edges aggregated into directory-level modules.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleDependencies {
+    pub level: usize,
+    pub modules: Vec<String>,
+    pub edges: Vec<ModuleDependency>,
+    /// `matrix[i][j]` counts edges from `modules[i]` to `modules[j]`.
+    pub matrix: Vec<Vec<i64>>,
+    pub internal_edges: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageNode {
+    pub dir: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub files: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageDependencies {
+    pub packages: Vec<PackageNode>,
+    pub edges: Vec<ModuleDependency>,
+    pub internal_edges: i64,
+    /// Edges with an end outside every package.
+    pub unpackaged_edges: i64,
+}
+
+/// Which way [`ImportGraph`] walks import edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportDirection {
+    /// Everything the file pulls in.
+    Out,
+    /// Everything that pulls the file in.
+    In,
+}
+
+impl ImportDirection {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "out" => Some(Self::Out),
+            "in" => Some(Self::In),
+            _ => None,
+        }
+    }
+}
+
+/// Files reached from one file through resolved imports, breadth first.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportGraph {
+    pub file_path: String,
+    pub direction: ImportDirection,
+    pub max_depth: usize,
+    pub rows: Vec<TransitiveImport>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TransitiveImport {
+    /// Imported or importing file, or the module name of an import that did
+    /// not resolve to an indexed file.
+    pub target: String,
+    /// `file`, or `module` for unresolved imports (never walked further).
+    pub kind: &'static str,
+    /// Import hops from the queried file; each target appears once, at its
+    /// shallowest depth.
+    pub depth: usize,
+    /// The file one hop closer to the queried file.
+    pub from: String,
+    /// Module name as written in the import statement.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub via: Option<String>,
+}
+
+/// A stored `annotation` entity: a marker comment and where it is.
+#[derive(Debug, Clone, Serialize)]
+pub struct AnnotationRow {
+    pub tag: String,
+    pub file_path: String,
+    pub line: i64,
+    pub col: i64,
+    pub text: String,
+}
+
+/// One `imports` edge into a module entity.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleImporter {
+    pub file_path: String,
+    pub line: i64,
+    pub col: i64,
+    /// The module name as written at this import; longer than the queried
+    /// name for submodule matches.
+    pub module: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImporterDirectory {
+    pub directory: String,
+    pub files: usize,
+    pub imports: usize,
+}
+
+/// Files importing a module, from its `imports` edges, with per-directory
+/// totals (most imports first).
+#[derive(Debug, Clone, Serialize)]
+pub struct WhoImports {
+    pub module: String,
+    pub importers: Vec<ModuleImporter>,
+    pub directories: Vec<ImporterDirectory>,
+    pub total_files: usize,
+}
+
+/// Indexed file, line, and symbol counts for one language.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanguageCoverage {
+    pub language: String,
+    pub files: i64,
+    pub lines: i64,
+    pub definitions: i64,
+    pub references: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntryPoint {
+    /// `cargo_bin`, `npm_bin`, `npm_main`, `npm_script`, `main_function`, or
+    /// `entry_file`.
+    pub kind: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    /// Line of the `main` function inside `file_path`, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub declared_in: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    pub calls: Vec<String>,
+}
+
+/// A directory documented by a `README.md` or rooted by a `mod.rs`, stored
+/// as a `package` entity that `contains` its files and nested packages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageInfo {
+    /// Repo-relative directory, `.` for the repo root.
+    pub dir: String,
+    pub anchor: String,
+    pub name: Option<String>,
+    pub title: Option<String>,
+    pub summary: Option<String>,
+    /// Files whose nearest package this is.
+    pub files: Vec<String>,
+    /// Directories of packages nested directly inside this one.
+    pub packages: Vec<String>,
+}
+
+/// A tracked file, as listed by the MCP `resources/list` method.
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexedFile {
+    pub path: String,
+    pub language: String,
+    pub size_bytes: i64,
+}
+
+/// One node captured by a `lumora.ast_search` tree-sitter query.
+#[derive(Debug, Clone, Serialize)]
+pub struct AstCapture {
+    pub file_path: String,
+    pub language: String,
+    /// Capture name from the query, without the `@`.
+    pub capture: String,
+    pub node_kind: String,
+    pub start_line: i64,
+    pub start_col: i64,
+    pub end_line: i64,
+    pub end_col: i64,
+    pub text: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub text_truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AstSearchResult {
+    pub matches: Vec<AstCapture>,
+    pub files_scanned: usize,
+    /// Languages the query compiled for; files in other languages are
+    /// skipped.
+    pub languages: Vec<String>,
+    /// Languages whose grammar rejected the query, with the reason.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped_languages: Vec<SkippedLanguage>,
+    /// Stopped at `max_results` or the deadline; more matches may exist.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedLanguage {
+    pub language: String,
+    pub error: String,
+}
+
+/// Discovered files no grammar or config rule picked up, grouped by extension.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnsupportedExtension {
+    pub extension: String,
+    pub files: usize,
+    pub examples: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LanguageReport {
+    pub languages: Vec<LanguageCoverage>,
+    pub indexed_files: i64,
+    pub unsupported_files: usize,
+    pub unsupported: Vec<UnsupportedExtension>,
+}
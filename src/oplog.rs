@@ -0,0 +1,621 @@
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::error::{coded, ErrorCode};
+use crate::fileops;
+use crate::indexer::sha256_hex;
+use crate::paths::RuntimePaths;
+
+pub const OPLOG_FILE_NAME: &str = "oplog.jsonl";
+
+const ROTATED_OPLOG_FILE_NAME: &str = "oplog.1.jsonl";
+
+const OPLOG_ROTATE_BYTES: u64 = 8 * 1024 * 1024;
+
+const TAIL_BLOCK_BYTES: u64 = 64 * 1024;
+
+/// Pre-images named by sha256.
+const UNDO_DIR_NAME: &str = "undo";
+
+const UNDO_CACHE_MAX_ENTRIES: usize = 100;
+
+const UNDO_CACHE_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+const MAX_LOGGED_ARG_CHARS: usize = 2_000;
+
+pub const REVERT_TOOL: &str = "oplog.revert";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OplogEntry {
+    pub id: u64,
+    /// Milliseconds since the unix epoch.
+    pub at_ms: u64,
+    pub tool: String,
+    pub args: Value,
+    pub files: Vec<OplogFile>,
+    /// sha256 over every file's path and before/after hashes.
+    pub diff_hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reverts: Option<u64>,
+}
+
+/// One file an entry touched. A `None` hash means the file did not exist.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OplogFile {
+    pub path: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// A change whose pre-images are saved but whose result is not logged yet.
+#[derive(Debug)]
+pub struct PendingOp {
+    tool: String,
+    args: Value,
+    files: Vec<(String, Option<String>)>,
+}
+
+pub fn oplog_path(paths: &RuntimePaths) -> PathBuf {
+    paths.state_dir.join(OPLOG_FILE_NAME)
+}
+
+fn rotated_oplog_path(paths: &RuntimePaths) -> PathBuf {
+    paths.state_dir.join(ROTATED_OPLOG_FILE_NAME)
+}
+
+fn blob_dir(paths: &RuntimePaths) -> PathBuf {
+    paths.state_dir.join(UNDO_DIR_NAME)
+}
+
+/// Saves the current contents of `targets` before a tool changes them.
+pub fn begin(
+    paths: &RuntimePaths,
+    tool: &str,
+    args: &Value,
+    targets: &[String],
+) -> Result<PendingOp> {
+    let mut files: Vec<(String, Option<String>)> = Vec::new();
+    for target in targets {
+        let Some(path) = repo_relative(&paths.repo_root, target) else {
+            continue;
+        };
+        if files.iter().any(|(seen, _)| *seen == path) {
+            continue;
+        }
+        let before = save_blob(paths, &paths.repo_root.join(&path))?;
+        files.push((path, before));
+    }
+    Ok(PendingOp {
+        tool: tool.to_string(),
+        args: elide_long_strings(args),
+        files,
+    })
+}
+
+impl PendingOp {
+    /// Appends the entry unless the tool left every file as it was.
+    pub fn commit(self, paths: &RuntimePaths) -> Result<Option<OplogEntry>> {
+        let files = with_after_hashes(paths, self.files)?;
+        if files.iter().all(|file| file.before == file.after) {
+            return Ok(None);
+        }
+        append(paths, self.tool, self.args, files, None).map(Some)
+    }
+}
+
+/// Every entry still in the journal, oldest first.
+pub fn read_entries(paths: &RuntimePaths) -> Result<Vec<OplogEntry>> {
+    let mut entries = read_log(&rotated_oplog_path(paths))?;
+    entries.extend(read_log(&oplog_path(paths))?);
+    Ok(entries)
+}
+
+fn read_log(path: &Path) -> Result<Vec<OplogEntry>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", path.display())),
+    };
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(index, line)| {
+            serde_json::from_str(line).with_context(|| {
+                format!(
+                    "{}: line {} is not an oplog entry",
+                    path.display(),
+                    index + 1
+                )
+            })
+        })
+        .collect()
+}
+
+fn recent_entries(paths: &RuntimePaths, count: usize) -> Result<Vec<OplogEntry>> {
+    let mut entries = read_log_tail(&oplog_path(paths), count)?;
+    if entries.len() < count {
+        let mut older = read_log_tail(&rotated_oplog_path(paths), count - entries.len())?;
+        older.append(&mut entries);
+        entries = older;
+    }
+    Ok(entries)
+}
+
+/// Reads backwards from the end of `path` until it holds `count` whole lines.
+fn read_log_tail(path: &Path, count: usize) -> Result<Vec<OplogEntry>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let read_error = || format!("failed to read {}", path.display());
+    let mut start = file.metadata().with_context(read_error)?.len();
+    let mut tail: Vec<u8> = Vec::new();
+    let mut newlines = 0;
+    // One newline more than `count`, so the first line kept is whole.
+    while start > 0 && newlines <= count {
+        let step = TAIL_BLOCK_BYTES.min(start);
+        start -= step;
+        let mut block = vec![0; step as usize];
+        file.seek(SeekFrom::Start(start)).with_context(read_error)?;
+        file.read_exact(&mut block).with_context(read_error)?;
+        newlines += block.iter().filter(|&&byte| byte == b'\n').count();
+        block.extend_from_slice(&tail);
+        tail = block;
+    }
+    if start > 0 {
+        let first_newline = tail.iter().position(|&byte| byte == b'\n').unwrap_or(0);
+        tail.drain(..=first_newline);
+    }
+    let text = String::from_utf8(tail)
+        .with_context(|| format!("{} is not valid UTF-8", path.display()))?;
+    let lines: Vec<&str> = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    lines[lines.len().saturating_sub(count)..]
+        .iter()
+        .map(|line| {
+            serde_json::from_str(line).with_context(|| {
+                format!(
+                    "{}: a line near the end is not an oplog entry",
+                    path.display()
+                )
+            })
+        })
+        .collect()
+}
+
+/// The newest entry that is neither a revert nor reverted already.
+pub fn last_undoable(paths: &RuntimePaths) -> Result<Option<u64>> {
+    let entries = read_entries(paths)?;
+    Ok(entries
+        .iter()
+        .rev()
+        .find(|entry| {
+            entry.reverts.is_none() && !entries.iter().any(|other| other.reverts == Some(entry.id))
+        })
+        .map(|entry| entry.id))
+}
+
+/// Restores the files entry `id` touched and logs that as a new entry.
+pub fn revert(paths: &RuntimePaths, id: u64, force: bool) -> Result<OplogEntry> {
+    let entries = read_entries(paths)?;
+    let entry = entries
+        .iter()
+        .find(|entry| entry.id == id)
+        .ok_or_else(|| coded(ErrorCode::InvalidParams, format!("no oplog entry #{id}")))?;
+    if let Some(undo) = entries.iter().find(|other| other.reverts == Some(id)) {
+        return Err(coded(
+            ErrorCode::EditConflict,
+            format!("oplog entry #{id} was already reverted by #{}", undo.id),
+        ));
+    }
+
+    let mut restores = Vec::new();
+    for file in &entry.files {
+        let path = paths.repo_root.join(&file.path);
+        let current = file_hash(&path)?;
+        if current != file.after && !force {
+            return Err(coded(
+                ErrorCode::EditConflict,
+                format!(
+                    "`{}` changed after oplog entry #{id}; pass --force to overwrite it",
+                    file.path
+                ),
+            ));
+        }
+        let content = match &file.before {
+            Some(hash) => match fs::read(blob_dir(paths).join(hash)) {
+                Ok(content) => Some(content),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    return Err(coded(
+                        ErrorCode::FileNotFound,
+                        format!(
+                        "the undo cache no longer holds `{}` as it was before oplog entry #{id}",
+                        file.path
+                    ),
+                    ))
+                }
+                Err(err) => return Err(err).context("failed to read the undo cache"),
+            },
+            None => None,
+        };
+        restores.push((file, path, content));
+    }
+
+    let targets: Vec<String> = entry.files.iter().map(|file| file.path.clone()).collect();
+    let pending = begin(
+        paths,
+        REVERT_TOOL,
+        &json!({ "id": id, "force": force }),
+        &targets,
+    )?;
+    for (file, path, content) in restores {
+        match content {
+            Some(content) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)
+                        .with_context(|| format!("failed to create {}", parent.display()))?;
+                }
+                fs::write(&path, content)
+                    .with_context(|| format!("failed to restore {}", file.path))?;
+            }
+            None if path.is_file() => {
+                fs::remove_file(&path)
+                    .with_context(|| format!("failed to remove {}", file.path))?;
+            }
+            None => {}
+        }
+    }
+    let files = with_after_hashes(paths, pending.files)?;
+    append(paths, pending.tool, pending.args, files, Some(id))
+}
+
+fn with_after_hashes(
+    paths: &RuntimePaths,
+    files: Vec<(String, Option<String>)>,
+) -> Result<Vec<OplogFile>> {
+    files
+        .into_iter()
+        .map(|(path, before)| {
+            let after = file_hash(&paths.repo_root.join(&path))?;
+            Ok(OplogFile {
+                path,
+                before,
+                after,
+            })
+        })
+        .collect()
+}
+
+fn append(
+    paths: &RuntimePaths,
+    tool: String,
+    args: Value,
+    files: Vec<OplogFile>,
+    reverts: Option<u64>,
+) -> Result<OplogEntry> {
+    let mut recent = recent_entries(paths, UNDO_CACHE_MAX_ENTRIES)?;
+    let id = recent.last().map_or(1, |last| last.id + 1);
+    let at_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default();
+    let summary: String = files
+        .iter()
+        .map(|file| {
+            format!(
+                "{}\t{}\t{}\n",
+                file.path,
+                file.before.as_deref().unwrap_or("-"),
+                file.after.as_deref().unwrap_or("-")
+            )
+        })
+        .collect();
+    let entry = OplogEntry {
+        id,
+        at_ms,
+        tool,
+        args,
+        diff_hash: sha256_hex(summary.as_bytes()),
+        files,
+        reverts,
+    };
+
+    fs::create_dir_all(&paths.state_dir)
+        .with_context(|| format!("failed to create {}", paths.state_dir.display()))?;
+    let path = oplog_path(paths);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    let mut line = serde_json::to_vec(&entry)?;
+    line.push(b'\n');
+    file.write_all(&line)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    let written = file
+        .metadata()
+        .with_context(|| format!("failed to read {}", path.display()))?
+        .len();
+    drop(file);
+    if written > OPLOG_ROTATE_BYTES {
+        rotate(paths)?;
+    }
+
+    recent.push(entry.clone());
+    prune_undo_cache(paths, &recent)?;
+    Ok(entry)
+}
+
+fn rotate(paths: &RuntimePaths) -> Result<()> {
+    let path = oplog_path(paths);
+    fs::rename(&path, rotated_oplog_path(paths))
+        .with_context(|| format!("failed to rotate {}", path.display()))
+}
+
+fn prune_undo_cache(paths: &RuntimePaths, recent: &[OplogEntry]) -> Result<()> {
+    let dir = blob_dir(paths);
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    let mut keep = HashSet::new();
+    let mut kept_bytes = 0;
+    'entries: for entry in recent.iter().rev().take(UNDO_CACHE_MAX_ENTRIES) {
+        for hash in entry.files.iter().filter_map(|file| file.before.as_deref()) {
+            if keep.contains(hash) {
+                continue;
+            }
+            let size = fs::metadata(dir.join(hash)).map_or(0, |meta| meta.len());
+            if kept_bytes + size > UNDO_CACHE_MAX_BYTES {
+                break 'entries;
+            }
+            kept_bytes += size;
+            keep.insert(hash.to_string());
+        }
+    }
+    for blob in fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))? {
+        let blob = blob?;
+        if !keep.contains(blob.file_name().to_string_lossy().as_ref()) {
+            let _ = fs::remove_file(blob.path());
+        }
+    }
+    Ok(())
+}
+
+fn save_blob(paths: &RuntimePaths, path: &Path) -> Result<Option<String>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let hash = sha256_hex(&content);
+    let dir = blob_dir(paths);
+    let blob = dir.join(&hash);
+    if !blob.exists() {
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        fs::write(&blob, &content)
+            .with_context(|| format!("failed to write {}", blob.display()))?;
+    }
+    Ok(Some(hash))
+}
+
+fn file_hash(path: &Path) -> Result<Option<String>> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(Some(sha256_hex(&content)))
+}
+
+/// A path under a directory that does not exist yet (a write with
+/// `create_dirs`) is normalized lexically.
+fn repo_relative(repo_root: &Path, user_path: &str) -> Option<String> {
+    if let Ok(resolved) = fileops::safe_resolve_path(repo_root, user_path) {
+        return fileops::to_rel_path(repo_root, &resolved).ok();
+    }
+    let mut parts = Vec::new();
+    for component in Path::new(&user_path.replace('\\', "/")).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?.to_string()),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+fn elide_long_strings(value: &Value) -> Value {
+    match value {
+        Value::String(text) if text.len() > MAX_LOGGED_ARG_CHARS => json!(format!(
+            "<{} bytes, sha256 {}>",
+            text.len(),
+            sha256_hex(text.as_bytes())
+        )),
+        Value::Array(items) => Value::Array(items.iter().map(elide_long_strings).collect()),
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .map(|(key, value)| (key.clone(), elide_long_strings(value)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// `YYYY-MM-DD HH:MM:SS` in UTC.
+pub fn format_utc(at_ms: u64) -> String {
+    let secs = at_ms / 1000;
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+    // Civil-from-days, after Howard Hinnant's date algorithms.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn test_paths() -> (RuntimePaths, TempDir) {
+        let dir = TempDir::new().unwrap();
+        let state_dir = dir.path().join(".lumora");
+        fs::create_dir_all(&state_dir).unwrap();
+        let paths = RuntimePaths {
+            repo_root: dir.path().to_path_buf(),
+            db_path: state_dir.join("graph.db"),
+            state_dir,
+        };
+        (paths, dir)
+    }
+
+    #[test]
+    fn test_revert_restores_pre_images_and_is_itself_logged() {
+        let (paths, dir) = test_paths();
+        fs::write(dir.path().join("a.txt"), "one\n").unwrap();
+
+        let pending = begin(
+            &paths,
+            "lumora.move_file",
+            &json!({ "source": "a.txt", "destination": "./b.txt" }),
+            &["a.txt".to_string(), "./b.txt".to_string()],
+        )
+        .unwrap();
+        fs::rename(dir.path().join("a.txt"), dir.path().join("b.txt")).unwrap();
+        let moved = pending.commit(&paths).unwrap().unwrap();
+        assert_eq!(moved.id, 1);
+        assert_eq!(moved.files[0].after, None);
+        assert_eq!(moved.files[1].path, "b.txt");
+        assert_eq!(moved.files[1].before, None);
+
+        fs::write(dir.path().join("b.txt"), "two\n").unwrap();
+        let err = revert(&paths, 1, false).unwrap_err();
+        assert_eq!(crate::error::error_code(&err), ErrorCode::EditConflict);
+
+        let undo = revert(&paths, 1, true).unwrap();
+        assert_eq!(undo.reverts, Some(1));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+            "one\n"
+        );
+        assert!(!dir.path().join("b.txt").exists());
+        assert!(revert(&paths, 1, true).is_err(), "already reverted");
+
+        // Reverting the revert brings back the edited destination.
+        revert(&paths, undo.id, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("b.txt")).unwrap(),
+            "two\n"
+        );
+        assert_eq!(read_entries(&paths).unwrap().len(), 3);
+        assert_eq!(last_undoable(&paths).unwrap(), None);
+    }
+
+    #[test]
+    fn test_undo_cache_keeps_only_the_newest_pre_images() {
+        let (paths, dir) = test_paths();
+        let file = dir.path().join("a.txt");
+        for round in 0..=UNDO_CACHE_MAX_ENTRIES {
+            fs::write(&file, format!("{round}\n")).unwrap();
+            let pending =
+                begin(&paths, "lumora.write_file", &json!({}), &["a.txt".into()]).unwrap();
+            fs::write(&file, format!("{round} edited\n")).unwrap();
+            pending.commit(&paths).unwrap();
+        }
+        let cached = fs::read_dir(blob_dir(&paths)).unwrap().count();
+        assert_eq!(cached, UNDO_CACHE_MAX_ENTRIES);
+        assert_eq!(
+            last_undoable(&paths).unwrap(),
+            Some(UNDO_CACHE_MAX_ENTRIES as u64 + 1)
+        );
+        let err = revert(&paths, 1, true).unwrap_err();
+        assert_eq!(crate::error::error_code(&err), ErrorCode::FileNotFound);
+    }
+
+    #[test]
+    fn test_tail_reads_only_the_newest_entries() {
+        let (paths, _dir) = test_paths();
+        let mut log = String::new();
+        for id in 1..=2_000 {
+            let entry = OplogEntry {
+                id,
+                at_ms: 0,
+                tool: "lumora.write_file".to_string(),
+                args: json!({ "path": format!("src/file_{id}.rs") }),
+                files: Vec::new(),
+                diff_hash: String::new(),
+                reverts: None,
+            };
+            log.push_str(&serde_json::to_string(&entry).unwrap());
+            log.push('\n');
+        }
+        assert!(log.len() as u64 > 2 * TAIL_BLOCK_BYTES);
+        fs::write(oplog_path(&paths), log).unwrap();
+
+        let ids = |entries: Vec<OplogEntry>| entries.iter().map(|e| e.id).collect::<Vec<_>>();
+        let tail = read_log_tail(&oplog_path(&paths), 150).unwrap();
+        assert_eq!(ids(tail), (1_851..=2_000).collect::<Vec<_>>());
+        assert_eq!(
+            read_log_tail(&oplog_path(&paths), 5_000).unwrap().len(),
+            2_000
+        );
+        assert!(read_log_tail(&paths.state_dir.join("missing.jsonl"), 1)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_rotated_journal_keeps_ids_and_history() {
+        let (paths, dir) = test_paths();
+        let file = dir.path().join("a.txt");
+        let edit = |round: usize| {
+            fs::write(&file, format!("{round}\n")).unwrap();
+            let pending =
+                begin(&paths, "lumora.write_file", &json!({}), &["a.txt".into()]).unwrap();
+            fs::write(&file, format!("{round} edited\n")).unwrap();
+            pending.commit(&paths).unwrap().unwrap().id
+        };
+        edit(1);
+        edit(2);
+        rotate(&paths).unwrap();
+        assert!(!oplog_path(&paths).exists());
+        assert_eq!(edit(3), 3);
+
+        let ids: Vec<u64> = read_entries(&paths).unwrap().iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+        let recent: Vec<u64> = recent_entries(&paths, 2)
+            .unwrap()
+            .iter()
+            .map(|e| e.id)
+            .collect();
+        assert_eq!(recent, vec![2, 3]);
+        revert(&paths, 2, true).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "2\n");
+    }
+
+    #[test]
+    fn test_formats_utc_timestamps() {
+        assert_eq!(format_utc(0), "1970-01-01 00:00:00");
+        assert_eq!(format_utc(1_709_210_096_000), "2024-02-29 12:34:56");
+    }
+}
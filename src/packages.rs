@@ -0,0 +1,289 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::model::PackageInfo;
+
+pub const CARGO_MANIFEST_FILE_NAME: &str = "Cargo.toml";
+pub const NPM_MANIFEST_FILE_NAME: &str = "package.json";
+
+/// Directory docs that make a directory a package, most descriptive first.
+const README_FILE_NAME: &str = "README.md";
+const RUST_MODULE_FILE_NAME: &str = "mod.rs";
+
+/// Longest summary kept from a package's docs.
+const MAX_SUMMARY_CHARS: usize = 280;
+
+/// Finds package directories among the parents of `paths`: any directory with
+/// a crate or npm manifest, a `README.md`, or a `mod.rs`. Each file belongs to
+/// its nearest package directory (its own or an ancestor's), and each package
+/// to its nearest enclosing one. Files outside every package are left out. The
+/// repo root, when it has a manifest or README, is the package `.`.
+pub fn discover_packages(repo_root: &Path, paths: &HashSet<String>) -> Vec<PackageInfo> {
+    let mut dirs = HashSet::new();
+    for path in paths {
+        let mut dir = parent_dir(path);
+        while dirs.insert(dir.to_string()) && !dir.is_empty() {
+            dir = parent_dir(dir);
+        }
+    }
+
+    let mut packages: BTreeMap<String, PackageInfo> = BTreeMap::new();
+    for dir in dirs {
+        let readme = join(&dir, README_FILE_NAME);
+        let module = join(&dir, RUST_MODULE_FILE_NAME);
+        let manifest = read_manifest(repo_root, &dir, paths);
+        let readme_text = fs::read_to_string(repo_root.join(&readme)).ok();
+        let has_module = paths.contains(&module);
+        if manifest.is_none() && readme_text.is_none() && !has_module {
+            continue;
+        }
+
+        let (mut anchor, mut title, mut summary) = match readme_text {
+            Some(text) => {
+                let (title, summary) = readme_summary(&text);
+                (readme, title, summary)
+            }
+            None if has_module => {
+                let summary = fs::read_to_string(repo_root.join(&module))
+                    .ok()
+                    .and_then(|text| module_doc_summary(&text));
+                (module, None, summary)
+            }
+            None => (String::new(), None, None),
+        };
+        let mut name = None;
+        if let Some(manifest) = manifest {
+            anchor = manifest.path;
+            title = title.or_else(|| Some(manifest.name.clone()));
+            summary = summary.or(manifest.description);
+            name = Some(manifest.name);
+        }
+        packages.insert(
+            dir.clone(),
+            PackageInfo {
+                dir,
+                anchor,
+                name,
+                title,
+                summary,
+                files: Vec::new(),
+                packages: Vec::new(),
+            },
+        );
+    }
+
+    let mut files: Vec<&String> = paths.iter().collect();
+    files.sort();
+    for path in files {
+        if let Some(owner) = nearest_package(&packages, parent_dir(path)) {
+            if let Some(package) = packages.get_mut(&owner) {
+                package.files.push(path.clone());
+            }
+        }
+    }
+    let dirs: Vec<String> = packages.keys().cloned().collect();
+    for dir in dirs {
+        if dir.is_empty() {
+            continue;
+        }
+        if let Some(owner) = nearest_package(&packages, parent_dir(&dir)) {
+            if let Some(package) = packages.get_mut(&owner) {
+                package.packages.push(dir);
+            }
+        }
+    }
+    packages
+        .into_values()
+        .map(|mut package| {
+            if package.dir.is_empty() {
+                package.dir = ".".to_string();
+            }
+            package
+        })
+        .collect()
+}
+
+struct Manifest {
+    path: String,
+    name: String,
+    description: Option<String>,
+}
+
+/// A virtual Cargo workspace (no `[package]`) is not a package.
+fn read_manifest(repo_root: &Path, dir: &str, paths: &HashSet<String>) -> Option<Manifest> {
+    let cargo = join(dir, CARGO_MANIFEST_FILE_NAME);
+    if paths.contains(&cargo) {
+        let text = fs::read_to_string(repo_root.join(&cargo)).ok()?;
+        let value: toml::Value = toml::from_str(&text).ok()?;
+        let package = value.get("package")?;
+        let field = |key: &str| package.get(key)?.as_str().map(str::to_string);
+        return Some(Manifest {
+            name: field("name")?,
+            description: field("description").and_then(|text| clip(&text)),
+            path: cargo,
+        });
+    }
+    let npm = join(dir, NPM_MANIFEST_FILE_NAME);
+    if paths.contains(&npm) {
+        let text = fs::read_to_string(repo_root.join(&npm)).ok()?;
+        let value: serde_json::Value = serde_json::from_str(&text).ok()?;
+        let field = |key: &str| value.get(key)?.as_str().map(str::to_string);
+        return Some(Manifest {
+            name: field("name").filter(|name| !name.is_empty())?,
+            description: field("description").and_then(|text| clip(&text)),
+            path: npm,
+        });
+    }
+    None
+}
+
+fn nearest_package(packages: &BTreeMap<String, PackageInfo>, mut dir: &str) -> Option<String> {
+    loop {
+        if packages.contains_key(dir) {
+            return Some(dir.to_string());
+        }
+        if dir.is_empty() {
+            return None;
+        }
+        dir = parent_dir(dir);
+    }
+}
+
+/// `src/storage/mod.rs` -> `src/storage`; top-level paths -> `""`.
+pub fn parent_dir(path: &str) -> &str {
+    path.rfind('/').map_or("", |index| &path[..index])
+}
+
+pub fn join(dir: &str, file_name: &str) -> String {
+    if dir.is_empty() {
+        file_name.to_string()
+    } else {
+        format!("{dir}/{file_name}")
+    }
+}
+
+/// The first heading and the first prose paragraph of a README. Badges,
+/// HTML, and code blocks are skipped.
+fn readme_summary(text: &str) -> (Option<String>, Option<String>) {
+    let mut title = None;
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut in_code = false;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        if let Some(heading) = line.strip_prefix('#') {
+            if !paragraph.is_empty() {
+                break;
+            }
+            if title.is_none() {
+                title = Some(heading.trim_start_matches('#').trim().to_string());
+            }
+            continue;
+        }
+        if line.is_empty() {
+            if !paragraph.is_empty() {
+                break;
+            }
+            continue;
+        }
+        if line.starts_with('<') || line.starts_with("[![") || line.starts_with("![") {
+            continue;
+        }
+        paragraph.push(line);
+    }
+    let title = title.filter(|title| !title.is_empty());
+    (title, clip(&paragraph.join(" ")))
+}
+
+/// The first paragraph of a Rust module's `//!` docs.
+fn module_doc_summary(text: &str) -> Option<String> {
+    let mut paragraph = Vec::new();
+    for line in text.lines() {
+        let Some(doc) = line.trim().strip_prefix("//!") else {
+            break;
+        };
+        let doc = doc.trim();
+        if doc.is_empty() {
+            if paragraph.is_empty() {
+                continue;
+            }
+            break;
+        }
+        paragraph.push(doc);
+    }
+    clip(&paragraph.join(" "))
+}
+
+fn clip(text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    if text.chars().count() <= MAX_SUMMARY_CHARS {
+        return Some(text.to_string());
+    }
+    let clipped: String = text.chars().take(MAX_SUMMARY_CHARS - 3).collect();
+    Some(format!("{}...", clipped.trim_end()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn readme_and_mod_rs_directories_become_nested_packages() {
+        let dir = TempDir::new().unwrap();
+        let write = |path: &str, text: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        };
+        write(
+            "services/billing/README.md",
+            "# Billing\n\n[![ci](badge.svg)](ci)\n\nCharges customers\nmonthly.\n\nMore.\n",
+        );
+        write("services/billing/src/lib.rs", "");
+        write(
+            "services/billing/src/store/mod.rs",
+            "//! Invoice storage.\n\nmod x;\n",
+        );
+        write("services/billing/src/store/sql.rs", "");
+        write("tools/run.py", "");
+
+        let paths: HashSet<String> = [
+            "services/billing/src/lib.rs",
+            "services/billing/src/store/mod.rs",
+            "services/billing/src/store/sql.rs",
+            "tools/run.py",
+        ]
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+        let packages = discover_packages(dir.path(), &paths);
+        assert_eq!(packages.len(), 2);
+
+        let billing = &packages[0];
+        assert_eq!(billing.dir, "services/billing");
+        assert_eq!(billing.anchor, "services/billing/README.md");
+        assert_eq!(billing.title.as_deref(), Some("Billing"));
+        assert_eq!(
+            billing.summary.as_deref(),
+            Some("Charges customers monthly.")
+        );
+        assert_eq!(billing.files, ["services/billing/src/lib.rs"]);
+        assert_eq!(billing.packages, ["services/billing/src/store"]);
+
+        let store = &packages[1];
+        assert_eq!(store.anchor, "services/billing/src/store/mod.rs");
+        assert_eq!(store.summary.as_deref(), Some("Invoice storage."));
+        assert_eq!(store.files.len(), 2);
+    }
+}
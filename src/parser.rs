@@ -1,11 +1,24 @@
-use std::collections::HashSet;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context, Result};
-use tree_sitter::{Language, Node, Parser, Query, QueryCursor};
+use tree_sitter::{InputEdit, Language, Node, Parser, Point, Query, QueryCursor, Tree};
 
 use crate::languages::{detect_language_from_ext, get_config};
-use crate::model::{Definition, FileExtraction, Import, LanguageKind, Reference, ReferenceKind};
+use crate::model::{
+    Annotation, Arity, AstCapture, Definition, FileExtraction, Import, LanguageKind, Reference,
+    ReferenceKind, Relation, RelationKind, SpanOffsets,
+};
+
+/// Signatures longer than this (usually long parameter lists) are cut with `...`.
+const MAX_SIGNATURE_CHARS: usize = 240;
+
+/// Hot files whose last tree is kept for incremental reparsing.
+pub const TREE_CACHE_CAPACITY: usize = 32;
+
+/// Changes spanning more bytes than this get a full parse; past that size
+/// tree-sitter would re-lex most of the file anyway.
+const MAX_INCREMENTAL_EDIT_BYTES: usize = 64 * 1024;
 
 pub fn detect_language(path: &Path) -> Option<LanguageKind> {
     let ext = path.extension().and_then(|item| item.to_str())?;
@@ -16,7 +29,139 @@ pub fn parse_file(path: &Path, source: &str) -> Result<Option<FileExtraction>> {
     let Some(language) = detect_language(path) else {
         return Ok(None);
     };
+    let (tree, _) = parse_tree(path, source, language, None)?;
+    extract_tree(&tree, source, language).map(Some)
+}
+
+/// Last parsed tree and source of recently changed files, so a small edit to
+/// a large file can be reparsed incrementally. Least recently used entries
+/// are dropped past [`TREE_CACHE_CAPACITY`].
+#[derive(Default)]
+pub struct TreeCache {
+    entries: HashMap<PathBuf, CachedTree>,
+    tick: u64,
+}
+
+struct CachedTree {
+    language: LanguageKind,
+    source: String,
+    tree: Tree,
+    used: u64,
+}
+
+impl TreeCache {
+    fn insert(&mut self, path: &Path, language: LanguageKind, source: &str, tree: Tree) {
+        self.tick += 1;
+        if !self.entries.contains_key(path) && self.entries.len() >= TREE_CACHE_CAPACITY {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(path, _)| path.clone())
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(
+            path.to_path_buf(),
+            CachedTree {
+                language,
+                source: source.to_string(),
+                tree,
+                used: self.tick,
+            },
+        );
+    }
+}
+
+/// Like [`parse_file`], but reuses the tree cached for `path` when the change
+/// since it is small, and caches the new tree. The flag is true when the
+/// parse was incremental.
+pub fn parse_file_cached(
+    path: &Path,
+    source: &str,
+    trees: &mut TreeCache,
+) -> Result<Option<(FileExtraction, bool)>> {
+    let Some(language) = detect_language(path) else {
+        return Ok(None);
+    };
+    let old_tree = trees
+        .entries
+        .remove(path)
+        .filter(|entry| entry.language == language)
+        .and_then(|entry| {
+            let edit = source_edit(&entry.source, source)?;
+            let mut tree = entry.tree;
+            tree.edit(&edit);
+            Some(tree)
+        });
+    let (tree, incremental) = parse_tree(path, source, language, old_tree.as_ref())?;
+    let extraction = extract_tree(&tree, source, language)?;
+    trees.insert(path, language, source, tree);
+    Ok(Some((extraction, incremental)))
+}
+
+/// Compiles a user-supplied tree-sitter query against `language`'s grammar.
+pub fn compile_query(language: LanguageKind, query_src: &str) -> Result<Query> {
+    let config = get_config(language)
+        .ok_or_else(|| anyhow!("no config registered for language {:?}", language))?;
+    Query::new(&config.grammar, query_src).map_err(|err| anyhow!("{err}"))
+}
+
+/// Every node captured by `query` in `source`, in document order. A node
+/// captured under the same name by several matches is listed once.
+pub fn query_captures(
+    path: &Path,
+    source: &str,
+    language: LanguageKind,
+    query: &Query,
+    max_text_chars: usize,
+) -> Result<Vec<AstCapture>> {
+    let (tree, _) = parse_tree(path, source, language, None)?;
+    let capture_names = query.capture_names();
+    let file_path = path.to_string_lossy().replace('\\', "/");
+    let mut seen = HashSet::new();
+    let mut captures = Vec::new();
+    let mut cursor = QueryCursor::new();
+    for query_match in cursor.matches(query, tree.root_node(), source.as_bytes()) {
+        for capture in query_match.captures {
+            let node = capture.node;
+            if !seen.insert((node.id(), capture.index)) {
+                continue;
+            }
+            let raw = node.utf8_text(source.as_bytes()).unwrap_or_default();
+            let text_truncated = raw.chars().count() > max_text_chars;
+            let text = if text_truncated {
+                raw.chars().take(max_text_chars).collect()
+            } else {
+                raw.to_string()
+            };
+            let start = node.start_position();
+            let end = node.end_position();
+            captures.push(AstCapture {
+                file_path: file_path.clone(),
+                language: language.as_str().to_string(),
+                capture: capture_names[capture.index as usize].to_string(),
+                node_kind: node.kind().to_string(),
+                start_line: start.row as i64 + 1,
+                start_col: start.column as i64 + 1,
+                end_line: end.row as i64 + 1,
+                end_col: end.column as i64 + 1,
+                text,
+                text_truncated,
+            });
+        }
+    }
+    captures.sort_by_key(|capture| (capture.start_line, capture.start_col));
+    Ok(captures)
+}
 
+fn parse_tree(
+    path: &Path,
+    source: &str,
+    language: LanguageKind,
+    old_tree: Option<&Tree>,
+) -> Result<(Tree, bool)> {
     let config = get_config(language)
         .ok_or_else(|| anyhow!("no config registered for language {:?}", language))?;
 
@@ -26,28 +171,174 @@ pub fn parse_file(path: &Path, source: &str) -> Result<Option<FileExtraction>> {
         .context("failed to load grammar")?;
 
     let tree = parser
-        .parse(source, None)
+        .parse(source, old_tree)
         .with_context(|| format!("failed to parse {}", path.display()))?;
+    Ok((tree, old_tree.is_some()))
+}
 
-    let (definitions, references, imports) = extract_with_query(
+fn extract_tree(tree: &Tree, source: &str, language: LanguageKind) -> Result<FileExtraction> {
+    let config = get_config(language)
+        .ok_or_else(|| anyhow!("no config registered for language {:?}", language))?;
+    let mut extraction = extract_with_query(
         &config.grammar,
         config.tags_query,
         tree.root_node(),
         source,
         language,
     )?;
+    extraction.noise_spans = collect_noise_spans(tree.root_node(), language);
+    extraction.annotations = collect_annotations(tree.root_node(), source);
+    Ok(extraction)
+}
+
+/// The single edit turning `old` into `new`: everything between their common
+/// prefix and common suffix. `None` when that span is too large to be worth
+/// an incremental parse.
+fn source_edit(old: &str, new: &str) -> Option<InputEdit> {
+    let (old_bytes, new_bytes) = (old.as_bytes(), new.as_bytes());
+    let prefix = old_bytes
+        .iter()
+        .zip(new_bytes)
+        .take_while(|(left, right)| left == right)
+        .count();
+    let suffix = old_bytes[prefix..]
+        .iter()
+        .rev()
+        .zip(new_bytes[prefix..].iter().rev())
+        .take_while(|(left, right)| left == right)
+        .count();
+    let old_end = old_bytes.len() - suffix;
+    let new_end = new_bytes.len() - suffix;
+    if (old_end - prefix).max(new_end - prefix) > MAX_INCREMENTAL_EDIT_BYTES {
+        return None;
+    }
+    Some(InputEdit {
+        start_byte: prefix,
+        old_end_byte: old_end,
+        new_end_byte: new_end,
+        start_position: byte_point(old_bytes, prefix),
+        old_end_position: byte_point(old_bytes, old_end),
+        new_end_position: byte_point(new_bytes, new_end),
+    })
+}
 
-    Ok(Some(FileExtraction {
+/// Row and byte column of `byte` in `text`, as tree-sitter counts them.
+fn byte_point(text: &[u8], byte: usize) -> Point {
+    let before = &text[..byte];
+    let row = before.iter().filter(|&&item| item == b'\n').count();
+    let line_start = before
+        .iter()
+        .rposition(|&item| item == b'\n')
+        .map_or(0, |index| index + 1);
+    Point::new(row, byte - line_start)
+}
+
+/// Collects byte ranges of comments and string literals. Data languages keep
+/// their strings, since those are the content rather than noise.
+fn collect_noise_spans(root: Node<'_>, language: LanguageKind) -> Vec<(usize, usize)> {
+    let keep_strings = matches!(
         language,
-        definitions,
-        references,
-        imports,
-    }))
+        LanguageKind::Json | LanguageKind::Toml | LanguageKind::Yaml
+    );
+    let mut spans = Vec::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        let kind = node.kind();
+        let is_comment = kind.contains("comment");
+        let is_string = !keep_strings
+            && (kind.ends_with("string")
+                || kind.ends_with("string_literal")
+                || kind == "char_literal"
+                || kind == "character_literal");
+        if node.is_named() && (is_comment || is_string) {
+            spans.push((node.start_byte(), node.end_byte()));
+            continue;
+        }
+        for index in (0..node.child_count()).rev() {
+            if let Some(child) = node.child(index) {
+                stack.push(child);
+            }
+        }
+    }
+    spans
+}
+
+/// Finds `TODO`, `FIXME`, and `HACK` markers inside comment nodes, at most
+/// one per comment line. Markers in strings or code are not comments, so
+/// the grammar rules them out.
+fn collect_annotations(root: Node<'_>, source: &str) -> Vec<Annotation> {
+    let mut annotations = Vec::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if node.is_named() && node.kind().contains("comment") {
+            let Some(text) = source.get(node.start_byte()..node.end_byte()) else {
+                continue;
+            };
+            let start = node.start_position();
+            for (index, line) in text.lines().enumerate() {
+                let Some((tag, offset, rest)) = find_annotation_marker(line) else {
+                    continue;
+                };
+                let col = if index == 0 {
+                    start.column + offset
+                } else {
+                    offset
+                };
+                annotations.push(Annotation {
+                    tag: tag.to_string(),
+                    line: (start.row + index) as i64 + 1,
+                    col: col as i64 + 1,
+                    text: rest.to_string(),
+                });
+            }
+            continue;
+        }
+        for index in (0..node.child_count()).rev() {
+            if let Some(child) = node.child(index) {
+                stack.push(child);
+            }
+        }
+    }
+    annotations.sort_by_key(|annotation| (annotation.line, annotation.col));
+    annotations
+}
+
+const ANNOTATION_TAGS: &[&str] = &["TODO", "FIXME", "HACK"];
+
+/// The first marker standing as its own word in `line`: the tag, its byte
+/// offset, and the note after it with separators and comment closers trimmed.
+fn find_annotation_marker(line: &str) -> Option<(&'static str, usize, &str)> {
+    let is_word = |ch: char| ch.is_alphanumeric() || ch == '_';
+    let mut first: Option<(&'static str, usize)> = None;
+    for tag in ANNOTATION_TAGS {
+        let found = line.match_indices(tag).find(|(offset, _)| {
+            let before = line[..*offset].chars().next_back();
+            let after = line[offset + tag.len()..].chars().next();
+            !before.is_some_and(is_word) && !after.is_some_and(is_word)
+        });
+        if let Some((offset, _)) = found {
+            if first.map_or(true, |(_, best)| offset < best) {
+                first = Some((tag, offset));
+            }
+        }
+    }
+    let (tag, offset) = first?;
+    let rest = line[offset + tag.len()..]
+        .trim_start_matches(|ch: char| ch == ':' || ch == '-' || ch.is_whitespace())
+        .trim_end()
+        .trim_end_matches("*/")
+        .trim_end_matches("-->")
+        .trim_end();
+    Some((tag, offset, rest))
 }
 
 #[derive(Clone)]
 struct TempDefinition {
     name: String,
+    /// The classes or namespaces a C++ name is qualified with, such as
+    /// `net::Server` in `void net::Server::connect()` or `myapp` in
+    /// `namespace myapp::net`.
+    scope: Option<String>,
     kind: String,
     line: i64,
     col: i64,
@@ -55,6 +346,11 @@ struct TempDefinition {
     end_col: i64,
     start_byte: usize,
     end_byte: usize,
+    offsets: SpanOffsets,
+    signature: Option<String>,
+    arity: Option<Arity>,
+    visibility: Option<String>,
+    modifiers: Vec<String>,
 }
 
 fn extract_with_query(
@@ -63,9 +359,17 @@ fn extract_with_query(
     root: Node<'_>,
     source: &str,
     language: LanguageKind,
-) -> Result<(Vec<Definition>, Vec<Reference>, Vec<Import>)> {
+) -> Result<FileExtraction> {
     if query_str.trim().is_empty() {
-        return Ok((Vec::new(), Vec::new(), Vec::new()));
+        return Ok(FileExtraction {
+            language,
+            definitions: Vec::new(),
+            references: Vec::new(),
+            imports: Vec::new(),
+            relations: Vec::new(),
+            noise_spans: Vec::new(),
+            annotations: Vec::new(),
+        });
     }
 
     let query =
@@ -74,10 +378,13 @@ fn extract_with_query(
 
     let mut cursor = QueryCursor::new();
     let mut temp_definitions = Vec::new();
+    let mut namespaces = Vec::new();
     let mut references = Vec::new();
     let mut imports = Vec::new();
+    let mut relations = Vec::new();
     let mut ref_dedupe = HashSet::new();
     let mut import_dedupe = HashSet::new();
+    let mut relation_dedupe = HashSet::new();
 
     for query_match in cursor.matches(&query, root, source.as_bytes()) {
         let mut definition_node = None;
@@ -85,6 +392,8 @@ fn extract_with_query(
         let mut call_nodes = Vec::new();
         let mut identifier_nodes = Vec::new();
         let mut import_nodes = Vec::new();
+        let mut relation_subject = None;
+        let mut relation_targets = Vec::new();
 
         for capture in query_match.captures {
             let capture_name = capture_names[capture.index as usize];
@@ -114,6 +423,55 @@ fn extract_with_query(
 
             if capture_name == "import" {
                 import_nodes.push(node);
+                continue;
+            }
+
+            if capture_name == "namespace" {
+                if let Some(namespace) = temp_namespace(node, source) {
+                    namespaces.push(namespace);
+                }
+                continue;
+            }
+
+            if capture_name == "relation.subject" {
+                relation_subject = Some(node);
+                continue;
+            }
+
+            match capture_name {
+                "relation.implements" => relation_targets.push((RelationKind::Implements, node)),
+                "relation.extends" => relation_targets.push((RelationKind::Extends, node)),
+                "relation.aliases" => relation_targets.push((RelationKind::Aliases, node)),
+                _ => {}
+            }
+        }
+
+        if let Some(subject) = relation_subject.and_then(|node| relation_type_name(node, source)) {
+            for (kind, target_node) in relation_targets {
+                let Some(target) = relation_type_name(target_node, source) else {
+                    continue;
+                };
+                if target == subject || matches!(target.as_str(), "self" | "super" | "crate") {
+                    continue;
+                }
+                let start = target_node.start_position();
+                let relation = Relation {
+                    subject: subject.clone(),
+                    target,
+                    kind,
+                    line: start.row as i64 + 1,
+                    col: start.column as i64 + 1,
+                };
+                let key = format!(
+                    "{}:{}:{}:{}",
+                    relation.subject,
+                    relation.kind.as_edge_type(),
+                    relation.target,
+                    relation.line
+                );
+                if relation_dedupe.insert(key) {
+                    relations.push(relation);
+                }
             }
         }
 
@@ -144,6 +502,8 @@ fn extract_with_query(
                 col: start.column as i64 + 1,
                 end_line: end.row as i64 + 1,
                 end_col: end.column as i64 + 1,
+                arity: call_arity(call_node),
+                offsets: Some(span_offsets(call_node, source)),
             };
             let key = format!(
                 "{}:{}:{}:{}",
@@ -182,6 +542,8 @@ fn extract_with_query(
                 col: start.column as i64 + 1,
                 end_line: end.row as i64 + 1,
                 end_col: end.column as i64 + 1,
+                arity: None,
+                offsets: Some(span_offsets(identifier_node, source)),
             };
             let key = format!(
                 "{}:{}:{}:{}",
@@ -229,8 +591,24 @@ fn extract_with_query(
             };
             let start = definition_node.start_position();
             let end = definition_node.end_position();
+            let (visibility, modifiers) =
+                definition_modifiers(definition_node, &name, source, language);
+            let scope = definition_name_node
+                .filter(|node| {
+                    matches!(
+                        node.kind(),
+                        "qualified_identifier" | "nested_namespace_specifier"
+                    )
+                })
+                .and_then(|node| node_text(node, source))
+                .and_then(|text| {
+                    let text: String = text.split_whitespace().collect();
+                    text.rsplit_once("::").map(|(scope, _)| scope.to_string())
+                })
+                .filter(|scope| !scope.is_empty());
             temp_definitions.push(TempDefinition {
                 name,
+                scope,
                 kind: definition_node.kind().to_string(),
                 line: start.row as i64 + 1,
                 col: start.column as i64 + 1,
@@ -238,12 +616,24 @@ fn extract_with_query(
                 end_col: end.column as i64 + 1,
                 start_byte: definition_node.start_byte(),
                 end_byte: definition_node.end_byte(),
+                offsets: span_offsets(definition_node, source),
+                signature: definition_signature(definition_node, source),
+                arity: definition_arity(definition_node, source),
+                visibility,
+                modifiers,
             });
         }
     }
 
-    let definitions = build_qualified_definitions(temp_definitions);
-    Ok((definitions, references, imports))
+    Ok(FileExtraction {
+        language,
+        definitions: build_qualified_definitions(temp_definitions, namespaces),
+        references,
+        imports,
+        relations,
+        noise_spans: Vec::new(),
+        annotations: Vec::new(),
+    })
 }
 
 fn resolve_definition_name(
@@ -270,38 +660,435 @@ fn resolve_definition_name(
     None
 }
 
+fn span_offsets(node: Node<'_>, source: &str) -> SpanOffsets {
+    SpanOffsets {
+        start_byte: node.start_byte(),
+        end_byte: node.end_byte(),
+        col_utf16: utf16_column(source, node.start_byte()),
+        end_col_utf16: utf16_column(source, node.end_byte()),
+    }
+}
+
+/// 1-based column of `byte` in UTF-16 code units, counted from the start of
+/// its line.
+fn utf16_column(source: &str, byte: usize) -> i64 {
+    let Some(prefix) = source.get(..byte) else {
+        return 1;
+    };
+    let line_start = prefix.rfind('\n').map_or(0, |index| index + 1);
+    prefix[line_start..].encode_utf16().count() as i64 + 1
+}
+
+/// Header of a callable definition: its source text up to the body, with
+/// whitespace collapsed and trailing `{`, `:`, `=`, or `;` dropped. Nodes
+/// without a parameter list (types, modules, constants) have no signature.
+fn definition_signature(node: Node<'_>, source: &str) -> Option<String> {
+    let declarator = node.child_by_field_name("declarator");
+    if !has_parameter_list(node) && !declarator.is_some_and(has_parameter_list) {
+        return None;
+    }
+    let end = definition_body(node)
+        .map(|body| body.start_byte())
+        .unwrap_or_else(|| node.end_byte());
+    let header = source
+        .get(node.start_byte()..end)?
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let header = header.trim_end_matches(['{', ':', '=', ';', ' ']);
+    if header.is_empty() {
+        return None;
+    }
+    if header.chars().count() > MAX_SIGNATURE_CHARS {
+        let cut: String = header.chars().take(MAX_SIGNATURE_CHARS).collect();
+        return Some(format!("{cut}..."));
+    }
+    Some(header.to_string())
+}
+
+/// Visibility and `async`/`static`/`abstract` modifiers of a definition,
+/// read from the keywords in its header (before the name, declarator, or
+/// body). Without an explicit visibility keyword, languages with an implicit
+/// rule fill it in: Rust items are private unless `pub` (trait members are
+/// left unknown), Python names with a leading underscore are private, Go
+/// names are public when capitalized, and JS/TS definitions are public when
+/// exported or declared in a class body.
+fn definition_modifiers(
+    node: Node<'_>,
+    name: &str,
+    source: &str,
+    language: LanguageKind,
+) -> (Option<String>, Vec<String>) {
+    let stop_at = ["name", "declarator", "body", "parameters"]
+        .iter()
+        .filter_map(|field| node.child_by_field_name(field))
+        .map(|child| child.id())
+        .collect::<HashSet<_>>();
+    let mut words = Vec::new();
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if stop_at.contains(&child.id()) {
+            break;
+        }
+        let kind = child.kind();
+        if kind.contains("modifier") || kind.contains("specifier") {
+            let text = node_text(child, source).unwrap_or_default();
+            words.extend(text.split_whitespace().map(str::to_string));
+        } else if !child.is_named() {
+            words.push(kind.to_string());
+        }
+    }
+    if let Some(parent) = node.parent().filter(|p| p.kind() == "decorated_definition") {
+        let mut cursor = parent.walk();
+        for decorator in parent.children(&mut cursor) {
+            let text = node_text(decorator, source).unwrap_or_default();
+            match text
+                .trim_start_matches('@')
+                .rsplit('.')
+                .next()
+                .unwrap_or_default()
+            {
+                "staticmethod" => words.push("static".to_string()),
+                "abstractmethod" => words.push("abstract".to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    let mut visibility = None;
+    let mut modifiers: Vec<String> = Vec::new();
+    for word in &words {
+        let explicit = match word.as_str() {
+            "pub" | "public" | "open" => Some("public"),
+            "private" | "fileprivate" => Some("private"),
+            "protected" => Some("protected"),
+            "internal" => Some("internal"),
+            word if word.starts_with("pub(") => Some("internal"),
+            _ => None,
+        };
+        if let Some(explicit) = explicit {
+            visibility.get_or_insert(explicit);
+        } else if matches!(word.as_str(), "async" | "static" | "abstract")
+            && !modifiers.contains(word)
+        {
+            modifiers.push(word.clone());
+        }
+    }
+    let visibility = visibility.or_else(|| implicit_visibility(node, name, language));
+    (visibility.map(str::to_string), modifiers)
+}
+
+fn implicit_visibility(node: Node<'_>, name: &str, language: LanguageKind) -> Option<&'static str> {
+    match language {
+        LanguageKind::Rust => {
+            if node.kind() == "impl_item" {
+                return None;
+            }
+            let mut ancestor = node.parent();
+            while let Some(current) = ancestor {
+                match current.kind() {
+                    "trait_item" => return None,
+                    "impl_item" if current.child_by_field_name("trait").is_some() => return None,
+                    "impl_item" | "function_item" | "mod_item" => break,
+                    _ => ancestor = current.parent(),
+                }
+            }
+            Some("private")
+        }
+        LanguageKind::Python => {
+            let dunder = name.starts_with("__") && name.ends_with("__");
+            Some(if name.starts_with('_') && !dunder {
+                "private"
+            } else {
+                "public"
+            })
+        }
+        LanguageKind::Go => Some(if name.starts_with(char::is_uppercase) {
+            "public"
+        } else {
+            "private"
+        }),
+        LanguageKind::JavaScript | LanguageKind::TypeScript | LanguageKind::Tsx => {
+            if node
+                .child_by_field_name("name")
+                .is_some_and(|name| name.kind() == "private_property_identifier")
+            {
+                return Some("private");
+            }
+            let mut ancestor = node.parent();
+            while let Some(current) = ancestor {
+                match current.kind() {
+                    "export_statement" | "class_body" => return Some("public"),
+                    "lexical_declaration" | "variable_declaration" | "variable_declarator" => {
+                        ancestor = current.parent();
+                    }
+                    _ => break,
+                }
+            }
+            Some("private")
+        }
+        _ => None,
+    }
+}
+
+fn has_parameter_list(node: Node<'_>) -> bool {
+    if node.child_by_field_name("parameters").is_some() {
+        return true;
+    }
+    let mut cursor = node.walk();
+    let found = node.children(&mut cursor).any(|child| {
+        let kind = child.kind();
+        kind.ends_with("parameters") || matches!(kind, "parameter_list" | "parameter")
+    });
+    found
+}
+
+/// Argument counts a callable definition accepts, read from its parameter
+/// list. Defaults and optional parameters lower `min`; splats and varargs
+/// drop `max`. `None` for definitions without a parameter list.
+fn definition_arity(node: Node<'_>, source: &str) -> Option<Arity> {
+    let mut list = node;
+    loop {
+        if let Some(parameters) = list.child_by_field_name("parameters") {
+            list = parameters;
+            break;
+        }
+        match list.child_by_field_name("declarator") {
+            Some(declarator) => list = declarator,
+            None => break,
+        }
+    }
+    if list.id() == node.id() {
+        let mut cursor = node.walk();
+        let nested = node.children(&mut cursor).find(|child| {
+            let kind = child.kind();
+            (kind.ends_with("parameters") && !kind.contains("type")) || kind == "parameter_list"
+        });
+        if let Some(nested) = nested {
+            list = nested;
+        } else if !has_parameter_list(node) {
+            return None;
+        }
+    }
+
+    let (mut required, mut optional) = (0, 0);
+    let mut receiver = false;
+    let mut variadic = false;
+    let mut last_required = false;
+    let mut cursor = list.walk();
+    for child in list.children(&mut cursor) {
+        let kind = child.kind();
+        if kind == "=" && last_required {
+            // `name: Type = default` in grammars (Kotlin, Swift) that keep
+            // the default beside the parameter rather than inside it.
+            required -= 1;
+            optional += 1;
+            last_required = false;
+            continue;
+        }
+        if !child.is_named() {
+            continue;
+        }
+        let text = node_text(child, source).unwrap_or_default();
+        if kind == "parameter_modifiers" && text.contains("vararg") {
+            variadic = true;
+            continue;
+        }
+        let is_parameter = kind.contains("parameter")
+            || kind.ends_with("_pattern")
+            || kind == "identifier"
+            || kind.contains("vararg");
+        if !is_parameter || kind.contains("separator") || kind == "parameter_modifiers" {
+            continue;
+        }
+        last_required = false;
+        let first = required + optional == 0 && !receiver;
+        if kind == "self_parameter"
+            || kind == "receiver_parameter"
+            || (first && kind == "identifier" && matches!(text.as_str(), "self" | "cls"))
+        {
+            receiver = true;
+        } else if kind == "parameter_declaration" && text == "void" {
+            continue;
+        } else if kind.contains("splat")
+            || kind.contains("spread")
+            || kind.contains("variadic")
+            || kind.contains("vararg")
+            || kind == "rest_pattern"
+        {
+            variadic = true;
+        } else if kind.contains("default")
+            || kind.contains("optional")
+            || kind == "assignment_pattern"
+            || kind == "block_parameter"
+            || kind == "keyword_parameter"
+            || has_child_kind(child, "equals_value_clause")
+        {
+            optional += 1;
+        } else if kind == "parameter_declaration" && list.kind() == "parameter_list" {
+            // Go: `a, b int` declares two parameters.
+            let mut names = child.walk();
+            required += child
+                .children_by_field_name("name", &mut names)
+                .count()
+                .max(1);
+            last_required = true;
+        } else {
+            required += 1;
+            last_required = true;
+        }
+    }
+    let max = required + optional + usize::from(receiver);
+    Some(Arity {
+        min: required,
+        max: (!variadic).then_some(max),
+    })
+}
+
+/// Arguments passed by a call node, or `None` when the call has no argument
+/// list or spreads one (`f(*args)`, `f(...rest)`), so the count is unknown.
+fn call_arity(call_node: Node<'_>) -> Option<usize> {
+    let arguments = call_node.child_by_field_name("arguments").or_else(|| {
+        let mut cursor = call_node.walk();
+        let suffix = call_node.children(&mut cursor).find(|child| {
+            matches!(
+                child.kind(),
+                "call_suffix" | "value_arguments" | "arguments"
+            )
+        });
+        suffix
+    })?;
+    let arguments = if arguments.kind() == "call_suffix" {
+        let mut cursor = arguments.walk();
+        let list = arguments
+            .children(&mut cursor)
+            .find(|child| child.kind() == "value_arguments");
+        list?
+    } else {
+        arguments
+    };
+    let mut count = 0;
+    let mut cursor = arguments.walk();
+    for child in arguments.named_children(&mut cursor) {
+        let kind = child.kind();
+        if kind.contains("comment") {
+            continue;
+        }
+        if kind.contains("splat") || kind.contains("spread") {
+            return None;
+        }
+        count += 1;
+    }
+    Some(count)
+}
+
+fn has_child_kind(node: Node<'_>, kind: &str) -> bool {
+    let mut cursor = node.walk();
+    let found = node.children(&mut cursor).any(|child| child.kind() == kind);
+    found
+}
+
+/// The `body` field, or the body child for grammars (Kotlin, Swift) that do
+/// not expose one.
+fn definition_body(node: Node<'_>) -> Option<Node<'_>> {
+    if let Some(body) = node.child_by_field_name("body") {
+        return Some(body);
+    }
+    let mut cursor = node.walk();
+    let body = node
+        .children(&mut cursor)
+        .find(|child| child.kind() == "function_body");
+    body
+}
+
 fn node_contains(container: Node<'_>, candidate: Node<'_>) -> bool {
     container.start_byte() <= candidate.start_byte() && candidate.end_byte() <= container.end_byte()
 }
 
-fn build_qualified_definitions(mut temp_definitions: Vec<TempDefinition>) -> Vec<Definition> {
-    temp_definitions.sort_by(|left, right| {
-        left.start_byte
-            .cmp(&right.start_byte)
-            .then_with(|| right.end_byte.cmp(&left.end_byte))
-    });
+/// A Java package or C# namespace: every definition inside `start_byte..end_byte`
+/// is qualified by its dotted `name`.
+struct TempNamespace {
+    name: String,
+    start_byte: usize,
+    end_byte: usize,
+}
+
+/// Builds a [`TempNamespace`] from the name captured as `@namespace`. Java
+/// `package` and C# file-scoped `namespace ...;` declarations cover the rest
+/// of the file; a C# `namespace ... { }` block covers its body.
+fn temp_namespace(name_node: Node<'_>, source: &str) -> Option<TempNamespace> {
+    let declaration = name_node.parent()?;
+    let name: String = node_text(name_node, source)?.split_whitespace().collect();
+    if name.is_empty() {
+        return None;
+    }
+    let end_byte = match declaration.kind() {
+        "package_declaration" | "file_scoped_namespace_declaration" => source.len(),
+        _ => declaration.end_byte(),
+    };
+    Some(TempNamespace {
+        name,
+        start_byte: declaration.start_byte(),
+        end_byte,
+    })
+}
+
+/// Qualifies each definition by the definitions and namespaces enclosing it.
+/// Nested definitions join with `::`; a namespace joins with `.`, as the
+/// language writes it, so `com.foo.Bar::baz` still nests one level deep.
+fn build_qualified_definitions(
+    temp_definitions: Vec<TempDefinition>,
+    namespaces: Vec<TempNamespace>,
+) -> Vec<Definition> {
+    enum Scoped {
+        Namespace(String),
+        Definition(Box<TempDefinition>),
+    }
+    let mut items: Vec<(usize, usize, Scoped)> = namespaces
+        .into_iter()
+        .map(|namespace| {
+            let name = Scoped::Namespace(namespace.name);
+            (namespace.start_byte, namespace.end_byte, name)
+        })
+        .chain(temp_definitions.into_iter().map(|item| {
+            (
+                item.start_byte,
+                item.end_byte,
+                Scoped::Definition(Box::new(item)),
+            )
+        }))
+        .collect();
+    items.sort_by(|left, right| left.0.cmp(&right.0).then_with(|| right.1.cmp(&left.1)));
 
     let mut results = Vec::new();
-    let mut stack: Vec<(usize, usize, String)> = Vec::new();
+    // (end byte, qualname, separator before a child's name)
+    let mut stack: Vec<(usize, String, &str)> = Vec::new();
     let mut dedupe = HashSet::new();
 
-    for item in temp_definitions {
-        while let Some((_, end_byte, _)) = stack.last() {
-            if *end_byte <= item.start_byte {
+    for (start_byte, end_byte, item) in items {
+        while let Some((parent_end, _, _)) = stack.last() {
+            if *parent_end <= start_byte {
                 stack.pop();
             } else {
                 break;
             }
         }
-
-        let qualname = if let Some((_, parent_end, parent_qualname)) = stack.last() {
-            if *parent_end >= item.end_byte {
-                format!("{parent_qualname}::{}", item.name)
-            } else {
-                item.name.clone()
+        let parent = stack
+            .last()
+            .filter(|(parent_end, _, _)| *parent_end >= end_byte)
+            .map(|(_, qualname, separator)| format!("{qualname}{separator}"))
+            .unwrap_or_default();
+
+        let item = match item {
+            Scoped::Definition(item) => *item,
+            Scoped::Namespace(namespace) => {
+                stack.push((end_byte, format!("{parent}{namespace}"), "."));
+                continue;
             }
-        } else {
-            item.name.clone()
+        };
+        let qualname = match &item.scope {
+            Some(scope) => format!("{parent}{scope}::{}", item.name),
+            None => format!("{parent}{}", item.name),
         };
 
         let definition = Definition {
@@ -312,6 +1099,11 @@ fn build_qualified_definitions(mut temp_definitions: Vec<TempDefinition>) -> Vec
             col: item.col,
             end_line: item.end_line,
             end_col: item.end_col,
+            signature: item.signature,
+            arity: item.arity,
+            offsets: Some(item.offsets),
+            visibility: item.visibility,
+            modifiers: item.modifiers,
         };
 
         let key = format!(
@@ -322,7 +1114,7 @@ fn build_qualified_definitions(mut temp_definitions: Vec<TempDefinition>) -> Vec
             results.push(definition);
         }
 
-        stack.push((item.start_byte, item.end_byte, qualname));
+        stack.push((end_byte, qualname, "::"));
     }
 
     results
@@ -400,6 +1192,10 @@ fn normalize_import(raw: &str, language: LanguageKind) -> String {
             .next()
             .unwrap_or_default()
             .to_string(),
+        LanguageKind::Go => raw.trim().trim_matches(['"', '`']).to_string(),
+        LanguageKind::JavaScript | LanguageKind::TypeScript | LanguageKind::Tsx => {
+            raw.trim().trim_matches(['"', '\'', '`']).to_string()
+        }
         _ => raw.trim().to_string(),
     }
 }
@@ -429,6 +1225,18 @@ fn extract_terminal_identifier(node: Node<'_>, source: &str) -> Option<String> {
     }
 }
 
+/// Names the base type of a relation target, so `List<String>` yields `List`
+/// and `pkg.Base` yields `Base`.
+fn relation_type_name(node: Node<'_>, source: &str) -> Option<String> {
+    match node.kind() {
+        "lifetime" => None,
+        "generic_type" | "generic_name" | "generic_type_with_turbofish" => node
+            .named_child(0)
+            .and_then(|child| relation_type_name(child, source)),
+        _ => extract_terminal_identifier(node, source),
+    }
+}
+
 fn node_text(node: Node<'_>, source: &str) -> Option<String> {
     node.utf8_text(source.as_bytes())
         .ok()
@@ -688,6 +1496,28 @@ const other = () => helper();
         assert!(!extraction.imports.is_empty());
     }
 
+    #[test]
+    fn parse_file_javascript_extracts_import_specifiers() {
+        let source = r#"
+import x from "./x";
+import './side-effect.js';
+export { y } from '../y';
+const z = require("./z");
+const lazy = import(`./lazy`);
+const other = load("./not-an-import");
+"#;
+        let extraction = parse_supported(Path::new("sample.js"), source);
+        let modules: Vec<&str> = extraction
+            .imports
+            .iter()
+            .map(|item| item.module.as_str())
+            .collect();
+        assert_eq!(
+            modules,
+            vec!["./x", "./side-effect.js", "../y", "./z", "./lazy"]
+        );
+    }
+
     #[test]
     fn parse_file_typescript_extracts_basics() {
         let source = r#"
@@ -814,6 +1644,65 @@ class Greeter {
         assert!(!extraction.imports.is_empty());
     }
 
+    #[test]
+    fn qualnames_include_namespaces_and_packages() {
+        let qualnames = |path: &str, source: &str| -> Vec<String> {
+            parse_supported(Path::new(path), source)
+                .definitions
+                .into_iter()
+                .map(|definition| definition.qualname)
+                .collect()
+        };
+
+        let cpp = qualnames(
+            "server.cpp",
+            r#"
+namespace myapp::net {
+class Server { void close() {} };
+}
+namespace myapp {
+void net::Server::connect() {}
+}
+"#,
+        );
+        assert!(cpp.contains(&"myapp::net".to_string()));
+        assert!(cpp.contains(&"myapp::net::Server::close".to_string()));
+        assert!(cpp.contains(&"myapp::net::Server::connect".to_string()));
+
+        let java = qualnames(
+            "Bar.java",
+            r#"
+package com.foo;
+
+class Bar {
+    void baz() {}
+    static class Inner {}
+}
+"#,
+        );
+        assert_eq!(
+            java,
+            vec!["com.foo.Bar", "com.foo.Bar::baz", "com.foo.Bar::Inner"]
+        );
+
+        let csharp = qualnames(
+            "Server.cs",
+            r#"
+namespace MyApp.Net {
+    class Server { void Connect() {} }
+}
+class Program {}
+"#,
+        );
+        assert_eq!(
+            csharp,
+            vec!["MyApp.Net.Server", "MyApp.Net.Server::Connect", "Program"]
+        );
+
+        let scoped = qualnames("Client.cs", "namespace MyApp;\nclass Client {}\n");
+        assert_eq!(scoped, vec!["MyApp.Client"]);
+    }
+
     #[test]
     fn parse_file_ruby_extracts_basics() {
         let source = r#"
@@ -1030,4 +1919,409 @@ run = helper 1
             .any(|item| item.name == "helper"));
         assert!(!extraction.imports.is_empty());
     }
+
+    fn relation_triples(extraction: &FileExtraction) -> HashSet<(String, &'static str, String)> {
+        extraction
+            .relations
+            .iter()
+            .map(|item| {
+                (
+                    item.subject.clone(),
+                    item.kind.as_edge_type(),
+                    item.target.clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_file_rust_extracts_type_relations() {
+        let source = r#"
+trait Shape: Clone + std::fmt::Debug + 'static {}
+struct Circle;
+impl Shape for Circle {}
+impl<T> From<T> for Wrapper<T> {}
+impl Circle { fn area(&self) {} }
+"#;
+        let triples = relation_triples(&parse_supported(Path::new("shapes.rs"), source));
+        assert!(triples.contains(&("Circle".into(), "implements", "Shape".into())));
+        assert!(triples.contains(&("Wrapper".into(), "implements", "From".into())));
+        assert!(triples.contains(&("Shape".into(), "extends", "Clone".into())));
+        assert!(triples.contains(&("Shape".into(), "extends", "Debug".into())));
+        assert_eq!(triples.len(), 4, "inherent impls and lifetimes are skipped");
+    }
+
+    #[test]
+    fn parse_file_python_java_and_typescript_extract_type_relations() {
+        let python = parse_supported(
+            Path::new("models.py"),
+            "class Admin(User, mixins.Audited, metaclass=Meta):\n    pass\n",
+        );
+        let triples = relation_triples(&python);
+        assert!(triples.contains(&("Admin".into(), "extends", "User".into())));
+        assert!(triples.contains(&("Admin".into(), "extends", "Audited".into())));
+        assert_eq!(triples.len(), 2, "keyword arguments are not base classes");
+
+        let java = parse_supported(
+            Path::new("Admin.java"),
+            r#"
+interface Named extends Comparable<Named> {}
+class Admin extends User implements Named, java.io.Serializable {}
+"#,
+        );
+        let triples = relation_triples(&java);
+        assert!(triples.contains(&("Admin".into(), "extends", "User".into())));
+        assert!(triples.contains(&("Admin".into(), "implements", "Named".into())));
+        assert!(triples.contains(&("Admin".into(), "implements", "Serializable".into())));
+        assert!(triples.contains(&("Named".into(), "extends", "Comparable".into())));
+
+        let typescript = parse_supported(
+            Path::new("admin.ts"),
+            r#"
+interface Named extends Base<string> {}
+class Admin extends User<Role> implements Named {}
+"#,
+        );
+        let triples = relation_triples(&typescript);
+        assert!(triples.contains(&("Admin".into(), "extends", "User".into())));
+        assert!(triples.contains(&("Admin".into(), "implements", "Named".into())));
+        assert!(triples.contains(&("Named".into(), "extends", "Base".into())));
+
+        let javascript = parse_supported(Path::new("admin.js"), "class Admin extends User {}\n");
+        assert!(relation_triples(&javascript).contains(&(
+            "Admin".into(),
+            "extends",
+            "User".into()
+        )));
+    }
+
+    #[test]
+    fn parse_file_extracts_alias_relations_from_renamed_imports() {
+        let rust = parse_supported(
+            Path::new("lib.rs"),
+            "pub use crate::config::Settings as Config;\nuse std::io::{self as stdio, Result};\n",
+        );
+        let triples = relation_triples(&rust);
+        assert!(triples.contains(&("Config".into(), "aliases", "Settings".into())));
+        assert_eq!(
+            triples.len(),
+            1,
+            "`self` renames name a module, not a symbol"
+        );
+
+        let python = parse_supported(
+            Path::new("app.py"),
+            "from pkg.models import User as Account, Role\nimport numpy as np\n",
+        );
+        assert_eq!(
+            relation_triples(&python),
+            HashSet::from([("Account".to_string(), "aliases", "User".to_string())])
+        );
+
+        let typescript = parse_supported(
+            Path::new("index.ts"),
+            "import { parse as parseConfig } from './config';\nexport { Store as default, Cache } from './store';\n",
+        );
+        let triples = relation_triples(&typescript);
+        assert!(triples.contains(&("parseConfig".into(), "aliases", "parse".into())));
+        assert!(triples.contains(&("default".into(), "aliases", "Store".into())));
+        assert_eq!(triples.len(), 2);
+    }
+
+    #[test]
+    fn parse_file_records_comment_and_string_spans() {
+        let source = "// header\nfn run() { let s = \"text\"; }\n";
+        let extraction = parse_supported(Path::new("noise.rs"), source);
+        let blanked: Vec<&str> = extraction
+            .noise_spans
+            .iter()
+            .map(|&(start, end)| &source[start..end])
+            .collect();
+        assert!(blanked.contains(&"// header\n") || blanked.contains(&"// header"));
+        assert!(blanked.contains(&"\"text\""));
+
+        let json = parse_supported(Path::new("data.json"), "{\"key\": \"value\"}");
+        assert!(json.noise_spans.is_empty(), "data strings are content");
+    }
+
+    #[test]
+    fn parse_file_collects_todo_markers_from_comments_only() {
+        let source = "// TODO: split this up\nfn run() {\n    let s = \"TODO not a comment\";\n    /* first\n       FIXME(ada) - leaks */\n    // TODOS and HACKY are other words\n}\n";
+        let rust = parse_supported(Path::new("todo.rs"), source);
+        let found: Vec<(&str, i64, i64, &str)> = rust
+            .annotations
+            .iter()
+            .map(|note| (note.tag.as_str(), note.line, note.col, note.text.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("TODO", 1, 4, "split this up"),
+                ("FIXME", 5, 8, "(ada) - leaks"),
+            ]
+        );
+
+        let python = parse_supported(Path::new("todo.py"), "x = 1  # HACK until v2\n");
+        assert_eq!(python.annotations.len(), 1);
+        assert_eq!(python.annotations[0].text, "until v2");
+    }
+
+    #[test]
+    fn parse_file_records_visibility_and_modifiers() {
+        let modifiers = |path: &str, source: &str, qualname: &str| {
+            let definition = parse_supported(Path::new(path), source)
+                .definitions
+                .into_iter()
+                .find(|definition| definition.qualname == qualname)
+                .unwrap_or_else(|| panic!("missing definition {qualname} in {path}"));
+            (definition.visibility, definition.modifiers.join(" "))
+        };
+        let visibility =
+            |path: &str, source: &str, qualname: &str| modifiers(path, source, qualname).0;
+
+        let rust = "pub async fn serve() {}\npub(crate) fn helper() {}\nfn local() {}\n\
+                    struct S;\nimpl Run for S { fn run(&self) {} }\n";
+        assert_eq!(
+            modifiers("src/lib.rs", rust, "serve"),
+            (Some("public".into()), "async".into())
+        );
+        assert_eq!(
+            visibility("src/lib.rs", rust, "helper").as_deref(),
+            Some("internal")
+        );
+        assert_eq!(
+            visibility("src/lib.rs", rust, "local").as_deref(),
+            Some("private")
+        );
+        assert_eq!(visibility("src/lib.rs", rust, "Run::run"), None);
+        assert_eq!(
+            visibility("src/lib.rs", rust, "S").as_deref(),
+            Some("private")
+        );
+
+        let python = "class C:\n    @staticmethod\n    def make():\n        pass\n\
+                      \n    async def _load(self):\n        pass\n";
+        assert_eq!(
+            modifiers("app.py", python, "C::make"),
+            (Some("public".into()), "static".into())
+        );
+        assert_eq!(
+            modifiers("app.py", python, "C::_load"),
+            (Some("private".into()), "async".into())
+        );
+
+        let ts = "export function api() {}\nfunction internal() {}\n\
+                  abstract class Base {\n  protected static async load() {}\n}\n";
+        assert_eq!(visibility("app.ts", ts, "api").as_deref(), Some("public"));
+        assert_eq!(
+            visibility("app.ts", ts, "internal").as_deref(),
+            Some("private")
+        );
+        assert_eq!(
+            modifiers("app.ts", ts, "load"),
+            (Some("protected".into()), "static async".into())
+        );
+
+        let java = "public abstract class Svc {\n  private static void reset() {}\n  public abstract void run();\n}\n";
+        assert_eq!(
+            modifiers("Svc.java", java, "Svc::reset"),
+            (Some("private".into()), "static".into())
+        );
+        assert_eq!(
+            modifiers("Svc.java", java, "Svc::run"),
+            (Some("public".into()), "abstract".into())
+        );
+        assert_eq!(
+            modifiers("Svc.java", java, "Svc"),
+            (Some("public".into()), "abstract".into())
+        );
+        assert_eq!(
+            visibility(
+                "main.go",
+                "package main\nfunc Run() {}\nfunc stop() {}\n",
+                "stop"
+            )
+            .as_deref(),
+            Some("private")
+        );
+    }
+
+    #[test]
+    fn parse_file_extracts_callable_signatures_across_languages() {
+        let signature = |path: &str, source: &str, qualname: &str| {
+            parse_supported(Path::new(path), source)
+                .definitions
+                .into_iter()
+                .find(|definition| definition.qualname == qualname)
+                .unwrap_or_else(|| panic!("missing definition {qualname} in {path}"))
+                .signature
+        };
+
+        assert_eq!(
+            signature(
+                "src/lib.rs",
+                "pub fn helper<T: Clone>(value: T,\n    other: &str) -> Option<T> {\n    None\n}\n",
+                "helper"
+            )
+            .as_deref(),
+            Some("pub fn helper<T: Clone>(value: T, other: &str) -> Option<T>")
+        );
+        assert_eq!(signature("src/lib.rs", "struct Plain;\n", "Plain"), None);
+        assert_eq!(
+            signature(
+                "app.py",
+                "class C:\n    def m(self, a: int = 2) -> int:\n        return a\n",
+                "C::m"
+            )
+            .as_deref(),
+            Some("def m(self, a: int = 2) -> int")
+        );
+        assert_eq!(
+            signature(
+                "main.go",
+                "package main\nfunc (s *S) Run(ctx context.Context) (int, error) { return 0, nil }\n",
+                "Run"
+            )
+            .as_deref(),
+            Some("func (s *S) Run(ctx context.Context) (int, error)")
+        );
+        assert_eq!(
+            signature(
+                "api.ts",
+                "export function f(a: number): string { return ''; }\n",
+                "f"
+            )
+            .as_deref(),
+            Some("function f(a: number): string")
+        );
+        assert_eq!(
+            signature(
+                "math.c",
+                "static int add(int a, int b) { return a + b; }\n",
+                "add"
+            )
+            .as_deref(),
+            Some("static int add(int a, int b)")
+        );
+        assert_eq!(
+            signature(
+                "Math.kt",
+                "fun add(a: Int, b: Int): Int { return a + b }\n",
+                "add"
+            )
+            .as_deref(),
+            Some("fun add(a: Int, b: Int): Int")
+        );
+    }
+
+    #[test]
+    fn parse_file_records_byte_offsets_and_utf16_columns() {
+        let source = "fn a() {}\nfn run() { let s = \"🦀é\"; go(); }\n";
+        let extraction = parse_supported(Path::new("src/lib.rs"), source);
+
+        let call = extraction
+            .references
+            .iter()
+            .find(|reference| reference.name == "go" && reference.kind == ReferenceKind::Call)
+            .expect("call to go");
+        assert_eq!((call.line, call.col, call.end_col), (2, 30, 34));
+        let offsets = call.offsets.expect("call offsets");
+        assert_eq!(offsets.start_byte, 39);
+        assert_eq!(&source[offsets.start_byte..offsets.end_byte], "go()");
+        assert_eq!((offsets.col_utf16, offsets.end_col_utf16), (27, 31));
+
+        let run = extraction
+            .definitions
+            .iter()
+            .find(|definition| definition.name == "run")
+            .expect("run definition");
+        let offsets = run.offsets.expect("definition offsets");
+        assert_eq!(offsets.start_byte, 10);
+        assert_eq!(offsets.col_utf16, 1);
+        assert_eq!(offsets.end_byte, source.len() - 1);
+    }
+
+    #[test]
+    fn parse_file_cached_reparses_small_edits_incrementally() {
+        let path = Path::new("src/hot.rs");
+        let body: String = (0..200)
+            .map(|index| format!("fn f{index}() -> i32 {{ g{index}() }}\n"))
+            .collect();
+        let mut trees = TreeCache::default();
+        let (_, incremental) = parse_file_cached(path, &body, &mut trees).unwrap().unwrap();
+        assert!(!incremental, "nothing cached yet");
+
+        let edited = body.replace(
+            "fn f100() -> i32 { g100() }",
+            "fn renamed(é: u8) {\n    h();\n}",
+        );
+        let (extraction, incremental) = parse_file_cached(path, &edited, &mut trees)
+            .unwrap()
+            .unwrap();
+        assert!(incremental);
+        let full = parse_supported(path, &edited);
+        assert_eq!(
+            serde_json::to_value(&extraction).unwrap(),
+            serde_json::to_value(&full).unwrap()
+        );
+        assert!(extraction
+            .definitions
+            .iter()
+            .any(|item| item.name == "renamed"));
+
+        let rewritten = format!("struct Big;\n{}", "// filler\n".repeat(8 * 1024));
+        let (_, incremental) = parse_file_cached(path, &rewritten, &mut trees)
+            .unwrap()
+            .unwrap();
+        assert!(!incremental, "large rewrites get a full parse");
+    }
+
+    #[test]
+    fn parse_file_records_definition_and_call_arity() {
+        let arity = |path: &str, source: &str, qualname: &str| {
+            parse_supported(Path::new(path), source)
+                .definitions
+                .into_iter()
+                .find(|definition| definition.qualname == qualname)
+                .unwrap_or_else(|| panic!("missing definition {qualname} in {path}"))
+                .arity
+                .map(|arity| (arity.min, arity.max))
+        };
+        let rust = "struct S;\nimpl S { fn m(&self, a: u8) {} }\nfn f(a: u8, b: u8) { f(1); S::m(&S, 2); }\n";
+        assert_eq!(arity("lib.rs", rust, "S::m"), Some((1, Some(2))));
+        assert_eq!(arity("lib.rs", rust, "f"), Some((2, Some(2))));
+        assert_eq!(arity("lib.rs", rust, "S"), None);
+        let calls: Vec<(String, Option<usize>)> = parse_supported(Path::new("lib.rs"), rust)
+            .references
+            .into_iter()
+            .filter(|reference| reference.kind == ReferenceKind::Call)
+            .map(|reference| (reference.name, reference.arity))
+            .collect();
+        assert!(calls.contains(&("f".to_string(), Some(1))), "{calls:?}");
+        assert!(calls.contains(&("m".to_string(), Some(2))), "{calls:?}");
+
+        let python = "class C:\n    def g(self, a, b=1, *rest):\n        pass\ndef h(a, *, b=2):\n    h(1, b=3)\n";
+        assert_eq!(arity("app.py", python, "C::g"), Some((1, None)));
+        assert_eq!(arity("app.py", python, "h"), Some((1, Some(2))));
+        assert_eq!(
+            arity("app.js", "function j(a, b = 1, ...c) {}\n", "j"),
+            Some((1, None))
+        );
+        assert_eq!(
+            arity(
+                "main.go",
+                "package main\nfunc k(a, b int, c string) {}\n",
+                "k"
+            ),
+            Some((3, Some(3)))
+        );
+        assert_eq!(
+            arity("main.c", "int v(void) { return 0; }\n", "v"),
+            Some((0, Some(0)))
+        );
+        assert_eq!(
+            arity("Main.kt", "fun kk(a: Int, b: Int = 2) {}\n", "kk"),
+            Some((1, Some(2)))
+        );
+    }
 }
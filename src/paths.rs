@@ -3,10 +3,21 @@ use std::fs;
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::{load_config, StateLocation};
 
 pub const STATE_DIR_NAME: &str = ".lumora";
 pub const DEFAULT_DB_FILE: &str = "graph.db";
 
+/// `global` or `repo`; overrides `[state] location` in `.lumora.toml`.
+pub const STATE_LOCATION_ENV: &str = "LUMORA_STATE";
+
+/// Written into a global state dir naming the repository it belongs to, so
+/// two checkouts can never share (or clobber) one graph.
+pub const STATE_OWNER_FILE: &str = "repo-root";
+
 #[derive(Debug, Clone)]
 pub struct RuntimePaths {
     pub repo_root: PathBuf,
@@ -22,7 +33,10 @@ pub fn resolve_runtime_paths(
     let repo_root = discover_repo_root(repo_hint)?;
     let state_dir = match state_dir_override {
         Some(explicit) => absolutize_path(explicit)?,
-        None => repo_root.join(STATE_DIR_NAME),
+        None => match state_location(&repo_root)? {
+            StateLocation::Repo => repo_root.join(STATE_DIR_NAME),
+            StateLocation::Global => global_state_dir(&global_state_root()?, &repo_root),
+        },
     };
 
     let db_path = match db_override {
@@ -46,6 +60,134 @@ pub fn ensure_state_layout(paths: &RuntimePaths) -> Result<()> {
             .with_context(|| format!("failed to create db parent {}", parent.display()))?;
     }
 
+    let is_global = global_state_root().is_ok_and(|root| paths.state_dir.starts_with(root));
+    if is_global {
+        claim_state_dir(&paths.state_dir, &paths.repo_root)?;
+    }
+
+    Ok(())
+}
+
+/// `LUMORA_STATE` when set, else `[state] location` from the repo's config.
+fn state_location(repo_root: &Path) -> Result<StateLocation> {
+    match env::var(STATE_LOCATION_ENV) {
+        Ok(raw) if !raw.is_empty() => StateLocation::parse(&raw).with_context(|| {
+            format!("{STATE_LOCATION_ENV} must be `repo` or `global`, not `{raw}`")
+        }),
+        _ => Ok(load_config(repo_root)?.state.location),
+    }
+}
+
+/// `$XDG_DATA_HOME/lumora`, falling back to `~/.local/share/lumora`
+/// (`%LOCALAPPDATA%\lumora` on Windows).
+pub fn global_state_root() -> Result<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            if cfg!(windows) {
+                env::var_os("LOCALAPPDATA").map(PathBuf::from)
+            } else {
+                env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+            }
+        })
+        .context("cannot place global state: set XDG_DATA_HOME or HOME")?;
+    Ok(data_home.join("lumora"))
+}
+
+/// `<root>/<hash of repo_root>`: one state dir per checkout path.
+pub fn global_state_dir(root: &Path, repo_root: &Path) -> PathBuf {
+    let normalized = repo_root.to_string_lossy().replace('\\', "/");
+    let digest = Sha256::digest(normalized.as_bytes());
+    let hash = digest[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    root.join(hash)
+}
+
+/// Records `repo_root` as the owner of `state_dir`, or refuses when another
+/// repository already owns it.
+fn claim_state_dir(state_dir: &Path, repo_root: &Path) -> Result<()> {
+    let owner_path = state_dir.join(STATE_OWNER_FILE);
+    let repo = repo_root.to_string_lossy();
+    match fs::read_to_string(&owner_path) {
+        Ok(owner) if owner.trim_end() == repo => Ok(()),
+        Ok(owner) => anyhow::bail!(
+            "state dir {} belongs to {}, not {repo}; pass --state-dir to use another",
+            state_dir.display(),
+            owner.trim_end()
+        ),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            fs::write(&owner_path, format!("{repo}\n"))
+                .with_context(|| format!("failed to write {}", owner_path.display()))
+        }
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", owner_path.display())),
+    }
+}
+
+/// One entry in a workspace config: a bare path or a path with overrides.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum WorkspaceRepo {
+    Path(PathBuf),
+    Detailed {
+        path: PathBuf,
+        state_dir: Option<PathBuf>,
+        db: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkspaceConfig {
+    repos: Vec<WorkspaceRepo>,
+}
+
+/// Loads a JSON workspace config (`{"repos": ["../a", {"path": "../b", "db": "..."}]}`).
+/// Relative paths resolve against the config file's directory.
+pub fn load_workspace_config(config_path: &Path) -> Result<Vec<RuntimePaths>> {
+    let raw = fs::read_to_string(config_path)
+        .with_context(|| format!("failed to read workspace config {}", config_path.display()))?;
+    let config: WorkspaceConfig = serde_json::from_str(&raw)
+        .with_context(|| format!("invalid workspace config {}", config_path.display()))?;
+    let base = absolutize_path(config_path)?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+
+    config
+        .repos
+        .into_iter()
+        .map(|repo| {
+            let (path, state_dir, db) = match repo {
+                WorkspaceRepo::Path(path) => (path, None, None),
+                WorkspaceRepo::Detailed {
+                    path,
+                    state_dir,
+                    db,
+                } => (path, state_dir, db),
+            };
+            let state_dir = state_dir.map(|dir| base.join(dir));
+            let db = db.map(|db| base.join(db));
+            resolve_runtime_paths(&base.join(path), state_dir.as_deref(), db.as_deref())
+        })
+        .collect()
+}
+
+/// Rejects repo lists where two entries would share a database.
+pub fn ensure_distinct_repos(repos: &[RuntimePaths]) -> Result<()> {
+    for (index, paths) in repos.iter().enumerate() {
+        if let Some(other) = repos[..index]
+            .iter()
+            .find(|other| other.db_path == paths.db_path || other.repo_root == paths.repo_root)
+        {
+            anyhow::bail!(
+                "{} and {} resolve to the same repository or database",
+                other.repo_root.display(),
+                paths.repo_root.display()
+            );
+        }
+    }
     Ok(())
 }
 
@@ -81,27 +223,44 @@ fn absolutize_path(path: &Path) -> Result<PathBuf> {
     };
 
     if candidate.exists() {
-        let canonical = match fs::canonicalize(&candidate) {
-            Ok(path) => path,
-            Err(_) => candidate,
-        };
-        Ok(strip_windows_verbatim_prefix(canonical))
+        Ok(canonicalize(&candidate).unwrap_or(candidate))
     } else {
         Ok(candidate)
     }
 }
 
+/// `fs::canonicalize` without the Windows verbatim prefix, so canonical paths
+/// compare and `strip_prefix` cleanly against the repo root that
+/// [`resolve_runtime_paths`] hands out. Long paths stay usable because std
+/// re-adds the prefix itself when a path exceeds `MAX_PATH`.
+pub fn canonicalize(path: &Path) -> std::io::Result<PathBuf> {
+    fs::canonicalize(path).map(strip_windows_verbatim_prefix)
+}
+
 fn strip_windows_verbatim_prefix(path: PathBuf) -> PathBuf {
     #[cfg(windows)]
     {
-        let raw = path.to_string_lossy();
-        if let Some(rest) = raw.strip_prefix(r"\\?\") {
-            return PathBuf::from(rest);
+        if let Some(stripped) = strip_verbatim_prefix(&path.to_string_lossy()) {
+            return PathBuf::from(stripped);
         }
     }
     path
 }
 
+/// `\\?\C:\x` becomes `C:\x` and `\\?\UNC\server\share\x` becomes
+/// `\\server\share\x`. Other verbatim forms (volume GUIDs, devices) have no
+/// plain spelling and are left alone.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn strip_verbatim_prefix(raw: &str) -> Option<String> {
+    let rest = raw.strip_prefix(r"\\?\")?;
+    if let Some(share) = rest.strip_prefix(r"UNC\") {
+        return Some(format!(r"\\{share}"));
+    }
+    let bytes = rest.as_bytes();
+    let is_drive = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    is_drive.then(|| rest.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -285,6 +444,35 @@ mod tests {
         assert_eq!(paths.db_path, missing_db);
     }
 
+    #[test]
+    fn load_workspace_config_resolves_repos_relative_to_config() {
+        let temp = TempDir::new().expect("failed to create temp dir");
+        for repo in ["a", "b"] {
+            fs::create_dir_all(temp.path().join(repo).join(".git")).expect("failed to create .git");
+        }
+        let config = temp.path().join("workspace.json");
+        fs::write(
+            &config,
+            r#"{"repos": ["a", {"path": "b", "db": "state/b.db"}]}"#,
+        )
+        .expect("failed to write config");
+
+        let repos = load_workspace_config(&config).expect("load_workspace_config failed");
+        let root = fs::canonicalize(temp.path()).expect("canonicalize failed");
+        assert_eq!(repos.len(), 2);
+        assert_eq!(repos[0].repo_root, root.join("a"));
+        assert_eq!(
+            repos[0].db_path,
+            root.join("a").join(STATE_DIR_NAME).join(DEFAULT_DB_FILE)
+        );
+        assert_eq!(repos[1].repo_root, root.join("b"));
+        assert_eq!(repos[1].db_path, root.join("state").join("b.db"));
+        ensure_distinct_repos(&repos).expect("repos should be distinct");
+
+        let duplicate = vec![repos[0].clone(), repos[0].clone()];
+        assert!(ensure_distinct_repos(&duplicate).is_err());
+    }
+
     #[cfg(not(windows))]
     #[test]
     fn resolve_runtime_paths_non_windows_verbatim_strip_is_passthrough() {
@@ -301,4 +489,65 @@ mod tests {
         assert_eq!(paths.state_dir, expected);
         assert!(!paths.state_dir.to_string_lossy().starts_with(r"\\?\"));
     }
+
+    #[test]
+    fn verbatim_prefixes_strip_to_drive_and_unc_spellings() {
+        assert_eq!(
+            strip_verbatim_prefix(r"\\?\C:\work\repo").as_deref(),
+            Some(r"C:\work\repo")
+        );
+        assert_eq!(
+            strip_verbatim_prefix(r"\\?\UNC\server\share\repo").as_deref(),
+            Some(r"\\server\share\repo")
+        );
+        assert_eq!(
+            strip_verbatim_prefix(r"\\?\Volume{1234}\repo"),
+            None,
+            "volume paths have no non-verbatim form"
+        );
+        assert_eq!(strip_verbatim_prefix(r"C:\work\repo"), None);
+    }
+
+    #[test]
+    fn resolve_runtime_paths_places_global_state_outside_the_repo() {
+        let temp = TempDir::new().expect("failed to create temp dir");
+        let repo_root = temp.path().join("repo");
+        fs::create_dir_all(repo_root.join(".git")).expect("failed to create .git");
+        fs::write(
+            repo_root.join(crate::config::CONFIG_FILE_NAME),
+            "[state]\nlocation = \"global\"\n",
+        )
+        .expect("failed to write config");
+
+        let paths =
+            resolve_runtime_paths(&repo_root, None, None).expect("resolve_runtime_paths failed");
+
+        let root = global_state_root().expect("global_state_root failed");
+        assert_eq!(paths.state_dir, global_state_dir(&root, &paths.repo_root));
+        assert!(!paths.state_dir.starts_with(&paths.repo_root));
+        assert_eq!(paths.db_path, paths.state_dir.join(DEFAULT_DB_FILE));
+
+        let explicit = temp.path().join("explicit");
+        let paths = resolve_runtime_paths(&repo_root, Some(&explicit), None)
+            .expect("resolve_runtime_paths failed");
+        assert_eq!(paths.state_dir, explicit);
+    }
+
+    #[test]
+    fn global_state_dirs_are_per_repo_and_claimed_by_one_owner() {
+        let temp = TempDir::new().expect("failed to create temp dir");
+        let root = temp.path().join("data");
+        let repo_a = Path::new("/work/a");
+        let repo_b = Path::new("/work/b");
+        let dir_a = global_state_dir(&root, repo_a);
+        assert_eq!(dir_a, global_state_dir(&root, repo_a));
+        assert_ne!(dir_a, global_state_dir(&root, repo_b));
+        assert_eq!(dir_a.parent(), Some(root.as_path()));
+
+        fs::create_dir_all(&dir_a).expect("failed to create state dir");
+        claim_state_dir(&dir_a, repo_a).expect("first claim failed");
+        claim_state_dir(&dir_a, repo_a).expect("owner can reclaim");
+        let err = claim_state_dir(&dir_a, repo_b).unwrap_err();
+        assert!(err.to_string().contains("belongs to /work/a"), "{err}");
+    }
 }
@@ -6,3 +6,5 @@
 (invocation_expression function: (_) @name) @reference.call
 (identifier) @reference.identifier
 (using_directive) @import
+(namespace_declaration name: (_) @namespace)
+(file_scoped_namespace_declaration name: (_) @namespace)
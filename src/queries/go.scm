@@ -3,4 +3,4 @@
 (type_declaration (type_spec name: (type_identifier) @name)) @definition.type
 (call_expression function: (_) @name) @reference.call
 [(identifier) (field_identifier) (type_identifier)] @reference.identifier
-(import_declaration) @import
+(import_spec path: (_) @import)
@@ -7,3 +7,7 @@
 (object_creation_expression type: (type_identifier) @name) @reference.call
 [(identifier) (type_identifier)] @reference.identifier
 (import_declaration) @import
+(package_declaration [(identifier) (scoped_identifier)] @namespace)
+(class_declaration name: (identifier) @relation.subject superclass: (superclass (_) @relation.extends))
+(class_declaration name: (identifier) @relation.subject interfaces: (super_interfaces (type_list (_) @relation.implements)))
+(interface_declaration name: (identifier) @relation.subject (extends_interfaces (type_list (_) @relation.extends)))
@@ -4,4 +4,10 @@
 (lexical_declaration (variable_declarator name: (identifier) @name value: [(arrow_function) (function_expression)])) @definition.function
 (call_expression function: (_) @name) @reference.call
 [(identifier) (property_identifier)] @reference.identifier
-(import_statement) @import
+(import_statement source: (string) @import)
+(export_statement source: (string) @import)
+(call_expression function: (identifier) @_require arguments: (arguments . (string) @import) (#eq? @_require "require"))
+(call_expression function: (import) arguments: (arguments . [(string) (template_string)] @import))
+(class_declaration name: (identifier) @relation.subject (class_heritage (_) @relation.extends))
+(import_specifier name: (identifier) @relation.aliases alias: (identifier) @relation.subject)
+(export_specifier name: (identifier) @relation.aliases alias: (identifier) @relation.subject)
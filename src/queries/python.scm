@@ -13,3 +13,9 @@
 ; Import statements
 (import_statement) @import
 (import_from_statement) @import
+
+; Type relations
+(class_definition name: (identifier) @relation.subject superclasses: (argument_list [(identifier) (attribute)] @relation.extends))
+
+; Renamed imports
+(import_from_statement name: (aliased_import name: (dotted_name) @relation.aliases alias: (identifier) @relation.subject))
@@ -33,3 +33,10 @@
 
 ; Use declarations (imports)
 (use_declaration) @import
+
+; Type relations
+(impl_item trait: (_) @relation.implements type: (_) @relation.subject)
+(trait_item name: (type_identifier) @relation.subject bounds: (trait_bounds (_) @relation.extends))
+
+; Renamed imports and re-exports
+(use_as_clause path: (_) @relation.aliases alias: (identifier) @relation.subject)
@@ -7,4 +7,12 @@
 (lexical_declaration (variable_declarator name: (identifier) @name value: [(arrow_function) (function_expression)])) @definition.function
 (call_expression function: (_) @name) @reference.call
 [(identifier) (property_identifier) (type_identifier)] @reference.identifier
-(import_statement) @import
+(import_statement source: (string) @import)
+(export_statement source: (string) @import)
+(call_expression function: (identifier) @_require arguments: (arguments . (string) @import) (#eq? @_require "require"))
+(call_expression function: (import) arguments: (arguments . [(string) (template_string)] @import))
+(class_declaration name: (type_identifier) @relation.subject (class_heritage (extends_clause value: (_) @relation.extends)))
+(class_declaration name: (type_identifier) @relation.subject (class_heritage (implements_clause (_) @relation.implements)))
+(interface_declaration name: (type_identifier) @relation.subject (extends_type_clause type: (_) @relation.extends))
+(import_specifier name: (identifier) @relation.aliases alias: (identifier) @relation.subject)
+(export_specifier name: (identifier) @relation.aliases alias: (identifier) @relation.subject)
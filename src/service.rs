@@ -0,0 +1,363 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Where `lumora serve` is registered to start at login.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServicePlatform {
+    /// A systemd user unit under `~/.config/systemd/user`.
+    Systemd,
+    /// A launchd agent under `~/Library/LaunchAgents`.
+    Launchd,
+    /// A Windows scheduled task that runs at logon.
+    WindowsTask,
+}
+
+impl ServicePlatform {
+    pub fn current() -> Self {
+        if cfg!(target_os = "macos") {
+            Self::Launchd
+        } else if cfg!(windows) {
+            Self::WindowsTask
+        } else {
+            Self::Systemd
+        }
+    }
+
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "systemd" => Some(Self::Systemd),
+            "launchd" => Some(Self::Launchd),
+            "windows" => Some(Self::WindowsTask),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Systemd => "systemd",
+            Self::Launchd => "launchd",
+            Self::WindowsTask => "windows",
+        }
+    }
+}
+
+/// Everything `setup-service` writes and runs, so `--dry-run` can show it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServicePlan {
+    pub platform: &'static str,
+    pub name: String,
+    /// The unit or plist to write; scheduled tasks are created by command
+    /// alone.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contents: Option<String>,
+    /// Commands that register and start the service, in order.
+    pub commands: Vec<ServiceCommand>,
+    /// How to check on the service once it is installed.
+    pub status_command: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceCommand {
+    pub args: Vec<String>,
+    /// Cleanup of a previous install, e.g. unloading an old agent; its
+    /// failure is ignored.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub may_fail: bool,
+}
+
+impl ServiceCommand {
+    fn new(args: &[&str]) -> Self {
+        Self {
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            may_fail: false,
+        }
+    }
+
+    fn cleanup(args: &[&str]) -> Self {
+        Self {
+            may_fail: true,
+            ..Self::new(args)
+        }
+    }
+}
+
+/// Builds the service definition that runs `exe serve` with `serve_args` for
+/// `repo_root`. `home` is the user's home directory; `config_home` overrides
+/// where systemd user units go (`$XDG_CONFIG_HOME`).
+pub fn plan_service(
+    platform: ServicePlatform,
+    exe: &Path,
+    repo_root: &Path,
+    serve_args: &[String],
+    home: &Path,
+    config_home: Option<&Path>,
+) -> ServicePlan {
+    let name = service_name(repo_root);
+    let mut argv = vec![exe.display().to_string(), "serve".to_string()];
+    argv.extend(serve_args.iter().cloned());
+
+    match platform {
+        ServicePlatform::Systemd => {
+            let unit_dir = config_home
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| home.join(".config"))
+                .join("systemd/user");
+            let unit = format!("{name}.service");
+            ServicePlan {
+                platform: platform.as_str(),
+                file_path: Some(unit_dir.join(&unit).display().to_string()),
+                contents: Some(systemd_unit(repo_root, &argv)),
+                commands: vec![
+                    ServiceCommand::new(&["systemctl", "--user", "daemon-reload"]),
+                    ServiceCommand::new(&["systemctl", "--user", "enable", &unit]),
+                    ServiceCommand::new(&["systemctl", "--user", "restart", &unit]),
+                ],
+                status_command: format!("systemctl --user status {unit}"),
+                name,
+            }
+        }
+        ServicePlatform::Launchd => {
+            let plist = home
+                .join("Library/LaunchAgents")
+                .join(format!("{name}.plist"))
+                .display()
+                .to_string();
+            ServicePlan {
+                platform: platform.as_str(),
+                contents: Some(launchd_plist(&name, repo_root, &argv)),
+                commands: vec![
+                    ServiceCommand::cleanup(&["launchctl", "unload", &plist]),
+                    ServiceCommand::new(&["launchctl", "load", "-w", &plist]),
+                ],
+                file_path: Some(plist),
+                status_command: format!("launchctl list {name}"),
+                name,
+            }
+        }
+        ServicePlatform::WindowsTask => {
+            let task_command = argv
+                .iter()
+                .map(|arg| windows_quote(arg))
+                .collect::<Vec<_>>()
+                .join(" ");
+            ServicePlan {
+                platform: platform.as_str(),
+                file_path: None,
+                contents: None,
+                commands: vec![
+                    ServiceCommand::new(&[
+                        "schtasks",
+                        "/Create",
+                        "/F",
+                        "/SC",
+                        "ONLOGON",
+                        "/TN",
+                        &name,
+                        "/TR",
+                        &task_command,
+                    ]),
+                    ServiceCommand::new(&["schtasks", "/Run", "/TN", &name]),
+                ],
+                status_command: format!("schtasks /Query /TN {name}"),
+                name,
+            }
+        }
+    }
+}
+
+/// Writes the plan's file and runs its commands, stopping at the first
+/// command that fails.
+pub fn install_service(plan: &ServicePlan) -> Result<()> {
+    if let (Some(path), Some(contents)) = (&plan.file_path, &plan.contents) {
+        let path = PathBuf::from(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+        fs::write(&path, contents)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    for command in &plan.commands {
+        let (program, args) = command
+            .args
+            .split_first()
+            .context("empty service command")?;
+        let status = Command::new(program).args(args).status();
+        if command.may_fail {
+            continue;
+        }
+        let status = status.with_context(|| format!("failed to launch `{program}`"))?;
+        anyhow::ensure!(
+            status.success(),
+            "`{}` failed; run it manually to see why",
+            command.args.join(" ")
+        );
+    }
+    Ok(())
+}
+
+/// `lumora-<repo dir>-<hash of the path>`: readable, and distinct for two
+/// checkouts that share a directory name.
+fn service_name(repo_root: &Path) -> String {
+    let dir = repo_root
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let mut slug = String::new();
+    for ch in dir.chars() {
+        if ch.is_ascii_alphanumeric() {
+            slug.push(ch);
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_matches('-');
+    let digest = Sha256::digest(repo_root.display().to_string().as_bytes());
+    let hash: String = digest[..4]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    if slug.is_empty() {
+        format!("lumora-{hash}")
+    } else {
+        format!("lumora-{slug}-{hash}")
+    }
+}
+
+fn systemd_unit(repo_root: &Path, argv: &[String]) -> String {
+    let exec = argv
+        .iter()
+        .map(|arg| systemd_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "[Unit]\n\
+         Description=Lumora index watcher for {repo}\n\
+         \n\
+         [Service]\n\
+         ExecStart={exec}\n\
+         WorkingDirectory={dir}\n\
+         Restart=on-failure\n\
+         RestartSec=5\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        repo = repo_root.display().to_string().replace('%', "%%"),
+        // systemd takes `WorkingDirectory=` literally, quotes included.
+        dir = repo_root.display().to_string().replace('%', "%%"),
+    )
+}
+
+/// Double-quotes an argument for `ExecStart=`; `%` starts a specifier, so it
+/// is doubled.
+fn systemd_quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%");
+    format!("\"{escaped}\"")
+}
+
+fn launchd_plist(label: &str, repo_root: &Path, argv: &[String]) -> String {
+    let arguments: String = argv
+        .iter()
+        .map(|arg| format!("        <string>{}</string>\n", xml_escape(arg)))
+        .collect();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+         <plist version=\"1.0\">\n\
+         <dict>\n\
+         \x20   <key>Label</key>\n\
+         \x20   <string>{label}</string>\n\
+         \x20   <key>ProgramArguments</key>\n\
+         \x20   <array>\n\
+         {arguments}\
+         \x20   </array>\n\
+         \x20   <key>WorkingDirectory</key>\n\
+         \x20   <string>{dir}</string>\n\
+         \x20   <key>RunAtLoad</key>\n\
+         \x20   <true/>\n\
+         \x20   <key>KeepAlive</key>\n\
+         \x20   <dict>\n\
+         \x20       <key>SuccessfulExit</key>\n\
+         \x20       <false/>\n\
+         \x20   </dict>\n\
+         </dict>\n\
+         </plist>\n",
+        label = xml_escape(label),
+        dir = xml_escape(&repo_root.display().to_string()),
+    )
+}
+
+fn xml_escape(raw: &str) -> String {
+    raw.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Quotes an argument inside a scheduled task's `/TR` command line.
+fn windows_quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    format!("\"{}\"", arg.replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_plan(platform: ServicePlatform) -> ServicePlan {
+        plan_service(
+            platform,
+            Path::new("/opt/bin/lumora"),
+            Path::new("/home/ada/My Repo"),
+            &["--repo".to_string(), "/home/ada/My Repo".to_string()],
+            Path::new("/home/ada"),
+            None,
+        )
+    }
+
+    #[test]
+    fn systemd_unit_runs_serve_for_the_repo() {
+        let plan = sample_plan(ServicePlatform::Systemd);
+        assert!(plan.name.starts_with("lumora-my-repo-"));
+        assert_eq!(
+            plan.file_path.as_deref(),
+            Some(format!("/home/ada/.config/systemd/user/{}.service", plan.name).as_str())
+        );
+        let unit = plan.contents.unwrap();
+        assert!(unit.contains(
+            "ExecStart=\"/opt/bin/lumora\" \"serve\" \"--repo\" \"/home/ada/My Repo\"\n"
+        ));
+        assert!(unit.contains("\nWorkingDirectory=/home/ada/My Repo\n"));
+        assert!(unit.contains("WantedBy=default.target"));
+        assert_eq!(plan.commands.len(), 3);
+        assert_ne!(plan.name, service_name(Path::new("/srv/My Repo")));
+    }
+
+    #[test]
+    fn launchd_and_windows_plans_quote_their_arguments() {
+        let plan = sample_plan(ServicePlatform::Launchd);
+        let plist = plan.contents.unwrap();
+        assert!(plist.contains("<string>/home/ada/My Repo</string>"));
+        assert!(plist.contains(&format!("<string>{}</string>", plan.name)));
+        assert!(plan.commands[0].may_fail);
+
+        let plan = sample_plan(ServicePlatform::WindowsTask);
+        assert!(plan.file_path.is_none());
+        assert_eq!(
+            plan.commands[0].args.last().unwrap(),
+            "/opt/bin/lumora serve --repo \"/home/ada/My Repo\""
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, DatabaseName, OpenFlags};
+use serde::Serialize;
+
+use crate::paths::RuntimePaths;
+use crate::storage::{GraphStore, BUSY_TIMEOUT};
+
+pub const SNAPSHOT_DIR_NAME: &str = "snapshots";
+const SNAPSHOT_EXTENSION: &str = "db";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotInfo {
+    pub name: String,
+    pub path: String,
+    pub size_bytes: u64,
+    /// Seconds since the Unix epoch when the snapshot file was last written.
+    pub created_unix: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<String>,
+}
+
+pub fn snapshot_dir(paths: &RuntimePaths) -> PathBuf {
+    paths.state_dir.join(SNAPSHOT_DIR_NAME)
+}
+
+/// Copies the live graph into `snapshots/<name>.db` with SQLite's online
+/// backup API, so a concurrently running daemon does not have to stop.
+/// Without a name, the snapshot is named after the current UTC time.
+pub fn create_snapshot(paths: &RuntimePaths, name: Option<&str>) -> Result<SnapshotInfo> {
+    anyhow::ensure!(
+        paths.db_path.exists(),
+        "no database at {}; run `lumora index` first",
+        paths.db_path.display()
+    );
+    let source = Connection::open_with_flags(&paths.db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("failed to open {}", paths.db_path.display()))?;
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => source.query_row("SELECT strftime('%Y%m%d-%H%M%S', 'now')", [], |row| {
+            row.get(0)
+        })?,
+    };
+    let target = snapshot_path(paths, &name)?;
+    anyhow::ensure!(
+        !target.exists(),
+        "snapshot `{name}` already exists at {}",
+        target.display()
+    );
+
+    fs::create_dir_all(snapshot_dir(paths))
+        .with_context(|| format!("failed to create {}", snapshot_dir(paths).display()))?;
+    source
+        .backup(DatabaseName::Main, &target, None)
+        .with_context(|| format!("failed to write snapshot {}", target.display()))?;
+    snapshot_info(&target)
+}
+
+/// Lists snapshots in name order (default names sort chronologically).
+pub fn list_snapshots(paths: &RuntimePaths) -> Result<Vec<SnapshotInfo>> {
+    let dir = snapshot_dir(paths);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut out = Vec::new();
+    for entry in fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) == Some(SNAPSHOT_EXTENSION) {
+            out.push(snapshot_info(&path)?);
+        }
+    }
+    out.sort_by(|left, right| left.name.cmp(&right.name));
+    Ok(out)
+}
+
+/// Replaces the live graph with a snapshot's contents in place, through the
+/// backup API, so open readers see the restored state on their next query.
+pub fn restore_snapshot(paths: &RuntimePaths, name: &str) -> Result<SnapshotInfo> {
+    let source = snapshot_path(paths, name)?;
+    anyhow::ensure!(
+        source.exists(),
+        "snapshot `{name}` not found in {}; run `lumora snapshot list`",
+        snapshot_dir(paths).display()
+    );
+    let info = snapshot_info(&source)?;
+
+    let mut target = Connection::open(&paths.db_path)
+        .with_context(|| format!("failed to open {}", paths.db_path.display()))?;
+    target.busy_timeout(BUSY_TIMEOUT)?;
+    target
+        .restore(
+            DatabaseName::Main,
+            &source,
+            None::<fn(rusqlite::backup::Progress)>,
+        )
+        .with_context(|| format!("failed to restore snapshot {}", source.display()))?;
+    Ok(info)
+}
+
+fn snapshot_path(paths: &RuntimePaths, name: &str) -> Result<PathBuf> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.'));
+    anyhow::ensure!(
+        valid,
+        "invalid snapshot name `{name}`: use letters, digits, `-`, `_`, or `.`"
+    );
+    Ok(snapshot_dir(paths).join(format!("{name}.{SNAPSHOT_EXTENSION}")))
+}
+
+fn snapshot_info(path: &Path) -> Result<SnapshotInfo> {
+    let metadata =
+        fs::metadata(path).with_context(|| format!("failed to stat {}", path.display()))?;
+    let created_unix = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    Ok(SnapshotInfo {
+        name: path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        path: path.display().to_string(),
+        size_bytes: metadata.len(),
+        created_unix,
+        schema_version: GraphStore::read_schema_version(path).ok().flatten(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn temp_paths(temp: &TempDir) -> RuntimePaths {
+        let repo_root = temp.path().to_path_buf();
+        RuntimePaths {
+            state_dir: repo_root.join(".lumora"),
+            db_path: repo_root.join(".lumora").join("graph.db"),
+            repo_root,
+        }
+    }
+
+    fn file_count(paths: &RuntimePaths) -> i64 {
+        Connection::open(&paths.db_path)
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    fn add_file(paths: &RuntimePaths, path: &str) {
+        Connection::open(&paths.db_path)
+            .unwrap()
+            .execute(
+                "INSERT INTO files(path, lang, content_hash, size_bytes, indexed_at)
+                 VALUES(?1, 'rust', 'h', 1, datetime('now'))",
+                [path],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn snapshot_create_list_and_restore_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let paths = temp_paths(&temp);
+        fs::create_dir_all(&paths.state_dir).unwrap();
+        drop(GraphStore::open(&paths.db_path).unwrap());
+        add_file(&paths, "src/a.rs");
+
+        let created = create_snapshot(&paths, Some("before-refactor")).unwrap();
+        assert_eq!(created.name, "before-refactor");
+        assert_eq!(created.schema_version.as_deref(), Some("1"));
+        assert!(create_snapshot(&paths, Some("before-refactor")).is_err());
+        assert!(create_snapshot(&paths, Some("../escape")).is_err());
+
+        add_file(&paths, "src/b.rs");
+        assert_eq!(file_count(&paths), 2);
+
+        let listed = list_snapshots(&paths).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "before-refactor");
+
+        restore_snapshot(&paths, "before-refactor").unwrap();
+        assert_eq!(file_count(&paths), 1);
+        assert!(restore_snapshot(&paths, "missing").is_err());
+    }
+}
@@ -1,18 +1,167 @@
 use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
 use std::path::Path;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
-use rusqlite::{params, Connection, OptionalExtension};
+use regex::Regex;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::ValueRef;
+use rusqlite::{
+    params, Connection, DatabaseName, OpenFlags, OptionalExtension, Savepoint, Transaction,
+    TransactionBehavior,
+};
 use serde_json::json;
 
+use crate::embeddings::{self, EmbeddingRecord};
+use crate::error::{coded, ErrorCode};
+use crate::graph_query::{self, GraphQueryResult};
+use crate::indexer::millis;
+use crate::low_signal::LowSignalNames;
 use crate::model::{
-    CloneHotspot, CloneMatch, DependencyPath, Entity, FileExtraction, PathHop, ReferenceLocation,
-    RelatedEdge, SelectorSuggestion, SliceResult, SymbolLocation, TopFileSummary,
+    AnnotationRow, Arity, CloneHotspot, CloneMatch, CloneRegion, Definition, DependencyPath,
+    DependencyPaths, DirClonePair, DirOutline, EdgeOrigin, EnclosingSymbolGroup, Entity,
+    FileExtraction, FileOutline, FileProfile, Fingerprint, Import, ImportDirection, ImportGraph,
+    ImporterDirectory, IndexedFile, LanguageCoverage, ModuleDependencies, ModuleDependency,
+    ModuleImporter, Neighbor, NeighborGroup, OutlineEntry, PackageDependencies, PackageInfo,
+    PackageNode, PathHop, ReferenceLocation, RelatedEdge, SelectorSuggestion, SemanticMatch,
+    SliceResult, SpanOffsets, SymbolLocation, SymbolNeighbors, TopFileSummary, TransitiveImport,
+    WhoImports,
 };
 
+/// Version written to `meta.schema_version` whenever a store is opened.
+pub const SCHEMA_VERSION: &str = "1";
+
+/// Cached parses from another build are ignored, since grammar or query
+/// changes can alter extraction output for identical content. Bump the
+/// suffix when extraction output changes between releases.
+const PARSE_CACHE_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "+10");
+
+/// SQLite virtual machine steps between deadline checks while a query runs.
+const DEADLINE_CHECK_OPS: i32 = 1_000;
+
+/// How long a statement waits for another connection's lock before failing
+/// with `database is locked`. Index runs hold the write lock for their whole
+/// batch, so this is generous.
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub struct GraphStore {
     conn: Connection,
+    /// Set while an [`IndexSession`] holds the outer transaction; reference
+    /// resolution and orphan cleanup are then deferred to
+    /// [`IndexSession::finish`].
+    in_session: bool,
+    /// Files whose reference edges need `resolved_symbol_key` recomputed.
+    dirty_files: HashSet<String>,
+    /// Wall-clock limit for read queries, see [`GraphStore::set_query_deadline`].
+    deadline: Option<QueryDeadline>,
+    /// Names slices rank down and test links skip; see [`LowSignalNames`].
+    low_signal: LowSignalNames,
+}
+
+/// A wall-clock budget shared by a [`GraphStore`] and its SQLite progress
+/// handler. It fires once: the statement running when time runs out is
+/// interrupted and traversal loops stop, but follow-up lookups needed to
+/// shape the partial result still run.
+#[derive(Debug, Clone)]
+pub struct QueryDeadline {
+    state: Arc<DeadlineState>,
+}
+
+#[derive(Debug)]
+struct DeadlineState {
+    at: Mutex<Option<Instant>>,
+    timed_out: AtomicBool,
+}
+
+impl QueryDeadline {
+    pub fn after(timeout: Duration) -> Self {
+        Self {
+            state: Arc::new(DeadlineState {
+                at: Mutex::new(Some(Instant::now() + timeout)),
+                timed_out: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.state.timed_out.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Returns true once the deadline has passed, disarming it the first
+    /// time so only one statement is interrupted.
+    pub fn expired(&self) -> bool {
+        if self.timed_out() {
+            return true;
+        }
+        let mut at = self.state.at.lock().unwrap_or_else(PoisonError::into_inner);
+        match *at {
+            Some(deadline) if Instant::now() >= deadline => {
+                *at = None;
+                self.state.timed_out.store(true, atomic::Ordering::Relaxed);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Progress handler body: interrupts only while the deadline is armed.
+    fn interrupt_now(&self) -> bool {
+        !self.timed_out() && self.expired()
+    }
+}
+
+/// A batch of index writes sharing one transaction. Each write method still
+/// runs in its own savepoint, so a failing file rolls back alone. Dropping
+/// the session without calling [`IndexSession::finish`] rolls back the batch.
+pub struct IndexSession<'a> {
+    store: &'a mut GraphStore,
+    finished: bool,
+}
+
+impl IndexSession<'_> {
+    /// Runs the deferred reference resolution, orphan cleanup, and parse cache
+    /// pruning once and commits the batch.
+    pub fn finish(mut self) -> Result<()> {
+        self.finished = true;
+        self.store.in_session = false;
+        let result = self
+            .store
+            .after_write()
+            .and_then(|_| self.store.prune_parse_cache())
+            .and_then(|_| Ok(self.store.conn.execute_batch("COMMIT")?));
+        if result.is_err() {
+            let _ = self.store.conn.execute_batch("ROLLBACK");
+        }
+        result
+    }
+}
+
+impl std::ops::Deref for IndexSession<'_> {
+    type Target = GraphStore;
+
+    fn deref(&self) -> &GraphStore {
+        self.store
+    }
+}
+
+impl std::ops::DerefMut for IndexSession<'_> {
+    fn deref_mut(&mut self) -> &mut GraphStore {
+        self.store
+    }
+}
+
+impl Drop for IndexSession<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.store.in_session = false;
+            self.store.dirty_files.clear();
+            let _ = self.store.conn.execute_batch("ROLLBACK");
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -49,6 +198,22 @@ pub struct ReferenceQueryOptions {
     pub offset: usize,
     pub dedup: bool,
     pub order: SortOrder,
+    /// Opaque token from a previous page's `next_cursor`; takes precedence over `offset`.
+    pub cursor: Option<String>,
+    /// Keep only edges the resolution pass bound to a definition.
+    pub resolved_only: bool,
+    /// Return nothing for names marked `external` (no definition in the repo).
+    pub exclude_externals: bool,
+    /// Drop references whose `confidence` is below this.
+    pub min_confidence: Option<f64>,
+    /// Drop edges whose `origin` is `heuristic`.
+    pub exclude_heuristic: bool,
+    /// Keep references made from test files; `false` leaves only
+    /// production call sites.
+    pub include_tests: bool,
+    /// Keep references made from files marked generated (`@generated`,
+    /// `DO NOT EDIT`), which are left out by default.
+    pub include_generated: bool,
 }
 
 impl Default for ReferenceQueryOptions {
@@ -62,6 +227,13 @@ impl Default for ReferenceQueryOptions {
             offset: 0,
             dedup: true,
             order: SortOrder::ScoreDesc,
+            cursor: None,
+            resolved_only: false,
+            exclude_externals: false,
+            min_confidence: None,
+            exclude_heuristic: false,
+            include_tests: true,
+            include_generated: false,
         }
     }
 }
@@ -73,6 +245,10 @@ pub struct SliceQueryOptions {
     pub suppress_low_signal_repeats: bool,
     pub low_signal_name_cap: usize,
     pub prefer_project_symbols: bool,
+    /// Leave `external` names out of the slice and do not expand through them.
+    pub exclude_externals: bool,
+    /// Skip edges whose `origin` is `heuristic`, and do not expand through them.
+    pub exclude_heuristic: bool,
 }
 
 impl Default for SliceQueryOptions {
@@ -83,6 +259,8 @@ impl Default for SliceQueryOptions {
             suppress_low_signal_repeats: true,
             low_signal_name_cap: 1,
             prefer_project_symbols: true,
+            exclude_externals: false,
+            exclude_heuristic: false,
         }
     }
 }
@@ -92,6 +270,16 @@ pub struct CloneQueryOptions {
     pub min_similarity: f64,
     pub limit: usize,
     pub offset: usize,
+    /// Drops candidates in a different language than the queried file, e.g.
+    /// JSON fixtures whose fingerprints happen to overlap a YAML file.
+    pub same_language_only: bool,
+    /// Keeps only candidates in this language, e.g. `python` when looking
+    /// for a Rust file's ported counterpart.
+    pub language: Option<String>,
+    /// Keep candidates classified as test files.
+    pub include_tests: bool,
+    /// Keep candidates marked as generated code.
+    pub include_generated: bool,
 }
 
 impl Default for CloneQueryOptions {
@@ -100,6 +288,80 @@ impl Default for CloneQueryOptions {
             min_similarity: 0.02,
             limit: 50,
             offset: 0,
+            same_language_only: false,
+            language: None,
+            include_tests: true,
+            include_generated: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DirOutlineOptions {
+    pub max_depth: Option<usize>,
+    pub max_files: usize,
+}
+
+impl Default for DirOutlineOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            max_files: 200,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SemanticSearchOptions {
+    pub kind: Option<String>,
+    pub file_glob: Option<String>,
+    pub min_score: f64,
+    pub limit: usize,
+}
+
+impl Default for SemanticSearchOptions {
+    fn default() -> Self {
+        Self {
+            kind: None,
+            file_glob: None,
+            min_score: 0.1,
+            limit: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SymbolSearchOptions {
+    /// Keeps definitions whose stored kind contains this text, so `function`
+    /// matches `function_item` and `function_declaration`.
+    pub kind: Option<String>,
+    pub language: Option<String>,
+    /// Keeps definitions with this stored visibility (`public`, `private`,
+    /// `protected`, `internal`); definitions with none recorded are dropped.
+    pub visibility: Option<String>,
+    /// Keeps definitions whose name no reference or call anywhere in the
+    /// index uses. Matching is by name, so this errs toward keeping a symbol
+    /// out of the list.
+    pub unreferenced: bool,
+    /// Keep definitions in test files. With `false`, `unreferenced` also
+    /// ignores uses from test files, so code only tests call counts as dead.
+    pub include_tests: bool,
+    /// With `unreferenced`, keep definitions in generated files, which often
+    /// carry API surface nothing in the repo calls.
+    pub include_generated: bool,
+    pub limit: usize,
+}
+
+impl Default for SymbolSearchOptions {
+    fn default() -> Self {
+        Self {
+            kind: None,
+            language: None,
+            visibility: None,
+            unreferenced: false,
+            include_tests: true,
+            include_generated: false,
+            limit: 200,
         }
     }
 }
@@ -112,6 +374,28 @@ pub struct PaginationInfo {
     pub returned: usize,
     pub has_more: bool,
     pub next_offset: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+impl PaginationInfo {
+    /// One line for the end of paged text output, such as `showing 1–200 of
+    /// 950, next: --offset 200`. `None` when the page holds every row.
+    pub fn text_hint(&self) -> Option<String> {
+        if self.returned == 0 || (self.offset == 0 && !self.has_more) {
+            return None;
+        }
+        let range = format!(
+            "showing {}–{} of {}",
+            self.offset + 1,
+            self.offset + self.returned,
+            self.total
+        );
+        Some(match self.next_offset {
+            Some(next) => format!("{range}, next: --offset {next}"),
+            None => range,
+        })
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -120,12 +404,158 @@ pub struct CloneAnalysis {
     pub candidate_files: usize,
     pub surviving_candidates: usize,
     pub filtered_by_threshold: usize,
+    /// Overlapping files dropped by `same_language_only` or `language`
+    /// before scoring; not counted in `candidate_files`.
+    pub filtered_by_language: usize,
+    /// Overlapping test files dropped by `include_tests: false`; not counted
+    /// in `candidate_files`.
+    pub filtered_tests: usize,
+    /// Overlapping generated files dropped unless `include_generated`; not
+    /// counted in `candidate_files`.
+    pub filtered_generated: usize,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_candidate_similarity: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub suggested_min_similarity: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub empty_reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params_warning: Option<String>,
+}
+
+/// How much of one directory tree is duplicated in another, as a whole.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DirCloneSummary {
+    pub dir: String,
+    pub other_dir: String,
+    /// Fingerprinted files under each directory that the test and generated
+    /// filters keep.
+    pub files: i64,
+    pub other_files: i64,
+    /// Distinct fingerprints under `dir` also found under `other_dir`.
+    pub shared_fingerprints: i64,
+    /// `shared_fingerprints` over the larger tree's distinct fingerprints.
+    pub similarity: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub empty_reason: Option<String>,
+}
+
+/// Edges not marked `heuristic`; edges stored before origins were recorded
+/// have none and are kept.
+const NOT_HEURISTIC_EDGE: &str = "(e.origin IS NULL OR e.origin != 'heuristic')";
+
+/// Paths of files classified as tests when they were indexed.
+const TEST_FILE_PATHS: &str = "SELECT path FROM files WHERE is_test = 1";
+
+/// Paths of files whose header marks them as generated code.
+const GENERATED_FILE_PATHS: &str = "SELECT path FROM files WHERE is_generated = 1";
+
+/// Rows whose `file_path` is set but not tracked in `files`.
+const UNTRACKED_FILE: &str = "file_path IS NOT NULL AND file_path NOT IN (SELECT path FROM files)";
+
+/// Edges whose source or target entity row no longer exists.
+const DANGLING_EDGE: &str = "src_entity_id NOT IN (SELECT id FROM entities)
+    OR dst_entity_id NOT IN (SELECT id FROM entities)";
+
+/// Columns two edges must share to count as the same edge.
+const EDGE_IDENTITY: &str =
+    "src_entity_id, dst_entity_id, edge_type, file_path, line, col, end_line, end_col, meta_json";
+
+/// Rows removed (or, for a dry run, that would be removed) by
+/// [`GraphStore::prune`].
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct PruneReport {
+    pub dry_run: bool,
+    /// Fingerprints of files no longer tracked.
+    pub fingerprints: usize,
+    /// Embeddings of files no longer tracked.
+    pub embeddings: usize,
+    /// Edges recorded by untracked files or touching entities being removed.
+    pub edges: usize,
+    /// Symbols and file nodes belonging to untracked files.
+    pub entities: usize,
+    /// `symbol_name` and `module` nodes with no remaining edges.
+    pub orphan_nodes: usize,
+    /// Parse cache entries from other versions or for untracked content.
+    pub parse_cache: usize,
+    pub vacuumed: bool,
+    pub bytes_before: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes_after: Option<u64>,
+}
+
+impl PruneReport {
+    pub fn total_rows(&self) -> usize {
+        self.fingerprints
+            + self.embeddings
+            + self.edges
+            + self.entities
+            + self.orphan_nodes
+            + self.parse_cache
+    }
+}
+
+/// Outcome of [`GraphStore::checkpoint_wal`], as `PRAGMA wal_checkpoint`
+/// reports it.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct WalCheckpoint {
+    /// Another connection blocked the checkpoint from completing.
+    pub busy: bool,
+    /// Frames in the WAL before the checkpoint, or -1 outside WAL mode.
+    pub log_frames: i64,
+    /// Frames copied into the database.
+    pub checkpointed_frames: i64,
+}
+
+pub const COMPACT_MIN_FREE_RATIO: f64 = 0.1;
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct OptimizeReport {
+    pub analyze_ms: f64,
+    pub bytes_before: u64,
+    pub free_bytes_before: u64,
+    pub compacted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compact_ms: Option<f64>,
+    pub bytes_after: u64,
+    pub bytes_reclaimed: u64,
+}
+
+/// Inconsistencies found by [`GraphStore::verify`]. With `fixed` set they
+/// have been repaired; the counts are what was found before the repair.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct VerifyReport {
+    pub fixed: bool,
+    /// Edges whose source or target entity row is gone.
+    pub dangling_edges: usize,
+    /// Tracked files that no longer exist in the repo.
+    pub missing_files: Vec<String>,
+    /// Entities recorded for files that are not tracked.
+    pub stale_entities: usize,
+    /// Fingerprints of files that are not tracked.
+    pub untracked_fingerprints: usize,
+    /// Embeddings of files that are not tracked.
+    pub untracked_embeddings: usize,
+    /// Extra entity rows sharing a key with another row.
+    pub duplicate_keys: usize,
+    /// Extra edges identical to another edge.
+    pub duplicate_edges: usize,
+    /// Problems reported by `PRAGMA quick_check`. These are not repaired;
+    /// rebuild with `lumora index --full` or restore a snapshot.
+    pub integrity_errors: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn issues(&self) -> usize {
+        self.dangling_edges
+            + self.missing_files.len()
+            + self.stale_entities
+            + self.untracked_fingerprints
+            + self.untracked_embeddings
+            + self.duplicate_keys
+            + self.duplicate_edges
+            + self.integrity_errors.len()
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -137,6 +567,22 @@ pub struct FreshnessInfo {
     pub schema_version: Option<String>,
     pub stale_after_hours: u64,
     pub is_stale: bool,
+    /// Files the last index run could not read, parse, or store; see
+    /// [`GraphStore::index_errors`].
+    pub index_errors: i64,
+    /// Paths parsed so far when the index is lazy; absent for a full index.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lazy_coverage: Option<Vec<String>>,
+}
+
+/// A file left out of (or stale in) the graph because indexing it failed.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct IndexError {
+    pub path: String,
+    /// `read`, `parse`, or `write`.
+    pub stage: String,
+    pub message: String,
+    pub failed_at: String,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -148,24 +594,110 @@ pub struct SelectorSuggestOptions {
     pub fuzzy: bool,
 }
 
+/// Assumed for files indexed before parameters were recorded per file.
+const LEGACY_FINGERPRINT_PARAMS: &str = "k_gram=5,window=4,min_tokens=5";
+
+/// An entity's degree centrality for selector discovery: the edges touching
+/// it, plus, for a symbol, the calls and references to the name it defines,
+/// since those land on the `symbol_name` node rather than the symbol.
+const ENTITY_DEGREE_SQL: &str = "(
+    (SELECT COUNT(*) FROM edges WHERE src_entity_id = entities.id)
+    + (SELECT COUNT(*) FROM edges WHERE dst_entity_id = entities.id)
+    + CASE WHEN entity_type = 'symbol' THEN (
+        SELECT COUNT(*)
+        FROM edges n
+        JOIN edges r ON r.dst_entity_id = n.dst_entity_id AND r.edge_type != 'names'
+        WHERE n.src_entity_id = entities.id AND n.edge_type = 'names'
+    ) ELSE 0 END
+)";
+
+/// Largest edit distance a "did you mean" suggestion may be from the query;
+/// shorter names allow less (a quarter of their length, at least one).
+const MAX_DID_YOU_MEAN_DISTANCE: usize = 3;
+
+/// Partial paths `dependency_paths` expands before it gives up and reports
+/// what it found as truncated.
+const MAX_PATH_SEARCH_STEPS: usize = 20_000;
+
+/// The edge a dependency path took into an entity, and where that edge was
+/// recorded.
+#[derive(Debug, Clone)]
+struct EdgeStep {
+    edge_type: String,
+    file_path: Option<String>,
+    line: Option<i64>,
+}
+
+impl EdgeStep {
+    /// The last step from a file into the package that contains it.
+    fn contains() -> Self {
+        Self {
+            edge_type: "contains".to_string(),
+            file_path: None,
+            line: None,
+        }
+    }
+}
+
+/// How many alternatives an ambiguous selector lists.
+const SELECTOR_CANDIDATE_LIMIT: usize = 5;
+
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct SelectorResolution {
     pub parsed_as: String,
     pub matched: usize,
     pub selected_key: Option<String>,
+    /// Set when several entities could be meant; `selected_key` is then just
+    /// the first of `candidates`, and `pick` chooses another.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub ambiguous: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<SelectorCandidate>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SelectorCandidate {
+    pub key: String,
+    pub entity_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<i64>,
+}
+
+impl SelectorCandidate {
+    fn from_entity(entity: &Entity) -> Self {
+        let kind = entity
+            .meta_json
+            .as_deref()
+            .and_then(|meta| serde_json::from_str::<serde_json::Value>(meta).ok())
+            .and_then(|meta| meta.get("kind")?.as_str().map(str::to_string));
+        Self {
+            key: entity.key.clone(),
+            entity_type: entity.entity_type.clone(),
+            kind,
+            lang: entity.lang.clone(),
+            file_path: entity.file_path.clone(),
+            line: entity.line,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 struct SelectorLookup {
     parsed_as: String,
     candidates: Vec<Entity>,
-    entity: Option<Entity>,
 }
 
 impl GraphStore {
     pub fn open(db_path: &Path) -> Result<Self> {
         let conn = Connection::open(db_path)
             .with_context(|| format!("failed to open sqlite db at {}", db_path.display()))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
 
         conn.execute_batch(
             "
@@ -178,7 +710,11 @@ impl GraphStore {
                 lang TEXT NOT NULL,
                 content_hash TEXT NOT NULL,
                 size_bytes INTEGER NOT NULL,
-                indexed_at TEXT NOT NULL
+                indexed_at TEXT NOT NULL,
+                line_count INTEGER,
+                mtime_ns INTEGER,
+                is_test INTEGER NOT NULL DEFAULT 0,
+                is_generated INTEGER NOT NULL DEFAULT 0
             );
 
             CREATE TABLE IF NOT EXISTS entities (
@@ -204,6 +740,8 @@ impl GraphStore {
                 line INTEGER,
                 col INTEGER,
                 meta_json TEXT,
+                origin TEXT,
+                confidence REAL,
                 FOREIGN KEY(src_entity_id) REFERENCES entities(id) ON DELETE CASCADE,
                 FOREIGN KEY(dst_entity_id) REFERENCES entities(id) ON DELETE CASCADE
             );
@@ -215,6 +753,32 @@ impl GraphStore {
                 span_end INTEGER NOT NULL
             );
 
+            CREATE TABLE IF NOT EXISTS embeddings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                file_path TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                label TEXT NOT NULL,
+                detail TEXT,
+                start_line INTEGER NOT NULL,
+                end_line INTEGER NOT NULL,
+                vector BLOB NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS parse_cache (
+                content_hash TEXT NOT NULL,
+                lang TEXT NOT NULL,
+                version TEXT NOT NULL,
+                extraction_json TEXT NOT NULL,
+                PRIMARY KEY(content_hash, lang)
+            );
+
+            CREATE TABLE IF NOT EXISTS index_errors (
+                path TEXT PRIMARY KEY,
+                stage TEXT NOT NULL,
+                message TEXT NOT NULL,
+                failed_at TEXT NOT NULL
+            );
+
             CREATE TABLE IF NOT EXISTS meta (
                 key TEXT PRIMARY KEY,
                 value TEXT NOT NULL
@@ -228,16 +792,155 @@ impl GraphStore {
             CREATE INDEX IF NOT EXISTS idx_edges_file ON edges(file_path);
             CREATE INDEX IF NOT EXISTS idx_fingerprints_hash ON fingerprints(fp_hash, file_path);
             CREATE INDEX IF NOT EXISTS idx_fingerprints_file ON fingerprints(file_path);
+            CREATE INDEX IF NOT EXISTS idx_embeddings_file ON embeddings(file_path);
             ",
         )?;
 
+        // Columns added after schema v1 shipped; older databases pick them up
+        // here and fill them in as files are reindexed.
+        add_column_if_missing(&conn, "files", "line_count", "INTEGER")?;
+        add_column_if_missing(&conn, "fingerprints", "start_line", "INTEGER")?;
+        add_column_if_missing(&conn, "fingerprints", "end_line", "INTEGER")?;
+        add_column_if_missing(&conn, "files", "fingerprint_params", "TEXT")?;
+        add_column_if_missing(&conn, "edges", "end_col", "INTEGER")?;
+        add_column_if_missing(&conn, "edges", "origin", "TEXT")?;
+        add_column_if_missing(&conn, "edges", "confidence", "REAL")?;
+        add_column_if_missing(&conn, "files", "mtime_ns", "INTEGER")?;
+        if add_column_if_missing(&conn, "files", "is_test", "INTEGER NOT NULL DEFAULT 0")? {
+            // The file entity already carried the classification.
+            conn.execute_batch(
+                "UPDATE files SET is_test = 1
+                 WHERE path IN (
+                     SELECT file_path FROM entities
+                     WHERE entity_type = 'file' AND json_extract(meta_json, '$.kind') = 'test'
+                 )",
+            )?;
+        }
+        add_column_if_missing(&conn, "files", "is_generated", "INTEGER NOT NULL DEFAULT 0")?;
+        if add_column_if_missing(&conn, "edges", "end_line", "INTEGER")? {
+            // Reference ends used to live in the edge meta; move them over.
+            conn.execute_batch(
+                "UPDATE edges
+                 SET end_line = json_extract(meta_json, '$.end_line'),
+                     end_col = json_extract(meta_json, '$.end_col'),
+                     meta_json = json_remove(meta_json, '$.end_line', '$.end_col')
+                 WHERE json_extract(meta_json, '$.end_line') IS NOT NULL",
+            )?;
+        }
+        register_regexp_function(&conn)?;
+
         conn.execute(
-            "INSERT INTO meta(key, value) VALUES('schema_version', '1')
+            "INSERT INTO meta(key, value) VALUES('schema_version', ?1)
              ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+            params![SCHEMA_VERSION],
+        )?;
+
+        Ok(Self {
+            conn,
+            in_session: false,
+            dirty_files: HashSet::new(),
+            deadline: None,
+            low_signal: LowSignalNames::default(),
+        })
+    }
+
+    /// Starts a batch session for an index run; see [`IndexSession`].
+    pub fn begin_index_session(&mut self) -> Result<IndexSession<'_>> {
+        anyhow::ensure!(!self.in_session, "an index session is already active");
+        self.conn.execute_batch("BEGIN IMMEDIATE")?;
+        self.in_session = true;
+        Ok(IndexSession {
+            store: self,
+            finished: false,
+        })
+    }
+
+    /// Reads `meta.schema_version` from an existing database without creating
+    /// or migrating anything.
+    pub fn read_schema_version(db_path: &Path) -> Result<Option<String>> {
+        let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("failed to open sqlite db at {}", db_path.display()))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        let has_meta: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'meta')",
             [],
+            |row| row.get(0),
         )?;
+        if !has_meta {
+            return Ok(None);
+        }
+        let version = conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'schema_version'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(version)
+    }
+
+    /// Replaces the built-in low-signal names, usually with the ones from
+    /// `.lumora.toml`.
+    pub fn set_low_signal_names(&mut self, names: LowSignalNames) {
+        self.low_signal = names;
+    }
+
+    /// The low-signal names in effect for this store.
+    pub fn low_signal_names(&self) -> &LowSignalNames {
+        &self.low_signal
+    }
+
+    /// Bounds later read queries by `deadline`. Queries that honor it return
+    /// the rows gathered so far once it passes; check [`Self::timed_out`].
+    pub fn set_query_deadline(&mut self, deadline: Option<QueryDeadline>) {
+        match &deadline {
+            Some(deadline) => {
+                let handler = deadline.clone();
+                self.conn
+                    .progress_handler(DEADLINE_CHECK_OPS, Some(move || handler.interrupt_now()));
+            }
+            None => self.conn.progress_handler(0, None::<fn() -> bool>),
+        }
+        self.deadline = deadline;
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.deadline
+            .as_ref()
+            .is_some_and(|deadline| deadline.timed_out())
+    }
+
+    /// Cooperative check for loops that issue many small queries.
+    fn out_of_time(&self) -> bool {
+        self.deadline
+            .as_ref()
+            .is_some_and(|deadline| deadline.expired())
+    }
+
+    /// Maps a query interrupted by the deadline to `None` so callers can
+    /// return what they have; other errors pass through.
+    fn unless_timed_out<T>(&self, result: Result<T>) -> Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if self.timed_out() && is_interrupt(&err) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
 
-        Ok(Self { conn })
+    /// Collects rows, treating an interrupt from the deadline as the end of
+    /// the result set.
+    fn collect_within_deadline<T>(
+        &self,
+        rows: impl Iterator<Item = rusqlite::Result<T>>,
+    ) -> Result<Vec<T>> {
+        let mut out = Vec::new();
+        for row in rows {
+            match self.unless_timed_out(row.map_err(Into::into))? {
+                Some(item) => out.push(item),
+                None => break,
+            }
+        }
+        Ok(out)
     }
 
     pub fn tracked_file_hash(&self, path: &str) -> Result<Option<String>> {
@@ -252,6 +955,52 @@ impl GraphStore {
         Ok(hash)
     }
 
+    /// Size and modification time recorded for `path` by
+    /// [`Self::set_file_mtime`]; `None` when the file is untracked or has no
+    /// trusted mtime, so the caller must hash it.
+    pub fn tracked_file_stat(&self, path: &str) -> Result<Option<(u64, i64)>> {
+        let stat: Option<(i64, Option<i64>)> = self
+            .conn
+            .query_row(
+                "SELECT size_bytes, mtime_ns FROM files WHERE path = ?1",
+                [path],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        Ok(stat.and_then(|(size, mtime)| mtime.map(|mtime| (size as u64, mtime))))
+    }
+
+    /// Records the mtime a file had when its stored hash was taken, so the
+    /// next run can skip reading it while size and mtime still match.
+    /// `None` forgets it.
+    pub fn set_file_mtime(&self, path: &str, mtime_ns: Option<i64>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE files SET mtime_ns = ?2 WHERE path = ?1",
+            params![path, mtime_ns],
+        )?;
+        Ok(())
+    }
+
+    /// Marks whether `path` carries a generated-code header; see
+    /// [`crate::indexer::has_generated_marker`].
+    pub fn set_file_generated(&self, path: &str, generated: bool) -> Result<()> {
+        self.conn.execute(
+            "UPDATE files SET is_generated = ?2 WHERE path = ?1",
+            params![path, generated],
+        )?;
+        Ok(())
+    }
+
+    pub fn tracked_file_language(&self, path: &str) -> Result<Option<String>> {
+        let lang = self
+            .conn
+            .query_row("SELECT lang FROM files WHERE path = ?1", [path], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        Ok(lang)
+    }
+
     pub fn tracked_files(&self) -> Result<HashSet<String>> {
         let mut stmt = self.conn.prepare("SELECT path FROM files")?;
         let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
@@ -262,14 +1011,35 @@ impl GraphStore {
         Ok(out)
     }
 
+    /// Tracked files in path order, starting after `after`.
+    pub fn indexed_files(&self, after: Option<&str>, limit: usize) -> Result<Vec<IndexedFile>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT path, lang, size_bytes FROM files
+             WHERE ?1 IS NULL OR path > ?1
+             ORDER BY path
+             LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![after, limit as i64], |row| {
+            Ok(IndexedFile {
+                path: row.get(0)?,
+                language: row.get(1)?,
+                size_bytes: row.get(2)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
     pub fn remove_files(
         &mut self,
         removed_paths: &[String],
         outcome: &mut UpsertOutcome,
     ) -> Result<()> {
-        let tx = self.conn.transaction()?;
+        let tx = begin_write(&mut self.conn)?;
         for file_path in removed_paths {
+            self.dirty_files
+                .extend(dependent_files_with_tx(&tx, file_path)?);
             tx.execute("DELETE FROM fingerprints WHERE file_path = ?1", [file_path])?;
+            tx.execute("DELETE FROM embeddings WHERE file_path = ?1", [file_path])?;
             tx.execute("DELETE FROM edges WHERE file_path = ?1", [file_path])?;
             tx.execute(
                 "DELETE FROM entities WHERE file_path = ?1 OR key = ?2",
@@ -279,67 +1049,497 @@ impl GraphStore {
             outcome.removed += 1;
         }
         tx.commit()?;
-        self.cleanup_orphan_nodes()?;
+        for file_path in removed_paths {
+            self.dirty_files.remove(file_path);
+        }
+        self.after_write()?;
         Ok(())
     }
 
-    pub fn index_file(
-        &mut self,
-        file_path: &str,
-        language: &str,
-        content_hash: &str,
-        size_bytes: u64,
-        extraction: &FileExtraction,
-        fingerprints: &[(i64, i64, i64)],
-        resolved_imports: &[(String, String)],
-        outcome: &mut UpsertOutcome,
-    ) -> Result<()> {
-        let tx = self.conn.transaction()?;
+    /// Moves all graph data for `old_path` to `new_path` in one transaction,
+    /// keeping `indexed_at` and skipping a reparse. Returns `false` (without
+    /// touching the store) when the rename can't be applied in place, e.g. the
+    /// new path changes the file's language or classification, so callers
+    /// should fall back to a regular reindex.
+    pub fn rename_file(&mut self, old_path: &str, new_path: &str) -> Result<bool> {
+        let same_extension = Path::new(old_path).extension() == Path::new(new_path).extension();
+        if !same_extension
+            || classify_special_file(old_path) != classify_special_file(new_path)
+            || classify_test_file(old_path) != classify_test_file(new_path)
+            || self.tracked_file_hash(old_path)?.is_none()
+            || self.tracked_file_hash(new_path)?.is_some()
+        {
+            return Ok(false);
+        }
 
-        tx.execute("DELETE FROM fingerprints WHERE file_path = ?1", [file_path])?;
-        tx.execute("DELETE FROM edges WHERE file_path = ?1", [file_path])?;
-        tx.execute(
-            "DELETE FROM entities WHERE file_path = ?1 AND entity_type != 'file'",
-            [file_path],
+        let tx = begin_write(&mut self.conn)?;
+        let conflicting: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM entities WHERE file_path = ?1 OR key = ?2",
+            params![new_path, file_key(new_path)],
+            |row| row.get(0),
         )?;
+        if conflicting > 0 {
+            return Ok(false);
+        }
 
         tx.execute(
-            "INSERT INTO files(path, lang, content_hash, size_bytes, indexed_at)
-             VALUES(?1, ?2, ?3, ?4, datetime('now'))
-             ON CONFLICT(path) DO UPDATE SET
-                lang=excluded.lang,
-                content_hash=excluded.content_hash,
-                size_bytes=excluded.size_bytes,
-                indexed_at=excluded.indexed_at",
-            params![file_path, language, content_hash, size_bytes as i64],
+            "UPDATE entities
+             SET key = 'symbol:' || ?2 || substr(key, length('symbol:' || ?1) + 1)
+             WHERE entity_type = 'symbol' AND file_path = ?1",
+            params![old_path, new_path],
         )?;
-
-        let file_entity_id = ensure_entity_with_tx(
-            &tx,
-            "file",
-            &file_key(file_path),
-            file_path,
-            Some(language),
-            Some(file_path),
-            None,
-            None,
-            None,
+        if let Some(entity_type) = classify_special_file(old_path) {
+            tx.execute(
+                "UPDATE entities SET key = ?2, name = ?3 WHERE key = ?1",
+                params![
+                    format!("{entity_type}:{old_path}"),
+                    format!("{entity_type}:{new_path}"),
+                    new_path
+                ],
+            )?;
+        }
+        tx.execute(
+            "UPDATE entities SET key = ?2, name = ?3, file_path = ?3 WHERE key = ?1",
+            params![file_key(old_path), file_key(new_path), new_path],
+        )?;
+        tx.execute(
+            "UPDATE entities
+             SET key = 'annotation:' || ?2 || substr(key, length('annotation:' || ?1) + 1)
+             WHERE entity_type = 'annotation' AND file_path = ?1",
+            params![old_path, new_path],
+        )?;
+        tx.execute(
+            "UPDATE entities SET file_path = ?2 WHERE file_path = ?1",
+            params![old_path, new_path],
+        )?;
+        tx.execute(
+            "UPDATE edges SET file_path = ?2 WHERE file_path = ?1",
+            params![old_path, new_path],
+        )?;
+        // Other files resolved their imports against the old location.
+        let dependents = dependent_files_with_tx(&tx, new_path)?;
+        tx.execute(
+            "DELETE FROM edges
+             WHERE edge_type IN ('resolves_to', 'depends_on')
+               AND file_path != ?1
+               AND dst_entity_id = (SELECT id FROM entities WHERE key = ?2)",
+            params![new_path, file_key(new_path)],
+        )?;
+        tx.execute(
+            "UPDATE fingerprints SET file_path = ?2 WHERE file_path = ?1",
+            params![old_path, new_path],
+        )?;
+        tx.execute(
+            "UPDATE embeddings
+             SET file_path = ?2,
+                 label = CASE WHEN kind = 'chunk'
+                     THEN ?2 || substr(label, length(?1) + 1)
+                     ELSE label END
+             WHERE file_path = ?1",
+            params![old_path, new_path],
+        )?;
+        tx.execute(
+            "UPDATE files SET path = ?2 WHERE path = ?1",
+            params![old_path, new_path],
+        )?;
+        tx.commit()?;
+        self.dirty_files.extend(dependents);
+        self.dirty_files.insert(new_path.to_string());
+        self.after_write()?;
+        Ok(true)
+    }
+
+    /// Returns the stored language and import list of an indexed file.
+    pub fn file_imports(&self, file_path: &str) -> Result<Option<(String, Vec<Import>)>> {
+        let language: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT lang FROM files WHERE path = ?1",
+                [file_path],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(language) = language else {
+            return Ok(None);
+        };
+
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT m.name, e.line, e.col
+            FROM edges e
+            JOIN entities m ON m.id = e.dst_entity_id AND m.entity_type = 'module'
+            WHERE e.edge_type = 'imports' AND e.file_path = ?1
+            ORDER BY e.line, e.col
+            ",
+        )?;
+        let rows = stmt.query_map([file_path], |row| {
+            Ok(Import {
+                module: row.get(0)?,
+                line: row.get::<_, Option<i64>>(1)?.unwrap_or_default(),
+                col: row.get::<_, Option<i64>>(2)?.unwrap_or_default(),
+            })
+        })?;
+        let imports = rows.collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(Some((language, imports)))
+    }
+
+    /// Replaces the `resolves_to`/`depends_on` edges owned by `file_path`.
+    pub fn replace_resolved_imports(
+        &mut self,
+        file_path: &str,
+        resolved_imports: &[(String, String)],
+    ) -> Result<()> {
+        let tx = begin_write(&mut self.conn)?;
+        let (file_entity_id, language): (i64, Option<String>) = tx.query_row(
+            "SELECT id, lang FROM entities WHERE key = ?1",
+            [file_key(file_path)],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        tx.execute(
+            "DELETE FROM edges
+             WHERE file_path = ?1 AND edge_type IN ('resolves_to', 'depends_on')",
+            [file_path],
+        )?;
+        insert_resolved_imports_with_tx(
+            &tx,
+            file_path,
+            language.as_deref().unwrap_or_default(),
+            file_entity_id,
+            resolved_imports,
+        )?;
+        tx.commit()?;
+        self.dirty_files.insert(file_path.to_string());
+        self.after_write()?;
+        Ok(())
+    }
+
+    /// Returns the extraction stored for this content, if it was produced by
+    /// the current build.
+    pub fn cached_extraction(
+        &self,
+        content_hash: &str,
+        language: &str,
+    ) -> Result<Option<FileExtraction>> {
+        let raw: Option<String> = self
+            .conn
+            .prepare_cached(
+                "SELECT extraction_json FROM parse_cache
+                 WHERE content_hash = ?1 AND lang = ?2 AND version = ?3",
+            )?
+            .query_row(
+                params![content_hash, language, PARSE_CACHE_VERSION],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(raw.and_then(|raw| serde_json::from_str(&raw).ok()))
+    }
+
+    pub fn cache_extraction(
+        &mut self,
+        content_hash: &str,
+        language: &str,
+        extraction: &FileExtraction,
+    ) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO parse_cache(content_hash, lang, version, extraction_json)
+             VALUES(?1, ?2, ?3, ?4)
+             ON CONFLICT(content_hash, lang) DO UPDATE SET
+                version=excluded.version,
+                extraction_json=excluded.extraction_json",
+            params![
+                content_hash,
+                language,
+                PARSE_CACHE_VERSION,
+                serde_json::to_string(extraction)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Drops cached parses from other versions or for content no tracked file
+    /// has. Returns the number of entries removed.
+    fn prune_parse_cache(&mut self) -> Result<usize> {
+        Ok(self.conn.execute(
+            "DELETE FROM parse_cache
+             WHERE version != ?1
+                OR content_hash NOT IN (SELECT content_hash FROM files)",
+            [PARSE_CACHE_VERSION],
+        )?)
+    }
+
+    pub fn embeddings_enabled(&self) -> Result<bool> {
+        let value: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'embeddings_enabled'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value.as_deref() == Some("1"))
+    }
+
+    pub fn set_embeddings_enabled(&self, enabled: bool) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO meta(key, value) VALUES('embeddings_enabled', ?1)
+             ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+            [if enabled { "1" } else { "0" }],
+        )?;
+        Ok(())
+    }
+
+    /// Repo-relative paths a lazy index has parsed so far, or `None` when the
+    /// index is not lazy. `.` stands for the whole repo.
+    pub fn lazy_coverage(&self) -> Result<Option<Vec<String>>> {
+        let value: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'lazy_coverage'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match value {
+            Some(raw) => Ok(Some(serde_json::from_str(&raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores the lazy coverage list; `None` turns lazy mode off.
+    pub fn set_lazy_coverage(&self, coverage: Option<&[String]>) -> Result<()> {
+        match coverage {
+            Some(paths) => {
+                self.conn.execute(
+                    "INSERT INTO meta(key, value) VALUES('lazy_coverage', ?1)
+                     ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+                    [serde_json::to_string(paths)?],
+                )?;
+            }
+            None => {
+                self.conn
+                    .execute("DELETE FROM meta WHERE key = 'lazy_coverage'", [])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether names with no definition in the repo are stored as `external`
+    /// entities instead of `symbol_name`.
+    pub fn external_entities_enabled(&self) -> Result<bool> {
+        let value: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'external_entities'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value.as_deref() == Some("1"))
+    }
+
+    pub fn set_external_entities_enabled(&mut self, enabled: bool) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO meta(key, value) VALUES('external_entities', ?1)
+             ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+            [if enabled { "1" } else { "0" }],
+        )?;
+        self.classify_external_names()?;
+        Ok(())
+    }
+
+    /// Returns how clone fingerprints in this index were computed (`code` or
+    /// `raw`), or `None` for indexes built before the mode was recorded.
+    pub fn fingerprint_mode(&self) -> Result<Option<String>> {
+        let value = self
+            .conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'fingerprint_mode'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value)
+    }
+
+    /// Files whose fingerprints were stored before line spans were recorded.
+    /// The indexer reparses just these instead of rebuilding the whole index.
+    pub fn files_missing_fingerprint_lines(&self) -> Result<HashSet<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT file_path FROM fingerprints WHERE start_line IS NULL")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut out = HashSet::new();
+        for row in rows {
+            out.insert(row?);
+        }
+        Ok(out)
+    }
+
+    pub fn set_fingerprint_mode(&self, mode: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO meta(key, value) VALUES('fingerprint_mode', ?1)
+             ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+            [mode],
+        )?;
+        Ok(())
+    }
+
+    pub fn fingerprint_params(&self) -> Result<Option<String>> {
+        let value = self
+            .conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'fingerprint_params'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value)
+    }
+
+    pub fn set_fingerprint_params(&self, params: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO meta(key, value) VALUES('fingerprint_params', ?1)
+             ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+            [params],
+        )?;
+        Ok(())
+    }
+
+    fn file_fingerprint_params(&self) -> Result<HashMap<String, String>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT path, COALESCE(fingerprint_params, '{LEGACY_FINGERPRINT_PARAMS}') FROM files"
+        ))?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn clear_embeddings(&self) -> Result<()> {
+        self.conn.execute("DELETE FROM embeddings", [])?;
+        Ok(())
+    }
+
+    pub fn replace_file_embeddings(
+        &mut self,
+        file_path: &str,
+        records: &[EmbeddingRecord],
+    ) -> Result<()> {
+        let tx = begin_write(&mut self.conn)?;
+        tx.execute("DELETE FROM embeddings WHERE file_path = ?1", [file_path])?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO embeddings(file_path, kind, label, detail, start_line, end_line, vector)
+                 VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for record in records {
+                stmt.execute(params![
+                    file_path,
+                    record.kind,
+                    record.label,
+                    record.detail,
+                    record.start_line,
+                    record.end_line,
+                    embeddings::encode_vector(&record.vector),
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn index_file(
+        &mut self,
+        file_path: &str,
+        language: &str,
+        content_hash: &str,
+        size_bytes: u64,
+        line_count: u64,
+        extraction: &FileExtraction,
+        fingerprints: &[Fingerprint],
+        resolved_imports: &[(String, String)],
+        outcome: &mut UpsertOutcome,
+    ) -> Result<()> {
+        let tx = begin_write(&mut self.conn)?;
+
+        tx.execute("DELETE FROM fingerprints WHERE file_path = ?1", [file_path])?;
+        tx.execute("DELETE FROM embeddings WHERE file_path = ?1", [file_path])?;
+        tx.execute("DELETE FROM edges WHERE file_path = ?1", [file_path])?;
+        tx.execute(
+            "DELETE FROM entities WHERE file_path = ?1 AND entity_type != 'file'",
+            [file_path],
+        )?;
+
+        let is_test_file = classify_test_file(file_path);
+        tx.execute(
+            "INSERT INTO files(path, lang, content_hash, size_bytes, line_count, indexed_at,
+                               fingerprint_params, is_test)
+             VALUES(?1, ?2, ?3, ?4, ?5, datetime('now'),
+                    (SELECT value FROM meta WHERE key = 'fingerprint_params'), ?6)
+             ON CONFLICT(path) DO UPDATE SET
+                lang=excluded.lang,
+                content_hash=excluded.content_hash,
+                size_bytes=excluded.size_bytes,
+                line_count=excluded.line_count,
+                indexed_at=excluded.indexed_at,
+                fingerprint_params=excluded.fingerprint_params,
+                mtime_ns=NULL,
+                is_test=excluded.is_test",
+            params![
+                file_path,
+                language,
+                content_hash,
+                size_bytes as i64,
+                line_count as i64,
+                is_test_file
+            ],
+        )?;
+
+        let file_kind = if is_test_file { "test" } else { "source" };
+        let file_entity_id = ensure_entity_with_tx(
+            &tx,
+            "file",
+            &file_key(file_path),
+            file_path,
+            Some(language),
+            Some(file_path),
             None,
-            Some(json!({"kind": "source"}).to_string()),
+            None,
+            None,
+            None,
+            Some(json!({"kind": file_kind}).to_string()),
         )?;
 
         let mut symbol_name_entities: HashMap<String, i64> = HashMap::new();
+        let mut test_symbols: Vec<(i64, i64, i64, &str)> = Vec::new();
         for definition in &extraction.definitions {
             let symbol_key = format!(
                 "symbol:{}:{}:{}:{}:{}",
                 file_path, definition.qualname, definition.kind, definition.line, definition.col
             );
-            let symbol_meta = json!({
+            let mut symbol_meta = json!({
                 "qualname": definition.qualname,
                 "kind": definition.kind,
                 "is_definition": true,
-            })
-            .to_string();
+            });
+            if let Some(signature) = &definition.signature {
+                symbol_meta["signature"] = json!(signature);
+            }
+            if let Some(visibility) = &definition.visibility {
+                symbol_meta["visibility"] = json!(visibility);
+            }
+            if !definition.modifiers.is_empty() {
+                symbol_meta["modifiers"] = json!(definition.modifiers);
+            }
+            if let Some(arity) = &definition.arity {
+                symbol_meta["min_arity"] = json!(arity.min);
+                if let Some(max) = arity.max {
+                    symbol_meta["max_arity"] = json!(max);
+                }
+            }
+            if let Some(offsets) = &definition.offsets {
+                offsets.write_meta(&mut symbol_meta);
+            }
+            let symbol_meta = symbol_meta.to_string();
 
             let symbol_entity_id = ensure_entity_with_tx(
                 &tx,
@@ -364,8 +1564,20 @@ impl GraphStore {
                 Some(definition.line),
                 Some(definition.col),
                 None,
+                None,
+                None,
+                EdgeOrigin::Ast,
             )?;
 
+            if is_test_symbol(is_test_file, definition) {
+                test_symbols.push((
+                    symbol_entity_id,
+                    definition.line,
+                    definition.end_line,
+                    &definition.name,
+                ));
+            }
+
             let name_entity_id = if let Some(existing) = symbol_name_entities.get(&definition.name)
             {
                 *existing
@@ -397,9 +1609,13 @@ impl GraphStore {
                 Some(definition.line),
                 Some(definition.col),
                 None,
+                None,
+                None,
+                EdgeOrigin::Ast,
             )?;
         }
 
+        let mut test_edges: HashSet<(i64, i64)> = HashSet::new();
         for reference in &extraction.references {
             let name_entity_id = if let Some(existing) = symbol_name_entities.get(&reference.name) {
                 *existing
@@ -422,11 +1638,16 @@ impl GraphStore {
                 entity_id
             };
 
-            let meta = json!({
-                "end_line": reference.end_line,
-                "end_col": reference.end_col
-            })
-            .to_string();
+            let meta = (reference.offsets.is_some() || reference.arity.is_some()).then(|| {
+                let mut meta = json!({});
+                if let Some(offsets) = &reference.offsets {
+                    offsets.write_meta(&mut meta);
+                }
+                if let Some(arity) = reference.arity {
+                    meta["arity"] = json!(arity);
+                }
+                meta.to_string()
+            });
 
             insert_edge_with_tx(
                 &tx,
@@ -436,43 +1657,85 @@ impl GraphStore {
                 Some(file_path),
                 Some(reference.line),
                 Some(reference.col),
-                Some(meta),
+                Some(reference.end_line),
+                Some(reference.end_col),
+                meta,
+                EdgeOrigin::Ast,
             )?;
+
+            // Link the innermost enclosing test symbol to the name it exercises.
+            let enclosing_test = test_symbols
+                .iter()
+                .filter(|(_, line, end_line, _)| {
+                    *line <= reference.line && reference.line <= *end_line
+                })
+                .min_by_key(|(_, line, end_line, _)| end_line - line);
+            if let Some((test_entity_id, _, _, test_name)) = enclosing_test {
+                if *test_name != reference.name
+                    && !self.low_signal.contains(Some(language), &reference.name)
+                    && test_edges.insert((*test_entity_id, name_entity_id))
+                {
+                    insert_edge_with_tx(
+                        &tx,
+                        *test_entity_id,
+                        name_entity_id,
+                        "tests",
+                        Some(file_path),
+                        Some(reference.line),
+                        Some(reference.col),
+                        None,
+                        None,
+                        None,
+                        EdgeOrigin::Heuristic,
+                    )?;
+                }
+            }
         }
 
-        for import_item in &extraction.imports {
-            let module_entity_id = ensure_entity_with_tx(
+        for relation in &extraction.relations {
+            let mut name_entity = |name: &str| -> Result<i64> {
+                if let Some(existing) = symbol_name_entities.get(name) {
+                    return Ok(*existing);
+                }
+                let entity_id = ensure_entity_with_tx(
+                    &tx,
+                    "symbol_name",
+                    &symbol_name_key(language, name),
+                    name,
+                    Some(language),
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                )?;
+                symbol_name_entities.insert(name.to_string(), entity_id);
+                Ok(entity_id)
+            };
+            let subject_id = name_entity(&relation.subject)?;
+            let target_id = name_entity(&relation.target)?;
+            insert_edge_with_tx(
                 &tx,
-                "module",
-                &module_key(language, &import_item.module),
-                &import_item.module,
-                Some(language),
-                None,
-                None,
-                None,
-                None,
+                subject_id,
+                target_id,
+                relation.kind.as_edge_type(),
+                Some(file_path),
+                Some(relation.line),
+                Some(relation.col),
                 None,
                 None,
-            )?;
-
-            insert_edge_with_tx(
-                &tx,
-                file_entity_id,
-                module_entity_id,
-                "imports",
-                Some(file_path),
-                Some(import_item.line),
-                Some(import_item.col),
                 None,
+                EdgeOrigin::Ast,
             )?;
         }
 
-        for (module_name, resolved_file) in resolved_imports {
+        for import_item in &extraction.imports {
             let module_entity_id = ensure_entity_with_tx(
                 &tx,
                 "module",
-                &module_key(language, module_name),
-                module_name,
+                &module_key(language, &import_item.module),
+                &import_item.module,
                 Some(language),
                 None,
                 None,
@@ -481,42 +1744,49 @@ impl GraphStore {
                 None,
                 None,
             )?;
-            let resolved_file_id = ensure_entity_with_tx(
-                &tx,
-                "file",
-                &file_key(resolved_file),
-                resolved_file,
-                Some(language),
-                Some(resolved_file),
-                None,
-                None,
-                None,
-                None,
-                Some(json!({"kind": "source"}).to_string()),
-            )?;
 
             insert_edge_with_tx(
                 &tx,
+                file_entity_id,
                 module_entity_id,
-                resolved_file_id,
-                "resolves_to",
+                "imports",
                 Some(file_path),
+                Some(import_item.line),
+                Some(import_item.col),
                 None,
                 None,
                 None,
+                EdgeOrigin::Ast,
             )?;
-            insert_edge_with_tx(
+        }
+
+        for annotation in &extraction.annotations {
+            ensure_entity_with_tx(
                 &tx,
-                file_entity_id,
-                resolved_file_id,
-                "depends_on",
+                "annotation",
+                &format!(
+                    "annotation:{file_path}:{}:{}",
+                    annotation.line, annotation.col
+                ),
+                &annotation.tag,
+                Some(language),
                 Some(file_path),
+                Some(annotation.line),
+                Some(annotation.col),
                 None,
                 None,
-                Some(json!({"via": module_name}).to_string()),
+                Some(json!({ "text": annotation.text }).to_string()),
             )?;
         }
 
+        insert_resolved_imports_with_tx(
+            &tx,
+            file_path,
+            language,
+            file_entity_id,
+            resolved_imports,
+        )?;
+
         let config_or_entry = classify_special_file(file_path);
         if let Some(entity_type) = config_or_entry {
             let special_id = ensure_entity_with_tx(
@@ -541,19 +1811,32 @@ impl GraphStore {
                 None,
                 None,
                 None,
+                None,
+                None,
+                EdgeOrigin::Ast,
             )?;
         }
 
-        for (fp_hash, span_start, span_end) in fingerprints {
+        for fp in fingerprints {
             tx.execute(
-                "INSERT INTO fingerprints(file_path, fp_hash, span_start, span_end)
-                 VALUES(?1, ?2, ?3, ?4)",
-                params![file_path, fp_hash, span_start, span_end],
+                "INSERT INTO fingerprints(file_path, fp_hash, span_start, span_end, start_line, end_line)
+                 VALUES(?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    file_path,
+                    fp.hash,
+                    fp.span_start,
+                    fp.span_end,
+                    fp.start_line,
+                    fp.end_line
+                ],
             )?;
         }
 
+        let dependents = dependent_files_with_tx(&tx, file_path)?;
         tx.commit()?;
-        self.cleanup_orphan_nodes()?;
+        self.dirty_files.extend(dependents);
+        self.dirty_files.insert(file_path.to_string());
+        self.after_write()?;
         outcome.updated += 1;
         Ok(())
     }
@@ -563,7 +1846,9 @@ impl GraphStore {
             "
             SELECT s.name, s.file_path, s.line, s.col, s.end_line, s.end_col,
                    json_extract(s.meta_json, '$.kind') as kind,
-                   json_extract(s.meta_json, '$.qualname') as qualname
+                   json_extract(s.meta_json, '$.qualname') as qualname,
+                   json_extract(s.meta_json, '$.signature') as signature,
+                   s.meta_json
             FROM entities sn
             JOIN edges en ON en.dst_entity_id = sn.id AND en.edge_type = 'names'
             JOIN entities s ON s.id = en.src_entity_id AND s.entity_type = 'symbol'
@@ -573,6 +1858,8 @@ impl GraphStore {
         )?;
 
         let rows = stmt.query_map([symbol_name], |row| {
+            let meta: Option<String> = row.get(9)?;
+            let (visibility, modifiers) = meta_modifiers(meta.as_deref());
             Ok(SymbolLocation {
                 symbol_name: row.get(0)?,
                 file_path: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
@@ -586,6 +1873,10 @@ impl GraphStore {
                 qualname: row
                     .get::<_, Option<String>>(7)?
                     .unwrap_or_else(|| symbol_name.to_string()),
+                signature: row.get(8)?,
+                visibility,
+                modifiers,
+                offsets: meta_offsets(meta),
             })
         })?;
 
@@ -593,249 +1884,1727 @@ impl GraphStore {
             .map_err(Into::into)
     }
 
-    pub fn symbol_references_page(
+    /// Definitions whose name or qualified name matches `pattern`, a regex
+    /// evaluated inside SQLite. Sits between exact `symbol_definitions`
+    /// lookups and fuzzy selector discovery.
+    pub fn symbols_matching(
         &self,
-        symbol_name: &str,
-        options: &ReferenceQueryOptions,
-    ) -> Result<(Vec<ReferenceLocation>, PaginationInfo)> {
-        let mut out = self.symbol_references_unpaged(symbol_name, options)?;
-        let total = out.len();
-        let start = options.offset.min(total);
-        let end = start.saturating_add(options.limit).min(total);
-        let rows = out.drain(start..end).collect::<Vec<_>>();
-        let pagination = build_pagination(total, options.offset, options.limit, rows.len());
-        Ok((rows, pagination))
-    }
+        pattern: &str,
+        options: &SymbolSearchOptions,
+    ) -> Result<Vec<SymbolLocation>> {
+        Regex::new(pattern).with_context(|| format!("invalid regex `{pattern}`"))?;
 
-    fn symbol_references_unpaged(
-        &self,
-        symbol_name: &str,
-        options: &ReferenceQueryOptions,
-    ) -> Result<Vec<ReferenceLocation>> {
-        let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
         let mut where_clauses = vec![
-            "sn.entity_type = 'symbol_name'".to_string(),
-            "sn.name = ?".to_string(),
+            "s.entity_type = 'symbol'".to_string(),
+            "(s.name REGEXP ?1 OR json_extract(s.meta_json, '$.qualname') REGEXP ?1)".to_string(),
         ];
-        params.push(Box::new(symbol_name.to_string()));
-
-        match options.edge_type_filter.as_deref() {
-            Some(edge_type) => {
-                where_clauses.push("e.edge_type = ?".to_string());
-                params.push(Box::new(edge_type.to_string()));
-            }
-            None => where_clauses.push("e.edge_type IN ('references', 'calls')".to_string()),
-        }
-
-        if let Some(glob) = options.file_glob.as_deref() {
-            where_clauses.push("e.file_path GLOB ?".to_string());
-            params.push(Box::new(glob.replace('\\', "/")));
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(pattern.to_string())];
+        if let Some(kind) = options.kind.as_deref() {
+            params.push(Box::new(kind.to_string()));
+            where_clauses.push(format!(
+                "instr(json_extract(s.meta_json, '$.kind'), ?{}) > 0",
+                params.len()
+            ));
         }
-
         if let Some(language) = options.language.as_deref() {
-            where_clauses.push("f.lang = ?".to_string());
             params.push(Box::new(language.to_string()));
+            where_clauses.push(format!("s.lang = ?{}", params.len()));
         }
-
-        if let Some(max_age_hours) = options.max_age_hours {
-            where_clauses.push("f.indexed_at >= datetime('now', ?)".to_string());
-            params.push(Box::new(format!("-{max_age_hours} hours")));
+        if let Some(visibility) = options.visibility.as_deref() {
+            params.push(Box::new(visibility.to_string()));
+            where_clauses.push(format!(
+                "json_extract(s.meta_json, '$.visibility') = ?{}",
+                params.len()
+            ));
+        }
+        if !options.include_tests {
+            where_clauses.push(format!("s.file_path NOT IN ({TEST_FILE_PATHS})"));
+        }
+        if options.unreferenced && !options.include_generated {
+            where_clauses.push(format!("s.file_path NOT IN ({GENERATED_FILE_PATHS})"));
         }
+        if options.unreferenced {
+            let from_production = if options.include_tests {
+                String::new()
+            } else {
+                format!("AND e.file_path NOT IN ({TEST_FILE_PATHS})")
+            };
+            where_clauses.push(format!(
+                "NOT EXISTS (
+                    SELECT 1 FROM entities sn
+                    JOIN edges e ON e.dst_entity_id = sn.id
+                    WHERE sn.entity_type IN ('symbol_name', 'external') AND sn.name = s.name
+                      AND e.edge_type IN ('references', 'calls')
+                      {from_production}
+                )"
+            ));
+        }
+        params.push(Box::new(options.limit.max(1) as i64));
 
         let sql = format!(
             "
-            SELECT sn.name, e.file_path, e.line, e.col, e.edge_type
-            FROM entities sn
-            JOIN edges e ON e.dst_entity_id = sn.id
-            LEFT JOIN files f ON f.path = e.file_path
+            SELECT s.name, s.file_path, s.line, s.col, s.end_line, s.end_col,
+                   json_extract(s.meta_json, '$.kind') as kind,
+                   json_extract(s.meta_json, '$.qualname') as qualname,
+                   json_extract(s.meta_json, '$.signature') as signature,
+                   s.meta_json
+            FROM entities s
             WHERE {}
-            ORDER BY e.file_path ASC, e.line ASC, e.col ASC
+            ORDER BY s.name, s.file_path, s.line
+            LIMIT ?{}
             ",
-            where_clauses.join(" AND ")
+            where_clauses.join(" AND "),
+            params.len()
         );
 
         let mut stmt = self.conn.prepare(&sql)?;
         let bind_params = rusqlite::params_from_iter(params.iter().map(|p| &**p));
-        let rows = stmt.query_map(bind_params, |row| {
-            Ok(ReferenceLocation {
-                symbol_name: row.get(0)?,
-                file_path: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
-                line: row.get::<_, Option<i64>>(2)?.unwrap_or_default(),
-                col: row.get::<_, Option<i64>>(3)?.unwrap_or_default(),
-                edge_type: row.get(4)?,
-                score: None,
-                why: None,
-            })
-        })?;
-
-        let mut out = Vec::new();
-        for row in rows {
-            out.push(row?);
-        }
-
-        if options.dedup {
-            let mut seen = HashSet::new();
-            out.retain(|item| {
-                seen.insert((
-                    item.file_path.clone(),
-                    item.line,
-                    item.col,
-                    item.edge_type.clone(),
-                ))
-            });
-        }
+        let rows = stmt.query_map(bind_params, symbol_location_row)?;
 
-        let def_files = self.definition_files_for_symbol(symbol_name)?;
-        for item in &mut out {
-            let mut score = if item.edge_type == "calls" { 2.0 } else { 1.0 };
-            let mut why = vec![format!("edge_type={}", item.edge_type)];
-            if def_files.contains(&item.file_path) {
-                score += 0.35;
-                why.push("same_file_as_definition".to_string());
-            }
-            item.score = Some(score);
-            item.why = Some(why.join(","));
-        }
+        self.collect_within_deadline(rows)
+    }
 
-        out.sort_by(reference_sorter(options.order));
-        Ok(out)
+    pub fn file_definitions(&self, path: &str) -> Result<Vec<SymbolLocation>> {
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT s.name, s.file_path, s.line, s.col, s.end_line, s.end_col,
+                   json_extract(s.meta_json, '$.kind') as kind,
+                   json_extract(s.meta_json, '$.qualname') as qualname,
+                   json_extract(s.meta_json, '$.signature') as signature,
+                   s.meta_json
+            FROM entities s
+            WHERE s.entity_type = 'symbol' AND s.file_path = ?1
+            ORDER BY s.line, s.col
+            ",
+        )?;
+        let rows = stmt.query_map([path], symbol_location_row)?;
+        self.collect_within_deadline(rows)
     }
 
-    pub fn dependency_path(
+    /// The index picks the bound definition, else one enclosing the cursor,
+    /// else one in the same file, else the first.
+    pub fn definition_at(
         &self,
-        from_selector: &str,
-        to_selector: &str,
-        max_depth: usize,
-    ) -> Result<DependencyPath> {
-        let from_resolution = self.resolve_selector(from_selector)?;
-        let to_resolution = self.resolve_selector(to_selector)?;
-
-        let Some(from) = from_resolution.entity else {
-            return Ok(DependencyPath {
-                found: false,
-                hops: Vec::new(),
-            });
+        path: &str,
+        identifier: &str,
+        line: i64,
+        col: i64,
+    ) -> Result<(Vec<SymbolLocation>, Option<usize>)> {
+        let at_cursor = ReferenceQueryOptions {
+            file_glob: Some(path.to_string()),
+            limit: usize::MAX,
+            order: SortOrder::LineAsc,
+            include_generated: true,
+            ..Default::default()
         };
-        let Some(to) = to_resolution.entity else {
-            return Ok(DependencyPath {
-                found: false,
-                hops: Vec::new(),
-            });
+        let bound_key = self
+            .symbol_references_unpaged(identifier, &at_cursor)?
+            .into_iter()
+            .find(|row| {
+                row.line == line && row.col <= col && row.end_col.map_or(true, |end| col <= end)
+            })
+            .and_then(|row| row.resolved_symbol_key);
+        let definitions = self.symbol_definitions(identifier)?;
+        let key_of = |definition: &SymbolLocation| {
+            format!(
+                "symbol:{}:{}:{}:{}:{}",
+                definition.file_path,
+                definition.qualname,
+                definition.kind,
+                definition.line,
+                definition.col
+            )
         };
+        let chosen = definitions
+            .iter()
+            .position(|definition| bound_key.as_deref() == Some(key_of(definition).as_str()))
+            .or_else(|| {
+                definitions.iter().position(|definition| {
+                    definition.file_path == path
+                        && definition.line <= line
+                        && definition.end_line.unwrap_or(definition.line) >= line
+                })
+            })
+            .or_else(|| {
+                definitions
+                    .iter()
+                    .position(|definition| definition.file_path == path)
+            })
+            .or((!definitions.is_empty()).then_some(0));
+        Ok((definitions, chosen))
+    }
 
-        if from.id == to.id {
-            return Ok(DependencyPath {
-                found: true,
-                hops: vec![PathHop {
-                    entity_key: from.key,
-                    entity_name: from.name,
-                    entity_type: from.entity_type,
-                }],
-            });
-        }
-
-        let mut queue: VecDeque<(i64, usize)> = VecDeque::new();
-        let mut seen: HashSet<i64> = HashSet::new();
-        let mut prev: HashMap<i64, i64> = HashMap::new();
-
-        queue.push_back((from.id, 0));
-        seen.insert(from.id);
-
-        while let Some((current, depth)) = queue.pop_front() {
-            if depth >= max_depth {
-                continue;
-            }
-            for neighbor in self.outgoing_neighbors(current)? {
-                if seen.insert(neighbor) {
-                    prev.insert(neighbor, current);
-                    if neighbor == to.id {
-                        let mut chain = vec![to.id];
-                        let mut cursor = to.id;
-                        while let Some(parent) = prev.get(&cursor) {
-                            chain.push(*parent);
-                            if *parent == from.id {
-                                break;
-                            }
-                            cursor = *parent;
-                        }
-                        chain.reverse();
+    pub fn tests_for_symbol(&self, symbol_name: &str) -> Result<Vec<SymbolLocation>> {
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT DISTINCT t.name, t.file_path, t.line, t.col, t.end_line, t.end_col,
+                   json_extract(t.meta_json, '$.kind') as kind,
+                   json_extract(t.meta_json, '$.qualname') as qualname,
+                   json_extract(t.meta_json, '$.signature') as signature,
+                   t.meta_json
+            FROM entities sn
+            JOIN edges e ON e.dst_entity_id = sn.id AND e.edge_type = 'tests'
+            JOIN entities t ON t.id = e.src_entity_id AND t.entity_type = 'symbol'
+            WHERE sn.entity_type IN ('symbol_name', 'external') AND sn.name = ?1
+            ORDER BY t.file_path, t.line
+            ",
+        )?;
 
-                        let mut hops = Vec::with_capacity(chain.len());
-                        for entity_id in chain {
-                            let entity = self.entity_by_id(entity_id)?;
-                            hops.push(PathHop {
-                                entity_key: entity.key,
-                                entity_name: entity.name,
-                                entity_type: entity.entity_type,
-                            });
-                        }
+        let rows = stmt.query_map([symbol_name], symbol_location_row)?;
 
-                        return Ok(DependencyPath { found: true, hops });
-                    }
-                    queue.push_back((neighbor, depth + 1));
-                }
-            }
-        }
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
 
-        Ok(DependencyPath {
-            found: false,
-            hops: Vec::new(),
-        })
+    pub fn entrypoint_files(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT file_path FROM entities
+             WHERE entity_type = 'entrypoint' AND file_path IS NOT NULL
+             ORDER BY file_path",
+        )?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
     }
 
-    pub fn dependency_path_with_diagnostics(
+    /// Only names some repo file defines, in order of first call.
+    pub fn calls_in_span(
         &self,
-        from_selector: &str,
-        to_selector: &str,
-        max_depth: usize,
-    ) -> Result<(DependencyPath, SelectorResolution, SelectorResolution)> {
-        let from_resolution = self.resolve_selector(from_selector)?;
-        let to_resolution = self.resolve_selector(to_selector)?;
+        file_path: &str,
+        start_line: i64,
+        end_line: i64,
+        limit: usize,
+    ) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT sn.name
+            FROM edges e
+            JOIN entities sn ON sn.id = e.dst_entity_id AND sn.entity_type = 'symbol_name'
+            WHERE e.edge_type = 'calls' AND e.file_path = ?1 AND e.line BETWEEN ?2 AND ?3
+            GROUP BY sn.name
+            ORDER BY MIN(e.line), MIN(e.col)
+            LIMIT ?4
+            ",
+        )?;
+        let rows = stmt.query_map(
+            params![file_path, start_line, end_line, limit as i64],
+            |row| row.get(0),
+        )?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
 
-        let from_diag = SelectorResolution {
-            parsed_as: from_resolution.parsed_as.clone(),
-            matched: from_resolution.candidates.len(),
-            selected_key: from_resolution.entity.as_ref().map(|item| item.key.clone()),
-        };
-        let to_diag = SelectorResolution {
-            parsed_as: to_resolution.parsed_as.clone(),
-            matched: to_resolution.candidates.len(),
-            selected_key: to_resolution.entity.as_ref().map(|item| item.key.clone()),
-        };
+    /// Runs a `MATCH ... RETURN ...` graph query (see [`crate::graph_query`])
+    /// against the entity and edge tables.
+    pub fn graph_query(&self, query: &str, limit: usize) -> Result<GraphQueryResult> {
+        let compiled = graph_query::compile(query, limit)?;
+        let mut stmt = self.conn.prepare(&compiled.sql)?;
+        let width = compiled.columns.len();
+        let rows = stmt.query_map(rusqlite::params_from_iter(compiled.params.iter()), |row| {
+            (0..width)
+                .map(|index| row.get::<_, rusqlite::types::Value>(index))
+                .collect::<rusqlite::Result<Vec<_>>>()
+        })?;
+        let mut rows = self.collect_within_deadline(rows)?;
+        let truncated = rows.len() > compiled.limit;
+        rows.truncate(compiled.limit);
+        Ok(GraphQueryResult {
+            columns: compiled.columns,
+            rows: rows
+                .into_iter()
+                .map(|row| {
+                    row.into_iter()
+                        .zip(&compiled.json_columns)
+                        .map(|(value, is_json)| graph_query::sql_to_json(value, *is_json))
+                        .collect()
+                })
+                .collect(),
+            truncated,
+        })
+    }
 
-        let path = self.dependency_path(from_selector, to_selector, max_depth)?;
-        Ok((path, from_diag, to_diag))
+    /// Per-language file, line, definition, and reference counts, largest
+    /// language first.
+    pub fn language_coverage(&self) -> Result<Vec<LanguageCoverage>> {
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT f.lang,
+                   COUNT(*),
+                   COALESCE(SUM(f.line_count), 0),
+                   COALESCE(SUM(s.definitions), 0),
+                   COALESCE(SUM(r.references_count), 0)
+            FROM files f
+            LEFT JOIN (
+                SELECT file_path, COUNT(*) AS definitions
+                FROM entities
+                WHERE entity_type = 'symbol'
+                GROUP BY file_path
+            ) s ON s.file_path = f.path
+            LEFT JOIN (
+                SELECT file_path, COUNT(*) AS references_count
+                FROM edges
+                WHERE edge_type IN ('calls', 'references')
+                GROUP BY file_path
+            ) r ON r.file_path = f.path
+            GROUP BY f.lang
+            ORDER BY COUNT(*) DESC, f.lang ASC
+            ",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(LanguageCoverage {
+                language: row.get(0)?,
+                files: row.get(1)?,
+                lines: row.get(2)?,
+                definitions: row.get(3)?,
+                references: row.get(4)?,
+            })
+        })?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
     }
 
-    pub fn minimal_slice_with_options(
+    /// Walks resolved imports breadth first from an indexed file, up to
+    /// `max_depth` hops: what it pulls in (`Out`) or what pulls it in (`In`).
+    /// Outgoing imports that never resolved to a file are listed as `module`
+    /// leaves. Returns `None` when `file_path` is not indexed.
+    pub fn import_graph(
         &self,
         file_path: &str,
-        line: Option<i64>,
-        depth: usize,
-        options: &SliceQueryOptions,
-    ) -> Result<Option<SliceResult>> {
-        let anchor = if let Some(line_no) = line {
-            self.anchor_symbol_for_line(file_path, line_no)?
-                .or_else(|| self.find_entity_by_key(&file_key(file_path)).ok().flatten())
-        } else {
-            self.find_entity_by_key(&file_key(file_path))?
-        };
-
-        let Some(anchor) = anchor else {
+        direction: ImportDirection,
+        max_depth: usize,
+    ) -> Result<Option<ImportGraph>> {
+        if self.tracked_file_hash(file_path)?.is_none() {
             return Ok(None);
-        };
-
-        let mut neighbors = Vec::new();
-        let mut frontier = vec![(anchor.id, 0usize)];
-        let mut seen: HashSet<i64> = HashSet::new();
-        seen.insert(anchor.id);
+        }
+        let max_depth = max_depth.max(1);
+        let mut rows = Vec::new();
+        let mut seen = HashSet::from([file_path.to_string()]);
+        let mut seen_modules = HashSet::new();
+        let mut frontier = vec![file_path.to_string()];
+        for depth in 1..=max_depth {
+            let mut next = Vec::new();
+            for current in &frontier {
+                let steps = match direction {
+                    ImportDirection::Out => self.imports_out_of(current)?,
+                    ImportDirection::In => self.imports_into(current)?,
+                };
+                for (target, resolved, via) in steps {
+                    let first_visit = if resolved {
+                        seen.insert(target.clone())
+                    } else {
+                        seen_modules.insert(target.clone())
+                    };
+                    if !first_visit {
+                        continue;
+                    }
+                    if resolved {
+                        next.push(target.clone());
+                    }
+                    rows.push(TransitiveImport {
+                        target,
+                        kind: if resolved { "file" } else { "module" },
+                        depth,
+                        from: current.clone(),
+                        via,
+                    });
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        Ok(Some(ImportGraph {
+            file_path: file_path.to_string(),
+            direction,
+            max_depth,
+            rows,
+        }))
+    }
+
+    /// `(file or module, resolved, module name)` for each import in `file_path`.
+    fn imports_out_of(&self, file_path: &str) -> Result<Vec<(String, bool, Option<String>)>> {
+        let mut stmt = self.conn.prepare_cached(
+            "
+            SELECT m.name, t.file_path
+            FROM edges e
+            JOIN entities m ON m.id = e.dst_entity_id AND m.entity_type = 'module'
+            LEFT JOIN edges r ON r.src_entity_id = m.id
+                AND r.edge_type = 'resolves_to' AND r.file_path = e.file_path
+            LEFT JOIN entities t ON t.id = r.dst_entity_id
+            WHERE e.edge_type = 'imports' AND e.file_path = ?1
+            ORDER BY e.line, e.col, t.file_path
+            ",
+        )?;
+        let rows = stmt.query_map([file_path], |row| {
+            let module: String = row.get(0)?;
+            Ok(match row.get::<_, Option<String>>(1)? {
+                Some(target) => (target, true, Some(module)),
+                None => (module, false, None),
+            })
+        })?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
+
+    /// `TODO`/`FIXME`/`HACK` comments, by file and line. `tags` limits the
+    /// markers (any case); `file_glob` is matched with SQLite `GLOB`.
+    pub fn annotations(
+        &self,
+        file_glob: Option<&str>,
+        tags: &[String],
+    ) -> Result<Vec<AnnotationRow>> {
+        let tags: Vec<String> = tags.iter().map(|tag| tag.to_ascii_uppercase()).collect();
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT name, file_path, line, col, json_extract(meta_json, '$.text')
+            FROM entities
+            WHERE entity_type = 'annotation'
+              AND (?1 IS NULL OR file_path GLOB ?1)
+            ORDER BY file_path, line, col
+            ",
+        )?;
+        let glob = file_glob.map(|glob| glob.replace('\\', "/"));
+        let rows = stmt.query_map([glob], |row| {
+            Ok(AnnotationRow {
+                tag: row.get(0)?,
+                file_path: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                line: row.get::<_, Option<i64>>(2)?.unwrap_or_default(),
+                col: row.get::<_, Option<i64>>(3)?.unwrap_or_default(),
+                text: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+            })
+        })?;
+        let rows = self.collect_within_deadline(rows)?;
+        Ok(rows
+            .into_iter()
+            .filter(|row| tags.is_empty() || tags.contains(&row.tag))
+            .collect())
+    }
+
+    /// Every `imports` edge into the module entities named `module`, ordered by
+    /// file and line. With `submodules`, names that continue `module` past a
+    /// `::`, `.`, or `/` separator match too (`crate::storage` covers
+    /// `crate::storage::GraphStore`).
+    pub fn who_imports(
+        &self,
+        module: &str,
+        submodules: bool,
+        language: Option<&str>,
+    ) -> Result<WhoImports> {
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT e.file_path, e.line, e.col, m.name, m.lang
+            FROM entities m
+            JOIN edges e ON e.dst_entity_id = m.id AND e.edge_type = 'imports'
+            WHERE m.entity_type = 'module'
+              AND (m.name = ?1
+                   OR (?2 AND substr(m.name, 1, length(?1)) = ?1
+                       AND substr(m.name, length(?1) + 1, 1) IN (':', '.', '/')))
+              AND (?3 IS NULL OR m.lang = ?3)
+              AND e.file_path IS NOT NULL
+            ORDER BY e.file_path, e.line, e.col
+            ",
+        )?;
+        let rows = stmt.query_map(params![module, submodules, language], |row| {
+            Ok(ModuleImporter {
+                file_path: row.get(0)?,
+                line: row.get::<_, Option<i64>>(1)?.unwrap_or_default(),
+                col: row.get::<_, Option<i64>>(2)?.unwrap_or_default(),
+                module: row.get(3)?,
+                language: row.get(4)?,
+            })
+        })?;
+        let importers = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut by_directory: std::collections::BTreeMap<&str, (HashSet<&str>, usize)> =
+            std::collections::BTreeMap::new();
+        for importer in &importers {
+            let directory = importer
+                .file_path
+                .rsplit_once('/')
+                .map_or(".", |(dir, _)| dir);
+            let entry = by_directory.entry(directory).or_default();
+            entry.0.insert(&importer.file_path);
+            entry.1 += 1;
+        }
+        let mut directories: Vec<ImporterDirectory> = by_directory
+            .into_iter()
+            .map(|(directory, (files, imports))| ImporterDirectory {
+                directory: directory.to_string(),
+                files: files.len(),
+                imports,
+            })
+            .collect();
+        directories.sort_by_key(|dir| std::cmp::Reverse(dir.imports));
+        let total_files = importers
+            .iter()
+            .map(|importer| importer.file_path.as_str())
+            .collect::<HashSet<_>>()
+            .len();
+
+        Ok(WhoImports {
+            module: module.to_string(),
+            importers,
+            directories,
+            total_files,
+        })
+    }
+
+    /// `(importing file, true, module name)` for each file depending on `file_path`.
+    fn imports_into(&self, file_path: &str) -> Result<Vec<(String, bool, Option<String>)>> {
+        let mut stmt = self.conn.prepare_cached(
+            "
+            SELECT e.file_path, json_extract(e.meta_json, '$.via')
+            FROM edges e
+            JOIN entities f ON f.id = e.dst_entity_id
+            WHERE e.edge_type = 'depends_on' AND f.key = ?1 AND e.file_path IS NOT NULL
+            ORDER BY e.file_path
+            ",
+        )?;
+        let rows = stmt.query_map([file_key(file_path)], |row| {
+            Ok((row.get(0)?, true, row.get(1)?))
+        })?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
+
+    /// Buckets file-level `depends_on` edges by their first `level` directory
+    /// components. Dependencies inside one module are only counted in
+    /// `internal_edges`.
+    pub fn module_dependencies(&self, level: usize) -> Result<ModuleDependencies> {
+        let level = level.max(1);
+        let mut modules = std::collections::BTreeSet::new();
+        let mut file_stmt = self.conn.prepare("SELECT path FROM files")?;
+        for path in file_stmt.query_map([], |row| row.get::<_, String>(0))? {
+            modules.insert(module_bucket(&path?, level));
+        }
+
+        let mut edge_stmt = self.conn.prepare(
+            "
+            SELECT src.file_path, dst.file_path, COUNT(*)
+            FROM edges e
+            JOIN entities src ON src.id = e.src_entity_id
+            JOIN entities dst ON dst.id = e.dst_entity_id
+            WHERE e.edge_type = 'depends_on'
+              AND src.file_path IS NOT NULL
+              AND dst.file_path IS NOT NULL
+            GROUP BY src.file_path, dst.file_path
+            ",
+        )?;
+        let rows = edge_stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })?;
+
+        let mut counts: HashMap<(String, String), i64> = HashMap::new();
+        let mut internal_edges = 0;
+        for row in rows {
+            let (src_file, dst_file, count) = row?;
+            let from = module_bucket(&src_file, level);
+            let to = module_bucket(&dst_file, level);
+            modules.insert(from.clone());
+            modules.insert(to.clone());
+            if from == to {
+                internal_edges += count;
+            } else {
+                *counts.entry((from, to)).or_default() += count;
+            }
+        }
+
+        let modules: Vec<String> = modules.into_iter().collect();
+        let index: HashMap<&str, usize> = modules
+            .iter()
+            .enumerate()
+            .map(|(i, module)| (module.as_str(), i))
+            .collect();
+        let mut matrix = vec![vec![0; modules.len()]; modules.len()];
+        let mut edges: Vec<ModuleDependency> = counts
+            .into_iter()
+            .map(|((from, to), edge_count)| {
+                matrix[index[from.as_str()]][index[to.as_str()]] = edge_count;
+                ModuleDependency {
+                    from,
+                    to,
+                    edge_count,
+                }
+            })
+            .collect();
+        edges.sort_by(|a, b| {
+            b.edge_count
+                .cmp(&a.edge_count)
+                .then_with(|| a.from.cmp(&b.from))
+                .then_with(|| a.to.cmp(&b.to))
+        });
+
+        Ok(ModuleDependencies {
+            level,
+            modules,
+            edges,
+            matrix,
+            internal_edges,
+        })
+    }
+
+    /// Each file counts toward the nearest package with a manifest name, or
+    /// its nearest package of any kind when `all_packages` is set.
+    pub fn package_dependencies(&self, all_packages: bool) -> Result<PackageDependencies> {
+        let mut package_stmt = self.conn.prepare(
+            "SELECT id, name, json_extract(meta_json, '$.name')
+             FROM entities WHERE entity_type = 'package'",
+        )?;
+        let mut packages: HashMap<i64, (String, Option<String>)> = HashMap::new();
+        for row in package_stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })? {
+            let (id, dir, name) = row?;
+            packages.insert(id, (dir, name));
+        }
+
+        let mut member_stmt = self.conn.prepare(
+            "
+            SELECT e.src_entity_id, m.id, m.entity_type, m.file_path
+            FROM edges e
+            JOIN entities p ON p.id = e.src_entity_id AND p.entity_type = 'package'
+            JOIN entities m ON m.id = e.dst_entity_id
+            WHERE e.edge_type = 'contains'
+            ",
+        )?;
+        let mut parent: HashMap<i64, i64> = HashMap::new();
+        let mut file_owner: HashMap<String, i64> = HashMap::new();
+        for row in member_stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })? {
+            let (package_id, member_id, member_type, file_path) = row?;
+            match (member_type.as_str(), file_path) {
+                ("package", _) => {
+                    parent.insert(member_id, package_id);
+                }
+                ("file", Some(path)) => {
+                    file_owner.insert(path, package_id);
+                }
+                _ => {}
+            }
+        }
+
+        let rollup = |mut id: i64| -> Option<i64> {
+            if all_packages {
+                return Some(id);
+            }
+            for _ in 0..=packages.len() {
+                if packages.get(&id).is_some_and(|(_, name)| name.is_some()) {
+                    return Some(id);
+                }
+                id = *parent.get(&id)?;
+            }
+            None
+        };
+        let file_package: HashMap<String, i64> = file_owner
+            .into_iter()
+            .filter_map(|(path, owner)| Some((path, rollup(owner)?)))
+            .collect();
+
+        let mut file_counts: HashMap<i64, usize> = HashMap::new();
+        for package_id in file_package.values() {
+            *file_counts.entry(*package_id).or_default() += 1;
+        }
+        let mut nodes: Vec<PackageNode> = packages
+            .iter()
+            .filter(|(_, (_, name))| all_packages || name.is_some())
+            .map(|(id, (dir, name))| PackageNode {
+                dir: dir.clone(),
+                name: name.clone(),
+                files: file_counts.get(id).copied().unwrap_or_default(),
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.dir.cmp(&b.dir));
+
+        let mut edge_stmt = self.conn.prepare(
+            "
+            SELECT src.file_path, dst.file_path, COUNT(*)
+            FROM edges e
+            JOIN entities src ON src.id = e.src_entity_id
+            JOIN entities dst ON dst.id = e.dst_entity_id
+            WHERE e.edge_type = 'depends_on'
+              AND src.file_path IS NOT NULL
+              AND dst.file_path IS NOT NULL
+            GROUP BY src.file_path, dst.file_path
+            ",
+        )?;
+        let rows = edge_stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })?;
+        let mut counts: HashMap<(i64, i64), i64> = HashMap::new();
+        let mut internal_edges = 0;
+        let mut unpackaged_edges = 0;
+        for row in rows {
+            let (src_file, dst_file, count) = row?;
+            match (file_package.get(&src_file), file_package.get(&dst_file)) {
+                (Some(from), Some(to)) if from == to => internal_edges += count,
+                (Some(from), Some(to)) => *counts.entry((*from, *to)).or_default() += count,
+                _ => unpackaged_edges += count,
+            }
+        }
+
+        let mut edges: Vec<ModuleDependency> = counts
+            .into_iter()
+            .map(|((from, to), edge_count)| ModuleDependency {
+                from: packages[&from].0.clone(),
+                to: packages[&to].0.clone(),
+                edge_count,
+            })
+            .collect();
+        edges.sort_by(|a, b| {
+            b.edge_count
+                .cmp(&a.edge_count)
+                .then_with(|| a.from.cmp(&b.from))
+                .then_with(|| a.to.cmp(&b.to))
+        });
+
+        Ok(PackageDependencies {
+            packages: nodes,
+            edges,
+            internal_edges,
+            unpackaged_edges,
+        })
+    }
+
+    /// Definition, import, call, and clone counts for one indexed file.
+    /// `None` when the file is not indexed. Incoming calls are matched by
+    /// name, like `symbol_callers`.
+    pub fn file_profile(&self, path: &str) -> Result<Option<FileProfile>> {
+        let path = normalize_selector_path(path);
+        let path = path.trim_start_matches("./");
+        let Some((language, is_test, is_generated, line_count, size_bytes, indexed_at)) = self
+            .conn
+            .query_row(
+                "SELECT lang, is_test, is_generated, line_count, size_bytes, indexed_at
+                 FROM files WHERE path = ?1",
+                [path],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, bool>(1)?,
+                        row.get::<_, bool>(2)?,
+                        row.get::<_, Option<i64>>(3)?,
+                        row.get::<_, i64>(4)?,
+                        row.get::<_, String>(5)?,
+                    ))
+                },
+            )
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        let mut stmt = self.conn.prepare(
+            "SELECT COALESCE(json_extract(meta_json, '$.kind'), 'unknown'), COUNT(*)
+             FROM entities
+             WHERE entity_type = 'symbol' AND file_path = ?1
+             GROUP BY 1",
+        )?;
+        let definitions = stmt
+            .query_map([path], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<BTreeMap<String, i64>>>()?;
+
+        let count =
+            |sql: &str| -> Result<i64> { Ok(self.conn.query_row(sql, [path], |row| row.get(0))?) };
+        let imports_out =
+            count("SELECT COUNT(*) FROM edges WHERE edge_type = 'imports' AND file_path = ?1")?;
+        let imports_in = count(
+            "SELECT COUNT(DISTINCT e.src_entity_id)
+             FROM edges e
+             JOIN entities dst ON dst.id = e.dst_entity_id
+             WHERE e.edge_type = 'depends_on'
+               AND dst.entity_type = 'file'
+               AND dst.file_path = ?1
+               AND e.src_entity_id != dst.id",
+        )?;
+        let calls_out =
+            count("SELECT COUNT(*) FROM edges WHERE edge_type = 'calls' AND file_path = ?1")?;
+        let (calls_in, caller_files) = self.conn.query_row(
+            "SELECT COUNT(*), COUNT(DISTINCT c.file_path)
+             FROM edges c
+             WHERE c.edge_type = 'calls'
+               AND c.file_path != ?1
+               AND c.dst_entity_id IN (
+                   SELECT n.dst_entity_id
+                   FROM edges n
+                   JOIN entities s ON s.id = n.src_entity_id
+                   WHERE n.edge_type = 'names'
+                     AND s.entity_type = 'symbol'
+                     AND s.file_path = ?1
+               )",
+            [path],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        let (clones, _, _) = self.clone_matches_page(
+            path,
+            &CloneQueryOptions {
+                min_similarity: 0.0,
+                limit: 1,
+                ..CloneQueryOptions::default()
+            },
+        )?;
+        let top_clone = clones.into_iter().next();
+
+        Ok(Some(FileProfile {
+            file_path: path.to_string(),
+            language,
+            is_test,
+            is_generated,
+            line_count,
+            size_bytes,
+            indexed_at,
+            definitions,
+            imports_out,
+            imports_in,
+            calls_out,
+            calls_in,
+            caller_files,
+            max_clone_similarity: top_clone.as_ref().map(|clone| clone.similarity),
+            most_similar_file: top_clone.map(|clone| clone.other_file),
+        }))
+    }
+
+    /// Aggregates stored symbol definitions for every indexed file under
+    /// `directory` without re-parsing sources.
+    pub fn dir_outline(&self, directory: &str, options: &DirOutlineOptions) -> Result<DirOutline> {
+        let (directory, prefix) = dir_prefix(directory);
+
+        let mut file_stmt = self.conn.prepare(
+            "SELECT path, lang FROM files
+             WHERE ?1 = '' OR substr(path, 1, length(?1)) = ?1
+             ORDER BY path",
+        )?;
+        let file_rows = file_stmt.query_map([&prefix], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut all_files = Vec::new();
+        for row in file_rows {
+            all_files.push(row?);
+        }
+
+        let total_files = all_files.len();
+        let truncated = total_files > options.max_files;
+        all_files.truncate(options.max_files);
+
+        let mut symbol_stmt = self.conn.prepare(
+            "
+            SELECT name, line, end_line,
+                   json_extract(meta_json, '$.kind') as kind,
+                   json_extract(meta_json, '$.qualname') as qualname,
+                   json_extract(meta_json, '$.signature') as signature
+            FROM entities
+            WHERE entity_type = 'symbol' AND file_path = ?1
+            ORDER BY line, col
+            ",
+        )?;
+
+        let mut files = Vec::with_capacity(all_files.len());
+        let mut total_entries = 0;
+        for (path, language) in all_files {
+            let rows = symbol_stmt.query_map([&path], |row| {
+                let name: String = row.get(0)?;
+                Ok(OutlineEntry {
+                    kind: row
+                        .get::<_, Option<String>>(3)?
+                        .unwrap_or_else(|| "unknown".to_string()),
+                    qualname: row
+                        .get::<_, Option<String>>(4)?
+                        .unwrap_or_else(|| name.clone()),
+                    line: row.get::<_, Option<i64>>(1)?.unwrap_or_default(),
+                    end_line: row.get(2)?,
+                    signature: row.get(5)?,
+                    depth: 0,
+                    name,
+                })
+            })?;
+
+            // Nesting comes from the stored spans, not the qualname, whose
+            // separator (`::`, `.`, `#`) depends on the language.
+            let mut entries = Vec::new();
+            let mut enclosing: Vec<i64> = Vec::new();
+            for row in rows {
+                let mut entry = row?;
+                let end_line = entry.end_line.unwrap_or(entry.line);
+                while enclosing
+                    .last()
+                    .is_some_and(|&outer_end| outer_end < end_line)
+                {
+                    enclosing.pop();
+                }
+                entry.depth = enclosing.len();
+                enclosing.push(end_line);
+                if options
+                    .max_depth
+                    .map_or(true, |max_depth| entry.depth <= max_depth)
+                {
+                    entries.push(entry);
+                }
+            }
+            total_entries += entries.len();
+            files.push(FileOutline {
+                path,
+                language,
+                entries,
+            });
+        }
+
+        Ok(DirOutline {
+            directory,
+            files,
+            total_files,
+            total_entries,
+            truncated,
+        })
+    }
+
+    /// Ranks stored vectors by cosine similarity to `query_vector`, which
+    /// must come from the same model.
+    pub fn semantic_search(
+        &self,
+        query_vector: &[f32],
+        options: &SemanticSearchOptions,
+    ) -> Result<Vec<SemanticMatch>> {
+        let mut where_clauses = vec!["1 = 1".to_string()];
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+        if let Some(kind) = options.kind.as_deref() {
+            where_clauses.push("kind = ?".to_string());
+            params.push(Box::new(kind.to_string()));
+        }
+        if let Some(glob) = options.file_glob.as_deref() {
+            where_clauses.push("file_path GLOB ?".to_string());
+            params.push(Box::new(glob.replace('\\', "/")));
+        }
+
+        let sql = format!(
+            "SELECT kind, label, detail, file_path, start_line, end_line, vector
+             FROM embeddings WHERE {}",
+            where_clauses.join(" AND ")
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let bind_params = rusqlite::params_from_iter(params.iter().map(|p| &**p));
+        let rows = stmt.query_map(bind_params, |row| {
+            let vector: Vec<u8> = row.get(6)?;
+            Ok(SemanticMatch {
+                kind: row.get(0)?,
+                label: row.get(1)?,
+                detail: row.get(2)?,
+                file_path: row.get(3)?,
+                start_line: row.get(4)?,
+                end_line: row.get(5)?,
+                score: embeddings::cosine_similarity(
+                    query_vector,
+                    &embeddings::decode_vector(&vector),
+                ) as f64,
+            })
+        })?;
+
+        let mut out = Vec::new();
+        for row in rows {
+            let item = row?;
+            if item.score >= options.min_score {
+                out.push(item);
+            }
+        }
+        out.sort_by(|left, right| {
+            right
+                .score
+                .partial_cmp(&left.score)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| left.file_path.cmp(&right.file_path))
+                .then_with(|| left.start_line.cmp(&right.start_line))
+        });
+        out.truncate(options.limit.max(1));
+        Ok(out)
+    }
+
+    pub fn symbol_references_page(
+        &self,
+        symbol_name: &str,
+        options: &ReferenceQueryOptions,
+    ) -> Result<(Vec<ReferenceLocation>, PaginationInfo)> {
+        let query = self.reference_cursor_query(symbol_name, options)?;
+        let (out, offset, cursor_id) = match options.cursor.as_deref() {
+            Some(cursor) => {
+                let (cursor_id, offset, rows) = reference_cursor_lookup(cursor, &query)?;
+                (rows, offset, Some(cursor_id))
+            }
+            None => (
+                Arc::new(self.symbol_references_unpaged(symbol_name, options)?),
+                options.offset,
+                None,
+            ),
+        };
+
+        let total = out.len();
+        let start = offset.min(total);
+        let end = start.saturating_add(options.limit).min(total);
+        let rows = out[start..end].to_vec();
+        let mut pagination = build_pagination(total, offset, options.limit, rows.len());
+        // A timed-out result is incomplete, so it is not cached for paging.
+        if let (Some(next_offset), false) = (pagination.next_offset, self.timed_out()) {
+            let cursor_id = match cursor_id {
+                Some(cursor_id) => cursor_id,
+                None => reference_cursor_store(query, out),
+            };
+            pagination.next_cursor = Some(format!("{cursor_id}:{next_offset}"));
+        }
+        Ok((rows, pagination))
+    }
+
+    /// Counts references per innermost enclosing definition, using stored
+    /// definition spans, and pages over the groups (largest first). Cursors
+    /// are not supported here; page with `offset`.
+    pub fn symbol_references_grouped(
+        &self,
+        symbol_name: &str,
+        options: &ReferenceQueryOptions,
+    ) -> Result<(Vec<EnclosingSymbolGroup>, PaginationInfo)> {
+        if options.cursor.is_some() {
+            return Err(coded(
+                ErrorCode::InvalidParams,
+                "`cursor` cannot be combined with grouping; page groups with `offset`",
+            ));
+        }
+        let references = self.symbol_references_unpaged(symbol_name, options)?;
+
+        let mut stmt = self.conn.prepare_cached(
+            "
+            SELECT json_extract(meta_json, '$.qualname'), json_extract(meta_json, '$.kind'),
+                   line, COALESCE(end_line, line)
+            FROM entities
+            WHERE entity_type = 'symbol' AND file_path = ?1 AND line IS NOT NULL
+            ",
+        )?;
+        let mut spans_by_file: HashMap<String, Vec<DefinitionSpan>> = HashMap::new();
+        let mut groups: HashMap<(String, Option<usize>), EnclosingSymbolGroup> = HashMap::new();
+        for reference in &references {
+            if !spans_by_file.contains_key(&reference.file_path) {
+                let rows = stmt.query_map([&reference.file_path], |row| {
+                    Ok(DefinitionSpan {
+                        qualname: row.get::<_, Option<String>>(0)?.unwrap_or_default(),
+                        kind: row.get(1)?,
+                        line: row.get(2)?,
+                        end_line: row.get(3)?,
+                    })
+                })?;
+                let spans = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+                spans_by_file.insert(reference.file_path.clone(), spans);
+            }
+            let spans = &spans_by_file[&reference.file_path];
+            // Innermost span: latest start, then earliest end.
+            let enclosing = spans
+                .iter()
+                .enumerate()
+                .filter(|(_, span)| span.line <= reference.line && reference.line <= span.end_line)
+                .max_by(|(_, left), (_, right)| {
+                    left.line
+                        .cmp(&right.line)
+                        .then(right.end_line.cmp(&left.end_line))
+                })
+                .map(|(index, _)| index);
+
+            let group = groups
+                .entry((reference.file_path.clone(), enclosing))
+                .or_insert_with(|| {
+                    let span = enclosing.map(|index| &spans[index]);
+                    EnclosingSymbolGroup {
+                        file_path: reference.file_path.clone(),
+                        enclosing_symbol: span.map(|span| span.qualname.clone()),
+                        kind: span.and_then(|span| span.kind.clone()),
+                        line: span.map(|span| span.line),
+                        end_line: span.map(|span| span.end_line),
+                        count: 0,
+                        calls: 0,
+                        lines: Vec::new(),
+                    }
+                });
+            group.count += 1;
+            if reference.edge_type == "calls" {
+                group.calls += 1;
+            }
+            group.lines.push(reference.line);
+        }
+
+        let mut out: Vec<EnclosingSymbolGroup> = groups.into_values().collect();
+        for group in &mut out {
+            group.lines.sort_unstable();
+            group.lines.dedup();
+        }
+        out.sort_by(|left, right| {
+            right
+                .count
+                .cmp(&left.count)
+                .then_with(|| left.file_path.cmp(&right.file_path))
+                .then_with(|| left.lines.first().cmp(&right.lines.first()))
+        });
+
+        let total = out.len();
+        let start = options.offset.min(total);
+        let end = start.saturating_add(options.limit).min(total);
+        let rows = out[start..end].to_vec();
+        let pagination = build_pagination(total, options.offset, options.limit, rows.len());
+        Ok((rows, pagination))
+    }
+
+    fn symbol_references_unpaged(
+        &self,
+        symbol_name: &str,
+        options: &ReferenceQueryOptions,
+    ) -> Result<Vec<ReferenceLocation>> {
+        let names = self.alias_names(symbol_name)?;
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+        let name_types = if options.exclude_externals {
+            "('symbol_name')"
+        } else {
+            "('symbol_name', 'external')"
+        };
+        let mut where_clauses = vec![
+            format!("sn.entity_type IN {name_types}"),
+            format!("sn.name IN ({})", vec!["?"; names.len()].join(", ")),
+        ];
+        for name in names {
+            params.push(Box::new(name));
+        }
+
+        match options.edge_type_filter.as_deref() {
+            Some(edge_type) => {
+                where_clauses.push("e.edge_type = ?".to_string());
+                params.push(Box::new(edge_type.to_string()));
+            }
+            None => where_clauses.push("e.edge_type IN ('references', 'calls')".to_string()),
+        }
+
+        if let Some(glob) = options.file_glob.as_deref() {
+            where_clauses.push("e.file_path GLOB ?".to_string());
+            params.push(Box::new(glob.replace('\\', "/")));
+        }
+
+        if let Some(language) = options.language.as_deref() {
+            where_clauses.push("f.lang = ?".to_string());
+            params.push(Box::new(language.to_string()));
+        }
+
+        if let Some(max_age_hours) = options.max_age_hours {
+            where_clauses.push("f.indexed_at >= datetime('now', ?)".to_string());
+            params.push(Box::new(format!("-{max_age_hours} hours")));
+        }
+
+        if options.resolved_only {
+            where_clauses
+                .push("json_extract(e.meta_json, '$.resolved_symbol_key') IS NOT NULL".to_string());
+        }
+
+        if options.exclude_heuristic {
+            where_clauses.push(NOT_HEURISTIC_EDGE.to_string());
+        }
+
+        if !options.include_tests {
+            where_clauses.push("COALESCE(f.is_test, 0) = 0".to_string());
+        }
+
+        if !options.include_generated {
+            where_clauses.push("COALESCE(f.is_generated, 0) = 0".to_string());
+        }
+
+        let sql = format!(
+            "
+            SELECT sn.name, e.file_path, e.line, e.col, e.edge_type,
+                   json_extract(e.meta_json, '$.resolved_symbol_key'), e.meta_json,
+                   e.end_line, e.end_col, json_extract(e.meta_json, '$.arity'),
+                   e.origin, e.confidence
+            FROM entities sn
+            JOIN edges e ON e.dst_entity_id = sn.id
+            LEFT JOIN files f ON f.path = e.file_path
+            WHERE {}
+            ORDER BY e.file_path ASC, e.line ASC, e.col ASC
+            ",
+            where_clauses.join(" AND ")
+        );
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let bind_params = rusqlite::params_from_iter(params.iter().map(|p| &**p));
+        let rows = stmt.query_map(bind_params, |row| {
+            let location = ReferenceLocation {
+                row_id: String::new(),
+                symbol_name: row.get(0)?,
+                file_path: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                line: row.get::<_, Option<i64>>(2)?.unwrap_or_default(),
+                col: row.get::<_, Option<i64>>(3)?.unwrap_or_default(),
+                end_line: row.get(7)?,
+                end_col: row.get(8)?,
+                edge_type: row.get(4)?,
+                resolved_symbol_key: row.get(5)?,
+                score: None,
+                confidence: None,
+                why: None,
+                origin: row.get(10)?,
+                offsets: meta_offsets(row.get(6)?),
+            }
+            .with_row_id();
+            Ok((
+                location,
+                row.get::<_, Option<i64>>(9)?,
+                row.get::<_, Option<f64>>(11)?,
+            ))
+        })?;
+
+        let mut out = self.collect_within_deadline(rows)?;
+
+        if options.dedup {
+            let mut seen = HashSet::new();
+            out.retain(|(item, _, _)| {
+                seen.insert((
+                    item.file_path.clone(),
+                    item.line,
+                    item.col,
+                    item.edge_type.clone(),
+                ))
+            });
+        }
+
+        let def_files = self.definition_files_for_symbol(symbol_name)?;
+        let mut importers = HashSet::new();
+        for def_file in &def_files {
+            for (importer, _, _) in self.imports_into(def_file)? {
+                importers.insert(importer);
+            }
+        }
+        let arities = self.definition_arities(symbol_name)?;
+        for (item, call_arity, edge_confidence) in &mut out {
+            let mut score = if item.edge_type == "calls" { 2.0 } else { 1.0 };
+            let mut confidence: f64 = 0.5;
+            let mut why = vec![format!("edge_type={}", item.edge_type)];
+            if item.symbol_name == symbol_name {
+                confidence += 0.1;
+                why.push("exact_name".to_string());
+            } else {
+                why.push(format!("alias_of={symbol_name}"));
+            }
+            if def_files.contains(&item.file_path) {
+                score += 0.35;
+                confidence += 0.1;
+                why.push("same_file_as_definition".to_string());
+            } else if importers.contains(&item.file_path) {
+                score += 0.2;
+                confidence += 0.15;
+                why.push("imports_definition".to_string());
+            } else if def_files.is_empty() {
+                confidence -= 0.1;
+            }
+            if item.resolved_symbol_key.is_some() {
+                score += 0.25;
+                confidence += 0.25;
+                why.push("resolved".to_string());
+            }
+            // Only judge arity against the definition the call resolved to,
+            // or against every same-named definition when unresolved.
+            let candidates: Vec<&Arity> = arities
+                .iter()
+                .filter(|(key, _)| {
+                    item.resolved_symbol_key
+                        .as_ref()
+                        .map_or(true, |resolved| resolved == key)
+                })
+                .map(|(_, arity)| arity)
+                .collect();
+            if let (Some(args), false) = (*call_arity, candidates.is_empty()) {
+                if candidates.iter().any(|arity| arity.accepts(args as usize)) {
+                    score += 0.1;
+                    confidence += 0.1;
+                    why.push("arity_match".to_string());
+                } else {
+                    score -= 0.5;
+                    confidence -= 0.3;
+                    why.push("arity_mismatch".to_string());
+                }
+            }
+            // An edge the indexer only guessed at caps how sure the match can be.
+            if let Some(edge_confidence) = *edge_confidence {
+                if edge_confidence < 1.0 {
+                    confidence *= edge_confidence;
+                    why.push(format!(
+                        "origin={}",
+                        item.origin.as_deref().unwrap_or("unknown")
+                    ));
+                }
+            }
+            item.score = Some(score);
+            item.confidence = Some((confidence.clamp(0.0, 1.0) * 100.0).round() / 100.0);
+            item.why = Some(why.join(","));
+        }
+
+        let mut out: Vec<ReferenceLocation> = out.into_iter().map(|(item, _, _)| item).collect();
+        if let Some(min_confidence) = options.min_confidence {
+            out.retain(|item| item.confidence.unwrap_or_default() >= min_confidence);
+        }
+        out.sort_by(reference_sorter(options.order));
+        Ok(out)
+    }
+
+    /// `symbol_name` plus every name linked to it through `aliases` edges, in
+    /// either direction and transitively, so a query for a canonical name
+    /// also finds uses under its re-exported or imported-as names.
+    fn alias_names(&self, symbol_name: &str) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare_cached(
+            "
+            SELECT DISTINCT other.name
+            FROM entities sn
+            JOIN edges e ON e.edge_type = 'aliases'
+                        AND (e.src_entity_id = sn.id OR e.dst_entity_id = sn.id)
+            JOIN entities other ON other.id = CASE WHEN e.src_entity_id = sn.id
+                                                   THEN e.dst_entity_id
+                                                   ELSE e.src_entity_id END
+            WHERE sn.entity_type IN ('symbol_name', 'external') AND sn.name = ?1
+            ",
+        )?;
+        let mut names = vec![symbol_name.to_string()];
+        let mut seen: HashSet<String> = names.iter().cloned().collect();
+        let mut index = 0;
+        while index < names.len() {
+            let rows = stmt.query_map([&names[index]], |row| row.get::<_, String>(0))?;
+            for name in rows {
+                let name = name?;
+                if seen.insert(name.clone()) {
+                    names.push(name);
+                }
+            }
+            index += 1;
+        }
+        Ok(names)
+    }
+
+    pub fn dependency_path(
+        &self,
+        from_selector: &str,
+        to_selector: &str,
+        max_depth: usize,
+    ) -> Result<DependencyPath> {
+        let (from, _) = self.choose_selector(from_selector, None)?;
+        let (to, _) = self.choose_selector(to_selector, None)?;
+        self.dependency_path_between(from, to, max_depth)
+    }
+
+    fn dependency_path_between(
+        &self,
+        from: Option<Entity>,
+        to: Option<Entity>,
+        max_depth: usize,
+    ) -> Result<DependencyPath> {
+        let (Some(from), Some(to)) = (from, to) else {
+            return Ok(DependencyPath {
+                found: false,
+                hops: Vec::new(),
+            });
+        };
+
+        if from.id == to.id {
+            return Ok(DependencyPath {
+                found: true,
+                hops: self.path_hops(vec![(from.id, None)])?,
+            });
+        }
+
+        // A package is reached through any file inside it; the package itself
+        // is appended as the last hop.
+        let package_members = if to.entity_type == "package" {
+            self.package_member_files(to.id)?
+        } else {
+            HashSet::new()
+        };
+        if package_members.contains(&from.id) {
+            return Ok(DependencyPath {
+                found: true,
+                hops: self.path_hops(vec![(from.id, None), (to.id, Some(EdgeStep::contains()))])?,
+            });
+        }
+
+        let mut queue: VecDeque<(i64, usize)> = VecDeque::new();
+        let mut seen: HashSet<i64> = HashSet::new();
+        let mut prev: HashMap<i64, (i64, EdgeStep)> = HashMap::new();
+
+        queue.push_back((from.id, 0));
+        seen.insert(from.id);
+
+        while let Some((current, depth)) = queue.pop_front() {
+            if depth >= max_depth {
+                continue;
+            }
+            if self.out_of_time() {
+                break;
+            }
+            let Some(edges) = self.unless_timed_out(self.outgoing_edges(current))? else {
+                break;
+            };
+            for (neighbor, step) in edges {
+                if seen.insert(neighbor) {
+                    prev.insert(neighbor, (current, step));
+                    let via_member = package_members.contains(&neighbor);
+                    if neighbor == to.id || via_member {
+                        let mut chain = Vec::new();
+                        let mut cursor = neighbor;
+                        while let Some((parent, step)) = prev.remove(&cursor) {
+                            chain.push((cursor, Some(step)));
+                            cursor = parent;
+                            if parent == from.id {
+                                break;
+                            }
+                        }
+                        chain.push((from.id, None));
+                        chain.reverse();
+                        if via_member {
+                            chain.push((to.id, Some(EdgeStep::contains())));
+                        }
+                        return Ok(DependencyPath {
+                            found: true,
+                            hops: self.path_hops(chain)?,
+                        });
+                    }
+                    queue.push_back((neighbor, depth + 1));
+                }
+            }
+        }
+
+        Ok(DependencyPath {
+            found: false,
+            hops: Vec::new(),
+        })
+    }
+
+    /// Turns a chain of entity ids, each with the edge that led to it, into
+    /// path hops.
+    fn path_hops(&self, chain: Vec<(i64, Option<EdgeStep>)>) -> Result<Vec<PathHop>> {
+        let mut hops = Vec::with_capacity(chain.len());
+        for (entity_id, step) in chain {
+            let entity = self.entity_by_id(entity_id)?;
+            let (edge_type, edge_file_path, edge_line) = match step {
+                Some(step) => (Some(step.edge_type), step.file_path, step.line),
+                None => (None, None, None),
+            };
+            hops.push(PathHop {
+                entity_key: entity.key,
+                entity_name: entity.name,
+                entity_type: entity.entity_type,
+                edge_type,
+                edge_file_path,
+                edge_line,
+            });
+        }
+        Ok(hops)
+    }
+
+    /// Like [`GraphStore::dependency_path`], but `from_pick`/`to_pick` choose
+    /// among ambiguous selector candidates and the resolutions are returned.
+    pub fn dependency_path_with_diagnostics(
+        &self,
+        from_selector: &str,
+        to_selector: &str,
+        max_depth: usize,
+        from_pick: Option<usize>,
+        to_pick: Option<usize>,
+    ) -> Result<(DependencyPath, SelectorResolution, SelectorResolution)> {
+        let (from, from_diag) = self.choose_selector(from_selector, from_pick)?;
+        let (to, to_diag) = self.choose_selector(to_selector, to_pick)?;
+        let path = self.dependency_path_between(from, to, max_depth)?;
+        Ok((path, from_diag, to_diag))
+    }
+
+    /// Up to `max_paths` distinct paths from `from_selector` to
+    /// `to_selector`, shortest first, with the edge type of every hop. Like
+    /// [`GraphStore::dependency_path_with_diagnostics`], picks choose among
+    /// ambiguous selector candidates.
+    pub fn dependency_paths_with_diagnostics(
+        &self,
+        from_selector: &str,
+        to_selector: &str,
+        max_depth: usize,
+        max_paths: usize,
+        from_pick: Option<usize>,
+        to_pick: Option<usize>,
+    ) -> Result<(DependencyPaths, SelectorResolution, SelectorResolution)> {
+        let (from, from_diag) = self.choose_selector(from_selector, from_pick)?;
+        let (to, to_diag) = self.choose_selector(to_selector, to_pick)?;
+        let paths = self.dependency_paths_between(from, to, max_depth, max_paths.max(1))?;
+        Ok((paths, from_diag, to_diag))
+    }
+
+    /// Breadth-first search over simple paths (no entity repeats within a
+    /// path). Each entity is expanded at most `max_paths` times, which is
+    /// enough to find the `max_paths` shortest paths through it without
+    /// enumerating every walk through a hub.
+    fn dependency_paths_between(
+        &self,
+        from: Option<Entity>,
+        to: Option<Entity>,
+        max_depth: usize,
+        max_paths: usize,
+    ) -> Result<DependencyPaths> {
+        let mut result = DependencyPaths {
+            found: false,
+            paths: Vec::new(),
+            truncated: false,
+        };
+        let (Some(from), Some(to)) = (from, to) else {
+            return Ok(result);
+        };
+        let package_members = if to.entity_type == "package" {
+            self.package_member_files(to.id)?
+        } else {
+            HashSet::new()
+        };
+
+        let mut queue: VecDeque<Vec<(i64, Option<EdgeStep>)>> = VecDeque::new();
+        let mut expansions: HashMap<i64, usize> = HashMap::new();
+        let mut steps = 0;
+        queue.push_back(vec![(from.id, None)]);
+
+        while let Some(chain) = queue.pop_front() {
+            let current = chain[chain.len() - 1].0;
+            if current == to.id || package_members.contains(&current) {
+                let mut chain = chain;
+                if current != to.id {
+                    chain.push((to.id, Some(EdgeStep::contains())));
+                }
+                result.paths.push(self.path_hops(chain)?);
+                if result.paths.len() >= max_paths {
+                    break;
+                }
+                continue;
+            }
+            if chain.len() > max_depth {
+                continue;
+            }
+            let visits = expansions.entry(current).or_default();
+            if *visits >= max_paths {
+                continue;
+            }
+            *visits += 1;
+            steps += 1;
+            if steps > MAX_PATH_SEARCH_STEPS || self.out_of_time() {
+                result.truncated = true;
+                break;
+            }
+            let Some(edges) = self.unless_timed_out(self.outgoing_edges(current))? else {
+                result.truncated = true;
+                break;
+            };
+            for (neighbor, step) in edges {
+                if chain.iter().any(|(id, _)| *id == neighbor) {
+                    continue;
+                }
+                let mut next = chain.clone();
+                next.push((neighbor, Some(step)));
+                queue.push_back(next);
+            }
+        }
+
+        result.found = !result.paths.is_empty();
+        Ok(result)
+    }
+
+    /// Resolves a selector to a symbol definition. `symbol_name:` selectors
+    /// (and auto selectors that land on one) choose among the definitions
+    /// named by it; file selectors and unknown names return `None`. `pick`
+    /// indexes the candidates, defaulting to the first.
+    pub fn symbol_for_selector(
+        &self,
+        selector: &str,
+        pick: Option<usize>,
+    ) -> Result<(Option<Entity>, SelectorResolution)> {
+        let lookup = self.resolve_selector(selector)?;
+        let matched = lookup.candidates.len();
+        let mut choices = selector_choices(lookup.candidates);
+        if choices
+            .first()
+            .is_some_and(|entity| entity.entity_type == "symbol_name")
+        {
+            let mut definitions = Vec::new();
+            for name in &choices {
+                definitions.extend(self.definitions_named_by(name.id)?);
+            }
+            choices = definitions;
+        }
+        choices.retain(|entity| entity.entity_type == "symbol");
+        choose_candidate(selector, lookup.parsed_as, matched, choices, pick)
+    }
+
+    /// Resolves `selector` to a graph entity, choosing among its candidates
+    /// with `pick` when given.
+    fn choose_selector(
+        &self,
+        selector: &str,
+        pick: Option<usize>,
+    ) -> Result<(Option<Entity>, SelectorResolution)> {
+        let lookup = self.resolve_selector(selector)?;
+        let matched = lookup.candidates.len();
+        let choices = selector_choices(lookup.candidates);
+        choose_candidate(selector, lookup.parsed_as, matched, choices, pick)
+    }
+
+    /// One-hop neighborhood of whatever `selector` resolves to: edge counts
+    /// per type in each direction, busiest type first, with up to `limit`
+    /// example neighbors per group.
+    pub fn symbol_neighbors(
+        &self,
+        selector: &str,
+        pick: Option<usize>,
+        limit: usize,
+    ) -> Result<(Option<SymbolNeighbors>, SelectorResolution)> {
+        let (entity, resolution) = self.choose_selector(selector, pick)?;
+        let Some(entity) = entity else {
+            return Ok((None, resolution));
+        };
+        let outgoing = self.neighbor_groups(entity.id, true, limit)?;
+        let incoming = self.neighbor_groups(entity.id, false, limit)?;
+        Ok((
+            Some(SymbolNeighbors {
+                entity: entity.with_meta_fields(),
+                outgoing,
+                incoming,
+            }),
+            resolution,
+        ))
+    }
+
+    fn neighbor_groups(
+        &self,
+        entity_id: i64,
+        outgoing: bool,
+        limit: usize,
+    ) -> Result<Vec<NeighborGroup>> {
+        let (this_end, other_end) = if outgoing {
+            ("src_entity_id", "dst_entity_id")
+        } else {
+            ("dst_entity_id", "src_entity_id")
+        };
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT edge_type, COUNT(*), COUNT(DISTINCT {other_end})
+             FROM edges
+             WHERE {this_end} = ?1
+             GROUP BY edge_type
+             ORDER BY COUNT(*) DESC, edge_type"
+        ))?;
+        let counts = stmt
+            .query_map([entity_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT n.key, n.name, n.entity_type,
+                    COALESCE(e.file_path, n.file_path), COALESCE(e.line, n.line)
+             FROM edges e
+             JOIN entities n ON n.id = e.{other_end}
+             WHERE e.{this_end} = ?1 AND e.edge_type = ?2
+             ORDER BY 4, 5, n.key
+             LIMIT ?3"
+        ))?;
+        let mut groups = Vec::with_capacity(counts.len());
+        for (edge_type, count, distinct) in counts {
+            let neighbors = stmt
+                .query_map(params![entity_id, edge_type, limit as i64], |row| {
+                    Ok(Neighbor {
+                        key: row.get(0)?,
+                        name: row.get(1)?,
+                        entity_type: row.get(2)?,
+                        file_path: row.get(3)?,
+                        line: row.get(4)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            groups.push(NeighborGroup {
+                edge_type,
+                count,
+                distinct,
+                neighbors,
+            });
+        }
+        Ok(groups)
+    }
+
+    fn definitions_named_by(&self, name_entity_id: i64) -> Result<Vec<Entity>> {
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT s.id, s.entity_type, s.key, s.name, s.lang, s.file_path, s.line, s.col,
+                   s.end_line, s.end_col, s.meta_json
+            FROM edges e
+            JOIN entities s ON s.id = e.src_entity_id
+            WHERE e.dst_entity_id = ?1 AND e.edge_type = 'names'
+            ORDER BY s.file_path, s.line, s.col
+            ",
+        )?;
+        let rows = stmt.query_map([name_entity_id], map_entity)?;
+        rows.collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(Into::into)
+    }
+
+    pub fn minimal_slice_with_options(
+        &self,
+        file_path: &str,
+        line: Option<i64>,
+        depth: usize,
+        options: &SliceQueryOptions,
+    ) -> Result<Option<SliceResult>> {
+        let anchor = if let Some(line_no) = line {
+            self.anchor_symbol_for_line(file_path, line_no)?
+                .or_else(|| self.find_entity_by_key(&file_key(file_path)).ok().flatten())
+        } else {
+            self.find_entity_by_key(&file_key(file_path))?
+        };
+
+        let Some(anchor) = anchor else {
+            return Ok(None);
+        };
+
+        let mut neighbors = Vec::new();
+        let mut frontier = vec![(anchor.id, 0usize)];
+        let mut seen: HashSet<i64> = HashSet::new();
+        seen.insert(anchor.id);
         let mut seen_edges: HashSet<(String, String, i64, String)> = HashSet::new();
 
-        for _ in 0..depth.max(1) {
+        'levels: for _ in 0..depth.max(1) {
             let mut next = Vec::new();
             for (node_id, level) in frontier {
-                for mut related in self.neighbor_edges(node_id)? {
-                    if seen.insert(related.entity.id) {
+                if self.out_of_time() {
+                    break 'levels;
+                }
+                let Some(edges) = self.unless_timed_out(self.neighbor_edges(node_id))? else {
+                    break 'levels;
+                };
+                for mut related in edges {
+                    if options.exclude_externals && related.entity.entity_type == "external" {
+                        continue;
+                    }
+                    if options.exclude_heuristic
+                        && related.origin.as_deref() == Some(EdgeOrigin::Heuristic.as_str())
+                    {
+                        continue;
+                    }
+                    // Packages are listed but not expanded; going through one
+                    // would pull in every sibling file.
+                    if seen.insert(related.entity.id) && related.entity.entity_type != "package" {
                         next.push((related.entity.id, level + 1));
                     }
                     if options.dedup
@@ -849,8 +3618,13 @@ impl GraphStore {
                         continue;
                     }
 
-                    let score =
-                        score_related_edge(&related, level + 1, options.prefer_project_symbols);
+                    let score = score_related_edge(
+                        &related,
+                        level + 1,
+                        options.prefer_project_symbols,
+                        &self.low_signal,
+                        anchor.lang.as_deref(),
+                    );
                     related.depth = Some((level + 1) as i64);
                     related.score = Some(score);
                     related.why = Some(format!(
@@ -883,7 +3657,7 @@ impl GraphStore {
             let cap = options.low_signal_name_cap.max(1);
             let mut seen_symbol_names: HashMap<String, usize> = HashMap::new();
             neighbors.retain(|edge| {
-                if edge.entity.entity_type != "symbol_name" {
+                if !matches!(edge.entity.entity_type.as_str(), "symbol_name" | "external") {
                     return true;
                 }
                 let per_name_cap = cap;
@@ -901,13 +3675,32 @@ impl GraphStore {
         Ok(Some(SliceResult { anchor, neighbors }))
     }
 
-    pub fn clone_matches_with_options(
-        &self,
-        file_path: &str,
-        options: &CloneQueryOptions,
-    ) -> Result<Vec<CloneMatch>> {
-        let (rows, _, _) = self.clone_matches_page(file_path, options)?;
-        Ok(rows)
+    /// Line ranges shared between two files, built from the spans of their
+    /// common fingerprints. Fingerprints stored before spans were recorded
+    /// carry no lines and are left out.
+    fn clone_regions(&self, file_path: &str, other_file: &str) -> Result<Vec<CloneRegion>> {
+        let mut stmt = self.conn.prepare_cached(
+            "
+            SELECT f1.start_line, f1.end_line, f2.start_line, f2.end_line
+            FROM fingerprints f1
+            JOIN fingerprints f2 ON f1.fp_hash = f2.fp_hash
+            WHERE f1.file_path = ?1
+              AND f2.file_path = ?2
+              AND f1.start_line IS NOT NULL
+              AND f2.start_line IS NOT NULL
+            ORDER BY f1.start_line, f2.start_line
+            ",
+        )?;
+        let rows = stmt.query_map([file_path, other_file], |row| {
+            Ok(CloneRegion {
+                start_line: row.get(0)?,
+                end_line: row.get(1)?,
+                other_start_line: row.get(2)?,
+                other_end_line: row.get(3)?,
+            })
+        })?;
+        let spans = self.collect_within_deadline(rows)?;
+        Ok(merge_clone_regions(spans))
     }
 
     pub fn clone_matches_page(
@@ -928,21 +3721,27 @@ impl GraphStore {
                 candidate_files: 0,
                 surviving_candidates: 0,
                 filtered_by_threshold: 0,
+                filtered_by_language: 0,
+                filtered_tests: 0,
+                filtered_generated: 0,
                 max_candidate_similarity: None,
                 suggested_min_similarity: Some(0.0),
                 empty_reason: Some(
                     "source file has no fingerprints; file may be too small or not yet indexed"
                         .to_string(),
                 ),
+                params_warning: None,
             };
             return Ok((Vec::new(), pagination, analysis));
         }
 
         let mut shared_stmt = self.conn.prepare(
             "
-            SELECT f2.file_path, COUNT(DISTINCT f1.fp_hash) AS shared_count
+            SELECT f2.file_path, COUNT(DISTINCT f1.fp_hash) AS shared_count, fl.lang,
+                   COALESCE(fl.is_test, 0), COALESCE(fl.is_generated, 0)
             FROM fingerprints f1
             JOIN fingerprints f2 ON f1.fp_hash = f2.fp_hash
+            LEFT JOIN files fl ON fl.path = f2.file_path
             WHERE f1.file_path = ?1
               AND f2.file_path != ?1
             GROUP BY f2.file_path
@@ -951,8 +3750,17 @@ impl GraphStore {
         )?;
 
         let shared_rows = shared_stmt.query_map([file_path], |row| {
-            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, bool>(3)?,
+                row.get::<_, bool>(4)?,
+            ))
         })?;
+        // The self-join is the expensive part; collect it first so the
+        // deadline bounds it rather than the per-file totals below.
+        let shared_rows = self.collect_within_deadline(shared_rows)?;
 
         let mut counts_stmt = self.conn.prepare(
             "SELECT file_path, COUNT(DISTINCT fp_hash) FROM fingerprints GROUP BY file_path",
@@ -967,9 +3775,33 @@ impl GraphStore {
             totals.insert(path, cnt);
         }
 
+        let own_language = if options.same_language_only {
+            self.tracked_file_language(file_path)?
+        } else {
+            None
+        };
+        let mut filtered_by_language = 0;
+        let mut filtered_tests = 0;
+        let mut filtered_generated = 0;
         let mut all_candidates = Vec::new();
-        for row in shared_rows {
-            let (other_file, shared_count) = row?;
+        for (other_file, shared_count, language, is_test, is_generated) in shared_rows {
+            if is_test && !options.include_tests {
+                filtered_tests += 1;
+                continue;
+            }
+            if is_generated && !options.include_generated {
+                filtered_generated += 1;
+                continue;
+            }
+            let excluded = options
+                .language
+                .as_deref()
+                .is_some_and(|wanted| language.as_deref() != Some(wanted))
+                || (options.same_language_only && language != own_language);
+            if excluded {
+                filtered_by_language += 1;
+                continue;
+            }
             let other_total = totals.get(&other_file).copied().unwrap_or(1);
             let denom = self_count.max(other_total) as f64;
             let similarity = shared_count as f64 / denom;
@@ -977,6 +3809,9 @@ impl GraphStore {
                 other_file,
                 shared_fingerprints: shared_count,
                 similarity,
+                language,
+                regions: Vec::new(),
+                fingerprint_params: None,
             });
         }
 
@@ -1004,11 +3839,41 @@ impl GraphStore {
         let total = surviving.len();
         let start = options.offset.min(total);
         let end = start.saturating_add(options.limit).min(total);
-        let rows = surviving[start..end].to_vec();
+        let mut rows = surviving[start..end].to_vec();
+        let file_params = self.file_fingerprint_params()?;
+        let own_params = file_params.get(file_path);
+        let mut mismatched = 0;
+        for row in &mut rows {
+            row.regions = self.clone_regions(file_path, &row.other_file)?;
+            let other_params = file_params.get(&row.other_file);
+            if other_params != own_params {
+                row.fingerprint_params = other_params.cloned();
+                mismatched += 1;
+            }
+        }
+        let params_warning = (mismatched > 0).then(|| {
+            format!(
+                "{mismatched} of {} matches were fingerprinted with different parameters than `{file_path}` ({}), so their similarity is not comparable; run `lumora index --full` to rebuild with the current settings",
+                rows.len(),
+                own_params.map_or("unknown", String::as_str)
+            )
+        });
         let pagination = build_pagination(total, options.offset, options.limit, rows.len());
 
         let empty_reason = if total > 0 {
             None
+        } else if candidate_files == 0 && filtered_by_language > 0 {
+            Some(format!(
+                "all {filtered_by_language} overlapping files were excluded by the language filter"
+            ))
+        } else if candidate_files == 0 && filtered_tests > 0 {
+            Some(format!(
+                "all {filtered_tests} overlapping files are tests; pass include_tests to see them"
+            ))
+        } else if candidate_files == 0 && filtered_generated > 0 {
+            Some(format!(
+                "all {filtered_generated} overlapping files are generated; pass include_generated to see them"
+            ))
         } else if candidate_files == 0 {
             Some("no overlapping fingerprints with other files".to_string())
         } else {
@@ -1022,14 +3887,59 @@ impl GraphStore {
             candidate_files,
             surviving_candidates: surviving_count,
             filtered_by_threshold,
+            filtered_by_language,
+            filtered_tests,
+            filtered_generated,
             max_candidate_similarity,
             suggested_min_similarity: max_candidate_similarity.map(|value| (value * 0.9).max(0.0)),
             empty_reason,
+            params_warning,
         };
 
         Ok((rows, pagination, analysis))
     }
 
+    /// How much of `other_file` shares fingerprints with `file_path`, scored
+    /// like a [`Self::clone_matches_page`] row. `None` when either file has
+    /// no fingerprints, e.g. it is too small or not indexed.
+    pub fn clone_match_between(
+        &self,
+        file_path: &str,
+        other_file: &str,
+    ) -> Result<Option<CloneMatch>> {
+        let count = |path: &str| -> Result<i64> {
+            Ok(self.conn.query_row(
+                "SELECT COUNT(DISTINCT fp_hash) FROM fingerprints WHERE file_path = ?1",
+                [path],
+                |row| row.get(0),
+            )?)
+        };
+        let (own_count, other_count) = (count(file_path)?, count(other_file)?);
+        if own_count == 0 || other_count == 0 {
+            return Ok(None);
+        }
+        let shared_fingerprints: i64 = self.conn.query_row(
+            "SELECT COUNT(DISTINCT f1.fp_hash)
+             FROM fingerprints f1
+             JOIN fingerprints f2 ON f1.fp_hash = f2.fp_hash
+             WHERE f1.file_path = ?1 AND f2.file_path = ?2",
+            params![file_path, other_file],
+            |row| row.get(0),
+        )?;
+        let file_params = self.file_fingerprint_params()?;
+        let other_params = file_params.get(other_file);
+        Ok(Some(CloneMatch {
+            other_file: other_file.to_string(),
+            shared_fingerprints,
+            similarity: shared_fingerprints as f64 / own_count.max(other_count) as f64,
+            language: self.tracked_file_language(other_file)?,
+            regions: self.clone_regions(file_path, other_file)?,
+            fingerprint_params: other_params
+                .filter(|params| file_params.get(file_path) != Some(*params))
+                .cloned(),
+        }))
+    }
+
     pub fn clone_hotspots(
         &self,
         file_path: &str,
@@ -1047,9 +3957,9 @@ impl GraphStore {
         let (rows, _, analysis) = self.clone_matches_page(
             file_path,
             &CloneQueryOptions {
-                min_similarity: options.min_similarity,
                 limit: usize::MAX,
                 offset: 0,
+                ..options.clone()
             },
         )?;
         let mut buckets: HashMap<String, (i64, f64, f64)> = HashMap::new();
@@ -1096,11 +4006,168 @@ impl GraphStore {
         Ok((rows, pagination, analysis))
     }
 
+    /// File pairs, one under `dir` and one under `other_dir`, that share
+    /// fingerprints, scored like [`Self::clone_matches_page`] rows, plus how
+    /// much the two trees overlap overall. `language` and
+    /// `same_language_only` narrow the pairs but not the tree totals.
+    pub fn clone_dirs_page(
+        &self,
+        dir: &str,
+        other_dir: &str,
+        options: &CloneQueryOptions,
+    ) -> Result<(Vec<DirClonePair>, PaginationInfo, DirCloneSummary)> {
+        let (dir, prefix) = dir_prefix(dir);
+        let (other_dir, other_prefix) = dir_prefix(other_dir);
+        let mut kept = String::new();
+        if !options.include_tests {
+            kept.push_str(&format!(" AND file_path NOT IN ({TEST_FILE_PATHS})"));
+        }
+        if !options.include_generated {
+            kept.push_str(&format!(" AND file_path NOT IN ({GENERATED_FILE_PATHS})"));
+        }
+        let under = |param: &str| {
+            format!("({param} = '' OR substr(file_path, 1, length({param})) = {param}){kept}")
+        };
+
+        let tree_totals = |tree_prefix: &str| -> Result<(i64, i64)> {
+            Ok(self.conn.query_row(
+                &format!(
+                    "SELECT COUNT(DISTINCT file_path), COUNT(DISTINCT fp_hash) FROM fingerprints WHERE {}",
+                    under("?1")
+                ),
+                [tree_prefix],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?)
+        };
+        let (files, fingerprints) = tree_totals(&prefix)?;
+        let (other_files, other_fingerprints) = tree_totals(&other_prefix)?;
+        let shared_fingerprints: i64 = self.conn.query_row(
+            &format!(
+                "SELECT COUNT(DISTINCT fp_hash) FROM fingerprints
+                 WHERE {} AND fp_hash IN (SELECT fp_hash FROM fingerprints WHERE {})",
+                under("?1"),
+                under("?2")
+            ),
+            [&prefix, &other_prefix],
+            |row| row.get(0),
+        )?;
+
+        let mut shared_stmt = self.conn.prepare(&format!(
+            "
+            SELECT f1.file_path, f2.file_path, COUNT(DISTINCT f1.fp_hash), l1.lang, l2.lang
+            FROM (SELECT file_path, fp_hash FROM fingerprints WHERE {}) f1
+            JOIN (SELECT file_path, fp_hash FROM fingerprints WHERE {}) f2
+              ON f1.fp_hash = f2.fp_hash
+            LEFT JOIN files l1 ON l1.path = f1.file_path
+            LEFT JOIN files l2 ON l2.path = f2.file_path
+            WHERE f1.file_path != f2.file_path
+            GROUP BY f1.file_path, f2.file_path
+            ",
+            under("?1"),
+            under("?2")
+        ))?;
+        let shared_rows = shared_stmt.query_map([&prefix, &other_prefix], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })?;
+        let shared_rows = self.collect_within_deadline(shared_rows)?;
+
+        let mut counts_stmt = self.conn.prepare(
+            "SELECT file_path, COUNT(DISTINCT fp_hash) FROM fingerprints GROUP BY file_path",
+        )?;
+        let totals = counts_stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .collect::<rusqlite::Result<HashMap<_, _>>>()?;
+
+        let candidate_pairs = shared_rows.len();
+        let mut pairs = Vec::new();
+        for (file, other_file, shared_count, language, other_language) in shared_rows {
+            let excluded = options.language.as_deref().is_some_and(|wanted| {
+                language.as_deref() != Some(wanted) || other_language.as_deref() != Some(wanted)
+            }) || (options.same_language_only && language != other_language);
+            if excluded {
+                continue;
+            }
+            let denom = totals
+                .get(&file)
+                .copied()
+                .unwrap_or(1)
+                .max(totals.get(&other_file).copied().unwrap_or(1));
+            let similarity = shared_count as f64 / denom as f64;
+            if similarity < options.min_similarity {
+                continue;
+            }
+            pairs.push(DirClonePair {
+                file,
+                other_file,
+                shared_fingerprints: shared_count,
+                similarity,
+                language,
+            });
+        }
+        pairs.sort_by(|left, right| {
+            right
+                .similarity
+                .partial_cmp(&left.similarity)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| right.shared_fingerprints.cmp(&left.shared_fingerprints))
+                .then_with(|| left.file.cmp(&right.file))
+                .then_with(|| left.other_file.cmp(&right.other_file))
+        });
+
+        let total = pairs.len();
+        let start = options.offset.min(total);
+        let end = start.saturating_add(options.limit).min(total);
+        let rows = pairs[start..end].to_vec();
+        let pagination = build_pagination(total, options.offset, options.limit, rows.len());
+
+        let empty_reason = if total > 0 {
+            None
+        } else if files == 0 {
+            Some(format!("no fingerprinted files under `{dir}`"))
+        } else if other_files == 0 {
+            Some(format!("no fingerprinted files under `{other_dir}`"))
+        } else if candidate_pairs == 0 {
+            Some("the two directories share no fingerprints".to_string())
+        } else {
+            Some(format!(
+                "all {candidate_pairs} overlapping file pairs were filtered by language or min_similarity={:.3}",
+                options.min_similarity
+            ))
+        };
+        let largest = fingerprints.max(other_fingerprints);
+        let summary = DirCloneSummary {
+            dir: if dir.is_empty() { ".".to_string() } else { dir },
+            other_dir: if other_dir.is_empty() {
+                ".".to_string()
+            } else {
+                other_dir
+            },
+            files,
+            other_files,
+            shared_fingerprints,
+            similarity: if largest == 0 {
+                0.0
+            } else {
+                shared_fingerprints as f64 / largest as f64
+            },
+            empty_reason,
+        };
+        Ok((rows, pagination, summary))
+    }
+
     pub fn top_reference_files(
         &self,
         rows: &[ReferenceLocation],
         limit: usize,
-    ) -> Vec<TopFileSummary> {
+    ) -> Result<Vec<TopFileSummary>> {
         let mut counts: HashMap<String, i64> = HashMap::new();
         for row in rows {
             *counts.entry(row.file_path.clone()).or_insert(0) += 1;
@@ -1108,7 +4175,11 @@ impl GraphStore {
 
         let mut out: Vec<TopFileSummary> = counts
             .into_iter()
-            .map(|(file_path, count)| TopFileSummary { file_path, count })
+            .map(|(file_path, count)| TopFileSummary {
+                file_path,
+                count,
+                owners: Vec::new(),
+            })
             .collect();
         out.sort_by(|left, right| {
             right
@@ -1119,7 +4190,168 @@ impl GraphStore {
         if limit > 0 && out.len() > limit {
             out.truncate(limit);
         }
-        out
+        for item in &mut out {
+            item.owners = self.file_owners(&item.file_path)?;
+        }
+        Ok(out)
+    }
+
+    /// Owners recorded on a file entity from `CODEOWNERS`.
+    pub fn file_owners(&self, file_path: &str) -> Result<Vec<String>> {
+        let raw: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT json_extract(meta_json, '$.owners') FROM entities
+                 WHERE entity_type = 'file' AND key = ?1",
+                [file_key(file_path)],
+                |row| row.get(0),
+            )
+            .optional()?
+            .flatten();
+        Ok(raw
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default())
+    }
+
+    /// Stores each file's `CODEOWNERS` owners on its file entity, touching only
+    /// files whose owners changed. Files missing from `owners` lose theirs.
+    pub fn sync_file_owners(&mut self, owners: &HashMap<String, Vec<String>>) -> Result<usize> {
+        let tx = begin_write(&mut self.conn)?;
+        let current = {
+            let mut stmt = tx.prepare(
+                "SELECT file_path, json_extract(meta_json, '$.owners') FROM entities
+                 WHERE entity_type = 'file' AND file_path IS NOT NULL",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+
+        let mut changed = 0;
+        for (file_path, stored) in current {
+            let wanted = owners
+                .get(&file_path)
+                .filter(|list| !list.is_empty())
+                .map(serde_json::to_string)
+                .transpose()?;
+            if stored == wanted {
+                continue;
+            }
+            match wanted {
+                Some(wanted) => tx.execute(
+                    "UPDATE entities
+                     SET meta_json = json_set(COALESCE(meta_json, '{}'), '$.owners', json(?2))
+                     WHERE entity_type = 'file' AND file_path = ?1",
+                    params![file_path, wanted],
+                )?,
+                None => tx.execute(
+                    "UPDATE entities SET meta_json = json_remove(meta_json, '$.owners')
+                     WHERE entity_type = 'file' AND file_path = ?1",
+                    [&file_path],
+                )?,
+            };
+            changed += 1;
+        }
+        tx.commit()?;
+        Ok(changed)
+    }
+
+    /// Replaces the stored `package` entities with `packages`: stale ones are
+    /// deleted with their edges, and each package's `contains` edges to its
+    /// files and nested packages are brought in line. Members that are not
+    /// indexed are skipped.
+    pub fn sync_packages(&mut self, packages: &[PackageInfo]) -> Result<()> {
+        let tx = begin_write(&mut self.conn)?;
+        let wanted: HashSet<String> = packages
+            .iter()
+            .map(|package| package_key(&package.dir))
+            .collect();
+        let stale = {
+            let mut stmt =
+                tx.prepare("SELECT id, key FROM entities WHERE entity_type = 'package'")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+                .into_iter()
+                .filter(|(_, key)| !wanted.contains(key))
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>()
+        };
+        for id in stale {
+            tx.execute("DELETE FROM entities WHERE id = ?1", [id])?;
+        }
+
+        for package in packages {
+            let meta = json!({
+                "anchor": package.anchor,
+                "name": package.name,
+                "title": package.title,
+                "summary": package.summary,
+            });
+            let package_id = ensure_entity_with_tx(
+                &tx,
+                "package",
+                &package_key(&package.dir),
+                &package.dir,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(meta.to_string()),
+            )?;
+
+            let members: HashSet<String> = package
+                .files
+                .iter()
+                .map(|path| file_key(path))
+                .chain(package.packages.iter().map(|dir| package_key(dir)))
+                .collect();
+            let current = {
+                let mut stmt = tx.prepare_cached(
+                    "SELECT e.id, dst.key FROM edges e
+                     JOIN entities dst ON dst.id = e.dst_entity_id
+                     WHERE e.src_entity_id = ?1 AND e.edge_type = 'contains'",
+                )?;
+                let rows = stmt.query_map([package_id], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                })?;
+                rows.collect::<rusqlite::Result<Vec<_>>>()?
+            };
+            let mut linked = HashSet::new();
+            for (edge_id, key) in current {
+                if members.contains(&key) && linked.insert(key) {
+                    continue;
+                }
+                tx.execute("DELETE FROM edges WHERE id = ?1", [edge_id])?;
+            }
+            for key in members.difference(&linked) {
+                let member_id: Option<i64> = tx
+                    .prepare_cached("SELECT id FROM entities WHERE key = ?1")?
+                    .query_row([key], |row| row.get(0))
+                    .optional()?;
+                if let Some(member_id) = member_id {
+                    insert_edge_with_tx(
+                        &tx,
+                        package_id,
+                        member_id,
+                        "contains",
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        EdgeOrigin::Heuristic,
+                    )?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
     }
 
     pub fn selector_suggestions_advanced(
@@ -1229,20 +4461,27 @@ impl GraphStore {
             where_clauses.join(" AND ")
         };
 
-        let sql = format!(
-            "
-            SELECT entity_type, key, name, file_path, line
-            FROM entities
-            WHERE {where_sql}
-            ORDER BY
+        // Without a query every entity is a candidate, so fetch the best
+        // connected ones rather than the first few files by key.
+        let type_order = "
                 CASE entity_type
                     WHEN 'file' THEN 0
                     WHEN 'symbol_name' THEN 1
                     WHEN 'symbol' THEN 2
                     WHEN 'module' THEN 3
                     ELSE 9
-                END,
-                key
+                END";
+        let order_sql = if query_lower.is_empty() {
+            format!("degree DESC, {type_order}, key")
+        } else {
+            format!("{type_order}, degree DESC, key")
+        };
+        let sql = format!(
+            "
+            SELECT entity_type, key, name, file_path, line, {ENTITY_DEGREE_SQL} AS degree
+            FROM entities
+            WHERE {where_sql}
+            ORDER BY {order_sql}
             LIMIT ?
             "
         );
@@ -1257,6 +4496,7 @@ impl GraphStore {
                 name: row.get(2)?,
                 file_path: row.get(3)?,
                 line: row.get(4)?,
+                degree: Some(row.get(5)?),
                 score: None,
                 why: None,
             })
@@ -1269,11 +4509,21 @@ impl GraphStore {
         Ok(out)
     }
 
-    pub fn index_warning(&self, stale_after_hours: u64) -> Result<Option<String>> {
+    pub fn is_empty(&self) -> Result<bool> {
         let file_count: i64 = self
             .conn
             .query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0))?;
-        if file_count == 0 {
+        Ok(file_count == 0)
+    }
+
+    pub fn index_warning(&self, stale_after_hours: u64) -> Result<Option<String>> {
+        if self.is_empty()? {
+            if self.lazy_coverage()?.is_some() {
+                return Ok(Some(
+                    "lazy index has nothing parsed yet; pass a path or file_glob to index that part of the repo"
+                        .to_string(),
+                ));
+            }
             return Ok(Some(
                 "index is empty; run lumora.index_repository before querying".to_string(),
             ));
@@ -1334,15 +4584,72 @@ impl GraphStore {
             true
         };
 
+        let index_errors: i64 =
+            self.conn
+                .query_row("SELECT COUNT(*) FROM index_errors", [], |row| row.get(0))?;
+
         Ok(FreshnessInfo {
             file_count,
             latest_indexed_at,
             schema_version,
             stale_after_hours,
             is_stale,
+            index_errors,
+            lazy_coverage: self.lazy_coverage()?,
         })
     }
 
+    /// Records why `path` could not be indexed, replacing any earlier failure.
+    pub fn record_index_error(&mut self, path: &str, stage: &str, message: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO index_errors(path, stage, message, failed_at)
+             VALUES(?1, ?2, ?3, datetime('now'))
+             ON CONFLICT(path) DO UPDATE SET
+                stage=excluded.stage,
+                message=excluded.message,
+                failed_at=excluded.failed_at",
+            params![path, stage, message],
+        )?;
+        Ok(())
+    }
+
+    pub fn clear_index_error(&mut self, path: &str) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM index_errors WHERE path = ?1", [path])?;
+        Ok(())
+    }
+
+    /// Drops failures for files that are no longer in the repository.
+    pub fn retain_index_errors(&mut self, current_paths: &HashSet<String>) -> Result<usize> {
+        let stale: Vec<String> = self
+            .index_errors()?
+            .into_iter()
+            .map(|error| error.path)
+            .filter(|path| !current_paths.contains(path))
+            .collect();
+        for path in &stale {
+            self.clear_index_error(path)?;
+        }
+        Ok(stale.len())
+    }
+
+    /// Files whose most recent indexing attempt failed, by path. A file that
+    /// was indexed before the failure keeps its older graph rows.
+    pub fn index_errors(&self) -> Result<Vec<IndexError>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path, stage, message, failed_at FROM index_errors ORDER BY path")?;
+        let rows = stmt.query_map([], |row| {
+            Ok(IndexError {
+                path: row.get(0)?,
+                stage: row.get(1)?,
+                message: row.get(2)?,
+                failed_at: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
     fn definition_files_for_symbol(&self, symbol_name: &str) -> Result<HashSet<String>> {
         let mut stmt = self.conn.prepare(
             "
@@ -1363,48 +4670,61 @@ impl GraphStore {
         Ok(out)
     }
 
+    /// `(symbol key, arity)` for each callable definition named `symbol_name`
+    /// whose parameter list was read at index time.
+    fn definition_arities(&self, symbol_name: &str) -> Result<Vec<(String, Arity)>> {
+        let mut stmt = self.conn.prepare_cached(
+            "
+            SELECT key, json_extract(meta_json, '$.min_arity'),
+                   json_extract(meta_json, '$.max_arity')
+            FROM entities
+            WHERE entity_type = 'symbol' AND name = ?1
+              AND json_extract(meta_json, '$.min_arity') IS NOT NULL
+            ",
+        )?;
+        let rows = stmt.query_map([symbol_name], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                Arity {
+                    min: row.get::<_, i64>(1)? as usize,
+                    max: row.get::<_, Option<i64>>(2)?.map(|max| max as usize),
+                },
+            ))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
     fn resolve_selector(&self, selector: &str) -> Result<SelectorLookup> {
         let parsed = parse_selector(selector)?;
         match parsed {
             ParsedSelector::Key(key) => {
-                let candidate = self.find_entity_by_key(&key)?;
-                let candidates = candidate.clone().into_iter().collect::<Vec<_>>();
+                let candidates = self.find_entity_by_key(&key)?.into_iter().collect();
                 Ok(SelectorLookup {
                     parsed_as: "key".to_string(),
-                    entity: candidate,
                     candidates,
                 })
             }
             ParsedSelector::File(path) => {
                 let normalized = normalize_selector_path(&path);
                 let key = file_key(&normalized);
-                let candidate = self.find_entity_by_key(&key)?;
-                let candidates = candidate.clone().into_iter().collect::<Vec<_>>();
+                let candidates = self.find_entity_by_key(&key)?.into_iter().collect();
                 Ok(SelectorLookup {
                     parsed_as: "file".to_string(),
-                    entity: candidate,
                     candidates,
                 })
             }
             ParsedSelector::SymbolName { lang, name } => {
                 let key = symbol_name_key(&lang, &name);
-                let candidate = self.find_entity_by_key(&key)?;
-                let candidates = candidate.clone().into_iter().collect::<Vec<_>>();
+                let candidates = self.find_entity_by_key(&key)?.into_iter().collect();
                 Ok(SelectorLookup {
                     parsed_as: "symbol_name".to_string(),
-                    entity: candidate,
                     candidates,
                 })
             }
-            ParsedSelector::Name(name) => {
-                let candidates = self.entities_by_name(&name)?;
-                let entity = candidates.first().cloned();
-                Ok(SelectorLookup {
-                    parsed_as: "name".to_string(),
-                    candidates,
-                    entity,
-                })
-            }
+            ParsedSelector::Name(name) => Ok(SelectorLookup {
+                parsed_as: "name".to_string(),
+                candidates: self.entities_by_name(&name)?,
+            }),
             ParsedSelector::Auto(raw) => {
                 let normalized = normalize_selector_path(&raw);
                 let mut candidates = Vec::new();
@@ -1423,21 +4743,97 @@ impl GraphStore {
                         .cmp(&entity_rank(&right.entity_type))
                         .then_with(|| left.key.cmp(&right.key))
                 });
-                let entity = candidates.first().cloned();
                 Ok(SelectorLookup {
                     parsed_as: "auto".to_string(),
                     candidates,
-                    entity,
                 })
             }
         }
     }
 
-    fn outgoing_neighbors(&self, entity_id: i64) -> Result<Vec<i64>> {
-        let mut stmt = self
-            .conn
-            .prepare("SELECT dst_entity_id FROM edges WHERE src_entity_id = ?1")?;
-        let rows = stmt.query_map([entity_id], |row| row.get::<_, i64>(0))?;
+    /// Known symbol names within a small edit distance of `name`, closest
+    /// first, for "did you mean" hints after an empty exact lookup. Case-only
+    /// differences count as the closest match; `name` itself is left out.
+    pub fn similar_symbol_names(&self, name: &str, limit: usize) -> Result<Vec<String>> {
+        let wanted = name.to_lowercase();
+        let len = wanted.chars().count();
+        let max_distance = (len / 4).clamp(1, MAX_DID_YOU_MEAN_DISTANCE);
+        let mut stmt = self.conn.prepare(
+            "
+            SELECT DISTINCT name FROM entities
+            WHERE entity_type IN ('symbol', 'symbol_name', 'external')
+              AND length(name) BETWEEN ?1 AND ?2
+              AND name != ?3
+            ",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                len.saturating_sub(max_distance) as i64,
+                (len + max_distance) as i64,
+                name
+            ],
+            |row| row.get::<_, String>(0),
+        )?;
+        let mut scored = Vec::new();
+        for candidate in self.collect_within_deadline(rows)? {
+            let distance = levenshtein(&wanted, &candidate.to_lowercase());
+            if distance <= max_distance {
+                scored.push((distance, candidate));
+            }
+        }
+        scored.sort();
+        Ok(scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, candidate)| candidate)
+            .collect())
+    }
+
+    /// Ids of the file entities inside a package, nested packages included.
+    fn package_member_files(&self, package_id: i64) -> Result<HashSet<i64>> {
+        let mut stmt = self.conn.prepare(
+            "
+            WITH RECURSIVE members(id) AS (
+                SELECT ?1
+                UNION
+                SELECT e.dst_entity_id FROM edges e
+                JOIN members m ON e.src_entity_id = m.id
+                WHERE e.edge_type = 'contains'
+            )
+            SELECT m.id FROM members m
+            JOIN entities en ON en.id = m.id AND en.entity_type = 'file'
+            ",
+        )?;
+        let rows = stmt.query_map([package_id], |row| row.get::<_, i64>(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Edges a dependency path can take out of `entity_id`, one per neighbor
+    /// and edge type, with where the first such edge was recorded.
+    fn outgoing_edges(&self, entity_id: i64) -> Result<Vec<(i64, EdgeStep)>> {
+        // A symbol name also leads to its definitions, so paths can continue
+        // through `implements`/`extends` edges into the supertype's symbol.
+        // That step follows a `names` edge backwards and is reported as one.
+        let mut stmt = self.conn.prepare(
+            "SELECT dst_entity_id, edge_type, file_path, MIN(line) FROM edges
+             WHERE src_entity_id = ?1
+             GROUP BY dst_entity_id, edge_type
+             UNION ALL
+             SELECT src_entity_id, edge_type, file_path, MIN(line) FROM edges
+             WHERE dst_entity_id = ?1 AND edge_type = 'names'
+             GROUP BY src_entity_id
+             ORDER BY 1, 2",
+        )?;
+        let rows = stmt.query_map([entity_id], |row| {
+            Ok((
+                row.get(0)?,
+                EdgeStep {
+                    edge_type: row.get(1)?,
+                    file_path: row.get(2)?,
+                    line: row.get(3)?,
+                },
+            ))
+        })?;
         rows.collect::<std::result::Result<Vec<_>, _>>()
             .map_err(Into::into)
     }
@@ -1518,7 +4914,8 @@ impl GraphStore {
             "
             SELECT e.edge_type,
                    dst.id, dst.entity_type, dst.key, dst.name, dst.lang, dst.file_path,
-                   dst.line, dst.col, dst.end_line, dst.end_col, dst.meta_json
+                   dst.line, dst.col, dst.end_line, dst.end_col, dst.meta_json,
+                   e.origin, e.confidence
             FROM edges e
             JOIN entities dst ON dst.id = e.dst_entity_id
             WHERE e.src_entity_id = ?1
@@ -1527,6 +4924,7 @@ impl GraphStore {
 
         let outgoing_rows = outgoing.query_map([entity_id], |row| {
             Ok(RelatedEdge {
+                row_id: String::new(),
                 edge_type: row.get(0)?,
                 direction: "outgoing".to_string(),
                 entity: Entity {
@@ -1540,12 +4938,18 @@ impl GraphStore {
                     col: row.get(8)?,
                     end_line: row.get(9)?,
                     end_col: row.get(10)?,
+                    signature: None,
+                    offsets: None,
                     meta_json: row.get(11)?,
-                },
+                }
+                .with_meta_fields(),
                 depth: None,
                 score: None,
                 why: None,
-            })
+                origin: row.get(12)?,
+                confidence: row.get(13)?,
+            }
+            .with_row_id())
         })?;
 
         for row in outgoing_rows {
@@ -1556,7 +4960,8 @@ impl GraphStore {
             "
             SELECT e.edge_type,
                    src.id, src.entity_type, src.key, src.name, src.lang, src.file_path,
-                   src.line, src.col, src.end_line, src.end_col, src.meta_json
+                   src.line, src.col, src.end_line, src.end_col, src.meta_json,
+                   e.origin, e.confidence
             FROM edges e
             JOIN entities src ON src.id = e.src_entity_id
             WHERE e.dst_entity_id = ?1
@@ -1565,6 +4970,7 @@ impl GraphStore {
 
         let incoming_rows = incoming.query_map([entity_id], |row| {
             Ok(RelatedEdge {
+                row_id: String::new(),
                 edge_type: row.get(0)?,
                 direction: "incoming".to_string(),
                 entity: Entity {
@@ -1578,12 +4984,18 @@ impl GraphStore {
                     col: row.get(8)?,
                     end_line: row.get(9)?,
                     end_col: row.get(10)?,
+                    signature: None,
+                    offsets: None,
                     meta_json: row.get(11)?,
-                },
+                }
+                .with_meta_fields(),
                 depth: None,
                 score: None,
                 why: None,
-            })
+                origin: row.get(12)?,
+                confidence: row.get(13)?,
+            }
+            .with_row_id())
         })?;
 
         for row in incoming_rows {
@@ -1593,17 +5005,430 @@ impl GraphStore {
         Ok(out)
     }
 
-    fn cleanup_orphan_nodes(&mut self) -> Result<()> {
+    /// Removes graph rows left behind for files that are no longer tracked,
+    /// orphaned name/module nodes, and stale parse cache entries. Everything
+    /// runs in one savepoint; a dry run rolls it back, so its counts are exactly
+    /// what a real run would delete. `vacuum` compacts the file afterwards
+    /// (ignored for dry runs).
+    pub fn prune(&mut self, dry_run: bool, vacuum: bool) -> Result<PruneReport> {
+        let mut report = PruneReport {
+            dry_run,
+            bytes_before: self.database_size()?,
+            ..Default::default()
+        };
+
+        let outer = self.conn.is_autocommit();
+        if outer {
+            self.conn.execute_batch("BEGIN IMMEDIATE")?;
+        }
+        self.conn.execute_batch("SAVEPOINT prune")?;
+        let result = self.prune_rows(&mut report);
+        let finish = if result.is_err() || dry_run {
+            "ROLLBACK TO prune; RELEASE prune"
+        } else {
+            "RELEASE prune"
+        };
+        self.conn.execute_batch(finish)?;
+        if outer {
+            self.conn.execute_batch("COMMIT")?;
+        }
+        result?;
+
+        if vacuum && !dry_run {
+            self.conn.execute_batch("VACUUM")?;
+            report.vacuumed = true;
+            report.bytes_after = Some(self.database_size()?);
+        }
+        Ok(report)
+    }
+
+    fn prune_rows(&mut self, report: &mut PruneReport) -> Result<()> {
+        report.fingerprints = self.conn.execute(
+            &format!("DELETE FROM fingerprints WHERE {UNTRACKED_FILE}"),
+            [],
+        )?;
+        report.embeddings = self.conn.execute(
+            &format!("DELETE FROM embeddings WHERE {UNTRACKED_FILE}"),
+            [],
+        )?;
+        report.edges = self
+            .conn
+            .execute(&format!("DELETE FROM edges WHERE {UNTRACKED_FILE}"), [])?;
+        // Edges into doomed entities are counted here rather than vanishing
+        // through the foreign key cascade.
+        report.edges += self.conn.execute(
+            &format!(
+                "DELETE FROM edges
+                 WHERE src_entity_id IN (SELECT id FROM entities WHERE entity_type != 'file' AND {UNTRACKED_FILE})
+                    OR dst_entity_id IN (SELECT id FROM entities WHERE entity_type != 'file' AND {UNTRACKED_FILE})"
+            ),
+            [],
+        )?;
+        report.entities = self.conn.execute(
+            &format!("DELETE FROM entities WHERE entity_type != 'file' AND {UNTRACKED_FILE}"),
+            [],
+        )?;
+        // A file node for an untracked path may still be the target of a
+        // resolved import; only drop it once nothing points at it.
+        report.entities += self.conn.execute(
+            &format!(
+                "DELETE FROM entities
+                 WHERE entity_type = 'file' AND {UNTRACKED_FILE}
+                   AND id NOT IN (SELECT src_entity_id FROM edges)
+                   AND id NOT IN (SELECT dst_entity_id FROM edges)"
+            ),
+            [],
+        )?;
+        report.orphan_nodes = self.cleanup_orphan_nodes()?;
+        report.parse_cache = self.prune_parse_cache()?;
+        Ok(())
+    }
+
+    /// Checks the graph for rows that a crash or a concurrent writer can leave
+    /// behind: edges pointing at missing entities, tracked files that are gone
+    /// from `repo_root`, entities and fingerprints of untracked files, and
+    /// duplicated entity keys or edges. With `fix` each of those is repaired;
+    /// integrity errors from SQLite itself are only reported.
+    pub fn verify(&mut self, repo_root: &Path, fix: bool) -> Result<VerifyReport> {
+        let count = |sql: &str| -> Result<usize> {
+            let count: i64 = self.conn.query_row(sql, [], |row| row.get(0))?;
+            Ok(count.max(0) as usize)
+        };
+        let mut report = VerifyReport {
+            dangling_edges: count(&format!("SELECT COUNT(*) FROM edges WHERE {DANGLING_EDGE}"))?,
+            stale_entities: count(&format!(
+                "SELECT COUNT(*) FROM entities WHERE entity_type != 'file' AND {UNTRACKED_FILE}"
+            ))?,
+            untracked_fingerprints: count(&format!(
+                "SELECT COUNT(*) FROM fingerprints WHERE {UNTRACKED_FILE}"
+            ))?,
+            untracked_embeddings: count(&format!(
+                "SELECT COUNT(*) FROM embeddings WHERE {UNTRACKED_FILE}"
+            ))?,
+            duplicate_keys: count(
+                "SELECT COALESCE(SUM(copies - 1), 0) FROM (
+                     SELECT COUNT(*) AS copies FROM entities NOT INDEXED
+                     GROUP BY key HAVING copies > 1
+                 )",
+            )?,
+            duplicate_edges: count(&format!(
+                "SELECT COALESCE(SUM(copies - 1), 0) FROM (
+                     SELECT COUNT(*) AS copies FROM edges
+                     GROUP BY {EDGE_IDENTITY} HAVING copies > 1
+                 )"
+            ))?,
+            ..Default::default()
+        };
+        let mut missing_files = self
+            .tracked_files()?
+            .into_iter()
+            .filter(|path| !repo_root.join(path).is_file())
+            .collect::<Vec<_>>();
+        missing_files.sort();
+        report.missing_files = missing_files;
+        report.integrity_errors = {
+            let mut stmt = self.conn.prepare("PRAGMA quick_check")?;
+            let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+                .into_iter()
+                .filter(|line| line != "ok")
+                .collect()
+        };
+
+        if fix {
+            self.repair(&report)?;
+            report.fixed = true;
+        }
+        Ok(report)
+    }
+
+    fn repair(&mut self, report: &VerifyReport) -> Result<()> {
+        if !report.missing_files.is_empty() {
+            self.remove_files(&report.missing_files, &mut UpsertOutcome::new())?;
+        }
+        let tx = begin_write(&mut self.conn)?;
+        if report.duplicate_keys > 0 {
+            // Repoint edges at the oldest row for each key before dropping
+            // the copies, then rebuild the unique index they slipped past.
+            tx.execute_batch(
+                "
+                CREATE TEMP TABLE IF NOT EXISTS duplicate_entities (id INTEGER PRIMARY KEY, keep_id INTEGER NOT NULL);
+                DELETE FROM duplicate_entities;
+                INSERT INTO duplicate_entities(id, keep_id)
+                SELECT e.id, k.keep_id
+                FROM entities e NOT INDEXED
+                JOIN (SELECT key, MIN(id) AS keep_id FROM entities NOT INDEXED GROUP BY key HAVING COUNT(*) > 1) k
+                  ON k.key = e.key
+                WHERE e.id != k.keep_id;
+                UPDATE edges SET src_entity_id = d.keep_id
+                FROM duplicate_entities d WHERE edges.src_entity_id = d.id;
+                UPDATE edges SET dst_entity_id = d.keep_id
+                FROM duplicate_entities d WHERE edges.dst_entity_id = d.id;
+                DELETE FROM entities WHERE id IN (SELECT id FROM duplicate_entities);
+                DELETE FROM duplicate_entities;
+                REINDEX entities;
+                ",
+            )?;
+        }
+        tx.execute(&format!("DELETE FROM edges WHERE {DANGLING_EDGE}"), [])?;
+        tx.execute(
+            &format!("DELETE FROM entities WHERE entity_type != 'file' AND {UNTRACKED_FILE}"),
+            [],
+        )?;
+        tx.execute(
+            &format!("DELETE FROM fingerprints WHERE {UNTRACKED_FILE}"),
+            [],
+        )?;
+        tx.execute(
+            &format!("DELETE FROM embeddings WHERE {UNTRACKED_FILE}"),
+            [],
+        )?;
+        tx.execute(
+            &format!(
+                "DELETE FROM edges WHERE id NOT IN (SELECT MIN(id) FROM edges GROUP BY {EDGE_IDENTITY})"
+            ),
+            [],
+        )?;
+        tx.commit()?;
+        self.cleanup_orphan_nodes()?;
+        Ok(())
+    }
+
+    /// Copies the write-ahead log back into the database and truncates it,
+    /// so a long-running writer doesn't grow the WAL without bound. Readers
+    /// still holding old snapshots can keep it from finishing; that shows up
+    /// as `busy` and the next checkpoint catches up.
+    pub fn checkpoint_wal(&self) -> Result<WalCheckpoint> {
+        Ok(self
+            .conn
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| {
+                Ok(WalCheckpoint {
+                    busy: row.get::<_, i64>(0)? != 0,
+                    log_frames: row.get(1)?,
+                    checkpointed_frames: row.get(2)?,
+                })
+            })?)
+    }
+
+    /// With `compact_via`, a fragmented database is rewritten there with
+    /// `VACUUM INTO` and copied back through the backup API, so attached
+    /// readers see the compacted file on their next query.
+    pub fn optimize(&mut self, compact_via: Option<&Path>) -> Result<OptimizeReport> {
+        let started = Instant::now();
+        self.conn.execute_batch("PRAGMA optimize; ANALYZE;")?;
+        let mut report = OptimizeReport {
+            analyze_ms: millis(started.elapsed()),
+            bytes_before: self.database_size()?,
+            free_bytes_before: self.conn.query_row(
+                "SELECT freelist_count * page_size FROM pragma_freelist_count(), pragma_page_size()",
+                [],
+                |row| row.get::<_, i64>(0).map(|bytes| bytes.max(0) as u64),
+            )?,
+            ..Default::default()
+        };
+
+        let worth_compacting = report.bytes_before > 0
+            && report.free_bytes_before as f64 / report.bytes_before as f64
+                >= COMPACT_MIN_FREE_RATIO;
+        if let (Some(staged), true) = (compact_via, worth_compacting) {
+            let started = Instant::now();
+            let _ = std::fs::remove_file(staged);
+            let compacted = self
+                .conn
+                .execute("VACUUM INTO ?1", [staged.to_string_lossy().as_ref()])
+                .map_err(anyhow::Error::from)
+                .and_then(|_| {
+                    self.conn
+                        .restore(
+                            DatabaseName::Main,
+                            staged,
+                            None::<fn(rusqlite::backup::Progress)>,
+                        )
+                        .map_err(anyhow::Error::from)
+                });
+            let _ = std::fs::remove_file(staged);
+            compacted.with_context(|| format!("failed to compact through {}", staged.display()))?;
+            self.checkpoint_wal()?;
+            report.compacted = true;
+            report.compact_ms = Some(millis(started.elapsed()));
+        }
+        report.bytes_after = self.database_size()?;
+        report.bytes_reclaimed = report.bytes_before.saturating_sub(report.bytes_after);
+        Ok(report)
+    }
+
+    fn database_size(&self) -> Result<u64> {
+        let size: i64 = self.conn.query_row(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(size.max(0) as u64)
+    }
+
+    /// Runs the post-write passes unless an [`IndexSession`] defers them.
+    fn after_write(&mut self) -> Result<()> {
+        if self.in_session {
+            return Ok(());
+        }
+        self.resolve_dirty_references()?;
+        self.classify_external_names()?;
+        self.cleanup_orphan_nodes()?;
+        self.conn.execute(
+            "INSERT INTO meta(key, value) VALUES('index_generation', '1')
+             ON CONFLICT(key) DO UPDATE SET value = CAST(value AS INTEGER) + 1",
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// Identifies a reference query for cursor reuse: the symbol and options
+    /// that shape the result set, this database, and its write generation,
+    /// so a cursor never pages over another query's rows or a stale index.
+    fn reference_cursor_query(
+        &self,
+        symbol_name: &str,
+        options: &ReferenceQueryOptions,
+    ) -> Result<ReferenceCursorQuery> {
+        let shaping = ReferenceQueryOptions {
+            limit: 0,
+            offset: 0,
+            cursor: None,
+            ..options.clone()
+        };
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (
+            symbol_name,
+            format!("{shaping:?}"),
+            self.conn.path().unwrap_or_default(),
+        )
+            .hash(&mut hasher);
+        let generation = self
+            .conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'index_generation'",
+                [],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(0);
+        Ok(ReferenceCursorQuery {
+            symbol_name: symbol_name.to_string(),
+            key: hasher.finish(),
+            generation,
+        })
+    }
+
+    /// With external entities enabled, retypes every name without a `names`
+    /// edge from a definition as `external`, and back once one appears.
+    /// Disabled, it turns any leftover externals back into `symbol_name`.
+    /// Keys keep the `symbol_name:` form either way, so selectors still work.
+    fn classify_external_names(&mut self) -> Result<()> {
+        if !self.external_entities_enabled()? {
+            self.conn.execute(
+                "UPDATE entities SET entity_type = 'symbol_name' WHERE entity_type = 'external'",
+                [],
+            )?;
+            return Ok(());
+        }
         self.conn.execute(
+            "
+            UPDATE entities
+            SET entity_type = classified.entity_type
+            FROM (
+                SELECT n.id,
+                       CASE WHEN EXISTS (
+                           SELECT 1 FROM edges d
+                           WHERE d.dst_entity_id = n.id AND d.edge_type = 'names'
+                       ) THEN 'symbol_name' ELSE 'external' END AS entity_type
+                FROM entities n
+                WHERE n.entity_type IN ('symbol_name', 'external')
+            ) AS classified
+            WHERE entities.id = classified.id
+              AND entities.entity_type != classified.entity_type
+            ",
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// Best-effort binding of `calls`/`references` edges in dirty files to the
+    /// symbol they most likely mean: a same-named definition in the same file
+    /// wins, then one in a file the referencing file imports (`depends_on`).
+    /// The winner's key is stored as `resolved_symbol_key` in the edge meta.
+    fn resolve_dirty_references(&mut self) -> Result<()> {
+        if self.dirty_files.is_empty() {
+            return Ok(());
+        }
+        let dirty = std::mem::take(&mut self.dirty_files);
+        let tx = begin_write(&mut self.conn)?;
+        tx.execute_batch(
+            "CREATE TEMP TABLE IF NOT EXISTS dirty_files (path TEXT PRIMARY KEY);
+             DELETE FROM dirty_files;",
+        )?;
+        {
+            let mut insert =
+                tx.prepare_cached("INSERT OR IGNORE INTO dirty_files(path) VALUES(?1)")?;
+            for path in &dirty {
+                insert.execute([path])?;
+            }
+        }
+        tx.execute(
+            "
+            WITH targets AS (
+                SELECT e.id AS edge_id,
+                       COALESCE(
+                           (SELECT s.key FROM entities s
+                            WHERE s.entity_type = 'symbol'
+                              AND s.file_path = e.file_path
+                              AND s.name = n.name
+                            ORDER BY s.line, s.col
+                            LIMIT 1),
+                           (SELECT s.key FROM edges d
+                            JOIN entities t ON t.id = d.dst_entity_id
+                            JOIN entities s ON s.file_path = t.file_path
+                            WHERE d.src_entity_id = e.src_entity_id
+                              AND d.edge_type = 'depends_on'
+                              AND s.entity_type = 'symbol'
+                              AND s.name = n.name
+                            ORDER BY s.file_path, s.line, s.col
+                            LIMIT 1)
+                       ) AS resolved
+                FROM edges e
+                JOIN entities n ON n.id = e.dst_entity_id
+                WHERE e.edge_type IN ('calls', 'references')
+                  AND e.file_path IN (SELECT path FROM dirty_files)
+            )
+            UPDATE edges
+            SET meta_json = CASE
+                WHEN targets.resolved IS NULL
+                    THEN json_remove(COALESCE(edges.meta_json, '{}'), '$.resolved_symbol_key')
+                ELSE json_set(COALESCE(edges.meta_json, '{}'), '$.resolved_symbol_key', targets.resolved)
+            END
+            FROM targets
+            WHERE edges.id = targets.edge_id
+            ",
+            [],
+        )?;
+        tx.execute("DELETE FROM dirty_files", [])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Deletes `symbol_name`, `external`, and `module` nodes no edge touches
+    /// any more.
+    /// Returns the number of nodes removed.
+    fn cleanup_orphan_nodes(&mut self) -> Result<usize> {
+        Ok(self.conn.execute(
             "
             DELETE FROM entities
-            WHERE entity_type IN ('symbol_name', 'module')
+            WHERE entity_type IN ('symbol_name', 'external', 'module')
               AND id NOT IN (SELECT src_entity_id FROM edges)
               AND id NOT IN (SELECT dst_entity_id FROM edges)
             ",
             [],
-        )?;
-        Ok(())
+        )?)
     }
 }
 
@@ -1619,15 +5444,19 @@ enum ParsedSelector {
 fn parse_selector(selector: &str) -> Result<ParsedSelector> {
     let value = selector.trim();
     if value.is_empty() {
-        anyhow::bail!(
-            "selector is empty. Examples: file:src/main.rs, symbol_name:rust:run_mcp_stdio, main"
-        );
+        return Err(coded(
+            ErrorCode::InvalidParams,
+            "selector is empty. Examples: file:src/main.rs, symbol_name:rust:run_mcp_stdio, main",
+        ));
     }
 
     if let Some(rest) = value.strip_prefix("file:") {
         let file = rest.trim();
         if file.is_empty() {
-            anyhow::bail!("invalid `file:` selector: missing path. Example: file:src/main.rs");
+            return Err(coded(
+                ErrorCode::InvalidParams,
+                "invalid `file:` selector: missing path. Example: file:src/main.rs",
+            ));
         }
         return Ok(ParsedSelector::File(file.to_string()));
     }
@@ -1637,9 +5466,7 @@ fn parse_selector(selector: &str) -> Result<ParsedSelector> {
         let lang = parts.next().unwrap_or_default().trim();
         let name = parts.next().unwrap_or_default().trim();
         if lang.is_empty() || name.is_empty() {
-            anyhow::bail!(
-                "invalid `symbol_name:` selector. Expected symbol_name:<lang>:<name>, e.g. symbol_name:rust:run_mcp_stdio"
-            );
+            return Err(coded(ErrorCode::InvalidParams, "invalid `symbol_name:` selector. Expected symbol_name:<lang>:<name>, e.g. symbol_name:rust:run_mcp_stdio"));
         }
         return Ok(ParsedSelector::SymbolName {
             lang: lang.to_string(),
@@ -1650,7 +5477,10 @@ fn parse_selector(selector: &str) -> Result<ParsedSelector> {
     if let Some(rest) = value.strip_prefix("symbol:") {
         let symbol = rest.trim();
         if symbol.is_empty() {
-            anyhow::bail!("invalid `symbol:` selector: missing name. Example: symbol:main");
+            return Err(coded(
+                ErrorCode::InvalidParams,
+                "invalid `symbol:` selector: missing name. Example: symbol:main",
+            ));
         }
         return Ok(ParsedSelector::Name(symbol.to_string()));
     }
@@ -1658,7 +5488,10 @@ fn parse_selector(selector: &str) -> Result<ParsedSelector> {
     if value.starts_with("key:") {
         let raw = value.trim_start_matches("key:").trim();
         if raw.is_empty() {
-            anyhow::bail!("invalid `key:` selector: missing key value");
+            return Err(coded(
+                ErrorCode::InvalidParams,
+                "invalid `key:` selector: missing key value",
+            ));
         }
         return Ok(ParsedSelector::Key(raw.to_string()));
     }
@@ -1668,9 +5501,9 @@ fn parse_selector(selector: &str) -> Result<ParsedSelector> {
         || value.starts_with("symbol:")
         || value.starts_with("key:")
     {
-        anyhow::bail!(
+        return Err(coded(ErrorCode::InvalidParams, format!(
             "unsupported selector form `{value}`. Examples: file:src/main.rs, symbol_name:rust:main, symbol:main"
-        );
+        )));
     }
 
     if value.starts_with("module:") || value.starts_with("symbol_name:") {
@@ -1689,12 +5522,74 @@ fn dedup_entities_by_id(items: &mut Vec<Entity>) {
     items.retain(|item| seen.insert(item.id));
 }
 
+/// Narrows selector candidates to the ones worth choosing between:
+/// definitions when any matched, since a bare name that hits one definition
+/// and its `symbol_name` node means that definition.
+fn selector_choices(candidates: Vec<Entity>) -> Vec<Entity> {
+    if candidates
+        .iter()
+        .any(|entity| entity.entity_type == "symbol")
+    {
+        candidates
+            .into_iter()
+            .filter(|entity| entity.entity_type == "symbol")
+            .collect()
+    } else {
+        candidates
+    }
+}
+
+fn choose_candidate(
+    selector: &str,
+    parsed_as: String,
+    matched: usize,
+    choices: Vec<Entity>,
+    pick: Option<usize>,
+) -> Result<(Option<Entity>, SelectorResolution)> {
+    let entity = match pick {
+        Some(index) => Some(choices.get(index).cloned().ok_or_else(|| {
+            let code = if choices.len() > 1 {
+                ErrorCode::SelectorAmbiguous
+            } else {
+                ErrorCode::InvalidParams
+            };
+            coded(
+                code,
+                format!(
+                    "pick {index} is out of range: selector `{selector}` has {} candidate(s)",
+                    choices.len()
+                ),
+            )
+        })?),
+        None => choices.first().cloned(),
+    };
+    let ambiguous = choices.len() > 1;
+    let candidates = if ambiguous {
+        choices
+            .iter()
+            .take(SELECTOR_CANDIDATE_LIMIT)
+            .map(SelectorCandidate::from_entity)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let resolution = SelectorResolution {
+        parsed_as,
+        matched,
+        selected_key: entity.as_ref().map(|item| item.key.clone()),
+        ambiguous,
+        candidates,
+    };
+    Ok((entity, resolution))
+}
+
 fn entity_rank(entity_type: &str) -> i64 {
     match entity_type {
         "symbol" => 0,
         "symbol_name" => 1,
         "file" => 2,
         "module" => 3,
+        "package" => 4,
         _ => 9,
     }
 }
@@ -1725,7 +5620,13 @@ fn reference_sorter(
     }
 }
 
-fn score_related_edge(edge: &RelatedEdge, depth: usize, prefer_project_symbols: bool) -> f64 {
+fn score_related_edge(
+    edge: &RelatedEdge,
+    depth: usize,
+    prefer_project_symbols: bool,
+    low_signal: &LowSignalNames,
+    language: Option<&str>,
+) -> f64 {
     let edge_weight = match edge.edge_type.as_str() {
         "calls" => 2.5,
         "depends_on" => 2.2,
@@ -1734,6 +5635,8 @@ fn score_related_edge(edge: &RelatedEdge, depth: usize, prefer_project_symbols:
         "references" => 1.2,
         "names" => 0.8,
         "contains" => 0.6,
+        "tests" => 0.7,
+        "implements" | "extends" => 1.6,
         _ => 1.0,
     };
     let direction_boost = if edge.direction == "outgoing" {
@@ -1744,8 +5647,10 @@ fn score_related_edge(edge: &RelatedEdge, depth: usize, prefer_project_symbols:
     let depth_penalty = (depth as f64 - 1.0) * 0.25;
     let mut score = edge_weight + direction_boost - depth_penalty;
 
-    if edge.entity.entity_type == "symbol_name" {
-        if is_low_signal_symbol_name(&edge.entity.name) {
+    if edge.entity.entity_type == "external" {
+        score -= 0.35;
+    } else if edge.entity.entity_type == "symbol_name" {
+        if low_signal.contains(language, &edge.entity.name) {
             score -= 1.3;
         } else if prefer_project_symbols && is_project_local_symbol_name(&edge.entity.name) {
             score += 0.35;
@@ -1765,6 +5670,125 @@ fn related_edge_sorter(left: &RelatedEdge, right: &RelatedEdge) -> Ordering {
         .then_with(|| left.entity.key.cmp(&right.entity.key))
 }
 
+const REFERENCE_CURSOR_TTL: Duration = Duration::from_secs(120);
+const REFERENCE_CURSOR_CAPACITY: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ReferenceCursorQuery {
+    symbol_name: String,
+    key: u64,
+    generation: i64,
+}
+
+struct ReferenceCursorEntry {
+    query: ReferenceCursorQuery,
+    rows: Arc<Vec<ReferenceLocation>>,
+    created_at: Instant,
+}
+
+#[derive(Default)]
+struct ReferenceCursorCache {
+    next_id: u64,
+    entries: HashMap<u64, ReferenceCursorEntry>,
+}
+
+/// Ranked reference results kept in-process so follow-up pages skip re-running
+/// the full query. Entries expire after a short TTL to bound staleness.
+fn reference_cursor_cache() -> &'static Mutex<ReferenceCursorCache> {
+    static CACHE: OnceLock<Mutex<ReferenceCursorCache>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(ReferenceCursorCache::default()))
+}
+
+fn reference_cursor_store(query: ReferenceCursorQuery, rows: Arc<Vec<ReferenceLocation>>) -> u64 {
+    let mut cache = reference_cursor_cache()
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    cache
+        .entries
+        .retain(|_, entry| entry.created_at.elapsed() < REFERENCE_CURSOR_TTL);
+    while cache.entries.len() >= REFERENCE_CURSOR_CAPACITY {
+        let Some(oldest) = cache
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.created_at)
+            .map(|(id, _)| *id)
+        else {
+            break;
+        };
+        cache.entries.remove(&oldest);
+    }
+
+    cache.next_id += 1;
+    let id = cache.next_id;
+    cache.entries.insert(
+        id,
+        ReferenceCursorEntry {
+            query,
+            rows,
+            created_at: Instant::now(),
+        },
+    );
+    id
+}
+
+fn reference_cursor_lookup(
+    cursor: &str,
+    query: &ReferenceCursorQuery,
+) -> Result<(u64, usize, Arc<Vec<ReferenceLocation>>)> {
+    let (id, offset) = cursor
+        .split_once(':')
+        .and_then(|(id, offset)| Some((id.parse::<u64>().ok()?, offset.parse::<usize>().ok()?)))
+        .ok_or_else(|| {
+            coded(
+                ErrorCode::InvalidParams,
+                format!("invalid cursor `{cursor}`"),
+            )
+        })?;
+
+    let cache = reference_cursor_cache()
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let entry = cache
+        .entries
+        .get(&id)
+        .filter(|entry| entry.created_at.elapsed() < REFERENCE_CURSOR_TTL)
+        .ok_or_else(|| {
+            coded(
+                ErrorCode::InvalidParams,
+                format!("cursor `{cursor}` has expired; re-run the query with offset instead"),
+            )
+        })?;
+    if entry.query.symbol_name != query.symbol_name {
+        return Err(coded(
+            ErrorCode::InvalidParams,
+            format!(
+                "cursor `{cursor}` belongs to a query for `{}`",
+                entry.query.symbol_name
+            ),
+        ));
+    }
+    if entry.query.key != query.key {
+        return Err(coded(
+            ErrorCode::InvalidParams,
+            format!("cursor `{cursor}` belongs to a query with different filters or another index; pass the same arguments as the first page"),
+        ));
+    }
+    if entry.query.generation != query.generation {
+        return Err(coded(
+            ErrorCode::InvalidParams,
+            format!("the index changed since cursor `{cursor}` was issued; re-run the query with offset instead"),
+        ));
+    }
+    Ok((id, offset, Arc::clone(&entry.rows)))
+}
+
+struct DefinitionSpan {
+    qualname: String,
+    kind: Option<String>,
+    line: i64,
+    end_line: i64,
+}
+
 fn build_pagination(total: usize, offset: usize, limit: usize, returned: usize) -> PaginationInfo {
     let safe_limit = limit.max(1);
     let safe_offset = offset.min(total);
@@ -1782,6 +5806,7 @@ fn build_pagination(total: usize, offset: usize, limit: usize, returned: usize)
         returned,
         has_more,
         next_offset,
+        next_cursor: None,
     }
 }
 
@@ -1810,10 +5835,15 @@ fn discovery_score(
 
     let mut score = 0.0;
     let mut reasons = Vec::new();
+    let degree = suggestion.degree.unwrap_or(0).max(0);
+    let centrality = (1.0 + degree as f64).ln();
 
     if query_lower.is_empty() {
-        score = 10.0 - entity_rank(&suggestion.entity_type) as f64;
+        // Centrality outweighs entity type here, so a core symbol ranks
+        // above a file nothing imports.
+        score = 10.0 - entity_rank(&suggestion.entity_type) as f64 + centrality * 4.0;
         reasons.push("no_query_default_ranking".to_string());
+        reasons.push(format!("degree={degree}"));
         return (score, reasons.join(","));
     }
 
@@ -1883,9 +5913,30 @@ fn discovery_score(
     if reasons.is_empty() {
         reasons.push("fallback_rank".to_string());
     }
+    // Small next to a name match, but enough to order vague matches.
+    if degree > 0 {
+        score += centrality * 2.0;
+        reasons.push(format!("degree={degree}"));
+    }
     (score, reasons.join(","))
 }
 
+/// Character-level edit distance (insertions, deletions, substitutions).
+fn levenshtein(left: &str, right: &str) -> usize {
+    let right: Vec<char> = right.chars().collect();
+    let mut previous: Vec<usize> = (0..=right.len()).collect();
+    let mut current = vec![0; right.len() + 1];
+    for (i, left_ch) in left.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, right_ch) in right.iter().enumerate() {
+            let substitution = previous[j] + usize::from(left_ch != *right_ch);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[right.len()]
+}
+
 fn fuzzy_subsequence_ratio(query: &str, text: &str) -> f64 {
     if query.is_empty() || text.is_empty() {
         return 0.0;
@@ -1915,56 +5966,256 @@ fn fuzzy_subsequence_ratio(query: &str, text: &str) -> f64 {
             qidx += 1;
         }
     }
-    matched as f64 / qchars.len() as f64
+    matched as f64 / qchars.len() as f64
+}
+
+fn is_project_local_symbol_name(name: &str) -> bool {
+    if name.len() <= 2 {
+        return false;
+    }
+
+    let lower = name.to_ascii_lowercase();
+    !matches!(
+        lower.as_str(),
+        "string"
+            | "str"
+            | "vec"
+            | "box"
+            | "result"
+            | "option"
+            | "path"
+            | "pathbuf"
+            | "hashmap"
+            | "hashset"
+            | "usize"
+            | "u64"
+            | "i64"
+            | "bool"
+    )
+}
+
+/// Backs SQLite's `X REGEXP Y` operator, which calls `regexp(Y, X)`. The
+/// compiled pattern is cached per statement; NULL text never matches.
+fn register_regexp_function(conn: &Connection) -> Result<()> {
+    conn.create_scalar_function(
+        "regexp",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let regex = ctx.get_or_create_aux(0, |pattern| -> Result<Regex> {
+                Ok(Regex::new(pattern.as_str()?)?)
+            })?;
+            match ctx.get_raw(1) {
+                ValueRef::Text(text) => Ok(std::str::from_utf8(text)
+                    .map(|text| regex.is_match(text))
+                    .unwrap_or(false)),
+                _ => Ok(false),
+            }
+        },
+    )?;
+    Ok(())
+}
+
+fn is_interrupt(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<rusqlite::Error>()
+        .and_then(rusqlite::Error::sqlite_error_code)
+        == Some(rusqlite::ErrorCode::OperationInterrupted)
+}
+
+/// `directory` normalized to a repo-relative path (`""` for the root) and
+/// the prefix its files' paths start with.
+fn dir_prefix(directory: &str) -> (String, String) {
+    let directory = directory
+        .replace('\\', "/")
+        .trim_start_matches("./")
+        .trim_end_matches('/')
+        .to_string();
+    if directory.is_empty() || directory == "." {
+        return (String::new(), String::new());
+    }
+    let prefix = format!("{directory}/");
+    (directory, prefix)
+}
+
+/// Most regions reported per clone match; the rest are usually scattered
+/// one-line coincidences rather than a copied block.
+const MAX_CLONE_REGIONS: usize = 20;
+
+/// Folds fingerprint spans (sorted by their start line in the queried file)
+/// into regions, extending a region whenever a span touches it on both sides.
+/// Larger regions win when there are more than [`MAX_CLONE_REGIONS`].
+fn merge_clone_regions(spans: Vec<CloneRegion>) -> Vec<CloneRegion> {
+    let mut regions: Vec<CloneRegion> = Vec::new();
+    for span in spans {
+        let touching = regions.iter_mut().rev().find(|region| {
+            span.start_line <= region.end_line + 1
+                && span.other_start_line <= region.other_end_line + 1
+                && span.other_end_line + 1 >= region.other_start_line
+        });
+        match touching {
+            Some(region) => {
+                region.end_line = region.end_line.max(span.end_line);
+                region.other_start_line = region.other_start_line.min(span.other_start_line);
+                region.other_end_line = region.other_end_line.max(span.other_end_line);
+            }
+            None => regions.push(span),
+        }
+    }
+
+    if regions.len() > MAX_CLONE_REGIONS {
+        regions.sort_by_key(|region| std::cmp::Reverse(region.end_line - region.start_line));
+        regions.truncate(MAX_CLONE_REGIONS);
+        regions.sort_by_key(|region| (region.start_line, region.other_start_line));
+    }
+    regions
+}
+
+/// Returns whether the column had to be added.
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, decl: &str) -> Result<bool> {
+    let exists: bool = conn.query_row(
+        &format!("SELECT EXISTS(SELECT 1 FROM pragma_table_info('{table}') WHERE name = ?1)"),
+        [column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"))?;
+    }
+    Ok(!exists)
+}
+
+/// A write transaction from [`begin_write`].
+enum WriteTx<'a> {
+    Outer(Transaction<'a>),
+    Nested(Savepoint<'a>),
+}
+
+impl std::ops::Deref for WriteTx<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            WriteTx::Outer(tx) => tx,
+            WriteTx::Nested(savepoint) => savepoint,
+        }
+    }
 }
 
-fn is_low_signal_symbol_name(name: &str) -> bool {
-    matches!(
-        name,
-        "Ok" | "Err"
-            | "Some"
-            | "None"
-            | "Result"
-            | "Option"
-            | "String"
-            | "Vec"
-            | "Box"
-            | "Self"
-            | "self"
-    )
+impl WriteTx<'_> {
+    fn commit(self) -> Result<()> {
+        match self {
+            WriteTx::Outer(tx) => tx.commit()?,
+            WriteTx::Nested(savepoint) => savepoint.commit()?,
+        }
+        Ok(())
+    }
 }
 
-fn is_project_local_symbol_name(name: &str) -> bool {
-    if is_low_signal_symbol_name(name) {
-        return false;
+/// Starts a write. Outside a transaction this is `BEGIN IMMEDIATE`, which
+/// takes the write lock up front: a deferred transaction that reads first
+/// cannot wait out a concurrent writer when it upgrades, and fails with
+/// `database is locked` right away. Inside one (an [`IndexSession`]) it is a
+/// savepoint. Dropping it uncommitted rolls back.
+fn begin_write(conn: &mut Connection) -> Result<WriteTx<'_>> {
+    if conn.is_autocommit() {
+        Ok(WriteTx::Outer(conn.transaction_with_behavior(
+            TransactionBehavior::Immediate,
+        )?))
+    } else {
+        Ok(WriteTx::Nested(conn.savepoint()?))
     }
+}
 
-    if name.len() <= 2 {
-        return false;
+/// Files whose `depends_on` edges point at `file_path`.
+fn dependent_files_with_tx(tx: &Connection, file_path: &str) -> Result<Vec<String>> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT DISTINCT e.file_path FROM edges e
+         JOIN entities f ON f.id = e.dst_entity_id
+         WHERE e.edge_type = 'depends_on' AND f.key = ?1 AND e.file_path IS NOT NULL",
+    )?;
+    let rows = stmt.query_map([file_key(file_path)], |row| row.get(0))?;
+    Ok(rows.collect::<std::result::Result<Vec<_>, _>>()?)
+}
+
+fn insert_resolved_imports_with_tx(
+    tx: &Connection,
+    file_path: &str,
+    language: &str,
+    file_entity_id: i64,
+    resolved_imports: &[(String, String)],
+) -> Result<()> {
+    for (module_name, resolved_file) in resolved_imports {
+        let module_entity_id = ensure_entity_with_tx(
+            tx,
+            "module",
+            &module_key(language, module_name),
+            module_name,
+            Some(language),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )?;
+        let resolved_file_id = ensure_entity_with_tx(
+            tx,
+            "file",
+            &file_key(resolved_file),
+            resolved_file,
+            Some(language),
+            Some(resolved_file),
+            None,
+            None,
+            None,
+            None,
+            Some(json!({"kind": "source"}).to_string()),
+        )?;
+
+        insert_edge_with_tx(
+            tx,
+            module_entity_id,
+            resolved_file_id,
+            "resolves_to",
+            Some(file_path),
+            None,
+            None,
+            None,
+            None,
+            None,
+            EdgeOrigin::ImportResolution,
+        )?;
+        insert_edge_with_tx(
+            tx,
+            file_entity_id,
+            resolved_file_id,
+            "depends_on",
+            Some(file_path),
+            None,
+            None,
+            None,
+            None,
+            Some(json!({"via": module_name}).to_string()),
+            EdgeOrigin::ImportResolution,
+        )?;
     }
+    Ok(())
+}
 
-    let lower = name.to_ascii_lowercase();
-    !matches!(
-        lower.as_str(),
-        "string"
-            | "str"
-            | "vec"
-            | "box"
-            | "result"
-            | "option"
-            | "path"
-            | "pathbuf"
-            | "hashmap"
-            | "hashset"
-            | "usize"
-            | "u64"
-            | "i64"
-            | "bool"
-    )
+/// Maps `src/storage/mod.rs` to `src` at level 1 and `src/storage` at level 2.
+/// Files with fewer directories fall back to their own directory, or `.` at
+/// the repository root.
+fn module_bucket(path: &str, level: usize) -> String {
+    let dirs: Vec<&str> = path.split('/').collect();
+    let dirs = &dirs[..dirs.len().saturating_sub(1)];
+    if dirs.is_empty() {
+        return ".".to_string();
+    }
+    dirs[..level.min(dirs.len())].join("/")
 }
 
+#[allow(clippy::too_many_arguments)]
 fn ensure_entity_with_tx(
-    tx: &rusqlite::Transaction<'_>,
+    tx: &Connection,
     entity_type: &str,
     key: &str,
     name: &str,
@@ -1976,7 +6227,7 @@ fn ensure_entity_with_tx(
     end_col: Option<i64>,
     meta_json: Option<String>,
 ) -> Result<i64> {
-    tx.execute(
+    tx.prepare_cached(
         "
         INSERT INTO entities(entity_type, key, name, lang, file_path, line, col, end_line, end_col, meta_json)
         VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
@@ -1991,7 +6242,8 @@ fn ensure_entity_with_tx(
             end_col=COALESCE(excluded.end_col, entities.end_col),
             meta_json=COALESCE(excluded.meta_json, entities.meta_json)
         ",
-        params![
+    )?
+    .execute(params![
             entity_type,
             key,
             name,
@@ -2002,40 +6254,47 @@ fn ensure_entity_with_tx(
             end_line,
             end_col,
             meta_json
-        ],
-    )?;
+        ])?;
 
-    tx.query_row("SELECT id FROM entities WHERE key = ?1", [key], |row| {
-        row.get(0)
-    })
-    .map_err(Into::into)
+    tx.prepare_cached("SELECT id FROM entities WHERE key = ?1")?
+        .query_row([key], |row| row.get(0))
+        .map_err(Into::into)
 }
 
+#[allow(clippy::too_many_arguments)]
 fn insert_edge_with_tx(
-    tx: &rusqlite::Transaction<'_>,
+    tx: &Connection,
     src_entity_id: i64,
     dst_entity_id: i64,
     edge_type: &str,
     file_path: Option<&str>,
     line: Option<i64>,
     col: Option<i64>,
+    end_line: Option<i64>,
+    end_col: Option<i64>,
     meta_json: Option<String>,
+    origin: EdgeOrigin,
 ) -> Result<()> {
-    tx.execute(
+    tx.prepare_cached(
         "
-        INSERT INTO edges(src_entity_id, dst_entity_id, edge_type, file_path, line, col, meta_json)
-        VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)
+        INSERT INTO edges(src_entity_id, dst_entity_id, edge_type, file_path, line, col, end_line, end_col, meta_json,
+                          origin, confidence)
+        VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
         ",
-        params![
-            src_entity_id,
-            dst_entity_id,
-            edge_type,
-            file_path,
-            line,
-            col,
-            meta_json
-        ],
-    )?;
+    )?
+    .execute(params![
+        src_entity_id,
+        dst_entity_id,
+        edge_type,
+        file_path,
+        line,
+        col,
+        end_line,
+        end_col,
+        meta_json,
+        origin.as_str(),
+        origin.confidence()
+    ])?;
     Ok(())
 }
 
@@ -2052,13 +6311,70 @@ fn map_entity(row: &rusqlite::Row<'_>) -> rusqlite::Result<Entity> {
         end_line: row.get(8)?,
         end_col: row.get(9)?,
         meta_json: row.get(10)?,
+        signature: None,
+        offsets: None,
+    }
+    .with_meta_fields())
+}
+
+/// Expects `name, file_path, line, col, end_line, end_col, kind, qualname,
+/// signature, meta_json`.
+fn symbol_location_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<SymbolLocation> {
+    let name: String = row.get(0)?;
+    let meta: Option<String> = row.get(9)?;
+    let (visibility, modifiers) = meta_modifiers(meta.as_deref());
+    Ok(SymbolLocation {
+        file_path: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+        line: row.get::<_, Option<i64>>(2)?.unwrap_or_default(),
+        col: row.get::<_, Option<i64>>(3)?.unwrap_or_default(),
+        end_line: row.get(4)?,
+        end_col: row.get(5)?,
+        kind: row
+            .get::<_, Option<String>>(6)?
+            .unwrap_or_else(|| "unknown".to_string()),
+        qualname: row
+            .get::<_, Option<String>>(7)?
+            .unwrap_or_else(|| name.clone()),
+        signature: row.get(8)?,
+        visibility,
+        modifiers,
+        offsets: meta_offsets(meta),
+        symbol_name: name,
     })
 }
 
+/// Visibility and modifiers stored in a symbol's meta by `index_file`.
+fn meta_modifiers(meta_json: Option<&str>) -> (Option<String>, Vec<String>) {
+    let Some(meta) = meta_json.and_then(|raw| serde_json::from_str::<serde_json::Value>(raw).ok())
+    else {
+        return (None, Vec::new());
+    };
+    let visibility = meta["visibility"].as_str().map(str::to_string);
+    let modifiers = meta["modifiers"]
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+    (visibility, modifiers)
+}
+
+fn meta_offsets(meta_json: Option<String>) -> Option<SpanOffsets> {
+    let meta = serde_json::from_str::<serde_json::Value>(&meta_json?).ok()?;
+    SpanOffsets::from_meta(&meta)
+}
+
 pub fn file_key(path: &str) -> String {
     format!("file:{path}")
 }
 
+pub fn package_key(dir: &str) -> String {
+    format!("package:{dir}")
+}
+
 pub fn symbol_name_key(lang: &str, symbol_name: &str) -> String {
     format!("symbol_name:{lang}:{symbol_name}")
 }
@@ -2087,6 +6403,46 @@ fn classify_special_file(path: &str) -> Option<&'static str> {
     None
 }
 
+fn classify_test_file(path: &str) -> bool {
+    let normalized = path.replace('\\', "/");
+    let mut components: Vec<&str> = normalized.split('/').collect();
+    let file_name = components.pop().unwrap_or_default();
+
+    if components.iter().any(|dir| {
+        matches!(
+            dir.to_ascii_lowercase().as_str(),
+            "test" | "tests" | "__tests__" | "spec" | "specs"
+        )
+    }) {
+        return true;
+    }
+
+    let stem = file_name.split('.').next().unwrap_or_default();
+    let lower_stem = stem.to_ascii_lowercase();
+    lower_stem.starts_with("test_")
+        || lower_stem.ends_with("_test")
+        || lower_stem.ends_with("_tests")
+        || lower_stem.ends_with("_spec")
+        || file_name.contains(".test.")
+        || file_name.contains(".spec.")
+        || (stem.len() > 4 && stem.ends_with("Test"))
+        || (stem.len() > 5 && stem.ends_with("Tests"))
+}
+
+fn is_test_symbol(in_test_file: bool, definition: &Definition) -> bool {
+    let callable = definition.kind.contains("function") || definition.kind.contains("method");
+    if in_test_file {
+        return callable;
+    }
+
+    // Language conventions for tests living next to production code, e.g. Rust
+    // `mod tests` blocks and pytest-style `test_*` functions.
+    callable
+        && (definition.name.starts_with("test_")
+            || definition.qualname.starts_with("tests::")
+            || definition.qualname.contains("::tests::"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2100,6 +6456,16 @@ mod tests {
         (store, dir)
     }
 
+    fn fp(hash: i64, start_line: i64, end_line: i64) -> Fingerprint {
+        Fingerprint {
+            hash,
+            span_start: start_line * 10,
+            span_end: start_line * 10 + 5,
+            start_line,
+            end_line,
+        }
+    }
+
     fn sample_extraction() -> FileExtraction {
         FileExtraction {
             language: LanguageKind::Rust,
@@ -2112,6 +6478,11 @@ mod tests {
                     col: 1,
                     end_line: 3,
                     end_col: 1,
+                    signature: Some("fn foo()".into()),
+                    arity: None,
+                    offsets: None,
+                    visibility: None,
+                    modifiers: Vec::new(),
                 },
                 Definition {
                     name: "Bar".into(),
@@ -2121,6 +6492,11 @@ mod tests {
                     col: 1,
                     end_line: 7,
                     end_col: 1,
+                    signature: None,
+                    arity: None,
+                    offsets: None,
+                    visibility: None,
+                    modifiers: Vec::new(),
                 },
             ],
             references: vec![
@@ -2131,6 +6507,8 @@ mod tests {
                     col: 5,
                     end_line: 2,
                     end_col: 8,
+                    arity: None,
+                    offsets: None,
                 },
                 Reference {
                     name: "baz".into(),
@@ -2139,6 +6517,8 @@ mod tests {
                     col: 10,
                     end_line: 2,
                     end_col: 13,
+                    arity: None,
+                    offsets: None,
                 },
             ],
             imports: vec![Import {
@@ -2146,6 +6526,9 @@ mod tests {
                 line: 1,
                 col: 1,
             }],
+            relations: vec![],
+            noise_spans: vec![],
+            annotations: vec![],
         }
     }
 
@@ -2159,6 +6542,7 @@ mod tests {
                 "rust",
                 "abc123",
                 100,
+                1,
                 &extraction,
                 &[],
                 &[],
@@ -2207,8 +6591,122 @@ mod tests {
         );
     }
 
+    fn symbol_name_count(store: &GraphStore) -> i64 {
+        store
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM entities WHERE entity_type = 'symbol_name'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_index_session_defers_orphan_cleanup_until_finish() {
+        let (mut store, _dir) = store_with_sample_data();
+        assert!(symbol_name_count(&store) > 0);
+
+        let mut session = store.begin_index_session().unwrap();
+        let mut outcome = UpsertOutcome::new();
+        session
+            .remove_files(&["src/main.rs".to_string()], &mut outcome)
+            .unwrap();
+        assert!(
+            symbol_name_count(&session) > 0,
+            "orphans should survive until the session finishes"
+        );
+        assert!(
+            session.begin_index_session().is_err(),
+            "sessions should not nest"
+        );
+        session.finish().unwrap();
+
+        assert_eq!(symbol_name_count(&store), 0);
+        assert!(store.tracked_files().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_index_session_rolls_back_when_dropped() {
+        let (mut store, _dir) = test_store();
+        {
+            let mut session = store.begin_index_session().unwrap();
+            let mut outcome = UpsertOutcome::new();
+            session
+                .index_file(
+                    "src/main.rs",
+                    "rust",
+                    "abc123",
+                    100,
+                    1,
+                    &sample_extraction(),
+                    &[],
+                    &[],
+                    &mut outcome,
+                )
+                .unwrap();
+            assert_eq!(session.tracked_files().unwrap().len(), 1);
+        }
+        assert!(
+            store.tracked_files().unwrap().is_empty(),
+            "unfinished session should roll back"
+        );
+        let session = store.begin_index_session().unwrap();
+        session.finish().unwrap();
+    }
+
     // ── Symbol queries ─────────────────────────────────────────────
 
+    #[test]
+    fn test_graph_query_matches_edges_and_aggregates() {
+        let (store, _dir) = store_with_sample_data();
+        let result = store
+            .graph_query(
+                "MATCH (f:file)-[:imports]->(m:module) WHERE f.path GLOB 'src/*' \
+                 RETURN f.path, m.name",
+                10,
+            )
+            .unwrap();
+        assert_eq!(result.columns, vec!["f.path", "m.name"]);
+        assert_eq!(
+            result.rows,
+            vec![vec![
+                json!("src/main.rs"),
+                json!("std::collections::HashMap")
+            ]]
+        );
+        assert!(!result.truncated);
+
+        let result = store
+            .graph_query(
+                "MATCH (f:file)-[:defines]->(s:symbol) RETURN s ORDER BY s.line LIMIT 1",
+                10,
+            )
+            .unwrap();
+        assert!(result.truncated);
+        assert_eq!(result.rows[0][0]["name"], "foo");
+        assert_eq!(result.rows[0][0]["kind"], "function_item");
+
+        let result = store
+            .graph_query(
+                "MATCH (f:file)-[r]->() RETURN r.type AS edge, count(*) AS n ORDER BY edge",
+                10,
+            )
+            .unwrap();
+        let counts: Vec<(String, i64)> = result
+            .rows
+            .iter()
+            .map(|row| {
+                (
+                    row[0].as_str().unwrap().to_string(),
+                    row[1].as_i64().unwrap(),
+                )
+            })
+            .collect();
+        assert!(counts.contains(&("defines".to_string(), 2)));
+        assert!(counts.contains(&("imports".to_string(), 1)));
+    }
+
     #[test]
     fn test_symbol_definitions() {
         let (store, _dir) = store_with_sample_data();
@@ -2224,6 +6722,106 @@ mod tests {
         assert_eq!(defs[0].end_line, Some(3), "end_line should be preserved");
     }
 
+    #[test]
+    fn test_symbols_matching_regex_kind_and_language() {
+        let (store, _dir) = store_with_sample_data();
+        let names = |pattern: &str, options: SymbolSearchOptions| -> Vec<String> {
+            store
+                .symbols_matching(pattern, &options)
+                .expect("symbols_matching should succeed")
+                .into_iter()
+                .map(|row| row.symbol_name)
+                .collect()
+        };
+
+        assert_eq!(names("^[A-Z]", SymbolSearchOptions::default()), vec!["Bar"]);
+        assert_eq!(names("o", SymbolSearchOptions::default()), vec!["foo"]);
+        assert_eq!(
+            names(
+                ".",
+                SymbolSearchOptions {
+                    kind: Some("function".into()),
+                    ..Default::default()
+                }
+            ),
+            vec!["foo"]
+        );
+        assert!(names(
+            ".",
+            SymbolSearchOptions {
+                language: Some("python".into()),
+                ..Default::default()
+            }
+        )
+        .is_empty());
+        assert_eq!(
+            names(
+                ".",
+                SymbolSearchOptions {
+                    limit: 1,
+                    ..Default::default()
+                }
+            ),
+            vec!["Bar"]
+        );
+        assert!(store
+            .symbols_matching("(", &SymbolSearchOptions::default())
+            .is_err());
+    }
+
+    #[test]
+    fn test_symbols_matching_filters_by_visibility_and_references() {
+        let (mut store, _dir) = test_store();
+        let mut extraction = sample_extraction();
+        extraction.definitions[0].visibility = Some("private".into());
+        extraction.definitions[0].modifiers = vec!["async".into()];
+        extraction.definitions[1].visibility = Some("public".into());
+        let mut outcome = UpsertOutcome::new();
+        store
+            .index_file(
+                "src/main.rs",
+                "rust",
+                "abc123",
+                100,
+                1,
+                &extraction,
+                &[],
+                &[],
+                &mut outcome,
+            )
+            .unwrap();
+        let names = |options: SymbolSearchOptions| -> Vec<String> {
+            store
+                .symbols_matching(".", &options)
+                .unwrap()
+                .into_iter()
+                .map(|row| row.symbol_name)
+                .collect()
+        };
+
+        let private = SymbolSearchOptions {
+            visibility: Some("private".into()),
+            ..Default::default()
+        };
+        assert_eq!(names(private), vec!["foo"]);
+        // `Bar` is referenced from `foo`; nothing refers to `foo`.
+        let unreferenced = SymbolSearchOptions {
+            unreferenced: true,
+            ..Default::default()
+        };
+        assert_eq!(names(unreferenced), vec!["foo"]);
+        let dead_public = SymbolSearchOptions {
+            visibility: Some("public".into()),
+            unreferenced: true,
+            ..Default::default()
+        };
+        assert!(names(dead_public).is_empty());
+
+        let foo = store.symbol_definitions("foo").unwrap().remove(0);
+        assert_eq!(foo.visibility.as_deref(), Some("private"));
+        assert_eq!(foo.modifiers, vec!["async"]);
+    }
+
     #[test]
     fn test_symbol_definitions_nonexistent() {
         let (store, _dir) = store_with_sample_data();
@@ -2258,12 +6856,176 @@ mod tests {
             edge_type_filter: Some("calls".into()),
             ..Default::default()
         };
-        let (rows, _pagination) = store
-            .symbol_references_page("baz", &options)
-            .expect("symbol_references_page with calls filter should succeed");
-        for row in &rows {
-            assert_eq!(row.edge_type, "calls", "all results should be calls");
-        }
+        let (rows, _pagination) = store
+            .symbol_references_page("baz", &options)
+            .expect("symbol_references_page with calls filter should succeed");
+        for row in &rows {
+            assert_eq!(row.edge_type, "calls", "all results should be calls");
+        }
+    }
+
+    #[test]
+    fn test_symbol_references_page_cursor() {
+        let (mut store, _dir) = test_store();
+        index_test_linkage_fixture(&mut store);
+        let options = ReferenceQueryOptions {
+            limit: 1,
+            order: SortOrder::LineAsc,
+            ..Default::default()
+        };
+        let (first, pagination) = store.symbol_references_page("foo", &options).unwrap();
+        assert_eq!(first.len(), 1);
+        assert_eq!(pagination.total, 2);
+        let cursor = pagination
+            .next_cursor
+            .expect("a partial page should carry a cursor");
+
+        let options = ReferenceQueryOptions {
+            cursor: Some(cursor.clone()),
+            ..options
+        };
+        let (second, pagination) = store.symbol_references_page("foo", &options).unwrap();
+        assert_eq!(second.len(), 1);
+        assert_ne!(first[0].line, second[0].line, "cursor should advance");
+        assert!(!pagination.has_more);
+        assert!(pagination.next_cursor.is_none(), "last page has no cursor");
+
+        assert!(
+            store.symbol_references_page("Bar", &options).is_err(),
+            "cursor should be rejected for a different symbol"
+        );
+        let reordered = ReferenceQueryOptions {
+            order: SortOrder::LineDesc,
+            ..options.clone()
+        };
+        assert!(
+            store.symbol_references_page("foo", &reordered).is_err(),
+            "cursor should be rejected for a different order"
+        );
+        let (mut other, _other_dir) = test_store();
+        index_test_linkage_fixture(&mut other);
+        assert!(
+            other.symbol_references_page("foo", &options).is_err(),
+            "cursor should be rejected for another database"
+        );
+        store
+            .remove_files(
+                &["does/not/exist.rs".to_string()],
+                &mut UpsertOutcome::new(),
+            )
+            .unwrap();
+        assert!(
+            store.symbol_references_page("foo", &options).is_err(),
+            "cursor should be rejected once the index changes"
+        );
+        let options = ReferenceQueryOptions {
+            cursor: Some("garbage".into()),
+            ..options
+        };
+        assert!(store.symbol_references_page("foo", &options).is_err());
+    }
+
+    #[test]
+    fn test_symbol_references_grouped_by_enclosing_symbol() {
+        let (mut store, _dir) = test_store();
+        let definition =
+            |name: &str, qualname: &str, kind: &str, line: i64, end_line: i64| Definition {
+                name: name.into(),
+                qualname: qualname.into(),
+                kind: kind.into(),
+                line,
+                col: 1,
+                end_line,
+                end_col: 1,
+                signature: None,
+                arity: None,
+                offsets: None,
+                visibility: None,
+                modifiers: Vec::new(),
+            };
+        let reference = |kind: ReferenceKind, line: i64| Reference {
+            name: "target".into(),
+            kind,
+            line,
+            col: 5,
+            end_line: line,
+            end_col: 11,
+            arity: None,
+            offsets: None,
+        };
+        let extraction = FileExtraction {
+            language: LanguageKind::Rust,
+            definitions: vec![
+                definition("Outer", "Outer", "impl_item", 1, 10),
+                definition("inner", "Outer::inner", "function_item", 3, 5),
+            ],
+            references: vec![
+                reference(ReferenceKind::Call, 4),
+                reference(ReferenceKind::Ref, 5),
+                reference(ReferenceKind::Ref, 7),
+                reference(ReferenceKind::Ref, 12),
+            ],
+            imports: vec![],
+            relations: vec![],
+            noise_spans: vec![],
+            annotations: vec![],
+        };
+        let mut outcome = UpsertOutcome::new();
+        store
+            .index_file(
+                "src/lib.rs",
+                "rust",
+                "hash",
+                10,
+                12,
+                &extraction,
+                &[],
+                &[],
+                &mut outcome,
+            )
+            .unwrap();
+
+        let (groups, pagination) = store
+            .symbol_references_grouped("target", &ReferenceQueryOptions::default())
+            .unwrap();
+        assert_eq!(pagination.total, 3);
+        let summary: Vec<(Option<&str>, usize, usize, Vec<i64>)> = groups
+            .iter()
+            .map(|group| {
+                (
+                    group.enclosing_symbol.as_deref(),
+                    group.count,
+                    group.calls,
+                    group.lines.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Some("Outer::inner"), 2, 1, vec![4, 5]),
+                (Some("Outer"), 1, 0, vec![7]),
+                (None, 1, 0, vec![12]),
+            ]
+        );
+
+        let paged = ReferenceQueryOptions {
+            limit: 1,
+            offset: 1,
+            ..Default::default()
+        };
+        let (groups, pagination) = store.symbol_references_grouped("target", &paged).unwrap();
+        assert_eq!(groups[0].enclosing_symbol.as_deref(), Some("Outer"));
+        assert_eq!(pagination.next_offset, Some(2));
+
+        let with_cursor = ReferenceQueryOptions {
+            cursor: Some("1:0".into()),
+            ..Default::default()
+        };
+        let err = store
+            .symbol_references_grouped("target", &with_cursor)
+            .unwrap_err();
+        assert_eq!(crate::error::error_code(&err), ErrorCode::InvalidParams);
     }
 
     #[test]
@@ -2295,6 +7057,144 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_reference_resolution_prefers_local_then_imported_definitions() {
+        let (mut store, _dir) = test_store();
+        let mut outcome = UpsertOutcome::new();
+        let extraction = |defines: bool, call_line: i64| FileExtraction {
+            language: LanguageKind::Rust,
+            definitions: if defines {
+                vec![Definition {
+                    name: "helper".into(),
+                    qualname: "helper".into(),
+                    kind: "function_item".into(),
+                    line: 1,
+                    col: 1,
+                    end_line: 3,
+                    end_col: 1,
+                    signature: None,
+                    arity: None,
+                    offsets: None,
+                    visibility: None,
+                    modifiers: Vec::new(),
+                }]
+            } else {
+                vec![]
+            },
+            references: vec![Reference {
+                name: "helper".into(),
+                kind: ReferenceKind::Call,
+                line: call_line,
+                col: 5,
+                end_line: call_line,
+                end_col: 11,
+                arity: None,
+                offsets: None,
+            }],
+            imports: vec![],
+            relations: vec![],
+            noise_spans: vec![],
+            annotations: vec![],
+        };
+        let imports_b = vec![("crate::b".to_string(), "src/b.rs".to_string())];
+
+        // The importer is indexed before its target exists; indexing the
+        // target later must re-resolve it.
+        store
+            .index_file(
+                "src/c.rs",
+                "rust",
+                "c",
+                10,
+                1,
+                &extraction(false, 2),
+                &[],
+                &imports_b,
+                &mut outcome,
+            )
+            .unwrap();
+        store
+            .index_file(
+                "src/a.rs",
+                "rust",
+                "a",
+                10,
+                1,
+                &extraction(true, 5),
+                &[],
+                &[],
+                &mut outcome,
+            )
+            .unwrap();
+        store
+            .index_file(
+                "src/b.rs",
+                "rust",
+                "b",
+                10,
+                1,
+                &extraction(true, 6),
+                &[],
+                &[],
+                &mut outcome,
+            )
+            .unwrap();
+        store
+            .index_file(
+                "src/d.rs",
+                "rust",
+                "d",
+                10,
+                1,
+                &extraction(false, 2),
+                &[],
+                &[],
+                &mut outcome,
+            )
+            .unwrap();
+
+        let options = ReferenceQueryOptions {
+            order: SortOrder::LineAsc,
+            ..Default::default()
+        };
+        let (rows, _) = store.symbol_references_page("helper", &options).unwrap();
+        let resolved: HashMap<&str, Option<&str>> = rows
+            .iter()
+            .map(|row| (row.file_path.as_str(), row.resolved_symbol_key.as_deref()))
+            .collect();
+        assert_eq!(
+            resolved["src/a.rs"],
+            Some("symbol:src/a.rs:helper:function_item:1:1")
+        );
+        assert_eq!(
+            resolved["src/b.rs"],
+            Some("symbol:src/b.rs:helper:function_item:1:1")
+        );
+        assert_eq!(
+            resolved["src/c.rs"],
+            Some("symbol:src/b.rs:helper:function_item:1:1"),
+            "imported definition should resolve once the target is indexed"
+        );
+        assert_eq!(resolved["src/d.rs"], None);
+
+        let options = ReferenceQueryOptions {
+            resolved_only: true,
+            ..options
+        };
+        let (rows, _) = store.symbol_references_page("helper", &options).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert!(rows.iter().all(|row| row.file_path != "src/d.rs"));
+
+        // Dropping the target unbinds the importer.
+        let mut outcome = UpsertOutcome::new();
+        store
+            .remove_files(&["src/b.rs".to_string()], &mut outcome)
+            .unwrap();
+        let (rows, _) = store.symbol_references_page("helper", &options).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].file_path, "src/a.rs");
+    }
+
     // ── Dependency path ────────────────────────────────────────────
 
     #[test]
@@ -2323,6 +7223,293 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_ambiguous_selectors_list_candidates_and_honor_pick() {
+        let (mut store, _dir) = store_with_sample_data();
+        store
+            .index_file(
+                "src/other.rs",
+                "rust",
+                "def456",
+                100,
+                1,
+                &sample_extraction(),
+                &[],
+                &[],
+                &mut UpsertOutcome::new(),
+            )
+            .unwrap();
+
+        for selector in ["foo", "symbol_name:rust:foo"] {
+            let (symbol, resolution) = store.symbol_for_selector(selector, None).unwrap();
+            assert!(resolution.ambiguous, "{selector} should be ambiguous");
+            assert_eq!(resolution.candidates.len(), 2);
+            assert_eq!(
+                resolution.candidates[1].file_path.as_deref(),
+                Some("src/other.rs")
+            );
+            assert_eq!(
+                resolution.candidates[0].kind.as_deref(),
+                Some("function_item")
+            );
+            assert_eq!(
+                symbol.unwrap().file_path.as_deref(),
+                Some("src/main.rs"),
+                "without pick the first candidate wins"
+            );
+
+            let (symbol, resolution) = store.symbol_for_selector(selector, Some(1)).unwrap();
+            assert_eq!(symbol.unwrap().file_path.as_deref(), Some("src/other.rs"));
+            assert_eq!(
+                resolution.selected_key,
+                Some(resolution.candidates[1].key.clone())
+            );
+        }
+
+        let err = store.symbol_for_selector("foo", Some(2)).unwrap_err();
+        assert_eq!(crate::error::error_code(&err), ErrorCode::SelectorAmbiguous);
+        let err = store
+            .symbol_for_selector("file:src/other.rs", Some(1))
+            .unwrap_err();
+        assert_eq!(crate::error::error_code(&err), ErrorCode::InvalidParams);
+
+        let (path, from, to) = store
+            .dependency_path_with_diagnostics("file:src/other.rs", "foo", 5, None, Some(1))
+            .unwrap();
+        assert!(!from.ambiguous && from.candidates.is_empty());
+        assert!(to.ambiguous);
+        assert!(path.found);
+        assert_eq!(
+            path.hops.last().unwrap().entity_key,
+            to.candidates[1].key,
+            "the picked definition is the path target"
+        );
+    }
+
+    #[test]
+    fn test_dependency_path_follows_type_relations() {
+        let (mut store, _dir) = test_store();
+        let mut outcome = UpsertOutcome::new();
+        let definition = |name: &str, kind: &str| Definition {
+            name: name.into(),
+            qualname: name.into(),
+            kind: kind.into(),
+            line: 1,
+            col: 1,
+            end_line: 1,
+            end_col: 20,
+            signature: None,
+            arity: None,
+            offsets: None,
+            visibility: None,
+            modifiers: Vec::new(),
+        };
+        let shape = FileExtraction {
+            language: LanguageKind::Rust,
+            definitions: vec![definition("Shape", "trait_item")],
+            references: vec![],
+            imports: vec![],
+            relations: vec![],
+            noise_spans: vec![],
+            annotations: vec![],
+        };
+        let circle = FileExtraction {
+            language: LanguageKind::Rust,
+            definitions: vec![definition("Circle", "struct_item")],
+            references: vec![],
+            imports: vec![],
+            relations: vec![Relation {
+                subject: "Circle".into(),
+                target: "Shape".into(),
+                kind: RelationKind::Implements,
+                line: 3,
+                col: 1,
+            }],
+            noise_spans: vec![],
+            annotations: vec![],
+        };
+        for (path, extraction) in [("src/shape.rs", &shape), ("src/circle.rs", &circle)] {
+            store
+                .index_file(
+                    path,
+                    "rust",
+                    path,
+                    10,
+                    1,
+                    extraction,
+                    &[],
+                    &[],
+                    &mut outcome,
+                )
+                .unwrap();
+        }
+
+        let edges: i64 = store
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM edges WHERE edge_type = 'implements' AND file_path = 'src/circle.rs'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(edges, 1, "implements edge should be stored once");
+
+        let result = store
+            .dependency_path("symbol_name:rust:Circle", "symbol:Shape", 5)
+            .unwrap();
+        assert!(result.found, "path should follow implements to the trait");
+        let last = result.hops.last().unwrap();
+        assert_eq!(last.entity_type, "symbol");
+        assert_eq!(last.entity_name, "Shape");
+        let edges: Vec<_> = result
+            .hops
+            .iter()
+            .map(|hop| {
+                (
+                    hop.edge_type.as_deref(),
+                    hop.edge_file_path.as_deref(),
+                    hop.edge_line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            edges,
+            vec![
+                (None, None, None),
+                (Some("implements"), Some("src/circle.rs"), Some(3)),
+                (Some("names"), Some("src/shape.rs"), Some(1)),
+            ],
+            "each hop records the edge it came through"
+        );
+    }
+
+    #[test]
+    fn test_dependency_path_hops_record_edge_type_and_provenance() {
+        let (mut store, _dir) = test_store();
+        let mut outcome = UpsertOutcome::new();
+        let util = FileExtraction {
+            language: LanguageKind::Rust,
+            definitions: vec![Definition {
+                name: "helper".into(),
+                qualname: "helper".into(),
+                kind: "function_item".into(),
+                line: 2,
+                col: 1,
+                end_line: 4,
+                end_col: 1,
+                signature: None,
+                arity: None,
+                offsets: None,
+                visibility: None,
+                modifiers: Vec::new(),
+            }],
+            references: vec![],
+            imports: vec![],
+            relations: vec![],
+            noise_spans: vec![],
+            annotations: vec![],
+        };
+        let app = FileExtraction {
+            language: LanguageKind::Rust,
+            definitions: vec![],
+            references: vec![Reference {
+                name: "helper".into(),
+                kind: ReferenceKind::Call,
+                line: 7,
+                col: 5,
+                end_line: 7,
+                end_col: 11,
+                arity: Some(0),
+                offsets: None,
+            }],
+            imports: vec![],
+            relations: vec![],
+            noise_spans: vec![],
+            annotations: vec![],
+        };
+        let mut index = |store: &mut GraphStore,
+                         path: &str,
+                         extraction: &FileExtraction,
+                         resolved: &[(String, String)]| {
+            store
+                .index_file(
+                    path,
+                    "rust",
+                    path,
+                    10,
+                    8,
+                    extraction,
+                    &[],
+                    resolved,
+                    &mut outcome,
+                )
+                .unwrap();
+        };
+        index(&mut store, "src/util.rs", &util, &[]);
+        index(&mut store, "src/app.rs", &app, &[]);
+
+        let hops = |path: &[PathHop]| {
+            path.iter()
+                .map(|hop| {
+                    (
+                        hop.entity_type.clone(),
+                        hop.edge_type.clone(),
+                        hop.edge_file_path.clone(),
+                        hop.edge_line,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected = vec![
+            ("file".to_string(), None, None, None),
+            (
+                "symbol_name".to_string(),
+                Some("calls".to_string()),
+                Some("src/app.rs".to_string()),
+                Some(7),
+            ),
+            (
+                "symbol".to_string(),
+                Some("names".to_string()),
+                Some("src/util.rs".to_string()),
+                Some(2),
+            ),
+        ];
+        let path = store
+            .dependency_path("file:src/app.rs", "symbol:helper", 5)
+            .unwrap();
+        assert!(path.found);
+        assert_eq!(hops(&path.hops), expected);
+
+        let (paths, _, _) = store
+            .dependency_paths_with_diagnostics("file:src/app.rs", "symbol:helper", 5, 3, None, None)
+            .unwrap();
+        assert_eq!(hops(&paths.paths[0]), expected);
+
+        // A resolved import is recorded by the importing file, without a line.
+        index(
+            &mut store,
+            "src/app.rs",
+            &app,
+            &[("util".to_string(), "src/util.rs".to_string())],
+        );
+        let path = store
+            .dependency_path("file:src/app.rs", "file:src/util.rs", 5)
+            .unwrap();
+        assert_eq!(
+            hops(&path.hops),
+            vec![
+                ("file".to_string(), None, None, None),
+                (
+                    "file".to_string(),
+                    Some("depends_on".to_string()),
+                    Some("src/app.rs".to_string()),
+                    None,
+                ),
+            ]
+        );
+    }
+
     // ── Minimal slice ──────────────────────────────────────────────
 
     #[test]
@@ -2334,6 +7521,94 @@ mod tests {
         assert!(result.is_some(), "should return a slice for indexed file");
     }
 
+    #[test]
+    fn test_signatures_surface_in_definitions_outline_and_slice() {
+        let (store, _dir) = store_with_sample_data();
+
+        let defs = store.symbol_definitions("foo").unwrap();
+        assert_eq!(defs[0].signature.as_deref(), Some("fn foo()"));
+        let defs = store.symbol_definitions("Bar").unwrap();
+        assert_eq!(defs[0].signature, None, "structs have no signature");
+
+        let outline = store
+            .dir_outline("src", &DirOutlineOptions::default())
+            .unwrap();
+        let foo = outline.files[0]
+            .entries
+            .iter()
+            .find(|entry| entry.name == "foo")
+            .expect("outline should list foo");
+        assert_eq!(foo.signature.as_deref(), Some("fn foo()"));
+
+        let slice = store
+            .minimal_slice_with_options("src/main.rs", None, 1, &SliceQueryOptions::default())
+            .unwrap()
+            .expect("slice should exist");
+        let foo = slice
+            .neighbors
+            .iter()
+            .find(|edge| edge.entity.name == "foo")
+            .expect("slice should include foo");
+        assert_eq!(foo.entity.signature.as_deref(), Some("fn foo()"));
+    }
+
+    #[test]
+    fn test_span_offsets_surface_in_definitions_references_and_slice() {
+        let (mut store, _dir) = test_store();
+        let mut extraction = sample_extraction();
+        let foo_offsets = SpanOffsets {
+            start_byte: 0,
+            end_byte: 24,
+            col_utf16: 1,
+            end_col_utf16: 2,
+        };
+        let bar_offsets = SpanOffsets {
+            start_byte: 16,
+            end_byte: 19,
+            col_utf16: 4,
+            end_col_utf16: 7,
+        };
+        extraction.definitions[0].offsets = Some(foo_offsets);
+        extraction.references[0].offsets = Some(bar_offsets);
+        store
+            .index_file(
+                "src/main.rs",
+                "rust",
+                "abc123",
+                100,
+                1,
+                &extraction,
+                &[],
+                &[],
+                &mut UpsertOutcome::new(),
+            )
+            .unwrap();
+
+        let defs = store.symbol_definitions("foo").unwrap();
+        assert_eq!(defs[0].offsets, Some(foo_offsets));
+        assert_eq!(store.symbol_definitions("Bar").unwrap()[0].offsets, None);
+
+        let refs = store
+            .symbol_references_page("Bar", &ReferenceQueryOptions::default())
+            .unwrap()
+            .0;
+        assert_eq!(refs[0].offsets, Some(bar_offsets));
+        let row = serde_json::to_value(&refs[0]).unwrap();
+        assert_eq!(row["col_utf16"], 4);
+        assert_eq!(row["start_byte"], 16);
+
+        let slice = store
+            .minimal_slice_with_options("src/main.rs", None, 1, &SliceQueryOptions::default())
+            .unwrap()
+            .expect("slice should exist");
+        let foo = slice
+            .neighbors
+            .iter()
+            .find(|edge| edge.entity.name == "foo")
+            .expect("slice should include foo");
+        assert_eq!(foo.entity.offsets, Some(foo_offsets));
+    }
+
     #[test]
     fn test_minimal_slice_missing_file() {
         let (store, _dir) = store_with_sample_data();
@@ -2343,6 +7618,50 @@ mod tests {
         assert!(result.is_none(), "should return None for nonexistent file");
     }
 
+    // ── Query deadline ─────────────────────────────────────────────
+
+    #[test]
+    fn test_query_deadline_interrupts_once_and_keeps_partial_results() {
+        let (mut store, _dir) = store_with_sample_data();
+        store.set_query_deadline(Some(QueryDeadline::after(Duration::ZERO)));
+
+        let mut stmt = store
+            .conn
+            .prepare(
+                "WITH RECURSIVE n(x) AS (SELECT 1 UNION ALL SELECT x + 1 FROM n) SELECT x FROM n",
+            )
+            .unwrap();
+        let rows = stmt.query_map([], |row| row.get::<_, i64>(0)).unwrap();
+        let rows = store
+            .collect_within_deadline(rows)
+            .expect("an interrupted query should yield its partial rows");
+        drop(stmt);
+        assert!(store.timed_out(), "deadline should have fired");
+        assert!(!rows.is_empty(), "rows read before the interrupt are kept");
+
+        let definitions = store
+            .symbol_definitions("foo")
+            .expect("follow-up lookups run once the deadline has fired");
+        assert_eq!(definitions.len(), 1);
+
+        let slice = store
+            .minimal_slice_with_options("src/main.rs", None, 2, &SliceQueryOptions::default())
+            .unwrap()
+            .expect("anchor is still resolved");
+        assert!(
+            slice.neighbors.is_empty(),
+            "traversal stops when out of time"
+        );
+
+        store.set_query_deadline(Some(QueryDeadline::after(Duration::from_secs(60))));
+        let slice = store
+            .minimal_slice_with_options("src/main.rs", None, 2, &SliceQueryOptions::default())
+            .unwrap()
+            .unwrap();
+        assert!(!store.timed_out());
+        assert!(!slice.neighbors.is_empty());
+    }
+
     // ── Clone detection ────────────────────────────────────────────
 
     #[test]
@@ -2370,8 +7689,9 @@ mod tests {
                 "rust",
                 "hash_a",
                 100,
+                1,
                 &extraction,
-                &[(100, 0, 10), (200, 10, 20)],
+                &[fp(100, 1, 3), fp(200, 4, 6)],
                 &[],
                 &mut outcome,
             )
@@ -2382,8 +7702,9 @@ mod tests {
                 "rust",
                 "hash_b",
                 100,
+                1,
                 &extraction,
-                &[(100, 0, 10), (300, 10, 20)],
+                &[fp(100, 7, 9), fp(300, 10, 12)],
                 &[],
                 &mut outcome,
             )
@@ -2399,6 +7720,101 @@ mod tests {
             !rows.is_empty(),
             "should find clone matches with shared fingerprints"
         );
+        assert_eq!(
+            rows[0].regions,
+            vec![CloneRegion {
+                start_line: 1,
+                end_line: 3,
+                other_start_line: 7,
+                other_end_line: 9,
+            }]
+        );
+    }
+
+    #[test]
+    fn clone_matches_filter_by_language() {
+        let (mut store, _dir) = test_store();
+        let extraction = sample_extraction();
+        let mut outcome = UpsertOutcome::new();
+        for (path, lang) in [
+            ("fixtures/a.json", "json"),
+            ("fixtures/b.json", "json"),
+            ("config/b.yaml", "yaml"),
+        ] {
+            store
+                .index_file(
+                    path,
+                    lang,
+                    "hash",
+                    100,
+                    1,
+                    &extraction,
+                    &[fp(100, 1, 3), fp(200, 4, 6)],
+                    &[],
+                    &mut outcome,
+                )
+                .unwrap();
+        }
+        let matched = |options: CloneQueryOptions| {
+            let (rows, _, analysis) = store
+                .clone_matches_page("fixtures/a.json", &options)
+                .unwrap();
+            let files = rows
+                .iter()
+                .map(|row| (row.other_file.clone(), row.language.clone().unwrap()))
+                .collect::<Vec<_>>();
+            (files, analysis)
+        };
+
+        let (files, analysis) = matched(CloneQueryOptions::default());
+        assert_eq!(files.len(), 2);
+        assert_eq!(analysis.filtered_by_language, 0);
+
+        let (files, analysis) = matched(CloneQueryOptions {
+            same_language_only: true,
+            ..Default::default()
+        });
+        assert_eq!(files, [("fixtures/b.json".to_string(), "json".to_string())]);
+        assert_eq!(analysis.filtered_by_language, 1);
+        assert_eq!(analysis.candidate_files, 1);
+
+        let (files, _) = matched(CloneQueryOptions {
+            language: Some("yaml".into()),
+            ..Default::default()
+        });
+        assert_eq!(files, [("config/b.yaml".to_string(), "yaml".to_string())]);
+
+        let (files, analysis) = matched(CloneQueryOptions {
+            language: Some("python".into()),
+            ..Default::default()
+        });
+        assert!(files.is_empty());
+        assert!(analysis
+            .empty_reason
+            .unwrap()
+            .contains("excluded by the language filter"));
+    }
+
+    #[test]
+    fn merge_clone_regions_joins_spans_touching_in_both_files() {
+        let span = |start_line, end_line, other_start_line, other_end_line| CloneRegion {
+            start_line,
+            end_line,
+            other_start_line,
+            other_end_line,
+        };
+        let merged = merge_clone_regions(vec![
+            span(1, 3, 11, 13),
+            span(3, 5, 13, 15),
+            span(6, 6, 16, 16),
+            // Contiguous here but elsewhere in the other file.
+            span(7, 8, 40, 41),
+            span(20, 21, 50, 51),
+        ]);
+        assert_eq!(
+            merged,
+            vec![span(1, 6, 11, 16), span(7, 8, 40, 41), span(20, 21, 50, 51)]
+        );
     }
 
     #[test]
@@ -2412,8 +7828,9 @@ mod tests {
                 "rust",
                 "hash_a",
                 100,
+                1,
                 &extraction,
-                &[(100, 0, 10), (200, 10, 20)],
+                &[fp(100, 1, 3), fp(200, 4, 6)],
                 &[],
                 &mut outcome,
             )
@@ -2424,20 +7841,97 @@ mod tests {
                 "rust",
                 "hash_b",
                 100,
+                1,
                 &extraction,
-                &[(100, 0, 10), (300, 10, 20)],
+                &[fp(100, 7, 9), fp(300, 10, 12)],
                 &[],
                 &mut outcome,
             )
             .unwrap();
-        let options = CloneQueryOptions {
-            min_similarity: 0.0,
-            ..Default::default()
-        };
-        let hotspots = store
-            .clone_hotspots("src/a.rs", &options)
-            .expect("clone_hotspots should succeed");
-        assert!(!hotspots.is_empty(), "should find at least one hotspot");
+        let options = CloneQueryOptions {
+            min_similarity: 0.0,
+            ..Default::default()
+        };
+        let hotspots = store
+            .clone_hotspots("src/a.rs", &options)
+            .expect("clone_hotspots should succeed");
+        assert!(!hotspots.is_empty(), "should find at least one hotspot");
+    }
+
+    #[test]
+    fn clone_dirs_pairs_files_across_two_trees() {
+        let (mut store, _dir) = test_store();
+        let extraction = sample_extraction();
+        let mut outcome = UpsertOutcome::new();
+        for (path, hashes) in [
+            ("services/billing/retry.rs", [100, 200, 300]),
+            ("services/billing/api.rs", [400, 500, 600]),
+            ("services/orders/retry.rs", [100, 200, 700]),
+            ("services/orders/util.rs", [800, 900, 600]),
+            ("services/ordersextra/retry.rs", [100, 200, 300]),
+        ] {
+            let fingerprints = hashes.map(|hash| fp(hash, 1, 2));
+            store
+                .index_file(
+                    path,
+                    "rust",
+                    path,
+                    100,
+                    1,
+                    &extraction,
+                    &fingerprints,
+                    &[],
+                    &mut outcome,
+                )
+                .unwrap();
+        }
+
+        let options = CloneQueryOptions {
+            min_similarity: 0.0,
+            ..Default::default()
+        };
+        let (pairs, pagination, summary) = store
+            .clone_dirs_page("services/billing", "./services/orders/", &options)
+            .unwrap();
+        let found = pairs
+            .iter()
+            .map(|pair| (pair.file.as_str(), pair.other_file.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                ("services/billing/retry.rs", "services/orders/retry.rs"),
+                ("services/billing/api.rs", "services/orders/util.rs"),
+            ]
+        );
+        assert!((pairs[0].similarity - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(pagination.total, 2);
+        assert_eq!((summary.files, summary.other_files), (2, 2));
+        assert_eq!(summary.other_dir, "services/orders");
+        assert_eq!(summary.shared_fingerprints, 3);
+        assert!((summary.similarity - 0.5).abs() < 1e-9);
+
+        let (pairs, _, summary) = store
+            .clone_dirs_page(
+                "services/billing",
+                "services/orders",
+                &CloneQueryOptions {
+                    min_similarity: 0.5,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(summary.empty_reason, None);
+
+        let (pairs, _, summary) = store
+            .clone_dirs_page("services/billing", "vendor", &options)
+            .unwrap();
+        assert!(pairs.is_empty());
+        assert_eq!(
+            summary.empty_reason.as_deref(),
+            Some("no fingerprinted files under `vendor`")
+        );
     }
 
     // ── Selector suggestions ───────────────────────────────────────
@@ -2473,6 +7967,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_selector_suggestions_rank_connected_entities_first() {
+        let (mut store, _dir) = store_with_sample_data();
+        let reference = |line| Reference {
+            name: "Bar".into(),
+            kind: ReferenceKind::Ref,
+            line,
+            col: 1,
+            end_line: line,
+            end_col: 4,
+            arity: None,
+            offsets: None,
+        };
+        let extraction = FileExtraction {
+            definitions: vec![],
+            references: vec![reference(1), reference(2), reference(3)],
+            imports: vec![],
+            ..sample_extraction()
+        };
+        store
+            .index_file(
+                "src/other.rs",
+                "rust",
+                "def456",
+                50,
+                1,
+                &extraction,
+                &[],
+                &[],
+                &mut UpsertOutcome::new(),
+            )
+            .unwrap();
+
+        let results = store
+            .selector_suggestions_advanced(&SelectorSuggestOptions {
+                limit: 3,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(results[0].entity_type, "symbol");
+        assert_eq!(results[0].name, "Bar");
+        assert_eq!(results[0].degree, Some(6));
+        assert!(results[0].why.as_deref().unwrap().contains("degree=6"));
+    }
+
     // ── top_reference_files ────────────────────────────────────────
 
     #[test]
@@ -2480,34 +8019,55 @@ mod tests {
         let (store, _dir) = test_store();
         let refs = vec![
             ReferenceLocation {
+                row_id: String::new(),
                 symbol_name: "x".into(),
                 file_path: "a.rs".into(),
                 line: 1,
                 col: 1,
+                end_line: None,
+                end_col: None,
                 edge_type: "references".into(),
+                resolved_symbol_key: None,
                 score: None,
+                confidence: None,
                 why: None,
+                origin: None,
+                offsets: None,
             },
             ReferenceLocation {
+                row_id: String::new(),
                 symbol_name: "x".into(),
                 file_path: "a.rs".into(),
                 line: 2,
                 col: 1,
+                end_line: None,
+                end_col: None,
                 edge_type: "references".into(),
+                resolved_symbol_key: None,
                 score: None,
+                confidence: None,
                 why: None,
+                origin: None,
+                offsets: None,
             },
             ReferenceLocation {
+                row_id: String::new(),
                 symbol_name: "x".into(),
                 file_path: "b.rs".into(),
                 line: 1,
                 col: 1,
+                end_line: None,
+                end_col: None,
                 edge_type: "calls".into(),
+                resolved_symbol_key: None,
                 score: None,
+                confidence: None,
                 why: None,
+                origin: None,
+                offsets: None,
             },
         ];
-        let summary = store.top_reference_files(&refs, 10);
+        let summary = store.top_reference_files(&refs, 10).unwrap();
         assert_eq!(summary.len(), 2, "should have 2 files");
         assert_eq!(
             summary[0].file_path, "a.rs",
@@ -2516,155 +8076,788 @@ mod tests {
         assert_eq!(summary[0].count, 2, "a.rs should have count=2");
     }
 
-    // ── build_pagination ───────────────────────────────────────────
-
     #[test]
-    fn test_build_pagination_basic() {
-        let p = build_pagination(10, 0, 5, 5);
-        assert_eq!(p.total, 10, "total should be 10");
-        assert!(p.has_more, "should have more pages");
-        assert_eq!(p.next_offset, Some(5), "next_offset should be 5");
-    }
+    fn test_sync_file_owners_updates_only_changed_files() {
+        let (mut store, _dir) = store_with_sample_data();
+        let owners = HashMap::from([("src/main.rs".to_string(), vec!["@core".to_string()])]);
+        assert_eq!(store.sync_file_owners(&owners).unwrap(), 1);
+        assert_eq!(store.sync_file_owners(&owners).unwrap(), 0);
+        assert_eq!(store.file_owners("src/main.rs").unwrap(), ["@core"]);
+        let kind: String = store
+            .conn
+            .query_row(
+                "SELECT json_extract(meta_json, '$.kind') FROM entities WHERE key = ?1",
+                [file_key("src/main.rs")],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(kind, "source", "other file metadata is kept");
+
+        assert_eq!(store.sync_file_owners(&HashMap::new()).unwrap(), 1);
+        assert!(store.file_owners("src/main.rs").unwrap().is_empty());
+    }
+
+    // ── build_pagination ───────────────────────────────────────────
+
+    #[test]
+    fn test_build_pagination_basic() {
+        let p = build_pagination(10, 0, 5, 5);
+        assert_eq!(p.total, 10, "total should be 10");
+        assert!(p.has_more, "should have more pages");
+        assert_eq!(p.next_offset, Some(5), "next_offset should be 5");
+    }
+
+    #[test]
+    fn test_build_pagination_no_more() {
+        let p = build_pagination(5, 0, 10, 5);
+        assert!(!p.has_more, "should not have more pages");
+        assert_eq!(p.next_offset, None, "next_offset should be None");
+    }
+
+    #[test]
+    fn test_pagination_text_hint_names_the_next_offset() {
+        assert_eq!(
+            build_pagination(950, 200, 200, 200).text_hint().as_deref(),
+            Some("showing 201–400 of 950, next: --offset 400")
+        );
+        assert_eq!(
+            build_pagination(950, 900, 200, 50).text_hint().as_deref(),
+            Some("showing 901–950 of 950")
+        );
+        assert_eq!(build_pagination(5, 0, 10, 5).text_hint(), None);
+    }
+
+    #[test]
+    fn test_build_pagination_offset_past_end() {
+        let p = build_pagination(5, 100, 10, 0);
+        assert_eq!(p.offset, 5, "offset should be clamped to total");
+        assert!(!p.has_more, "should not have more pages");
+    }
+
+    // ── parse_selector ─────────────────────────────────────────────
+
+    #[test]
+    fn test_parse_selector_file() {
+        let result = parse_selector("file:src/main.rs").expect("should parse file selector");
+        match result {
+            ParsedSelector::File(p) => assert_eq!(p, "src/main.rs", "path should match"),
+            other => panic!("expected File variant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_selector_symbol_name() {
+        let result =
+            parse_selector("symbol_name:rust:foo").expect("should parse symbol_name selector");
+        match result {
+            ParsedSelector::SymbolName { lang, name } => {
+                assert_eq!(lang, "rust", "lang should be rust");
+                assert_eq!(name, "foo", "name should be foo");
+            }
+            other => panic!("expected SymbolName variant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_selector_symbol() {
+        let result = parse_selector("symbol:main").expect("should parse symbol selector");
+        match result {
+            ParsedSelector::Name(n) => assert_eq!(n, "main", "name should be main"),
+            other => panic!("expected Name variant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_selector_key() {
+        let result = parse_selector("key:some_key").expect("should parse key selector");
+        match result {
+            ParsedSelector::Key(k) => assert_eq!(k, "some_key", "key should match"),
+            other => panic!("expected Key variant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_selector_auto() {
+        let result = parse_selector("main").expect("should parse auto selector");
+        match result {
+            ParsedSelector::Auto(v) => assert_eq!(v, "main", "auto value should match"),
+            other => panic!("expected Auto variant, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_selector_empty_errors() {
+        assert!(parse_selector("").is_err(), "empty selector should error");
+        assert!(
+            parse_selector("file:").is_err(),
+            "file: with no path should error"
+        );
+        assert!(
+            parse_selector("symbol_name:rust:").is_err(),
+            "symbol_name with empty name should error"
+        );
+        assert!(
+            parse_selector("symbol:").is_err(),
+            "symbol: with no name should error"
+        );
+    }
+
+    // ── Public key helpers ─────────────────────────────────────────
+
+    #[test]
+    fn test_file_key() {
+        assert_eq!(
+            file_key("src/main.rs"),
+            "file:src/main.rs",
+            "file_key format"
+        );
+    }
+
+    #[test]
+    fn test_symbol_name_key() {
+        assert_eq!(
+            symbol_name_key("rust", "foo"),
+            "symbol_name:rust:foo",
+            "symbol_name_key format"
+        );
+    }
+
+    #[test]
+    fn test_module_key() {
+        assert_eq!(
+            module_key("rust", "std::io"),
+            "module:rust:std::io",
+            "module_key format"
+        );
+    }
+
+    // ── classify_special_file ──────────────────────────────────────
+
+    #[test]
+    fn test_classify_special_file() {
+        assert_eq!(
+            classify_special_file("project/Cargo.toml"),
+            Some("config"),
+            "Cargo.toml should be config"
+        );
+        assert_eq!(
+            classify_special_file("project/src/main.rs"),
+            Some("entrypoint"),
+            "src/main.rs should be entrypoint"
+        );
+        assert_eq!(
+            classify_special_file("project/src/lib.rs"),
+            Some("entrypoint"),
+            "src/lib.rs should be entrypoint"
+        );
+        assert_eq!(
+            classify_special_file("project/__main__.py"),
+            Some("entrypoint"),
+            "__main__.py should be entrypoint"
+        );
+        assert_eq!(
+            classify_special_file("random.rs"),
+            None,
+            "random.rs should be None"
+        );
+    }
+
+    #[test]
+    fn test_classify_test_file() {
+        assert!(classify_test_file("tests/integration.rs"), "tests/ dir");
+        assert!(classify_test_file("pkg/test_models.py"), "test_ prefix");
+        assert!(classify_test_file("pkg/server_test.go"), "_test suffix");
+        assert!(classify_test_file("web/app.test.ts"), ".test. infix");
+        assert!(classify_test_file("web/__tests__/app.js"), "__tests__ dir");
+        assert!(classify_test_file("src/main/FooTest.java"), "Test suffix");
+        assert!(
+            !classify_test_file("src/latest.rs"),
+            "latest.rs is not a test"
+        );
+        assert!(!classify_test_file("src/main.rs"), "main.rs is not a test");
+    }
+
+    // ── Test linkage ──────────────────────────────────────────────
+
+    fn index_test_linkage_fixture(store: &mut GraphStore) {
+        let mut outcome = UpsertOutcome::new();
+        store
+            .index_file(
+                "src/main.rs",
+                "rust",
+                "abc123",
+                100,
+                1,
+                &sample_extraction(),
+                &[],
+                &[],
+                &mut outcome,
+            )
+            .unwrap();
+
+        let extraction = FileExtraction {
+            language: LanguageKind::Rust,
+            definitions: vec![
+                Definition {
+                    name: "test_foo_works".into(),
+                    qualname: "test_foo_works".into(),
+                    kind: "function_item".into(),
+                    line: 1,
+                    col: 1,
+                    end_line: 4,
+                    end_col: 1,
+                    signature: None,
+                    arity: None,
+                    offsets: None,
+                    visibility: None,
+                    modifiers: Vec::new(),
+                },
+                Definition {
+                    name: "helper".into(),
+                    qualname: "helper".into(),
+                    kind: "function_item".into(),
+                    line: 6,
+                    col: 1,
+                    end_line: 8,
+                    end_col: 1,
+                    signature: None,
+                    arity: None,
+                    offsets: None,
+                    visibility: None,
+                    modifiers: Vec::new(),
+                },
+            ],
+            references: vec![
+                Reference {
+                    name: "foo".into(),
+                    kind: ReferenceKind::Call,
+                    line: 2,
+                    col: 5,
+                    end_line: 2,
+                    end_col: 8,
+                    arity: None,
+                    offsets: None,
+                },
+                Reference {
+                    name: "foo".into(),
+                    kind: ReferenceKind::Call,
+                    line: 3,
+                    col: 5,
+                    end_line: 3,
+                    end_col: 8,
+                    arity: None,
+                    offsets: None,
+                },
+                Reference {
+                    name: "Bar".into(),
+                    kind: ReferenceKind::Ref,
+                    line: 7,
+                    col: 5,
+                    end_line: 7,
+                    end_col: 8,
+                    arity: None,
+                    offsets: None,
+                },
+            ],
+            imports: vec![],
+            relations: vec![],
+            noise_spans: vec![],
+            annotations: vec![],
+        };
+        store
+            .index_file(
+                "tests/foo_test.rs",
+                "rust",
+                "def456",
+                80,
+                1,
+                &extraction,
+                &[],
+                &[],
+                &mut outcome,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_tests_for_symbol() {
+        let (mut store, _dir) = test_store();
+        index_test_linkage_fixture(&mut store);
 
-    #[test]
-    fn test_build_pagination_no_more() {
-        let p = build_pagination(5, 0, 10, 5);
-        assert!(!p.has_more, "should not have more pages");
-        assert_eq!(p.next_offset, None, "next_offset should be None");
+        let tests = store.tests_for_symbol("foo").unwrap();
+        assert_eq!(tests.len(), 1, "repeated calls should dedupe to one test");
+        assert_eq!(tests[0].qualname, "test_foo_works");
+        assert_eq!(tests[0].file_path, "tests/foo_test.rs");
+
+        let tests = store.tests_for_symbol("Bar").unwrap();
+        assert_eq!(tests.len(), 1, "functions in test files count as tests");
+        assert_eq!(tests[0].qualname, "helper");
+
+        assert!(store.tests_for_symbol("missing").unwrap().is_empty());
     }
 
     #[test]
-    fn test_build_pagination_offset_past_end() {
-        let p = build_pagination(5, 100, 10, 0);
-        assert_eq!(p.offset, 5, "offset should be clamped to total");
-        assert!(!p.has_more, "should not have more pages");
-    }
+    fn edges_record_origin_and_heuristic_edges_can_be_excluded() {
+        let (mut store, _dir) = test_store();
+        index_test_linkage_fixture(&mut store);
 
-    // ── parse_selector ─────────────────────────────────────────────
+        let slice = |exclude_heuristic| {
+            let options = SliceQueryOptions {
+                suppress_low_signal_repeats: false,
+                exclude_heuristic,
+                ..Default::default()
+            };
+            store
+                .minimal_slice_with_options("tests/foo_test.rs", Some(2), 1, &options)
+                .unwrap()
+                .unwrap()
+                .neighbors
+        };
+        let neighbors = slice(false);
+        let tests_edge = neighbors
+            .iter()
+            .find(|edge| edge.edge_type == "tests" && edge.entity.name == "foo")
+            .expect("test should link to foo");
+        assert_eq!(tests_edge.origin.as_deref(), Some("heuristic"));
+        assert_eq!(tests_edge.confidence, Some(0.6));
+        let defines = neighbors
+            .iter()
+            .find(|edge| edge.edge_type == "defines")
+            .unwrap();
+        assert_eq!(defines.origin.as_deref(), Some("ast"));
+        assert_eq!(defines.confidence, Some(1.0));
+        assert!(slice(true).iter().all(|edge| edge.edge_type != "tests"));
 
-    #[test]
-    fn test_parse_selector_file() {
-        let result = parse_selector("file:src/main.rs").expect("should parse file selector");
-        match result {
-            ParsedSelector::File(p) => assert_eq!(p, "src/main.rs", "path should match"),
-            other => panic!("expected File variant, got {:?}", other),
-        }
+        let refs = |edge_type: &str, exclude_heuristic| {
+            let options = ReferenceQueryOptions {
+                edge_type_filter: Some(edge_type.to_string()),
+                exclude_heuristic,
+                ..Default::default()
+            };
+            store.symbol_references_page("foo", &options).unwrap().0
+        };
+        let calls = refs("calls", true);
+        assert_eq!(calls.len(), 2);
+        assert!(calls.iter().all(|row| row.origin.as_deref() == Some("ast")));
+        let tests = refs("tests", false);
+        assert_eq!(tests.len(), 1);
+        assert!(tests[0]
+            .why
+            .as_deref()
+            .unwrap()
+            .contains("origin=heuristic"));
+        assert!(tests[0].confidence.unwrap() < calls[0].confidence.unwrap());
+        assert!(refs("tests", true).is_empty());
     }
 
     #[test]
-    fn test_parse_selector_symbol_name() {
-        let result =
-            parse_selector("symbol_name:rust:foo").expect("should parse symbol_name selector");
-        match result {
-            ParsedSelector::SymbolName { lang, name } => {
-                assert_eq!(lang, "rust", "lang should be rust");
-                assert_eq!(name, "foo", "name should be foo");
-            }
-            other => panic!("expected SymbolName variant, got {:?}", other),
-        }
+    fn test_tests_edges_ignore_non_test_code() {
+        let (store, _dir) = store_with_sample_data();
+        assert!(
+            store.tests_for_symbol("foo").unwrap().is_empty(),
+            "source files should not produce tests edges"
+        );
     }
 
-    #[test]
-    fn test_parse_selector_symbol() {
-        let result = parse_selector("symbol:main").expect("should parse symbol selector");
-        match result {
-            ParsedSelector::Name(n) => assert_eq!(n, "main", "name should be main"),
-            other => panic!("expected Name variant, got {:?}", other),
-        }
-    }
+    // ── Renames ───────────────────────────────────────────────────
 
     #[test]
-    fn test_parse_selector_key() {
-        let result = parse_selector("key:some_key").expect("should parse key selector");
-        match result {
-            ParsedSelector::Key(k) => assert_eq!(k, "some_key", "key should match"),
-            other => panic!("expected Key variant, got {:?}", other),
-        }
-    }
+    fn test_rename_file_moves_graph_data() {
+        let (mut store, _dir) = store_with_sample_data();
+        assert!(store.rename_file("src/main.rs", "src/app.rs").unwrap());
 
-    #[test]
-    fn test_parse_selector_auto() {
-        let result = parse_selector("main").expect("should parse auto selector");
-        match result {
-            ParsedSelector::Auto(v) => assert_eq!(v, "main", "auto value should match"),
-            other => panic!("expected Auto variant, got {:?}", other),
-        }
+        assert!(store.tracked_file_hash("src/main.rs").unwrap().is_none());
+        assert_eq!(
+            store.tracked_file_hash("src/app.rs").unwrap().as_deref(),
+            Some("abc123")
+        );
+        let defs = store.symbol_definitions("foo").unwrap();
+        assert_eq!(defs[0].file_path, "src/app.rs");
+        let refs = store
+            .symbol_references_page("Bar", &ReferenceQueryOptions::default())
+            .unwrap()
+            .0;
+        assert!(refs.iter().all(|row| row.file_path == "src/app.rs"));
+        let imports = store.file_imports("src/app.rs").unwrap().unwrap().1;
+        assert_eq!(imports[0].module, "std::collections::HashMap");
     }
 
     #[test]
-    fn test_parse_selector_empty_errors() {
-        assert!(parse_selector("").is_err(), "empty selector should error");
+    fn test_rename_file_declines_classification_changes() {
+        let (mut store, _dir) = store_with_sample_data();
         assert!(
-            parse_selector("file:").is_err(),
-            "file: with no path should error"
+            !store.rename_file("src/main.rs", "src/main.py").unwrap(),
+            "language change needs a reindex"
         );
         assert!(
-            parse_selector("symbol_name:rust:").is_err(),
-            "symbol_name with empty name should error"
+            !store.rename_file("src/main.rs", "tests/main.rs").unwrap(),
+            "test classification change needs a reindex"
         );
         assert!(
-            parse_selector("symbol:").is_err(),
-            "symbol: with no name should error"
+            !store.rename_file("src/missing.rs", "src/other.rs").unwrap(),
+            "untracked source cannot be renamed"
         );
+        assert!(store.tracked_file_hash("src/main.rs").unwrap().is_some());
     }
 
-    // ── Public key helpers ─────────────────────────────────────────
+    // ── Module dependencies ───────────────────────────────────────
 
     #[test]
-    fn test_file_key() {
-        assert_eq!(
-            file_key("src/main.rs"),
-            "file:src/main.rs",
-            "file_key format"
-        );
+    fn test_module_bucket_levels() {
+        assert_eq!(module_bucket("src/storage/mod.rs", 1), "src");
+        assert_eq!(module_bucket("src/storage/mod.rs", 2), "src/storage");
+        assert_eq!(module_bucket("src/main.rs", 2), "src");
+        assert_eq!(module_bucket("build.rs", 1), ".");
     }
 
     #[test]
-    fn test_symbol_name_key() {
-        assert_eq!(
-            symbol_name_key("rust", "foo"),
-            "symbol_name:rust:foo",
-            "symbol_name_key format"
-        );
+    fn test_module_dependencies_aggregates_depends_on_edges() {
+        let (mut store, _dir) = test_store();
+        let mut outcome = UpsertOutcome::new();
+        let empty = FileExtraction {
+            language: LanguageKind::Python,
+            definitions: vec![],
+            references: vec![],
+            imports: vec![],
+            relations: vec![],
+            noise_spans: vec![],
+            annotations: vec![],
+        };
+        let files: [(&str, &[(String, String)]); 4] = [
+            (
+                "app/api/views.py",
+                &[
+                    ("core.models".into(), "core/models.py".into()),
+                    ("app.api.util".into(), "app/api/util.py".into()),
+                ],
+            ),
+            (
+                "app/cli.py",
+                &[("core.models".into(), "core/models.py".into())],
+            ),
+            ("app/api/util.py", &[]),
+            ("core/models.py", &[]),
+        ];
+        for (path, imports) in files {
+            store
+                .index_file(
+                    path,
+                    "python",
+                    path,
+                    1,
+                    1,
+                    &empty,
+                    &[],
+                    imports,
+                    &mut outcome,
+                )
+                .unwrap();
+        }
+
+        let level1 = store.module_dependencies(1).unwrap();
+        assert_eq!(level1.modules, vec!["app", "core"]);
+        assert_eq!(level1.edges.len(), 1);
+        assert_eq!(level1.edges[0].from, "app");
+        assert_eq!(level1.edges[0].to, "core");
+        assert_eq!(level1.edges[0].edge_count, 2);
+        assert_eq!(level1.matrix, vec![vec![0, 2], vec![0, 0]]);
+        assert_eq!(level1.internal_edges, 1);
+
+        let level2 = store.module_dependencies(2).unwrap();
+        assert_eq!(level2.modules, vec!["app", "app/api", "core"]);
+        let pairs: Vec<(&str, &str)> = level2
+            .edges
+            .iter()
+            .map(|edge| (edge.from.as_str(), edge.to.as_str()))
+            .collect();
+        assert!(pairs.contains(&("app/api", "core")));
+        assert!(pairs.contains(&("app", "core")));
+        assert_eq!(level2.internal_edges, 1);
     }
 
     #[test]
-    fn test_module_key() {
+    fn test_who_imports_lists_importers_and_directory_counts() {
+        let (mut store, _dir) = test_store();
+        let mut outcome = UpsertOutcome::new();
+        let files = [
+            ("app/views.py", vec!["billing", "billing.api"]),
+            ("app/cli.py", vec!["billing_extra"]),
+            ("tools/run.py", vec!["billing.api.v2"]),
+            ("main.py", vec!["billing"]),
+        ];
+        for (path, modules) in files {
+            let extraction = FileExtraction {
+                language: LanguageKind::Python,
+                definitions: vec![],
+                references: vec![],
+                imports: modules
+                    .iter()
+                    .enumerate()
+                    .map(|(i, module)| Import {
+                        module: module.to_string(),
+                        line: i as i64 + 1,
+                        col: 1,
+                    })
+                    .collect(),
+                relations: vec![],
+                noise_spans: vec![],
+                annotations: vec![],
+            };
+            store
+                .index_file(
+                    path,
+                    "python",
+                    path,
+                    1,
+                    1,
+                    &extraction,
+                    &[],
+                    &[],
+                    &mut outcome,
+                )
+                .unwrap();
+        }
+
+        let exact = store.who_imports("billing", false, None).unwrap();
+        let sites: Vec<(&str, i64)> = exact
+            .importers
+            .iter()
+            .map(|importer| (importer.file_path.as_str(), importer.line))
+            .collect();
+        assert_eq!(sites, [("app/views.py", 1), ("main.py", 1)]);
+
+        let nested = store.who_imports("billing", true, None).unwrap();
         assert_eq!(
-            module_key("rust", "std::io"),
-            "module:rust:std::io",
-            "module_key format"
+            nested.importers.len(),
+            4,
+            "`billing_extra` is not a submodule"
         );
-    }
+        assert_eq!(nested.total_files, 3);
+        let directories: Vec<(&str, usize, usize)> = nested
+            .directories
+            .iter()
+            .map(|dir| (dir.directory.as_str(), dir.files, dir.imports))
+            .collect();
+        assert_eq!(directories, [("app", 1, 2), (".", 1, 1), ("tools", 1, 1)]);
 
-    // ── classify_special_file ──────────────────────────────────────
+        assert!(store
+            .who_imports("billing", true, Some("rust"))
+            .unwrap()
+            .importers
+            .is_empty());
+    }
 
     #[test]
-    fn test_classify_special_file() {
-        assert_eq!(
-            classify_special_file("project/Cargo.toml"),
-            Some("config"),
-            "Cargo.toml should be config"
-        );
+    fn test_import_graph_walks_resolved_imports_both_ways() {
+        let (mut store, _dir) = test_store();
+        let mut outcome = UpsertOutcome::new();
+        let resolved = |module: &str, file: &str| vec![(module.to_string(), file.to_string())];
+        let files = [
+            (
+                "app/views.py",
+                vec!["app.util", "requests"],
+                resolved("app.util", "app/util.py"),
+            ),
+            (
+                "app/util.py",
+                vec!["core.models"],
+                resolved("core.models", "core/models.py"),
+            ),
+            (
+                "app/cli.py",
+                vec!["core.models"],
+                resolved("core.models", "core/models.py"),
+            ),
+            ("core/models.py", vec![], vec![]),
+        ];
+        for (path, modules, resolved) in files {
+            let extraction = FileExtraction {
+                language: LanguageKind::Python,
+                definitions: vec![],
+                references: vec![],
+                imports: modules
+                    .iter()
+                    .enumerate()
+                    .map(|(i, module)| Import {
+                        module: module.to_string(),
+                        line: i as i64 + 1,
+                        col: 1,
+                    })
+                    .collect(),
+                relations: vec![],
+                noise_spans: vec![],
+                annotations: vec![],
+            };
+            store
+                .index_file(
+                    path,
+                    "python",
+                    path,
+                    1,
+                    1,
+                    &extraction,
+                    &[],
+                    &resolved,
+                    &mut outcome,
+                )
+                .unwrap();
+        }
+
+        let summary = |graph: ImportGraph| -> Vec<(String, &'static str, usize)> {
+            graph
+                .rows
+                .into_iter()
+                .map(|row| (row.target, row.kind, row.depth))
+                .collect()
+        };
+
+        let direct = store
+            .import_graph("app/views.py", ImportDirection::Out, 1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(direct.rows[0].via.as_deref(), Some("app.util"));
         assert_eq!(
-            classify_special_file("project/src/main.rs"),
-            Some("entrypoint"),
-            "src/main.rs should be entrypoint"
+            summary(direct),
+            vec![
+                ("app/util.py".to_string(), "file", 1),
+                ("requests".to_string(), "module", 1),
+            ]
         );
+
+        let out = store
+            .import_graph("app/views.py", ImportDirection::Out, 10)
+            .unwrap()
+            .unwrap();
+        assert_eq!(out.rows[2].from, "app/util.py");
+        assert_eq!(summary(out)[2], ("core/models.py".to_string(), "file", 2));
+
+        let importers = store
+            .import_graph("core/models.py", ImportDirection::In, 10)
+            .unwrap()
+            .unwrap();
         assert_eq!(
-            classify_special_file("project/src/lib.rs"),
-            Some("entrypoint"),
-            "src/lib.rs should be entrypoint"
+            summary(importers),
+            vec![
+                ("app/cli.py".to_string(), "file", 1),
+                ("app/util.py".to_string(), "file", 1),
+                ("app/views.py".to_string(), "file", 2),
+            ]
         );
+        assert!(store
+            .import_graph("missing.py", ImportDirection::Out, 1)
+            .unwrap()
+            .is_none());
+    }
+
+    // ── Directory outline ─────────────────────────────────────────
+
+    #[test]
+    fn test_dir_outline_reads_stored_symbols() {
+        let (mut store, _dir) = test_store();
+        index_test_linkage_fixture(&mut store);
+
+        let outline = store
+            .dir_outline("src/", &DirOutlineOptions::default())
+            .unwrap();
+        assert_eq!(outline.directory, "src");
+        assert_eq!(outline.total_files, 1, "tests/ should be excluded");
+        assert_eq!(outline.files[0].path, "src/main.rs");
+        let names: Vec<&str> = outline.files[0]
+            .entries
+            .iter()
+            .map(|entry| entry.qualname.as_str())
+            .collect();
+        assert_eq!(names, vec!["foo", "Bar"]);
+
+        let whole_repo = store
+            .dir_outline(
+                ".",
+                &DirOutlineOptions {
+                    max_files: 1,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(whole_repo.total_files, 2);
+        assert_eq!(whole_repo.files.len(), 1);
+        assert!(whole_repo.truncated);
+    }
+
+    #[test]
+    fn test_dir_outline_nests_by_span_not_qualname() {
+        let (mut store, _dir) = test_store();
+        let definition = |qualname: &str, line: i64, end_line: i64| Definition {
+            name: qualname.rsplit('.').next().unwrap().into(),
+            qualname: qualname.into(),
+            kind: "function_definition".into(),
+            line,
+            col: 1,
+            end_line,
+            end_col: 1,
+            signature: None,
+            arity: None,
+            offsets: None,
+            visibility: None,
+            modifiers: Vec::new(),
+        };
+        let extraction = FileExtraction {
+            language: LanguageKind::Python,
+            definitions: vec![
+                definition("app.Server", 1, 10),
+                definition("app.Server.run", 2, 6),
+                definition("app.Server.run.retry", 3, 4),
+                definition("app.main", 12, 14),
+            ],
+            references: vec![],
+            imports: vec![],
+            relations: vec![],
+            noise_spans: vec![],
+            annotations: vec![],
+        };
+        let mut outcome = UpsertOutcome::new();
+        store
+            .index_file(
+                "app.py",
+                "python",
+                "h",
+                10,
+                14,
+                &extraction,
+                &[],
+                &[],
+                &mut outcome,
+            )
+            .unwrap();
+
+        let outline = |max_depth| {
+            store
+                .dir_outline(
+                    ".",
+                    &DirOutlineOptions {
+                        max_depth,
+                        ..Default::default()
+                    },
+                )
+                .unwrap()
+                .files
+                .remove(0)
+                .entries
+                .into_iter()
+                .map(|entry| (entry.name, entry.depth))
+                .collect::<Vec<_>>()
+        };
         assert_eq!(
-            classify_special_file("project/__main__.py"),
-            Some("entrypoint"),
-            "__main__.py should be entrypoint"
+            outline(None),
+            vec![
+                ("Server".to_string(), 0),
+                ("run".to_string(), 1),
+                ("retry".to_string(), 2),
+                ("main".to_string(), 0),
+            ]
         );
         assert_eq!(
-            classify_special_file("random.rs"),
-            None,
-            "random.rs should be None"
+            outline(Some(0)),
+            vec![("Server".to_string(), 0), ("main".to_string(), 0)]
         );
     }
 
@@ -2672,10 +8865,11 @@ mod tests {
 
     #[test]
     fn test_is_low_signal_symbol_name() {
-        assert!(is_low_signal_symbol_name("Ok"), "Ok is low signal");
-        assert!(is_low_signal_symbol_name("Some"), "Some is low signal");
+        let names = LowSignalNames::default();
+        assert!(names.contains(Some("rust"), "Ok"), "Ok is low signal");
+        assert!(names.contains(Some("rust"), "Some"), "Some is low signal");
         assert!(
-            !is_low_signal_symbol_name("GraphStore"),
+            !names.contains(Some("rust"), "GraphStore"),
             "GraphStore is not low signal"
         );
     }
@@ -2725,6 +8919,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("parse_config", "parse_config"), 0);
+        assert_eq!(levenshtein("parse_confg", "parse_config"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+    }
+
     // ── Freshness and warnings ─────────────────────────────────────
 
     #[test]
@@ -2771,4 +8973,182 @@ mod tests {
             "freshly indexed db should not produce a warning"
         );
     }
+
+    // ── Prune ─────────────────────────────────────────────────────
+
+    #[test]
+    fn test_reference_rows_carry_end_positions_and_old_meta_migrates() {
+        let (store, dir) = store_with_sample_data();
+        let end = |store: &GraphStore| {
+            let (rows, _) = store
+                .symbol_references_page("baz", &ReferenceQueryOptions::default())
+                .unwrap();
+            (rows[0].end_line, rows[0].end_col)
+        };
+        assert_eq!(end(&store), (Some(2), Some(13)));
+
+        // Databases from before the columns existed kept the ends in meta.
+        store
+            .conn
+            .execute_batch(
+                "UPDATE edges
+                 SET meta_json = json_set(COALESCE(meta_json, '{}'), '$.end_line', end_line, '$.end_col', end_col)
+                 WHERE end_line IS NOT NULL;
+                 ALTER TABLE edges DROP COLUMN end_line;
+                 ALTER TABLE edges DROP COLUMN end_col;",
+            )
+            .unwrap();
+        drop(store);
+        let store = GraphStore::open(&dir.path().join("test.db")).unwrap();
+        assert_eq!(end(&store), (Some(2), Some(13)));
+        let leftover: i64 = store
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM edges WHERE json_extract(meta_json, '$.end_line') IS NOT NULL",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(leftover, 0);
+    }
+
+    #[test]
+    fn test_prune_removes_rows_for_untracked_files() {
+        let (mut store, _dir) = store_with_sample_data();
+        store
+            .conn
+            .execute("DELETE FROM files WHERE path = 'src/main.rs'", [])
+            .unwrap();
+        let count_entities = |store: &GraphStore| -> i64 {
+            store
+                .conn
+                .query_row(
+                    "SELECT COUNT(*) FROM entities WHERE file_path = 'src/main.rs'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        let before = count_entities(&store);
+        assert!(before > 0);
+
+        let dry = store.prune(true, false).unwrap();
+        assert!(dry.dry_run);
+        assert!(dry.entities > 0, "dry run should count stale entities");
+        assert!(dry.edges > 0, "dry run should count stale edges");
+        assert_eq!(count_entities(&store), before, "dry run must not delete");
+
+        let real = store.prune(false, true).unwrap();
+        assert!(!real.dry_run);
+        assert_eq!(real.entities, dry.entities);
+        assert_eq!(real.edges, dry.edges);
+        assert!(real.vacuumed);
+        assert!(real.bytes_after.is_some());
+        assert_eq!(count_entities(&store), 0);
+
+        let again = store.prune(false, false).unwrap();
+        assert_eq!(again.total_rows(), 0);
+    }
+
+    #[test]
+    fn test_optimize_compacts_only_when_enough_pages_are_free() {
+        let (mut store, dir) = store_with_sample_data();
+        let staged = dir.path().join("compact.db");
+        let fresh = store.optimize(Some(&staged)).unwrap();
+        assert!(!fresh.compacted, "nothing to reclaim yet: {fresh:?}");
+
+        store
+            .conn
+            .execute_batch(
+                "CREATE TABLE filler(blob BLOB);
+                 WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 200)
+                 INSERT INTO filler SELECT randomblob(4096) FROM n;
+                 DROP TABLE filler;",
+            )
+            .unwrap();
+        let report = store.optimize(Some(&staged)).unwrap();
+        assert!(report.compacted, "{report:?}");
+        assert!(report.compact_ms.is_some());
+        assert!(report.bytes_reclaimed >= 200 * 4096, "{report:?}");
+        assert!(!staged.exists(), "the staged copy should be removed");
+
+        let reader = GraphStore::open(&dir.path().join("test.db")).unwrap();
+        assert_eq!(
+            reader.symbol_definitions("foo").unwrap().len(),
+            store.symbol_definitions("foo").unwrap().len()
+        );
+        assert!(!store.symbol_definitions("foo").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_writers_wait_for_a_concurrent_index_session() {
+        let (mut store, dir) = store_with_sample_data();
+        let mut other = GraphStore::open(&dir.path().join("test.db")).unwrap();
+        let (started, wait_started) = std::sync::mpsc::channel();
+        let indexer = std::thread::spawn(move || {
+            let mut session = store.begin_index_session().unwrap();
+            session.set_external_entities_enabled(true).unwrap();
+            started.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(300));
+            session.finish().unwrap();
+        });
+        wait_started.recv().unwrap();
+
+        other
+            .remove_files(&["src/main.rs".to_string()], &mut UpsertOutcome::new())
+            .expect("write should wait for the session instead of failing");
+        indexer.join().unwrap();
+        assert!(other.tracked_files().unwrap().is_empty());
+
+        let checkpoint = other.checkpoint_wal().unwrap();
+        assert!(!checkpoint.busy);
+        let wal = std::fs::metadata(dir.path().join("test.db-wal")).unwrap();
+        assert_eq!(wal.len(), 0, "TRUNCATE should empty the WAL");
+    }
+
+    #[test]
+    fn test_verify_reports_and_repairs_inconsistencies() {
+        let (mut store, dir) = store_with_sample_data();
+        std::fs::create_dir_all(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        let clean = store.verify(dir.path(), false).unwrap();
+        assert_eq!(clean.issues(), 0, "fresh index should verify: {clean:?}");
+
+        store
+            .conn
+            .execute_batch(
+                "
+                PRAGMA foreign_keys = OFF;
+                INSERT INTO edges(src_entity_id, dst_entity_id, edge_type)
+                SELECT id, 999999, 'calls' FROM entities LIMIT 1;
+                INSERT INTO edges(src_entity_id, dst_entity_id, edge_type, file_path, line, col, end_line, end_col, meta_json)
+                SELECT src_entity_id, dst_entity_id, edge_type, file_path, line, col, end_line, end_col, meta_json
+                FROM edges WHERE edge_type = 'calls' LIMIT 1;
+                PRAGMA foreign_keys = ON;
+                INSERT INTO entities(entity_type, key, name, file_path)
+                VALUES('symbol', 'symbol:src/lost.rs#ghost', 'ghost', 'src/lost.rs');
+                INSERT INTO fingerprints(file_path, fp_hash, span_start, span_end)
+                VALUES('src/lost.rs', 1, 0, 10);
+                INSERT INTO files(path, lang, content_hash, size_bytes, indexed_at)
+                VALUES('src/deleted.rs', 'rust', 'def456', 10, '2024-01-01T00:00:00Z');
+                ",
+            )
+            .unwrap();
+
+        let found = store.verify(dir.path(), false).unwrap();
+        assert!(!found.fixed);
+        assert_eq!(found.dangling_edges, 1);
+        assert_eq!(found.duplicate_edges, 1);
+        assert_eq!(found.stale_entities, 1);
+        assert_eq!(found.untracked_fingerprints, 1);
+        assert_eq!(found.missing_files, ["src/deleted.rs"]);
+        assert!(found.integrity_errors.is_empty());
+
+        let repaired = store.verify(dir.path(), true).unwrap();
+        assert!(repaired.fixed);
+        assert_eq!(repaired.issues(), found.issues());
+        let after = store.verify(dir.path(), false).unwrap();
+        assert_eq!(after.issues(), 0, "repair should leave nothing: {after:?}");
+        assert!(!store.tracked_files().unwrap().contains("src/deleted.rs"));
+    }
 }
@@ -0,0 +1,522 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+
+use crate::model::{ReferenceLocation, SelectorSuggestion, SliceResult};
+use crate::paths::RuntimePaths;
+use crate::storage::{
+    GraphStore, ReferenceQueryOptions, SelectorSuggestOptions, SliceQueryOptions,
+};
+
+const SYMBOL_LIMIT: usize = 200;
+const REFERENCE_LIMIT: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Search,
+    Symbols,
+    References,
+    Slice,
+}
+
+impl Pane {
+    fn next(self) -> Self {
+        match self {
+            Pane::Search => Pane::Symbols,
+            Pane::Symbols => Pane::References,
+            Pane::References => Pane::Slice,
+            Pane::Slice => Pane::Search,
+        }
+    }
+
+    fn previous(self) -> Self {
+        match self {
+            Pane::Search => Pane::Slice,
+            Pane::Symbols => Pane::Search,
+            Pane::References => Pane::Symbols,
+            Pane::Slice => Pane::References,
+        }
+    }
+}
+
+struct App {
+    store: GraphStore,
+    focus: Pane,
+    query: String,
+    symbols: Vec<SelectorSuggestion>,
+    symbol_state: ListState,
+    references: Vec<ReferenceLocation>,
+    reference_state: ListState,
+    slice: Option<SliceResult>,
+    slice_state: ListState,
+    status: String,
+    should_quit: bool,
+}
+
+/// Runs the interactive graph explorer until the user quits.
+pub fn run_tui(paths: &RuntimePaths) -> Result<()> {
+    let store = GraphStore::open(&paths.db_path)?;
+    let mut app = App::new(store);
+    if let Some(warning) = app.store.index_warning(24)? {
+        app.status = warning;
+    }
+
+    let mut terminal = ratatui::init();
+    let result = (|| -> Result<()> {
+        while !app.should_quit {
+            terminal.draw(|frame| draw(frame, &mut app))?;
+            if !event::poll(Duration::from_millis(250))? {
+                continue;
+            }
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    app.handle_key(key);
+                }
+            }
+        }
+        Ok(())
+    })();
+    ratatui::restore();
+    result
+}
+
+impl App {
+    fn new(store: GraphStore) -> Self {
+        Self {
+            store,
+            focus: Pane::Search,
+            query: String::new(),
+            symbols: Vec::new(),
+            symbol_state: ListState::default(),
+            references: Vec::new(),
+            reference_state: ListState::default(),
+            slice: None,
+            slice_state: ListState::default(),
+            status: "type a symbol name and press Enter".to_string(),
+            should_quit: false,
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.should_quit = true;
+            return;
+        }
+
+        match key.code {
+            KeyCode::Tab => {
+                self.focus = self.focus.next();
+                return;
+            }
+            KeyCode::BackTab => {
+                self.focus = self.focus.previous();
+                return;
+            }
+            _ => {}
+        }
+
+        if self.focus == Pane::Search {
+            match key.code {
+                KeyCode::Char(ch) => self.query.push(ch),
+                KeyCode::Backspace => {
+                    self.query.pop();
+                }
+                KeyCode::Enter => self.search(),
+                KeyCode::Esc => self.focus = Pane::Symbols,
+                _ => {}
+            }
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.should_quit = true,
+            KeyCode::Char('/') => self.focus = Pane::Search,
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+                self.focus = match self.focus {
+                    Pane::Symbols => Pane::References,
+                    _ => Pane::Slice,
+                };
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                self.focus = match self.focus {
+                    Pane::Slice => Pane::References,
+                    _ => Pane::Symbols,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    fn search(&mut self) {
+        let options = SelectorSuggestOptions {
+            query: Some(self.query.trim().to_string()),
+            file_glob: None,
+            entity_type: Some("symbol".to_string()),
+            limit: SYMBOL_LIMIT,
+            fuzzy: true,
+        };
+        match self.store.selector_suggestions_advanced(&options) {
+            Ok(symbols) => {
+                self.status = format!("{} symbols match `{}`", symbols.len(), self.query);
+                self.symbols = symbols;
+                self.symbol_state.select(if self.symbols.is_empty() {
+                    None
+                } else {
+                    Some(0)
+                });
+                self.focus = Pane::Symbols;
+                self.load_symbol();
+            }
+            Err(err) => self.status = format!("search failed: {err}"),
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let (state, len) = match self.focus {
+            Pane::Symbols => (&mut self.symbol_state, self.symbols.len()),
+            Pane::References => (&mut self.reference_state, self.references.len()),
+            Pane::Slice => (
+                &mut self.slice_state,
+                self.slice.as_ref().map(|s| s.neighbors.len()).unwrap_or(0),
+            ),
+            Pane::Search => return,
+        };
+        if len == 0 {
+            return;
+        }
+        let current = state.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, len as isize - 1) as usize;
+        if Some(next) == state.selected() {
+            return;
+        }
+        state.select(Some(next));
+
+        match self.focus {
+            Pane::Symbols => self.load_symbol(),
+            Pane::References => self.load_reference_slice(),
+            _ => {}
+        }
+    }
+
+    fn load_symbol(&mut self) {
+        self.references.clear();
+        self.reference_state.select(None);
+        self.slice = None;
+        self.slice_state.select(None);
+
+        let Some(symbol) = self
+            .symbol_state
+            .selected()
+            .and_then(|idx| self.symbols.get(idx))
+            .cloned()
+        else {
+            return;
+        };
+
+        let options = ReferenceQueryOptions {
+            limit: REFERENCE_LIMIT,
+            ..Default::default()
+        };
+        match self.store.symbol_references_page(&symbol.name, &options) {
+            Ok((rows, pagination)) => {
+                self.status = format!(
+                    "{} references to `{}` ({} total)",
+                    rows.len(),
+                    symbol.name,
+                    pagination.total
+                );
+                self.references = rows;
+                if !self.references.is_empty() {
+                    self.reference_state.select(Some(0));
+                }
+            }
+            Err(err) => self.status = format!("reference query failed: {err}"),
+        }
+
+        if let Some(file_path) = symbol.file_path.as_deref() {
+            self.load_slice(file_path, symbol.line);
+        }
+    }
+
+    fn load_reference_slice(&mut self) {
+        let Some(reference) = self
+            .reference_state
+            .selected()
+            .and_then(|idx| self.references.get(idx))
+            .cloned()
+        else {
+            return;
+        };
+        self.load_slice(&reference.file_path, Some(reference.line));
+    }
+
+    fn load_slice(&mut self, file_path: &str, line: Option<i64>) {
+        match self.store.minimal_slice_with_options(
+            file_path,
+            line,
+            1,
+            &SliceQueryOptions::default(),
+        ) {
+            Ok(slice) => {
+                self.slice_state.select(
+                    slice
+                        .as_ref()
+                        .filter(|slice| !slice.neighbors.is_empty())
+                        .map(|_| 0),
+                );
+                self.slice = slice;
+            }
+            Err(err) => self.status = format!("slice failed: {err}"),
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .split(frame.area());
+
+    let search = Paragraph::new(app.query.as_str())
+        .block(pane_block("Search symbols", app.focus == Pane::Search));
+    frame.render_widget(search, rows[0]);
+    if app.focus == Pane::Search {
+        let cursor_x = rows[0].x + 1 + app.query.chars().count() as u16;
+        frame.set_cursor_position((
+            cursor_x.min(rows[0].right().saturating_sub(2)),
+            rows[0].y + 1,
+        ));
+    }
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(30),
+            Constraint::Percentage(35),
+            Constraint::Percentage(35),
+        ])
+        .split(rows[1]);
+
+    let symbol_items: Vec<ListItem> = app
+        .symbols
+        .iter()
+        .map(|symbol| {
+            ListItem::new(Line::from(vec![
+                Span::raw(symbol.name.clone()),
+                Span::styled(
+                    format!(
+                        "  {}:{}",
+                        symbol.file_path.as_deref().unwrap_or("?"),
+                        symbol.line.unwrap_or_default()
+                    ),
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ]))
+        })
+        .collect();
+    render_list(
+        frame,
+        columns[0],
+        "Symbols",
+        symbol_items,
+        &mut app.symbol_state,
+        app.focus == Pane::Symbols,
+    );
+
+    let reference_items: Vec<ListItem> = app
+        .references
+        .iter()
+        .map(|reference| {
+            ListItem::new(format!(
+                "{}:{}:{} [{}]",
+                reference.file_path, reference.line, reference.col, reference.edge_type
+            ))
+        })
+        .collect();
+    render_list(
+        frame,
+        columns[1],
+        "References",
+        reference_items,
+        &mut app.reference_state,
+        app.focus == Pane::References,
+    );
+
+    let (slice_title, slice_items) = match app.slice.as_ref() {
+        Some(slice) => (
+            format!("Slice: {}", slice.anchor.name),
+            slice
+                .neighbors
+                .iter()
+                .map(|edge| {
+                    ListItem::new(format!(
+                        "{} {} [{}] {}",
+                        if edge.direction == "outgoing" {
+                            "->"
+                        } else {
+                            "<-"
+                        },
+                        edge.edge_type,
+                        edge.entity.entity_type,
+                        edge.entity.name
+                    ))
+                })
+                .collect(),
+        ),
+        None => ("Slice".to_string(), Vec::new()),
+    };
+    render_list(
+        frame,
+        columns[2],
+        &slice_title,
+        slice_items,
+        &mut app.slice_state,
+        app.focus == Pane::Slice,
+    );
+
+    let help = "Tab: switch pane  /: search  j/k: move  Enter: drill in  q: quit";
+    let status = Paragraph::new(format!("{}  |  {help}", app.status))
+        .style(Style::default().fg(Color::DarkGray));
+    frame.render_widget(status, rows[2]);
+}
+
+fn render_list(
+    frame: &mut Frame,
+    area: Rect,
+    title: &str,
+    items: Vec<ListItem>,
+    state: &mut ListState,
+    focused: bool,
+) {
+    let list = List::new(items)
+        .block(pane_block(title, focused))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, area, state);
+}
+
+fn pane_block(title: &str, focused: bool) -> Block<'_> {
+    let border_style = if focused {
+        Style::default().fg(Color::Cyan)
+    } else {
+        Style::default()
+    };
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(border_style)
+        .title(title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Definition, FileExtraction, LanguageKind, Reference, ReferenceKind};
+    use crate::storage::UpsertOutcome;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use tempfile::TempDir;
+
+    fn test_app() -> (App, TempDir) {
+        let dir = TempDir::new().unwrap();
+        let mut store = GraphStore::open(&dir.path().join("graph.db")).unwrap();
+        let extraction = FileExtraction {
+            language: LanguageKind::Rust,
+            definitions: vec![Definition {
+                name: "greet".into(),
+                qualname: "greet".into(),
+                kind: "function_item".into(),
+                line: 1,
+                col: 1,
+                end_line: 3,
+                end_col: 1,
+                signature: None,
+                arity: None,
+                offsets: None,
+                visibility: None,
+                modifiers: Vec::new(),
+            }],
+            references: vec![Reference {
+                name: "greet".into(),
+                kind: ReferenceKind::Call,
+                line: 5,
+                col: 5,
+                end_line: 5,
+                end_col: 10,
+                arity: None,
+                offsets: None,
+            }],
+            imports: vec![],
+            relations: vec![],
+            noise_spans: vec![],
+            annotations: vec![],
+        };
+        store
+            .index_file(
+                "src/lib.rs",
+                "rust",
+                "hash",
+                10,
+                1,
+                &extraction,
+                &[],
+                &[],
+                &mut UpsertOutcome::new(),
+            )
+            .unwrap();
+        (App::new(store), dir)
+    }
+
+    fn press(app: &mut App, code: KeyCode) {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn search_loads_symbols_references_and_slice() {
+        let (mut app, _dir) = test_app();
+        for ch in "greet".chars() {
+            press(&mut app, KeyCode::Char(ch));
+        }
+        press(&mut app, KeyCode::Enter);
+
+        assert_eq!(app.focus, Pane::Symbols);
+        assert_eq!(app.symbols.len(), 1);
+        assert_eq!(app.references.len(), 1);
+        assert_eq!(app.references[0].line, 5);
+        assert!(app.slice.is_some(), "definition slice should load");
+
+        press(&mut app, KeyCode::Tab);
+        assert_eq!(app.focus, Pane::References);
+        press(&mut app, KeyCode::Char('q'));
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn draw_renders_panes() {
+        let (mut app, _dir) = test_app();
+        app.query = "greet".to_string();
+        app.search();
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| draw(frame, &mut app)).unwrap();
+        let rendered: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(rendered.contains("Symbols"));
+        assert!(rendered.contains("src/lib.rs:5:5 [calls]"));
+    }
+}